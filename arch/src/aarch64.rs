@@ -1,5 +1,196 @@
+pub mod debug;
+pub mod paging;
+
+/// The ELF machine type of executables for AArch64.
+pub const ELF_MACHINE: u16 = 183;
+
+/// The ELF relocation type `R_AARCH64_RELATIVE`, which adds the load slide to an
+/// address.
+pub const ELF_RELATIVE: u32 = 1027;
+
 #[cfg(target_arch = "aarch64")]
 #[inline(always)]
 pub fn halt() -> ! {
-  loop {}
+  loop {
+    unsafe { core::arch::asm!("msr daifset, #0xf; wfi") };
+  }
+}
+
+/// The PSCI function that powers the machine off.
+const PSCI_SYSTEM_OFF: u64 = 0x8400_0008;
+
+/// Powers the machine off through PSCI, halting if that fails.
+///
+/// PSCI carries no status, so `code` is dropped; emulators exit with success.
+#[cfg(target_arch = "aarch64")]
+pub fn exit_emulator(code: u32) -> ! {
+  let _ = code;
+  // SAFETY: the firmware provides PSCI through the hypervisor call, as QEMU's
+  // virtual machine does, and the call does not return if it succeeds.
+  unsafe {
+    core::arch::asm!(
+      "hvc #0",
+      inout("x0") PSCI_SYSTEM_OFF => _,
+      lateout("x1") _,
+      lateout("x2") _,
+      lateout("x3") _,
+    )
+  };
+  halt()
+}
+
+#[inline(always)]
+pub fn cycle_counter() -> u64 {
+  let value: u64;
+  // SAFETY: the virtual counter is readable at every exception level that
+  // this runs at. The barrier keeps the read from being speculated early.
+  unsafe { core::arch::asm!("isb; mrs {}, cntvct_el0", out(reg) value) };
+  value
+}
+
+#[inline(always)]
+pub fn cycle_counter_begin() -> u64 {
+  // The barrier of `cycle_counter` already waits for the instructions ahead
+  // of the read.
+  cycle_counter()
+}
+
+#[inline(always)]
+pub fn cycle_counter_end() -> u64 {
+  let value: u64;
+  // SAFETY: as for `cycle_counter`. The second barrier keeps the code that
+  // follows from starting before the read.
+  unsafe {
+    core::arch::asm!("isb; mrs {}, cntvct_el0; isb", out(reg) value)
+  };
+  value
+}
+
+/// Returns the quotient and remainder of `a * b / c`, or `None` if `c` is
+/// zero or the quotient does not fit in 64 bits.
+///
+/// AArch64 has no division of 128 bits by 64, so a product that fits in 64
+/// bits, the common case, is divided with `udiv`, and a wider one in
+/// software.
+#[inline(always)]
+pub fn mul_div(a: u64, b: u64, c: u64) -> Option<(u64, u64)> {
+  if c == 0 {
+    return None;
+  }
+  if let Some(product) = a.checked_mul(b) {
+    return Some((product / c, product % c));
+  }
+  let product = a as u128 * b as u128;
+  let quotient = u64::try_from(product / c as u128).ok()?;
+  Some((quotient, (product % c as u128) as u64))
+}
+
+/// Returns the frequency of the virtual counter, as the firmware programmed
+/// it into CNTFRQ.
+#[inline(always)]
+pub fn counter_frequency() -> Option<u64> {
+  let value: u64;
+  // SAFETY: the counter frequency register is readable at every exception
+  // level that this runs at.
+  unsafe { core::arch::asm!("mrs {}, cntfrq_el0", out(reg) value) };
+  Some(value).filter(|&value| value != 0)
+}
+
+/// The number of times RNDR is retried before it is taken to have failed.
+const RNDR_RETRIES: usize = 10;
+
+/// Returns 64 bits from RNDR, where the processor implements FEAT_RNG.
+#[inline(always)]
+pub fn hardware_random() -> Option<u64> {
+  let isar0: u64;
+  // SAFETY: the instruction set attribute register is readable at every
+  // exception level that this runs at.
+  unsafe { core::arch::asm!("mrs {}, id_aa64isar0_el1", out(reg) isar0) };
+  if (isar0 >> 60) & 0xf == 0 {
+    return None;
+  }
+  (0..RNDR_RETRIES).find_map(|_| {
+    let (value, ok): (u64, u64);
+    // SAFETY: the processor implements RNDR, which clears Z on success. It is
+    // named by its encoding, which assemblers without FEAT_RNG accept.
+    unsafe {
+      core::arch::asm!(
+        "mrs {}, s3_3_c2_c4_0",
+        "cset {}, ne",
+        out(reg) value,
+        out(reg) ok,
+        options(nomem, nostack),
+      )
+    };
+    (ok != 0).then_some(value)
+  })
+}
+
+/// Returns the affinity fields of the running processor's MPIDR.
+#[inline(always)]
+pub fn processor_id() -> u64 {
+  let mpidr: u64;
+  // SAFETY: the multiprocessor affinity register is readable at every
+  // exception level that this runs at.
+  unsafe { core::arch::asm!("mrs {}, mpidr_el1", out(reg) mpidr) };
+  mpidr & 0xff_00ff_ffff
+}
+
+/// The IRQ mask bit of DAIF, which is clear while interrupts are enabled.
+const DAIF_I: u64 = 1 << 7;
+
+/// Disables IRQs and FIQs on the running processor, returning whether IRQs
+/// were enabled.
+#[inline(always)]
+pub fn disable_interrupts() -> bool {
+  let daif: u64;
+  // SAFETY: reading and masking DAIF only affect the running processor.
+  // Memory is not marked untouched, so that accesses are not moved out of the
+  // critical section.
+  unsafe { core::arch::asm!("mrs {}, daif; msr daifset, #3", out(reg) daif) };
+  daif & DAIF_I == 0
+}
+
+/// Enables IRQs and FIQs on the running processor.
+#[inline(always)]
+pub fn enable_interrupts() {
+  // SAFETY: as for `disable_interrupts`; whoever enables interrupts must be
+  // ready to take them.
+  unsafe { core::arch::asm!("msr daifclr, #3") };
+}
+
+/// Waits for the next interrupt, then enables IRQs and FIQs on the running
+/// processor so that it is taken.
+///
+/// `wfi` ends when an interrupt is pending even while it is masked, so one
+/// that became pending while they were disabled is not missed.
+#[inline(always)]
+pub fn wait_for_interrupt() {
+  // SAFETY: as for `enable_interrupts`; waiting only affects the running
+  // processor.
+  unsafe { core::arch::asm!("wfi; msr daifclr, #3") };
+}
+
+/// Orders loads before the barrier ahead of loads and stores after it, as
+/// observed by the other processors of the inner shareable domain.
+#[inline(always)]
+pub fn read_barrier() {
+  // SAFETY: a barrier only orders memory accesses.
+  unsafe { core::arch::asm!("dmb ishld", options(nostack, preserves_flags)) };
+}
+
+/// Orders stores before the barrier ahead of stores after it, as observed by
+/// the other processors of the inner shareable domain.
+#[inline(always)]
+pub fn write_barrier() {
+  // SAFETY: a barrier only orders memory accesses.
+  unsafe { core::arch::asm!("dmb ishst", options(nostack, preserves_flags)) };
+}
+
+/// Orders all memory accesses before the barrier ahead of those after it, as
+/// observed by the other processors of the inner shareable domain.
+#[inline(always)]
+pub fn full_barrier() {
+  // SAFETY: a barrier only orders memory accesses.
+  unsafe { core::arch::asm!("dmb ish", options(nostack, preserves_flags)) };
 }
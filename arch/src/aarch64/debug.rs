@@ -0,0 +1,125 @@
+//! This module provides the AArch64 self-hosted debug registers: the
+//! breakpoint value and control registers DBGBVR and DBGBCR for execution,
+//! and the watchpoint registers DBGWVR and DBGWCR for data accesses.
+//!
+//! Every implementation has at least two of each, which are all that are
+//! used, so that hardware breakpoint `n` is breakpoint `n` when it traps on
+//! execution, and watchpoint `n` otherwise.
+
+use crate::debug::Watch;
+
+/// The number of hardware breakpoints.
+pub const BREAKPOINTS: usize = 2;
+
+/// The instruction that software breakpoints are written as, `brk #0`.
+pub const BREAKPOINT_INSTRUCTION: &[u8] = &[0x00, 0x00, 0x20, 0xd4];
+
+/// The bits of MDSCR_EL1 that enable debug exceptions from breakpoints and
+/// watchpoints, and at the exception level of the kernel.
+const MDSCR_MDE: u64 = 1 << 15;
+const MDSCR_KDE: u64 = 1 << 13;
+
+/// The control bits that enable a breakpoint or watchpoint at EL0 and EL1.
+const CONTROL_ENABLE: u64 = 0b111;
+
+/// The byte address select of a breakpoint on any A64 instruction.
+const BREAKPOINT_BYTES: u64 = 0b1111 << 5;
+
+/// Traps on the hardware breakpoint `index`, returning `false` if there is no
+/// such breakpoint, or the range cannot be watched.
+///
+/// Ranges that are watched must be 1 to 8 bytes long, within one aligned
+/// doubleword; breakpoints on execution are always one instruction.
+///
+/// # Arguments
+///
+/// * `index` - the breakpoint, below [`BREAKPOINTS`]
+/// * `address` - the virtual address of the first byte
+/// * `watch` - the accesses to trap on
+/// * `len` - the number of bytes to watch
+///
+/// # Safety
+///
+/// This must be called at EL1, with a handler for the debug exceptions
+/// installed.
+pub unsafe fn set_breakpoint(
+  index: usize,
+  address: u64,
+  watch: Watch,
+  len: usize,
+) -> bool {
+  if index >= BREAKPOINTS {
+    return false;
+  }
+  let offset = (address % 8) as usize;
+  let access: u64 = match watch {
+    Watch::Execute => {
+      write_breakpoint(index, address & !0b11, CONTROL_ENABLE | BREAKPOINT_BYTES);
+      enable();
+      return true;
+    }
+    _ if len == 0 || offset + len > 8 => return false,
+    Watch::Write => 0b10,
+    Watch::ReadWrite => 0b11,
+  };
+  let bytes = ((1u64 << len) - 1) << offset;
+  let control = CONTROL_ENABLE | access << 3 | bytes << 5;
+  write_watchpoint(index, address & !0b111, control);
+  enable();
+  true
+}
+
+/// Stops trapping on the hardware breakpoint `index`, if there is one.
+///
+/// # Arguments
+///
+/// * `index` - the breakpoint, below [`BREAKPOINTS`]
+///
+/// # Safety
+///
+/// This must be called at EL1.
+pub unsafe fn clear_breakpoint(index: usize) {
+  if index < BREAKPOINTS {
+    write_breakpoint(index, 0, 0);
+    write_watchpoint(index, 0, 0);
+  }
+}
+
+/// Unlocks the debug registers and unmasks debug exceptions at EL1.
+unsafe fn enable() {
+  let mdscr: u64;
+  core::arch::asm!("mrs {}, mdscr_el1", out(reg) mdscr);
+  core::arch::asm!(
+    "msr oslar_el1, xzr",
+    "msr mdscr_el1, {}",
+    "isb",
+    "msr daifclr, #8",
+    in(reg) mdscr | MDSCR_MDE | MDSCR_KDE,
+  );
+}
+
+unsafe fn write_breakpoint(index: usize, address: u64, control: u64) {
+  match index {
+    0 => core::arch::asm!(
+      "msr dbgbvr0_el1, {}", "msr dbgbcr0_el1, {}", "isb",
+      in(reg) address, in(reg) control,
+    ),
+    _ => core::arch::asm!(
+      "msr dbgbvr1_el1, {}", "msr dbgbcr1_el1, {}", "isb",
+      in(reg) address, in(reg) control,
+    ),
+  }
+}
+
+unsafe fn write_watchpoint(index: usize, address: u64, control: u64) {
+  match index {
+    0 => core::arch::asm!(
+      "msr dbgwvr0_el1, {}", "msr dbgwcr0_el1, {}", "isb",
+      in(reg) address, in(reg) control,
+    ),
+    _ => core::arch::asm!(
+      "msr dbgwvr1_el1, {}", "msr dbgwcr1_el1, {}", "isb",
+      in(reg) address, in(reg) control,
+    ),
+  }
+}
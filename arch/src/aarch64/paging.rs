@@ -0,0 +1,194 @@
+//! This module provides the AArch64 encoding of page table entries for the
+//! 4 KiB granule with 48-bit virtual addresses, and the switch onto a set of
+//! tables to enter a kernel.
+//!
+//! The same root table is installed for both halves of the address space, so
+//! that it is indexed just like x86-64 tables: the lower half through
+//! `TTBR0_EL1`, and the higher half through `TTBR1_EL1`. All memory is mapped
+//! as normal, write-back cacheable memory.
+
+use crate::paging::PageFlags;
+
+crate::register! {
+  /// A translation table descriptor.
+  struct Entry(u64) {
+    /// The entry maps memory or refers to a table.
+    VALID: bool = 0, rw;
+
+    /// The entry refers to a table, or maps a page from the last level,
+    /// rather than mapping a block.
+    TABLE: bool = 1, rw;
+
+    /// The mapped memory may only be read.
+    READ_ONLY: bool = 7, rw;
+
+    /// The shareability of the mapped memory.
+    SHAREABILITY: u64 = 8..10, rw;
+
+    /// The mapped memory has been accessed, which must be set up front so
+    /// that the first access does not fault.
+    ACCESSED: bool = 10, rw;
+
+    /// The bits of the physical address that the entry refers to, which is
+    /// aligned to a page.
+    ADDRESS: u64 = 12..48, rw;
+
+    /// The mapped memory may not be executed from at EL1.
+    PRIVILEGED_NO_EXECUTE: bool = 53, rw;
+
+    /// The mapped memory may not be executed from at EL0.
+    UNPRIVILEGED_NO_EXECUTE: bool = 54, rw;
+  }
+}
+
+/// The [`Entry::SHAREABILITY`] of inner shareable memory.
+const INNER_SHAREABLE: u64 = 3;
+
+/// The memory attributes, of which only the first, normal write-back
+/// cacheable memory, is used.
+const MAIR: u64 = 0xff;
+
+/// The translation control: 48-bit virtual addresses and the 4 KiB granule in
+/// both halves, with table walks that are inner shareable and write-back
+/// cacheable. The physical address size is filled in from the processor.
+const TCR: u64 = 16 // T0SZ
+  | 1 << 8 // IRGN0
+  | 1 << 10 // ORGN0
+  | 3 << 12 // SH0
+  | 16 << 16 // T1SZ
+  | 1 << 24 // IRGN1
+  | 1 << 26 // ORGN1
+  | 3 << 28 // SH1
+  | 2 << 30; // TG1
+
+/// Returns an entry that refers to the next-level table at `address`.
+///
+/// # Arguments
+///
+/// * `address` - the physical address of the table
+pub fn table_entry(address: u64) -> u64 {
+  Entry::new(address)
+    .with(Entry::VALID, true)
+    .with(Entry::TABLE, true)
+    .bits()
+}
+
+/// Returns an entry that maps the memory at `address` with the access given
+/// by `flags`.
+///
+/// # Arguments
+///
+/// * `address` - the physical address of the memory
+/// * `flags` - the access permitted to the mapping
+/// * `huge` - whether the entry maps a block from the level above the last,
+///   rather than a page from the last level
+pub fn leaf_entry(address: u64, flags: PageFlags, huge: bool) -> u64 {
+  Entry::new(address)
+    .with(Entry::VALID, true)
+    .with(Entry::ACCESSED, true)
+    .with(Entry::SHAREABILITY, INNER_SHAREABLE)
+    .with(Entry::UNPRIVILEGED_NO_EXECUTE, true)
+    .with(Entry::TABLE, !huge)
+    .with(Entry::READ_ONLY, !flags.writable)
+    .with(Entry::PRIVILEGED_NO_EXECUTE, !flags.executable)
+    .bits()
+}
+
+/// Returns the leaf entry `new`, with its access widened to include that of
+/// the leaf entry `old` that maps the same memory.
+///
+/// # Arguments
+///
+/// * `old` - the existing entry
+/// * `new` - the entry replacing it
+pub fn widen(old: u64, new: u64) -> u64 {
+  let restrictions =
+    Entry::READ_ONLY.mask() | Entry::PRIVILEGED_NO_EXECUTE.mask();
+  (old | new) & !restrictions | old & new & restrictions
+}
+
+/// Returns `true` if `entry` maps memory or refers to a table.
+///
+/// # Arguments
+///
+/// * `entry` - the entry
+pub fn is_present(entry: u64) -> bool {
+  Entry::new(entry).get(Entry::VALID)
+}
+
+/// Returns `true` if `entry`, from a level above the last, maps memory
+/// directly rather than referring to a table.
+///
+/// # Arguments
+///
+/// * `entry` - the entry
+pub fn is_huge(entry: u64) -> bool {
+  let entry = Entry::new(entry);
+  entry.get(Entry::VALID) && !entry.get(Entry::TABLE)
+}
+
+/// Returns the physical address that `entry` refers to.
+///
+/// # Arguments
+///
+/// * `entry` - the entry
+pub fn address(entry: u64) -> u64 {
+  entry & Entry::ADDRESS.mask()
+}
+
+/// Returns `true` if the processor is in a state that [`enter`] can switch
+/// from, which is running at EL1.
+pub fn can_enter() -> bool {
+  let current_el: u64;
+  // SAFETY: the current exception level is readable at every exception level
+  // above EL0.
+  unsafe { core::arch::asm!("mrs {}, CurrentEL", out(reg) current_el) };
+  (current_el >> 2) & 3 == 1
+}
+
+/// Switches to the page tables at `root` and the stack ending at `stack`, and
+/// calls the kernel `entry` point with `argument`.
+///
+/// The kernel is called with the AAPCS64 calling convention, at EL1, with
+/// `argument` in `x0` and all exceptions masked.
+///
+/// # Arguments
+///
+/// * `root` - the physical address of the root page table
+/// * `entry` - the virtual address of the kernel entry point
+/// * `stack` - the 16-byte aligned virtual address of the top of the stack
+/// * `argument` - the argument to pass to the kernel
+///
+/// # Safety
+///
+/// The processor must be running at EL1, as reported by [`can_enter`]. The
+/// tables at `root` must identity-map the running code, and map `entry` and
+/// the stack. Nothing may rely on the previous tables afterwards.
+pub unsafe fn enter(root: u64, entry: u64, stack: u64, argument: u64) -> ! {
+  let features: u64;
+  core::arch::asm!("mrs {}, ID_AA64MMFR0_EL1", out(reg) features);
+  // The physical address size is limited to what the processor supports.
+  let tcr = TCR | (features & 0xf) << 32;
+  core::arch::asm!(
+    "msr daifset, #0xf",
+    "msr MAIR_EL1, {mair}",
+    "msr TCR_EL1, {tcr}",
+    "msr TTBR0_EL1, {root}",
+    "msr TTBR1_EL1, {root}",
+    "dsb ish",
+    "tlbi vmalle1",
+    "dsb ish",
+    "isb",
+    "mov sp, {stack}",
+    "mov x29, xzr",
+    "blr {entry}",
+    "udf #0",
+    mair = in(reg) MAIR,
+    tcr = in(reg) tcr,
+    root = in(reg) root,
+    stack = in(reg) stack,
+    entry = in(reg) entry,
+    in("x0") argument,
+    options(noreturn),
+  )
+}
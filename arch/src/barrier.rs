@@ -0,0 +1,27 @@
+//! This module provides memory barriers, which order the memory accesses of
+//! the running processor as they are observed by the other processors.
+//!
+//! Atomics order accesses to themselves; these barriers are for ordering plain
+//! accesses around them, such as reading a value guarded by a sequence
+//! counter. Each barrier also keeps the compiler from moving accesses across
+//! it.
+
+use crate::target;
+
+/// Orders loads before the barrier ahead of loads after it.
+#[inline(always)]
+pub fn read() {
+  target::read_barrier()
+}
+
+/// Orders stores before the barrier ahead of stores after it.
+#[inline(always)]
+pub fn write() {
+  target::write_barrier()
+}
+
+/// Orders all loads and stores before the barrier ahead of those after it.
+#[inline(always)]
+pub fn full() {
+  target::full_barrier()
+}
@@ -0,0 +1,114 @@
+//! This module provides critical sections, during which the running processor
+//! takes no interrupts.
+//!
+//! A critical section is entered by creating a [`CriticalSection`], and left
+//! when it is dropped. Sections nest: leaving one only re-enables interrupts
+//! if they were enabled when it was entered, so an inner section never
+//! re-enables them early for an outer one.
+//!
+//! Hosted targets, on which the crate is only built to be tested, cannot
+//! mask interrupts, so each thread there stands in for a processor, with an
+//! interrupt flag of its own that sections disable and restore.
+
+#[cfg(any(target_os = "none", target_os = "uefi"))]
+use crate::target as interrupts;
+use core::marker::PhantomData;
+
+/// A guard that keeps interrupts disabled on the running processor for as
+/// long as it lives.
+#[must_use = "interrupts are restored as soon as the section is dropped"]
+pub struct CriticalSection {
+  /// Whether interrupts were enabled when the section was entered.
+  enabled: bool,
+
+  // The section belongs to the processor that entered it, whose interrupts it
+  // restores, so it must not be moved to another.
+  _not_send: PhantomData<*const ()>,
+}
+
+impl CriticalSection {
+  /// Enters a critical section, disabling interrupts on the running processor
+  /// until the section is dropped.
+  #[inline(always)]
+  pub fn enter() -> Self {
+    Self {
+      enabled: interrupts::disable_interrupts(),
+      _not_send: PhantomData,
+    }
+  }
+
+  /// Returns whether interrupts were enabled when this section was entered,
+  /// and so will be re-enabled when it is left.
+  pub fn interrupts_were_enabled(&self) -> bool {
+    self.enabled
+  }
+}
+
+impl Drop for CriticalSection {
+  #[inline(always)]
+  fn drop(&mut self) {
+    if self.enabled {
+      interrupts::enable_interrupts();
+    }
+  }
+}
+
+/// Runs `f` within a critical section.
+///
+/// # Arguments
+///
+/// * `f` - the function to run with interrupts disabled
+#[inline(always)]
+pub fn with<R>(f: impl FnOnce(&CriticalSection) -> R) -> R {
+  let section = CriticalSection::enter();
+  f(&section)
+}
+
+/// The interrupt flag of each thread on hosted targets, where the processor's
+/// cannot be changed.
+#[cfg(not(any(target_os = "none", target_os = "uefi")))]
+mod interrupts {
+  use core::cell::Cell;
+
+  std::thread_local! {
+    static ENABLED: Cell<bool> = Cell::new(true);
+  }
+
+  /// Disables the interrupts of the running thread, returning whether they
+  /// were enabled.
+  pub fn disable_interrupts() -> bool {
+    ENABLED.with(|enabled| enabled.replace(false))
+  }
+
+  /// Enables the interrupts of the running thread.
+  pub fn enable_interrupts() {
+    ENABLED.with(|enabled| enabled.set(true));
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn sections_restore_interrupts_when_left() {
+    let outer = CriticalSection::enter();
+    assert!(outer.interrupts_were_enabled());
+    drop(outer);
+
+    assert!(with(|section| section.interrupts_were_enabled()));
+  }
+
+  #[test]
+  fn nested_sections_leave_interrupts_disabled() {
+    let outer = CriticalSection::enter();
+    let inner = CriticalSection::enter();
+    assert!(!inner.interrupts_were_enabled());
+    drop(inner);
+
+    // Leaving the inner section must not re-enable interrupts for the outer.
+    assert!(!CriticalSection::enter().interrupts_were_enabled());
+    drop(outer);
+    assert!(CriticalSection::enter().interrupts_were_enabled());
+  }
+}
@@ -0,0 +1,21 @@
+//! This module defines the architecture-independent description of hardware
+//! breakpoints and watchpoints, which each architecture's `debug` module
+//! programs into its debug registers.
+//!
+//! Each architecture has a small, fixed number of slots, given by its
+//! `BREAKPOINTS`, each of which traps on one address or range of bytes. A
+//! trap is reported to the running code as a debug exception, which it must
+//! have a handler for before any slot is set.
+
+/// The accesses that a hardware breakpoint traps on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Watch {
+  /// Executing the instruction at the address.
+  Execute,
+
+  /// Writing any of the bytes.
+  Write,
+
+  /// Reading or writing any of the bytes.
+  ReadWrite,
+}
@@ -1,5 +1,10 @@
 #![no_std]
 
+// Hosted targets, which the crate is only built for to be tested, stand in
+// for the processor with threads.
+#[cfg(not(any(target_os = "none", target_os = "uefi")))]
+extern crate std;
+
 macro_rules! define_arch {
   ($mod_name:ident, $arch_str:tt) => {
     #[cfg(target_arch = $arch_str)]
@@ -15,6 +20,14 @@ macro_rules! define_arch {
 define_arch!(aarch64, "aarch64");
 define_arch!(x86_64, "x86_64");
 
+pub mod barrier;
+pub mod critical_section;
+pub mod debug;
+pub mod mmio;
+pub mod paging;
+pub mod register;
+pub mod volatile;
+
 /// A module that buckets functionality that exists for the architecture being
 /// targeted for compilation.
 ///
@@ -35,3 +48,69 @@ pub mod target {
 pub fn halt() -> ! {
   target::halt()
 }
+
+// Enables interrupts and waits until the next one arrives.
+//
+// An interrupt that became pending while interrupts were disabled ends the
+// wait at once, so code that checks for work with interrupts disabled, and
+// then waits, never sleeps through the interrupt that brought the work.
+pub fn wait_for_interrupt() {
+  target::wait_for_interrupt()
+}
+
+// Exits the emulator that the machine is running in with `code`, where the
+// emulator supports it, for automated testing.
+//
+// Like `halt`, this never returns; without an emulator to exit, the machine
+// is halted or powered off.
+pub fn exit_emulator(code: u32) -> ! {
+  target::exit_emulator(code)
+}
+
+// Reads the CPU's free-running cycle counter.
+//
+// The counter only ever increases, at a rate that is constant but specific to
+// the machine; it must be calibrated against a known delay to measure time.
+pub fn cycle_counter() -> u64 {
+  target::cycle_counter()
+}
+
+// Reads the cycle counter to start a measurement, once every instruction
+// before it has completed, so that none of the code before is counted.
+pub fn cycle_counter_begin() -> u64 {
+  target::cycle_counter_begin()
+}
+
+// Reads the cycle counter to end a measurement, once every instruction before
+// it has completed, and before any after it starts, so that all of the code
+// measured is counted and none of the code after.
+pub fn cycle_counter_end() -> u64 {
+  target::cycle_counter_end()
+}
+
+// Returns the rate of the cycle counter, in ticks per second, where the
+// machine reports it.
+//
+// Where it does not, the counter must be calibrated against another clock.
+pub fn counter_frequency() -> Option<u64> {
+  target::counter_frequency()
+}
+
+// Returns the quotient and remainder of `a * b / c`, computed without
+// losing the high bits of the product, or `None` if `c` is zero or the
+// quotient does not fit in 64 bits.
+//
+// x86-64 divides the 128-bit product in one instruction, which is much faster
+// than the generic division of 128-bit integers.
+pub fn mul_div(a: u64, b: u64, c: u64) -> Option<(u64, u64)> {
+  target::mul_div(a, b, c)
+}
+
+// Returns 64 bits from the processor's random number generator, or `None` if
+// it has none, or it failed to produce any after a few tries.
+//
+// This is RDRAND on x86-64, and RNDR on AArch64. Its output is meant to seed a
+// deterministic generator, rather than to be used directly.
+pub fn hardware_random() -> Option<u64> {
+  target::hardware_random()
+}
@@ -0,0 +1,136 @@
+//! This module provides [`MmioRegion`], the window of a memory-mapped device,
+//! through which the device's registers are accessed.
+//!
+//! A region is mapped from the device's physical range by a [`Mapper`], which
+//! is the paging layer of whatever is running, and then hands out references
+//! to blocks of registers within it, checking that each block lies wholly
+//! inside the window:
+//!
+//! ```ignore
+//! #[repr(C)]
+//! struct Uart {
+//!   data: Volatile<u32>,
+//!   status: ReadOnly<u32>,
+//! }
+//!
+//! let region = unsafe { MmioRegion::map(&mut mapper, UART_BASE, 0x1000)? };
+//! let uart = region.registers::<Uart>(0)?;
+//! uart.data.write(b'a' as u32);
+//! ```
+//!
+//! Register blocks should be made of the wrappers in
+//! [`volatile`](crate::volatile), so that every access reaches the device.
+
+use crate::paging::{Frame, FrameRange, Page, PAGE_SIZE};
+use core::fmt;
+use core::mem;
+
+/// A paging layer that can map the memory of devices.
+pub trait Mapper {
+  /// Maps `frames` for access to a device, without caching, returning the
+  /// first page of where they are mapped, or `None` if they could not be
+  /// mapped.
+  ///
+  /// # Arguments
+  ///
+  /// * `frames` - the frames of the device's memory
+  fn map_device(&mut self, frames: FrameRange) -> Option<Page>;
+}
+
+/// A [`Mapper`] for when all of physical memory is already mapped at the same
+/// virtual addresses, with devices uncached, as it is by UEFI firmware.
+pub struct Identity;
+
+impl Mapper for Identity {
+  fn map_device(&mut self, frames: FrameRange) -> Option<Page> {
+    Page::from_start_address(frames.start.start_address())
+  }
+}
+
+/// The mapped window of a memory-mapped device.
+///
+/// The mapping is kept for as long as the system runs, as devices are not
+/// unmapped.
+pub struct MmioRegion {
+  phys: u64,
+  virt: u64,
+  len: u64,
+}
+
+impl MmioRegion {
+  /// Maps the `len` bytes of device memory at `phys` through `mapper`,
+  /// returning `None` if they could not be mapped.
+  ///
+  /// # Arguments
+  ///
+  /// * `mapper` - the paging layer to map the memory with
+  /// * `phys` - the physical address of the device's window
+  /// * `len` - the size of the device's window
+  ///
+  /// # Safety
+  ///
+  /// The memory must belong to a device, which nothing else accesses in ways
+  /// that conflict with the users of the region.
+  pub unsafe fn map(
+    mapper: &mut impl Mapper,
+    phys: u64,
+    len: u64,
+  ) -> Option<Self> {
+    let end = phys.checked_add(len)?;
+    let page = mapper.map_device(Frame::covering(phys..end))?;
+    Some(Self {
+      phys,
+      virt: page.start_address() + phys % PAGE_SIZE,
+      len,
+    })
+  }
+
+  /// Returns the physical address of the window.
+  pub fn phys(&self) -> u64 {
+    self.phys
+  }
+
+  /// Returns the virtual address that the window is mapped at.
+  pub fn virt(&self) -> u64 {
+    self.virt
+  }
+
+  /// Returns the size of the window.
+  pub fn len(&self) -> u64 {
+    self.len
+  }
+
+  /// Returns whether the window is empty.
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Returns the block of registers `T` at `offset` into the window, or
+  /// `None` if it does not lie wholly within the window, or is misaligned.
+  ///
+  /// # Arguments
+  ///
+  /// * `offset` - the offset of the block from the start of the window
+  pub fn registers<T>(&self, offset: u64) -> Option<&T> {
+    let end = offset.checked_add(mem::size_of::<T>() as u64)?;
+    let address = self.virt + offset;
+    if end > self.len || address % mem::align_of::<T>() as u64 != 0 {
+      return None;
+    }
+    // SAFETY: the block lies within the window, which is mapped for as long
+    // as the system runs, and is suitably aligned.
+    Some(unsafe { &*(address as *const T) })
+  }
+}
+
+impl fmt::Debug for MmioRegion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "MmioRegion({:#x}..{:#x} at {:#x})",
+      self.phys,
+      self.phys + self.len,
+      self.virt
+    )
+  }
+}
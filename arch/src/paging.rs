@@ -0,0 +1,52 @@
+//! This module defines the architecture-independent description of page
+//! mappings, which each architecture's `paging` module encodes into its own
+//! page table entries.
+//!
+//! Every supported architecture translates 48-bit virtual addresses through
+//! four levels of 512-entry tables of 4 KiB pages, with the level above the
+//! last able to map a 2 MiB block directly. The tables are therefore walked
+//! the same way everywhere; only the bits of the entries differ.
+
+mod page;
+
+pub use page::{
+  Frame, FrameRange, Page, PageRange, PageSize, Size1GiB, Size2MiB, Size4KiB,
+};
+
+/// The size of a page.
+pub const PAGE_SIZE: u64 = 4096;
+
+/// The size of a block mapped directly by an entry of the level above the
+/// last.
+pub const HUGE_PAGE_SIZE: u64 = 512 * PAGE_SIZE;
+
+/// The number of entries in each page table.
+pub const ENTRIES: usize = 512;
+
+/// The lowest bit of a virtual address translated by each level, from the
+/// root table down.
+pub const LEVEL_SHIFTS: [u32; 4] = [39, 30, 21, 12];
+
+/// The access permitted to a mapping.
+#[derive(Clone, Copy)]
+pub struct PageFlags {
+  /// The memory may be written to.
+  pub writable: bool,
+
+  /// The memory may be executed from.
+  pub executable: bool,
+}
+
+impl PageFlags {
+  /// Memory that may be read and written, but not executed.
+  pub const READ_WRITE: Self = Self {
+    writable: true,
+    executable: false,
+  };
+
+  /// Memory that may be read, written, and executed.
+  pub const ALL: Self = Self {
+    writable: true,
+    executable: true,
+  };
+}
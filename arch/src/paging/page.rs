@@ -0,0 +1,409 @@
+//! This module defines [`Page`]s of virtual memory and [`Frame`]s of physical
+//! memory, each of one of the sizes that the page tables can map, along with
+//! ranges of them to iterate over.
+//!
+//! Both are plain addresses underneath, but keeping them apart, and keeping
+//! their size in their type, makes it impossible to map a frame where a page
+//! was meant, or to map a 4 KiB frame as a 2 MiB one.
+
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::ops::{Add, AddAssign, Range, RangeInclusive, Sub, SubAssign};
+
+/// A size of page that the page tables can map.
+pub trait PageSize: 'static {
+  /// The size of the page, in bytes, which is a power of two.
+  const SIZE: u64;
+
+  /// The name of the size, for display.
+  const NAME: &'static str;
+}
+
+/// The size of a page mapped by the last level of the page tables.
+pub enum Size4KiB {}
+
+/// The size of a block mapped directly by the level above the last.
+pub enum Size2MiB {}
+
+/// The size of a block mapped directly by the level two above the last.
+pub enum Size1GiB {}
+
+impl PageSize for Size4KiB {
+  const SIZE: u64 = super::PAGE_SIZE;
+  const NAME: &'static str = "4KiB";
+}
+
+impl PageSize for Size2MiB {
+  const SIZE: u64 = super::HUGE_PAGE_SIZE;
+  const NAME: &'static str = "2MiB";
+}
+
+impl PageSize for Size1GiB {
+  const SIZE: u64 = super::HUGE_PAGE_SIZE * 512;
+  const NAME: &'static str = "1GiB";
+}
+
+/// Defines a type of page, with `$what` naming the memory it is of, along with
+/// its range type.
+macro_rules! define_page {
+  ($(#[$doc:meta])* $name:ident, $range:ident, $what:literal) => {
+    $(#[$doc])*
+    pub struct $name<S: PageSize = Size4KiB> {
+      address: u64,
+      _size: PhantomData<S>,
+    }
+
+    impl<S: PageSize> $name<S> {
+      #[doc = concat!("The size of the ", $what, ", in bytes.")]
+      pub const SIZE: u64 = S::SIZE;
+
+      #[doc = concat!("Returns the ", $what, " that contains `address`.")]
+      ///
+      /// # Arguments
+      ///
+      /// * `address` - any address within it
+      pub const fn containing_address(address: u64) -> Self {
+        Self {
+          address: address & !(S::SIZE - 1),
+          _size: PhantomData,
+        }
+      }
+
+      #[doc = concat!(
+        "Returns the ", $what, " that starts at `address`, or `None` if ",
+        "`address` is not aligned to its size."
+      )]
+      ///
+      /// # Arguments
+      ///
+      /// * `address` - the address of its first byte
+      pub const fn from_start_address(address: u64) -> Option<Self> {
+        if address % S::SIZE != 0 {
+          return None;
+        }
+        Some(Self::containing_address(address))
+      }
+
+      #[doc = concat!("Returns the ", $what, " numbered `number`.")]
+      ///
+      /// # Arguments
+      ///
+      /// * `number` - the start address divided by the size
+      pub const fn from_number(number: u64) -> Self {
+        Self::containing_address(number * S::SIZE)
+      }
+
+      /// Returns the address of the first byte.
+      pub const fn start_address(self) -> u64 {
+        self.address
+      }
+
+      /// Returns the address just past the last byte.
+      pub const fn end_address(self) -> u64 {
+        self.address + S::SIZE
+      }
+
+      /// Returns the start address divided by the size.
+      pub const fn number(self) -> u64 {
+        self.address / S::SIZE
+      }
+
+      #[doc = concat!(
+        "Returns an iterator over every ", $what, " from `range.start` up ",
+        "to, but not including, `range.end`."
+      )]
+      ///
+      /// # Arguments
+      ///
+      /// * `range` - the range to iterate over
+      pub const fn range(range: Range<Self>) -> $range<S> {
+        $range {
+          start: range.start,
+          end: range.end,
+        }
+      }
+
+      #[doc = concat!(
+        "Returns an iterator over every ", $what, " from `range.start` up ",
+        "to and including `range.end`."
+      )]
+      ///
+      /// # Arguments
+      ///
+      /// * `range` - the range to iterate over
+      pub fn range_inclusive(range: RangeInclusive<Self>) -> $range<S> {
+        $range {
+          start: *range.start(),
+          end: *range.end() + 1,
+        }
+      }
+
+      #[doc = concat!(
+        "Returns an iterator over every ", $what, " that holds any of ",
+        "the addresses of `range`."
+      )]
+      ///
+      /// # Arguments
+      ///
+      /// * `range` - the addresses to cover
+      pub fn covering(range: Range<u64>) -> $range<S> {
+        let start = Self::containing_address(range.start);
+        if range.is_empty() {
+          return Self::range(start..start);
+        }
+        let end =
+          Self::containing_address(range.end.saturating_add(S::SIZE - 1));
+        Self::range(start..end)
+      }
+    }
+
+    impl<S: PageSize> Clone for $name<S> {
+      fn clone(&self) -> Self {
+        *self
+      }
+    }
+
+    impl<S: PageSize> Copy for $name<S> {}
+
+    impl<S: PageSize> PartialEq for $name<S> {
+      fn eq(&self, other: &Self) -> bool {
+        self.address == other.address
+      }
+    }
+
+    impl<S: PageSize> Eq for $name<S> {}
+
+    impl<S: PageSize> PartialOrd for $name<S> {
+      fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+      }
+    }
+
+    impl<S: PageSize> Ord for $name<S> {
+      fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.address.cmp(&other.address)
+      }
+    }
+
+    impl<S: PageSize> Hash for $name<S> {
+      fn hash<H: Hasher>(&self, state: &mut H) {
+        self.address.hash(state);
+      }
+    }
+
+    impl<S: PageSize> fmt::Debug for $name<S> {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+          f,
+          concat!(stringify!($name), "[{}]({:#x})"),
+          S::NAME,
+          self.address
+        )
+      }
+    }
+
+    impl<S: PageSize> Add<u64> for $name<S> {
+      type Output = Self;
+
+      /// Returns the one `rhs` further along.
+      fn add(self, rhs: u64) -> Self {
+        Self::containing_address(self.address + rhs * S::SIZE)
+      }
+    }
+
+    impl<S: PageSize> AddAssign<u64> for $name<S> {
+      fn add_assign(&mut self, rhs: u64) {
+        *self = *self + rhs;
+      }
+    }
+
+    impl<S: PageSize> Sub<u64> for $name<S> {
+      type Output = Self;
+
+      /// Returns the one `rhs` further back.
+      fn sub(self, rhs: u64) -> Self {
+        Self::containing_address(self.address - rhs * S::SIZE)
+      }
+    }
+
+    impl<S: PageSize> SubAssign<u64> for $name<S> {
+      fn sub_assign(&mut self, rhs: u64) {
+        *self = *self - rhs;
+      }
+    }
+
+    impl<S: PageSize> Sub<Self> for $name<S> {
+      type Output = u64;
+
+      /// Returns how many lie between `rhs` and this one.
+      fn sub(self, rhs: Self) -> u64 {
+        (self.address - rhs.address) / S::SIZE
+      }
+    }
+
+    #[doc = concat!(
+      "An iterator over a range of each ", $what, " in address order."
+    )]
+    pub struct $range<S: PageSize = Size4KiB> {
+      /// The first that has not been returned from the front.
+      pub start: $name<S>,
+
+      /// The first past those that have not been returned from the back.
+      pub end: $name<S>,
+    }
+
+    impl<S: PageSize> Clone for $range<S> {
+      fn clone(&self) -> Self {
+        Self {
+          start: self.start,
+          end: self.end,
+        }
+      }
+    }
+
+    impl<S: PageSize> fmt::Debug for $range<S> {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}..{:?}", self.start, self.end)
+      }
+    }
+
+    impl<S: PageSize> Iterator for $range<S> {
+      type Item = $name<S>;
+
+      fn next(&mut self) -> Option<Self::Item> {
+        if self.start >= self.end {
+          return None;
+        }
+        let next = self.start;
+        self.start += 1;
+        Some(next)
+      }
+
+      fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = if self.start >= self.end {
+          0
+        } else {
+          (self.end - self.start) as usize
+        };
+        (len, Some(len))
+      }
+    }
+
+    impl<S: PageSize> DoubleEndedIterator for $range<S> {
+      fn next_back(&mut self) -> Option<Self::Item> {
+        if self.start >= self.end {
+          return None;
+        }
+        self.end -= 1;
+        Some(self.end)
+      }
+    }
+
+    impl<S: PageSize> ExactSizeIterator for $range<S> {}
+
+    impl<S: PageSize> FusedIterator for $range<S> {}
+  };
+}
+
+define_page!(
+  /// A page of virtual memory, of size `S`.
+  Page,
+  PageRange,
+  "page"
+);
+
+define_page!(
+  /// A frame of physical memory, of size `S`.
+  Frame,
+  FrameRange,
+  "frame"
+);
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use crate::paging::HUGE_PAGE_SIZE;
+  use std::format;
+  use std::vec::Vec;
+
+  #[test]
+  fn pages_contain_unaligned_addresses() {
+    let page = Page::<Size4KiB>::containing_address(0x1234);
+
+    assert_eq!(page.start_address(), 0x1000);
+    assert_eq!(page.end_address(), 0x2000);
+    assert_eq!(page.number(), 1);
+    assert_eq!(Page::<Size4KiB>::containing_address(0x1fff), page);
+    assert_eq!(Page::<Size4KiB>::containing_address(0x2000).number(), 2);
+    assert_eq!(Frame::<Size2MiB>::containing_address(0x3f_ffff).number(), 1);
+    assert_eq!(
+      Frame::<Size1GiB>::containing_address(u64::MAX).start_address(),
+      0xffff_ffff_c000_0000
+    );
+  }
+
+  #[test]
+  fn start_addresses_must_be_aligned() {
+    assert_eq!(
+      Page::<Size4KiB>::from_start_address(0x2000),
+      Some(Page::from_number(2))
+    );
+    assert_eq!(Page::<Size4KiB>::from_start_address(0x2001), None);
+    assert_eq!(Frame::<Size2MiB>::from_start_address(0x1000), None);
+    assert_eq!(
+      Frame::<Size2MiB>::from_start_address(0).map(Frame::end_address),
+      Some(HUGE_PAGE_SIZE)
+    );
+  }
+
+  #[test]
+  fn pages_step_by_their_size() {
+    let mut page = Page::<Size2MiB>::from_number(3);
+
+    assert_eq!((page + 2).number(), 5);
+    assert_eq!((page - 3).start_address(), 0);
+    assert_eq!(page + 4 - page, 4);
+    page += 1;
+    page -= 2;
+    assert_eq!(page.number(), 2);
+    assert_eq!(format!("{:?}", page), "Page[2MiB](0x400000)");
+  }
+
+  #[test]
+  fn ranges_exclude_or_include_their_end() {
+    let start = Frame::<Size4KiB>::from_number(1);
+    let end = Frame::from_number(4);
+
+    let numbers: Vec<_> = Frame::range(start..end).map(Frame::number).collect();
+    assert_eq!(numbers, [1, 2, 3]);
+    assert_eq!(Frame::range_inclusive(start..=end).len(), 4);
+    assert_eq!(Frame::range(end..start).len(), 0);
+    assert_eq!(Frame::range(start..start).next(), None);
+
+    let mut range = Frame::range(start..end);
+    assert_eq!(range.next_back(), Some(Frame::from_number(3)));
+    assert_eq!(range.next(), Some(start));
+    assert_eq!(range.len(), 1);
+  }
+
+  #[test]
+  fn covering_ranges_round_out_to_whole_pages() {
+    let numbers =
+      |range: PageRange| range.map(Page::number).collect::<Vec<_>>();
+
+    assert_eq!(numbers(Page::covering(0x1fff..0x2001)), [1, 2]);
+    assert_eq!(numbers(Page::covering(0x1000..0x3000)), [1, 2]);
+    assert_eq!(numbers(Page::covering(0x1000..0x1001)), [1]);
+    assert_eq!(Page::<Size4KiB>::covering(0x1800..0x1800).len(), 0);
+    let (start, end) = (0x3000, 0x1000);
+    assert_eq!(Page::<Size4KiB>::covering(start..end).len(), 0);
+    assert_eq!(
+      Page::<Size2MiB>::covering(HUGE_PAGE_SIZE - 1..HUGE_PAGE_SIZE + 1).len(),
+      2
+    );
+  }
+}
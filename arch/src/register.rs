@@ -0,0 +1,410 @@
+//! This module provides the [`register!`](crate::register!) macro, which
+//! declares the layout of a hardware register or of a structure like it, such
+//! as a page table entry, as named fields of bits.
+//!
+//! Each field is a constant [`Field`] of the register type, which carries the
+//! type of its value and whether it may be read or written, so that reading a
+//! write-only field or writing a read-only one fails to compile:
+//!
+//! ```ignore
+//! arch::register! {
+//!   /// A page table entry.
+//!   pub struct Entry(u64) {
+//!     /// The entry maps a page or table.
+//!     pub PRESENT: bool = 0, rw;
+//!     /// The number of the frame that the entry refers to.
+//!     pub FRAME: u64 = 12..52, rw;
+//!     /// Bits that the processor ignores, and software may use.
+//!     pub AVAILABLE: u64 = 52..59, reserved;
+//!   }
+//! }
+//!
+//! let entry = Entry::new(0).with(Entry::PRESENT, true).with(Entry::FRAME, 5);
+//! assert_eq!(entry.get(Entry::FRAME), 5);
+//! ```
+//!
+//! Fields are `rw` (read-write), `ro` (read-only), `wo` (write-only) or
+//! `reserved`, which may be neither read nor written through the field, and
+//! whose bits are collected in the register's `RESERVED` mask. Single bits
+//! are given by their index, and wider fields by the range of their bits,
+//! excluding the end.
+
+use core::fmt;
+use core::marker::PhantomData;
+
+/// Whether a [`Field`] may be read or written.
+pub trait Access {}
+
+/// An [`Access`] that permits reading.
+pub trait Readable: Access {}
+
+/// An [`Access`] that permits writing.
+pub trait Writable: Access {}
+
+/// The access of a field that may be both read and written.
+pub enum ReadWrite {}
+
+/// The access of a field that may only be read.
+pub enum ReadOnly {}
+
+/// The access of a field that may only be written.
+pub enum WriteOnly {}
+
+/// The access of a field that is reserved, and may be neither read nor
+/// written.
+pub enum Reserved {}
+
+impl Access for ReadWrite {}
+impl Access for ReadOnly {}
+impl Access for WriteOnly {}
+impl Access for Reserved {}
+impl Readable for ReadWrite {}
+impl Readable for ReadOnly {}
+impl Writable for ReadWrite {}
+impl Writable for WriteOnly {}
+
+/// A type that the value of a [`Field`] can have.
+///
+/// Values are converted from and to the bits of the field, shifted down to
+/// the least significant bits; bits beyond the width of the field are
+/// discarded on writing.
+pub trait Value: Sized {
+  /// Converts the bits of a field to a value.
+  ///
+  /// # Arguments
+  ///
+  /// * `bits` - the bits of the field
+  fn from_bits(bits: u64) -> Self;
+
+  /// Converts the value to the bits of a field.
+  fn into_bits(self) -> u64;
+}
+
+impl Value for bool {
+  fn from_bits(bits: u64) -> Self {
+    bits != 0
+  }
+
+  fn into_bits(self) -> u64 {
+    self as u64
+  }
+}
+
+macro_rules! impl_value {
+  ($($ty:ty),*) => {
+    $(
+      impl Value for $ty {
+        fn from_bits(bits: u64) -> Self {
+          bits as $ty
+        }
+
+        fn into_bits(self) -> u64 {
+          self as u64
+        }
+      }
+    )*
+  };
+}
+
+impl_value!(u8, u16, u32, u64, usize);
+
+/// A field of the register `R`, holding a `V`, with the access `A`.
+pub struct Field<R, V, A> {
+  shift: u32,
+  width: u32,
+  _field: PhantomData<(R, V, A)>,
+}
+
+impl<R, V, A> Field<R, V, A> {
+  /// Constructs a field of `width` bits, starting at bit `shift`.
+  ///
+  /// # Arguments
+  ///
+  /// * `shift` - the index of the least significant bit of the field
+  /// * `width` - the number of bits of the field
+  ///
+  /// # Panics
+  ///
+  /// Panics if the field is empty or extends beyond 64 bits.
+  pub const fn new(shift: u32, width: u32) -> Self {
+    assert!(width > 0 && shift + width <= 64, "field out of bounds");
+    Self {
+      shift,
+      width,
+      _field: PhantomData,
+    }
+  }
+
+  /// Returns the index of the least significant bit of the field.
+  pub const fn shift(&self) -> u32 {
+    self.shift
+  }
+
+  /// Returns the number of bits of the field.
+  pub const fn width(&self) -> u32 {
+    self.width
+  }
+
+  /// Returns the mask of the bits of the field, in place.
+  pub const fn mask(&self) -> u64 {
+    (u64::MAX >> (64 - self.width)) << self.shift
+  }
+}
+
+impl<R, V, A> Clone for Field<R, V, A> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+
+impl<R, V, A> Copy for Field<R, V, A> {}
+
+impl<R, V, A> fmt::Debug for Field<R, V, A> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.width {
+      1 => write!(f, "Field({})", self.shift),
+      width => write!(f, "Field({}..{})", self.shift, self.shift + width),
+    }
+  }
+}
+
+/// Declares the layout of a register; see the [module](crate::register).
+#[macro_export]
+macro_rules! register {
+  (
+    $(#[$meta:meta])*
+    $vis:vis struct $name:ident($bits:ty) {
+      $(
+        $(#[$field_meta:meta])*
+        $field_vis:vis $field:ident: $value:ty =
+          $low:literal $(.. $high:literal)?, $access:ident;
+      )*
+    }
+  ) => {
+    $(#[$meta])*
+    #[repr(transparent)]
+    #[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+    $vis struct $name($bits);
+
+    #[allow(dead_code)]
+    impl $name {
+      $(
+        $(#[$field_meta])*
+        $field_vis const $field: $crate::register::Field<
+          Self,
+          $value,
+          $crate::register!(@access $access),
+        > = $crate::register::Field::new(
+          $low,
+          $crate::register!(@width $low $($high)?),
+        );
+      )*
+
+      /// The mask of the reserved bits.
+      pub const RESERVED: $bits = 0 $(
+        | $crate::register!(@reserved $access, Self::$field.mask()) as $bits
+      )*;
+
+      /// Constructs the register from its raw bits.
+      ///
+      /// # Arguments
+      ///
+      /// * `bits` - the raw bits
+      pub const fn new(bits: $bits) -> Self {
+        Self(bits)
+      }
+
+      /// Returns the raw bits of the register.
+      pub const fn bits(self) -> $bits {
+        self.0
+      }
+
+      /// Returns the value of `field`.
+      ///
+      /// # Arguments
+      ///
+      /// * `field` - the field to read
+      pub fn get<V, A>(self, field: $crate::register::Field<Self, V, A>) -> V
+      where
+        V: $crate::register::Value,
+        A: $crate::register::Readable,
+      {
+        V::from_bits((self.0 as u64 & field.mask()) >> field.shift())
+      }
+
+      /// Sets `field` to `value`.
+      ///
+      /// # Arguments
+      ///
+      /// * `field` - the field to write
+      /// * `value` - the value to write
+      pub fn set<V, A>(
+        &mut self,
+        field: $crate::register::Field<Self, V, A>,
+        value: V,
+      ) where
+        V: $crate::register::Value,
+        A: $crate::register::Writable,
+      {
+        let bits = (value.into_bits() << field.shift()) & field.mask();
+        self.0 = ((self.0 as u64 & !field.mask()) | bits) as $bits;
+      }
+
+      /// Returns the register with `field` set to `value`.
+      ///
+      /// # Arguments
+      ///
+      /// * `field` - the field to write
+      /// * `value` - the value to write
+      pub fn with<V, A>(
+        mut self,
+        field: $crate::register::Field<Self, V, A>,
+        value: V,
+      ) -> Self
+      where
+        V: $crate::register::Value,
+        A: $crate::register::Writable,
+      {
+        self.set(field, value);
+        self
+      }
+    }
+
+    impl ::core::fmt::Debug for $name {
+      fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        let mut s = f.debug_struct(stringify!($name));
+        $(
+          $crate::register!(
+            @debug $access,
+            s,
+            stringify!($field),
+            (self.0 as u64 & Self::$field.mask()) >> Self::$field.shift()
+          );
+        )*
+        s.finish()
+      }
+    }
+  };
+
+  (@access rw) => { $crate::register::ReadWrite };
+  (@access ro) => { $crate::register::ReadOnly };
+  (@access wo) => { $crate::register::WriteOnly };
+  (@access reserved) => { $crate::register::Reserved };
+
+  (@width $low:literal) => { 1 };
+  (@width $low:literal $high:literal) => { $high - $low };
+
+  (@reserved reserved, $mask:expr) => { $mask };
+  (@reserved $access:ident, $mask:expr) => { 0 };
+
+  (@debug wo, $s:ident, $name:expr, $bits:expr) => {};
+  (@debug reserved, $s:ident, $name:expr, $bits:expr) => {};
+  (@debug $access:ident, $s:ident, $name:expr, $bits:expr) => {
+    $s.field($name, &format_args!("{:#x}", $bits));
+  };
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use std::format;
+
+  crate::register! {
+    /// A register of every kind of field.
+    struct Control(u32) {
+      /// A single read-write bit.
+      ENABLE: bool = 0, rw;
+      /// A read-write field of several bits.
+      MODE: u8 = 1..4, rw;
+      /// A read-only bit.
+      BUSY: bool = 4, ro;
+      /// A write-only field.
+      COMMAND: u8 = 8..12, wo;
+      /// Reserved bits.
+      RESERVED_HIGH: u32 = 24..32, reserved;
+      /// A field that overlaps both `COMMAND` and the bits above it.
+      WIDE: u16 = 8..20, rw;
+    }
+  }
+
+  crate::register! {
+    /// A register whose field spans all of it.
+    struct Whole(u64) {
+      /// Every bit.
+      ALL: u64 = 0..64, rw;
+      /// The top bit.
+      TOP: bool = 63, rw;
+    }
+  }
+
+  #[test]
+  fn fields_are_set_read_and_cleared() {
+    let mut control = Control::new(0).with(Control::ENABLE, true);
+    control.set(Control::MODE, 5);
+
+    assert!(control.get(Control::ENABLE));
+    assert_eq!(control.get(Control::MODE), 5);
+    assert_eq!(control.bits(), 0b1011);
+    control.set(Control::ENABLE, false);
+    control.set(Control::MODE, 0);
+    assert_eq!(control.bits(), 0);
+  }
+
+  #[test]
+  fn writes_are_truncated_to_the_field() {
+    let control = Control::new(0).with(Control::MODE, 0xff);
+
+    assert_eq!(control.get(Control::MODE), 0b111);
+    assert_eq!(control.bits(), 0b1110);
+    assert!(Control::new(1 << 4)
+      .with(Control::MODE, 0xff)
+      .get(Control::BUSY));
+    assert_eq!(Control::new(!0).with(Control::MODE, 0).bits(), !0b1110);
+  }
+
+  #[test]
+  fn read_only_and_write_only_fields_use_their_bits() {
+    let control = Control::new(1 << 4);
+    assert!(control.get(Control::BUSY));
+
+    let control = Control::new(0).with(Control::COMMAND, 0xa);
+    assert_eq!(control.bits(), 0xa00);
+  }
+
+  #[test]
+  fn overlapping_fields_share_their_bits() {
+    let control = Control::new(0).with(Control::WIDE, 0xfff);
+    let control = control.with(Control::COMMAND, 0);
+
+    assert_eq!(control.get(Control::WIDE), 0xff0);
+    assert_eq!(control.bits(), 0xff000);
+  }
+
+  #[test]
+  fn masks_cover_their_bits() {
+    assert_eq!(Control::ENABLE.mask(), 1);
+    assert_eq!(Control::MODE.mask(), 0b1110);
+    assert_eq!((Control::WIDE.shift(), Control::WIDE.width()), (8, 12));
+    assert_eq!(Control::RESERVED, 0xff00_0000);
+    assert_eq!(Whole::ALL.mask(), u64::MAX);
+    assert_eq!(Whole::TOP.mask(), 1 << 63);
+    assert_eq!(Whole::RESERVED, 0);
+
+    let whole = Whole::new(0).with(Whole::ALL, u64::MAX);
+    assert!(whole.get(Whole::TOP));
+    assert_eq!(whole.with(Whole::TOP, false).get(Whole::ALL), u64::MAX >> 1);
+  }
+
+  #[test]
+  fn debug_shows_only_readable_fields() {
+    let control = Control::new(0xffff_ffff);
+
+    assert_eq!(
+      format!("{:?}", control),
+      "Control { ENABLE: 0x1, MODE: 0x7, BUSY: 0x1, WIDE: 0xfff }"
+    );
+    assert_eq!(format!("{:?}", Control::MODE), "Field(1..4)");
+    assert_eq!(format!("{:?}", Control::BUSY), "Field(4)");
+  }
+}
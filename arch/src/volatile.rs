@@ -0,0 +1,211 @@
+//! This module provides wrappers for values that are accessed with volatile
+//! reads and writes, such as the registers of a memory-mapped device.
+//!
+//! A block of device registers is declared as a `#[repr(C)]` structure of
+//! these wrappers, and used through a reference to the device's memory:
+//!
+//! ```ignore
+//! #[repr(C)]
+//! struct Uart {
+//!   data: Volatile<u32>,
+//!   status: ReadOnly<u32>,
+//!   interrupt_clear: WriteOnly<u32>,
+//! }
+//!
+//! let uart = unsafe { &*(base as *const Uart) };
+//! uart.interrupt_clear.write(!0);
+//! while uart.status.read() & TX_FULL != 0 {}
+//! uart.data.write(b'a' as u32);
+//! ```
+//!
+//! Every access is made, exactly once and in program order, even where the
+//! compiler could otherwise see that a read is unused or that a write is
+//! overwritten; accesses are not ordered against other processors, for which
+//! see [`barrier`](crate::barrier). The values are plain integers or
+//! [`register!`](crate::register!) types, whose accesses the processor makes
+//! whole.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ptr;
+
+/// A value that may be both read and written.
+#[repr(transparent)]
+pub struct Volatile<T: Copy>(UnsafeCell<T>);
+
+/// A value that may only be read.
+#[repr(transparent)]
+pub struct ReadOnly<T: Copy>(UnsafeCell<T>);
+
+/// A value that may only be written.
+#[repr(transparent)]
+pub struct WriteOnly<T: Copy>(UnsafeCell<T>);
+
+impl<T: Copy> Volatile<T> {
+  /// Constructs the value, such as for memory shared with a device that is
+  /// allocated by software.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the initial value
+  pub const fn new(value: T) -> Self {
+    Self(UnsafeCell::new(value))
+  }
+
+  /// Reads the value.
+  #[inline(always)]
+  pub fn read(&self) -> T {
+    // SAFETY: the value is valid for as long as it is borrowed, and is only
+    // ever accessed whole.
+    unsafe { ptr::read_volatile(self.0.get()) }
+  }
+
+  /// Writes `value`.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to write
+  #[inline(always)]
+  pub fn write(&self, value: T) {
+    // SAFETY: the value is valid for as long as it is borrowed, and is only
+    // ever accessed whole.
+    unsafe { ptr::write_volatile(self.0.get(), value) }
+  }
+
+  /// Reads the value, and writes back the result of `f` on it.
+  ///
+  /// The read and the write are separate accesses, so a device or another
+  /// processor may change the value between them.
+  ///
+  /// # Arguments
+  ///
+  /// * `f` - computes the new value from the old one
+  #[inline(always)]
+  pub fn update(&self, f: impl FnOnce(T) -> T) {
+    self.write(f(self.read()));
+  }
+}
+
+impl<T: Copy> ReadOnly<T> {
+  /// Constructs the value, such as for memory shared with a device that is
+  /// allocated by software.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the initial value
+  pub const fn new(value: T) -> Self {
+    Self(UnsafeCell::new(value))
+  }
+
+  /// Reads the value.
+  #[inline(always)]
+  pub fn read(&self) -> T {
+    // SAFETY: the value is valid for as long as it is borrowed, and is only
+    // ever accessed whole.
+    unsafe { ptr::read_volatile(self.0.get()) }
+  }
+}
+
+impl<T: Copy> WriteOnly<T> {
+  /// Constructs the value, such as for memory shared with a device that is
+  /// allocated by software.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the initial value
+  pub const fn new(value: T) -> Self {
+    Self(UnsafeCell::new(value))
+  }
+
+  /// Writes `value`.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to write
+  #[inline(always)]
+  pub fn write(&self, value: T) {
+    // SAFETY: the value is valid for as long as it is borrowed, and is only
+    // ever accessed whole.
+    unsafe { ptr::write_volatile(self.0.get(), value) }
+  }
+}
+
+// SAFETY: every access is a single whole read or write of a `Send` value, so
+// sharing them between processors is no different to sharing the device.
+unsafe impl<T: Copy + Send> Sync for Volatile<T> {}
+unsafe impl<T: Copy + Send> Sync for ReadOnly<T> {}
+unsafe impl<T: Copy + Send> Sync for WriteOnly<T> {}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for Volatile<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_tuple("Volatile").field(&self.read()).finish()
+  }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for ReadOnly<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_tuple("ReadOnly").field(&self.read()).finish()
+  }
+}
+
+impl<T: Copy> fmt::Debug for WriteOnly<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // Reading the value may be meaningless, or have side effects.
+    f.write_str("WriteOnly(..)")
+  }
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::format;
+
+  /// A block of registers, laid over an ordinary buffer.
+  #[repr(C)]
+  struct Registers {
+    data: Volatile<u32>,
+    status: ReadOnly<u32>,
+    command: WriteOnly<u32>,
+  }
+
+  crate::register! {
+    /// A register of a single field.
+    struct Status(u16) {
+      /// Every bit.
+      VALUE: u16 = 0..16, rw;
+    }
+  }
+
+  #[test]
+  fn accesses_go_through_to_memory() {
+    let mut memory = [1u32, 2, 3];
+    // SAFETY: the buffer is as large and aligned as the registers, and is
+    // only accessed through them while they are borrowed.
+    let registers = unsafe { &*memory.as_mut_ptr().cast::<Registers>() };
+
+    assert_eq!(registers.data.read(), 1);
+    assert_eq!(registers.status.read(), 2);
+    registers.data.write(10);
+    registers.command.write(30);
+    registers.data.update(|value| value + 1);
+    assert_eq!(memory, [11, 2, 30]);
+  }
+
+  #[test]
+  fn owned_values_hold_registers() {
+    let status = Volatile::new(Status::new(0));
+    status.update(|status| status.with(Status::VALUE, 0xbeef));
+
+    assert_eq!(status.read().bits(), 0xbeef);
+    assert_eq!(ReadOnly::new(7u8).read(), 7);
+  }
+
+  #[test]
+  fn debug_never_reads_write_only_values() {
+    assert_eq!(format!("{:?}", Volatile::new(5u32)), "Volatile(5)");
+    assert_eq!(format!("{:?}", ReadOnly::new(6u32)), "ReadOnly(6)");
+    assert_eq!(format!("{:?}", WriteOnly::new(7u32)), "WriteOnly(..)");
+  }
+}
@@ -1,3 +1,13 @@
+pub mod debug;
+pub mod paging;
+
+/// The ELF machine type of executables for x86-64.
+pub const ELF_MACHINE: u16 = 62;
+
+/// The ELF relocation type `R_X86_64_RELATIVE`, which adds the load slide to an
+/// address.
+pub const ELF_RELATIVE: u32 = 8;
+
 #[cfg(target_arch = "x86_64")]
 #[inline(always)]
 pub fn halt() -> ! {
@@ -5,3 +15,208 @@ pub fn halt() -> ! {
     unsafe { core::arch::asm!("cli; hlt") };
   }
 }
+
+/// The I/O port of QEMU's `isa-debug-exit` device, as it is configured for
+/// testing.
+const DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// Exits QEMU through its `isa-debug-exit` device, which makes QEMU exit with
+/// the status `code << 1 | 1`, halting if there is no such device.
+#[cfg(target_arch = "x86_64")]
+pub fn exit_emulator(code: u32) -> ! {
+  // SAFETY: nothing but the debug exit device is expected at the port, and
+  // writes to an unused port are ignored.
+  unsafe {
+    core::arch::asm!("out dx, eax", in("dx") DEBUG_EXIT_PORT, in("eax") code)
+  };
+  halt()
+}
+
+#[inline(always)]
+pub fn cycle_counter() -> u64 {
+  // SAFETY: the timestamp counter is readable at every privilege level that
+  // this runs at.
+  unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[inline(always)]
+pub fn cycle_counter_begin() -> u64 {
+  // SAFETY: as for `cycle_counter`. The fence keeps the read from starting
+  // before the instructions ahead of it have completed.
+  unsafe {
+    core::arch::x86_64::_mm_lfence();
+    core::arch::x86_64::_rdtsc()
+  }
+}
+
+#[inline(always)]
+pub fn cycle_counter_end() -> u64 {
+  // SAFETY: as for `cycle_counter`. The fences keep the read after the code
+  // measured, and the code that follows after the read; `lfence` is used
+  // rather than `rdtscp`, which not every processor has.
+  unsafe {
+    core::arch::x86_64::_mm_lfence();
+    let value = core::arch::x86_64::_rdtsc();
+    core::arch::x86_64::_mm_lfence();
+    value
+  }
+}
+
+/// Returns the frequency of the timestamp counter, where the processor
+/// reports it in the TSC and core crystal clock leaf of CPUID.
+#[inline(always)]
+pub fn counter_frequency() -> Option<u64> {
+  use core::arch::x86_64::__cpuid;
+  // SAFETY: every x86-64 processor has CPUID, and the clock leaf is only read
+  // when the processor reports it. Newer toolchains consider CPUID safe.
+  #[allow(unused_unsafe)]
+  let leaf = unsafe {
+    if __cpuid(0).eax < 0x15 {
+      return None;
+    }
+    __cpuid(0x15)
+  };
+  if leaf.eax == 0 || leaf.ebx == 0 || leaf.ecx == 0 {
+    return None;
+  }
+  Some(leaf.ecx as u64 * leaf.ebx as u64 / leaf.eax as u64)
+}
+
+/// The bit of ECX in the feature leaf of CPUID that reports RDRAND.
+const CPUID_RDRAND: u32 = 1 << 30;
+
+/// The number of times RDRAND is retried before it is taken to have failed,
+/// as Intel recommends.
+const RDRAND_RETRIES: usize = 10;
+
+/// Returns 64 bits from RDRAND, where the processor has it.
+#[inline(always)]
+pub fn hardware_random() -> Option<u64> {
+  use core::arch::x86_64::{__cpuid, _rdrand64_step};
+  // SAFETY: every x86-64 processor has CPUID. Newer toolchains consider CPUID
+  // safe.
+  #[allow(unused_unsafe)]
+  let features = unsafe { __cpuid(1).ecx };
+  if features & CPUID_RDRAND == 0 {
+    return None;
+  }
+  let mut value = 0;
+  // SAFETY: the processor reports RDRAND.
+  (0..RDRAND_RETRIES)
+    .any(|_| unsafe { _rdrand64_step(&mut value) } == 1)
+    .then_some(value)
+}
+
+/// Returns the quotient and remainder of `a * b / c`, or `None` if `c` is
+/// zero or the quotient does not fit in 64 bits.
+///
+/// `mul` leaves the whole product in RDX:RAX, which `div` divides directly;
+/// it would fault on a quotient that does not fit, which is ruled out first.
+#[inline(always)]
+pub fn mul_div(a: u64, b: u64, c: u64) -> Option<(u64, u64)> {
+  let (mut low, mut high): (u64, u64);
+  // SAFETY: multiplying only writes the named registers.
+  unsafe {
+    core::arch::asm!(
+      "mul {b}",
+      b = in(reg) b,
+      inout("rax") a => low,
+      out("rdx") high,
+      options(pure, nomem, nostack),
+    )
+  };
+  if high >= c {
+    return None;
+  }
+  // SAFETY: the divisor is greater than the high half of the dividend, so it
+  // is not zero and the quotient fits.
+  unsafe {
+    core::arch::asm!(
+      "div {c}",
+      c = in(reg) c,
+      inout("rax") low,
+      inout("rdx") high,
+      options(pure, nomem, nostack),
+    )
+  };
+  Some((low, high))
+}
+
+/// Returns the ID of the running processor's local APIC, as its x2APIC ID
+/// where the processor reports one.
+#[inline(always)]
+pub fn processor_id() -> u64 {
+  use core::arch::x86_64::__cpuid;
+  // SAFETY: every x86-64 processor has CPUID, and the topology leaf is only
+  // read when the processor reports it. Newer toolchains consider CPUID safe.
+  #[allow(unused_unsafe)]
+  unsafe {
+    if __cpuid(0).eax >= 0xb && __cpuid(0xb).ebx != 0 {
+      __cpuid(0xb).edx as u64
+    } else {
+      (__cpuid(1).ebx >> 24) as u64
+    }
+  }
+}
+
+/// The interrupt flag of RFLAGS, which is set while maskable interrupts are
+/// enabled.
+const RFLAGS_IF: u64 = 1 << 9;
+
+/// Disables maskable interrupts on the running processor, returning whether
+/// they were enabled.
+#[inline(always)]
+pub fn disable_interrupts() -> bool {
+  let flags: u64;
+  // SAFETY: reading RFLAGS and clearing the interrupt flag only affect the
+  // running processor. Memory is not marked untouched, so that accesses are
+  // not moved out of the critical section.
+  unsafe { core::arch::asm!("pushfq; pop {}; cli", out(reg) flags) };
+  flags & RFLAGS_IF != 0
+}
+
+/// Enables maskable interrupts on the running processor.
+#[inline(always)]
+pub fn enable_interrupts() {
+  // SAFETY: as for `disable_interrupts`; whoever enables interrupts must be
+  // ready to take them.
+  unsafe { core::arch::asm!("sti") };
+}
+
+/// Enables maskable interrupts on the running processor, and halts it until
+/// the next one arrives.
+///
+/// `sti` only takes effect after the instruction that follows it, so an
+/// interrupt that became pending while they were disabled ends the `hlt`
+/// rather than being taken just before it.
+#[inline(always)]
+pub fn wait_for_interrupt() {
+  // SAFETY: as for `enable_interrupts`; halting only affects the running
+  // processor.
+  unsafe { core::arch::asm!("sti; hlt") };
+}
+
+/// Orders loads before the barrier ahead of loads after it.
+///
+/// x86-64 never reorders loads with other loads, so only the compiler needs
+/// to be kept from reordering them.
+#[inline(always)]
+pub fn read_barrier() {
+  core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+/// Orders stores before the barrier ahead of stores after it.
+///
+/// x86-64 never reorders stores with other stores, so only the compiler needs
+/// to be kept from reordering them.
+#[inline(always)]
+pub fn write_barrier() {
+  core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+/// Orders all memory accesses before the barrier ahead of those after it.
+#[inline(always)]
+pub fn full_barrier() {
+  // SAFETY: a fence only orders memory accesses.
+  unsafe { core::arch::asm!("mfence", options(nostack, preserves_flags)) };
+}
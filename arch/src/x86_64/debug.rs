@@ -0,0 +1,101 @@
+//! This module provides the x86-64 debug registers: the addresses of the four
+//! hardware breakpoints in DR0 to DR3, and their conditions in DR7.
+
+use crate::debug::Watch;
+
+/// The number of hardware breakpoints.
+pub const BREAKPOINTS: usize = 4;
+
+/// The instruction that software breakpoints are written as, `int3`.
+pub const BREAKPOINT_INSTRUCTION: &[u8] = &[0xcc];
+
+/// The bit of DR7 that enables breakpoint 0 locally; that of breakpoint `n`
+/// is shifted by `2n` bits.
+const DR7_ENABLE: u64 = 1 << 0;
+
+/// The shift of the condition and length fields of breakpoint 0 in DR7; those
+/// of breakpoint `n` are shifted by a further `4n` bits.
+const DR7_CONDITION_SHIFT: usize = 16;
+
+/// Traps on the hardware breakpoint `index`, returning `false` if there is no
+/// such breakpoint, or the range cannot be watched.
+///
+/// Ranges that are watched must be 1, 2, 4 or 8 bytes long and aligned to
+/// their length; breakpoints on execution are always one byte.
+///
+/// # Arguments
+///
+/// * `index` - the breakpoint, below [`BREAKPOINTS`]
+/// * `address` - the virtual address of the first byte
+/// * `watch` - the accesses to trap on
+/// * `len` - the number of bytes to watch
+///
+/// # Safety
+///
+/// This must be called at privilege level 0, with a handler for the debug
+/// exception installed.
+pub unsafe fn set_breakpoint(
+  index: usize,
+  address: u64,
+  watch: Watch,
+  len: usize,
+) -> bool {
+  let condition: u64 = match watch {
+    Watch::Execute => 0b00,
+    Watch::Write => 0b01,
+    Watch::ReadWrite => 0b11,
+  };
+  let length: u64 = match len {
+    _ if watch == Watch::Execute => 0b00,
+    _ if address % len.max(1) as u64 != 0 => return false,
+    1 => 0b00,
+    2 => 0b01,
+    4 => 0b11,
+    8 => 0b10,
+    _ => return false,
+  };
+  if !write_address(index, address) {
+    return false;
+  }
+  let enable = DR7_ENABLE << (2 * index);
+  let shift = DR7_CONDITION_SHIFT + 4 * index;
+  let dr7 = read_dr7() & !(0b1111 << shift) | enable;
+  write_dr7(dr7 | (length << 2 | condition) << shift);
+  true
+}
+
+/// Stops trapping on the hardware breakpoint `index`, if there is one.
+///
+/// # Arguments
+///
+/// * `index` - the breakpoint, below [`BREAKPOINTS`]
+///
+/// # Safety
+///
+/// This must be called at privilege level 0.
+pub unsafe fn clear_breakpoint(index: usize) {
+  if index < BREAKPOINTS {
+    write_dr7(read_dr7() & !(DR7_ENABLE << (2 * index)));
+  }
+}
+
+unsafe fn write_address(index: usize, address: u64) -> bool {
+  match index {
+    0 => core::arch::asm!("mov dr0, {}", in(reg) address),
+    1 => core::arch::asm!("mov dr1, {}", in(reg) address),
+    2 => core::arch::asm!("mov dr2, {}", in(reg) address),
+    3 => core::arch::asm!("mov dr3, {}", in(reg) address),
+    _ => return false,
+  }
+  true
+}
+
+unsafe fn read_dr7() -> u64 {
+  let dr7: u64;
+  core::arch::asm!("mov {}, dr7", out(reg) dr7);
+  dr7
+}
+
+unsafe fn write_dr7(dr7: u64) {
+  core::arch::asm!("mov dr7, {}", in(reg) dr7);
+}
@@ -0,0 +1,151 @@
+//! This module provides the x86-64 encoding of page table entries for 4-level
+//! paging, and the switch onto a set of tables to enter a kernel.
+
+use crate::paging::PageFlags;
+
+crate::register! {
+  /// A page table entry.
+  struct Entry(u64) {
+    /// The entry maps a page or table.
+    PRESENT: bool = 0, rw;
+
+    /// The mapped memory may be written to.
+    WRITABLE: bool = 1, rw;
+
+    /// A page directory entry maps a huge page rather than a page table.
+    HUGE: bool = 7, rw;
+
+    /// The bits of the physical address that the entry refers to, which is
+    /// aligned to a page.
+    ADDRESS: u64 = 12..52, rw;
+
+    /// The mapped memory may not be executed from.
+    NO_EXECUTE: bool = 63, rw;
+  }
+}
+
+/// The model-specific register holding the extended feature enables.
+const IA32_EFER: u32 = 0xc000_0080;
+
+/// Returns an entry that refers to the next-level table at `address`.
+///
+/// # Arguments
+///
+/// * `address` - the physical address of the table
+pub fn table_entry(address: u64) -> u64 {
+  Entry::new(address)
+    .with(Entry::PRESENT, true)
+    .with(Entry::WRITABLE, true)
+    .bits()
+}
+
+/// Returns an entry that maps the memory at `address` with the access given
+/// by `flags`.
+///
+/// # Arguments
+///
+/// * `address` - the physical address of the memory
+/// * `flags` - the access permitted to the mapping
+/// * `huge` - whether the entry maps a huge page from the level above the
+///   last, rather than a page from the last level
+pub fn leaf_entry(address: u64, flags: PageFlags, huge: bool) -> u64 {
+  Entry::new(address)
+    .with(Entry::PRESENT, true)
+    .with(Entry::WRITABLE, flags.writable)
+    .with(Entry::NO_EXECUTE, !flags.executable)
+    .with(Entry::HUGE, huge)
+    .bits()
+}
+
+/// Returns the leaf entry `new`, with its access widened to include that of
+/// the leaf entry `old` that maps the same memory.
+///
+/// # Arguments
+///
+/// * `old` - the existing entry
+/// * `new` - the entry replacing it
+pub fn widen(old: u64, new: u64) -> u64 {
+  let (old, new) = (Entry::new(old), Entry::new(new));
+  let no_execute = old.get(Entry::NO_EXECUTE) && new.get(Entry::NO_EXECUTE);
+  Entry::new(old.bits() | new.bits())
+    .with(Entry::NO_EXECUTE, no_execute)
+    .bits()
+}
+
+/// Returns `true` if `entry` maps memory or refers to a table.
+///
+/// # Arguments
+///
+/// * `entry` - the entry
+pub fn is_present(entry: u64) -> bool {
+  Entry::new(entry).get(Entry::PRESENT)
+}
+
+/// Returns `true` if `entry`, from a level above the last, maps memory
+/// directly rather than referring to a table.
+///
+/// # Arguments
+///
+/// * `entry` - the entry
+pub fn is_huge(entry: u64) -> bool {
+  Entry::new(entry).get(Entry::HUGE)
+}
+
+/// Returns the physical address that `entry` refers to.
+///
+/// # Arguments
+///
+/// * `entry` - the entry
+pub fn address(entry: u64) -> u64 {
+  entry & Entry::ADDRESS.mask()
+}
+
+/// Returns `true` if the processor is in a state that [`enter`] can switch
+/// from.
+pub fn can_enter() -> bool {
+  true
+}
+
+/// Switches to the page tables at `root` and the stack ending at `stack`, and
+/// calls the kernel `entry` point with `argument`.
+///
+/// The kernel is called with the System V calling convention, with
+/// `argument` in `rdi`, interrupts disabled, and read-only pages enforced
+/// against the kernel itself.
+///
+/// # Arguments
+///
+/// * `root` - the physical address of the root page table
+/// * `entry` - the virtual address of the kernel entry point
+/// * `stack` - the 16-byte aligned virtual address of the top of the stack
+/// * `argument` - the argument to pass to the kernel
+///
+/// # Safety
+///
+/// The tables at `root` must identity-map the running code, and map `entry`
+/// and the stack. Nothing may rely on the previous tables afterwards.
+pub unsafe fn enter(root: u64, entry: u64, stack: u64, argument: u64) -> ! {
+  core::arch::asm!(
+    "cli",
+    // Enable the no-execute bit, which is reserved until EFER.NXE is set.
+    "rdmsr",
+    "bts eax, 11",
+    "wrmsr",
+    // Enforce read-only pages on the kernel too, which firmware may leave
+    // unenforced by clearing CR0.WP.
+    "mov rax, cr0",
+    "bts rax, 16",
+    "mov cr0, rax",
+    "mov cr3, r8",
+    "mov rsp, r10",
+    "xor ebp, ebp",
+    "call r9",
+    "ud2",
+    in("r8") root,
+    in("r9") entry,
+    in("r10") stack,
+    in("ecx") IA32_EFER,
+    in("rdi") argument,
+    options(noreturn),
+  )
+}
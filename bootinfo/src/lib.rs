@@ -0,0 +1,864 @@
+//! This crate defines the [`BootInfo`] structure that the bootloader hands to
+//! the kernel on entry, describing the machine and everything the bootloader
+//! has set up.
+//!
+//! All structures are `#[repr(C)]`, since the bootloader and kernel are
+//! separate binaries that may be built by different compilers. Addresses are
+//! passed as plain integers rather than pointers; unless documented otherwise
+//! they are physical addresses, which the bootloader identity-maps. The size
+//! of each structure and the offsets of its fields are asserted with
+//! [`kcore::layout`], so that changing the layout by accident fails to build.
+//!
+//! The records that hold no addresses of other data, such as [`PhysRange`]
+//! and [`MemoryRegion`], can also be encoded with [`kcore::serialize`], for
+//! the kernel to save them beyond the memory that the bootloader handed off
+//! in.
+#![no_std]
+
+pub mod log;
+
+use kcore::symbols::SymbolMap;
+use kcore::tlv;
+use kcore::version::{BuildInfo, Version};
+
+/// The value of [`BootInfo::magic`], used by the kernel to sanity-check that
+/// it was handed a [`BootInfo`] at all.
+pub const MAGIC: u64 = u64::from_be_bytes(*b"UNTITLED");
+
+/// The size of [`BootInfo::seed`], in bytes.
+pub const SEED_SIZE: usize = 32;
+
+/// The version of the [`BootInfo`] layout described by this crate.
+///
+/// This is incremented whenever fields are added to the end of [`BootInfo`].
+pub const VERSION: u32 = 12;
+
+/// The information handed from the bootloader to the kernel on entry.
+///
+/// The kernel entry point is called with the address of this structure as its
+/// only argument, following the platform's C calling convention.
+#[repr(C)]
+pub struct BootInfo {
+  /// Always [`MAGIC`].
+  pub magic: u64,
+
+  /// The [`VERSION`] of the layout that the bootloader wrote.
+  pub version: u32,
+
+  /// The size of this structure as written by the bootloader, in bytes.
+  pub size: u32,
+
+  /// The map of physical memory at the time of handoff.
+  pub memory_map: MemoryMap,
+
+  /// The framebuffer that the firmware left configured, if any.
+  pub framebuffer: Framebuffer,
+
+  /// The physical memory that the kernel image was loaded into.
+  ///
+  /// Unlike other memory used by the bootloader, it is not identity-mapped;
+  /// it is only mapped at the kernel's virtual addresses, with the
+  /// permissions of each segment.
+  pub kernel: PhysRange,
+
+  /// The physical memory that the initrd was loaded into, which is empty if
+  /// no initrd was loaded.
+  pub initrd: PhysRange,
+
+  /// The stack that the kernel is entered on.
+  pub stack: Stack,
+
+  /// The early boot log written by the bootloader, which the kernel may
+  /// replay into its own log; see [`log`].
+  pub log: log::Log,
+
+  /// The timestamps of the phases of boot.
+  pub times: BootTimes,
+
+  /// The additional modules loaded alongside the kernel.
+  pub modules: Modules,
+
+  /// The symbol map of the kernel image, if it has a symbol table.
+  pub symbols: Symbols,
+
+  /// The physical memory holding the flattened device tree that the
+  /// firmware provided, with any overlays applied, which is empty if the
+  /// firmware provided none.
+  pub device_tree: PhysRange,
+
+  /// The processors of the machine, as described by the ACPI MADT, or by the
+  /// device tree if the firmware provided no MADT.
+  pub cpus: Cpus,
+
+  /// How the bootloader that wrote this structure was built.
+  pub bootloader: Build,
+
+  /// The physical memory holding the optional extensions of this structure,
+  /// as a [`tlv`] list of records tagged with [`Extension`]s, which is empty
+  /// if there are none.
+  pub extensions: PhysRange,
+
+  /// Random bytes drawn by the bootloader from its generator, which was
+  /// seeded from every source of entropy it found, for the kernel to seed its
+  /// own generator with before it can gather entropy of its own.
+  pub seed: [u8; SEED_SIZE],
+}
+
+kcore::const_assert_size!(BootInfo, 736);
+kcore::const_assert_offset!(
+  BootInfo,
+  magic: 0,
+  version: 8,
+  size: 12,
+  memory_map: 16,
+  framebuffer: 32,
+  kernel: 72,
+  initrd: 88,
+  stack: 104,
+  log: 128,
+  times: 144,
+  modules: 544,
+  symbols: 560,
+  device_tree: 576,
+  cpus: 592,
+  bootloader: 608,
+  extensions: 688,
+  seed: 704,
+);
+
+impl BootInfo {
+  /// Constructs an empty [`BootInfo`] for the current [`VERSION`].
+  pub const fn new() -> Self {
+    Self {
+      magic: MAGIC,
+      version: VERSION,
+      size: core::mem::size_of::<Self>() as u32,
+      memory_map: MemoryMap { address: 0, len: 0 },
+      framebuffer: Framebuffer::NONE,
+      kernel: PhysRange::EMPTY,
+      initrd: PhysRange::EMPTY,
+      stack: Stack {
+        address: 0,
+        physical: PhysRange::EMPTY,
+      },
+      log: log::Log::NONE,
+      times: BootTimes::EMPTY,
+      modules: Modules { address: 0, len: 0 },
+      symbols: Symbols::NONE,
+      device_tree: PhysRange::EMPTY,
+      cpus: Cpus { address: 0, len: 0 },
+      bootloader: Build::UNKNOWN,
+      extensions: PhysRange::EMPTY,
+      seed: [0; SEED_SIZE],
+    }
+  }
+
+  /// Returns an iterator over the extensions of this structure, including
+  /// any of kinds that the kernel does not know, which it should skip.
+  ///
+  /// # Safety
+  ///
+  /// This is only safe to call while the extensions written by the bootloader
+  /// are still mapped at their identity address and have not been reclaimed.
+  pub unsafe fn extensions(&self) -> tlv::Records<'_> {
+    if self.extensions.is_empty() {
+      return tlv::Records::new(&[]);
+    }
+    tlv::Records::new(core::slice::from_raw_parts(
+      self.extensions.start as *const u8,
+      self.extensions.len as usize,
+    ))
+  }
+}
+
+impl Default for BootInfo {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// A contiguous range of physical memory.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PhysRange {
+  /// The physical address of the start of the range.
+  pub start: u64,
+
+  /// The length of the range, in bytes.
+  pub len: u64,
+}
+
+kcore::const_assert_size!(PhysRange, 16);
+kcore::const_assert_offset!(PhysRange, start: 0, len: 8);
+
+kcore::serializable!(PhysRange { start, len });
+
+impl PhysRange {
+  /// A range which contains no memory.
+  pub const EMPTY: Self = Self { start: 0, len: 0 };
+
+  /// Returns the physical address one past the end of the range.
+  #[inline]
+  pub const fn end(&self) -> u64 {
+    self.start + self.len
+  }
+
+  /// Returns `true` if the range contains no memory.
+  #[inline]
+  pub const fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+}
+
+/// The stack that the kernel is entered on.
+///
+/// The stack is mapped at a virtual address with an unmapped guard page
+/// directly below it, so that overflowing it faults rather than corrupting
+/// other memory.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Stack {
+  /// The virtual address of the lowest byte of the stack. The stack pointer
+  /// starts at the end of the stack, `address + physical.len`.
+  pub address: u64,
+
+  /// The physical memory backing the stack.
+  pub physical: PhysRange,
+}
+
+kcore::const_assert_size!(Stack, 24);
+kcore::const_assert_offset!(Stack, address: 0, physical: 8);
+
+impl Stack {
+  /// Returns the virtual address one past the end of the stack, which is
+  /// where the stack pointer starts.
+  #[inline]
+  pub const fn top(&self) -> u64 {
+    self.address + self.physical.len
+  }
+}
+
+/// The maximum number of [`Timestamp`]s in [`BootTimes`].
+pub const MAX_TIMESTAMPS: usize = 24;
+
+/// A phase of boot, as timestamped in [`BootTimes`].
+///
+/// This is a transparent wrapper rather than an `enum`, so that a kernel may
+/// safely receive phases added by newer bootloaders.
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BootPhase(pub u32);
+
+impl BootPhase {
+  /// The firmware entered the bootloader.
+  pub const FIRMWARE_ENTRY: Self = Self(1);
+
+  /// The boot menu was shown.
+  pub const BOOT_MENU: Self = Self(2);
+
+  /// The boot configuration was read and parsed.
+  pub const CONFIG_PARSE: Self = Self(3);
+
+  /// Memory was tested, if the configuration asked for it.
+  pub const MEMORY_TEST: Self = Self(4);
+
+  /// The kernel was read.
+  pub const KERNEL_READ: Self = Self(5);
+
+  /// The kernel was verified against its digest.
+  pub const KERNEL_VERIFY: Self = Self(6);
+
+  /// The initrd was read.
+  pub const INITRD_READ: Self = Self(7);
+
+  /// The initrd was verified against its digest.
+  pub const INITRD_VERIFY: Self = Self(8);
+
+  /// The kernel executable was laid out in memory.
+  pub const ELF_LOAD: Self = Self(9);
+
+  /// The kernel's address space was built.
+  pub const PAGING: Self = Self(10);
+
+  /// The bootloader began handing off to the kernel.
+  pub const HANDOFF: Self = Self(11);
+
+  /// A module was read.
+  pub const MODULE_READ: Self = Self(12);
+
+  /// A module was verified against its digest.
+  pub const MODULE_VERIFY: Self = Self(13);
+
+  /// Device tree overlays were applied.
+  pub const DEVICE_TREE: Self = Self(14);
+
+  /// Returns a short, human-readable name for the phase.
+  pub const fn name(self) -> &'static str {
+    match self {
+      Self::FIRMWARE_ENTRY => "firmware entry",
+      Self::BOOT_MENU => "boot menu",
+      Self::CONFIG_PARSE => "config parse",
+      Self::MEMORY_TEST => "memory test",
+      Self::KERNEL_READ => "kernel read",
+      Self::KERNEL_VERIFY => "kernel verify",
+      Self::INITRD_READ => "initrd read",
+      Self::INITRD_VERIFY => "initrd verify",
+      Self::ELF_LOAD => "ELF load",
+      Self::PAGING => "paging",
+      Self::HANDOFF => "handoff",
+      Self::MODULE_READ => "module read",
+      Self::MODULE_VERIFY => "module verify",
+      Self::DEVICE_TREE => "device tree",
+      _ => "unknown",
+    }
+  }
+}
+
+/// The start of a phase of boot.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Timestamp {
+  /// The value of the cycle counter when the phase started.
+  pub ticks: u64,
+
+  /// The phase that started.
+  pub phase: BootPhase,
+
+  /// Reserved for future use; always zero.
+  pub reserved: u32,
+}
+
+kcore::const_assert_size!(Timestamp, 16);
+kcore::const_assert_offset!(Timestamp, ticks: 0, phase: 8, reserved: 12);
+
+/// The timestamps of the phases of boot, in the order they started.
+///
+/// Each phase lasts until the next one starts; the last, [`BootPhase::HANDOFF`],
+/// lasts until the kernel is entered. A phase may appear more than once, such
+/// as when a payload is read again from another source.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BootTimes {
+  /// The rate of the cycle counter, in ticks per microsecond, or `0` if it
+  /// was not measured.
+  pub ticks_per_us: u64,
+
+  /// The number of valid entries in `timestamps`.
+  pub len: u64,
+
+  /// The timestamps, of which only the first `len` are valid.
+  pub timestamps: [Timestamp; MAX_TIMESTAMPS],
+}
+
+kcore::const_assert_size!(BootTimes, 16 + 16 * MAX_TIMESTAMPS);
+kcore::const_assert_offset!(BootTimes, ticks_per_us: 0, len: 8, timestamps: 16);
+
+impl BootTimes {
+  /// An empty set of timestamps.
+  pub const EMPTY: Self = Self {
+    ticks_per_us: 0,
+    len: 0,
+    timestamps: [Timestamp {
+      ticks: 0,
+      phase: BootPhase(0),
+      reserved: 0,
+    }; MAX_TIMESTAMPS],
+  };
+
+  /// Returns the valid timestamps, in the order they were taken.
+  pub fn timestamps(&self) -> &[Timestamp] {
+    &self.timestamps[..(self.len as usize).min(MAX_TIMESTAMPS)]
+  }
+}
+
+/// The kind of memory in a [`MemoryRegion`].
+///
+/// This is a transparent wrapper rather than an `enum`, so that a kernel may
+/// safely receive kinds added by newer bootloaders.
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryKind(pub u32);
+
+kcore::serializable!(MemoryKind { 0 });
+
+impl MemoryKind {
+  /// Memory that is free for the kernel to use.
+  pub const USABLE: Self = Self(1);
+
+  /// Memory that must not be used.
+  pub const RESERVED: Self = Self(2);
+
+  /// Memory holding ACPI tables, which may be used once they are parsed.
+  pub const ACPI_RECLAIMABLE: Self = Self(3);
+
+  /// Memory that the firmware requires preserved across sleep states.
+  pub const ACPI_NVS: Self = Self(4);
+
+  /// Memory-mapped I/O regions described by the firmware.
+  pub const MMIO: Self = Self(5);
+
+  /// Memory used by the firmware runtime services.
+  pub const FIRMWARE_RUNTIME: Self = Self(6);
+
+  /// Memory used by the bootloader and firmware boot services, holding
+  /// everything described by the [`BootInfo`]. This may be used once the
+  /// kernel no longer needs the handoff data.
+  pub const BOOTLOADER: Self = Self(7);
+
+  /// Memory in which errors have been detected.
+  pub const UNUSABLE: Self = Self(8);
+}
+
+/// A contiguous region of physical memory of a single kind.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryRegion {
+  /// The physical memory spanned by the region.
+  pub range: PhysRange,
+
+  /// The kind of memory in the region.
+  pub kind: MemoryKind,
+
+  /// Reserved for future use; always zero.
+  pub reserved: u32,
+}
+
+kcore::const_assert_size!(MemoryRegion, 24);
+kcore::const_assert_offset!(MemoryRegion, range: 0, kind: 16, reserved: 20);
+
+kcore::serializable!(MemoryRegion {
+  range,
+  kind,
+  reserved
+});
+
+/// The map of physical memory, as an array of [`MemoryRegion`]s sorted by
+/// address.
+#[repr(C)]
+pub struct MemoryMap {
+  /// The address of the first [`MemoryRegion`].
+  pub address: u64,
+
+  /// The number of [`MemoryRegion`]s.
+  pub len: u64,
+}
+
+kcore::const_assert_size!(MemoryMap, 16);
+kcore::const_assert_offset!(MemoryMap, address: 0, len: 8);
+
+impl MemoryMap {
+  /// Returns the regions of the memory map as a slice.
+  ///
+  /// # Safety
+  ///
+  /// This is only safe to call while the memory map written by the bootloader
+  /// is still mapped at its identity address and has not been reclaimed.
+  pub unsafe fn regions(&self) -> &[MemoryRegion] {
+    if self.len == 0 {
+      return &[];
+    }
+    core::slice::from_raw_parts(
+      self.address as *const MemoryRegion,
+      self.len as usize,
+    )
+  }
+}
+
+/// A file that the bootloader loaded alongside the kernel, such as a firmware
+/// blob or configuration bundle, as listed in the boot configuration.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Module {
+  /// The physical memory holding the contents of the module, which starts at
+  /// a page boundary.
+  pub range: PhysRange,
+
+  /// The physical memory holding the path the module was loaded from, as
+  /// UTF-8 without a terminator.
+  pub path: PhysRange,
+
+  /// The SHA256 digest of the contents of the module.
+  pub digest: [u8; 32],
+}
+
+kcore::const_assert_size!(Module, 64);
+kcore::const_assert_offset!(Module, range: 0, path: 16, digest: 32);
+
+impl Module {
+  /// Returns the path the module was loaded from.
+  ///
+  /// # Safety
+  ///
+  /// This is only safe to call while the path written by the bootloader is
+  /// still mapped at its identity address and has not been reclaimed.
+  pub unsafe fn path(&self) -> &str {
+    if self.path.is_empty() {
+      return "";
+    }
+    let bytes = core::slice::from_raw_parts(
+      self.path.start as *const u8,
+      self.path.len as usize,
+    );
+    core::str::from_utf8(bytes).unwrap_or_default()
+  }
+}
+
+/// The additional modules loaded alongside the kernel, as an array of
+/// [`Module`]s in the order they are listed in the boot configuration.
+#[repr(C)]
+pub struct Modules {
+  /// The address of the first [`Module`].
+  pub address: u64,
+
+  /// The number of [`Module`]s.
+  pub len: u64,
+}
+
+kcore::const_assert_size!(Modules, 16);
+kcore::const_assert_offset!(Modules, address: 0, len: 8);
+
+impl Modules {
+  /// Returns the modules as a slice.
+  ///
+  /// # Safety
+  ///
+  /// This is only safe to call while the modules written by the bootloader
+  /// are still mapped at their identity address and have not been reclaimed.
+  pub unsafe fn modules(&self) -> &[Module] {
+    if self.len == 0 {
+      return &[];
+    }
+    core::slice::from_raw_parts(
+      self.address as *const Module,
+      self.len as usize,
+    )
+  }
+}
+
+/// A processor of the machine.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cpu {
+  /// The hardware ID of the processor: the ID of its local APIC on x86-64,
+  /// or the affinity fields of its MPIDR on AArch64.
+  pub id: u64,
+
+  /// The ACPI processor UID of the processor, or `0` if it was described by
+  /// the device tree.
+  pub acpi_uid: u32,
+
+  /// The `Cpu::*` flags describing the processor.
+  pub flags: u32,
+}
+
+kcore::const_assert_size!(Cpu, 16);
+kcore::const_assert_offset!(Cpu, id: 0, acpi_uid: 8, flags: 12);
+
+impl Cpu {
+  /// The processor is enabled, and may be started.
+  pub const ENABLED: u32 = 1 << 0;
+
+  /// The processor is the one that ran the bootloader and enters the kernel.
+  pub const BOOTSTRAP: u32 = 1 << 1;
+}
+
+/// The processors of the machine, as an array of [`Cpu`]s in the order the
+/// firmware describes them.
+#[repr(C)]
+pub struct Cpus {
+  /// The address of the first [`Cpu`].
+  pub address: u64,
+
+  /// The number of [`Cpu`]s.
+  pub len: u64,
+}
+
+kcore::const_assert_size!(Cpus, 16);
+kcore::const_assert_offset!(Cpus, address: 0, len: 8);
+
+impl Cpus {
+  /// Returns the processors as a slice.
+  ///
+  /// # Safety
+  ///
+  /// This is only safe to call while the processors written by the bootloader
+  /// are still mapped at their identity address and have not been reclaimed.
+  pub unsafe fn cpus(&self) -> &[Cpu] {
+    if self.len == 0 {
+      return &[];
+    }
+    core::slice::from_raw_parts(self.address as *const Cpu, self.len as usize)
+  }
+}
+
+/// The symbol map of the kernel image, generated from its ELF symbol table
+/// by the bootloader, so that addresses can be symbolized before the kernel
+/// can read its own image; see [`kcore::symbols`].
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Symbols {
+  /// The physical memory holding the symbol map, whose addresses are those
+  /// that the kernel was loaded at.
+  pub map: PhysRange,
+}
+
+kcore::const_assert_size!(Symbols, 16);
+kcore::const_assert_offset!(Symbols, map: 0);
+
+impl Symbols {
+  /// [`Symbols`] describing the absence of a symbol map.
+  pub const NONE: Self = Self {
+    map: PhysRange::EMPTY,
+  };
+
+  /// Returns the symbol map, if there is one and it is valid.
+  ///
+  /// # Safety
+  ///
+  /// This is only safe to call while the symbol map written by the
+  /// bootloader is still mapped at its identity address and has not been
+  /// reclaimed.
+  pub unsafe fn map(&self) -> Option<SymbolMap<'_>> {
+    if self.map.is_empty() {
+      return None;
+    }
+    let data = core::slice::from_raw_parts(
+      self.map.start as *const u8,
+      self.map.len as usize,
+    );
+    SymbolMap::parse(data).ok()
+  }
+
+  /// Returns the name of the function or data object containing `address`,
+  /// and the offset of `address` into it, if any.
+  ///
+  /// # Arguments
+  ///
+  /// * `address` - the virtual address to symbolize
+  ///
+  /// # Safety
+  ///
+  /// As for [`map`](Self::map).
+  pub unsafe fn lookup(&self, address: u64) -> Option<(&str, u64)> {
+    let symbol = self.map()?.symbolize(address)?;
+    Some((symbol.name, symbol.offset))
+  }
+}
+
+/// The kind of an extension of a [`BootInfo`], the tag of its record in
+/// [`BootInfo::extensions`].
+///
+/// Extensions carry data that not every boot has, or that is too new to have
+/// a field of its own; kernels skip those that they do not know.
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Extension(pub u32);
+
+impl Extension {
+  /// The workarounds for the firmware that the bootloader applied, as an
+  /// encoded [`FirmwareQuirks`].
+  pub const FIRMWARE_QUIRKS: Self = Self(1);
+}
+
+/// The workarounds for the firmware that the bootloader applied, which the
+/// kernel may need to apply too.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct FirmwareQuirks {
+  /// The `FirmwareQuirks::*` flags of the workarounds.
+  pub flags: u32,
+}
+
+kcore::serializable!(FirmwareQuirks { flags });
+
+impl FirmwareQuirks {
+  /// Boot services memory is reported as reserved, since the firmware's
+  /// runtime services still use it.
+  pub const KEEP_BOOT_SERVICES_MEMORY: u32 = 1 << 0;
+
+  /// The graphics mode was set again before the framebuffer was described.
+  pub const RESET_GRAPHICS_MODE: u32 = 1 << 1;
+}
+
+/// How a binary was built, as a fixed-size copy of a [`BuildInfo`].
+///
+/// Strings are UTF-8, padded with zeros, and truncated if they do not fit.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Build {
+  /// The version of the binary.
+  pub version: Version,
+
+  /// Reserved for future use; always zero.
+  pub reserved: u32,
+
+  /// The abbreviated hash of the git commit that was built.
+  pub git_hash: [u8; 16],
+
+  /// The target triple that was built for.
+  pub target: [u8; 32],
+
+  /// The profile that was built with.
+  pub profile: [u8; 16],
+}
+
+kcore::const_assert_size!(Build, 80);
+kcore::const_assert_offset!(
+  Build,
+  version: 0,
+  reserved: 12,
+  git_hash: 16,
+  target: 32,
+  profile: 64,
+);
+
+kcore::serializable!(Build {
+  version,
+  reserved,
+  git_hash,
+  target,
+  profile
+});
+
+impl Build {
+  /// A [`Build`] describing nothing, with a version of `0.0.0`.
+  pub const UNKNOWN: Self = Self {
+    version: Version::new(0, 0, 0),
+    reserved: 0,
+    git_hash: [0; 16],
+    target: [0; 32],
+    profile: [0; 16],
+  };
+
+  /// Constructs the record of `info`.
+  ///
+  /// # Arguments
+  ///
+  /// * `info` - the build information to record
+  pub const fn new(info: &BuildInfo) -> Self {
+    Self {
+      version: info.version,
+      reserved: 0,
+      git_hash: pad(info.git_hash),
+      target: pad(info.target),
+      profile: pad(info.profile),
+    }
+  }
+
+  /// Returns the abbreviated hash of the git commit that was built.
+  pub fn git_hash(&self) -> &str {
+    unpad(&self.git_hash)
+  }
+
+  /// Returns the target triple that was built for.
+  pub fn target(&self) -> &str {
+    unpad(&self.target)
+  }
+
+  /// Returns the profile that was built with.
+  pub fn profile(&self) -> &str {
+    unpad(&self.profile)
+  }
+}
+
+/// Copies as much of `text` as fits into an array padded with zeros.
+///
+/// # Arguments
+///
+/// * `text` - the text to copy
+const fn pad<const N: usize>(text: &str) -> [u8; N] {
+  let bytes = text.as_bytes();
+  let mut array = [0; N];
+  let mut index = 0;
+  while index < N && index < bytes.len() {
+    array[index] = bytes[index];
+    index += 1;
+  }
+  array
+}
+
+/// Returns the text held in `array` before its padding, up to the last whole
+/// character if it was truncated.
+///
+/// # Arguments
+///
+/// * `array` - the padded text
+fn unpad(array: &[u8]) -> &str {
+  let end = array.iter().position(|&c| c == 0).unwrap_or(array.len());
+  match core::str::from_utf8(&array[..end]) {
+    Ok(text) => text,
+    Err(error) => {
+      core::str::from_utf8(&array[..error.valid_up_to()]).unwrap_or_default()
+    }
+  }
+}
+
+/// The layout of the channels within a pixel of a [`Framebuffer`].
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PixelFormat(pub u32);
+
+impl PixelFormat {
+  /// Each pixel is 32 bits, with red in the lowest byte, then green and blue.
+  pub const RGB: Self = Self(0);
+
+  /// Each pixel is 32 bits, with blue in the lowest byte, then green and red.
+  pub const BGR: Self = Self(1);
+}
+
+/// A linear framebuffer configured by the firmware.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Framebuffer {
+  /// The virtual address that the framebuffer is mapped at, or `0` if there
+  /// is no framebuffer.
+  pub address: u64,
+
+  /// The physical address of the framebuffer.
+  pub physical_address: u64,
+
+  /// The size of the framebuffer, in bytes.
+  pub size: u64,
+
+  /// The number of visible pixels in each row.
+  pub width: u32,
+
+  /// The number of rows.
+  pub height: u32,
+
+  /// The number of pixels between the start of one row and the next.
+  pub stride: u32,
+
+  /// The layout of each pixel.
+  pub format: PixelFormat,
+}
+
+kcore::const_assert_size!(Framebuffer, 40);
+kcore::const_assert_offset!(
+  Framebuffer,
+  address: 0,
+  physical_address: 8,
+  size: 16,
+  width: 24,
+  height: 28,
+  stride: 32,
+  format: 36,
+);
+
+impl Framebuffer {
+  /// A [`Framebuffer`] describing the absence of a framebuffer.
+  pub const NONE: Self = Self {
+    address: 0,
+    physical_address: 0,
+    size: 0,
+    width: 0,
+    height: 0,
+    stride: 0,
+    format: PixelFormat::RGB,
+  };
+
+  /// Returns `true` if this describes a framebuffer that is present.
+  #[inline]
+  pub const fn is_present(&self) -> bool {
+    self.address != 0
+  }
+}
@@ -0,0 +1,54 @@
+//! This module defines the early boot log, a ring of records written by the
+//! bootloader that the kernel may replay into its own log once its console is
+//! up.
+//!
+//! The log is a [`kcore::collections::RecordRing`]: a [`LogHeader`] directly
+//! followed by its data, holding records of UTF-8 text that are each framed by
+//! their length. Once the log is full, the oldest records are dropped to make
+//! room for new ones.
+
+pub use kcore::collections::{
+  RecordRing as LogWriter, RecordRingHeader as LogHeader, Records, WRAP,
+};
+
+/// The early boot log, as described by the [`BootInfo`](crate::BootInfo).
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Log {
+  /// The address of the [`LogHeader`], or `0` if there is no log.
+  pub address: u64,
+
+  /// The size of the log, including its header, in bytes.
+  pub size: u64,
+}
+
+kcore::const_assert_size!(Log, 16);
+kcore::const_assert_offset!(Log, address: 0, size: 8);
+
+impl Log {
+  /// A [`Log`] describing the absence of a log.
+  pub const NONE: Self = Self {
+    address: 0,
+    size: 0,
+  };
+
+  /// Returns an iterator over the records of the log, from oldest to newest.
+  ///
+  /// # Safety
+  ///
+  /// This is only safe to call while the log written by the bootloader is
+  /// still mapped at its identity address and has not been reclaimed.
+  pub unsafe fn records(&self) -> Records<'_> {
+    let header_size = core::mem::size_of::<LogHeader>() as u64;
+    if self.address == 0 || self.size < header_size {
+      return Records::EMPTY;
+    }
+    let header = &*(self.address as *const LogHeader);
+    let capacity = (header.capacity as u64).min(self.size - header_size);
+    let data = core::slice::from_raw_parts(
+      (self.address + header_size) as *const u8,
+      capacity as usize,
+    );
+    Records::new(header, data)
+  }
+}
@@ -0,0 +1,26 @@
+//! Sets the variables that `kcore::build_info!` reads, describing the commit,
+//! target and profile that the bootloader is built from.
+
+use std::process::Command;
+
+fn main() {
+  let git_hash = Command::new("git")
+    .args(["rev-parse", "--short=12", "HEAD"])
+    .output()
+    .ok()
+    .filter(|output| output.status.success())
+    .and_then(|output| String::from_utf8(output.stdout).ok());
+  if let Some(git_hash) = git_hash {
+    println!("cargo:rustc-env=BUILD_GIT_HASH={}", git_hash.trim());
+  }
+  for (name, variable) in
+    [("TARGET", "BUILD_TARGET"), ("PROFILE", "BUILD_PROFILE")]
+  {
+    if let Ok(value) = std::env::var(name) {
+      println!("cargo:rustc-env={}={}", variable, value);
+    }
+  }
+  println!("cargo:rerun-if-changed=../.git/HEAD");
+  println!("cargo:rerun-if-changed=../.git/refs/heads");
+  println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,62 @@
+//! This module provides discovery of the firmware's ACPI tables, which are
+//! parsed by [`kcore::acpi`].
+//!
+//! Tables are read in place from firmware memory, which is identity-mapped
+//! while boot services are active.
+
+use kcore::acpi::{Madt, Tables};
+use uefi::table::{cfg, Boot, SystemTable};
+
+/// The firmware's tables, read through its identity mapping.
+pub type FirmwareTables =
+  Tables<'static, fn(u64, usize) -> Option<&'static [u8]>>;
+
+/// Returns the address of the firmware's RSDP, preferring the ACPI 2.0 one.
+///
+/// # Arguments
+///
+/// * `system_table` - the system table
+pub fn rsdp(system_table: &SystemTable<Boot>) -> Option<u64> {
+  let table = system_table.config_table();
+  [cfg::ACPI2_GUID, cfg::ACPI_GUID].iter().find_map(|guid| {
+    table
+      .iter()
+      .find(|entry| entry.guid == *guid)
+      .map(|entry| entry.address as u64)
+  })
+}
+
+/// Returns the tables listed by the RSDP at `rsdp`, or `None` if it or its
+/// root table is not valid.
+///
+/// # Arguments
+///
+/// * `rsdp` - the address of the RSDP
+pub fn tables(rsdp: u64) -> Option<FirmwareTables> {
+  Tables::new(rsdp, map as fn(u64, usize) -> Option<&'static [u8]>).ok()
+}
+
+/// Returns the firmware's MADT, if it has a valid one.
+///
+/// # Arguments
+///
+/// * `system_table` - the system table
+pub fn madt(system_table: &SystemTable<Boot>) -> Option<Madt<'static>> {
+  tables(rsdp(system_table)?)?.madt()
+}
+
+/// Returns the `len` bytes of firmware memory at the physical `address`.
+///
+/// # Arguments
+///
+/// * `address` - the physical address of the bytes
+/// * `len` - the number of bytes
+fn map(address: u64, len: usize) -> Option<&'static [u8]> {
+  if address == 0 {
+    return None;
+  }
+  // SAFETY: the firmware describes its tables by their physical addresses,
+  // which it identity-maps, and never frees them. Their lengths are read from
+  // their headers before the rest of them is.
+  Some(unsafe { core::slice::from_raw_parts(address as *const u8, len) })
+}
@@ -0,0 +1,599 @@
+//! This module provides reading of the cpio and tar archives that initrd
+//! images are packed in, and the verification of their members against a
+//! manifest of digests.
+//!
+//! cpio archives are read by [`kcore::cpio`], and must be in the "newc"
+//! format. Several uncompressed archives may be concatenated, as when early
+//! microcode is prepended to an initrd; anything that follows them and is not
+//! another archive, such as a compressed one, is not read. tar
+//! archives may be in the POSIX ustar or GNU formats, with long names given by
+//! GNU or pax extended headers.
+//!
+//! A manifest lists a digest and a path on each line, in the format written
+//! by `sha256sum`. Every regular file of the archive must be listed in the
+//! manifest with its digest, and every file listed must be in the archive.
+
+use crate::error::status_of;
+use core::fmt;
+use core::str::FromStr;
+use crypto::sha256;
+use kcore::cpio;
+use kcore::vfs::FileType;
+use uefi::Status;
+
+/// The size of a tar header, and the unit that tar archives are padded to.
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// The magic number of a tar header, at [`TAR_MAGIC_OFFSET`].
+const TAR_MAGIC: &[u8; 5] = b"ustar";
+
+/// The magic number of a POSIX ustar header, which may have a name prefix.
+const USTAR_MAGIC: &[u8; 6] = b"ustar\0";
+
+/// The offset of the magic number in a tar header.
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// The format of an archive.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+  Cpio,
+  Tar,
+}
+
+/// The type of a member of an archive.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+  /// A regular file.
+  File,
+
+  /// A directory.
+  Directory,
+
+  /// A symbolic or hard link.
+  Link,
+
+  /// A device, pipe, or other special file.
+  Other,
+}
+
+/// The path of a member of an archive, relative to its root.
+///
+/// tar archives may split long paths into a prefix and a name, which are
+/// joined by a `/`.
+#[derive(Clone, Copy)]
+pub struct Path<'a> {
+  prefix: &'a str,
+  name: &'a str,
+}
+
+impl<'a> Path<'a> {
+  /// Constructs a [`Path`] from `prefix` and `name`, without any leading `/`
+  /// or `./`, or trailing `/`.
+  ///
+  /// # Arguments
+  ///
+  /// * `prefix` - the directory that `name` is in, which may be empty
+  /// * `name` - the name of the member
+  fn new(prefix: &'a str, name: &'a str) -> Self {
+    let prefix = relative(prefix).trim_end_matches('/');
+    let name = name.trim_end_matches('/');
+    let name = if prefix.is_empty() {
+      relative(name)
+    } else {
+      name
+    };
+    Self { prefix, name }
+  }
+
+  /// Returns `true` if this path is the same as `path`.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path to compare with, as written in a manifest
+  pub fn is(&self, path: &str) -> bool {
+    let path = relative(path);
+    if self.prefix.is_empty() {
+      return self.name == path;
+    }
+    path
+      .strip_prefix(self.prefix)
+      .and_then(|rest| rest.strip_prefix('/'))
+      == Some(self.name)
+  }
+}
+
+impl fmt::Display for Path<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if !self.prefix.is_empty() {
+      write!(f, "{}/", self.prefix)?;
+    }
+    f.write_str(self.name)
+  }
+}
+
+/// A member of an archive.
+pub struct Member<'a> {
+  /// The path of the member.
+  pub path: Path<'a>,
+
+  /// The type of the member.
+  pub kind: Kind,
+
+  /// The contents of the member, which are empty for all but regular files.
+  pub data: &'a [u8],
+
+  /// The offset of the header that follows the member.
+  end: usize,
+}
+
+/// An iterator over the members of an archive.
+#[derive(Clone, Copy)]
+pub struct Members<'a> {
+  data: &'a [u8],
+  format: Format,
+  position: usize,
+}
+
+impl<'a> Members<'a> {
+  /// Constructs an iterator over the members of the archive in `data`.
+  ///
+  /// Fails with [`Status::UNSUPPORTED`] if `data` is not a cpio or tar
+  /// archive.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the archive
+  pub fn new(data: &'a [u8]) -> uefi::Result<Self> {
+    let format = if cpio::is_cpio(data) {
+      Format::Cpio
+    } else if data.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + TAR_MAGIC.len())
+      == Some(TAR_MAGIC)
+    {
+      Format::Tar
+    } else {
+      return Err(Status::UNSUPPORTED.into());
+    };
+    Ok(Self {
+      data,
+      format,
+      position: 0,
+    })
+  }
+
+  /// Returns an iterator over the members of the same archive, from the one
+  /// whose header is at `position`.
+  ///
+  /// # Arguments
+  ///
+  /// * `position` - the offset of the header to start from
+  fn at(&self, position: usize) -> Self {
+    Self { position, ..*self }
+  }
+
+  /// Reads the cpio member at the current position, skipping the trailers of
+  /// concatenated archives.
+  fn next_cpio(&mut self) -> uefi::Result<Option<Member<'a>>> {
+    let rest = self.data.get(self.position..).unwrap_or_default();
+    if rest.is_empty() {
+      return Ok(None);
+    }
+    let mut entries = cpio::Entries::new(rest).map_err(status_of)?;
+    let entry = entries.next().transpose().map_err(status_of)?;
+    self.position += entries.offset();
+    Ok(entry.map(|entry| Member {
+      path: Path::new("", entry.name),
+      kind: match entry.file_type() {
+        FileType::File => Kind::File,
+        FileType::Directory => Kind::Directory,
+        FileType::Symlink => Kind::Link,
+        FileType::Other => Kind::Other,
+      },
+      data: entry.data,
+      end: self.position,
+    }))
+  }
+
+  /// Reads the tar member at the current position, applying any extended
+  /// headers that precede it.
+  fn next_tar(&mut self) -> uefi::Result<Option<Member<'a>>> {
+    let mut long_name = None;
+    loop {
+      if self.position >= self.data.len() {
+        return Ok(None);
+      }
+      let header = self
+        .data
+        .get(self.position..self.position + TAR_BLOCK_SIZE)
+        .ok_or(Status::LOAD_ERROR)?;
+      // The archive ends with a zero block.
+      if header.iter().all(|&byte| byte == 0) {
+        self.position = self.data.len();
+        return Ok(None);
+      }
+
+      let checksum =
+        header
+          .iter()
+          .enumerate()
+          .fold(0, |sum, (i, &byte)| match i {
+            148..=155 => sum + b' ' as u64,
+            _ => sum + byte as u64,
+          });
+      if parse_tar_number(&header[148..156]) != Some(checksum) {
+        return Err(Status::LOAD_ERROR.into());
+      }
+      let size = parse_tar_number(&header[124..136])
+        .and_then(|size| usize::try_from(size).ok())
+        .ok_or(Status::LOAD_ERROR)?;
+      let data_start = self.position + TAR_BLOCK_SIZE;
+      let data = data_start
+        .checked_add(size)
+        .and_then(|end| self.data.get(data_start..end))
+        .ok_or(Status::LOAD_ERROR)?;
+      self.position = data_start + align(size, TAR_BLOCK_SIZE);
+
+      let kind = match header[156] {
+        // GNU long names, and pax extended headers, apply to the next member.
+        b'L' => {
+          long_name = Some(c_str(data)?);
+          continue;
+        }
+        b'x' => {
+          long_name = pax_path(data)?.or(long_name);
+          continue;
+        }
+        b'K' | b'g' => continue,
+        b'0' | 0 | b'7' => Kind::File,
+        b'5' => Kind::Directory,
+        b'1' | b'2' => Kind::Link,
+        _ => Kind::Other,
+      };
+      let path = match long_name {
+        Some(name) => Path::new("", name),
+        None => {
+          // Only POSIX ustar headers have a name prefix; GNU headers use the
+          // same bytes for other fields.
+          let magic = &header[TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 6];
+          let prefix = if magic == USTAR_MAGIC {
+            c_str(&header[345..500])?
+          } else {
+            ""
+          };
+          Path::new(prefix, c_str(&header[..100])?)
+        }
+      };
+      return Ok(Some(Member {
+        path,
+        kind,
+        data,
+        end: self.position,
+      }));
+    }
+  }
+}
+
+impl<'a> Iterator for Members<'a> {
+  type Item = uefi::Result<Member<'a>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let result = match self.format {
+      Format::Cpio => self.next_cpio(),
+      Format::Tar => self.next_tar(),
+    };
+    // Nothing more is read from a malformed archive.
+    if result.is_err() {
+      self.position = self.data.len();
+    }
+    result.transpose()
+  }
+}
+
+/// An entry of a manifest.
+struct Entry<'a> {
+  /// The path of the file, relative to the root of the archive.
+  path: &'a str,
+
+  /// The digest the file is required to have.
+  digest: sha256::Digest,
+
+  /// The offset of the line that follows the entry.
+  end: usize,
+}
+
+/// An iterator over the entries of a manifest.
+#[derive(Clone, Copy)]
+struct Entries<'a> {
+  text: &'a str,
+  position: usize,
+}
+
+impl<'a> Entries<'a> {
+  /// Returns an iterator over the entries of the manifest `text`, from the
+  /// line at `position`.
+  ///
+  /// # Arguments
+  ///
+  /// * `text` - the manifest
+  /// * `position` - the offset of the line to start from
+  fn new(text: &'a str, position: usize) -> Self {
+    Self { text, position }
+  }
+}
+
+impl<'a> Iterator for Entries<'a> {
+  type Item = uefi::Result<Entry<'a>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      let rest = self.text.get(self.position..).unwrap_or_default();
+      if rest.is_empty() {
+        return None;
+      }
+      let (line, len) = match rest.find('\n') {
+        Some(end) => (&rest[..end], end + 1),
+        None => (rest, rest.len()),
+      };
+      self.position += len;
+      let line = line.trim_end();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      // Each line is a digest, then a space, then either a space or a `*`
+      // marking the file as binary, then the path.
+      let entry = line.split_once(' ').and_then(|(digest, path)| {
+        let path = path.strip_prefix([' ', '*']).unwrap_or(path);
+        Some(Entry {
+          path,
+          digest: sha256::Digest::from_str(digest).ok()?,
+          end: self.position,
+        })
+      });
+      return match entry {
+        Some(entry) => Some(Ok(entry)),
+        None => {
+          self.position = self.text.len();
+          Some(Err(Status::LOAD_ERROR.into()))
+        }
+      };
+    }
+  }
+}
+
+/// The reason that an archive failed verification against a manifest.
+pub enum Rejection<'a> {
+  /// The data is not a cpio or tar archive.
+  Unsupported,
+
+  /// The archive is malformed.
+  MalformedArchive,
+
+  /// The manifest is malformed.
+  MalformedManifest,
+
+  /// A regular file is not listed in the manifest.
+  Unlisted(Path<'a>),
+
+  /// A regular file does not match its digest in the manifest.
+  Mismatch(Path<'a>),
+
+  /// A file listed in the manifest is not a regular file of the archive.
+  Missing(&'a str),
+}
+
+impl Rejection<'_> {
+  /// Returns the status that the rejection is reported with.
+  pub fn status(&self) -> Status {
+    match self {
+      Rejection::Unsupported => Status::UNSUPPORTED,
+      Rejection::MalformedArchive | Rejection::MalformedManifest => {
+        Status::LOAD_ERROR
+      }
+      Rejection::Unlisted(_)
+      | Rejection::Mismatch(_)
+      | Rejection::Missing(_) => Status::SECURITY_VIOLATION,
+    }
+  }
+}
+
+impl fmt::Display for Rejection<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Rejection::Unsupported => f.write_str("not a cpio or tar archive"),
+      Rejection::MalformedArchive => f.write_str("the archive is malformed"),
+      Rejection::MalformedManifest => f.write_str("the manifest is malformed"),
+      Rejection::Unlisted(path) => {
+        write!(f, "'{}' is not listed in the manifest", path)
+      }
+      Rejection::Mismatch(path) => {
+        write!(f, "'{}' does not match its digest in the manifest", path)
+      }
+      Rejection::Missing(path) => {
+        write!(
+          f,
+          "'{}' is listed in the manifest but not in the archive",
+          path
+        )
+      }
+    }
+  }
+}
+
+/// Verifies every member of the archive in `data` against `manifest`,
+/// returning the number of regular files that were verified.
+///
+/// # Arguments
+///
+/// * `data` - the archive
+/// * `manifest` - the manifest, in the format written by `sha256sum`
+pub fn verify<'a>(
+  data: &'a [u8],
+  manifest: &'a str,
+) -> Result<usize, Rejection<'a>> {
+  let members = Members::new(data).map_err(|_| Rejection::Unsupported)?;
+
+  let mut count = 0;
+  let mut cursor = 0;
+  for member in members {
+    let member = member.map_err(|_| Rejection::MalformedArchive)?;
+    if member.kind != Kind::File {
+      continue;
+    }
+    let entry = find_wrapping(
+      |position| Entries::new(manifest, position),
+      |entry| entry.end,
+      &mut cursor,
+      |entry| member.path.is(entry.path),
+    )
+    .map_err(|_| Rejection::MalformedManifest)?
+    .ok_or(Rejection::Unlisted(member.path))?;
+    if sha256::hash_bytes(member.data) != entry.digest {
+      return Err(Rejection::Mismatch(member.path));
+    }
+    count += 1;
+  }
+
+  let mut cursor = 0;
+  for entry in Entries::new(manifest, 0) {
+    let entry = entry.map_err(|_| Rejection::MalformedManifest)?;
+    find_wrapping(
+      |position| members.at(position),
+      |member| member.end,
+      &mut cursor,
+      |member| member.kind == Kind::File && member.path.is(entry.path),
+    )
+    .map_err(|_| Rejection::MalformedArchive)?
+    .ok_or(Rejection::Missing(entry.path))?;
+  }
+  Ok(count)
+}
+
+/// Finds the first item that satisfies `predicate`, searching from the one at
+/// `cursor` to the end, then from the start back to `cursor`, and moves
+/// `cursor` past it.
+///
+/// Manifests are usually written in the same order as their archive, in which
+/// case each search ends at the first item it looks at.
+///
+/// # Arguments
+///
+/// * `items` - the items from a position on
+/// * `end` - the position that follows an item
+/// * `cursor` - the position to start from
+/// * `predicate` - the condition the item must satisfy
+fn find_wrapping<T, I: Iterator<Item = uefi::Result<T>>>(
+  items: impl Fn(usize) -> I,
+  end: impl Fn(&T) -> usize,
+  cursor: &mut usize,
+  predicate: impl Fn(&T) -> bool,
+) -> uefi::Result<Option<T>> {
+  let start = *cursor;
+  for item in items(start) {
+    let item = item?;
+    if predicate(&item) {
+      *cursor = end(&item);
+      return Ok(Some(item));
+    }
+  }
+  for item in items(0) {
+    let item = item?;
+    if end(&item) > start {
+      break;
+    }
+    if predicate(&item) {
+      *cursor = end(&item);
+      return Ok(Some(item));
+    }
+  }
+  Ok(None)
+}
+
+/// Returns `path` without any leading `/` or `./`.
+///
+/// # Arguments
+///
+/// * `path` - the path
+fn relative(mut path: &str) -> &str {
+  loop {
+    path = match path.strip_prefix("./").or_else(|| path.strip_prefix('/')) {
+      Some(rest) => rest,
+      None => return path,
+    };
+  }
+}
+
+/// Rounds `value` up to a multiple of `alignment`.
+///
+/// # Arguments
+///
+/// * `value` - the value to round
+/// * `alignment` - the alignment, which must be a power of two
+fn align(value: usize, alignment: usize) -> usize {
+  (value + alignment - 1) & !(alignment - 1)
+}
+
+/// Returns the string in `bytes` up to the first NUL, if any.
+///
+/// # Arguments
+///
+/// * `bytes` - the bytes of the string
+fn c_str(bytes: &[u8]) -> uefi::Result<&str> {
+  let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+  core::str::from_utf8(&bytes[..len]).map_err(|_| Status::LOAD_ERROR.into())
+}
+
+/// Parses the numeric field of a tar header, which is either octal text, or a
+/// big-endian binary number marked by the top bit of its first byte.
+///
+/// # Arguments
+///
+/// * `field` - the field
+fn parse_tar_number(field: &[u8]) -> Option<u64> {
+  if field[0] & 0x80 != 0 {
+    return field
+      .iter()
+      .enumerate()
+      .try_fold(0u64, |value, (i, &byte)| {
+        let byte = if i == 0 { byte & 0x7f } else { byte };
+        value.checked_mul(256)?.checked_add(byte as u64)
+      });
+  }
+  let text = core::str::from_utf8(field).ok()?;
+  let text = text.trim_matches(|c| c == ' ' || c == '\0');
+  if text.is_empty() {
+    return Some(0);
+  }
+  u64::from_str_radix(text, 8).ok()
+}
+
+/// Returns the path given by the pax extended header records in `records`, if
+/// any.
+///
+/// # Arguments
+///
+/// * `records` - the records, each of the form `length key=value\n`
+fn pax_path(records: &[u8]) -> uefi::Result<Option<&str>> {
+  let mut records = records;
+  let mut path = None;
+  while !records.is_empty() {
+    let space = records
+      .iter()
+      .position(|&b| b == b' ')
+      .ok_or(Status::LOAD_ERROR)?;
+    let len = core::str::from_utf8(&records[..space])
+      .ok()
+      .and_then(|len| len.parse::<usize>().ok())
+      .filter(|&len| len > space && len <= records.len())
+      .ok_or(Status::LOAD_ERROR)?;
+    let record = records[space + 1..len]
+      .strip_suffix(b"\n")
+      .ok_or(Status::LOAD_ERROR)?;
+    let record =
+      core::str::from_utf8(record).map_err(|_| Status::LOAD_ERROR)?;
+    if let Some(value) = record.strip_prefix("path=") {
+      path = Some(value);
+    }
+    records = &records[len..];
+  }
+  Ok(path)
+}
@@ -0,0 +1,381 @@
+//! This module provides raw reads from the boot disk through the firmware's
+//! Block I/O protocol.
+//!
+//! This is the fallback used to load payloads when they cannot be found on a
+//! file system, in which case they are read from ranges of logical blocks
+//! given in the boot configuration. Small reads go through a read cache so
+//! that probing on-disk metadata does not reach the device once per sector.
+
+use crate::efi::loaded_image;
+use crate::error;
+use crate::loader::{self, Buffer, Progress, Step};
+use uefi::proto::device_path::DevicePath;
+use uefi::proto::media::block::BlockIO;
+use uefi::table::boot::{
+  BootServices, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol,
+  SearchType,
+};
+use uefi::{Handle, Status};
+
+/// The maximum number of ranges that a payload may be split across.
+pub const MAX_RANGES: usize = 8;
+
+/// The number of lines held by the read cache of a [`BlockReader`].
+const CACHE_LINES: usize = 16;
+
+/// The number of bytes held by each line of the read cache.
+///
+/// This is increased to the block size of devices with larger blocks.
+const CACHE_LINE_SIZE: usize = loader::PAGE_SIZE;
+
+/// A contiguous range of logical blocks.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct LbaRange {
+  /// The first logical block of the range.
+  pub start: u64,
+
+  /// The number of logical blocks in the range.
+  pub count: u64,
+}
+
+/// An ordered list of [`LbaRange`]s that make up a single payload.
+#[derive(Clone, Copy)]
+pub struct LbaRanges {
+  ranges: [LbaRange; MAX_RANGES],
+  len: usize,
+}
+
+impl LbaRanges {
+  /// Constructs an empty [`LbaRanges`].
+  pub const fn new() -> Self {
+    Self {
+      ranges: [LbaRange { start: 0, count: 0 }; MAX_RANGES],
+      len: 0,
+    }
+  }
+
+  /// Parses a comma-separated list of `start+count` block ranges, such as
+  /// `2048+512, 8192+64`.
+  ///
+  /// Returns [`None`] if any range is malformed, or if there are more than
+  /// [`MAX_RANGES`] ranges.
+  ///
+  /// # Arguments
+  ///
+  /// * `s` - the string to parse
+  pub fn parse(s: &str) -> Option<Self> {
+    let mut result = Self::new();
+    for range in s.split(',') {
+      let (start, count) = range.split_once('+')?;
+      let range = LbaRange {
+        start: start.trim().parse().ok()?,
+        count: count.trim().parse().ok()?,
+      };
+      *result.ranges.get_mut(result.len)? = range;
+      result.len += 1;
+    }
+    Some(result)
+  }
+
+  /// Returns the ranges as a slice.
+  pub fn as_slice(&self) -> &[LbaRange] {
+    &self.ranges[..self.len]
+  }
+
+  /// Returns `true` if there are no ranges.
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+}
+
+impl Default for LbaRanges {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// The bookkeeping for one line of the read cache.
+#[derive(Clone, Copy)]
+struct CacheLine {
+  /// The first block held by this line, or [`None`] if the line is empty.
+  lba: Option<u64>,
+
+  /// The value of [`BlockReader::clock`] when this line was last used.
+  last_used: u64,
+}
+
+/// A reader of logical blocks from a Block I/O device, with a small read
+/// cache for byte-granular accesses.
+pub struct BlockReader<'a> {
+  io: ScopedProtocol<'a, BlockIO>,
+  media_id: u32,
+  block_size: usize,
+  line_size: usize,
+  lines: [CacheLine; CACHE_LINES],
+  cache: &'static mut [u8],
+  clock: u64,
+}
+
+impl<'a> BlockReader<'a> {
+  /// Opens a [`BlockReader`] for the whole disk that `image` was loaded from.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  pub fn open_boot_disk(
+    bs: &'a BootServices,
+    image: Handle,
+  ) -> uefi::Result<Self> {
+    let device = loaded_image::device(bs, image)?;
+    let disk = Self::find_disk(bs, image, device)?;
+    Self::open(bs, image, disk)
+  }
+
+  /// Opens a [`BlockReader`] for the Block I/O device on `handle`.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  /// * `handle` - the handle of the block device
+  pub fn open(
+    bs: &'a BootServices,
+    image: Handle,
+    handle: Handle,
+  ) -> uefi::Result<Self> {
+    let io = Self::open_protocol::<BlockIO>(bs, image, handle)?;
+    let media = io.media();
+    if !media.is_media_present() {
+      return Err(Status::NO_MEDIA.into());
+    }
+    let block_size = media.block_size() as usize;
+    let media_id = media.media_id();
+    let line_size = CACHE_LINE_SIZE.max(block_size);
+    let cache = loader::allocate_buffer(bs, line_size * CACHE_LINES)?.leak();
+
+    Ok(Self {
+      io,
+      media_id,
+      block_size,
+      line_size,
+      lines: [CacheLine {
+        lba: None,
+        last_used: 0,
+      }; CACHE_LINES],
+      cache,
+      clock: 0,
+    })
+  }
+
+  /// Finds the handle of the whole disk that contains the partition
+  /// `device`, or `device` itself if it is not a partition.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  /// * `device` - the handle of the device to find the disk of
+  fn find_disk(
+    bs: &BootServices,
+    image: Handle,
+    device: Handle,
+  ) -> uefi::Result<Handle> {
+    let device_path = Self::open_protocol::<DevicePath>(bs, image, device)?;
+    let device_path = device_path.as_bytes();
+
+    let handles =
+      bs.locate_handle_buffer(SearchType::from_proto::<BlockIO>())?;
+    for &handle in handles.iter() {
+      let Ok(io) = Self::open_protocol::<BlockIO>(bs, image, handle) else {
+        continue;
+      };
+      let Ok(path) = Self::open_protocol::<DevicePath>(bs, image, handle)
+      else {
+        continue;
+      };
+      // A disk's device path is a prefix of the paths of its partitions,
+      // excluding the 4-byte end node.
+      let path = path.as_bytes();
+      let path = &path[..path.len().saturating_sub(4)];
+      if !io.media().is_logical_partition() && device_path.starts_with(path) {
+        return Ok(handle);
+      }
+    }
+    Ok(device)
+  }
+
+  /// Opens the protocol `P` on `handle` without taking exclusive ownership.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  /// * `handle` - the handle to open the protocol on
+  fn open_protocol<P: uefi::proto::ProtocolPointer + ?Sized>(
+    bs: &BootServices,
+    image: Handle,
+    handle: Handle,
+  ) -> uefi::Result<ScopedProtocol<'_, P>> {
+    let params = OpenProtocolParams {
+      handle,
+      agent: image,
+      controller: None,
+    };
+    // SAFETY: the file system drivers also have the device open, so it cannot
+    // be opened exclusively. The bootloader only ever reads through it.
+    unsafe {
+      bs.open_protocol::<P>(params, OpenProtocolAttributes::GetProtocol)
+    }
+  }
+
+  /// Returns the size of the device's blocks, in bytes.
+  pub fn block_size(&self) -> usize {
+    self.block_size
+  }
+
+  /// Reads whole blocks starting at `lba` directly into `buffer`, bypassing
+  /// the read cache.
+  ///
+  /// `buffer` must be a multiple of the block size in length, and aligned to
+  /// the device's I/O alignment; page-aligned buffers always are.
+  ///
+  /// # Arguments
+  ///
+  /// * `lba` - the first block to read
+  /// * `buffer` - the buffer to read the blocks into
+  pub fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> uefi::Result {
+    if buffer.is_empty() {
+      return Ok(());
+    }
+    self.io.read_blocks(self.media_id, lba, buffer)
+  }
+
+  /// Reads `buffer.len()` bytes starting at byte `offset` of the device,
+  /// through the read cache.
+  ///
+  /// This is intended for small, repeated reads of on-disk metadata; payloads
+  /// should be read with [`BlockReader::read_blocks`] instead.
+  ///
+  /// # Arguments
+  ///
+  /// * `offset` - the byte offset on the device to read from
+  /// * `buffer` - the buffer to read into
+  pub fn read(
+    &mut self,
+    mut offset: u64,
+    mut buffer: &mut [u8],
+  ) -> uefi::Result {
+    let line_size = self.line_size as u64;
+    while !buffer.is_empty() {
+      let line_offset = offset % line_size;
+      let line = self.fetch_line(offset - line_offset)?;
+      let len = buffer.len().min(line.len() - line_offset as usize);
+      buffer[..len].copy_from_slice(&line[line_offset as usize..][..len]);
+
+      buffer = &mut buffer[len..];
+      offset += len as u64;
+    }
+    Ok(())
+  }
+
+  /// Returns the contents of the cache line that starts at byte `offset`,
+  /// reading it from the device into the least recently used line if it is
+  /// not already cached.
+  ///
+  /// # Arguments
+  ///
+  /// * `offset` - the byte offset of the line, aligned to the line size
+  fn fetch_line(&mut self, offset: u64) -> uefi::Result<&[u8]> {
+    let lba = offset / self.block_size as u64;
+    self.clock += 1;
+
+    let index = match self.lines.iter().position(|l| l.lba == Some(lba)) {
+      Some(index) => index,
+      None => {
+        let index = (0..CACHE_LINES)
+          .min_by_key(|&i| {
+            (self.lines[i].lba.is_some(), self.lines[i].last_used)
+          })
+          .unwrap_or(0);
+
+        // Lines that extend past the end of the device are read short, and
+        // the remainder of the line is left zeroed.
+        let blocks_per_line = (self.line_size / self.block_size) as u64;
+        let last_block = self.io.media().last_block();
+        let blocks = blocks_per_line.min(last_block.saturating_sub(lba) + 1);
+        let line = &mut self.cache[index * self.line_size..][..self.line_size];
+        line.fill(0);
+
+        self.lines[index].lba = None;
+        let len = blocks as usize * self.block_size;
+        self.io.read_blocks(self.media_id, lba, &mut line[..len])?;
+        self.lines[index].lba = Some(lba);
+        index
+      }
+    };
+    self.lines[index].last_used = self.clock;
+    Ok(&self.cache[index * self.line_size..][..self.line_size])
+  }
+
+  /// Reads each range of `ranges` in turn into a newly allocated buffer.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `ranges` - the ranges of blocks to read
+  /// * `progress` - the receiver of progress reports
+  pub fn read_ranges(
+    &mut self,
+    bs: &BootServices,
+    ranges: &LbaRanges,
+    progress: &mut dyn Progress,
+  ) -> uefi::Result<Buffer> {
+    let blocks: u64 = ranges.as_slice().iter().map(|r| r.count).sum();
+    let size = usize::try_from(blocks)
+      .ok()
+      .and_then(|blocks| blocks.checked_mul(self.block_size))
+      .ok_or(Status::BAD_BUFFER_SIZE)?;
+    let mut data = loader::allocate_buffer(bs, size)?;
+
+    // Reads are split into chunks of whole blocks, so that progress can be
+    // reported between them.
+    let chunk = (loader::PROGRESS_CHUNK / self.block_size).max(1) as u64;
+    let mut offset = 0;
+    progress.report(Step::Read, 0, size);
+    for range in ranges.as_slice() {
+      let mut lba = range.start;
+      while lba < range.start + range.count {
+        let count = chunk.min(range.start + range.count - lba);
+        let len = count as usize * self.block_size;
+        self.read_blocks(lba, &mut data[offset..offset + len])?;
+        lba += count;
+        offset += len;
+        progress.report(Step::Read, offset, size);
+      }
+    }
+    Ok(data)
+  }
+}
+
+impl kcore::block::BlockDevice for BlockReader<'_> {
+  fn block_size(&self) -> usize {
+    self.block_size
+  }
+
+  fn block_count(&self) -> u64 {
+    self.io.media().last_block() + 1
+  }
+
+  fn read_blocks(
+    &mut self,
+    lba: u64,
+    buffer: &mut [u8],
+  ) -> kcore::error::Result<()> {
+    // Reads go through the read cache, so that buffers need not be aligned.
+    let offset = lba * self.block_size as u64;
+    self
+      .read(offset, buffer)
+      .map_err(|error| error::kind_of(error.status()))
+  }
+}
@@ -0,0 +1,153 @@
+//! This module provides the persistent boot log, a file on the boot volume
+//! that the early boot log is appended to at the end of each boot, along with
+//! when the boot started and how it ended.
+//!
+//! Machines without a screen leave no other trace of a failed boot. The file
+//! is kept below [`SIZE_LIMIT`] by dropping its oldest entries whenever a new
+//! one would not fit.
+
+use crate::fs;
+use crate::loader::{self, Buffer};
+use core::fmt::{self, Write};
+use kcore::fmt::SliceWriter;
+use uefi::table::boot::BootServices;
+use uefi::table::runtime::{RuntimeServices, Time};
+use uefi::{Handle, Status};
+
+/// The path of the persistent boot log, relative to the boot volume root.
+pub const PATH: &str = r"\EFI\untitled\bootlog.txt";
+
+/// The size that the persistent boot log is kept below, in bytes.
+const SIZE_LIMIT: usize = 256 * 1024;
+
+/// The start of the line that begins each entry of the persistent boot log.
+const ENTRY_MARKER: &str = "--- boot at ";
+
+/// The number of bytes of an entry beyond its records, for its first line and
+/// the outcome of the boot.
+const ENTRY_OVERHEAD: usize = 1024;
+
+/// Appends an entry for this boot to the persistent boot log, holding
+/// `records` and `outcome`.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `rt` - the runtime services, to read the time from
+/// * `records` - the records of the early boot log
+/// * `outcome` - how the boot ended
+pub fn save<'r>(
+  bs: &BootServices,
+  image: Handle,
+  rt: &RuntimeServices,
+  records: impl Iterator<Item = &'r [u8]> + Clone,
+  outcome: &dyn fmt::Display,
+) -> uefi::Result {
+  let time = rt.get_time().ok();
+  let size = records
+    .clone()
+    .map(|record| record.len() + 1)
+    .sum::<usize>();
+  let mut entry = loader::allocate_buffer(bs, size + ENTRY_OVERHEAD)?;
+  let result = format_entry(&mut entry, time.as_ref(), records, outcome)
+    .and_then(|len| append(bs, image, &entry[..len], time.as_ref()));
+  loader::free_buffer(bs, entry)?;
+  result
+}
+
+/// Writes the entry for this boot into `buffer`, returning its length.
+///
+/// # Arguments
+///
+/// * `buffer` - the buffer to write into
+/// * `time` - the time that the boot started, if known
+/// * `records` - the records of the early boot log
+/// * `outcome` - how the boot ended
+fn format_entry<'r>(
+  buffer: &mut [u8],
+  time: Option<&Time>,
+  records: impl Iterator<Item = &'r [u8]>,
+  outcome: &dyn fmt::Display,
+) -> uefi::Result<usize> {
+  let mut writer = SliceWriter::new(buffer);
+  let result = match time {
+    Some(time) => writeln!(
+      writer,
+      "{}{:04}-{:02}-{:02} {:02}:{:02}:{:02} ---",
+      ENTRY_MARKER,
+      time.year(),
+      time.month(),
+      time.day(),
+      time.hour(),
+      time.minute(),
+      time.second()
+    ),
+    None => writeln!(writer, "{}an unknown time ---", ENTRY_MARKER),
+  };
+  // Records are always valid UTF-8, as they are recorded from `str`s.
+  let result = records.fold(result, |result, record| {
+    let record = core::str::from_utf8(record).unwrap_or_default();
+    result.and_then(|_| writeln!(writer, "{}", record))
+  });
+  result
+    .and_then(|_| writeln!(writer, "{}", outcome))
+    .map_err(|_| Status::BUFFER_TOO_SMALL)?;
+  Ok(writer.len())
+}
+
+/// Appends `entry` to the persistent boot log, dropping the oldest entries
+/// that no longer fit.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `entry` - the entry to append
+/// * `time` - the time of the update, if known
+fn append(
+  bs: &BootServices,
+  image: Handle,
+  entry: &[u8],
+  time: Option<&Time>,
+) -> uefi::Result {
+  let mut root = fs::open_boot_volume(bs, image)?;
+  let existing = match fs::read_file(bs, &mut root, PATH, &mut ()) {
+    Ok(existing) => existing,
+    Err(err) if err.status() == Status::NOT_FOUND => Buffer::empty(),
+    Err(err) => return Err(err),
+  };
+  let kept = &existing[oldest_kept(&existing, entry.len())..];
+
+  let result = loader::allocate_buffer(bs, kept.len() + entry.len()).and_then(
+    |mut data| {
+      data[..kept.len()].copy_from_slice(kept);
+      data[kept.len()..].copy_from_slice(entry);
+      let result = fs::update_file(&mut root, PATH, &data, time);
+      loader::free_buffer(bs, data)?;
+      result
+    },
+  );
+  loader::free_buffer(bs, existing)?;
+  result
+}
+
+/// Returns the offset of the oldest entry of `log` that is kept when an entry
+/// of `len` bytes is appended, such that the log stays below [`SIZE_LIMIT`].
+///
+/// # Arguments
+///
+/// * `log` - the contents of the persistent boot log
+/// * `len` - the length of the entry to append
+fn oldest_kept(log: &[u8], len: usize) -> usize {
+  let excess = (log.len() + len).saturating_sub(SIZE_LIMIT);
+  if excess == 0 {
+    return 0;
+  }
+  (excess..log.len())
+    .find(|&offset| {
+      log[offset..].starts_with(ENTRY_MARKER.as_bytes())
+        && log[offset - 1] == b'\n'
+    })
+    .unwrap_or(log.len())
+}
@@ -0,0 +1,423 @@
+//! This module provides parsing for the bootloader's `boot.cfg` file.
+//!
+//! The configuration is a line-oriented `key = value` format, where anything
+//! following a `#` is treated as a comment. Unrecognized keys are ignored so
+//! that configurations written for newer bootloaders remain usable by older
+//! ones.
+
+use crate::blockio::LbaRanges;
+use core::str::FromStr;
+use crypto::sha256;
+use kcore::guid::Guid;
+
+/// The path of the boot configuration, relative to the boot volume root.
+pub const CONFIG_PATH: &str = r"\EFI\untitled\boot.cfg";
+
+#[derive(Clone, Copy)]
+enum ConfigErrorKind {
+  MissingSeparator,
+  BadInteger,
+  BadValue,
+}
+
+/// An error raised when the contents of `boot.cfg` are malformed.
+#[derive(Clone, Copy)]
+pub struct ParseConfigError {
+  line: usize,
+  kind: ConfigErrorKind,
+}
+
+impl core::fmt::Display for ParseConfigError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    match &self.kind {
+      ConfigErrorKind::MissingSeparator => write!(
+        f,
+        "boot.cfg line {}: expected an entry of the form 'key = value'",
+        self.line
+      ),
+      ConfigErrorKind::BadInteger => write!(
+        f,
+        "boot.cfg line {}: value is not a non-negative integer",
+        self.line
+      ),
+      ConfigErrorKind::BadValue => write!(
+        f,
+        "boot.cfg line {}: value is not valid for this key",
+        self.line
+      ),
+    }
+  }
+}
+
+impl core::fmt::Debug for ParseConfigError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    <Self as core::fmt::Display>::fmt(self, f)
+  }
+}
+
+impl From<ParseConfigError> for kcore::error::Error {
+  fn from(_: ParseConfigError) -> Self {
+    kcore::error::Error::InvalidArgument
+  }
+}
+
+/// Where the bootloader reads the kernel and initrd from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BootMode {
+  /// Payloads are read from the volume the bootloader was loaded from.
+  Disk,
+
+  /// Payloads are fetched from a TFTP server through PXE.
+  Network,
+
+  /// Payloads are read from the ISO9660 file system of the disk the
+  /// bootloader was loaded from, such as when it was booted from the El
+  /// Torito image of optical or ISO-hybrid media.
+  Iso,
+}
+
+/// The protocol used to hand control to the kernel.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+  /// The kernel is entered in the higher half with a `BootInfo`.
+  Native,
+
+  /// The kernel is booted as a Multiboot2-compliant kernel.
+  Multiboot2,
+
+  /// The kernel is booted as a Limine protocol kernel.
+  Limine,
+}
+
+/// Whether conventional memory is tested before the kernel is loaded.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MemoryTest {
+  /// Memory is not tested.
+  Off,
+
+  /// Memory is tested, and bad ranges are reported.
+  Report,
+
+  /// Memory is tested, and bad ranges are reported and withheld from the
+  /// kernel.
+  Exclude,
+}
+
+/// How much the bootloader writes to the console while booting.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+  /// Only the splash, and failures, are shown.
+  Quiet,
+
+  /// Progress and the steps of the boot are shown.
+  Normal,
+
+  /// Everything logged is shown, including details such as boot times, and
+  /// is echoed to the serial port.
+  Verbose,
+}
+
+/// The most additional modules that may be listed.
+pub const MAX_MODULES: usize = 16;
+
+/// A file to load alongside the kernel, such as an additional module.
+pub struct Module<'a> {
+  /// The path of the file.
+  pub path: &'a str,
+
+  /// The digest the file is required to have, if any.
+  pub sha256: Option<sha256::Digest>,
+}
+
+/// The files of a kind to load alongside the kernel, in the order they are
+/// listed.
+pub struct Modules<'a> {
+  modules: [Module<'a>; MAX_MODULES],
+  len: usize,
+}
+
+impl<'a> Modules<'a> {
+  /// A module that has not been listed.
+  const UNLISTED: Module<'a> = Module {
+    path: "",
+    sha256: None,
+  };
+
+  /// Constructs an empty [`Modules`].
+  pub const fn new() -> Self {
+    Self {
+      modules: [Self::UNLISTED; MAX_MODULES],
+      len: 0,
+    }
+  }
+
+  /// Appends `module` to the list.
+  ///
+  /// Returns [`None`] if there are already [`MAX_MODULES`] modules.
+  ///
+  /// # Arguments
+  ///
+  /// * `module` - the module to append
+  pub fn push(&mut self, module: Module<'a>) -> Option<()> {
+    *self.modules.get_mut(self.len)? = module;
+    self.len += 1;
+    Some(())
+  }
+
+  /// Returns the modules as a slice.
+  pub fn as_slice(&self) -> &[Module<'a>] {
+    &self.modules[..self.len]
+  }
+}
+
+impl Default for Modules<'_> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// The settings that control the behavior of the bootloader.
+///
+/// String settings borrow from the text the configuration was parsed from.
+pub struct Config<'a> {
+  /// The number of seconds the firmware watchdog is armed for while loading.
+  ///
+  /// A value of `0` leaves the watchdog disabled.
+  pub watchdog_timeout: usize,
+
+  /// Where the kernel and initrd are read from.
+  pub boot_mode: BootMode,
+
+  /// The unique or type GUID of the partition of the boot disk holding an
+  /// ext2, ext3, or ext4 file system to read the kernel and initrd from,
+  /// rather than the boot volume, if any.
+  pub partition: Option<Guid>,
+
+  /// The protocol used to hand control to the kernel.
+  pub protocol: Protocol,
+
+  /// The path of the kernel image.
+  pub kernel: &'a str,
+
+  /// The digest the kernel image is required to have, if any.
+  pub kernel_sha256: Option<sha256::Digest>,
+
+  /// The blocks of the boot disk to read the kernel image from, if it cannot
+  /// be read from [`Config::kernel`].
+  pub kernel_lba: LbaRanges,
+
+  /// The path of the initrd image, if one should be loaded.
+  pub initrd: Option<&'a str>,
+
+  /// The digest the initrd image is required to have, if any.
+  pub initrd_sha256: Option<sha256::Digest>,
+
+  /// The blocks of the boot disk to read the initrd image from, if it cannot
+  /// be read from [`Config::initrd`].
+  pub initrd_lba: LbaRanges,
+
+  /// The path of the manifest that the members of the initrd archive are
+  /// verified against, if any.
+  pub initrd_manifest: Option<&'a str>,
+
+  /// The additional files to load alongside the kernel, which are only
+  /// handed to kernels booted with the native protocol.
+  pub modules: Modules<'a>,
+
+  /// The device tree overlays to apply, in order, to the device tree that the
+  /// firmware provides, if any.
+  pub overlays: Modules<'a>,
+
+  /// The IPv4 address of the TFTP server, overriding the one given by DHCP.
+  pub tftp_server: Option<[u8; 4]>,
+
+  /// The number of times a failed TFTP transfer is retried.
+  pub tftp_retries: usize,
+
+  /// Whether conventional memory is tested before the kernel is loaded.
+  pub memtest: MemoryTest,
+
+  /// How much is written to the console while booting, unless overridden by
+  /// a key held at startup.
+  pub verbosity: Verbosity,
+
+  /// Whether the log of each boot is appended to the persistent boot log on
+  /// the boot volume.
+  pub boot_log: bool,
+}
+
+impl<'a> Config<'a> {
+  /// The default number of seconds before the watchdog resets the machine.
+  pub const DEFAULT_WATCHDOG_TIMEOUT: usize = 120;
+
+  /// The default path of the kernel image.
+  pub const DEFAULT_KERNEL: &'static str = r"\EFI\untitled\kernel.elf";
+
+  /// The default number of times a failed TFTP transfer is retried.
+  pub const DEFAULT_TFTP_RETRIES: usize = 3;
+
+  /// Constructs a [`Config`] with all settings at their default values.
+  pub const fn new() -> Self {
+    Self {
+      watchdog_timeout: Self::DEFAULT_WATCHDOG_TIMEOUT,
+      boot_mode: BootMode::Disk,
+      partition: None,
+      protocol: Protocol::Native,
+      kernel: Self::DEFAULT_KERNEL,
+      kernel_sha256: None,
+      kernel_lba: LbaRanges::new(),
+      initrd: None,
+      initrd_sha256: None,
+      initrd_lba: LbaRanges::new(),
+      initrd_manifest: None,
+      modules: Modules::new(),
+      overlays: Modules::new(),
+      tftp_server: None,
+      tftp_retries: Self::DEFAULT_TFTP_RETRIES,
+      memtest: MemoryTest::Off,
+      verbosity: Verbosity::Normal,
+      boot_log: false,
+    }
+  }
+
+  /// Parses a [`Config`] from the text contents of a `boot.cfg` file.
+  ///
+  /// Settings that are not specified in `text` retain their default values.
+  ///
+  /// # Arguments
+  ///
+  /// * `text` - the contents of the configuration file
+  pub fn parse(text: &'a str) -> Result<Self, ParseConfigError> {
+    let mut config = Self::new();
+    config.apply(text)?;
+    Ok(config)
+  }
+
+  /// Applies the settings given by `text`, in the format of a `boot.cfg` file,
+  /// on top of those already in this [`Config`].
+  ///
+  /// Settings that are not specified in `text` retain their current values.
+  ///
+  /// # Arguments
+  ///
+  /// * `text` - the settings to apply
+  pub fn apply(&mut self, text: &'a str) -> Result<(), ParseConfigError> {
+    let config = self;
+    for (index, line) in text.lines().enumerate() {
+      let line = match line.split_once('#') {
+        Some((content, _)) => content,
+        None => line,
+      }
+      .trim();
+      if line.is_empty() {
+        continue;
+      }
+
+      let error = |kind| ParseConfigError {
+        line: index + 1,
+        kind,
+      };
+      let integer = |value: &str| {
+        value
+          .parse::<usize>()
+          .map_err(|_| error(ConfigErrorKind::BadInteger))
+      };
+      let digest = |value: &str| {
+        sha256::Digest::from_str(value)
+          .map_err(|_| error(ConfigErrorKind::BadValue))
+      };
+      let ranges = |value: &str| {
+        LbaRanges::parse(value).ok_or(error(ConfigErrorKind::BadValue))
+      };
+      // Listed files are given as their path, optionally followed by their
+      // digest, and are added to those already listed.
+      let list = |modules: &mut Modules<'a>, value: &'a str| {
+        let mut fields = value.split_whitespace();
+        let module = Module {
+          path: fields.next().ok_or(error(ConfigErrorKind::BadValue))?,
+          sha256: fields.next().map(digest).transpose()?,
+        };
+        if fields.next().is_some() {
+          return Err(error(ConfigErrorKind::BadValue));
+        }
+        modules.push(module).ok_or(error(ConfigErrorKind::BadValue))
+      };
+      let (key, value) = line
+        .split_once('=')
+        .ok_or(error(ConfigErrorKind::MissingSeparator))?;
+      let (key, value) = (key.trim(), value.trim());
+
+      match key {
+        "watchdog_timeout" => config.watchdog_timeout = integer(value)?,
+        "boot_mode" => {
+          config.boot_mode = match value {
+            "disk" => BootMode::Disk,
+            "network" => BootMode::Network,
+            "iso" => BootMode::Iso,
+            _ => return Err(error(ConfigErrorKind::BadValue)),
+          }
+        }
+        "partition" => {
+          config.partition = Some(
+            Guid::try_parse(value).ok_or(error(ConfigErrorKind::BadValue))?,
+          )
+        }
+        "protocol" => {
+          config.protocol = match value {
+            "native" => Protocol::Native,
+            "multiboot2" => Protocol::Multiboot2,
+            "limine" => Protocol::Limine,
+            _ => return Err(error(ConfigErrorKind::BadValue)),
+          }
+        }
+        "kernel" => config.kernel = value,
+        "kernel_sha256" => config.kernel_sha256 = Some(digest(value)?),
+        "kernel_lba" => config.kernel_lba = ranges(value)?,
+        "initrd" => config.initrd = Some(value),
+        "initrd_sha256" => config.initrd_sha256 = Some(digest(value)?),
+        "initrd_lba" => config.initrd_lba = ranges(value)?,
+        "initrd_manifest" => config.initrd_manifest = Some(value),
+        "module" => list(&mut config.modules, value)?,
+        "dtb_overlay" => list(&mut config.overlays, value)?,
+        "tftp_server" => {
+          config.tftp_server = Some(
+            crate::net::parse_ipv4(value)
+              .ok_or(error(ConfigErrorKind::BadValue))?,
+          )
+        }
+        "tftp_retries" => config.tftp_retries = integer(value)?,
+        "memtest" => {
+          config.memtest = match value {
+            "off" => MemoryTest::Off,
+            "report" => MemoryTest::Report,
+            "exclude" => MemoryTest::Exclude,
+            _ => return Err(error(ConfigErrorKind::BadValue)),
+          }
+        }
+        "verbosity" => {
+          config.verbosity = match value {
+            "quiet" => Verbosity::Quiet,
+            "normal" => Verbosity::Normal,
+            "verbose" => Verbosity::Verbose,
+            _ => return Err(error(ConfigErrorKind::BadValue)),
+          }
+        }
+        "boot_log" => {
+          config.boot_log = match value {
+            "off" => false,
+            "on" => true,
+            _ => return Err(error(ConfigErrorKind::BadValue)),
+          }
+        }
+        _ => {}
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Default for Config<'_> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
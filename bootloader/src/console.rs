@@ -0,0 +1,290 @@
+//! This module provides the boot console, which draws the log on the graphics
+//! output in place of the firmware's text console.
+//!
+//! Firmware text consoles draw each character straight to the framebuffer, and
+//! scroll by moving the framebuffer's contents within it. On large
+//! framebuffers that are not cached, scrolling a long log that way visibly
+//! tears and can take seconds. The boot console instead draws to, and scrolls
+//! within, an off-screen copy of the screen, and copies only the rectangle
+//! that changed to the screen once each write is done.
+
+use crate::loader::{self, Buffer};
+use crate::progress;
+use core::fmt;
+use kcore::font::Font;
+use uefi::proto::console::gop::{BltOp, BltPixel, BltRegion, GraphicsOutput};
+use uefi::table::boot::{
+  BootServices, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol,
+};
+use uefi::{Handle, Status};
+
+/// The number of pixels of space left between lines of text, before scaling.
+const LEADING: usize = 2;
+
+/// The screen height that glyphs are drawn at their natural size up to, in
+/// pixels. Glyphs are scaled by whole multiples of this on taller screens,
+/// so that text stays legible.
+const SCALE_HEIGHT: usize = 480;
+
+/// The color of text.
+const FOREGROUND: BltPixel = BltPixel::new(0xc0, 0xc0, 0xc0);
+
+/// The color of the background.
+const BACKGROUND: BltPixel = BltPixel::new(0, 0, 0);
+
+/// The boot console.
+pub struct Console<'a> {
+  bs: &'a BootServices,
+  gop: ScopedProtocol<'a, GraphicsOutput>,
+
+  /// The font that text is drawn with.
+  font: Font<'static>,
+
+  /// The off-screen copy of the screen, which is drawn to and scrolled within
+  /// before being copied to the screen.
+  buffer: &'static mut [BltPixel],
+
+  /// The width and height of the screen, in pixels.
+  resolution: (usize, usize),
+
+  /// The number of pixels that each pixel of a glyph is drawn as, in each
+  /// direction.
+  scale: usize,
+
+  /// The number of columns and rows of text that fit on the screen.
+  columns: usize,
+  rows: usize,
+
+  /// The column and row that the next character is drawn at.
+  column: usize,
+  row: usize,
+
+  /// The rectangle of the copy that has changed since it was last copied to
+  /// the screen, if any.
+  damage: Option<Damage>,
+}
+
+impl<'a> Console<'a> {
+  /// Opens a [`Console`] on the graphics output, clearing the screen.
+  ///
+  /// Text is drawn above the strip at the bottom of the screen that progress
+  /// bars are drawn in.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  pub fn open(bs: &'a BootServices, image: Handle) -> uefi::Result<Self> {
+    let params = OpenProtocolParams {
+      handle: bs.get_handle_for_protocol::<GraphicsOutput>()?,
+      agent: image,
+      controller: None,
+    };
+    // SAFETY: the console driver holds the graphics output open, so it cannot
+    // be opened exclusively. It is only drawn to, never reconfigured.
+    let gop = unsafe {
+      bs.open_protocol::<GraphicsOutput>(
+        params,
+        OpenProtocolAttributes::GetProtocol,
+      )?
+    };
+
+    let (width, height) = gop.current_mode_info().resolution();
+    let font = Font::builtin();
+    let scale = (height / SCALE_HEIGHT).max(1);
+    let columns = width / (font.width() * scale);
+    let rows = height.saturating_sub(progress::RESERVED_HEIGHT)
+      / ((font.height() + LEADING) * scale);
+    if columns == 0 || rows == 0 {
+      return Err(Status::UNSUPPORTED.into());
+    }
+
+    let pixels = width * height;
+    let bytes =
+      loader::allocate_buffer(bs, pixels * core::mem::size_of::<BltPixel>())?
+        .leak();
+    // SAFETY: the buffer is large enough for every pixel of the screen, and
+    // is never referred to other than through the pixels. Pixels are plain
+    // bytes, for which any bytes are valid.
+    let buffer = unsafe {
+      core::slice::from_raw_parts_mut(bytes.as_mut_ptr().cast(), pixels)
+    };
+    buffer.fill(BACKGROUND);
+
+    let mut console = Self {
+      bs,
+      gop,
+      font,
+      buffer,
+      resolution: (width, height),
+      scale,
+      columns,
+      rows,
+      column: 0,
+      row: 0,
+      damage: None,
+    };
+    console.damage(0, 0, width, height);
+    console.flush();
+    Ok(console)
+  }
+
+  /// Returns the number of rows, of a grid of `rows` rows over the whole
+  /// screen, that are covered by the text drawn so far.
+  ///
+  /// # Arguments
+  ///
+  /// * `rows` - the number of rows of the grid
+  pub fn rows_covered(&self, rows: usize) -> usize {
+    let bottom = (self.row + 1) * self.line_height();
+    let height = self.resolution.1;
+    (bottom * rows + height - 1) / height
+  }
+
+  /// Returns the width of a character, in pixels.
+  fn char_width(&self) -> usize {
+    self.font.width() * self.scale
+  }
+
+  /// Returns the height of a line of text, in pixels.
+  fn line_height(&self) -> usize {
+    (self.font.height() + LEADING) * self.scale
+  }
+
+  /// Draws `c` at the cursor, and advances the cursor past it.
+  ///
+  /// # Arguments
+  ///
+  /// * `c` - the character to draw
+  fn draw(&mut self, c: char) {
+    if self.column == self.columns {
+      self.new_line();
+    }
+    let glyph = self.font.glyph_or_replacement(c);
+    let (width, height) = (self.char_width(), self.line_height());
+    let (x, y) = (self.column * width, self.row * height);
+    let stride = self.resolution.0;
+    for dy in 0..height {
+      let row = dy / self.scale;
+      let start = (y + dy) * stride + x;
+      for (dx, pixel) in
+        self.buffer[start..start + width].iter_mut().enumerate()
+      {
+        let lit = glyph.is_some_and(|glyph| {
+          row < glyph.height() && glyph.pixel(dx / self.scale, row)
+        });
+        *pixel = if lit { FOREGROUND } else { BACKGROUND };
+      }
+    }
+    self.damage(x, y, width, height);
+    self.column += 1;
+  }
+
+  /// Moves the cursor to the start of the next line, scrolling the text up by
+  /// a line if the cursor is on the last.
+  fn new_line(&mut self) {
+    self.column = 0;
+    if self.row + 1 < self.rows {
+      self.row += 1;
+      return;
+    }
+    let line = self.line_height() * self.resolution.0;
+    let text = self.rows * line;
+    self.buffer.copy_within(line..text, 0);
+    self.buffer[text - line..text].fill(BACKGROUND);
+    self.damage(0, 0, self.resolution.0, self.rows * self.line_height());
+  }
+
+  /// Records that the rectangle at `x` and `y`, of `width` by `height`
+  /// pixels, has changed.
+  ///
+  /// # Arguments
+  ///
+  /// * `x` - the left edge of the rectangle
+  /// * `y` - the top edge of the rectangle
+  /// * `width` - the width of the rectangle
+  /// * `height` - the height of the rectangle
+  fn damage(&mut self, x: usize, y: usize, width: usize, height: usize) {
+    let changed = Damage {
+      left: x,
+      top: y,
+      right: x + width,
+      bottom: y + height,
+    };
+    self.damage = Some(match self.damage {
+      Some(damage) => damage.union(changed),
+      None => changed,
+    });
+  }
+
+  /// Copies the rectangle that has changed, if any, to the screen.
+  fn flush(&mut self) {
+    let Some(damage) = self.damage.take() else {
+      return;
+    };
+    let _ = self.gop.blt(BltOp::BufferToVideo {
+      buffer: self.buffer,
+      src: BltRegion::SubRectangle {
+        coords: (damage.left, damage.top),
+        px_stride: self.resolution.0,
+      },
+      dest: (damage.left, damage.top),
+      dims: (damage.right - damage.left, damage.bottom - damage.top),
+    });
+  }
+}
+
+impl fmt::Write for Console<'_> {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    for c in s.chars() {
+      match c {
+        '\n' => self.new_line(),
+        '\r' => self.column = 0,
+        c if c.is_control() => {}
+        c => self.draw(c),
+      }
+    }
+    self.flush();
+    Ok(())
+  }
+}
+
+impl Drop for Console<'_> {
+  fn drop(&mut self) {
+    let len = core::mem::size_of_val(self.buffer);
+    // SAFETY: the pixels are the whole of the buffer allocated for them, which
+    // nothing refers to once the console is gone.
+    let bytes = unsafe {
+      Buffer::from_leaked(core::slice::from_raw_parts_mut(
+        self.buffer.as_mut_ptr().cast(),
+        len,
+      ))
+    };
+    let _ = loader::free_buffer(self.bs, bytes);
+  }
+}
+
+/// A rectangle of the screen that has changed, in pixels.
+#[derive(Clone, Copy)]
+struct Damage {
+  left: usize,
+  top: usize,
+  right: usize,
+  bottom: usize,
+}
+
+impl Damage {
+  /// Returns the smallest rectangle that covers both this one and `other`.
+  ///
+  /// # Arguments
+  ///
+  /// * `other` - the other rectangle
+  fn union(self, other: Self) -> Self {
+    Self {
+      left: self.left.min(other.left),
+      top: self.top.min(other.top),
+      right: self.right.max(other.right),
+      bottom: self.bottom.max(other.bottom),
+    }
+  }
+}
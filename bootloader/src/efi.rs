@@ -0,0 +1,13 @@
+//! This module provides the bootloader's own bindings to the UEFI protocols it
+//! uses, as part of replacing the external `uefi` crate.
+//!
+//! Each protocol is bound by a `#[repr(C)]` structure matching its layout in
+//! the UEFI specification, which implements [`protocol::Protocol`] to name its
+//! GUID, and is found and opened with the helpers in [`protocol`]. Whatever
+//! the firmware hands back through a protocol is wrapped by a safe type that
+//! owns it.
+
+pub mod file;
+pub mod loaded_image;
+pub mod protocol;
+pub mod rng;
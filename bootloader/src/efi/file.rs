@@ -0,0 +1,433 @@
+//! This module provides bindings to the Simple File System and File
+//! protocols, which give access to the files of a volume, such as the ESP.
+//!
+//! A [`File`] is a handle to an open file or directory, which is closed when it
+//! is dropped. Files are opened for reading, or for writing, which creates
+//! them if needed; files are never deleted.
+
+use super::protocol::Protocol;
+use crate::ucs2::{Ucs2Buf, Ucs2Str};
+use core::ptr::{self, NonNull};
+use kcore::bytes::read_u64;
+use kcore::guid::Guid;
+use uefi::table::runtime::Time;
+use uefi::{Status, StatusExt};
+
+/// The maximum length of a path, in UCS-2 characters, including the null
+/// terminator.
+const PATH_SIZE: usize = 256;
+
+/// The maximum length of a file name in a [`FileInfo`], in UCS-2 characters,
+/// including the null terminator.
+const NAME_SIZE: usize = 128;
+
+/// The size of the buffer that information about a file is read into.
+const INFO_SIZE: usize = 512;
+
+kcore::bitflags! {
+  /// The modes that a file is opened in.
+  struct OpenMode: u64 {
+    /// The file is read from.
+    const READ = 0x1;
+
+    /// The file is written to, which needs [`OpenMode::READ`] too.
+    const WRITE = 0x2;
+
+    /// The file is created if it does not exist, which needs
+    /// [`OpenMode::WRITE`] too.
+    const CREATE = 0x8000_0000_0000_0000;
+  }
+}
+
+kcore::bitflags! {
+  /// The attributes of a file.
+  pub struct Attributes: u64 {
+    /// The file may not be written to.
+    const READ_ONLY = 0x1;
+
+    /// The file is hidden from listings.
+    const HIDDEN = 0x2;
+
+    /// The file belongs to the system.
+    const SYSTEM = 0x4;
+
+    /// The file is a directory.
+    const DIRECTORY = 0x10;
+
+    /// The file has changed since it was last archived.
+    const ARCHIVE = 0x20;
+  }
+}
+
+/// The time zone of times that are not in any particular time zone.
+const UNSPECIFIED_TIMEZONE: i16 = 0x07ff;
+
+/// The identifier of the `EFI_FILE_INFO` information type.
+const FILE_INFO: Guid = Guid::parse("09576e92-6d3f-11d2-8e39-00a0c969723b");
+
+/// The identifier of the `EFI_FILE_SYSTEM_INFO` information type.
+const FILE_SYSTEM_INFO: Guid =
+  Guid::parse("09576e93-6d3f-11d2-8e39-00a0c969723b");
+
+/// The Simple File System protocol.
+#[repr(C)]
+pub struct SimpleFileSystem {
+  revision: u64,
+  open_volume: unsafe extern "efiapi" fn(
+    this: *mut Self,
+    root: *mut *mut FileProtocol,
+  ) -> Status,
+}
+
+kcore::const_assert_size!(SimpleFileSystem, 16);
+kcore::const_assert_offset!(SimpleFileSystem, revision: 0, open_volume: 8);
+
+// SAFETY: the structure matches the layout of the protocol.
+unsafe impl Protocol for SimpleFileSystem {
+  const GUID: Guid = Guid::parse("964e5b22-6459-11d2-8e39-00a0c969723b");
+}
+
+impl SimpleFileSystem {
+  /// Opens the root directory of the volume.
+  pub fn open_volume(&mut self) -> uefi::Result<File> {
+    let mut root = ptr::null_mut();
+    // SAFETY: `self` is a valid protocol, and `root` is valid for writes.
+    unsafe { (self.open_volume)(self, &mut root) }.to_result()?;
+    File::from_raw(root)
+  }
+}
+
+/// The File protocol.
+#[repr(C)]
+struct FileProtocol {
+  revision: u64,
+  open: unsafe extern "efiapi" fn(
+    this: *mut Self,
+    new_handle: *mut *mut Self,
+    file_name: *const u16,
+    open_mode: OpenMode,
+    attributes: Attributes,
+  ) -> Status,
+  close: unsafe extern "efiapi" fn(this: *mut Self) -> Status,
+  delete: usize,
+  read: unsafe extern "efiapi" fn(
+    this: *mut Self,
+    buffer_size: *mut usize,
+    buffer: *mut u8,
+  ) -> Status,
+  write: unsafe extern "efiapi" fn(
+    this: *mut Self,
+    buffer_size: *mut usize,
+    buffer: *const u8,
+  ) -> Status,
+  get_position: usize,
+  set_position:
+    unsafe extern "efiapi" fn(this: *mut Self, position: u64) -> Status,
+  get_info: unsafe extern "efiapi" fn(
+    this: *mut Self,
+    information_type: *const Guid,
+    buffer_size: *mut usize,
+    buffer: *mut u8,
+  ) -> Status,
+  set_info: unsafe extern "efiapi" fn(
+    this: *mut Self,
+    information_type: *const Guid,
+    buffer_size: usize,
+    buffer: *const u8,
+  ) -> Status,
+  flush: unsafe extern "efiapi" fn(this: *mut Self) -> Status,
+}
+
+kcore::const_assert_size!(FileProtocol, 88);
+kcore::const_assert_offset!(
+  FileProtocol,
+  revision: 0,
+  open: 8,
+  close: 16,
+  read: 32,
+  write: 40,
+  set_position: 56,
+  get_info: 64,
+  set_info: 72,
+  flush: 80,
+);
+
+/// A buffer that information about a file is read into, aligned to satisfy
+/// every information type.
+#[repr(C, align(8))]
+struct InfoBuffer([u8; INFO_SIZE]);
+
+/// An open file or directory.
+pub struct File {
+  raw: NonNull<FileProtocol>,
+}
+
+impl File {
+  /// Takes ownership of the file `raw` returned by the firmware.
+  ///
+  /// # Arguments
+  ///
+  /// * `raw` - the file, which must be open if it is not null
+  fn from_raw(raw: *mut FileProtocol) -> uefi::Result<Self> {
+    let raw = NonNull::new(raw).ok_or(Status::DEVICE_ERROR)?;
+    Ok(Self { raw })
+  }
+
+  fn raw(&mut self) -> &mut FileProtocol {
+    // SAFETY: the file stays open, and so valid, until it is dropped.
+    unsafe { self.raw.as_mut() }
+  }
+
+  /// Opens the file at `path`, relative to this directory, for reading.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the file, with components separated by `\` or
+  ///   `/`
+  pub fn open(&mut self, path: &str) -> uefi::Result<File> {
+    self.open_with_mode(path, OpenMode::READ)
+  }
+
+  /// Opens the file at `path`, relative to this directory, for reading and
+  /// writing, creating it if it does not exist.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the file, with components separated by `\` or
+  ///   `/`
+  pub fn open_writable(&mut self, path: &str) -> uefi::Result<File> {
+    self
+      .open_with_mode(path, OpenMode::READ | OpenMode::WRITE | OpenMode::CREATE)
+  }
+
+  /// Opens the file at `path`, relative to this directory, in `mode`.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the file
+  /// * `mode` - the mode to open the file in
+  fn open_with_mode(
+    &mut self,
+    path: &str,
+    mode: OpenMode,
+  ) -> uefi::Result<File> {
+    let name = Ucs2Buf::<PATH_SIZE>::from_path(path)?;
+
+    let raw = self.raw();
+    let mut file = ptr::null_mut();
+    // SAFETY: `raw` is a valid file, `file` is valid for writes, and `name` is
+    // null-terminated.
+    unsafe {
+      (raw.open)(raw, &mut file, name.as_ptr(), mode, Attributes::empty())
+    }
+    .to_result()?;
+    File::from_raw(file)
+  }
+
+  /// Reads from the current position of the file into `buffer`, returning the
+  /// number of bytes read, which is `0` at the end of the file.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer to read into
+  pub fn read(&mut self, buffer: &mut [u8]) -> uefi::Result<usize> {
+    let raw = self.raw();
+    let mut size = buffer.len();
+    // SAFETY: `raw` is a valid file, and `buffer` is valid for writes of
+    // `size` bytes.
+    unsafe { (raw.read)(raw, &mut size, buffer.as_mut_ptr()) }.to_result()?;
+    Ok(size)
+  }
+
+  /// Writes all of `data` at the current position of the file, extending the
+  /// file as needed.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the data to write
+  pub fn write(&mut self, data: &[u8]) -> uefi::Result {
+    let raw = self.raw();
+    let mut size = data.len();
+    // SAFETY: `raw` is a valid file, and `data` is valid for reads of `size`
+    // bytes.
+    unsafe { (raw.write)(raw, &mut size, data.as_ptr()) }.to_result()?;
+    // The firmware only writes less than asked for when it fails.
+    if size != data.len() {
+      return Err(Status::VOLUME_FULL.into());
+    }
+    Ok(())
+  }
+
+  /// Writes everything written to the file so far through to its device.
+  pub fn flush(&mut self) -> uefi::Result {
+    let raw = self.raw();
+    // SAFETY: `raw` is a valid file.
+    unsafe { (raw.flush)(raw) }.to_result()
+  }
+
+  /// Reads the next entry of this directory, returning `None` once every entry
+  /// has been read.
+  pub fn read_entry(&mut self) -> uefi::Result<Option<FileInfo>> {
+    let mut buffer = InfoBuffer([0; INFO_SIZE]);
+    match self.read(&mut buffer.0)? {
+      0 => Ok(None),
+      _ => Ok(Some(FileInfo::parse(&buffer.0))),
+    }
+  }
+
+  /// Moves the current position of the file to `position` bytes from its
+  /// start.
+  ///
+  /// # Arguments
+  ///
+  /// * `position` - the new position
+  pub fn set_position(&mut self, position: u64) -> uefi::Result {
+    let raw = self.raw();
+    // SAFETY: `raw` is a valid file.
+    unsafe { (raw.set_position)(raw, position) }.to_result()
+  }
+
+  /// Returns information about the file.
+  pub fn info(&mut self) -> uefi::Result<FileInfo> {
+    let buffer = self.get_info(&FILE_INFO)?;
+    Ok(FileInfo::parse(&buffer.0))
+  }
+
+  /// Sets the size of the file to `size` bytes, truncating or extending it,
+  /// and the times it was last accessed and modified to `time`, if given.
+  ///
+  /// # Arguments
+  ///
+  /// * `size` - the new size of the file
+  /// * `time` - the time of the modification, if known
+  pub fn set_size_and_time(
+    &mut self,
+    size: u64,
+    time: Option<&Time>,
+  ) -> uefi::Result {
+    let mut buffer = self.get_info(&FILE_INFO)?;
+    buffer.0[8..16].copy_from_slice(&size.to_le_bytes());
+    if let Some(time) = time {
+      let time = encode_time(time);
+      buffer.0[40..56].copy_from_slice(&time);
+      buffer.0[56..72].copy_from_slice(&time);
+    }
+    let len = read_u64(&buffer.0, 0) as usize;
+    if len > INFO_SIZE {
+      return Err(Status::BAD_BUFFER_SIZE.into());
+    }
+
+    let raw = self.raw();
+    // SAFETY: `raw` is a valid file, and `buffer` holds a file information
+    // structure of `len` bytes.
+    unsafe { (raw.set_info)(raw, &FILE_INFO, len, buffer.0.as_ptr()) }
+      .to_result()
+  }
+
+  /// Returns information about the volume that the file is on.
+  pub fn volume_info(&mut self) -> uefi::Result<FileSystemInfo> {
+    let buffer = self.get_info(&FILE_SYSTEM_INFO)?;
+    Ok(FileSystemInfo {
+      volume_size: read_u64(&buffer.0, 16),
+      free_space: read_u64(&buffer.0, 24),
+    })
+  }
+
+  /// Reads the information of type `id` about the file.
+  ///
+  /// # Arguments
+  ///
+  /// * `id` - the identifier of the information type
+  fn get_info(&mut self, id: &Guid) -> uefi::Result<InfoBuffer> {
+    let raw = self.raw();
+    let mut buffer = InfoBuffer([0; INFO_SIZE]);
+    let mut size = INFO_SIZE;
+    // SAFETY: `raw` is a valid file, and `buffer` is valid for writes of
+    // `size` bytes.
+    unsafe { (raw.get_info)(raw, id, &mut size, buffer.0.as_mut_ptr()) }
+      .to_result()?;
+    Ok(buffer)
+  }
+}
+
+impl Drop for File {
+  fn drop(&mut self) {
+    let raw = self.raw();
+    // SAFETY: `raw` is a valid file, which is not used again once closed.
+    let _ = unsafe { (raw.close)(raw) };
+  }
+}
+
+/// Information about a file.
+pub struct FileInfo {
+  /// The size of the file, in bytes.
+  pub file_size: u64,
+
+  /// The attributes of the file.
+  pub attributes: Attributes,
+
+  name: Ucs2Buf<NAME_SIZE>,
+}
+
+impl FileInfo {
+  /// Parses the `EFI_FILE_INFO` structure in `buffer`.
+  ///
+  /// Names too long to fit in a [`FileInfo`] are truncated.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer holding the structure
+  fn parse(buffer: &[u8; INFO_SIZE]) -> Self {
+    let mut info = Self {
+      file_size: read_u64(buffer, 8),
+      attributes: Attributes::from_bits_retain(read_u64(buffer, 72)),
+      name: Ucs2Buf::new(),
+    };
+    let units = buffer[80..].chunks_exact(2);
+    for unit in units.map(|b| u16::from_le_bytes([b[0], b[1]])) {
+      if unit == 0 || info.name.push_unit(unit).is_err() {
+        break;
+      }
+    }
+    info
+  }
+
+  /// Returns `true` if the file is a directory.
+  pub fn is_directory(&self) -> bool {
+    self.attributes.contains(Attributes::DIRECTORY)
+  }
+
+  /// Returns the name of the file, which can be displayed.
+  pub fn name(&self) -> &Ucs2Str {
+    &self.name
+  }
+}
+
+/// Information about a volume.
+pub struct FileSystemInfo {
+  /// The size of the volume, in bytes.
+  pub volume_size: u64,
+
+  /// The number of bytes free on the volume.
+  pub free_space: u64,
+}
+
+/// Returns `time` encoded as an `EFI_TIME` structure.
+///
+/// # Arguments
+///
+/// * `time` - the time to encode
+fn encode_time(time: &Time) -> [u8; 16] {
+  let mut bytes = [0; 16];
+  bytes[0..2].copy_from_slice(&time.year().to_le_bytes());
+  bytes[2] = time.month();
+  bytes[3] = time.day();
+  bytes[4] = time.hour();
+  bytes[5] = time.minute();
+  bytes[6] = time.second();
+  bytes[8..12].copy_from_slice(&time.nanosecond().to_le_bytes());
+  let zone = time.time_zone().unwrap_or(UNSPECIFIED_TIMEZONE);
+  bytes[12..14].copy_from_slice(&zone.to_le_bytes());
+  bytes[14] = time.daylight().bits();
+  bytes
+}
@@ -0,0 +1,72 @@
+//! This module provides bindings to the Loaded Image protocol, which describes
+//! an image loaded by the firmware, such as the bootloader itself.
+
+use super::protocol::{self, Protocol};
+use core::ffi::c_void;
+use kcore::guid::Guid;
+use uefi::table::boot::BootServices;
+use uefi::{Handle, Status};
+
+/// The Loaded Image protocol.
+#[repr(C)]
+pub struct LoadedImage {
+  revision: u32,
+  parent_handle: *mut c_void,
+  system_table: *const c_void,
+  device_handle: *mut c_void,
+  file_path: *const c_void,
+  reserved: *const c_void,
+  load_options_size: u32,
+  load_options: *const c_void,
+  image_base: *const c_void,
+  image_size: u64,
+  image_code_type: u32,
+  image_data_type: u32,
+  unload: usize,
+}
+
+kcore::const_assert_size!(LoadedImage, 96);
+kcore::const_assert_offset!(
+  LoadedImage,
+  revision: 0,
+  parent_handle: 8,
+  device_handle: 24,
+  load_options_size: 48,
+  load_options: 56,
+  image_base: 64,
+  image_size: 72,
+  image_code_type: 80,
+  unload: 88,
+);
+
+// SAFETY: the structure matches the layout of the protocol.
+unsafe impl Protocol for LoadedImage {
+  const GUID: Guid = Guid::parse("5b1b31a1-9562-11d2-8e3f-00a0c969723b");
+}
+
+impl LoadedImage {
+  /// Returns the handle of the device that the image was loaded from, or
+  /// `None` if it was not loaded from a device, such as when it was loaded
+  /// from memory.
+  pub fn device(&self) -> Option<Handle> {
+    // SAFETY: the firmware either leaves the device handle null or sets it to
+    // a valid handle.
+    unsafe { Handle::from_ptr(self.device_handle) }
+  }
+}
+
+/// Returns the handle of the device that `image` was loaded from.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the image
+pub fn device(bs: &BootServices, image: Handle) -> uefi::Result<Handle> {
+  // SAFETY: the firmware installs the Loaded Image protocol on the image for
+  // as long as the image runs, and it is only read from.
+  let loaded_image =
+    unsafe { protocol::handle_protocol::<LoadedImage>(bs, image)? };
+  loaded_image
+    .device()
+    .ok_or_else(|| Status::NOT_FOUND.into())
+}
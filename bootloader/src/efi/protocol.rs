@@ -0,0 +1,331 @@
+//! This module provides the [`Protocol`] trait implemented by every protocol
+//! binding, and the helpers that find and open protocols through the boot
+//! services.
+//!
+//! The helpers call the firmware's boot services table directly, viewed
+//! through [`RawBootServices`], rather than the `uefi` crate's wrappers, so
+//! that they work with any [`Protocol`].
+
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use kcore::guid::Guid;
+use uefi::table::boot::BootServices;
+use uefi::{Handle, Status, StatusExt};
+
+/// The attribute that opens a protocol without recording a user of it.
+const GET_PROTOCOL: u32 = 0x02;
+
+/// The attribute that opens a protocol for exclusive use, disconnecting any
+/// drivers that use it.
+const EXCLUSIVE: u32 = 0x20;
+
+/// The search type of `LocateHandleBuffer` that finds handles by protocol.
+const BY_PROTOCOL: u32 = 2;
+
+/// A protocol binding, identified by its GUID.
+///
+/// # Safety
+///
+/// Implementors must be `#[repr(C)]` structures matching the layout of the
+/// protocol identified by [`Protocol::GUID`].
+pub unsafe trait Protocol {
+  /// The GUID that identifies the protocol.
+  const GUID: Guid;
+}
+
+/// The boot services table, as laid out in the UEFI specification.
+///
+/// Only the functions used by this module are named; the rest are kept as
+/// placeholders of the same size.
+#[repr(C)]
+struct RawBootServices {
+  header: [u8; 24],
+  raise_tpl_to_allocate_pool: [usize; 6],
+  free_pool: unsafe extern "efiapi" fn(buffer: *mut c_void) -> Status,
+  create_event_to_uninstall_protocol_interface: [usize; 9],
+  handle_protocol: unsafe extern "efiapi" fn(
+    handle: *mut c_void,
+    protocol: *const Guid,
+    interface: *mut *mut c_void,
+  ) -> Status,
+  reserved_to_disconnect_controller: [usize; 15],
+  open_protocol: unsafe extern "efiapi" fn(
+    handle: *mut c_void,
+    protocol: *const Guid,
+    interface: *mut *mut c_void,
+    agent: *mut c_void,
+    controller: *mut c_void,
+    attributes: u32,
+  ) -> Status,
+  close_protocol: unsafe extern "efiapi" fn(
+    handle: *mut c_void,
+    protocol: *const Guid,
+    agent: *mut c_void,
+    controller: *mut c_void,
+  ) -> Status,
+  open_protocol_information: usize,
+  protocols_per_handle: usize,
+  locate_handle_buffer: unsafe extern "efiapi" fn(
+    search_type: u32,
+    protocol: *const Guid,
+    search_key: *const c_void,
+    count: *mut usize,
+    buffer: *mut *mut *mut c_void,
+  ) -> Status,
+  locate_protocol: unsafe extern "efiapi" fn(
+    protocol: *const Guid,
+    registration: *const c_void,
+    interface: *mut *mut c_void,
+  ) -> Status,
+}
+
+kcore::const_assert_size!(RawBootServices, 328);
+kcore::const_assert_offset!(
+  RawBootServices,
+  free_pool: 72,
+  handle_protocol: 152,
+  open_protocol: 280,
+  close_protocol: 288,
+  locate_handle_buffer: 312,
+  locate_protocol: 320,
+);
+
+/// Returns the firmware's boot services table behind `bs`.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+fn raw(bs: &BootServices) -> &RawBootServices {
+  // SAFETY: `BootServices` is a transparent wrapper of the firmware's boot
+  // services table, which `RawBootServices` matches the layout of.
+  unsafe { &*(bs as *const BootServices).cast::<RawBootServices>() }
+}
+
+/// How a protocol is opened by [`open_protocol`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+  /// The protocol is opened alongside any other users, such as the drivers
+  /// that manage it.
+  Shared,
+
+  /// The protocol is opened for exclusive use, disconnecting any drivers that
+  /// use it until it is closed.
+  Exclusive,
+}
+
+/// A protocol opened by [`open_protocol`], which is closed when dropped.
+pub struct Opened<'a, P: Protocol> {
+  bs: &'a BootServices,
+  handle: Handle,
+  agent: Handle,
+  interface: NonNull<P>,
+}
+
+impl<P: Protocol> Deref for Opened<'_, P> {
+  type Target = P;
+
+  fn deref(&self) -> &P {
+    // SAFETY: the protocol stays valid until it is closed, when dropped.
+    unsafe { self.interface.as_ref() }
+  }
+}
+
+impl<P: Protocol> DerefMut for Opened<'_, P> {
+  fn deref_mut(&mut self) -> &mut P {
+    // SAFETY: the protocol stays valid until it is closed, when dropped.
+    unsafe { self.interface.as_mut() }
+  }
+}
+
+impl<P: Protocol> Drop for Opened<'_, P> {
+  fn drop(&mut self) {
+    let raw = raw(self.bs);
+    // SAFETY: the protocol was opened on `handle` by `agent`, and is not used
+    // again once closed.
+    let _ = unsafe {
+      (raw.close_protocol)(
+        self.handle.as_ptr(),
+        &P::GUID,
+        self.agent.as_ptr(),
+        ptr::null_mut(),
+      )
+    };
+  }
+}
+
+/// Opens the protocol `P` on `handle` on behalf of `agent`.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `handle` - the handle to open the protocol on
+/// * `agent` - the handle of the image opening the protocol
+/// * `access` - how the protocol is opened
+///
+/// # Safety
+///
+/// With [`Access::Shared`], the protocol may be in use by others, who may
+/// change it from under the caller; the caller must not rely on it being left
+/// as it was, nor change it in ways that others do not expect.
+pub unsafe fn open_protocol<'a, P: Protocol>(
+  bs: &'a BootServices,
+  handle: Handle,
+  agent: Handle,
+  access: Access,
+) -> uefi::Result<Opened<'a, P>> {
+  let attributes = match access {
+    Access::Shared => GET_PROTOCOL,
+    Access::Exclusive => EXCLUSIVE,
+  };
+  let raw = raw(bs);
+  let mut interface = ptr::null_mut();
+  (raw.open_protocol)(
+    handle.as_ptr(),
+    &P::GUID,
+    &mut interface,
+    agent.as_ptr(),
+    ptr::null_mut(),
+    attributes,
+  )
+  .to_result()?;
+  let interface =
+    NonNull::new(interface.cast::<P>()).ok_or(Status::UNSUPPORTED)?;
+  Ok(Opened {
+    bs,
+    handle,
+    agent,
+    interface,
+  })
+}
+
+/// Opens the protocol `P` on `handle` for exclusive use by `agent`.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `handle` - the handle to open the protocol on
+/// * `agent` - the handle of the image opening the protocol
+pub fn open_protocol_exclusive<P: Protocol>(
+  bs: &BootServices,
+  handle: Handle,
+  agent: Handle,
+) -> uefi::Result<Opened<'_, P>> {
+  // SAFETY: nothing else can use the protocol while it is open exclusively.
+  unsafe { open_protocol(bs, handle, agent, Access::Exclusive) }
+}
+
+/// Returns the protocol `P` on `handle` for reading, without recording a user
+/// of it.
+///
+/// This suits protocols that are owned by the caller, such as the
+/// [`LoadedImage`](super::loaded_image::LoadedImage) of its own image.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `handle` - the handle to find the protocol on
+///
+/// # Safety
+///
+/// The protocol is not closed, and nothing stops its owner from removing it;
+/// the caller must ensure that it outlives the reference.
+pub unsafe fn handle_protocol<P: Protocol>(
+  bs: &BootServices,
+  handle: Handle,
+) -> uefi::Result<&P> {
+  let raw = raw(bs);
+  let mut interface = ptr::null_mut();
+  (raw.handle_protocol)(handle.as_ptr(), &P::GUID, &mut interface)
+    .to_result()?;
+  interface
+    .cast::<P>()
+    .cast_const()
+    .as_ref()
+    .ok_or_else(|| Status::UNSUPPORTED.into())
+}
+
+/// Returns the first instance of the protocol `P`, on whichever handle it is
+/// found, for reading, without recording a user of it.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+///
+/// # Safety
+///
+/// The protocol is not closed, and nothing stops its owner from removing it;
+/// the caller must ensure that it outlives the reference.
+pub unsafe fn locate_protocol<P: Protocol>(
+  bs: &BootServices,
+) -> uefi::Result<&P> {
+  let raw = raw(bs);
+  let mut interface = ptr::null_mut();
+  (raw.locate_protocol)(&P::GUID, ptr::null(), &mut interface).to_result()?;
+  interface
+    .cast::<P>()
+    .cast_const()
+    .as_ref()
+    .ok_or_else(|| Status::NOT_FOUND.into())
+}
+
+/// The handles that a protocol was found on by [`locate_handles`], which are
+/// freed when dropped.
+pub struct Handles<'a> {
+  bs: &'a BootServices,
+  buffer: NonNull<Handle>,
+  len: usize,
+  _handles: PhantomData<&'a [Handle]>,
+}
+
+impl Deref for Handles<'_> {
+  type Target = [Handle];
+
+  fn deref(&self) -> &[Handle] {
+    // SAFETY: the firmware allocated the buffer with `len` handles, which are
+    // never null, and it stays allocated until dropped.
+    unsafe { core::slice::from_raw_parts(self.buffer.as_ptr(), self.len) }
+  }
+}
+
+impl Drop for Handles<'_> {
+  fn drop(&mut self) {
+    let raw = raw(self.bs);
+    // SAFETY: the buffer was allocated by the firmware from pool, and is not
+    // used again once freed.
+    let _ = unsafe { (raw.free_pool)(self.buffer.as_ptr().cast()) };
+  }
+}
+
+/// Returns every handle that the protocol `P` is installed on.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+pub fn locate_handles<P: Protocol>(
+  bs: &BootServices,
+) -> uefi::Result<Handles<'_>> {
+  let raw = raw(bs);
+  let mut count = 0;
+  let mut buffer = ptr::null_mut();
+  // SAFETY: `count` and `buffer` are valid for writes.
+  unsafe {
+    (raw.locate_handle_buffer)(
+      BY_PROTOCOL,
+      &P::GUID,
+      ptr::null(),
+      &mut count,
+      &mut buffer,
+    )
+  }
+  .to_result()?;
+  let buffer =
+    NonNull::new(buffer.cast::<Handle>()).ok_or(Status::NOT_FOUND)?;
+  Ok(Handles {
+    bs,
+    buffer,
+    len: count,
+    _handles: PhantomData,
+  })
+}
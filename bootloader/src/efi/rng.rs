@@ -0,0 +1,44 @@
+//! This module provides bindings to the RNG protocol, through which the
+//! firmware hands out entropy from the random number generators of the
+//! platform, such as a TPM.
+
+use super::protocol::Protocol;
+use core::ptr;
+use kcore::guid::Guid;
+use uefi::{Status, StatusExt};
+
+/// The RNG protocol.
+#[repr(C)]
+pub struct Rng {
+  get_info: usize,
+  get_rng: unsafe extern "efiapi" fn(
+    this: *const Self,
+    algorithm: *const Guid,
+    len: usize,
+    value: *mut u8,
+  ) -> Status,
+}
+
+kcore::const_assert_size!(Rng, 16);
+kcore::const_assert_offset!(Rng, get_info: 0, get_rng: 8);
+
+// SAFETY: the structure matches the layout of the protocol.
+unsafe impl Protocol for Rng {
+  const GUID: Guid = Guid::parse("3152bca5-eade-433d-862e-c01cdc291f44");
+}
+
+impl Rng {
+  /// Fills `buffer` with entropy, from the firmware's default algorithm.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer to fill
+  pub fn fill(&self, buffer: &mut [u8]) -> uefi::Result {
+    // SAFETY: `self` is a valid protocol, and `buffer` is valid for writes of
+    // its length.
+    unsafe {
+      (self.get_rng)(self, ptr::null(), buffer.len(), buffer.as_mut_ptr())
+    }
+    .to_result()
+  }
+}
@@ -0,0 +1,245 @@
+//! This module provides just enough of ELF executables to load a kernel: the
+//! loadable segments, the relative relocations of position-independent
+//! executables, and the symbol table, on top of the parsing in
+//! [`kcore::elf`].
+//!
+//! Both 64-bit executables for the target architecture and 32-bit i386
+//! executables are accepted, since kernels booted through Multiboot2 are
+//! commonly the latter. Position-independent executables are only accepted
+//! as 64-bit executables.
+
+use crate::error::status_of;
+use kcore::elf::{
+  Dynamic, ProgramHeader, Relocations, SymbolTable, DT_REL, DT_RELA,
+  DT_RELAENT, DT_RELASZ, DT_RELR, EM_386, ET_DYN, ET_EXEC, PT_DYNAMIC, R_NONE,
+};
+use uefi::Status;
+
+pub use kcore::elf::{Class, PF_W, PF_X};
+
+/// A loadable segment of an ELF executable.
+#[derive(Clone, Copy)]
+pub struct Segment {
+  /// The offset of the segment's contents within the file.
+  pub offset: usize,
+
+  /// The virtual address the segment is loaded at.
+  pub vaddr: u64,
+
+  /// The physical address the segment is loaded at, which only Multiboot2
+  /// kernels are loaded by.
+  #[cfg_attr(not(target_arch = "x86_64"), allow(dead_code))]
+  pub paddr: u64,
+
+  /// The number of bytes of the segment stored in the file.
+  pub file_size: usize,
+
+  /// The number of bytes the segment occupies in memory; anything past
+  /// [`Segment::file_size`] is zero-filled.
+  pub mem_size: usize,
+
+  /// The `PF_*` permission flags of the segment.
+  pub flags: u32,
+}
+
+impl From<ProgramHeader> for Segment {
+  fn from(header: ProgramHeader) -> Self {
+    Self {
+      offset: header.offset as usize,
+      vaddr: header.vaddr,
+      paddr: header.paddr,
+      file_size: header.file_size as usize,
+      mem_size: header.mem_size as usize,
+      flags: header.flags,
+    }
+  }
+}
+
+/// A validated ELF executable.
+pub struct Elf<'a> {
+  elf: kcore::elf::Elf<'a>,
+  position_independent: bool,
+}
+
+impl<'a> Elf<'a> {
+  /// Parses and validates the ELF file header of `data`.
+  ///
+  /// Fails with [`Status::LOAD_ERROR`] if `data` is not a well-formed ELF
+  /// file, and with [`Status::UNSUPPORTED`] if it is not a little-endian
+  /// executable for the target architecture or i386, or a little-endian
+  /// position-independent executable for the target architecture.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the contents of the ELF file
+  pub fn parse(data: &'a [u8]) -> uefi::Result<Self> {
+    let elf = kcore::elf::Elf::parse(data).map_err(status_of)?;
+    let position_independent = match elf.file_type() {
+      ET_EXEC => false,
+      ET_DYN if elf.class() == Class::Elf64 => true,
+      _ => return Err(Status::UNSUPPORTED.into()),
+    };
+    if elf.machine() != machine(elf.class()) {
+      return Err(Status::UNSUPPORTED.into());
+    }
+    Ok(Self {
+      elf,
+      position_independent,
+    })
+  }
+
+  /// Returns the word size of the executable.
+  pub fn class(&self) -> Class {
+    self.elf.class()
+  }
+
+  /// Returns `true` if the executable is position-independent, and so may be
+  /// loaded at any address once [relocated](Elf::relocate).
+  pub fn is_position_independent(&self) -> bool {
+    self.position_independent
+  }
+
+  /// Returns the virtual address of the entry point.
+  pub fn entry(&self) -> u64 {
+    self.elf.entry()
+  }
+
+  /// Returns the raw contents of the ELF file.
+  pub fn data(&self) -> &'a [u8] {
+    self.elf.data()
+  }
+
+  /// Returns an iterator over the loadable segments of the executable.
+  ///
+  /// Fails with [`Status::LOAD_ERROR`] if any segment extends past the end of
+  /// the file, or is larger on disk than in memory.
+  pub fn segments(&self) -> impl Iterator<Item = uefi::Result<Segment>> + 'a {
+    self.elf.segments().map(|header| match header {
+      Ok(header) => Ok(header.into()),
+      Err(error) => Err(status_of(error).into()),
+    })
+  }
+
+  /// Applies the relocations of the executable to its `image`, which holds
+  /// its loadable segments laid out from the virtual address `base`, for
+  /// having been moved by `slide` from the addresses it was linked at.
+  ///
+  /// Only the relative relocations of the target architecture are supported;
+  /// executables with any others fail with [`Status::UNSUPPORTED`]. Fails
+  /// with [`Status::LOAD_ERROR`] if the relocations or the locations they
+  /// apply to lie outside of `image`. Executables that are not
+  /// position-independent are left untouched.
+  ///
+  /// # Arguments
+  ///
+  /// * `image` - the loaded segments of the executable
+  /// * `base` - the virtual address, as linked, of the start of `image`
+  /// * `slide` - the distance the executable was moved from where it was
+  ///   linked
+  pub fn relocate(
+    &self,
+    image: &mut [u8],
+    base: u64,
+    slide: u64,
+  ) -> uefi::Result {
+    if !self.position_independent {
+      return Ok(());
+    }
+    let class = self.elf.class();
+    let Some(dynamic) = self
+      .elf
+      .program_headers()
+      .find(|header| header.ty == PT_DYNAMIC)
+    else {
+      return Ok(());
+    };
+    let dynamic = slice(image, base, dynamic.vaddr, dynamic.mem_size as usize)?;
+
+    let (mut rela, mut rela_size, mut rela_entry) =
+      (None, 0, class.rela_size());
+    for (tag, value) in Dynamic::new(class, dynamic) {
+      match tag {
+        DT_RELA => rela = Some(value),
+        DT_RELASZ => rela_size = value as usize,
+        DT_RELAENT => rela_entry = value as usize,
+        DT_REL | DT_RELR => return Err(Status::UNSUPPORTED.into()),
+        _ => {}
+      }
+    }
+    let Some(rela) = rela else {
+      return Ok(());
+    };
+
+    Relocations::new(class, slice(image, base, rela, rela_size)?, rela_entry)
+      .map_err(status_of)?;
+    let start = (rela - base) as usize;
+    for index in 0..rela_size / rela_entry {
+      // Each relocation is read on its own, since the locations they apply
+      // to are in the same image.
+      let entry = &image[start + index * rela_entry..][..rela_entry];
+      let relocation = Relocations::new(class, entry, rela_entry)
+        .map_err(status_of)?
+        .next()
+        .ok_or(Status::LOAD_ERROR)?;
+      match relocation.ty {
+        R_NONE => {}
+        arch::target::ELF_RELATIVE => {
+          slice(image, base, relocation.offset, 8)?.copy_from_slice(
+            &(relocation.addend as u64).wrapping_add(slide).to_le_bytes(),
+          );
+        }
+        _ => return Err(Status::UNSUPPORTED.into()),
+      }
+    }
+    Ok(())
+  }
+
+  /// Returns the symbol table of the executable, or [`None`] if it has none
+  /// or is not a 64-bit executable.
+  ///
+  /// Fails with [`Status::LOAD_ERROR`] if the section headers or the tables
+  /// extend past the end of the file.
+  pub fn symbols(&self) -> uefi::Result<Option<SymbolTable<'a>>> {
+    if self.elf.class() != Class::Elf64 {
+      return Ok(None);
+    }
+    Ok(self.elf.symbol_table().map_err(status_of)?)
+  }
+}
+
+/// Returns the machine type that executables of `class` must target.
+///
+/// # Arguments
+///
+/// * `class` - the word size of the executable
+fn machine(class: Class) -> u16 {
+  match class {
+    Class::Elf32 => EM_386,
+    Class::Elf64 => arch::target::ELF_MACHINE,
+  }
+}
+
+/// Returns the `len` bytes of `image`, which is loaded from the virtual
+/// address `base`, that are at the virtual address `address`.
+///
+/// Fails with [`Status::LOAD_ERROR`] if they are not all within `image`.
+///
+/// # Arguments
+///
+/// * `image` - the loaded segments of an executable
+/// * `base` - the virtual address of the start of `image`
+/// * `address` - the virtual address of the bytes
+/// * `len` - the number of bytes
+fn slice(
+  image: &mut [u8],
+  base: u64,
+  address: u64,
+  len: usize,
+) -> uefi::Result<&mut [u8]> {
+  let start = address
+    .checked_sub(base)
+    .and_then(|start| usize::try_from(start).ok())
+    .ok_or(Status::LOAD_ERROR)?;
+  let end = start.checked_add(len).ok_or(Status::LOAD_ERROR)?;
+  image.get_mut(start..end).ok_or(Status::LOAD_ERROR.into())
+}
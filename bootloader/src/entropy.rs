@@ -0,0 +1,40 @@
+//! This module provides the seeding of [`BOOT_RNG`], the generator that the
+//! bootloader draws random numbers from, and hands the kernel a seed from in
+//! its [`BootInfo`](bootinfo::BootInfo).
+//!
+//! The generator is seeded from every source of entropy there is: the
+//! firmware's RNG protocol, the random number generator of the processor,
+//! and the cycle counter. The last only varies a little between boots, so a
+//! generator seeded from it alone is predictable, which is reported.
+
+use crate::efi::protocol;
+use crate::efi::rng::Rng;
+use crypto::drbg::{BOOT_RNG, SEED_SIZE};
+use kcore::rand::{Hardware, RngCore};
+use uefi::table::boot::BootServices;
+
+/// The personalization of the bootloader's generator.
+const PERSONALIZATION: &[u8] = b"bootloader";
+
+/// Seeds [`BOOT_RNG`], returning `false` if the cycle counter was the only
+/// source of entropy.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+pub fn seed(bs: &BootServices) -> bool {
+  let mut entropy = [0; 2 * SEED_SIZE + 8];
+  let (firmware, rest) = entropy.split_at_mut(SEED_SIZE);
+  let (processor, counter) = rest.split_at_mut(SEED_SIZE);
+
+  // SAFETY: the protocol is only used here, while boot services are active.
+  let rng = unsafe { protocol::locate_protocol::<Rng>(bs) };
+  let from_firmware = rng.and_then(|rng| rng.fill(firmware)).is_ok();
+  let from_processor =
+    Hardware::new().is_some_and(|mut rng| rng.try_fill(processor).is_ok());
+  counter.copy_from_slice(&arch::cycle_counter().to_le_bytes());
+
+  // Seeding only fails with less entropy than this holds.
+  let _ = BOOT_RNG.seed(&entropy, PERSONALIZATION);
+  from_firmware || from_processor
+}
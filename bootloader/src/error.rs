@@ -0,0 +1,323 @@
+//! This module provides the errors that stop the bootloader, and their
+//! reporting.
+//!
+//! Failures are recorded with the phase of boot they happened in and the file
+//! involved, if any, so that they can be reported to the user with a
+//! description of what went wrong and a suggestion of how to fix it, rather
+//! than as a bare status code returned to the firmware.
+
+use crate::log::{self, SerialWriter};
+use core::fmt::{self, Write};
+use uefi::table::boot::BootServices;
+use uefi::table::{Boot, SystemTable};
+use uefi::{Handle, Status};
+
+/// The number of microseconds that a reported error is left on screen before
+/// returning to the firmware, which may clear it.
+const REPORT_DELAY: usize = 10_000_000;
+
+/// A specialized [`Result`](core::result::Result) for failures that stop the
+/// bootloader.
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// The phase of boot that a failure happened in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+  /// Setting up the bootloader itself.
+  Startup,
+
+  /// Opening the source that payloads are read from.
+  Source,
+
+  /// Loading the kernel.
+  Kernel,
+
+  /// Loading the initrd.
+  Initrd,
+
+  /// Loading an additional module.
+  Module,
+
+  /// Loading a device tree overlay and applying it.
+  Overlay,
+
+  /// Laying out the kernel and building the state it is entered with.
+  Prepare,
+
+  /// Leaving the firmware and entering the kernel.
+  Handoff,
+}
+
+impl Phase {
+  /// Returns a description of what was being done in this phase, completing
+  /// the sentence "failed to ...".
+  fn action(self) -> &'static str {
+    match self {
+      Phase::Startup => "start the bootloader",
+      Phase::Source => "open the boot source",
+      Phase::Kernel => "load the kernel",
+      Phase::Initrd => "load the initrd",
+      Phase::Module => "load the module",
+      Phase::Overlay => "apply the device tree overlay",
+      Phase::Prepare => "prepare the kernel",
+      Phase::Handoff => "hand off to the kernel",
+    }
+  }
+}
+
+/// A failure that stops the bootloader.
+#[derive(Clone, Copy)]
+pub struct Error {
+  phase: Phase,
+  path: Option<&'static str>,
+  status: Status,
+}
+
+impl Error {
+  /// Constructs an [`Error`] for a failure with `status` in `phase`.
+  ///
+  /// # Arguments
+  ///
+  /// * `phase` - the phase of boot that failed
+  /// * `status` - the status that the failure was reported with
+  pub const fn new(phase: Phase, status: Status) -> Self {
+    Self {
+      phase,
+      path: None,
+      status,
+    }
+  }
+
+  /// Returns this error, recording that it concerns the file at `path`.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the file involved
+  pub const fn with_path(self, path: &'static str) -> Self {
+    Self {
+      path: Some(path),
+      ..self
+    }
+  }
+
+  /// Returns the status that the failure was reported with.
+  pub fn status(&self) -> Status {
+    self.status
+  }
+
+  /// Returns a suggestion of how the user may fix the failure.
+  fn remediation(&self) -> &'static str {
+    match (self.phase, self.status) {
+      (_, Status::SECURITY_VIOLATION) => {
+        "the file does not match its digest in boot.cfg or its manifest; \
+         reinstall the file or update the digest"
+      }
+      (_, Status::OUT_OF_RESOURCES) => {
+        "there is not enough free memory; reduce the size of the payloads or \
+         add memory"
+      }
+      (Phase::Source, _) => {
+        "check that the boot volume is readable, or that the network is \
+         connected and a DHCP server is reachable"
+      }
+      (Phase::Overlay, Status::LOAD_ERROR | Status::NOT_FOUND) => {
+        "the overlay is malformed or targets a node or label that the \
+         firmware's device tree lacks; rebuild it with 'dtc -@'"
+      }
+      (Phase::Kernel | Phase::Initrd | Phase::Module, Status::NOT_FOUND) => {
+        "check that the path in boot.cfg names a file on the boot volume, or \
+         set its '_lba' key to read it from the boot disk"
+      }
+      (
+        Phase::Kernel | Phase::Initrd | Phase::Module,
+        Status::TIMEOUT | Status::TFTP_ERROR | Status::NO_RESPONSE,
+      ) => "check that the TFTP server is running and serves the file",
+      (
+        Phase::Kernel | Phase::Initrd | Phase::Module,
+        Status::LOAD_ERROR | Status::CRC_ERROR | Status::UNSUPPORTED,
+      ) => {
+        "the file is corrupt or compressed in an unsupported format; \
+         reinstall it"
+      }
+      (Phase::Prepare, Status::UNSUPPORTED | Status::LOAD_ERROR) => {
+        "the kernel is not an executable for this machine, or does not \
+         support the protocol set in boot.cfg"
+      }
+      _ => "check boot.cfg and the installed files, then try again",
+    }
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "error: failed to {}", self.phase.action())?;
+    if let Some(path) = self.path {
+      write!(f, " '{}'", path)?;
+    }
+    writeln!(f, ": {} ({:?})", describe(self.status), self.status)?;
+    write!(f, "hint: {}", self.remediation())
+  }
+}
+
+impl fmt::Debug for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    <Self as fmt::Display>::fmt(self, f)
+  }
+}
+
+/// Attaches the phase of boot to failures reported by the firmware.
+pub trait Context<T> {
+  /// Converts a failure into an [`Error`] that happened in `phase`.
+  ///
+  /// # Arguments
+  ///
+  /// * `phase` - the phase of boot that failed
+  fn context(self, phase: Phase) -> Result<T>;
+}
+
+impl<T, D: fmt::Debug> Context<T> for uefi::Result<T, D> {
+  fn context(self, phase: Phase) -> Result<T> {
+    self.map_err(|err| Error::new(phase, err.status()))
+  }
+}
+
+impl<T> Context<T> for kcore::error::Result<T> {
+  fn context(self, phase: Phase) -> Result<T> {
+    self.map_err(|err| Error::new(phase, status_of(err)))
+  }
+}
+
+impl From<Error> for kcore::error::Error {
+  fn from(error: Error) -> Self {
+    kind_of(error.status)
+  }
+}
+
+/// Returns a description of what `status` means.
+///
+/// # Arguments
+///
+/// * `status` - the status to describe
+pub fn describe(status: Status) -> &'static str {
+  match status {
+    Status::SUCCESS => "success",
+    Status::LOAD_ERROR => "the file is malformed",
+    Status::INVALID_PARAMETER => "a parameter was incorrect",
+    Status::UNSUPPORTED => "the operation is not supported",
+    Status::BAD_BUFFER_SIZE => "the data is larger than can be handled",
+    Status::BUFFER_TOO_SMALL => "a buffer was too small for the data",
+    Status::NOT_READY => "there is no data pending",
+    Status::DEVICE_ERROR => "the device reported an error",
+    Status::WRITE_PROTECTED => "the device is write-protected",
+    Status::OUT_OF_RESOURCES => "out of memory",
+    Status::VOLUME_CORRUPTED => "the file system is corrupted",
+    Status::VOLUME_FULL => "the file system is full",
+    Status::NO_MEDIA => "the device contains no media",
+    Status::MEDIA_CHANGED => "the media has changed",
+    Status::NOT_FOUND => "not found",
+    Status::ACCESS_DENIED => "access denied",
+    Status::NO_RESPONSE => "the server did not respond",
+    Status::NO_MAPPING => "there is no mapping to the device",
+    Status::TIMEOUT => "timed out",
+    Status::NOT_STARTED => "the protocol has not been started",
+    Status::ALREADY_STARTED => "the protocol has already been started",
+    Status::ABORTED => "the operation was aborted",
+    Status::ICMP_ERROR => "an ICMP error occurred",
+    Status::TFTP_ERROR => "a TFTP error occurred",
+    Status::PROTOCOL_ERROR => "a network protocol error occurred",
+    Status::SECURITY_VIOLATION => "the digest does not match",
+    Status::CRC_ERROR => "the checksum does not match",
+    Status::END_OF_MEDIA => "the end of the media was reached",
+    Status::END_OF_FILE => "the end of the file was reached",
+    Status::COMPROMISED_DATA => "the data has been compromised",
+    Status::HTTP_ERROR => "an HTTP error occurred",
+    _ => "an unknown error occurred",
+  }
+}
+
+/// Returns the kind of failure that `status` reports.
+///
+/// # Arguments
+///
+/// * `status` - the status to classify
+pub fn kind_of(status: Status) -> kcore::error::Error {
+  use kcore::error::Error;
+  match status {
+    Status::NOT_FOUND => Error::NotFound,
+    Status::OUT_OF_RESOURCES | Status::VOLUME_FULL => Error::NoMemory,
+    Status::INVALID_PARAMETER | Status::BAD_BUFFER_SIZE => {
+      Error::InvalidArgument
+    }
+    Status::UNSUPPORTED | Status::INCOMPATIBLE_VERSION => Error::Unsupported,
+    Status::ACCESS_DENIED
+    | Status::WRITE_PROTECTED
+    | Status::SECURITY_VIOLATION => Error::PermissionDenied,
+    Status::ALREADY_STARTED => Error::AlreadyExists,
+    Status::NOT_READY | Status::NOT_STARTED => Error::Busy,
+    Status::TIMEOUT | Status::NO_RESPONSE => Error::Timeout,
+    Status::LOAD_ERROR
+    | Status::VOLUME_CORRUPTED
+    | Status::CRC_ERROR
+    | Status::COMPROMISED_DATA => Error::Corrupted,
+    Status::BUFFER_TOO_SMALL => Error::BufferTooSmall,
+    _ => Error::IoError,
+  }
+}
+
+/// Returns the status that reports failures of `kind` to the firmware.
+///
+/// # Arguments
+///
+/// * `kind` - the kind of failure
+pub fn status_of(kind: kcore::error::Error) -> Status {
+  use kcore::error::Error;
+  match kind {
+    Error::NotFound => Status::NOT_FOUND,
+    Error::AlreadyExists => Status::ALREADY_STARTED,
+    Error::NoMemory => Status::OUT_OF_RESOURCES,
+    Error::InvalidArgument => Status::INVALID_PARAMETER,
+    Error::Unsupported => Status::UNSUPPORTED,
+    Error::IoError => Status::DEVICE_ERROR,
+    Error::PermissionDenied => Status::ACCESS_DENIED,
+    Error::Busy => Status::NOT_READY,
+    Error::Timeout => Status::TIMEOUT,
+    Error::Corrupted => Status::LOAD_ERROR,
+    Error::BufferTooSmall => Status::BUFFER_TOO_SMALL,
+  }
+}
+
+/// Reports `error` on the console and the first serial port, then waits for
+/// long enough that it can be read before returning to the firmware.
+///
+/// # Arguments
+///
+/// * `system_table` - the system table
+/// * `image` - the handle of the bootloader image
+/// * `error` - the failure to report
+pub fn report(
+  system_table: &mut SystemTable<Boot>,
+  image: Handle,
+  error: &Error,
+) {
+  let _ = writeln!(system_table.stdout(), "{}", error);
+  let bs = system_table.boot_services();
+  let _ = write_serial(bs, image, error);
+  bs.stall(REPORT_DELAY);
+}
+
+/// Writes `error` to the first serial port, if there is one.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `error` - the failure to write
+fn write_serial(
+  bs: &BootServices,
+  image: Handle,
+  error: &Error,
+) -> uefi::Result {
+  let mut serial = log::open_serial(bs, image)?;
+  let _ = writeln!(SerialWriter(&mut serial), "{}", error);
+  Ok(())
+}
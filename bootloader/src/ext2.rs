@@ -0,0 +1,551 @@
+//! This module provides a read-only driver for ext2 file systems, and the
+//! ext3 and ext4 file systems that extend it, so that payloads can be read
+//! from a Linux-style boot partition rather than only from the ESP.
+//!
+//! The partition is found in the boot disk's GUID partition table, and read
+//! through the firmware's Block I/O protocol. Files may be mapped by block
+//! lists or by ext4 extents. The journal is never replayed, so changes that
+//! were not yet checkpointed when the file system was last unmounted are not
+//! seen. File systems using features that change how files are found or
+//! stored, such as inline data or encryption, are refused.
+
+use crate::blockio::BlockReader;
+use crate::error::status_of;
+use crate::gpt;
+use crate::loader::{self, Buffer, Progress, Source, Step};
+use kcore::bytes::{read_u16, read_u32};
+use kcore::guid::Guid;
+use kcore::path::{Component, Path, PathBuf, MAX_PATH_LEN};
+use uefi::table::boot::BootServices;
+use uefi::{Handle, Status};
+
+/// The byte offset of the superblock from the start of the file system.
+const SUPERBLOCK_OFFSET: u64 = 1024;
+
+/// The magic number of the superblock.
+const MAGIC: u16 = 0xef53;
+
+/// The inode of the root directory.
+const ROOT_INODE: u32 = 2;
+
+/// The size of inodes on file systems of the original revision.
+const ORIGINAL_INODE_SIZE: usize = 128;
+
+/// The size of group descriptors without the 64-bit feature.
+const ORIGINAL_DESC_SIZE: usize = 32;
+
+/// The directory entries record the type of the file.
+const INCOMPAT_FILETYPE: u32 = 0x2;
+
+/// The journal needs to be replayed.
+const INCOMPAT_RECOVER: u32 = 0x4;
+
+/// Files may be mapped by extents.
+const INCOMPAT_EXTENTS: u32 = 0x40;
+
+/// Block numbers and group descriptors are 64-bit.
+const INCOMPAT_64BIT: u32 = 0x80;
+
+/// The file system is protected against being mounted on several hosts.
+const INCOMPAT_MMP: u32 = 0x100;
+
+/// The metadata of groups may be stored together in their first group.
+const INCOMPAT_FLEX_BG: u32 = 0x200;
+
+/// Large extended attributes may be stored in inodes of their own.
+const INCOMPAT_EA_INODE: u32 = 0x400;
+
+/// The checksum seed is stored in the superblock.
+const INCOMPAT_CSUM_SEED: u32 = 0x2000;
+
+/// Directories may be larger than 2 GiB, or have deeper hash trees.
+const INCOMPAT_LARGEDIR: u32 = 0x4000;
+
+/// The incompatible features that do not affect reading files.
+const SUPPORTED_INCOMPAT: u32 = INCOMPAT_FILETYPE
+  | INCOMPAT_RECOVER
+  | INCOMPAT_EXTENTS
+  | INCOMPAT_64BIT
+  | INCOMPAT_MMP
+  | INCOMPAT_FLEX_BG
+  | INCOMPAT_EA_INODE
+  | INCOMPAT_CSUM_SEED
+  | INCOMPAT_LARGEDIR;
+
+/// The inode flag marking a file that is mapped by extents.
+const EXTENTS_FL: u32 = 0x8_0000;
+
+/// The inode flag marking a file whose data is stored in the inode itself.
+const INLINE_DATA_FL: u32 = 0x1000_0000;
+
+/// The bits of an inode's mode that give the type of file.
+const MODE_TYPE: u16 = 0xf000;
+
+/// The type of a directory.
+const MODE_DIRECTORY: u16 = 0x4000;
+
+/// The type of a regular file.
+const MODE_REGULAR: u16 = 0x8000;
+
+/// The magic number of an extent tree node.
+const EXTENT_MAGIC: u16 = 0xf30a;
+
+/// The size of extent tree node headers and entries.
+const EXTENT_ENTRY_SIZE: usize = 12;
+
+/// The length of an extent is above this if the extent is uninitialized,
+/// reading as zeroes.
+const EXTENT_MAX_INITIALIZED: u16 = 32768;
+
+/// The number of block pointers held in an inode that map blocks directly.
+const DIRECT_BLOCKS: u64 = 12;
+
+/// An inode, with just the fields needed to read the file it describes.
+struct Inode {
+  mode: u16,
+  size: u64,
+  flags: u32,
+  block: [u8; 60],
+}
+
+impl Inode {
+  /// Returns `true` if the inode is of the file type `ty`.
+  ///
+  /// # Arguments
+  ///
+  /// * `ty` - the type of file, one of the `MODE_*` types
+  fn is(&self, ty: u16) -> bool {
+    self.mode & MODE_TYPE == ty
+  }
+}
+
+/// A node of an extent tree, which is either held within an inode or stored
+/// in a block of its own.
+#[derive(Clone, Copy)]
+enum Node<'a> {
+  /// The root node, held in the block pointers of an inode.
+  Inode(&'a [u8; 60]),
+
+  /// A node stored in the block with the given number.
+  Block(u64),
+}
+
+/// A read-only ext2, ext3, or ext4 file system.
+pub struct Ext2<'a> {
+  disk: BlockReader<'a>,
+  start: u64,
+  end: u64,
+  block_size: u64,
+  inodes_per_group: u32,
+  inode_size: usize,
+  desc_size: usize,
+  descriptors: u64,
+  incompat: u32,
+}
+
+impl<'a> Ext2<'a> {
+  /// Opens the file system on the partition of the boot disk whose unique or
+  /// type GUID is `partition`.
+  ///
+  /// Fails with [`Status::VOLUME_CORRUPTED`] if the partition does not hold
+  /// an ext2, ext3, or ext4 file system, and with [`Status::UNSUPPORTED`] if
+  /// the file system uses features that this driver cannot read.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  /// * `partition` - the unique or type GUID of the partition
+  pub fn open(
+    bs: &'a BootServices,
+    image: Handle,
+    partition: &Guid,
+  ) -> uefi::Result<Self> {
+    let mut disk = BlockReader::open_boot_disk(bs, image)?;
+    let partition = gpt::find(&mut disk, partition)?;
+    let block_size = disk.block_size() as u64;
+    let start = partition.first_lba * block_size;
+    let end = (partition.last_lba + 1) * block_size;
+
+    let mut superblock = [0; 256];
+    disk.read(start + SUPERBLOCK_OFFSET, &mut superblock)?;
+    if read_u16(&superblock, 56) != MAGIC {
+      return Err(Status::VOLUME_CORRUPTED.into());
+    }
+    let incompat = read_u32(&superblock, 96);
+    if incompat & !SUPPORTED_INCOMPAT != 0 {
+      return Err(Status::UNSUPPORTED.into());
+    }
+
+    let log_block_size = read_u32(&superblock, 24);
+    let inodes_per_group = read_u32(&superblock, 40);
+    let (inode_size, desc_size) = match read_u32(&superblock, 76) {
+      0 => (ORIGINAL_INODE_SIZE, ORIGINAL_DESC_SIZE),
+      _ => {
+        let desc_size = match incompat & INCOMPAT_64BIT {
+          0 => ORIGINAL_DESC_SIZE,
+          _ => read_u16(&superblock, 254) as usize,
+        };
+        (read_u16(&superblock, 88) as usize, desc_size)
+      }
+    };
+    if log_block_size > 6
+      || inodes_per_group == 0
+      || inode_size < ORIGINAL_INODE_SIZE
+      || desc_size < ORIGINAL_DESC_SIZE
+    {
+      return Err(Status::VOLUME_CORRUPTED.into());
+    }
+
+    // The group descriptors follow the block holding the superblock.
+    let first_data_block = read_u32(&superblock, 20) as u64;
+    Ok(Self {
+      disk,
+      start,
+      end,
+      block_size: 1024 << log_block_size,
+      inodes_per_group,
+      inode_size,
+      desc_size,
+      descriptors: first_data_block + 1,
+      incompat,
+    })
+  }
+
+  /// Reads `buffer.len()` bytes at byte `offset` of the file system.
+  ///
+  /// Fails with [`Status::VOLUME_CORRUPTED`] if any of the bytes lie beyond
+  /// the end of the partition.
+  ///
+  /// # Arguments
+  ///
+  /// * `offset` - the byte offset within the file system
+  /// * `buffer` - the buffer to read into
+  fn read_bytes(&mut self, offset: u64, buffer: &mut [u8]) -> uefi::Result {
+    let position = self
+      .start
+      .checked_add(offset)
+      .filter(|position| {
+        position
+          .checked_add(buffer.len() as u64)
+          .is_some_and(|end| end <= self.end)
+      })
+      .ok_or(Status::VOLUME_CORRUPTED)?;
+    self.disk.read(position, buffer)
+  }
+
+  /// Reads the consecutive blocks starting at `block` into `buffer`, whose
+  /// length is a multiple of the block size.
+  ///
+  /// Blocks that are page-aligned on the disk are read straight into
+  /// `buffer`, bypassing the read cache.
+  ///
+  /// # Arguments
+  ///
+  /// * `block` - the first block to read
+  /// * `buffer` - the page-aligned buffer to read the blocks into
+  fn read_blocks(&mut self, block: u64, buffer: &mut [u8]) -> uefi::Result {
+    let offset = block
+      .checked_mul(self.block_size)
+      .ok_or(Status::VOLUME_CORRUPTED)?;
+    let page = loader::PAGE_SIZE as u64;
+    let position = self.start + offset;
+    let disk_block_size = self.disk.block_size() as u64;
+    if self.block_size % page != 0 || position % disk_block_size != 0 {
+      return self.read_bytes(offset, buffer);
+    }
+    if position + buffer.len() as u64 > self.end {
+      return Err(Status::VOLUME_CORRUPTED.into());
+    }
+    self.disk.read_blocks(position / disk_block_size, buffer)
+  }
+
+  /// Reads the inode numbered `number`.
+  ///
+  /// # Arguments
+  ///
+  /// * `number` - the number of the inode, counting from 1
+  fn inode(&mut self, number: u32) -> uefi::Result<Inode> {
+    let index = number.checked_sub(1).ok_or(Status::VOLUME_CORRUPTED)?;
+    let group = (index / self.inodes_per_group) as u64;
+    let index = (index % self.inodes_per_group) as u64;
+
+    let mut descriptor = [0; 64];
+    let descriptor = &mut descriptor[..self.desc_size.min(64)];
+    let offset =
+      self.descriptors * self.block_size + group * self.desc_size as u64;
+    self.read_bytes(offset, descriptor)?;
+    let mut table = read_u32(descriptor, 8) as u64;
+    if self.incompat & INCOMPAT_64BIT != 0 && descriptor.len() >= 64 {
+      table |= (read_u32(descriptor, 40) as u64) << 32;
+    }
+
+    let mut inode = [0; ORIGINAL_INODE_SIZE];
+    let offset = table * self.block_size + index * self.inode_size as u64;
+    self.read_bytes(offset, &mut inode)?;
+    let mut block = [0; 60];
+    block.copy_from_slice(&inode[40..100]);
+    Ok(Inode {
+      mode: read_u16(&inode, 0),
+      size: read_u32(&inode, 4) as u64 | (read_u32(&inode, 108) as u64) << 32,
+      flags: read_u32(&inode, 32),
+      block,
+    })
+  }
+
+  /// Returns the block holding block `logical` of the file `inode`, and the
+  /// number of blocks from there that are known to be consecutive on disk.
+  ///
+  /// Blocks that are not stored, such as holes in sparse files, are reported
+  /// as block `0`, and are read as zeroes.
+  ///
+  /// # Arguments
+  ///
+  /// * `inode` - the file
+  /// * `logical` - the index of the block within the file
+  fn map(&mut self, inode: &Inode, logical: u64) -> uefi::Result<(u64, u64)> {
+    if inode.flags & INLINE_DATA_FL != 0 {
+      return Err(Status::UNSUPPORTED.into());
+    }
+    if inode.flags & EXTENTS_FL != 0 {
+      return self.map_extent(Node::Inode(&inode.block), logical);
+    }
+
+    // Block lists map the first blocks directly, then through up to three
+    // levels of indirect blocks.
+    if logical < DIRECT_BLOCKS {
+      let block = read_u32(&inode.block, logical as usize * 4) as u64;
+      return Ok((block, 1));
+    }
+    let per_block = self.block_size / 4;
+    let mut index = logical - DIRECT_BLOCKS;
+    let mut span = per_block;
+    for level in 0..3 {
+      if index < span {
+        let mut block = read_u32(&inode.block, (12 + level) * 4) as u64;
+        for _ in 0..=level {
+          if block == 0 {
+            break;
+          }
+          span /= per_block;
+          let mut pointer = [0; 4];
+          let slot = index / span;
+          index %= span;
+          self.read_bytes(block * self.block_size + slot * 4, &mut pointer)?;
+          block = u32::from_le_bytes(pointer) as u64;
+        }
+        return Ok((block, 1));
+      }
+      index -= span;
+      span *= per_block;
+    }
+    Err(Status::VOLUME_CORRUPTED.into())
+  }
+
+  /// Returns the block holding block `logical` of a file mapped by the extent
+  /// tree under `node`, and the number of blocks from there that are
+  /// consecutive on disk.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the root of the extent tree
+  /// * `logical` - the index of the block within the file
+  fn map_extent(
+    &mut self,
+    mut node: Node,
+    logical: u64,
+  ) -> uefi::Result<(u64, u64)> {
+    // Trees are at most five levels deep.
+    for _ in 0..5 {
+      let mut header = [0; EXTENT_ENTRY_SIZE];
+      self.read_node(node, 0, &mut header)?;
+      if read_u16(&header, 0) != EXTENT_MAGIC {
+        return Err(Status::VOLUME_CORRUPTED.into());
+      }
+      let entries = read_u16(&header, 2) as usize;
+      let depth = read_u16(&header, 6);
+
+      // Entries are sorted by the first logical block that they cover; the
+      // last one starting at or before `logical` is the one that covers it.
+      let mut found = None;
+      let mut next = None;
+      for i in 0..entries {
+        let mut entry = [0; EXTENT_ENTRY_SIZE];
+        self.read_node(node, (i + 1) * EXTENT_ENTRY_SIZE, &mut entry)?;
+        if read_u32(&entry, 0) as u64 > logical {
+          next = Some(read_u32(&entry, 0) as u64);
+          break;
+        }
+        found = Some(entry);
+      }
+
+      if depth > 0 {
+        let entry = found.ok_or(Status::VOLUME_CORRUPTED)?;
+        let child =
+          read_u32(&entry, 4) as u64 | (read_u16(&entry, 8) as u64) << 32;
+        node = Node::Block(child);
+        continue;
+      }
+
+      // A block that no extent covers is a hole, which extends to the next
+      // extent, if any.
+      let hole = (0, next.map_or(1, |next| next - logical));
+      let Some(entry) = found else {
+        return Ok(hole);
+      };
+      let first = read_u32(&entry, 0) as u64;
+      let mut len = read_u16(&entry, 4);
+      let uninitialized = len > EXTENT_MAX_INITIALIZED;
+      if uninitialized {
+        len -= EXTENT_MAX_INITIALIZED;
+      }
+      let len = len as u64;
+      if logical >= first + len {
+        return Ok(hole);
+      }
+      let offset = logical - first;
+      if uninitialized {
+        return Ok((0, len - offset));
+      }
+      let start =
+        read_u32(&entry, 8) as u64 | (read_u16(&entry, 6) as u64) << 32;
+      return Ok((start + offset, len - offset));
+    }
+    Err(Status::VOLUME_CORRUPTED.into())
+  }
+
+  /// Reads `buffer.len()` bytes at byte `offset` of the extent tree `node`.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node to read
+  /// * `offset` - the byte offset within the node
+  /// * `buffer` - the buffer to read into
+  fn read_node(
+    &mut self,
+    node: Node,
+    offset: usize,
+    buffer: &mut [u8],
+  ) -> uefi::Result {
+    match node {
+      Node::Inode(block) => {
+        let bytes = block
+          .get(offset..offset + buffer.len())
+          .ok_or(Status::VOLUME_CORRUPTED)?;
+        buffer.copy_from_slice(bytes);
+        Ok(())
+      }
+      Node::Block(block) => {
+        if offset + buffer.len() > self.block_size as usize {
+          return Err(Status::VOLUME_CORRUPTED.into());
+        }
+        self.read_bytes(block * self.block_size + offset as u64, buffer)
+      }
+    }
+  }
+
+  /// Returns the number of the inode named `name` in the directory `dir`.
+  ///
+  /// # Arguments
+  ///
+  /// * `dir` - the directory to look in
+  /// * `name` - the name of the entry
+  fn lookup(&mut self, dir: &Inode, name: &str) -> uefi::Result<u32> {
+    let blocks = (dir.size + self.block_size - 1) / self.block_size;
+    for logical in 0..blocks {
+      let (block, _) = self.map(dir, logical)?;
+      if block == 0 {
+        continue;
+      }
+      let base = block * self.block_size;
+      let mut offset = 0;
+      while offset + 8 <= self.block_size {
+        let mut header = [0; 8];
+        self.read_bytes(base + offset, &mut header)?;
+        let inode = read_u32(&header, 0);
+        let rec_len = read_u16(&header, 4) as u64;
+        let name_len = header[6] as usize;
+        if rec_len < 8 || offset + rec_len > self.block_size {
+          return Err(Status::VOLUME_CORRUPTED.into());
+        }
+        if inode != 0
+          && name_len == name.len()
+          && 8 + name_len as u64 <= rec_len
+        {
+          let mut entry_name = [0; 255];
+          let entry_name = &mut entry_name[..name_len];
+          self.read_bytes(base + offset + 8, entry_name)?;
+          if entry_name == name.as_bytes() {
+            return Ok(inode);
+          }
+        }
+        offset += rec_len;
+      }
+    }
+    Err(Status::NOT_FOUND.into())
+  }
+
+  /// Finds the regular file at `path`, relative to the root directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the file, with `\` or `/` as the separator
+  fn find(&mut self, path: &str) -> uefi::Result<Inode> {
+    let mut inode = self.inode(ROOT_INODE)?;
+    let path: PathBuf<MAX_PATH_LEN> =
+      Path::new(path).normalize().map_err(status_of)?;
+    for name in path.components().filter_map(Component::name) {
+      if !inode.is(MODE_DIRECTORY) {
+        return Err(Status::NOT_FOUND.into());
+      }
+      let number = self.lookup(&inode, name)?;
+      inode = self.inode(number)?;
+    }
+    if !inode.is(MODE_REGULAR) {
+      return Err(Status::INVALID_PARAMETER.into());
+    }
+    Ok(inode)
+  }
+}
+
+impl Source for Ext2<'_> {
+  fn read(
+    &mut self,
+    bs: &BootServices,
+    path: &str,
+    progress: &mut dyn Progress,
+  ) -> uefi::Result<Buffer> {
+    let inode = self.find(path)?;
+    let size =
+      usize::try_from(inode.size).map_err(|_| Status::BAD_BUFFER_SIZE)?;
+    let block_size = self.block_size as usize;
+    let blocks = (size + block_size - 1) / block_size;
+    // Whole blocks are read, so the buffer is rounded up to hold them.
+    let mut buffer = loader::allocate_buffer(bs, blocks * block_size)?;
+
+    // Runs of consecutive blocks are read together, in chunks so that progress
+    // can be reported between them.
+    let chunk = (loader::PROGRESS_CHUNK / block_size).max(1) as u64;
+    let mut logical = 0;
+    progress.report(Step::Read, 0, size);
+    while logical < blocks as u64 {
+      let (block, run) = self.map(&inode, logical)?;
+      let run = run.min(blocks as u64 - logical).min(chunk).max(1);
+      let data = &mut buffer[logical as usize * block_size..]
+        [..run as usize * block_size];
+      if block == 0 {
+        data.fill(0);
+      } else {
+        self.read_blocks(block, data)?;
+      }
+      logical += run;
+      progress.report(
+        Step::Read,
+        size.min(logical as usize * block_size),
+        size,
+      );
+    }
+    buffer.truncate(size);
+    Ok(buffer)
+  }
+}
@@ -0,0 +1,35 @@
+//! This module provides discovery of the firmware's flattened device tree,
+//! which is parsed and edited by [`kcore::fdt`].
+
+use uefi::table::{Boot, SystemTable};
+use uefi::{guid, Guid};
+
+pub use kcore::fdt::DeviceTree;
+use kcore::fdt::HEADER_SIZE;
+
+/// The GUID of the configuration table holding the device tree.
+pub const GUID: Guid = guid!("b1b621d5-f19c-41a5-830b-d9152c69aae0");
+
+/// Returns the firmware's device tree, if it provides a well-formed one.
+///
+/// # Arguments
+///
+/// * `system_table` - the system table
+pub fn firmware_tree(
+  system_table: &SystemTable<Boot>,
+) -> Option<&'static [u8]> {
+  let entry = system_table
+    .config_table()
+    .iter()
+    .find(|entry| entry.guid == GUID)?;
+  // SAFETY: the firmware's device tree starts with its header, and is never
+  // freed.
+  let header = unsafe {
+    core::slice::from_raw_parts(entry.address as *const u8, HEADER_SIZE)
+  };
+  let size = DeviceTree::size_of(header).ok()?;
+  // SAFETY: the header gives the size of the whole tree.
+  let tree =
+    unsafe { core::slice::from_raw_parts(entry.address as *const u8, size) };
+  DeviceTree::parse(tree).ok().map(|_| tree)
+}
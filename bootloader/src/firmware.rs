@@ -0,0 +1,185 @@
+//! This module provides the identification of the firmware, with the quirks
+//! of buggy firmware that need working around, and the ways back into the
+//! firmware, for when the user would rather not boot at all.
+//!
+//! Quirks are looked up by the firmware vendor string in the system table,
+//! since that is all that firmware reliably reports about itself.
+//!
+//! Rebooting into the firmware's setup is requested through the
+//! `OsIndications` variable, which the firmware reads, and clears, on the next
+//! boot. Firmware that does not list the request in `OsIndicationsSupported`
+//! does not honour it.
+
+use crate::ucs2::Ucs2Str;
+use core::convert::Infallible;
+use core::fmt;
+use uefi::table::runtime::{
+  ResetType, RuntimeServices, VariableAttributes, VariableVendor,
+};
+use uefi::table::{Boot, SystemTable};
+use uefi::Status;
+
+/// The variable listing the indications that the firmware supports.
+const OS_INDICATIONS_SUPPORTED: &Ucs2Str =
+  crate::ucs2!("OsIndicationsSupported");
+
+/// The variable holding the indications requested of the firmware.
+const OS_INDICATIONS: &Ucs2Str = crate::ucs2!("OsIndications");
+
+/// The indication that requests the firmware's setup on the next boot.
+const BOOT_TO_FW_UI: u64 = 0x1;
+
+/// The workarounds needed on a firmware.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quirks {
+  /// Boot services memory is reported to the kernel as reserved, rather than
+  /// as reclaimable, for firmware whose runtime services still use it after
+  /// boot services are exited.
+  pub keep_boot_services_memory: bool,
+
+  /// The graphics mode is set again before the framebuffer is described, for
+  /// firmware whose framebuffer is not usable until a mode has been set.
+  pub reset_graphics_mode: bool,
+}
+
+impl Quirks {
+  /// No workarounds, as on well-behaved firmware.
+  pub const NONE: Self = Self {
+    keep_boot_services_memory: false,
+    reset_graphics_mode: false,
+  };
+
+  /// Returns the quirks of the firmware described by `system_table`.
+  ///
+  /// # Arguments
+  ///
+  /// * `system_table` - the system table
+  pub fn detect(system_table: &SystemTable<Boot>) -> Self {
+    let vendor = system_table.firmware_vendor();
+    KNOWN_QUIRKS
+      .iter()
+      .filter(|known| starts_with(vendor.into(), known.vendor))
+      .fold(Self::NONE, |quirks, known| quirks.union(known.quirks))
+  }
+
+  /// Returns the workarounds of both `self` and `other`.
+  ///
+  /// # Arguments
+  ///
+  /// * `other` - the other workarounds
+  const fn union(self, other: Self) -> Self {
+    Self {
+      keep_boot_services_memory: self.keep_boot_services_memory
+        || other.keep_boot_services_memory,
+      reset_graphics_mode: self.reset_graphics_mode
+        || other.reset_graphics_mode,
+    }
+  }
+}
+
+impl fmt::Display for Quirks {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let names = [
+      (self.keep_boot_services_memory, "keep boot services memory"),
+      (self.reset_graphics_mode, "reset graphics mode"),
+    ];
+    let mut separator = "";
+    for (_, name) in names.iter().filter(|(enabled, _)| *enabled) {
+      write!(f, "{}{}", separator, name)?;
+      separator = ", ";
+    }
+    if separator.is_empty() {
+      f.write_str("none")?;
+    }
+    Ok(())
+  }
+}
+
+/// The quirks of a family of firmware.
+struct KnownQuirks {
+  /// The start of the firmware vendor string that identifies the family.
+  vendor: &'static str,
+
+  /// The workarounds needed by the family.
+  quirks: Quirks,
+}
+
+/// The firmware known to need workarounds.
+const KNOWN_QUIRKS: [KnownQuirks; 2] = [
+  // Apple's graphics output reports a framebuffer that is not scanned out
+  // until a mode is set.
+  KnownQuirks {
+    vendor: "Apple",
+    quirks: Quirks {
+      reset_graphics_mode: true,
+      ..Quirks::NONE
+    },
+  },
+  // Some Insyde runtime services read data left in boot services memory.
+  KnownQuirks {
+    vendor: "INSYDE",
+    quirks: Quirks {
+      keep_boot_services_memory: true,
+      ..Quirks::NONE
+    },
+  },
+];
+
+/// Returns `true` if `vendor` starts with `prefix`, ignoring case.
+///
+/// # Arguments
+///
+/// * `vendor` - the firmware vendor string
+/// * `prefix` - the prefix to look for
+fn starts_with(vendor: &Ucs2Str, prefix: &str) -> bool {
+  let mut chars = vendor.chars();
+  prefix
+    .chars()
+    .all(|p| chars.next().is_some_and(|c| c.eq_ignore_ascii_case(&p)))
+}
+
+/// Reboots into the firmware's setup, returning only if it cannot be done.
+///
+/// Fails with [`Status::UNSUPPORTED`] if the firmware does not support being
+/// asked to enter its setup.
+///
+/// # Arguments
+///
+/// * `rt` - the runtime services
+pub fn enter_setup(rt: &RuntimeServices) -> uefi::Result<Infallible> {
+  let supported = read_u64(rt, OS_INDICATIONS_SUPPORTED)?;
+  if supported & BOOT_TO_FW_UI == 0 {
+    return Err(Status::UNSUPPORTED.into());
+  }
+
+  // Indications already requested by others are kept; the variable is absent
+  // if there are none.
+  let indications = read_u64(rt, OS_INDICATIONS).unwrap_or(0) | BOOT_TO_FW_UI;
+  rt.set_variable(
+    OS_INDICATIONS.as_cstr16(),
+    &VariableVendor::GLOBAL_VARIABLE,
+    VariableAttributes::NON_VOLATILE
+      | VariableAttributes::BOOTSERVICE_ACCESS
+      | VariableAttributes::RUNTIME_ACCESS,
+    &indications.to_le_bytes(),
+  )?;
+  rt.reset(ResetType::COLD, Status::SUCCESS, None)
+}
+
+/// Reads the global variable `name`, which holds a `u64`.
+///
+/// # Arguments
+///
+/// * `rt` - the runtime services
+/// * `name` - the name of the variable
+fn read_u64(rt: &RuntimeServices, name: &Ucs2Str) -> uefi::Result<u64> {
+  let mut buffer = [0; 8];
+  let (data, _) = rt.get_variable(
+    name.as_cstr16(),
+    &VariableVendor::GLOBAL_VARIABLE,
+    &mut buffer,
+  )?;
+  let mut bytes = [0; 8];
+  bytes[..data.len()].copy_from_slice(data);
+  Ok(u64::from_le_bytes(bytes))
+}
@@ -0,0 +1,131 @@
+//! This module provides helpers for reading files from the volume that the
+//! bootloader image was itself loaded from, and for updating them.
+
+use crate::efi::file::{File, SimpleFileSystem};
+use crate::efi::loaded_image;
+use crate::efi::protocol::{self, Access};
+use crate::loader::{self, Buffer, Progress, Source, Step};
+use uefi::table::boot::BootServices;
+use uefi::table::runtime::Time;
+use uefi::{Handle, Status};
+
+/// Opens the root directory of the volume that `image` was loaded from.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the image whose volume should be opened
+pub fn open_boot_volume(
+  bs: &BootServices,
+  image: Handle,
+) -> uefi::Result<File> {
+  let device = loaded_image::device(bs, image)?;
+  let mut fs =
+    protocol::open_protocol_exclusive::<SimpleFileSystem>(bs, device, image)?;
+  fs.open_volume()
+}
+
+/// Opens the root directory of the volume on `handle`.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `handle` - the handle of the volume to open
+pub fn open_volume(
+  bs: &BootServices,
+  image: Handle,
+  handle: Handle,
+) -> uefi::Result<File> {
+  // SAFETY: the volume may already be open elsewhere, such as by the boot
+  // volume's root directory, so it cannot be opened exclusively. It is only
+  // used to open its root directory.
+  let mut fs = unsafe {
+    protocol::open_protocol::<SimpleFileSystem>(
+      bs,
+      handle,
+      image,
+      Access::Shared,
+    )?
+  };
+  fs.open_volume()
+}
+
+/// Reads the entire contents of the file at `path` into a newly allocated
+/// buffer.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `root` - the directory that `path` is relative to
+/// * `path` - the path of the file to read
+/// * `progress` - the receiver of progress reports
+pub fn read_file(
+  bs: &BootServices,
+  root: &mut File,
+  path: &str,
+  progress: &mut dyn Progress,
+) -> uefi::Result<Buffer> {
+  let mut file = root.open(path)?;
+  let info = file.info()?;
+  if info.is_directory() {
+    return Err(Status::INVALID_PARAMETER.into());
+  }
+  let size = info.file_size as usize;
+  let mut buffer = loader::allocate_buffer(bs, size)?;
+
+  let mut offset = 0;
+  progress.report(Step::Read, 0, size);
+  while offset < size {
+    let end = size.min(offset + loader::PROGRESS_CHUNK);
+    let read = file.read(&mut buffer[offset..end])?;
+    if read == 0 {
+      break;
+    }
+    offset += read;
+    progress.report(Step::Read, offset, size);
+  }
+  buffer.truncate(offset);
+  Ok(buffer)
+}
+
+/// Replaces the contents of the file at `path` with `data`, creating it if
+/// needed, and records `time` as the time it was modified, if known.
+///
+/// The contents are written through to the device before the size of the
+/// file is changed, so that the file never extends past what has been
+/// written. Should writing the contents fail part way, the file keeps its
+/// size, and holds a mix of its old and new contents.
+///
+/// # Arguments
+///
+/// * `root` - the directory that `path` is relative to
+/// * `path` - the path of the file to update
+/// * `data` - the new contents of the file
+/// * `time` - the time of the update, if known
+pub fn update_file(
+  root: &mut File,
+  path: &str,
+  data: &[u8],
+  time: Option<&Time>,
+) -> uefi::Result {
+  let mut file = root.open_writable(path)?;
+  if file.info()?.is_directory() {
+    return Err(Status::INVALID_PARAMETER.into());
+  }
+  file.write(data)?;
+  file.flush()?;
+  file.set_size_and_time(data.len() as u64, time)?;
+  file.flush()
+}
+
+impl Source for File {
+  fn read(
+    &mut self,
+    bs: &BootServices,
+    path: &str,
+    progress: &mut dyn Progress,
+  ) -> uefi::Result<Buffer> {
+    read_file(bs, self, path, progress)
+  }
+}
@@ -0,0 +1,39 @@
+//! This module provides lookup of partitions in the GUID partition table of a
+//! disk, through [`kcore::gpt`].
+//!
+//! Both the header and the partition entries are checked against their
+//! CRC32s, and the backup at the end of the disk is read if the primary table
+//! is damaged. The bootloader never writes to the table.
+
+use crate::blockio::BlockReader;
+use crate::error::status_of;
+use kcore::error::Error;
+use kcore::gpt::{Gpt, Partition};
+use kcore::guid::Guid;
+use uefi::Status;
+
+/// Finds the partition of `disk` whose unique partition GUID or partition
+/// type GUID is `guid`, taking the first if there are several.
+///
+/// Fails with [`Status::VOLUME_CORRUPTED`] if the disk has no valid GUID
+/// partition table, and with [`Status::NOT_FOUND`] if no partition matches.
+///
+/// # Arguments
+///
+/// * `disk` - the whole disk to read the partition table of
+/// * `guid` - the unique or type GUID of the partition
+pub fn find(disk: &mut BlockReader, guid: &Guid) -> uefi::Result<Partition> {
+  let status = |error| match error {
+    Error::Corrupted => Status::VOLUME_CORRUPTED,
+    error => status_of(error),
+  };
+  let mut gpt = Gpt::open(disk).map_err(status)?;
+  let found = gpt
+    .find(|partition| {
+      partition.type_guid == *guid || partition.unique_guid == *guid
+    })
+    .map_err(status)?;
+  found
+    .map(|(_, partition)| partition)
+    .ok_or_else(|| Status::NOT_FOUND.into())
+}
@@ -0,0 +1,601 @@
+//! This module provides the handoff from the bootloader to the kernel: laying
+//! out the kernel image, building the address space it starts in, and
+//! describing the machine to it through a [`BootInfo`].
+//!
+//! The kernel is entered in an address space that identity-maps the memory
+//! used by the bootloader and boot services, maps each kernel segment at its
+//! higher-half virtual address with the segment's permissions, and maps the
+//! framebuffer at [`FRAMEBUFFER_BASE`]. Only memory holding code is mapped
+//! executable, and the kernel image is mapped nowhere else, so that none of
+//! its segments can be written or executed through an alias. The kernel
+//! starts on a dedicated stack mapped above an unmapped guard page at
+//! [`STACK_GUARD`].
+
+use crate::elf::{self, Elf};
+use crate::error::status_of;
+use crate::firmware::Quirks;
+use crate::loader::{self, LoadedFile, PAGE_SIZE};
+use crate::paging::{AddressSpace, PageFlags};
+use crate::timing::Timeline;
+use bootinfo::log::Log;
+use bootinfo::{
+  BootInfo, BootPhase, BootTimes, Build, Cpu, Cpus, Extension, FirmwareQuirks,
+  Framebuffer, MemoryKind, MemoryRegion, Module, Modules, PhysRange,
+  PixelFormat, Symbols,
+};
+use crypto::drbg::BOOT_RNG;
+use kcore::rand::RngCore;
+use kcore::{symbols, tlv};
+use uefi::proto::console::gop::{self, GraphicsOutput};
+use uefi::table::boot::{
+  BootServices, MemoryMap, MemoryType, OpenProtocolAttributes,
+  OpenProtocolParams,
+};
+use uefi::{Handle, Status};
+
+/// The lowest virtual address of the higher half, where the kernel must be
+/// linked.
+const HIGHER_HALF: u64 = 0xffff_8000_0000_0000;
+
+/// The virtual address that position-independent kernels linked below the
+/// higher half are loaded at.
+pub const PIE_BASE: u64 = 0xffff_ffff_8000_0000;
+
+/// The virtual address that the framebuffer is mapped at.
+pub const FRAMEBUFFER_BASE: u64 = 0xffff_e000_0000_0000;
+
+/// The virtual address of the guard page below the kernel stack, which is
+/// left unmapped so that overflowing the stack faults.
+pub const STACK_GUARD: u64 = 0xffff_d000_0000_0000;
+
+/// The size of the stack that the kernel is entered on.
+const STACK_SIZE: usize = 64 * 1024;
+
+/// The number of times setting the graphics mode is attempted, when the
+/// firmware needs it set before its framebuffer can be used.
+const MODE_SET_ATTEMPTS: usize = 3;
+
+/// The number of page tables reserved for building the kernel address space.
+const PAGE_TABLES: usize = 512;
+
+/// The number of memory map entries reserved beyond those in the map when it
+/// is first read, to account for the firmware splitting regions before boot
+/// services are exited, and for the boot protocols that split regions of
+/// their own out of it, such as the kernel and initrd.
+pub const SPARE_REGIONS: usize = 32;
+
+/// The size of the memory reserved for the extensions of the boot
+/// information.
+const EXTENSIONS_SIZE: usize = PAGE_SIZE;
+
+/// Everything prepared for entering the kernel, which only remains to be
+/// completed once boot services have been exited.
+pub struct Handoff {
+  space: AddressSpace,
+  entry: u64,
+  boot_info: &'static mut BootInfo,
+  regions: &'static mut [MemoryRegion],
+  quirks: Quirks,
+}
+
+impl Handoff {
+  /// Lays out the `kernel` executable in memory and builds the address space
+  /// to enter it in.
+  ///
+  /// This must be the last use of boot services to allocate memory before
+  /// they are exited, since the memory map is read to decide what to
+  /// identity-map. Fails with [`Status::UNSUPPORTED`] if the processor is not
+  /// in a state that the kernel can be entered from.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  /// * `kernel` - the loaded kernel executable
+  /// * `initrd` - the loaded initrd, if any
+  /// * `log` - the early boot log to hand to the kernel
+  /// * `timeline` - the timeline to record the phases of preparing on
+  /// * `quirks` - the workarounds needed on the firmware
+  pub fn prepare(
+    bs: &BootServices,
+    image: Handle,
+    kernel: &LoadedFile,
+    initrd: Option<&LoadedFile>,
+    log: Log,
+    timeline: &mut Timeline,
+    quirks: Quirks,
+  ) -> uefi::Result<Self> {
+    if !arch::target::paging::can_enter() {
+      return Err(Status::UNSUPPORTED.into());
+    }
+    let mut space = AddressSpace::new(bs, PAGE_TABLES)?;
+    let executable = &*kernel.data;
+    let (kernel, entry) = load_kernel(bs, &mut space, executable)?;
+    let symbols = write_symbols(bs, executable, entry)?;
+    timeline.stamp(BootPhase::PAGING);
+
+    let boot_info =
+      loader::allocate_buffer(bs, core::mem::size_of::<BootInfo>())?
+        .leak()
+        .as_mut_ptr()
+        .cast::<BootInfo>();
+    // SAFETY: the buffer is page-aligned, large enough for a `BootInfo`, and
+    // never freed.
+    let boot_info = unsafe {
+      boot_info.write(BootInfo::new());
+      &mut *boot_info
+    };
+    boot_info.kernel = kernel;
+    boot_info.bootloader = Build::new(&crate::BUILD);
+    boot_info.extensions = write_extensions(bs, quirks)?;
+    boot_info.symbols = symbols;
+    boot_info.log = log;
+    (&BOOT_RNG)
+      .try_fill(&mut boot_info.seed)
+      .map_err(status_of)?;
+    if let Some(initrd) = initrd {
+      boot_info.initrd = PhysRange {
+        start: initrd.data.as_ptr() as u64,
+        len: initrd.data.len() as u64,
+      };
+    }
+
+    let stack = loader::allocate_buffer(bs, STACK_SIZE)?.leak();
+    boot_info.stack = bootinfo::Stack {
+      address: STACK_GUARD + PAGE_SIZE as u64,
+      physical: PhysRange {
+        start: stack.as_ptr() as u64,
+        len: stack.len() as u64,
+      },
+    };
+    space.map(
+      boot_info.stack.address,
+      boot_info.stack.physical.start,
+      boot_info.stack.physical.len,
+      PageFlags::READ_WRITE,
+    )?;
+
+    // Opening the framebuffer may allocate, so it must happen before the
+    // memory map is read.
+    if let Ok(framebuffer) = framebuffer(bs, image, quirks) {
+      space.map(
+        FRAMEBUFFER_BASE,
+        framebuffer.physical_address,
+        framebuffer.size,
+        PageFlags::READ_WRITE,
+      )?;
+      boot_info.framebuffer = framebuffer;
+    }
+
+    let size = bs.memory_map_size();
+    let count = size.map_size / size.entry_size + SPARE_REGIONS;
+    let regions = loader::allocate_buffer(
+      bs,
+      count * core::mem::size_of::<MemoryRegion>(),
+    )?
+    .leak();
+    // SAFETY: the buffer is page-aligned, large enough for `count` regions,
+    // and never freed. Regions are plain integers, for which any bytes are
+    // valid.
+    let regions = unsafe {
+      core::slice::from_raw_parts_mut(
+        regions.as_mut_ptr().cast::<MemoryRegion>(),
+        count,
+      )
+    };
+
+    let buffer = loader::allocate_buffer(
+      bs,
+      size.map_size + SPARE_REGIONS * size.entry_size,
+    )?
+    .leak();
+    let map = bs.memory_map(buffer)?;
+    let image = (kernel.start, kernel.start + kernel.len);
+    for descriptor in map.entries() {
+      let Some(flags) = Self::identity_flags(descriptor.ty) else {
+        continue;
+      };
+      let start = descriptor.phys_start;
+      let end = start + descriptor.page_count * PAGE_SIZE as u64;
+      for (start, end) in [(start, end.min(image.0)), (start.max(image.1), end)]
+      {
+        if start < end {
+          space.map(start, start, end - start, flags)?;
+        }
+      }
+    }
+
+    Ok(Self {
+      space,
+      entry,
+      boot_info,
+      regions,
+      quirks,
+    })
+  }
+
+  /// Returns the access that memory of type `ty` is identity-mapped with in
+  /// the kernel address space, or [`None`] if it is not identity-mapped.
+  ///
+  /// # Arguments
+  ///
+  /// * `ty` - the type of memory
+  fn identity_flags(ty: MemoryType) -> Option<PageFlags> {
+    match ty {
+      MemoryType::LOADER_CODE | MemoryType::BOOT_SERVICES_CODE => {
+        Some(PageFlags::ALL)
+      }
+      MemoryType::LOADER_DATA | MemoryType::BOOT_SERVICES_DATA => {
+        Some(PageFlags::READ_WRITE)
+      }
+      _ => None,
+    }
+  }
+
+  /// Returns the kind of memory reported to the kernel for memory of type
+  /// `ty`.
+  ///
+  /// # Arguments
+  ///
+  /// * `ty` - the type of memory
+  /// * `quirks` - the workarounds needed on the firmware
+  fn memory_kind(ty: MemoryType, quirks: Quirks) -> MemoryKind {
+    match ty {
+      MemoryType::BOOT_SERVICES_CODE | MemoryType::BOOT_SERVICES_DATA
+        if quirks.keep_boot_services_memory =>
+      {
+        MemoryKind::RESERVED
+      }
+      MemoryType::CONVENTIONAL => MemoryKind::USABLE,
+      MemoryType::LOADER_CODE
+      | MemoryType::LOADER_DATA
+      | MemoryType::BOOT_SERVICES_CODE
+      | MemoryType::BOOT_SERVICES_DATA => MemoryKind::BOOTLOADER,
+      MemoryType::RUNTIME_SERVICES_CODE | MemoryType::RUNTIME_SERVICES_DATA => {
+        MemoryKind::FIRMWARE_RUNTIME
+      }
+      MemoryType::ACPI_RECLAIM => MemoryKind::ACPI_RECLAIMABLE,
+      MemoryType::ACPI_NON_VOLATILE => MemoryKind::ACPI_NVS,
+      MemoryType::MMIO | MemoryType::MMIO_PORT_SPACE => MemoryKind::MMIO,
+      MemoryType::UNUSABLE => MemoryKind::UNUSABLE,
+      _ => MemoryKind::RESERVED,
+    }
+  }
+
+  /// Records the additional `modules` loaded alongside the kernel in the boot
+  /// information.
+  ///
+  /// # Arguments
+  ///
+  /// * `modules` - the descriptions of the modules, in identity-mapped memory
+  pub fn set_modules(&mut self, modules: &'static [Module]) {
+    self.boot_info.modules = Modules {
+      address: modules.as_ptr() as u64,
+      len: modules.len() as u64,
+    };
+  }
+
+  /// Records the processors of the machine in the boot information.
+  ///
+  /// # Arguments
+  ///
+  /// * `cpus` - the descriptions of the processors, in identity-mapped memory
+  pub fn set_cpus(&mut self, cpus: &'static [Cpu]) {
+    self.boot_info.cpus = Cpus {
+      address: cpus.as_ptr() as u64,
+      len: cpus.len() as u64,
+    };
+  }
+
+  /// Records the flattened device tree in the boot information.
+  ///
+  /// # Arguments
+  ///
+  /// * `tree` - the device tree, in memory that is never freed
+  pub fn set_device_tree(&mut self, tree: &'static [u8]) {
+    self.boot_info.device_tree = PhysRange {
+      start: tree.as_ptr() as u64,
+      len: tree.len() as u64,
+    };
+  }
+
+  /// Records the timestamps of the phases of boot in the boot information.
+  ///
+  /// # Arguments
+  ///
+  /// * `times` - the timestamps of the phases of boot so far
+  pub fn set_times(&mut self, times: &BootTimes) {
+    self.boot_info.times = *times;
+  }
+
+  /// Records the final memory `map` in the boot information, then switches
+  /// to the kernel address space and enters the kernel.
+  ///
+  /// Adjacent regions of the same kind are merged. Should the map still not
+  /// fit in the space reserved for it, the highest regions are dropped.
+  ///
+  /// # Arguments
+  ///
+  /// * `map` - the memory map returned when exiting boot services
+  pub fn enter(self, mut map: MemoryMap<'static>) -> ! {
+    map.sort();
+    let mut len = 0;
+    for descriptor in map.entries() {
+      let region = MemoryRegion {
+        range: PhysRange {
+          start: descriptor.phys_start,
+          len: descriptor.page_count * PAGE_SIZE as u64,
+        },
+        kind: Self::memory_kind(descriptor.ty, self.quirks),
+        reserved: 0,
+      };
+      let capacity = self.regions.len();
+      match self.regions[..len].last_mut() {
+        Some(last)
+          if last.kind == region.kind
+            && last.range.end() == region.range.start =>
+        {
+          last.range.len += region.range.len;
+        }
+        _ if len < capacity => {
+          self.regions[len] = region;
+          len += 1;
+        }
+        _ => break,
+      }
+    }
+    self.boot_info.memory_map = bootinfo::MemoryMap {
+      address: self.regions.as_ptr() as u64,
+      len: len as u64,
+    };
+
+    let stack = self.boot_info.stack.top();
+    let boot_info = self.boot_info as *mut BootInfo as u64;
+    // SAFETY: boot services have been exited, since the final memory map is
+    // only returned when exiting them. The address space identity-maps all
+    // loader and boot services memory, which holds the bootloader and the boot
+    // information, and maps the kernel entry point and stack.
+    unsafe {
+      arch::target::paging::enter(
+        self.space.root(),
+        self.entry,
+        stack,
+        boot_info,
+      )
+    }
+  }
+}
+
+/// Lays out the kernel executable `data` contiguously in memory, and maps each
+/// of its segments into `space` at its virtual address with the segment's
+/// permissions.
+///
+/// Position-independent kernels are relocated; those linked below the higher
+/// half are moved to [`PIE_BASE`], and others are loaded where they were
+/// linked.
+///
+/// Returns the physical memory holding the kernel, and its entry point.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `space` - the address space to map the kernel into
+/// * `data` - the contents of the kernel executable
+pub fn load_kernel(
+  bs: &BootServices,
+  space: &mut AddressSpace,
+  data: &[u8],
+) -> uefi::Result<(PhysRange, u64)> {
+  let elf = Elf::parse(data)?;
+  if elf.class() != elf::Class::Elf64 {
+    return Err(Status::UNSUPPORTED.into());
+  }
+  let (linked, size) = kernel_extent(&elf)?;
+  let slide = if elf.is_position_independent() && linked < HIGHER_HALF {
+    PIE_BASE - linked
+  } else {
+    0
+  };
+  let base = linked.wrapping_add(slide);
+  if base < HIGHER_HALF || base > u64::MAX - size as u64 {
+    return Err(Status::LOAD_ERROR.into());
+  }
+
+  let memory = loader::allocate_buffer(bs, size)?.leak();
+  memory.fill(0);
+  let phys = memory.as_ptr() as u64;
+  for segment in elf.segments() {
+    let segment = segment?;
+    let offset = (segment.vaddr - linked) as usize;
+    memory[offset..offset + segment.file_size]
+      .copy_from_slice(&elf.data()[segment.offset..][..segment.file_size]);
+  }
+  elf.relocate(memory, linked, slide)?;
+
+  for segment in elf.segments() {
+    let segment = segment?;
+    let vaddr = segment.vaddr + slide;
+    let start = vaddr & !(PAGE_SIZE as u64 - 1);
+    let end = vaddr + segment.mem_size as u64;
+    let flags = PageFlags {
+      writable: segment.flags & elf::PF_W != 0,
+      executable: segment.flags & elf::PF_X != 0,
+    };
+    space.map(start, phys + (start - base), end - start, flags)?;
+  }
+
+  let range = PhysRange {
+    start: phys,
+    len: size as u64,
+  };
+  Ok((range, elf.entry() + slide))
+}
+
+/// Writes the symbol map of the kernel executable `data`, which was loaded
+/// with its entry point at `entry`, to memory handed to the kernel.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `data` - the contents of the kernel executable
+/// * `entry` - the virtual address the entry point was loaded at
+fn write_symbols(
+  bs: &BootServices,
+  data: &[u8],
+  entry: u64,
+) -> uefi::Result<Symbols> {
+  let elf = Elf::parse(data)?;
+  let Some(table) = elf.symbols()? else {
+    return Ok(Symbols::NONE);
+  };
+  let memory =
+    loader::allocate_buffer(bs, symbols::map_size(&table).map_err(status_of)?)?
+      .leak();
+  let slide = entry.wrapping_sub(elf.entry());
+  let len = symbols::write_map(&table, slide, memory).map_err(status_of)?;
+  Ok(Symbols {
+    map: PhysRange {
+      start: memory.as_ptr() as u64,
+      len: len as u64,
+    },
+  })
+}
+
+/// Writes the extensions of the boot information, which describe the
+/// workarounds applied for the firmware, to memory handed to the kernel.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `quirks` - the workarounds needed on the firmware
+fn write_extensions(
+  bs: &BootServices,
+  quirks: Quirks,
+) -> uefi::Result<PhysRange> {
+  let memory = loader::allocate_buffer(bs, EXTENSIONS_SIZE)?.leak();
+  let start = memory.as_ptr() as u64;
+  let mut extensions = tlv::Writer::new(memory);
+  let flags = [
+    (
+      quirks.keep_boot_services_memory,
+      FirmwareQuirks::KEEP_BOOT_SERVICES_MEMORY,
+    ),
+    (
+      quirks.reset_graphics_mode,
+      FirmwareQuirks::RESET_GRAPHICS_MODE,
+    ),
+  ];
+  let quirks = FirmwareQuirks {
+    flags: flags
+      .iter()
+      .filter(|(applied, _)| *applied)
+      .fold(0, |flags, (_, flag)| flags | flag),
+  };
+  extensions
+    .push_encoded(Extension::FIRMWARE_QUIRKS.0, &quirks)
+    .map_err(status_of)?;
+  Ok(PhysRange {
+    start,
+    len: extensions.len() as u64,
+  })
+}
+
+/// Returns the page-aligned virtual address and size of the memory spanned
+/// by the loadable segments of the kernel `elf`, as linked.
+///
+/// Fails with [`Status::LOAD_ERROR`] if the segments wrap around the address
+/// space, or the entry point lies outside of them.
+///
+/// # Arguments
+///
+/// * `elf` - the kernel executable
+fn kernel_extent(elf: &Elf) -> uefi::Result<(u64, usize)> {
+  let page = PAGE_SIZE as u64;
+  let (mut start, mut end) = (u64::MAX, 0);
+  for segment in elf.segments() {
+    let segment = segment?;
+    let segment_end = segment
+      .vaddr
+      .checked_add(segment.mem_size as u64)
+      .ok_or(Status::LOAD_ERROR)?;
+    start = start.min(segment.vaddr & !(page - 1));
+    end = end.max(segment_end);
+  }
+  if end < start || end > u64::MAX - page {
+    return Err(Status::LOAD_ERROR.into());
+  }
+  if !(start..end).contains(&elf.entry()) {
+    return Err(Status::LOAD_ERROR.into());
+  }
+  let end = (end + page - 1) & !(page - 1);
+  Ok((start, (end - start) as usize))
+}
+
+/// Describes the framebuffer of the firmware's graphics output, if it has
+/// a linear framebuffer in a supported pixel format.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `quirks` - the workarounds needed on the firmware
+pub fn framebuffer(
+  bs: &BootServices,
+  image: Handle,
+  quirks: Quirks,
+) -> uefi::Result<Framebuffer> {
+  let params = OpenProtocolParams {
+    handle: bs.get_handle_for_protocol::<GraphicsOutput>()?,
+    agent: image,
+    controller: None,
+  };
+  // SAFETY: the console driver holds the graphics output open, so it cannot
+  // be opened exclusively. It is only queried, and at most set to the mode
+  // it is already in.
+  let mut gop = unsafe {
+    bs.open_protocol::<GraphicsOutput>(
+      params,
+      OpenProtocolAttributes::GetProtocol,
+    )?
+  };
+  if quirks.reset_graphics_mode {
+    reset_mode(&mut gop);
+  }
+
+  let info = gop.current_mode_info();
+  let format = match info.pixel_format() {
+    gop::PixelFormat::Rgb => PixelFormat::RGB,
+    gop::PixelFormat::Bgr => PixelFormat::BGR,
+    _ => return Err(Status::UNSUPPORTED.into()),
+  };
+  let (width, height) = info.resolution();
+  let mut buffer = gop.frame_buffer();
+  Ok(Framebuffer {
+    address: FRAMEBUFFER_BASE,
+    physical_address: buffer.as_mut_ptr() as u64,
+    size: buffer.size() as u64,
+    width: width as u32,
+    height: height as u32,
+    stride: info.stride() as u32,
+    format,
+  })
+}
+
+/// Sets the graphics output `gop` to the mode it is already in, trying up to
+/// [`MODE_SET_ATTEMPTS`] times, since firmware that needs its mode set may
+/// also fail to set it at first.
+///
+/// # Arguments
+///
+/// * `gop` - the graphics output
+fn reset_mode(gop: &mut GraphicsOutput) {
+  let current = gop.current_mode_info();
+  let Some(mode) = gop.modes().find(|mode| *mode.info() == current) else {
+    return;
+  };
+  for _ in 0..MODE_SET_ATTEMPTS {
+    if gop.set_mode(&mode).is_ok() {
+      break;
+    }
+  }
+}
@@ -0,0 +1,324 @@
+//! This module provides a read-only driver for ISO9660 file systems, so that
+//! payloads can be read from optical media and ISO-hybrid images.
+//!
+//! When booting from such media, the firmware only sees the small El Torito
+//! boot image holding the bootloader; the payloads live in the ISO9660 file
+//! system around it, which is read from the boot disk through the firmware's
+//! Block I/O protocol. Names are taken from Rock Ridge entries where present,
+//! and matched exactly; plain ISO9660 names are matched ignoring case and
+//! their version suffix.
+
+use crate::blockio::BlockReader;
+use crate::error::status_of;
+use crate::loader::{self, Buffer, Progress, Source, Step};
+use kcore::bytes::{read_u16, read_u32};
+use kcore::path::{Component, Path, PathBuf, MAX_PATH_LEN};
+use uefi::table::boot::BootServices;
+use uefi::{Handle, Status};
+
+/// The byte offset of the first volume descriptor.
+const DESCRIPTORS_OFFSET: u64 = 16 * 2048;
+
+/// The size of a volume descriptor.
+const DESCRIPTOR_SIZE: usize = 2048;
+
+/// The identifier held by every volume descriptor.
+const IDENTIFIER: &[u8; 5] = b"CD001";
+
+/// The type of the primary volume descriptor.
+const PRIMARY: u8 = 1;
+
+/// The type of the descriptor that terminates the set.
+const TERMINATOR: u8 = 255;
+
+/// The most volume descriptors that are looked through for the primary one.
+const MAX_DESCRIPTORS: u64 = 32;
+
+/// The size of the fixed part of a directory record, before its name.
+const RECORD_HEADER_SIZE: usize = 33;
+
+/// The directory record flag marking a directory.
+const FLAG_DIRECTORY: u8 = 1 << 1;
+
+/// The directory record flag marking a file that continues in another
+/// record.
+const FLAG_MULTI_EXTENT: u8 = 1 << 7;
+
+/// The Rock Ridge name flag marking a name that continues in the next entry.
+const NAME_CONTINUE: u8 = 1 << 0;
+
+/// The longest name that is read.
+const MAX_NAME: usize = 255;
+
+/// A file or directory, as described by its directory record.
+#[derive(Clone, Copy)]
+struct Record {
+  /// The first logical block of the contents.
+  extent: u64,
+
+  /// The size of the contents, in bytes.
+  size: u64,
+
+  /// The `FLAG_*` flags of the record.
+  flags: u8,
+}
+
+/// A read-only ISO9660 file system.
+pub struct Iso9660<'a> {
+  disk: BlockReader<'a>,
+  block_size: u64,
+  root: Record,
+}
+
+impl<'a> Iso9660<'a> {
+  /// Opens the ISO9660 file system of the disk that `image` was loaded from.
+  ///
+  /// Fails with [`Status::VOLUME_CORRUPTED`] if the disk does not hold an
+  /// ISO9660 file system.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  pub fn open(bs: &'a BootServices, image: Handle) -> uefi::Result<Self> {
+    let mut disk = BlockReader::open_boot_disk(bs, image)?;
+    let mut descriptor = [0; DESCRIPTOR_SIZE];
+    for index in 0..MAX_DESCRIPTORS {
+      let offset = DESCRIPTORS_OFFSET + index * DESCRIPTOR_SIZE as u64;
+      disk.read(offset, &mut descriptor)?;
+      if descriptor[1..6] != *IDENTIFIER || descriptor[0] == TERMINATOR {
+        break;
+      }
+      if descriptor[0] != PRIMARY {
+        continue;
+      }
+
+      let block_size = read_u16(&descriptor, 128) as u64;
+      if block_size == 0 {
+        break;
+      }
+      let root = Self::parse_record(&descriptor[156..190]);
+      return Ok(Self {
+        disk,
+        block_size,
+        root,
+      });
+    }
+    Err(Status::VOLUME_CORRUPTED.into())
+  }
+
+  /// Parses the fixed part of the directory record `record`.
+  ///
+  /// # Arguments
+  ///
+  /// * `record` - the directory record
+  fn parse_record(record: &[u8]) -> Record {
+    Record {
+      extent: read_u32(record, 2) as u64,
+      size: read_u32(record, 10) as u64,
+      flags: record[25],
+    }
+  }
+
+  /// Returns the record of the entry named `name` in the directory `dir`.
+  ///
+  /// # Arguments
+  ///
+  /// * `dir` - the directory to look in
+  /// * `name` - the name of the entry
+  fn lookup(&mut self, dir: &Record, name: &str) -> uefi::Result<Record> {
+    let base = dir.extent * self.block_size;
+    let mut offset = 0;
+    while offset < dir.size {
+      let mut record = [0; 255];
+      self.disk.read(base + offset, &mut record[..1])?;
+      let len = record[0] as usize;
+      // Records never cross a block; the rest of a block that has no room for
+      // another record is zero.
+      if len == 0 {
+        offset = (offset / self.block_size + 1) * self.block_size;
+        continue;
+      }
+      if len < RECORD_HEADER_SIZE {
+        return Err(Status::VOLUME_CORRUPTED.into());
+      }
+      let record = &mut record[..len];
+      self.disk.read(base + offset, record)?;
+      offset += len as u64;
+
+      let name_len = record[32] as usize;
+      let iso_name = record
+        .get(RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + name_len)
+        .ok_or(Status::VOLUME_CORRUPTED)?;
+      // The system use area follows the name, padded to an even offset.
+      let system_use = RECORD_HEADER_SIZE + name_len + (name_len + 1) % 2;
+      let mut rock_ridge = [0; MAX_NAME];
+      let matches = match self.rock_ridge_name(
+        record.get(system_use..).unwrap_or_default(),
+        &mut rock_ridge,
+      )? {
+        Some(len) => rock_ridge[..len] == *name.as_bytes(),
+        None => iso_name_matches(iso_name, name),
+      };
+      if matches {
+        return Ok(Self::parse_record(record));
+      }
+    }
+    Err(Status::NOT_FOUND.into())
+  }
+
+  /// Collects the Rock Ridge name held in the system use `area` of a
+  /// directory record into `name`, returning its length, or [`None`] if the
+  /// record has no Rock Ridge name.
+  ///
+  /// Names continued in a continuation area are followed through one
+  /// continuation area.
+  ///
+  /// # Arguments
+  ///
+  /// * `area` - the system use area of the directory record
+  /// * `name` - the buffer to collect the name in
+  fn rock_ridge_name(
+    &mut self,
+    area: &[u8],
+    name: &mut [u8; MAX_NAME],
+  ) -> uefi::Result<Option<usize>> {
+    let mut len = None;
+    let continuation = collect_name(area, name, &mut len);
+    let continues = len.map_or(true, |(_, more)| more);
+    if let Some((block, offset, size)) = continuation.filter(|_| continues) {
+      let mut area = [0; DESCRIPTOR_SIZE];
+      let area = &mut area[..(size as usize).min(DESCRIPTOR_SIZE)];
+      self.disk.read(block * self.block_size + offset, area)?;
+      collect_name(area, name, &mut len);
+    }
+    Ok(len.map(|(len, _)| len))
+  }
+
+  /// Finds the file at `path`, relative to the root directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the file, with `\` or `/` as the separator
+  fn find(&mut self, path: &str) -> uefi::Result<Record> {
+    let mut record = self.root;
+    let path: PathBuf<MAX_PATH_LEN> =
+      Path::new(path).normalize().map_err(status_of)?;
+    for name in path.components().filter_map(Component::name) {
+      if record.flags & FLAG_DIRECTORY == 0 {
+        return Err(Status::NOT_FOUND.into());
+      }
+      record = self.lookup(&record, name)?;
+    }
+    if record.flags & FLAG_DIRECTORY != 0 {
+      return Err(Status::INVALID_PARAMETER.into());
+    }
+    // Files larger than a single extent can hold are split across records,
+    // which are not followed.
+    if record.flags & FLAG_MULTI_EXTENT != 0 {
+      return Err(Status::UNSUPPORTED.into());
+    }
+    Ok(record)
+  }
+}
+
+impl Source for Iso9660<'_> {
+  fn read(
+    &mut self,
+    bs: &BootServices,
+    path: &str,
+    progress: &mut dyn Progress,
+  ) -> uefi::Result<Buffer> {
+    let record = self.find(path)?;
+    let size =
+      usize::try_from(record.size).map_err(|_| Status::BAD_BUFFER_SIZE)?;
+    // Whole blocks of the disk are read, so the buffer is rounded up to hold
+    // them.
+    let disk_block_size = self.disk.block_size();
+    let blocks = (size + disk_block_size - 1) / disk_block_size;
+    let mut buffer = loader::allocate_buffer(bs, blocks * disk_block_size)?;
+
+    let position = record.extent * self.block_size;
+    if position % disk_block_size as u64 != 0 {
+      return Err(Status::VOLUME_CORRUPTED.into());
+    }
+    let lba = position / disk_block_size as u64;
+    let chunk = (loader::PROGRESS_CHUNK / disk_block_size).max(1);
+    let mut done = 0;
+    progress.report(Step::Read, 0, size);
+    while done < blocks {
+      let count = chunk.min(blocks - done);
+      self.disk.read_blocks(
+        lba + done as u64,
+        &mut buffer[done * disk_block_size..][..count * disk_block_size],
+      )?;
+      done += count;
+      progress.report(Step::Read, size.min(done * disk_block_size), size);
+    }
+    buffer.truncate(size);
+    Ok(buffer)
+  }
+}
+
+/// Appends the Rock Ridge name entries of the system use `area` to `name`,
+/// recording the length collected so far and whether the name continues in
+/// `len`, and returns the continuation area that the entries continue in, if
+/// any, as its block, offset, and size.
+///
+/// # Arguments
+///
+/// * `area` - the system use entries
+/// * `name` - the buffer to collect the name in
+/// * `len` - the length of the name collected so far, and whether it
+///   continues, or [`None`] if no name has been found
+fn collect_name(
+  area: &[u8],
+  name: &mut [u8; MAX_NAME],
+  len: &mut Option<(usize, bool)>,
+) -> Option<(u64, u64, u64)> {
+  let mut continuation = None;
+  let mut i = 0;
+  while i + 4 <= area.len() {
+    let entry_len = area[i + 2] as usize;
+    if entry_len < 4 || i + entry_len > area.len() {
+      break;
+    }
+    let entry = &area[i..i + entry_len];
+    match &entry[..2] {
+      b"NM" if entry_len >= 5 => {
+        let (start, _) = len.unwrap_or((0, true));
+        let part = &entry[5..];
+        let end = (start + part.len()).min(MAX_NAME);
+        name[start..end].copy_from_slice(&part[..end - start]);
+        *len = Some((end, entry[4] & NAME_CONTINUE != 0));
+      }
+      b"CE" if entry_len >= 28 => {
+        continuation = Some((
+          read_u32(entry, 4) as u64,
+          read_u32(entry, 12) as u64,
+          read_u32(entry, 20) as u64,
+        ));
+      }
+      b"ST" => break,
+      _ => {}
+    }
+    i += entry_len;
+  }
+  continuation
+}
+
+/// Returns `true` if the plain ISO9660 name `iso_name` matches `name`,
+/// ignoring case, the version suffix, and a trailing dot.
+///
+/// # Arguments
+///
+/// * `iso_name` - the name of the directory record
+/// * `name` - the name to match
+fn iso_name_matches(iso_name: &[u8], name: &str) -> bool {
+  let iso_name = match iso_name.iter().position(|&c| c == b';') {
+    Some(end) => &iso_name[..end],
+    None => iso_name,
+  };
+  let iso_name = iso_name.strip_suffix(b".").unwrap_or(iso_name);
+  iso_name.eq_ignore_ascii_case(name.as_bytes())
+}
@@ -0,0 +1,222 @@
+//! This module provides keyboard input through the Simple Text Input Ex
+//! protocol, which reports the modifiers held with each key.
+//!
+//! The protocol is opened on the console's input device. When the firmware
+//! does not provide it there, keys are read through the console's plain
+//! Simple Text Input protocol instead, and are reported with no modifiers.
+
+use crate::efi::protocol::{self, Access, Opened, Protocol};
+use core::mem::MaybeUninit;
+use core::ptr;
+use kcore::guid::Guid;
+use uefi::proto::console::text::{Input, Key, ScanCode};
+use uefi::table::boot::{
+  BootServices, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol,
+};
+use uefi::{Char16, Event, Handle, Status};
+
+/// Set in a key's shift state if the rest of the state is valid.
+const SHIFT_STATE_VALID: u32 = 0x8000_0000;
+
+/// The shift state bits of either shift key.
+const SHIFT_PRESSED: u32 = 0x3;
+
+/// The shift state bits of either control key.
+const CONTROL_PRESSED: u32 = 0xc;
+
+/// The shift state bits of either alt key.
+const ALT_PRESSED: u32 = 0x30;
+
+/// The Simple Text Input Ex protocol.
+#[repr(C)]
+pub struct InputEx {
+  reset: unsafe extern "efiapi" fn(this: *mut Self, extended: bool) -> Status,
+  read_key_stroke_ex:
+    unsafe extern "efiapi" fn(this: *mut Self, key: *mut KeyData) -> Status,
+  wait_for_key_ex: Event,
+  set_state: usize,
+  register_key_notify: usize,
+  unregister_key_notify: usize,
+}
+
+kcore::const_assert_size!(InputEx, 48);
+kcore::const_assert_offset!(
+  InputEx,
+  reset: 0,
+  read_key_stroke_ex: 8,
+  wait_for_key_ex: 16,
+);
+
+// SAFETY: the structure matches the layout of the protocol.
+unsafe impl Protocol for InputEx {
+  const GUID: Guid = Guid::parse("dd9e7534-7762-4698-8c14-f58517a625aa");
+}
+
+/// A key read through [`InputEx`], with the state of the keyboard when it was
+/// pressed.
+#[repr(C)]
+struct KeyData {
+  scan_code: ScanCode,
+  unicode_char: u16,
+  shift_state: u32,
+  toggle_state: u8,
+}
+
+kcore::const_assert_size!(KeyData, 12);
+kcore::const_assert_offset!(
+  KeyData,
+  scan_code: 0,
+  unicode_char: 2,
+  shift_state: 4,
+  toggle_state: 8,
+);
+
+/// A key press, with the modifiers that were held when it was made.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct KeyPress {
+  /// The key that was pressed.
+  pub key: Key,
+
+  /// Whether either shift key was held.
+  pub shift: bool,
+
+  /// Whether either control key was held.
+  pub ctrl: bool,
+
+  /// Whether either alt key was held.
+  pub alt: bool,
+}
+
+/// The keyboard of the console.
+pub struct Keyboard<'a> {
+  ex: Option<Opened<'a, InputEx>>,
+}
+
+impl<'a> Keyboard<'a> {
+  /// Opens the keyboard of the console whose input is `stdin`.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  /// * `stdin` - the console's input
+  pub fn open(bs: &'a BootServices, image: Handle, stdin: &Input) -> Self {
+    // The firmware does not say which handle the console's input is on, so it
+    // is found by the identity of its Simple Text Input protocol.
+    let ex = protocol::locate_handles::<InputEx>(bs)
+      .ok()
+      .and_then(|handles| {
+        handles.iter().copied().find(|&handle| {
+          open_input(bs, image, handle)
+            .is_ok_and(|input| ptr::eq(&*input, stdin))
+        })
+      })
+      .and_then(|handle| {
+        // SAFETY: the console driver holds its input open, so it cannot be
+        // opened exclusively. It is only read from, never reconfigured.
+        unsafe { protocol::open_protocol(bs, handle, image, Access::Shared) }
+          .ok()
+      });
+    Self { ex }
+  }
+
+  /// Reads the next key press, if one is waiting.
+  ///
+  /// # Arguments
+  ///
+  /// * `stdin` - the console's input
+  pub fn read(&mut self, stdin: &mut Input) -> uefi::Result<Option<KeyPress>> {
+    let Some(ex) = &mut self.ex else {
+      return Ok(stdin.read_key()?.map(|key| KeyPress {
+        key,
+        shift: false,
+        ctrl: false,
+        alt: false,
+      }));
+    };
+
+    loop {
+      let mut data = MaybeUninit::<KeyData>::uninit();
+      let ex: &mut InputEx = ex;
+      // SAFETY: `ex` is a valid protocol, and `data` is valid for writes.
+      match unsafe { (ex.read_key_stroke_ex)(ex, data.as_mut_ptr()) } {
+        Status::NOT_READY => return Ok(None),
+        Status::SUCCESS => {}
+        status => return Err(status.into()),
+      }
+      // SAFETY: the firmware fills in the key on success.
+      let data = unsafe { data.assume_init() };
+
+      // Keyboards may report changes to the modifiers alone as keys with
+      // neither a character nor a scan code, which are not key presses.
+      let key = match data.scan_code {
+        ScanCode::NULL => match Char16::try_from(data.unicode_char) {
+          Ok(c) if data.unicode_char != 0 => Key::Printable(c),
+          _ => continue,
+        },
+        scan_code => Key::Special(scan_code),
+      };
+      let state = match data.shift_state & SHIFT_STATE_VALID {
+        0 => 0,
+        _ => data.shift_state,
+      };
+      return Ok(Some(KeyPress {
+        key,
+        shift: state & SHIFT_PRESSED != 0,
+        ctrl: state & CONTROL_PRESSED != 0,
+        alt: state & ALT_PRESSED != 0,
+      }));
+    }
+  }
+
+  /// Discards any key presses that are waiting, such as those queued by a key
+  /// being held down.
+  ///
+  /// # Arguments
+  ///
+  /// * `stdin` - the console's input
+  pub fn flush(&mut self, stdin: &mut Input) -> uefi::Result {
+    while self.read(stdin)?.is_some() {}
+    Ok(())
+  }
+
+  /// Returns an event that is signaled when a key press is waiting.
+  ///
+  /// # Arguments
+  ///
+  /// * `stdin` - the console's input
+  pub fn wait_event(&self, stdin: &Input) -> Event {
+    let event = match &self.ex {
+      Some(ex) => &ex.wait_for_key_ex,
+      None => stdin.wait_for_key_event(),
+    };
+    // SAFETY: the event is owned by the firmware's console driver, which
+    // outlives the keyboard, and is only ever waited on.
+    unsafe { event.unsafe_clone() }
+  }
+}
+
+/// Opens the Simple Text Input protocol on `handle` without taking exclusive
+/// ownership.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `handle` - the handle to open the protocol on
+fn open_input(
+  bs: &BootServices,
+  image: Handle,
+  handle: Handle,
+) -> uefi::Result<ScopedProtocol<'_, Input>> {
+  let params = OpenProtocolParams {
+    handle,
+    agent: image,
+    controller: None,
+  };
+  // SAFETY: the console driver holds its input open, so it cannot be opened
+  // exclusively. It is only read from, never reconfigured.
+  unsafe {
+    bs.open_protocol::<Input>(params, OpenProtocolAttributes::GetProtocol)
+  }
+}
@@ -0,0 +1,1259 @@
+//! This module provides booting of kernels that implement the Limine boot
+//! protocol, as an alternative to the native handoff.
+//!
+//! The kernel is laid out as for the native handoff, then its loaded image is
+//! scanned for the identifiers of its requests. Each understood request is
+//! answered with a response in bootloader-reclaimable memory, referred to by
+//! its address in the higher-half direct map (HHDM). The requests answered
+//! are for the HHDM, the memory map, the framebuffer, the modules, which hold
+//! the initrd, and the startup of the other processors; any others are left
+//! unanswered, as the protocol permits.
+//!
+//! Base revisions 0 and 1 are supported. The first 4 GiB of memory are
+//! identity-mapped, and all of physical memory is mapped in the HHDM with
+//! whole huge pages. Other processors are started from a trampoline below
+//! 1 MiB, in the x2APIC mode the firmware left them in, and wait in long mode
+//! for the kernel to give them an address to jump to.
+
+use crate::acpi;
+use crate::firmware::Quirks;
+use crate::handoff;
+use crate::loader::{self, LoadedFile, PAGE_SIZE};
+use crate::paging::{AddressSpace, PageFlags, HUGE_PAGE_SIZE};
+use arch::mmio::{Identity, MmioRegion};
+use arch::volatile::Volatile;
+use bootinfo::{PhysRange, PixelFormat};
+use kcore::acpi::Madt;
+use kcore::bytes::read_u64;
+use kcore::time::{self, CycleCounter, Deadline, Duration};
+use uefi::table::boot::{AllocateType, BootServices, MemoryMap, MemoryType};
+use uefi::table::{Boot, SystemTable};
+use uefi::{Handle, Status};
+
+/// The identifier that every request begins with.
+const COMMON_MAGIC: [u64; 2] = [0xc7b1_dd30_df4c_8b88, 0x0a82_e883_a194_f07b];
+
+/// The identifier of the tag declaring the base revision the kernel needs.
+const BASE_REVISION_MAGIC: [u64; 2] =
+  [0xf956_2b2d_5c95_a6c8, 0x6a7b_3849_4453_6bdc];
+
+/// The identifiers that follow [`COMMON_MAGIC`] in the requests answered.
+const HHDM_REQUEST: [u64; 2] = [0x48dc_f1cb_8ad2_b852, 0x6398_4e95_9a98_244b];
+const MEMMAP_REQUEST: [u64; 2] = [0x67cf_3d9d_378a_806f, 0xe304_acdf_c50c_3c62];
+const FRAMEBUFFER_REQUEST: [u64; 2] =
+  [0x9d58_27dc_d881_dd75, 0xa314_8604_f6fa_b11b];
+const MODULE_REQUEST: [u64; 2] = [0x3e7e_2797_02be_32af, 0xca1c_4f3b_d128_0cee];
+const SMP_REQUEST: [u64; 2] = [0x95a6_7b81_9a1b_857e, 0xa0b6_1b72_3b6a_73e0];
+
+/// The offset of the response pointer within a request.
+const RESPONSE_OFFSET: usize = 40;
+
+/// The size of the largest request answered.
+const REQUEST_SIZE: usize = 56;
+
+/// The highest base revision supported.
+const MAX_BASE_REVISION: u64 = 1;
+
+/// The virtual address that physical memory is mapped at in the HHDM.
+const HHDM_OFFSET: u64 = 0xffff_8000_0000_0000;
+
+/// The memory below this address is identity-mapped, as base revision 0
+/// requires.
+const IDENTITY_LIMIT: u64 = 0x1_0000_0000;
+
+/// The Limine memory map types.
+const MEMORY_USABLE: u64 = 0;
+const MEMORY_RESERVED: u64 = 1;
+const MEMORY_ACPI_RECLAIMABLE: u64 = 2;
+const MEMORY_ACPI_NVS: u64 = 3;
+const MEMORY_BAD: u64 = 4;
+const MEMORY_BOOTLOADER_RECLAIMABLE: u64 = 5;
+const MEMORY_KERNEL_AND_MODULES: u64 = 6;
+
+/// The framebuffer memory model of direct RGB pixels.
+const FRAMEBUFFER_RGB: u8 = 1;
+
+/// The SMP flag indicating the local APICs are in x2APIC mode.
+const SMP_X2APIC: u32 = 1;
+
+/// The size of the stack that each processor is entered on.
+const STACK_SIZE: usize = 64 * 1024;
+
+/// The number of page tables reserved for building the kernel address space.
+const PAGE_TABLES: usize = 512;
+
+/// The path that the initrd module is reported with.
+const INITRD_PATH: &[u8] = b"/initrd\0";
+
+/// The model-specific register holding the extended feature enables.
+const IA32_EFER: u32 = 0xc000_0080;
+
+/// The model-specific register holding the local APIC base and mode.
+const IA32_APIC_BASE: u32 = 0x1b;
+
+/// The bit of [`IA32_APIC_BASE`] indicating the local APIC is in x2APIC mode.
+const APIC_X2APIC_MODE: u64 = 1 << 10;
+
+/// The bits of [`IA32_APIC_BASE`] that hold the address of the xAPIC
+/// registers.
+const APIC_BASE_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// The size of the page of xAPIC registers.
+const XAPIC_SIZE: u64 = 0x1000;
+
+/// The offsets of the xAPIC ID and interrupt command registers.
+const XAPIC_ID: u64 = 0x20;
+const XAPIC_ICR_LOW: u64 = 0x300;
+const XAPIC_ICR_HIGH: u64 = 0x310;
+
+/// The model-specific registers of the x2APIC ID and interrupt command
+/// registers.
+const X2APIC_ID: u32 = 0x802;
+const X2APIC_ICR: u32 = 0x830;
+
+/// The interrupt command that asserts INIT.
+const ICR_INIT: u32 = 0x4500;
+
+/// The interrupt command that sends a startup IPI, with the page of the
+/// startup code in its low byte.
+const ICR_STARTUP: u32 = 0x4600;
+
+/// The bit of the interrupt command indicating it has not yet been sent.
+const ICR_PENDING: u32 = 1 << 12;
+
+/// Processors are only started from below this address.
+const TRAMPOLINE_LIMIT: u64 = 0xf_ffff;
+
+/// The offsets of the parameters of the trampoline, as laid out in its
+/// assembly below.
+const TRAMPOLINE_ROOT: usize = 8;
+const TRAMPOLINE_STACK: usize = 16;
+const TRAMPOLINE_INFO: usize = 24;
+const TRAMPOLINE_STARTED: usize = 32;
+
+/// The time to wait after INIT, after a startup IPI, and for a processor to
+/// report that it has started.
+const INIT_DELAY: Duration = Duration::from_millis(10);
+const STARTUP_DELAY: Duration = Duration::from_micros(200);
+const START_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The response to the HHDM request.
+#[repr(C)]
+struct HhdmResponse {
+  revision: u64,
+  offset: u64,
+}
+
+kcore::const_assert_size!(HhdmResponse, 16);
+kcore::const_assert_offset!(HhdmResponse, revision: 0, offset: 8);
+
+/// The response to the memory map request.
+#[repr(C)]
+struct MemmapResponse {
+  revision: u64,
+  entry_count: u64,
+  entries: u64,
+}
+
+kcore::const_assert_size!(MemmapResponse, 24);
+kcore::const_assert_offset!(
+  MemmapResponse,
+  revision: 0,
+  entry_count: 8,
+  entries: 16,
+);
+
+/// An entry of the memory map.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct MemmapEntry {
+  base: u64,
+  length: u64,
+  ty: u64,
+}
+
+kcore::const_assert_size!(MemmapEntry, 24);
+kcore::const_assert_offset!(MemmapEntry, base: 0, length: 8, ty: 16);
+
+/// The response to the framebuffer request.
+#[repr(C)]
+struct FramebufferResponse {
+  revision: u64,
+  framebuffer_count: u64,
+  framebuffers: u64,
+}
+
+kcore::const_assert_size!(FramebufferResponse, 24);
+kcore::const_assert_offset!(
+  FramebufferResponse,
+  revision: 0,
+  framebuffer_count: 8,
+  framebuffers: 16,
+);
+
+/// A framebuffer, as described to the kernel.
+#[repr(C)]
+struct Framebuffer {
+  address: u64,
+  width: u64,
+  height: u64,
+  pitch: u64,
+  bpp: u16,
+  memory_model: u8,
+  red_mask_size: u8,
+  red_mask_shift: u8,
+  green_mask_size: u8,
+  green_mask_shift: u8,
+  blue_mask_size: u8,
+  blue_mask_shift: u8,
+  unused: [u8; 7],
+  edid_size: u64,
+  edid: u64,
+}
+
+kcore::const_assert_size!(Framebuffer, 64);
+kcore::const_assert_offset!(
+  Framebuffer,
+  address: 0,
+  width: 8,
+  height: 16,
+  pitch: 24,
+  bpp: 32,
+  memory_model: 34,
+  red_mask_size: 35,
+  blue_mask_shift: 40,
+  edid_size: 48,
+  edid: 56,
+);
+
+/// The response to the module request.
+#[repr(C)]
+struct ModuleResponse {
+  revision: u64,
+  module_count: u64,
+  modules: u64,
+}
+
+kcore::const_assert_size!(ModuleResponse, 24);
+kcore::const_assert_offset!(
+  ModuleResponse,
+  revision: 0,
+  module_count: 8,
+  modules: 16,
+);
+
+/// A file loaded by the bootloader, as described to the kernel.
+#[repr(C)]
+struct File {
+  revision: u64,
+  address: u64,
+  size: u64,
+  path: u64,
+  cmdline: u64,
+  media_type: u32,
+  unused: u32,
+  tftp_ip: u32,
+  tftp_port: u32,
+  partition_index: u32,
+  mbr_disk_id: u32,
+  gpt_disk_uuid: [u8; 16],
+  gpt_part_uuid: [u8; 16],
+  part_uuid: [u8; 16],
+}
+
+kcore::const_assert_size!(File, 112);
+kcore::const_assert_offset!(
+  File,
+  revision: 0,
+  address: 8,
+  size: 16,
+  path: 24,
+  cmdline: 32,
+  media_type: 40,
+  tftp_ip: 48,
+  partition_index: 56,
+  mbr_disk_id: 60,
+  gpt_disk_uuid: 64,
+  gpt_part_uuid: 80,
+  part_uuid: 96,
+);
+
+/// The response to the SMP request.
+#[repr(C)]
+struct SmpResponse {
+  revision: u64,
+  flags: u32,
+  bsp_lapic_id: u32,
+  cpu_count: u64,
+  cpus: u64,
+}
+
+kcore::const_assert_size!(SmpResponse, 32);
+kcore::const_assert_offset!(
+  SmpResponse,
+  revision: 0,
+  flags: 8,
+  bsp_lapic_id: 12,
+  cpu_count: 16,
+  cpus: 24,
+);
+
+/// A processor, as described to the kernel.
+///
+/// The kernel starts a waiting processor by writing the address for it to
+/// jump to into `goto_address`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SmpInfo {
+  processor_id: u32,
+  lapic_id: u32,
+  reserved: u64,
+  goto_address: u64,
+  extra_argument: u64,
+}
+
+kcore::const_assert_size!(SmpInfo, 32);
+kcore::const_assert_offset!(
+  SmpInfo,
+  processor_id: 0,
+  lapic_id: 4,
+  reserved: 8,
+  goto_address: 16,
+  extra_argument: 24,
+);
+
+/// The offsets of the requests found in the kernel image.
+#[derive(Default)]
+struct Requests {
+  base_revision: Option<usize>,
+  hhdm: Option<usize>,
+  memmap: Option<usize>,
+  framebuffer: Option<usize>,
+  module: Option<usize>,
+  smp: Option<usize>,
+}
+
+impl Requests {
+  /// Finds the requests in the loaded kernel `image`.
+  ///
+  /// Requests are 8-byte aligned, and must fit within the image.
+  ///
+  /// # Arguments
+  ///
+  /// * `image` - the loaded kernel image
+  fn find(image: &[u8]) -> Self {
+    let mut requests = Self::default();
+    let end = (image.len() + 1).saturating_sub(REQUEST_SIZE);
+    for offset in (0..end).step_by(8) {
+      let id = |word: usize| read_u64(image, offset + word * 8);
+      let magic = [id(0), id(1)];
+      if magic == BASE_REVISION_MAGIC {
+        requests.base_revision = Some(offset);
+        continue;
+      }
+      if magic != COMMON_MAGIC {
+        continue;
+      }
+      let slot = match [id(2), id(3)] {
+        HHDM_REQUEST => &mut requests.hhdm,
+        MEMMAP_REQUEST => &mut requests.memmap,
+        FRAMEBUFFER_REQUEST => &mut requests.framebuffer,
+        MODULE_REQUEST => &mut requests.module,
+        SMP_REQUEST => &mut requests.smp,
+        _ => continue,
+      };
+      *slot = Some(offset);
+    }
+    requests
+  }
+}
+
+/// A bump allocator of the responses and the structures they refer to.
+struct Arena {
+  buffer: &'static mut [u8],
+  used: usize,
+}
+
+impl Arena {
+  /// Moves `value` into the arena.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to move into the arena
+  fn alloc<T>(&mut self, value: T) -> uefi::Result<&'static mut T> {
+    let start = self.reserve::<T>(1)?;
+    // SAFETY: the reserved space is suitably aligned for a `T`, and is never
+    // handed out again.
+    unsafe {
+      start.write(value);
+      Ok(&mut *start)
+    }
+  }
+
+  /// Moves `len` copies of `value` into the arena.
+  ///
+  /// # Arguments
+  ///
+  /// * `len` - the number of copies
+  /// * `value` - the value to copy
+  fn alloc_slice<T: Copy>(
+    &mut self,
+    len: usize,
+    value: T,
+  ) -> uefi::Result<&'static mut [T]> {
+    let start = self.reserve::<T>(len)?;
+    // SAFETY: the reserved space is suitably aligned for `len` values of `T`,
+    // and is never handed out again.
+    unsafe {
+      for i in 0..len {
+        start.add(i).write(value);
+      }
+      Ok(core::slice::from_raw_parts_mut(start, len))
+    }
+  }
+
+  /// Reserves aligned space for `len` values of `T`.
+  ///
+  /// # Arguments
+  ///
+  /// * `len` - the number of values
+  fn reserve<T>(&mut self, len: usize) -> uefi::Result<*mut T> {
+    let align = core::mem::align_of::<T>();
+    let start = (self.used + align - 1) & !(align - 1);
+    let end = start + len * core::mem::size_of::<T>();
+    let space = self
+      .buffer
+      .get_mut(start..end)
+      .ok_or(Status::BUFFER_TOO_SMALL)?;
+    self.used = end;
+    Ok(space.as_mut_ptr().cast())
+  }
+}
+
+/// The memory map response, with space for every entry the final map may
+/// hold.
+struct Memmap {
+  response: &'static mut MemmapResponse,
+  entries: &'static mut [MemmapEntry],
+}
+
+/// The processors to start, and what is needed to start them.
+struct Smp {
+  response: &'static mut SmpResponse,
+  cpus: &'static mut [SmpInfo],
+  pointers: &'static mut [u64],
+  stacks: &'static mut [u8],
+  trampoline: &'static mut [u8],
+  apic: Apic,
+  clock: CycleCounter,
+}
+
+/// Everything prepared for entering a Limine kernel, which only remains to be
+/// completed once boot services have been exited.
+pub struct Handoff {
+  space: AddressSpace,
+  entry: u64,
+  stack: u64,
+  modules: [PhysRange; 2],
+  memmap: Option<Memmap>,
+  smp: Option<Smp>,
+  quirks: Quirks,
+}
+
+impl Handoff {
+  /// Lays out the `kernel` executable in memory, builds the address space to
+  /// enter it in, and answers its requests.
+  ///
+  /// This must be the last use of boot services to allocate memory before
+  /// they are exited, since the memory map is read to decide what to map.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `system_table` - the system table
+  /// * `image` - the handle of the bootloader image
+  /// * `kernel` - the loaded kernel executable
+  /// * `initrd` - the loaded initrd, passed to the kernel as a module
+  /// * `quirks` - the workarounds needed on the firmware
+  pub fn prepare(
+    bs: &BootServices,
+    system_table: &SystemTable<Boot>,
+    image: Handle,
+    kernel: &LoadedFile,
+    initrd: Option<&LoadedFile>,
+    quirks: Quirks,
+  ) -> uefi::Result<Self> {
+    let mut space = AddressSpace::new(bs, PAGE_TABLES)?;
+    let (kernel, entry) = handoff::load_kernel(bs, &mut space, kernel.data)?;
+    // SAFETY: the kernel was just laid out in this memory, which is never
+    // freed, and nothing else refers to it.
+    let memory = unsafe {
+      core::slice::from_raw_parts_mut(
+        kernel.start as *mut u8,
+        kernel.len as usize,
+      )
+    };
+    let requests = Requests::find(memory);
+    if let Some(offset) = requests.base_revision {
+      // The kernel learns that its base revision is supported from it being
+      // cleared.
+      if read_u64(memory, offset + 16) <= MAX_BASE_REVISION {
+        write_u64(memory, offset + 16, 0);
+      }
+    }
+
+    let madt = requests.smp.and_then(|_| acpi::madt(system_table));
+    let cpu_count = madt.map_or(0, |madt| madt.processors().count());
+    let size = bs.memory_map_size();
+    let capacity = size.map_size / size.entry_size + handoff::SPARE_REGIONS;
+    let mut arena = Arena {
+      buffer: loader::allocate_buffer(
+        bs,
+        PAGE_SIZE
+          + capacity * (core::mem::size_of::<MemmapEntry>() + 8)
+          + cpu_count * (core::mem::size_of::<SmpInfo>() + 8),
+      )?
+      .leak(),
+      used: 0,
+    };
+
+    if let Some(offset) = requests.hhdm {
+      let response = arena.alloc(HhdmResponse {
+        revision: 0,
+        offset: HHDM_OFFSET,
+      })?;
+      write_u64(memory, offset + RESPONSE_OFFSET, hhdm(response));
+    }
+
+    if let Some(offset) = requests.framebuffer {
+      if let Ok(framebuffer) = handoff::framebuffer(bs, image, quirks) {
+        map_huge(
+          &mut space,
+          HHDM_OFFSET,
+          framebuffer.physical_address,
+          framebuffer.physical_address + framebuffer.size,
+          PageFlags::READ_WRITE,
+        )?;
+        let (red, blue) = match framebuffer.format {
+          PixelFormat::BGR => (16, 0),
+          _ => (0, 16),
+        };
+        let framebuffer = arena.alloc(Framebuffer {
+          address: HHDM_OFFSET + framebuffer.physical_address,
+          width: framebuffer.width as u64,
+          height: framebuffer.height as u64,
+          pitch: framebuffer.stride as u64 * 4,
+          bpp: 32,
+          memory_model: FRAMEBUFFER_RGB,
+          red_mask_size: 8,
+          red_mask_shift: red,
+          green_mask_size: 8,
+          green_mask_shift: 8,
+          blue_mask_size: 8,
+          blue_mask_shift: blue,
+          unused: [0; 7],
+          edid_size: 0,
+          edid: 0,
+        })?;
+        let pointer = arena.alloc(hhdm(framebuffer))?;
+        let response = arena.alloc(FramebufferResponse {
+          revision: 0,
+          framebuffer_count: 1,
+          framebuffers: hhdm(pointer),
+        })?;
+        write_u64(memory, offset + RESPONSE_OFFSET, hhdm(response));
+      }
+    }
+
+    let initrd = initrd.map_or(PhysRange::EMPTY, |initrd| PhysRange {
+      start: initrd.data.as_ptr() as u64,
+      len: initrd.data.len() as u64,
+    });
+    if let Some(offset) = requests.module {
+      let path = arena.alloc_slice(INITRD_PATH.len(), 0u8)?;
+      path.copy_from_slice(INITRD_PATH);
+      let cmdline = arena.alloc(0u8)?;
+      let module = arena.alloc(File {
+        revision: 0,
+        address: HHDM_OFFSET + initrd.start,
+        size: initrd.len,
+        path: hhdm(&path[0]),
+        cmdline: hhdm(cmdline),
+        media_type: 0,
+        unused: 0,
+        tftp_ip: 0,
+        tftp_port: 0,
+        partition_index: 0,
+        mbr_disk_id: 0,
+        gpt_disk_uuid: [0; 16],
+        gpt_part_uuid: [0; 16],
+        part_uuid: [0; 16],
+      })?;
+      let pointer = arena.alloc(hhdm(module))?;
+      let response = arena.alloc(ModuleResponse {
+        revision: 0,
+        module_count: u64::from(!initrd.is_empty()),
+        modules: hhdm(pointer),
+      })?;
+      write_u64(memory, offset + RESPONSE_OFFSET, hhdm(response));
+    }
+
+    let smp = match (requests.smp, madt) {
+      (Some(offset), Some(madt)) => {
+        let smp = Smp::prepare(bs, &mut arena, madt)?;
+        write_u64(memory, offset + RESPONSE_OFFSET, hhdm(smp.response));
+        Some(smp)
+      }
+      _ => None,
+    };
+
+    let stack = loader::allocate_buffer(bs, STACK_SIZE)?.leak();
+    let stack = HHDM_OFFSET + stack.as_ptr() as u64 + STACK_SIZE as u64;
+
+    let memmap = match requests.memmap {
+      Some(offset) => {
+        let entries = arena.alloc_slice(
+          capacity,
+          MemmapEntry {
+            base: 0,
+            length: 0,
+            ty: MEMORY_RESERVED,
+          },
+        )?;
+        let pointers = arena.alloc_slice(capacity, 0u64)?;
+        for (pointer, entry) in pointers.iter_mut().zip(entries.iter()) {
+          *pointer = hhdm(entry);
+        }
+        let response = arena.alloc(MemmapResponse {
+          revision: 0,
+          entry_count: 0,
+          entries: hhdm(&pointers[0]),
+        })?;
+        write_u64(memory, offset + RESPONSE_OFFSET, hhdm(response));
+        Some(Memmap { response, entries })
+      }
+      None => None,
+    };
+
+    // Everything allocated for the kernel must be in the map when it is read,
+    // so that it is mapped.
+    let buffer = loader::allocate_buffer(
+      bs,
+      size.map_size + handoff::SPARE_REGIONS * size.entry_size,
+    )?
+    .leak();
+    let map = bs.memory_map(buffer)?;
+    map_huge(&mut space, 0, 0, IDENTITY_LIMIT, PageFlags::ALL)?;
+    map_huge(
+      &mut space,
+      HHDM_OFFSET,
+      0,
+      IDENTITY_LIMIT,
+      PageFlags::READ_WRITE,
+    )?;
+    for descriptor in map.entries() {
+      let start = descriptor.phys_start;
+      let end = start + descriptor.page_count * PAGE_SIZE as u64;
+      if is_identity_mapped(descriptor.ty) {
+        map_huge(&mut space, 0, start, end, PageFlags::ALL)?;
+      }
+      map_huge(&mut space, HHDM_OFFSET, start, end, PageFlags::READ_WRITE)?;
+    }
+
+    let mut modules = [kernel, initrd];
+    modules.sort_unstable_by_key(|module| module.start);
+    Ok(Self {
+      space,
+      entry,
+      stack,
+      modules,
+      memmap,
+      smp,
+      quirks,
+    })
+  }
+
+  /// Records the final memory `map` in the memory map response, starts the
+  /// other processors, then switches to the kernel address space and enters
+  /// the kernel.
+  ///
+  /// # Arguments
+  ///
+  /// * `map` - the memory map returned when exiting boot services
+  pub fn enter(mut self, mut map: MemoryMap<'static>) -> ! {
+    if let Some(memmap) = &mut self.memmap {
+      map.sort();
+      let (mut len, capacity) = (0, memmap.entries.len());
+      let mut push = |base: u64, end: u64, ty: u64| {
+        if base >= end {
+          return;
+        }
+        match memmap.entries[..len].last_mut() {
+          Some(last) if last.ty == ty && last.base + last.length == base => {
+            last.length += end - base;
+          }
+          _ if len < capacity => {
+            memmap.entries[len] = MemmapEntry {
+              base,
+              length: end - base,
+              ty,
+            };
+            len += 1;
+          }
+          _ => {}
+        }
+      };
+      for descriptor in map.entries() {
+        let start = descriptor.phys_start;
+        let end = start + descriptor.page_count * PAGE_SIZE as u64;
+        let ty = memory_type(descriptor.ty, self.quirks);
+        // The kernel and its modules are split out of the loader memory that
+        // holds them.
+        let mut position = start;
+        for module in &self.modules {
+          let (from, to) = (module.start.max(start), module.end().min(end));
+          if from < to {
+            push(position, from, ty);
+            push(from, to, MEMORY_KERNEL_AND_MODULES);
+            position = to;
+          }
+        }
+        push(position, end, ty);
+      }
+      memmap.response.entry_count = len as u64;
+    }
+
+    let root = self.space.root();
+    if let Some(smp) = &mut self.smp {
+      smp.start(root);
+    }
+
+    // SAFETY: boot services have been exited, since the final memory map is
+    // only returned when exiting them. The address space identity-maps all
+    // loader memory, which holds the bootloader, and maps the kernel entry
+    // point and the stack in the HHDM.
+    unsafe {
+      core::arch::asm!(
+        "cli",
+        "cld",
+        // Enable the no-execute bit, which is reserved until EFER.NXE is set.
+        "rdmsr",
+        "bts eax, 11",
+        "wrmsr",
+        "mov cr3, r8",
+        "mov rsp, r10",
+        // The kernel is entered with a null return address, and with all
+        // other general purpose registers cleared.
+        "push 0",
+        "push r9",
+        "xor eax, eax",
+        "xor ebx, ebx",
+        "xor ecx, ecx",
+        "xor edx, edx",
+        "xor esi, esi",
+        "xor edi, edi",
+        "xor ebp, ebp",
+        "xor r8d, r8d",
+        "xor r9d, r9d",
+        "xor r10d, r10d",
+        "xor r11d, r11d",
+        "xor r12d, r12d",
+        "xor r13d, r13d",
+        "xor r14d, r14d",
+        "xor r15d, r15d",
+        "ret",
+        in("r8") root,
+        in("r9") self.entry,
+        in("r10") self.stack,
+        in("ecx") IA32_EFER,
+        options(noreturn),
+      )
+    }
+  }
+}
+
+impl Smp {
+  /// Prepares to start the enabled processors described by the `madt`,
+  /// allocating their descriptions from `arena`.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `arena` - the arena to allocate the response from
+  /// * `madt` - the MADT
+  fn prepare(
+    bs: &BootServices,
+    arena: &mut Arena,
+    madt: Madt<'static>,
+  ) -> uefi::Result<Self> {
+    let apic = Apic::current();
+    let limit: u64 = if apic.x2apic { u32::MAX.into() } else { 0xfe };
+    let count = madt
+      .processors()
+      .filter(|cpu| cpu.enabled && cpu.id <= limit)
+      .count();
+    let cpus = arena.alloc_slice(
+      count,
+      SmpInfo {
+        processor_id: 0,
+        lapic_id: 0,
+        reserved: 0,
+        goto_address: 0,
+        extra_argument: 0,
+      },
+    )?;
+    let processors = madt
+      .processors()
+      .filter(|cpu| cpu.enabled && cpu.id <= limit);
+    for (info, cpu) in cpus.iter_mut().zip(processors) {
+      info.processor_id = cpu.uid;
+      info.lapic_id = cpu.id as u32;
+    }
+    let pointers = arena.alloc_slice(count, 0u64)?;
+    let response = arena.alloc(SmpResponse {
+      revision: 0,
+      flags: if apic.x2apic { SMP_X2APIC } else { 0 },
+      bsp_lapic_id: apic.id(),
+      cpu_count: 0,
+      cpus: hhdm(pointers.as_ptr()),
+    })?;
+
+    let stacks = loader::allocate_buffer(bs, count.max(1) * STACK_SIZE)?.leak();
+    let trampoline = Self::install_trampoline(bs)?;
+
+    // The timestamp counter, which is used to time the startup sequence once
+    // boot services are gone, is measured against the firmware's stall where
+    // the processor does not report its frequency.
+    let clock = CycleCounter::architectural().unwrap_or_else(|| {
+      let start = arch::cycle_counter();
+      bs.stall(INIT_DELAY.as_micros() as usize);
+      let cycles = (arch::cycle_counter() - start) as u128;
+      let frequency = cycles * 1_000_000 / INIT_DELAY.as_micros();
+      CycleCounter::new((frequency as u64).max(1))
+    });
+
+    Ok(Self {
+      response,
+      cpus,
+      pointers,
+      stacks,
+      trampoline,
+      apic,
+      clock,
+    })
+  }
+
+  /// Copies the startup trampoline below 1 MiB, followed by a page reserved
+  /// for a copy of the root page table.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  fn install_trampoline(bs: &BootServices) -> uefi::Result<&'static mut [u8]> {
+    // SAFETY: the symbols are defined by the trampoline's assembly below,
+    // and only their addresses are taken. Newer compilers no longer require
+    // `unsafe` for this, but the minimum supported one does.
+    #[allow(unused_unsafe)]
+    let (start, end) = unsafe {
+      (
+        core::ptr::addr_of!(limine_trampoline),
+        core::ptr::addr_of!(limine_trampoline_end),
+      )
+    };
+    // SAFETY: the trampoline is a single block of code between the two
+    // symbols.
+    let code = unsafe {
+      core::slice::from_raw_parts(start, end as usize - start as usize)
+    };
+    let address = bs.allocate_pages(
+      AllocateType::MaxAddress(TRAMPOLINE_LIMIT),
+      MemoryType::LOADER_CODE,
+      2,
+    )?;
+    // SAFETY: the firmware returned a unique allocation of two pages.
+    let trampoline = unsafe {
+      core::slice::from_raw_parts_mut(address as *mut u8, 2 * PAGE_SIZE)
+    };
+    trampoline[..code.len()].copy_from_slice(code);
+    Ok(trampoline)
+  }
+
+  /// Starts every processor other than this one, leaving each waiting in the
+  /// address space with the root table `root`, and records those that started
+  /// in the response.
+  ///
+  /// # Arguments
+  ///
+  /// * `root` - the physical address of the root page table
+  fn start(&mut self, root: u64) {
+    // Processors first enable paging with a copy of the root table below
+    // 4 GiB, since they can only load 32 bits into CR3 at that point.
+    let (code, table) = self.trampoline.split_at_mut(PAGE_SIZE);
+    // SAFETY: the root table is a page in the identity-mapped pool of the
+    // address space.
+    table.copy_from_slice(unsafe {
+      core::slice::from_raw_parts(root as *const u8, PAGE_SIZE)
+    });
+    write_u64(code, TRAMPOLINE_ROOT, root);
+    let page = (code.as_ptr() as u64 / PAGE_SIZE as u64) as u32;
+
+    let mut count = 0;
+    for index in 0..self.cpus.len() {
+      let id = self.cpus[index].lapic_id;
+      if id != self.response.bsp_lapic_id && !self.start_one(index, page) {
+        continue;
+      }
+      self.pointers[count] = hhdm(&self.cpus[index]);
+      count += 1;
+    }
+    self.response.cpu_count = count as u64;
+  }
+
+  /// Starts the processor at `index` from the trampoline at `page`, returning
+  /// `true` if it started.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the processor
+  /// * `page` - the page number of the trampoline
+  fn start_one(&mut self, index: usize, page: u32) -> bool {
+    let stack = &self.stacks[index * STACK_SIZE..][..STACK_SIZE];
+    let stack = HHDM_OFFSET + stack.as_ptr() as u64 + STACK_SIZE as u64;
+    let info = hhdm(&self.cpus[index]);
+    let code = self.trampoline.as_mut_ptr();
+    // SAFETY: the parameters lie within the trampoline, which the processor
+    // being started only reads until it reports that it has started.
+    unsafe {
+      code
+        .add(TRAMPOLINE_STACK)
+        .cast::<u64>()
+        .write_volatile(stack);
+      code.add(TRAMPOLINE_INFO).cast::<u64>().write_volatile(info);
+      code.add(TRAMPOLINE_STARTED).cast::<u64>().write_volatile(0);
+    }
+    let started = || {
+      // SAFETY: as above; the processor writes this once it has started.
+      unsafe { code.add(TRAMPOLINE_STARTED).cast::<u64>().read_volatile() != 0 }
+    };
+
+    let id = self.cpus[index].lapic_id;
+    self.apic.send(id, ICR_INIT);
+    time::delay(&self.clock, INIT_DELAY);
+    // A second startup IPI is only sent if the first one was missed.
+    self.apic.send(id, ICR_STARTUP | page);
+    if Deadline::after(&self.clock, STARTUP_DELAY).wait(&self.clock, started) {
+      return true;
+    }
+    self.apic.send(id, ICR_STARTUP | page);
+    Deadline::after(&self.clock, START_TIMEOUT).wait(&self.clock, started)
+  }
+}
+
+/// The local APIC of the running processor.
+struct Apic {
+  registers: MmioRegion,
+  x2apic: bool,
+}
+
+impl Apic {
+  /// Returns the local APIC of the running processor, in its current mode.
+  fn current() -> Self {
+    // SAFETY: every x86-64 processor has the APIC base register.
+    let base = unsafe { rdmsr(IA32_APIC_BASE) };
+    // SAFETY: the xAPIC registers belong to the local APIC, which nothing
+    // else uses while the bootloader runs.
+    let registers = unsafe {
+      MmioRegion::map(&mut Identity, base & APIC_BASE_MASK, XAPIC_SIZE)
+    }
+    .expect("the xAPIC registers are identity-mapped");
+    Self {
+      registers,
+      x2apic: base & APIC_X2APIC_MODE != 0,
+    }
+  }
+
+  /// Returns the ID of the local APIC.
+  fn id(&self) -> u32 {
+    if self.x2apic {
+      // SAFETY: the x2APIC registers exist in x2APIC mode.
+      unsafe { rdmsr(X2APIC_ID) as u32 }
+    } else {
+      self.xapic(XAPIC_ID).read() >> 24
+    }
+  }
+
+  /// Sends the interrupt `command` to the processor with the local APIC
+  /// `id`, waiting for it to be sent.
+  ///
+  /// # Arguments
+  ///
+  /// * `id` - the ID of the destination local APIC
+  /// * `command` - the low half of the interrupt command
+  fn send(&self, id: u32, command: u32) {
+    if self.x2apic {
+      // SAFETY: the x2APIC registers exist in x2APIC mode, and sends through
+      // them complete immediately.
+      unsafe { wrmsr(X2APIC_ICR, (id as u64) << 32 | command as u64) };
+      return;
+    }
+    self.xapic(XAPIC_ICR_HIGH).write(id << 24);
+    let low = self.xapic(XAPIC_ICR_LOW);
+    low.write(command);
+    while low.read() & ICR_PENDING != 0 {
+      core::hint::spin_loop();
+    }
+  }
+
+  /// Returns the xAPIC register at `offset` from the base of the registers.
+  ///
+  /// # Arguments
+  ///
+  /// * `offset` - the offset of the register
+  fn xapic(&self, offset: u64) -> &Volatile<u32> {
+    self
+      .registers
+      .registers(offset)
+      .expect("xAPIC registers lie within their page")
+  }
+}
+
+/// Maps the physical memory from `start` to `end`, rounded out to whole huge
+/// pages, at `offset` above its physical address.
+///
+/// Mapping everything with huge pages keeps overlapping ranges from needing
+/// conflicting page sizes.
+///
+/// # Arguments
+///
+/// * `space` - the address space to map into
+/// * `offset` - the offset of the virtual addresses from the physical ones
+/// * `start` - the physical address of the start of the memory
+/// * `end` - the physical address of the end of the memory
+/// * `flags` - the access permitted to the mapping
+fn map_huge(
+  space: &mut AddressSpace,
+  offset: u64,
+  start: u64,
+  end: u64,
+  flags: PageFlags,
+) -> uefi::Result {
+  let start = start & !(HUGE_PAGE_SIZE - 1);
+  let end = (end + HUGE_PAGE_SIZE - 1) & !(HUGE_PAGE_SIZE - 1);
+  space.map(offset + start, start, end - start, flags)
+}
+
+/// Returns `true` if memory of type `ty` is identity-mapped in the kernel
+/// address space, beyond the first 4 GiB.
+///
+/// # Arguments
+///
+/// * `ty` - the type of memory
+fn is_identity_mapped(ty: MemoryType) -> bool {
+  matches!(ty, MemoryType::LOADER_CODE | MemoryType::LOADER_DATA)
+}
+
+/// Returns the Limine memory map type reported for memory of type `ty`.
+///
+/// # Arguments
+///
+/// * `ty` - the type of memory
+/// * `quirks` - the workarounds needed on the firmware
+fn memory_type(ty: MemoryType, quirks: Quirks) -> u64 {
+  match ty {
+    MemoryType::BOOT_SERVICES_CODE | MemoryType::BOOT_SERVICES_DATA
+      if quirks.keep_boot_services_memory =>
+    {
+      MEMORY_RESERVED
+    }
+    MemoryType::CONVENTIONAL
+    | MemoryType::BOOT_SERVICES_CODE
+    | MemoryType::BOOT_SERVICES_DATA => MEMORY_USABLE,
+    MemoryType::LOADER_CODE | MemoryType::LOADER_DATA => {
+      MEMORY_BOOTLOADER_RECLAIMABLE
+    }
+    MemoryType::ACPI_RECLAIM => MEMORY_ACPI_RECLAIMABLE,
+    MemoryType::ACPI_NON_VOLATILE => MEMORY_ACPI_NVS,
+    MemoryType::UNUSABLE => MEMORY_BAD,
+    _ => MEMORY_RESERVED,
+  }
+}
+
+/// Returns the address of `value` in the HHDM.
+///
+/// # Arguments
+///
+/// * `value` - a value in identity-mapped memory
+fn hhdm<T>(value: *const T) -> u64 {
+  HHDM_OFFSET + value as u64
+}
+
+fn write_u64(data: &mut [u8], offset: usize, value: u64) {
+  data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Reads the model-specific register `msr`.
+///
+/// # Arguments
+///
+/// * `msr` - the register to read
+///
+/// # Safety
+///
+/// The register must exist.
+unsafe fn rdmsr(msr: u32) -> u64 {
+  let (low, high): (u32, u32);
+  core::arch::asm!(
+    "rdmsr",
+    in("ecx") msr,
+    out("eax") low,
+    out("edx") high,
+    options(nomem, nostack, preserves_flags),
+  );
+  (high as u64) << 32 | low as u64
+}
+
+/// Writes `value` to the model-specific register `msr`.
+///
+/// # Arguments
+///
+/// * `msr` - the register to write
+/// * `value` - the value to write
+///
+/// # Safety
+///
+/// The register must exist, and writing it must not break any assumptions
+/// of the running code.
+unsafe fn wrmsr(msr: u32, value: u64) {
+  core::arch::asm!(
+    "wrmsr",
+    in("ecx") msr,
+    in("eax") value as u32,
+    in("edx") (value >> 32) as u32,
+    options(nostack, preserves_flags),
+  );
+}
+
+extern "C" {
+  static limine_trampoline: u8;
+  static limine_trampoline_end: u8;
+}
+
+// The trampoline that other processors are started at in real mode, from a
+// page below 1 MiB. It is position-independent, and begins with parameters
+// written by the processor starting it:
+//
+// * offset 8 - the physical address of the root page table
+// * offset 16 - the top of the processor's stack
+// * offset 24 - the processor's `SmpInfo`
+// * offset 32 - set once the parameters have been read
+//
+// It enables paging through the copy of the root table in the page following
+// it, enters long mode directly, switches to the real root table, then waits
+// for the kernel to write the address to jump to into the `SmpInfo`.
+core::arch::global_asm!(
+  ".global limine_trampoline",
+  ".global limine_trampoline_end",
+  ".balign 16",
+  ".code16",
+  "limine_trampoline:",
+  "cli",
+  "jmp limine_trampoline_start",
+  ".balign 8",
+  // The parameters.
+  ".quad 0",
+  ".quad 0",
+  ".quad 0",
+  ".quad 0",
+  "limine_trampoline_gdtr:",
+  ".word 23",
+  ".long 0",
+  ".word 0",
+  "limine_trampoline_far:",
+  ".long 0",
+  ".word 0x08",
+  ".word 0",
+  "limine_trampoline_gdt:",
+  ".quad 0",
+  // Flat 64-bit code and data segments.
+  ".quad 0x00af9a000000ffff",
+  ".quad 0x00cf92000000ffff",
+  "limine_trampoline_start:",
+  ".set limine_gdt_offset, limine_trampoline_gdt - limine_trampoline",
+  ".set limine_gdtr_offset, limine_trampoline_gdtr - limine_trampoline",
+  ".set limine_far_offset, limine_trampoline_far - limine_trampoline",
+  ".set limine_long_offset, limine_trampoline_long - limine_trampoline",
+  "cld",
+  "mov ax, cs",
+  "mov ds, ax",
+  "xor ebx, ebx",
+  "mov bx, ax",
+  "shl ebx, 4",
+  // Point the GDT descriptor and the far pointer at the linear addresses of
+  // their targets, which depend on where the trampoline was placed.
+  "lea eax, [ebx + limine_gdt_offset]",
+  "mov dword ptr [limine_gdtr_offset + 2], eax",
+  "lea eax, [ebx + limine_long_offset]",
+  "mov dword ptr [limine_far_offset], eax",
+  "lgdt [limine_gdtr_offset]",
+  // Enable PAE, and SSE as on the processor starting this one.
+  "mov eax, cr4",
+  "or eax, 0x620",
+  "mov cr4, eax",
+  "lea eax, [ebx + 0x1000]",
+  "mov cr3, eax",
+  // Enable long mode and the no-execute bit.
+  "mov ecx, 0xc0000080",
+  "rdmsr",
+  "or eax, 0x900",
+  "wrmsr",
+  // Enable caching, the FPU, protected mode, and paging.
+  "mov eax, cr0",
+  "and eax, 0x9ffffffb",
+  "or eax, 0x80000023",
+  "mov cr0, eax",
+  // A far jump through the 32-bit far pointer, `jmp fword ptr [...]`, which
+  // the assembler does not encode correctly in 16-bit code.
+  ".byte 0x66, 0xff, 0x2e",
+  ".word limine_far_offset",
+  ".code64",
+  "limine_trampoline_long:",
+  "mov ax, 0x10",
+  "mov ds, ax",
+  "mov es, ax",
+  "mov ss, ax",
+  "xor eax, eax",
+  "mov fs, ax",
+  "mov gs, ax",
+  "mov ebx, ebx",
+  "mov rax, [rbx + 8]",
+  "mov cr3, rax",
+  "mov rsp, [rbx + 16]",
+  "mov rdi, [rbx + 24]",
+  "mov qword ptr [rbx + 32], 1",
+  // Wait for the `goto_address` of the `SmpInfo`.
+  "2:",
+  "pause",
+  "mov rax, [rdi + 16]",
+  "test rax, rax",
+  "jz 2b",
+  // Jump with a null return address, and with all general purpose registers
+  // other than the `SmpInfo` argument cleared.
+  "push 0",
+  "push rax",
+  "xor eax, eax",
+  "xor ebx, ebx",
+  "xor ecx, ecx",
+  "xor edx, edx",
+  "xor esi, esi",
+  "xor ebp, ebp",
+  "xor r8d, r8d",
+  "xor r9d, r9d",
+  "xor r10d, r10d",
+  "xor r11d, r11d",
+  "xor r12d, r12d",
+  "xor r13d, r13d",
+  "xor r14d, r14d",
+  "xor r15d, r15d",
+  "ret",
+  "limine_trampoline_end:",
+);
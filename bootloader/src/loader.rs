@@ -0,0 +1,329 @@
+//! This module provides the loading of boot payloads, such as the kernel and
+//! initrd, into memory from wherever they are stored.
+//!
+//! Payloads may be stored compressed, in which case they are decompressed
+//! after being read; digests always apply to the decompressed payload.
+
+use crate::error::status_of;
+use crate::lz4;
+use core::ops::{Deref, DerefMut};
+use crypto::{sha256, Hasher};
+use kcore::gzip;
+use uefi::table::boot::{AllocateType, BootServices, MemoryType};
+use uefi::Status;
+
+/// The size of a page, as used by the firmware's page allocator.
+pub const PAGE_SIZE: usize = 4096;
+
+/// The number of bytes that are read or hashed between reports of progress.
+pub const PROGRESS_CHUNK: usize = 1 << 20;
+
+/// A step of loading a file that progress is reported for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+  /// The file is being read from its source.
+  Read,
+
+  /// The file is being hashed, and checked against its expected digest.
+  Verify,
+}
+
+/// A receiver of reports of the progress made while loading a file.
+pub trait Progress {
+  /// Reports that `done` of the `total` bytes of `step` have been processed.
+  ///
+  /// # Arguments
+  ///
+  /// * `step` - the step that progress was made in
+  /// * `done` - the number of bytes processed so far
+  /// * `total` - the number of bytes to process in all
+  fn report(&mut self, step: Step, done: usize, total: usize);
+}
+
+/// Progress is not reported anywhere.
+impl Progress for () {
+  fn report(&mut self, _: Step, _: usize, _: usize) {}
+}
+
+/// A location that boot payloads may be read from, such as the boot volume or
+/// a network server.
+pub trait Source {
+  /// Reads the entire contents of the file at `path` into newly allocated
+  /// memory.
+  ///
+  /// Paths are always written in the style of the boot volume, with `\` as the
+  /// separator; sources are responsible for translating them as needed.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `path` - the path of the file to read
+  /// * `progress` - the receiver of progress reports for [`Step::Read`]
+  fn read(
+    &mut self,
+    bs: &BootServices,
+    path: &str,
+    progress: &mut dyn Progress,
+  ) -> uefi::Result<Buffer>;
+
+  /// Reads the file at `path` as [`read`](Self::read) does, along with the
+  /// SHA256 digest of what was read, if the source hashes the file as it
+  /// arrives.
+  ///
+  /// Sources that do not hash files as they read them return no digest,
+  /// which is the default, and the file is hashed once it has been read.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `path` - the path of the file to read
+  /// * `progress` - the receiver of progress reports for [`Step::Read`]
+  fn read_hashed(
+    &mut self,
+    bs: &BootServices,
+    path: &str,
+    progress: &mut dyn Progress,
+  ) -> uefi::Result<(Buffer, Option<sha256::Digest>)> {
+    Ok((self.read(bs, path, progress)?, None))
+  }
+}
+
+/// A file that has been loaded into memory and hashed.
+pub struct LoadedFile {
+  /// The contents of the file.
+  pub data: &'static mut [u8],
+
+  /// The SHA256 digest of [`LoadedFile::data`].
+  pub digest: sha256::Digest,
+}
+
+/// A page-aligned buffer allocated by [`allocate_buffer`], which records the
+/// number of pages allocated for it so that they are all freed, even once the
+/// buffer has been [truncated](Buffer::truncate).
+///
+/// A buffer that is dropped, rather than given to [`free_buffer`], stays
+/// allocated for the rest of boot services.
+pub struct Buffer {
+  data: &'static mut [u8],
+  pages: usize,
+}
+
+impl Buffer {
+  /// Returns an empty buffer, which has no pages allocated for it.
+  pub fn empty() -> Self {
+    Self {
+      data: &mut [],
+      pages: 0,
+    }
+  }
+
+  /// Takes back the contents of a buffer that was [leaked](Buffer::leak), so
+  /// that it can be freed.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the contents of the leaked buffer
+  ///
+  /// # Safety
+  ///
+  /// `data` must be the whole of what a buffer that was never truncated
+  /// leaked, and must not be referred to once the buffer is freed.
+  pub unsafe fn from_leaked(data: &'static mut [u8]) -> Self {
+    let pages = (data.len() + PAGE_SIZE - 1) / PAGE_SIZE;
+    Self { data, pages }
+  }
+
+  /// Shortens the buffer to `len` bytes, keeping every page allocated for it.
+  /// Has no effect if the buffer is no longer than `len`.
+  ///
+  /// # Arguments
+  ///
+  /// * `len` - the new length of the buffer
+  pub fn truncate(&mut self, len: usize) {
+    if len < self.data.len() {
+      let data = core::mem::take(&mut self.data);
+      self.data = &mut data[..len];
+    }
+  }
+
+  /// Returns the contents of the buffer, which then stays allocated for the
+  /// rest of boot services.
+  pub fn leak(self) -> &'static mut [u8] {
+    self.data
+  }
+}
+
+impl Deref for Buffer {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    self.data
+  }
+}
+
+impl DerefMut for Buffer {
+  fn deref_mut(&mut self) -> &mut [u8] {
+    self.data
+  }
+}
+
+/// Allocates a page-aligned buffer of at least `size` bytes from memory of
+/// type [`MemoryType::LOADER_DATA`].
+///
+/// The buffer stays allocated until it is given to [`free_buffer`], or for
+/// the rest of boot services if it never is.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `size` - the number of bytes to allocate
+pub fn allocate_buffer(bs: &BootServices, size: usize) -> uefi::Result<Buffer> {
+  if size == 0 {
+    return Ok(Buffer::empty());
+  }
+  let pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+  let address =
+    bs.allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, pages)?;
+
+  // SAFETY: the firmware returned a unique allocation of `pages` pages, which
+  // is at least `size` bytes long.
+  let data =
+    unsafe { core::slice::from_raw_parts_mut(address as *mut u8, size) };
+  Ok(Buffer { data, pages })
+}
+
+/// Frees a buffer previously returned by [`allocate_buffer`], freeing every
+/// page that was allocated for it.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `buffer` - the buffer to free
+pub fn free_buffer(bs: &BootServices, buffer: Buffer) -> uefi::Result {
+  if buffer.pages == 0 {
+    return Ok(());
+  }
+  bs.free_pages(buffer.data.as_ptr() as u64, buffer.pages)
+}
+
+/// Decompresses the already-read file `data` into newly allocated memory if
+/// it is LZ4 or gzip compressed, freeing the compressed copy; otherwise
+/// returns `data` unchanged.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `data` - the contents of the file
+pub fn unpack(bs: &BootServices, data: Buffer) -> uefi::Result<Buffer> {
+  type Decompress = fn(&[u8], &mut [u8]) -> uefi::Result<usize>;
+  let (len, decompress): (usize, Decompress) = if lz4::is_lz4(&data) {
+    (lz4::decompressed_len(&data)?, lz4::decompress)
+  } else if gzip::is_gzip(&data) {
+    let len = gzip::decompressed_len(&data).map_err(status_of)?;
+    (len, |data, output| {
+      gzip::decompress(data, output).map_err(|err| status_of(err).into())
+    })
+  } else {
+    return Ok(data);
+  };
+
+  let mut output = allocate_buffer(bs, len)?;
+  let len = match decompress(&data, &mut output) {
+    Ok(len) => len,
+    Err(err) => {
+      let _ = free_buffer(bs, output);
+      return Err(err);
+    }
+  };
+  free_buffer(bs, data)?;
+  output.truncate(len);
+  Ok(output)
+}
+
+/// Loads the file at `path` from `source`, decompressing it if needed, and
+/// hashes its contents, as they are read if the source can.
+///
+/// If an `expected` digest is given, the load fails with
+/// [`Status::SECURITY_VIOLATION`] when the file does not match it.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `source` - the source to read the file from
+/// * `path` - the path of the file to read
+/// * `expected` - the digest the file is required to have, if any
+/// * `progress` - the receiver of progress reports
+pub fn load(
+  bs: &BootServices,
+  source: &mut dyn Source,
+  path: &str,
+  expected: Option<&sha256::Digest>,
+  progress: &mut dyn Progress,
+) -> uefi::Result<LoadedFile> {
+  let (data, digest) = source.read_hashed(bs, path, progress)?;
+  match digest {
+    // The digest of what was read is only that of the payload when the file
+    // was not compressed.
+    Some(digest) if !is_compressed(&data) => {
+      check(data.leak(), digest, expected)
+    }
+    _ => verify(unpack(bs, data)?.leak(), expected, progress),
+  }
+}
+
+/// Hashes the already-read file `data`, and checks it against the `expected`
+/// digest if one is given.
+///
+/// Fails with [`Status::SECURITY_VIOLATION`] when the digests do not match.
+///
+/// # Arguments
+///
+/// * `data` - the contents of the file
+/// * `expected` - the digest the file is required to have, if any
+/// * `progress` - the receiver of progress reports for [`Step::Verify`]
+pub fn verify(
+  data: &'static mut [u8],
+  expected: Option<&sha256::Digest>,
+  progress: &mut dyn Progress,
+) -> uefi::Result<LoadedFile> {
+  let mut hasher = sha256::SHA256::new();
+  progress.report(Step::Verify, 0, data.len());
+  for (i, chunk) in data.chunks(PROGRESS_CHUNK).enumerate() {
+    hasher.update(chunk);
+    let done = i * PROGRESS_CHUNK + chunk.len();
+    progress.report(Step::Verify, done, data.len());
+  }
+  check(data, hasher.digest(), expected)
+}
+
+/// Returns whether the already-read file `data` is compressed, and so is
+/// decompressed by [`unpack`].
+///
+/// # Arguments
+///
+/// * `data` - the contents of the file
+fn is_compressed(data: &[u8]) -> bool {
+  lz4::is_lz4(data) || gzip::is_gzip(data)
+}
+
+/// Checks the `digest` of the file `data` against the `expected` digest if
+/// one is given, failing with [`Status::SECURITY_VIOLATION`] when they do not
+/// match.
+///
+/// # Arguments
+///
+/// * `data` - the contents of the file
+/// * `digest` - the digest of `data`
+/// * `expected` - the digest the file is required to have, if any
+fn check(
+  data: &'static mut [u8],
+  digest: sha256::Digest,
+  expected: Option<&sha256::Digest>,
+) -> uefi::Result<LoadedFile> {
+  match expected {
+    Some(expected) if *expected != digest => {
+      Err(Status::SECURITY_VIOLATION.into())
+    }
+    _ => Ok(LoadedFile { data, digest }),
+  }
+}
@@ -0,0 +1,294 @@
+//! This module provides the bootloader's log, which records everything
+//! written to it, a line at a time, in the early boot log handed to the
+//! kernel, and echoes it to the console as the verbosity allows.
+//!
+//! Echoed text is drawn by the [boot console](crate::console) when there is a
+//! graphics output, and is otherwise written to the firmware's text console.
+//!
+//! The early boot log is placed in loader data, so that it is identity-mapped
+//! for the kernel, and is described by [`bootinfo::log`]. When it cannot be
+//! allocated, output still reaches the console, and the kernel is handed no
+//! log. Everything is recorded whatever the verbosity, which only filters
+//! what is echoed.
+//!
+//! The log is a [`Sink`], so that it is written to with the macros of
+//! [`kcore::log`]. Errors and warnings are marked as such, and records below
+//! [`Level::Info`] are details, which are only echoed when booting verbosely.
+
+use crate::config::Verbosity;
+use crate::console::Console;
+use crate::keyboard::Keyboard;
+use crate::loader;
+use bootinfo::log::{Log, LogWriter};
+use core::fmt::{self, Write};
+use kcore::log::{Level, Record, Sink};
+use uefi::proto::console::serial::Serial;
+use uefi::proto::console::text::{Key, Output};
+use uefi::table::boot::{
+  BootServices, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol,
+};
+use uefi::table::{Boot, SystemTable};
+use uefi::Handle;
+
+/// The size of the early boot log, including its header, in bytes.
+const LOG_SIZE: usize = 64 * 1024;
+
+/// The maximum length of a record, in bytes. Longer lines are split across
+/// records, and empty lines are not recorded.
+const LINE_SIZE: usize = 256;
+
+/// The key that, held at startup, boots verbosely.
+const VERBOSE_KEY: char = 'v';
+
+/// The key that, held at startup, boots quietly.
+const QUIET_KEY: char = 's';
+
+/// The bootloader's log.
+pub struct Logger<'a> {
+  stdout: &'a mut Output,
+  console: Option<Console<'a>>,
+  serial: Option<ScopedProtocol<'a, Serial>>,
+  ring: Option<LogWriter<'static>>,
+  log: Log,
+  verbosity: Verbosity,
+
+  /// The line being written, which is recorded once it is complete.
+  line: [u8; LINE_SIZE],
+  len: usize,
+}
+
+impl<'a> Logger<'a> {
+  /// Constructs a [`Logger`] that echoes to the boot console, or to `stdout`
+  /// if there is no graphics output to draw it on, with
+  /// [`Verbosity::Normal`], allocating the early boot log to record to.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  /// * `stdout` - the console to echo to
+  pub fn new(
+    bs: &'a BootServices,
+    image: Handle,
+    stdout: &'a mut Output,
+  ) -> Self {
+    let (ring, log) = match loader::allocate_buffer(bs, LOG_SIZE) {
+      Ok(buffer) => {
+        let buffer = buffer.leak();
+        let log = Log {
+          address: buffer.as_ptr() as u64,
+          size: buffer.len() as u64,
+        };
+        match LogWriter::new(buffer) {
+          Some(ring) => (Some(ring), log),
+          None => (None, Log::NONE),
+        }
+      }
+      Err(_) => (None, Log::NONE),
+    };
+    Self {
+      stdout,
+      console: Console::open(bs, image).ok(),
+      serial: open_serial(bs, image).ok(),
+      ring,
+      log,
+      verbosity: Verbosity::Normal,
+      line: [0; LINE_SIZE],
+      len: 0,
+    }
+  }
+
+  /// Returns the verbosity that the log echoes with.
+  pub fn verbosity(&self) -> Verbosity {
+    self.verbosity
+  }
+
+  /// Sets the verbosity that the log echoes with from now on.
+  ///
+  /// # Arguments
+  ///
+  /// * `verbosity` - the verbosity
+  pub fn set_verbosity(&mut self, verbosity: Verbosity) {
+    self.verbosity = verbosity;
+  }
+
+  /// Returns the console that the log echoes to, for output that is not meant
+  /// to be recorded, such as progress bars.
+  pub fn stdout(&mut self) -> &mut Output {
+    self.stdout
+  }
+
+  /// Returns the description of the early boot log to hand to the kernel.
+  ///
+  /// The kernel reads the log in place, so lines completed after this is
+  /// called are handed to it as well.
+  pub fn describe(&self) -> Log {
+    self.log
+  }
+
+  /// Returns the records of the early boot log, from oldest to newest.
+  pub fn records(&self) -> impl Iterator<Item = &[u8]> + Clone {
+    self.ring.iter().flat_map(LogWriter::records)
+  }
+
+  /// Records the line being written, if any.
+  fn record(&mut self) {
+    if self.len == 0 {
+      return;
+    }
+    if let Some(ring) = &mut self.ring {
+      ring.push(&self.line[..self.len]);
+    }
+    self.len = 0;
+  }
+
+  /// Records `s`, and echoes it if the verbosity is at least `level`.
+  ///
+  /// # Arguments
+  ///
+  /// * `s` - the text to write
+  /// * `level` - the least verbosity that the text is echoed with
+  fn write(&mut self, s: &str, level: Verbosity) -> fmt::Result {
+    for c in s.chars() {
+      match c {
+        '\n' => self.record(),
+        '\r' => {}
+        _ => {
+          // Lines are split between characters, so that every record is
+          // valid UTF-8.
+          if self.len + c.len_utf8() > LINE_SIZE {
+            self.record();
+          }
+          c.encode_utf8(&mut self.line[self.len..]);
+          self.len += c.len_utf8();
+        }
+      }
+    }
+    if self.verbosity < level {
+      return Ok(());
+    }
+    // The firmware's text console usually echoes to the serial port itself,
+    // but the boot console does not.
+    if self.console.is_some() || self.verbosity == Verbosity::Verbose {
+      if let Some(serial) = &mut self.serial {
+        let _ = SerialWriter(serial).write_str(s);
+      }
+    }
+    match &mut self.console {
+      Some(console) => console.write_str(s),
+      None => self.stdout.write_str(s),
+    }
+  }
+}
+
+impl Drop for Logger<'_> {
+  fn drop(&mut self) {
+    // Whatever is written to the firmware's text console afterwards, such as
+    // a failure, is written below the log rather than over it.
+    let Some(console) = &self.console else {
+      return;
+    };
+    if let Ok(Some(mode)) = self.stdout.current_mode() {
+      let row = console.rows_covered(mode.rows());
+      let _ = self.stdout.set_cursor_position(0, row.min(mode.rows() - 1));
+    }
+  }
+}
+
+impl fmt::Write for Logger<'_> {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    self.write(s, Verbosity::Normal)
+  }
+}
+
+impl Sink for Logger<'_> {
+  fn log(&mut self, record: &Record<'_>) {
+    let (prefix, level) = match record.level {
+      Level::Error => ("error: ", Verbosity::Normal),
+      Level::Warn => ("warning: ", Verbosity::Normal),
+      Level::Info => ("", Verbosity::Normal),
+      Level::Debug | Level::Trace => ("", Verbosity::Verbose),
+    };
+    let _ = writeln!(Echoed(self, level), "{}{}", prefix, record.args);
+  }
+}
+
+/// A writer to the log of text that is echoed only if the verbosity is at
+/// least the one it holds.
+struct Echoed<'l, 'a>(&'l mut Logger<'a>, Verbosity);
+
+impl fmt::Write for Echoed<'_, '_> {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    self.0.write(s, self.1)
+  }
+}
+
+/// An adapter for writing formatted text to a serial port, with line endings
+/// translated for terminals.
+pub struct SerialWriter<'a>(pub &'a mut Serial);
+
+impl fmt::Write for SerialWriter<'_> {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    for (i, line) in s.split('\n').enumerate() {
+      if i != 0 {
+        self.0.write(b"\r\n").map_err(|_| fmt::Error)?;
+      }
+      self.0.write(line.as_bytes()).map_err(|_| fmt::Error)?;
+    }
+    Ok(())
+  }
+}
+
+/// Opens the first serial port, if there is one.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+pub fn open_serial(
+  bs: &BootServices,
+  image: Handle,
+) -> uefi::Result<ScopedProtocol<'_, Serial>> {
+  let params = OpenProtocolParams {
+    handle: bs.get_handle_for_protocol::<Serial>()?,
+    agent: image,
+    controller: None,
+  };
+  // SAFETY: the serial port may be held open by the console driver, so it
+  // cannot be opened exclusively. It is only written to, never reconfigured.
+  unsafe {
+    bs.open_protocol::<Serial>(params, OpenProtocolAttributes::GetProtocol)
+  }
+}
+
+/// Returns the verbosity chosen by a key held down at startup, if any:
+/// [`VERBOSE_KEY`] for [`Verbosity::Verbose`], or [`QUIET_KEY`] for
+/// [`Verbosity::Quiet`].
+///
+/// A held key is seen as the presses queued by it repeating, all of which are
+/// consumed.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `console` - the system table to access the console through
+pub fn held_verbosity(
+  bs: &BootServices,
+  image: Handle,
+  console: &mut SystemTable<Boot>,
+) -> Option<Verbosity> {
+  let mut keyboard = Keyboard::open(bs, image, console.stdin());
+  let mut verbosity = None;
+  while let Ok(Some(press)) = keyboard.read(console.stdin()) {
+    verbosity = match press.key {
+      Key::Printable(c) => match char::from(c).to_ascii_lowercase() {
+        VERBOSE_KEY => Some(Verbosity::Verbose),
+        QUIET_KEY => Some(Verbosity::Quiet),
+        _ => verbosity,
+      },
+      Key::Special(_) => verbosity,
+    };
+  }
+  verbosity
+}
@@ -0,0 +1,249 @@
+//! This module provides decompression of payloads stored in the LZ4 frame
+//! format, as produced by the `lz4` command-line tool.
+//!
+//! Frame and block checksums are skipped rather than verified; the integrity
+//! of the decompressed payload is instead established by its SHA256 digest.
+//! Frames that require an external dictionary are not supported.
+
+use uefi::Status;
+
+/// The magic number that every LZ4 frame begins with.
+pub const MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+
+/// The frame descriptor flag indicating each block is followed by a checksum.
+const FLAG_BLOCK_CHECKSUM: u8 = 1 << 4;
+
+/// The frame descriptor flag indicating the header holds the content size.
+const FLAG_CONTENT_SIZE: u8 = 1 << 3;
+
+/// The frame descriptor flag indicating the frame ends with a checksum.
+const FLAG_CONTENT_CHECKSUM: u8 = 1 << 2;
+
+/// The frame descriptor flag indicating a dictionary is required.
+const FLAG_DICTIONARY_ID: u8 = 1 << 0;
+
+/// The bit of a block size marking the block as stored uncompressed.
+const BLOCK_UNCOMPRESSED: u32 = 1 << 31;
+
+/// The shortest match that can be encoded.
+const MIN_MATCH: usize = 4;
+
+/// Returns `true` if `data` begins with an LZ4 frame.
+///
+/// # Arguments
+///
+/// * `data` - the data to check
+pub fn is_lz4(data: &[u8]) -> bool {
+  data.starts_with(&MAGIC)
+}
+
+/// Returns the number of bytes that the LZ4 frame in `data` decompresses to.
+///
+/// This is read from the frame header if present, and otherwise computed by
+/// walking the frame without producing any output.
+///
+/// # Arguments
+///
+/// * `data` - the LZ4 frame
+pub fn decompressed_len(data: &[u8]) -> uefi::Result<usize> {
+  let frame = Frame::parse(data)?;
+  if let Some(len) = frame.content_size {
+    return usize::try_from(len).map_err(|_| Status::BAD_BUFFER_SIZE.into());
+  }
+  frame.decode(None)
+}
+
+/// Decompresses the LZ4 frame in `data` into `output`, returning the number of
+/// bytes written.
+///
+/// Fails with [`Status::LOAD_ERROR`] if the frame is malformed, or if it does
+/// not fit in `output`.
+///
+/// # Arguments
+///
+/// * `data` - the LZ4 frame
+/// * `output` - the buffer to decompress into
+pub fn decompress(data: &[u8], output: &mut [u8]) -> uefi::Result<usize> {
+  Frame::parse(data)?.decode(Some(output))
+}
+
+/// The parsed header of an LZ4 frame.
+struct Frame<'a> {
+  flags: u8,
+  content_size: Option<u64>,
+  blocks: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+  /// Parses the header of the LZ4 frame in `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the LZ4 frame
+  fn parse(data: &'a [u8]) -> uefi::Result<Self> {
+    if !is_lz4(data) {
+      return Err(Status::LOAD_ERROR.into());
+    }
+    let flags = *data.get(4).ok_or(Status::LOAD_ERROR)?;
+    if flags >> 6 != 0b01 {
+      return Err(Status::UNSUPPORTED.into());
+    }
+    if flags & FLAG_DICTIONARY_ID != 0 {
+      return Err(Status::UNSUPPORTED.into());
+    }
+
+    // The descriptor is the flags, the block size byte, the optional content
+    // size, and a header checksum.
+    let mut offset = 6;
+    let content_size = if flags & FLAG_CONTENT_SIZE != 0 {
+      let bytes = data.get(offset..offset + 8).ok_or(Status::LOAD_ERROR)?;
+      offset += 8;
+      let mut size = [0; 8];
+      size.copy_from_slice(bytes);
+      Some(u64::from_le_bytes(size))
+    } else {
+      None
+    };
+    offset += 1;
+
+    Ok(Self {
+      flags,
+      content_size,
+      blocks: data.get(offset..).ok_or(Status::LOAD_ERROR)?,
+    })
+  }
+
+  /// Decodes every block of the frame into `output`, or only measures their
+  /// decoded length if there is no `output`.
+  ///
+  /// Blocks are decoded into one contiguous buffer, so blocks that reference
+  /// the data of earlier blocks are decoded correctly.
+  ///
+  /// # Arguments
+  ///
+  /// * `output` - the buffer to decompress into, if any
+  fn decode(&self, mut output: Option<&mut [u8]>) -> uefi::Result<usize> {
+    let checksum_len = if self.flags & FLAG_BLOCK_CHECKSUM != 0 {
+      4
+    } else {
+      0
+    };
+
+    let mut input = self.blocks;
+    let mut len = 0;
+    loop {
+      let header = input.get(..4).ok_or(Status::LOAD_ERROR)?;
+      let size =
+        u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+      input = &input[4..];
+      if size == 0 {
+        break;
+      }
+
+      let block_len = (size & !BLOCK_UNCOMPRESSED) as usize;
+      let block = input.get(..block_len).ok_or(Status::LOAD_ERROR)?;
+      len = if size & BLOCK_UNCOMPRESSED != 0 {
+        let end = len + block.len();
+        if let Some(output) = output.as_deref_mut() {
+          output
+            .get_mut(len..end)
+            .ok_or(Status::LOAD_ERROR)?
+            .copy_from_slice(block);
+        }
+        end
+      } else {
+        decode_block(block, output.as_deref_mut(), len)?
+      };
+      input = input
+        .get(block_len + checksum_len..)
+        .ok_or(Status::LOAD_ERROR)?;
+    }
+
+    if self.flags & FLAG_CONTENT_CHECKSUM != 0 && input.len() < 4 {
+      return Err(Status::LOAD_ERROR.into());
+    }
+    match self.content_size {
+      Some(size) if size != len as u64 => Err(Status::LOAD_ERROR.into()),
+      _ => Ok(len),
+    }
+  }
+}
+
+/// Decodes the compressed LZ4 `block` into `output` at offset `start`, or only
+/// measures its decoded length if there is no `output`. Returns the offset
+/// that the block's output ends at.
+///
+/// # Arguments
+///
+/// * `block` - the compressed block
+/// * `output` - the buffer to decompress into, if any
+/// * `start` - the offset in `output` that the block begins at
+fn decode_block(
+  mut block: &[u8],
+  mut output: Option<&mut [u8]>,
+  start: usize,
+) -> uefi::Result<usize> {
+  let mut position = start;
+  loop {
+    let token = *block.first().ok_or(Status::LOAD_ERROR)?;
+    block = &block[1..];
+
+    let literals = read_length(&mut block, (token >> 4) as usize)?;
+    let bytes = block.get(..literals).ok_or(Status::LOAD_ERROR)?;
+    if let Some(output) = output.as_deref_mut() {
+      output
+        .get_mut(position..position + literals)
+        .ok_or(Status::LOAD_ERROR)?
+        .copy_from_slice(bytes);
+    }
+    block = &block[literals..];
+    position += literals;
+
+    // The last sequence of a block holds only literals.
+    if block.is_empty() {
+      return Ok(position);
+    }
+
+    let offset = block.get(..2).ok_or(Status::LOAD_ERROR)?;
+    let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+    block = &block[2..];
+    let len = read_length(&mut block, (token & 0xf) as usize)? + MIN_MATCH;
+    if offset == 0 || offset > position {
+      return Err(Status::LOAD_ERROR.into());
+    }
+
+    if let Some(output) = output.as_deref_mut() {
+      if position + len > output.len() {
+        return Err(Status::LOAD_ERROR.into());
+      }
+      // Matches may overlap the bytes they produce, so they are copied one
+      // byte at a time.
+      for i in position..position + len {
+        output[i] = output[i - offset];
+      }
+    }
+    position += len;
+  }
+}
+
+/// Reads a length that starts with the 4-bit `nibble` from a token, followed
+/// by extension bytes in `block` if the nibble is saturated.
+///
+/// # Arguments
+///
+/// * `block` - the remainder of the block, advanced past any extension bytes
+/// * `nibble` - the length held in the token
+fn read_length(block: &mut &[u8], nibble: usize) -> uefi::Result<usize> {
+  let mut len = nibble;
+  if nibble == 0xf {
+    loop {
+      let byte = *block.first().ok_or(Status::LOAD_ERROR)?;
+      *block = &block[1..];
+      len = len.checked_add(byte as usize).ok_or(Status::LOAD_ERROR)?;
+      if byte != 0xff {
+        break;
+      }
+    }
+  }
+  Ok(len)
+}
@@ -1,15 +1,73 @@
 #![no_std]
 #![no_main]
+// Self-test builds never boot, which leaves most of the bootloader unused.
+#![cfg_attr(feature = "selftest", allow(dead_code))]
 
+mod acpi;
+mod archive;
+mod blockio;
+mod bootlog;
+mod config;
+mod console;
+mod efi;
+mod elf;
+mod entropy;
+mod error;
+mod ext2;
+mod fdt;
+mod firmware;
+mod fs;
+mod gpt;
+mod handoff;
+mod iso9660;
+mod keyboard;
+#[cfg(target_arch = "x86_64")]
+mod limine;
+mod loader;
+mod log;
+mod lz4;
+mod memtest;
+mod menu;
+#[cfg(target_arch = "x86_64")]
+mod multiboot2;
+mod net;
+mod paging;
+mod panic;
+mod progress;
+#[cfg(feature = "selftest")]
+mod selftest;
+mod shell;
+mod timing;
+mod ucs2;
+mod watchdog;
+
+use core::fmt::Write;
+
+use blockio::{BlockReader, LbaRanges};
+use bootinfo::log::Log;
+use bootinfo::{BootPhase, PhysRange};
+use config::{BootMode, Config, Verbosity};
+use crypto::sha256;
+use error::{status_of, Context, Error, Phase};
+use ext2::Ext2;
+use fdt::DeviceTree;
+use iso9660::Iso9660;
+use kcore::{info, warn};
+use loader::{LoadedFile, Source};
+use log::Logger;
+use net::TftpSource;
+use progress::ProgressBar;
+use timing::{Stamped, Timeline};
+use ucs2::Ucs2Str;
+use uefi::table::boot::BootServices;
 use uefi::table::{Boot, SystemTable};
-use uefi::{cstr16, entry, Handle, Status};
+use uefi::{entry, Handle, Status};
 
-#[panic_handler]
-fn panic(_: &core::panic::PanicInfo) -> ! {
-  arch::halt()
-}
+/// How the bootloader was built, which is printed at startup and handed to
+/// the kernel.
+const BUILD: kcore::version::BuildInfo = kcore::build_info!();
 
-const BOOT_SPLASH: &'static uefi::CStr16 = cstr16!(
+const BOOT_SPLASH: &Ucs2Str = ucs2!(
   r"______                _    _                    _
 | ___ \              | |  | |                  | |
 | |_/ /  ___    ___  | |_ | |  ___    __ _   __| |  ___  _ __
@@ -19,14 +77,765 @@ const BOOT_SPLASH: &'static uefi::CStr16 = cstr16!(
 "
 );
 
-#[entry]
-fn uefi_main(image: Handle, mut system_table: SystemTable<Boot>) -> Status {
-  let stdout = system_table.stdout();
-  if let Err(err) = stdout.output_string(BOOT_SPLASH) {
-    return err.status();
+/// The boot configuration, and the payloads it says to load: the kernel, the
+/// initrd if any, the descriptions of any additional modules, and the device
+/// tree with any overlays applied, if the firmware provided one.
+type Payloads = (
+  Config<'static>,
+  LoadedFile,
+  Option<LoadedFile>,
+  &'static [bootinfo::Module],
+  Option<&'static [u8]>,
+);
+
+/// Loads the boot configuration from `source`.
+///
+/// A missing or malformed configuration is not fatal; the problem is reported
+/// and the default configuration is used instead.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `source` - the source to read the configuration from
+/// * `log` - the log to report problems to
+fn load_config(
+  bs: &BootServices,
+  source: &mut dyn Source,
+  log: &mut Logger,
+) -> Config<'static> {
+  let bytes = match source.read(bs, config::CONFIG_PATH, &mut ()) {
+    Ok(bytes) => bytes.leak(),
+    Err(_) => return Config::new(),
+  };
+  let text = match core::str::from_utf8(bytes) {
+    Ok(text) => text,
+    Err(err) => {
+      warn!(
+        logger: log,
+        "{} is not UTF-8 past byte {}; using defaults",
+        config::CONFIG_PATH,
+        err.valid_up_to()
+      );
+      return Config::new();
+    }
+  };
+
+  Config::parse(text).unwrap_or_else(|err| {
+    warn!(logger: log, "{}; using defaults", err);
+    Config::new()
+  })
+}
+
+/// Loads the payload `name` from `path` on `source`, showing its progress and
+/// reporting the outcome.
+///
+/// If the payload has no path, or cannot be read from `source`, it is instead
+/// read from the blocks of the boot disk given by `ranges`, if any.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `source` - the source to read the payload from
+/// * `disk` - the boot disk, opened on first use
+/// * `log` - the log to report to
+/// * `timeline` - the timeline to record the steps of loading on
+/// * `phase` - the phase of boot that loads the payload
+/// * `name` - the name of the payload to report
+/// * `path` - the path of the payload, if any
+/// * `ranges` - the blocks of the boot disk holding the payload
+/// * `expected` - the digest the payload is required to have, if any
+#[allow(clippy::too_many_arguments)]
+fn load_payload<'a>(
+  bs: &'a BootServices,
+  image: Handle,
+  source: &mut dyn Source,
+  disk: &mut Option<BlockReader<'a>>,
+  log: &mut Logger,
+  timeline: &mut Timeline,
+  phase: Phase,
+  name: &str,
+  path: Option<&'static str>,
+  ranges: &LbaRanges,
+  expected: Option<&sha256::Digest>,
+) -> error::Result<LoadedFile> {
+  let (read, verify) = match phase {
+    Phase::Initrd => (BootPhase::INITRD_READ, BootPhase::INITRD_VERIFY),
+    Phase::Module | Phase::Overlay => {
+      (BootPhase::MODULE_READ, BootPhase::MODULE_VERIFY)
+    }
+    _ => (BootPhase::KERNEL_READ, BootPhase::KERNEL_VERIFY),
+  };
+  let mut result = match path {
+    Some(path) => {
+      let mut bar = ProgressBar::new(bs, image, log, name);
+      let mut progress = Stamped::new(timeline, &mut bar, read, verify);
+      loader::load(bs, source, path, expected, &mut progress)
+    }
+    None => Err(Status::NOT_FOUND.into()),
+  };
+
+  // A payload that was read but failed verification must not be replaced by
+  // another copy of it.
+  let fallback = match &result {
+    Err(err) => err.status() != Status::SECURITY_VIOLATION,
+    Ok(_) => false,
+  } && !ranges.is_empty();
+  if fallback {
+    if let Some(path) = path {
+      warn!(
+        logger: log,
+        "{} '{}' is unavailable; reading it from the boot disk",
+        name,
+        path
+      );
+    }
+    result = match disk {
+      Some(disk) => Ok(disk),
+      None => BlockReader::open_boot_disk(bs, image).map(|d| disk.insert(d)),
+    }
+    .and_then(|disk| {
+      let mut bar = ProgressBar::new(bs, image, log, name);
+      let mut progress = Stamped::new(timeline, &mut bar, read, verify);
+      let data = disk.read_ranges(bs, ranges, &mut progress)?;
+      loader::verify(loader::unpack(bs, data)?.leak(), expected, &mut progress)
+    });
+  }
+
+  let location = path.filter(|_| !fallback).unwrap_or("<boot disk>");
+  let file = result
+    .map_err(|err| Error::new(phase, err.status()).with_path(location))?;
+  info!(
+    logger: log,
+    "loaded {} '{}' ({} bytes, sha256 {})",
+    name,
+    location,
+    file.data.len(),
+    file.digest
+  );
+  Ok(file)
+}
+
+/// Verifies the members of the `initrd` archive against the manifest at
+/// `path` on `source`, reporting the outcome.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `source` - the source to read the manifest from
+/// * `log` - the log to report to
+/// * `initrd` - the initrd
+/// * `location` - the path of the initrd to report failures against
+/// * `path` - the path of the manifest
+fn verify_members(
+  bs: &BootServices,
+  source: &mut dyn Source,
+  log: &mut Logger,
+  initrd: &LoadedFile,
+  location: &'static str,
+  path: &'static str,
+) -> error::Result<()> {
+  let manifest = source
+    .read(bs, path, &mut ())
+    .map_err(|err| Error::new(Phase::Initrd, err.status()).with_path(path))?;
+  let result = match core::str::from_utf8(&manifest) {
+    Ok(text) => archive::verify(initrd.data, text).map_err(|rejection| {
+      kcore::error!(logger: log, "initrd: {}", rejection);
+      Error::new(Phase::Initrd, rejection.status()).with_path(location)
+    }),
+    Err(_) => {
+      Err(Error::new(Phase::Initrd, Status::LOAD_ERROR).with_path(path))
+    }
+  };
+  let _ = loader::free_buffer(bs, manifest);
+  let count = result?;
+  info!(
+    logger: log,
+    "verified {} initrd files against manifest '{}'",
+    count,
+    path
+  );
+  Ok(())
+}
+
+/// Loads each of the additional `modules` from `source`, returning their
+/// descriptions for the kernel.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `source` - the source to read the modules from
+/// * `disk` - the boot disk, opened on first use
+/// * `log` - the log to report to
+/// * `timeline` - the timeline to record the steps of loading on
+/// * `modules` - the modules to load
+fn load_modules<'a>(
+  bs: &'a BootServices,
+  image: Handle,
+  source: &mut dyn Source,
+  disk: &mut Option<BlockReader<'a>>,
+  log: &mut Logger,
+  timeline: &mut Timeline,
+  modules: &[config::Module<'static>],
+) -> error::Result<&'static [bootinfo::Module]> {
+  if modules.is_empty() {
+    return Ok(&[]);
   }
+  let buffer = loader::allocate_buffer(
+    bs,
+    modules.len() * core::mem::size_of::<bootinfo::Module>(),
+  )
+  .context(Phase::Module)?
+  .leak();
+  // SAFETY: the buffer is page-aligned, large enough for every module, and
+  // never freed. Modules are plain integers, for which any bytes are valid.
+  let descriptions = unsafe {
+    core::slice::from_raw_parts_mut(
+      buffer.as_mut_ptr().cast::<bootinfo::Module>(),
+      modules.len(),
+    )
+  };
+
+  for (module, description) in modules.iter().zip(descriptions.iter_mut()) {
+    let file = load_payload(
+      bs,
+      image,
+      source,
+      disk,
+      log,
+      timeline,
+      Phase::Module,
+      "module",
+      Some(module.path),
+      &LbaRanges::new(),
+      module.sha256.as_ref(),
+    )?;
+    let mut digest = [0; 32];
+    for (byte, digest_byte) in digest.iter_mut().zip(file.digest.iter()) {
+      *byte = *digest_byte;
+    }
+    // Loaded files start at a page boundary, and are never freed.
+    *description = bootinfo::Module {
+      range: PhysRange {
+        start: file.data.as_ptr() as u64,
+        len: file.data.len() as u64,
+      },
+      path: PhysRange {
+        start: module.path.as_ptr() as u64,
+        len: module.path.len() as u64,
+      },
+      digest,
+    };
+  }
+  Ok(descriptions)
+}
+
+/// Applies the device tree `overlays`, loaded from `source`, in order to a
+/// copy of the firmware's device `tree`, returning the tree to hand to the
+/// kernel.
+///
+/// Overlays are ignored if the firmware provided no device tree.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `source` - the source to read the overlays from
+/// * `disk` - the boot disk, opened on first use
+/// * `log` - the log to report to
+/// * `timeline` - the timeline to record the steps of loading on
+/// * `tree` - the firmware's device tree, if any
+/// * `overlays` - the overlays to apply
+#[allow(clippy::too_many_arguments)]
+fn apply_overlays<'a>(
+  bs: &'a BootServices,
+  image: Handle,
+  source: &mut dyn Source,
+  disk: &mut Option<BlockReader<'a>>,
+  log: &mut Logger,
+  timeline: &mut Timeline,
+  tree: Option<&'static [u8]>,
+  overlays: &[config::Module<'static>],
+) -> error::Result<Option<&'static [u8]>> {
+  let Some(mut tree) = tree else {
+    if !overlays.is_empty() {
+      warn!(logger: log, "no device tree to apply overlays to");
+    }
+    return Ok(None);
+  };
+
+  for overlay in overlays {
+    let file = load_payload(
+      bs,
+      image,
+      source,
+      disk,
+      log,
+      timeline,
+      Phase::Overlay,
+      "overlay",
+      Some(overlay.path),
+      &LbaRanges::new(),
+      overlay.sha256.as_ref(),
+    )?;
+    timeline.stamp(BootPhase::DEVICE_TREE);
+    // Each overlay is applied to a new copy with room for everything it may
+    // add. The copies are never freed, which leaves them to the kernel to
+    // reclaim along with the rest of the bootloader's memory.
+    let error = |status: Status| {
+      Error::new(Phase::Overlay, status).with_path(overlay.path)
+    };
+    let buffer = loader::allocate_buffer(bs, tree.len() + file.data.len())
+      .map_err(|err| error(err.status()))?
+      .leak();
+    let mut merged =
+      DeviceTree::copy(tree, buffer).map_err(|err| error(status_of(err)))?;
+    DeviceTree::new(file.data)
+      .and_then(|mut overlay| merged.apply(&mut overlay))
+      .map_err(|err| error(status_of(err)))?;
+    tree = merged.into_bytes();
+    info!(logger: log, "applied device tree overlay '{}'", overlay.path);
+  }
+  Ok(Some(tree))
+}
+
+/// Describes the processors of the machine for the kernel, from the ACPI
+/// MADT if the firmware provides one, and otherwise from the `cpus` node of
+/// the `device_tree`.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `system_table` - the system table
+/// * `device_tree` - the device tree, if any
+fn list_cpus(
+  bs: &BootServices,
+  system_table: &SystemTable<Boot>,
+  device_tree: Option<&'static [u8]>,
+) -> uefi::Result<&'static [bootinfo::Cpu]> {
+  let bootstrap = arch::target::processor_id();
+  let describe = |id, acpi_uid, enabled| {
+    let mut flags = 0;
+    if enabled {
+      flags |= bootinfo::Cpu::ENABLED;
+    }
+    if id == bootstrap {
+      flags |= bootinfo::Cpu::BOOTSTRAP;
+    }
+    bootinfo::Cpu {
+      id,
+      acpi_uid,
+      flags,
+    }
+  };
+  let madt = acpi::madt(system_table);
+  let tree = device_tree
+    .filter(|_| madt.is_none())
+    .and_then(|tree| DeviceTree::parse(tree).ok());
+  let cpus = || {
+    let from_madt = madt
+      .into_iter()
+      .flat_map(|madt| madt.processors())
+      .map(|cpu| describe(cpu.id, cpu.uid, cpu.enabled));
+    let from_tree = tree
+      .iter()
+      .flat_map(DeviceTree::cpus)
+      .map(|cpu| describe(cpu.id, 0, cpu.enabled));
+    from_madt.chain(from_tree)
+  };
+
+  let count = cpus().count();
+  if count == 0 {
+    return Ok(&[]);
+  }
+  let buffer =
+    loader::allocate_buffer(bs, count * core::mem::size_of::<bootinfo::Cpu>())?
+      .leak();
+  // SAFETY: the buffer is page-aligned, large enough for every processor,
+  // and never freed. Processors are plain integers, for which any bytes are
+  // valid.
+  let descriptions = unsafe {
+    core::slice::from_raw_parts_mut(
+      buffer.as_mut_ptr().cast::<bootinfo::Cpu>(),
+      count,
+    )
+  };
+  for (description, cpu) in descriptions.iter_mut().zip(cpus()) {
+    *description = cpu;
+  }
+  Ok(descriptions)
+}
+
+/// Loads the boot configuration, then the kernel, the initrd if one is
+/// configured, any additional modules, and any device tree overlays, from
+/// wherever the configuration says to.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `log` - the log to report to
+/// * `timeline` - the timeline to record the phases of loading on
+/// * `entry` - the settings of a one-off entry to apply to the configuration
+/// * `held` - the verbosity chosen by a key held at startup, if any, which
+///   overrides the configured one
+/// * `device_tree` - the firmware's device tree, if any
+/// * `boot_log` - set to the description of the log if the configuration asks
+///   for it to be saved to the persistent boot log
+#[allow(clippy::too_many_arguments)]
+fn load_payloads(
+  bs: &BootServices,
+  image: Handle,
+  log: &mut Logger,
+  timeline: &mut Timeline,
+  entry: &'static str,
+  held: Option<Verbosity>,
+  device_tree: Option<&'static [u8]>,
+  boot_log: &mut Option<Log>,
+) -> error::Result<Payloads> {
+  // Everything is read from the boot volume, unless the bootloader was itself
+  // loaded over the network.
+  let mut volume = fs::open_boot_volume(bs, image);
+  let network_booted = volume.is_err();
+  let mut tftp = None;
+  let source: &mut dyn Source = match &mut volume {
+    Ok(root) => root,
+    Err(_) => tftp.insert(
+      TftpSource::open(bs, image, None, Config::DEFAULT_TFTP_RETRIES)
+        .context(Phase::Source)?,
+    ),
+  };
+
+  timeline.stamp(BootPhase::CONFIG_PARSE);
+  let mut config = load_config(bs, source, log);
+  // The settings of one-off entries are checked as they are entered, so they
+  // always apply.
+  let _ = config.apply(entry);
+  log.set_verbosity(held.unwrap_or(config.verbosity));
+  if config.boot_log {
+    *boot_log = Some(log.describe());
+  }
+  timeline.stamp(BootPhase::MEMORY_TEST);
+  memtest::run(bs, log, config.memtest).context(Phase::Startup)?;
+  watchdog::arm(bs, config.watchdog_timeout).context(Phase::Startup)?;
+
+  let mut partition = None;
+  let mut iso = None;
+  let source: &mut dyn Source =
+    if config.boot_mode == BootMode::Network && !network_booted {
+      tftp.insert(
+        TftpSource::open(bs, image, config.tftp_server, config.tftp_retries)
+          .context(Phase::Source)?,
+      )
+    } else if config.boot_mode == BootMode::Iso {
+      iso.insert(Iso9660::open(bs, image).context(Phase::Source)?)
+    } else if let Some(guid) = &config.partition {
+      partition.insert(Ext2::open(bs, image, guid).context(Phase::Source)?)
+    } else {
+      source
+    };
+
+  let mut disk = None;
+  let kernel = load_payload(
+    bs,
+    image,
+    source,
+    &mut disk,
+    log,
+    timeline,
+    Phase::Kernel,
+    "kernel",
+    Some(config.kernel),
+    &config.kernel_lba,
+    config.kernel_sha256.as_ref(),
+  )?;
+  let initrd = if config.initrd.is_some() || !config.initrd_lba.is_empty() {
+    Some(load_payload(
+      bs,
+      image,
+      source,
+      &mut disk,
+      log,
+      timeline,
+      Phase::Initrd,
+      "initrd",
+      config.initrd,
+      &config.initrd_lba,
+      config.initrd_sha256.as_ref(),
+    )?)
+  } else {
+    None
+  };
+  if let (Some(initrd), Some(manifest)) = (&initrd, config.initrd_manifest) {
+    let location = config.initrd.unwrap_or("<boot disk>");
+    verify_members(bs, source, log, initrd, location, manifest)?;
+  }
+  let modules = load_modules(
+    bs,
+    image,
+    source,
+    &mut disk,
+    log,
+    timeline,
+    config.modules.as_slice(),
+  )?;
+  let device_tree = apply_overlays(
+    bs,
+    image,
+    source,
+    &mut disk,
+    log,
+    timeline,
+    device_tree,
+    config.overlays.as_slice(),
+  )?;
+  Ok((config, kernel, initrd, modules, device_tree))
+}
+
+/// Loads the kernel and enters it, returning only if booting fails or if the
+/// user chooses to return to the firmware, with the status to exit with.
+///
+/// # Arguments
+///
+/// * `image` - the handle of the bootloader image
+/// * `system_table` - the system table
+/// * `console` - the system table to access the console through
+/// * `timeline` - the timeline to record the phases of boot on
+/// * `boot_log` - set to the description of the log if the configuration asks
+///   for it to be saved to the persistent boot log, so that it can be saved
+///   if booting fails
+fn boot(
+  image: Handle,
+  system_table: SystemTable<Boot>,
+  console: &mut SystemTable<Boot>,
+  mut timeline: Timeline,
+  boot_log: &mut Option<Log>,
+) -> error::Result<Status> {
+  let stdout = console.stdout();
+  stdout
+    .output_string(BOOT_SPLASH.as_cstr16())
+    .context(Phase::Startup)?;
+  let _ = writeln!(stdout, "{}", BUILD);
+
+  let _ = writeln!(
+    stdout,
+    "firmware: {} (revision {:#x})",
+    system_table.firmware_vendor(),
+    system_table.firmware_revision()
+  );
+  let quirks = firmware::Quirks::detect(&system_table);
+  if quirks != firmware::Quirks::NONE {
+    let _ = writeln!(stdout, "firmware quirks: {}", quirks);
+  }
+
   let bs = system_table.boot_services();
-  bs.stall(10_000_000);
+  // The key has to be checked for before the menu, which would otherwise
+  // take it as input.
+  let held = log::held_verbosity(bs, image, console);
+  timeline.calibrate(bs);
+  if !entropy::seed(bs) {
+    let _ = writeln!(
+      console.stdout(),
+      "no source of entropy; random numbers are predictable"
+    );
+  }
+  timeline.stamp(BootPhase::BOOT_MENU);
+  let entry = loop {
+    break match menu::show(bs, image, console).context(Phase::Startup)? {
+      menu::Choice::Boot => Ok(""),
+      menu::Choice::Edit => shell::edit(bs, image, console),
+      menu::Choice::Shell => shell::run(bs, image, console),
+      menu::Choice::Setup => {
+        match firmware::enter_setup(system_table.runtime_services()) {
+          Ok(never) => match never {},
+          Err(err) => {
+            let _ = writeln!(
+              console.stdout(),
+              "cannot reboot into firmware setup: {}",
+              error::describe(err.status())
+            );
+            continue;
+          }
+        }
+      }
+      // Nothing has been loaded or allocated yet, so there is nothing to
+      // free before returning.
+      menu::Choice::Exit => return Ok(Status::SUCCESS),
+    };
+  }
+  .context(Phase::Startup)?;
+  watchdog::arm(bs, Config::DEFAULT_WATCHDOG_TIMEOUT)
+    .context(Phase::Startup)?;
+
+  let mut log = Logger::new(bs, image, console.stdout());
+  let (config, kernel, initrd, modules, device_tree) = load_payloads(
+    bs,
+    image,
+    &mut log,
+    &mut timeline,
+    entry,
+    held,
+    fdt::firmware_tree(&system_table),
+    boot_log,
+  )?;
+  if let Some(tree) = device_tree {
+    // The tree with overlays applied replaces the firmware's, so that kernels
+    // booted with any protocol find it through the system table.
+    // SAFETY: the tree is in memory that is never freed.
+    unsafe { bs.install_configuration_table(&fdt::GUID, tree.as_ptr().cast()) }
+      .context(Phase::Prepare)?;
+  }
+  timeline.stamp(BootPhase::ELF_LOAD);
+
+  #[cfg(target_arch = "x86_64")]
+  if config.protocol == config::Protocol::Multiboot2 {
+    let handoff = multiboot2::Handoff::prepare(
+      bs,
+      &system_table,
+      image,
+      &kernel,
+      initrd.as_ref(),
+      quirks,
+    )
+    .map_err(|err| {
+      Error::new(Phase::Prepare, err.status()).with_path(config.kernel)
+    })?;
+    timeline.stamp(BootPhase::HANDOFF);
+    timeline.report(&mut log);
+    save_log(bs, image, &system_table, boot_log, &log);
+    drop(log);
 
-  Status::SUCCESS
+    watchdog::disarm(bs).context(Phase::Handoff)?;
+    panic::uninstall();
+    if handoff.keeps_boot_services() {
+      let memory_map = multiboot2::memory_map(bs).context(Phase::Handoff)?;
+      handoff.enter(memory_map)
+    }
+    let (_, memory_map) = system_table.exit_boot_services();
+    handoff.enter(memory_map)
+  }
+
+  #[cfg(target_arch = "x86_64")]
+  if config.protocol == config::Protocol::Limine {
+    let handoff = limine::Handoff::prepare(
+      bs,
+      &system_table,
+      image,
+      &kernel,
+      initrd.as_ref(),
+      quirks,
+    )
+    .map_err(|err| {
+      Error::new(Phase::Prepare, err.status()).with_path(config.kernel)
+    })?;
+    timeline.stamp(BootPhase::HANDOFF);
+    timeline.report(&mut log);
+    save_log(bs, image, &system_table, boot_log, &log);
+    drop(log);
+
+    watchdog::disarm(bs).context(Phase::Handoff)?;
+    panic::uninstall();
+    let (_, memory_map) = system_table.exit_boot_services();
+    handoff.enter(memory_map)
+  }
+
+  // Only the native protocol is supported beyond x86-64.
+  #[cfg(not(target_arch = "x86_64"))]
+  if config.protocol != config::Protocol::Native {
+    return Err(
+      Error::new(Phase::Prepare, Status::UNSUPPORTED).with_path(config.kernel),
+    );
+  }
+
+  let cpus =
+    list_cpus(bs, &system_table, device_tree).context(Phase::Prepare)?;
+  let mut handoff = handoff::Handoff::prepare(
+    bs,
+    image,
+    &kernel,
+    initrd.as_ref(),
+    log.describe(),
+    &mut timeline,
+    quirks,
+  )
+  .map_err(|err| {
+    Error::new(Phase::Prepare, err.status()).with_path(config.kernel)
+  })?;
+  timeline.stamp(BootPhase::HANDOFF);
+  timeline.report(&mut log);
+  save_log(bs, image, &system_table, boot_log, &log);
+  // The log holds the serial port open, which has to be closed while boot
+  // services are still available.
+  drop(log);
+  handoff.set_modules(modules);
+  handoff.set_cpus(cpus);
+  if let Some(tree) = device_tree {
+    handoff.set_device_tree(tree);
+  }
+  handoff.set_times(timeline.times());
+
+  // Nothing past this point can service the firmware watchdog, so it must
+  // not be left running into the kernel.
+  watchdog::disarm(bs).context(Phase::Handoff)?;
+  panic::uninstall();
+  let (_, memory_map) = system_table.exit_boot_services();
+  handoff.enter(memory_map)
+}
+
+/// Appends `log` to the persistent boot log as the kernel is about to be
+/// entered, if the configuration asks for it.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `system_table` - the system table
+/// * `boot_log` - the description of the log, if it is to be saved
+/// * `log` - the log
+fn save_log(
+  bs: &BootServices,
+  image: Handle,
+  system_table: &SystemTable<Boot>,
+  boot_log: &Option<Log>,
+  log: &Logger,
+) {
+  if boot_log.is_some() {
+    let rt = system_table.runtime_services();
+    let _ = bootlog::save(bs, image, rt, log.records(), &"entering the kernel");
+  }
+}
+
+#[cfg(not(feature = "selftest"))]
+#[entry]
+fn uefi_main(image: Handle, system_table: SystemTable<Boot>) -> Status {
+  panic::install(&system_table);
+  let timeline = Timeline::new();
+  // SAFETY: the clone is only used to access the console, which nothing
+  // borrowed from `system_table` refers to, and only before boot services are
+  // exited; booting only returns while they are still available.
+  let mut console = unsafe { system_table.unsafe_clone() };
+  let mut boot_log = None;
+  match boot(image, system_table, &mut console, timeline, &mut boot_log) {
+    Ok(status) => status,
+    Err(err) => {
+      if let Some(log) = boot_log {
+        // SAFETY: the logger that wrote the log is gone, and the log is in
+        // memory that is never freed.
+        let records = unsafe { log.records() };
+        let bs = console.boot_services();
+        let rt = console.runtime_services();
+        let _ = bootlog::save(bs, image, rt, records, &err);
+      }
+      error::report(&mut console, image, &err);
+      err.status()
+    }
+  }
+}
+
+#[cfg(feature = "selftest")]
+#[entry]
+fn uefi_main(image: Handle, system_table: SystemTable<Boot>) -> Status {
+  panic::install(&system_table);
+  selftest::run(image, system_table)
 }
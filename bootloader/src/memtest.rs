@@ -0,0 +1,244 @@
+//! This module provides the boot-time memory test, which checks the
+//! conventional memory reported by the firmware for faults.
+//!
+//! Each region is claimed from the firmware while it is tested, so that
+//! nothing else can use it meanwhile. Two patterns are written over the whole
+//! region and then read back: walking ones, which catches stuck and coupled
+//! data bits, and address-in-address, which catches faults in address
+//! decoding. Faulty pages may be withheld from the kernel by claiming them as
+//! unusable memory, which every handoff reports as bad.
+
+use crate::config::MemoryTest;
+use crate::loader::{self, PAGE_SIZE};
+use crate::log::Logger;
+use crate::watchdog;
+use kcore::fmt::ByteSize;
+use kcore::{info, warn};
+use uefi::table::boot::{AllocateType, BootServices, MemoryType};
+
+/// The maximum number of conventional memory regions that are tested.
+const MAX_REGIONS: usize = 256;
+
+/// The maximum number of bad ranges that are recorded.
+const MAX_BAD_RANGES: usize = 32;
+
+/// The number of extra memory descriptors to leave room for, in case the
+/// memory map grows while it is being read.
+const SPARE_DESCRIPTORS: usize = 8;
+
+/// A range of physical memory, in bytes.
+#[derive(Clone, Copy, Default)]
+struct Range {
+  start: u64,
+  end: u64,
+}
+
+/// The ranges of pages that failed the test.
+struct BadRanges {
+  ranges: [Range; MAX_BAD_RANGES],
+  len: usize,
+
+  /// Whether more bad ranges were found than could be recorded.
+  overflowed: bool,
+}
+
+impl BadRanges {
+  /// Records the page at `page` as bad, merging it with the range it extends,
+  /// if any.
+  ///
+  /// # Arguments
+  ///
+  /// * `page` - the physical address of the page
+  fn add(&mut self, page: u64) {
+    let end = page + PAGE_SIZE as u64;
+    let ranges = &mut self.ranges[..self.len];
+    if let Some(range) =
+      ranges.iter_mut().find(|r| r.start <= end && page <= r.end)
+    {
+      range.start = range.start.min(page);
+      range.end = range.end.max(end);
+    } else if self.len < MAX_BAD_RANGES {
+      self.ranges[self.len] = Range { start: page, end };
+      self.len += 1;
+    } else {
+      self.overflowed = true;
+    }
+  }
+
+  fn as_slice(&self) -> &[Range] {
+    &self.ranges[..self.len]
+  }
+}
+
+/// Tests conventional memory if `mode` asks for it, reporting any bad ranges
+/// to `log`, and withholding them from the kernel if `mode` is
+/// [`MemoryTest::Exclude`].
+///
+/// The firmware watchdog is disarmed while testing, since a test may run for
+/// longer than any reasonable timeout; the caller is responsible for
+/// re-arming it.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `log` - the log to report to
+/// * `mode` - whether, and how, memory is tested
+pub fn run(
+  bs: &BootServices,
+  log: &mut Logger,
+  mode: MemoryTest,
+) -> uefi::Result {
+  if mode == MemoryTest::Off {
+    return Ok(());
+  }
+  watchdog::disarm(bs)?;
+
+  let mut regions = [Range::default(); MAX_REGIONS];
+  let count = conventional_regions(bs, &mut regions)?;
+  let total: u64 = regions[..count].iter().map(|r| r.end - r.start).sum();
+  info!(logger: log, "testing {} of memory", ByteSize(total));
+
+  let mut bad = BadRanges {
+    ranges: [Range::default(); MAX_BAD_RANGES],
+    len: 0,
+    overflowed: false,
+  };
+  let mut tested = 0;
+  for region in &regions[..count] {
+    let pages = ((region.end - region.start) / PAGE_SIZE as u64) as usize;
+    // Regions may have been taken by the firmware since the map was read;
+    // those are skipped rather than tested from under their new owner.
+    let Ok(start) = bs.allocate_pages(
+      AllocateType::Address(region.start),
+      MemoryType::LOADER_DATA,
+      pages,
+    ) else {
+      continue;
+    };
+
+    // SAFETY: the region was just allocated, and so is owned by nothing but
+    // the test, and memory is identity-mapped during boot services.
+    let words = unsafe {
+      core::slice::from_raw_parts_mut(
+        start as *mut u64,
+        pages * PAGE_SIZE / core::mem::size_of::<u64>(),
+      )
+    };
+    test(words, start, &mut bad, |i, _| 1 << (i % 64));
+    test(words, start, &mut bad, |_, address| address);
+    tested += (pages * PAGE_SIZE) as u64;
+    bs.free_pages(start, pages)?;
+  }
+
+  info!(
+    logger: log,
+    "memory test: {} tested, {} bad ranges",
+    ByteSize(tested),
+    bad.len
+  );
+  for range in bad.as_slice() {
+    let outcome = match mode {
+      MemoryTest::Exclude => {
+        let pages = ((range.end - range.start) / PAGE_SIZE as u64) as usize;
+        let withheld = bs.allocate_pages(
+          AllocateType::Address(range.start),
+          MemoryType::UNUSABLE,
+          pages,
+        );
+        match withheld {
+          Ok(_) => "; withheld from the kernel",
+          Err(_) => "; could not be withheld from the kernel",
+        }
+      }
+      _ => "",
+    };
+    warn!(
+      logger: log,
+      "bad memory at {:#x}-{:#x}{}",
+      range.start,
+      range.end,
+      outcome
+    );
+  }
+  if bad.overflowed {
+    warn!(
+      logger: log,
+      "more bad ranges were found than can be recorded; only the first {} \
+       are reported",
+      MAX_BAD_RANGES
+    );
+  }
+  Ok(())
+}
+
+/// Reads the conventional memory regions from the firmware's memory map into
+/// `regions`, returning how many were read.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `regions` - the buffer to read the regions into
+fn conventional_regions(
+  bs: &BootServices,
+  regions: &mut [Range],
+) -> uefi::Result<usize> {
+  let size = bs.memory_map_size();
+  let mut buffer = loader::allocate_buffer(
+    bs,
+    size.map_size + SPARE_DESCRIPTORS * size.entry_size,
+  )?;
+  let count = bs.memory_map(&mut buffer).map(|memory_map| {
+    let mut count = 0;
+    for descriptor in memory_map.entries() {
+      if descriptor.ty != MemoryType::CONVENTIONAL {
+        continue;
+      }
+      let Some(region) = regions.get_mut(count) else {
+        break;
+      };
+      *region = Range {
+        start: descriptor.phys_start,
+        end: descriptor.phys_start + descriptor.page_count * PAGE_SIZE as u64,
+      };
+      count += 1;
+    }
+    count
+  });
+  loader::free_buffer(bs, buffer)?;
+  count
+}
+
+/// Writes `pattern` over `words`, then reads it back, recording the pages of
+/// any words that do not hold the value written as bad.
+///
+/// The whole pattern is written before any of it is read, so that a write
+/// that lands at the wrong address is caught.
+///
+/// # Arguments
+///
+/// * `words` - the memory to test
+/// * `base` - the physical address of `words`
+/// * `bad` - the bad ranges to record faults in
+/// * `pattern` - returns the value to write to the word at an index and
+///   physical address
+fn test(
+  words: &mut [u64],
+  base: u64,
+  bad: &mut BadRanges,
+  pattern: impl Fn(usize, u64) -> u64,
+) {
+  let address = |i: usize| base + (i * core::mem::size_of::<u64>()) as u64;
+  for (i, word) in words.iter_mut().enumerate() {
+    // SAFETY: `word` is a valid, aligned reference. The access is volatile so
+    // that it is not elided as a dead store.
+    unsafe { core::ptr::write_volatile(word, pattern(i, address(i))) };
+  }
+  for (i, word) in words.iter().enumerate() {
+    // SAFETY: `word` is a valid, aligned reference. The access is volatile so
+    // that it really reads back from memory.
+    let value = unsafe { core::ptr::read_volatile(word) };
+    if value != pattern(i, address(i)) {
+      bad.add(address(i) & !(PAGE_SIZE as u64 - 1));
+    }
+  }
+}
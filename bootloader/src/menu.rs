@@ -0,0 +1,229 @@
+//! This module provides the boot menu, which is offered at startup before
+//! anything is loaded.
+//!
+//! The menu counts down to booting normally, and stops counting as soon as any
+//! key is pressed. An entry is chosen by moving to it with the arrow keys and
+//! pressing Enter, by pressing its function key, or by pressing its shortcut
+//! without any modifiers held.
+
+use crate::keyboard::{KeyPress, Keyboard};
+use core::fmt::Write;
+use uefi::proto::console::text::{Key, ScanCode};
+use uefi::table::boot::{BootServices, EventType, TimerTrigger, Tpl};
+use uefi::table::{Boot, SystemTable};
+use uefi::Handle;
+
+/// The number of seconds the menu waits before booting normally.
+const MENU_TIMEOUT: usize = 3;
+
+/// The number of 100ns units in a second, as used by timer events.
+const TICKS_PER_SECOND: u64 = 10_000_000;
+
+/// An entry of the boot menu.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+  /// Boot with the boot configuration.
+  Boot,
+
+  /// Edit a one-off entry, then boot it.
+  Edit,
+
+  /// Enter the recovery shell.
+  Shell,
+
+  /// Reboot into the firmware's setup.
+  Setup,
+
+  /// Return to the firmware's boot manager without booting.
+  Exit,
+}
+
+/// An entry of the boot menu, and the keys that choose it.
+struct Item {
+  choice: Choice,
+  function_key: ScanCode,
+  shortcut: char,
+  label: &'static str,
+}
+
+/// The entries of the boot menu, in the order they are shown.
+const ITEMS: [Item; 5] = [
+  Item {
+    choice: Choice::Boot,
+    function_key: ScanCode::FUNCTION_1,
+    shortcut: 'b',
+    label: "boot",
+  },
+  Item {
+    choice: Choice::Edit,
+    function_key: ScanCode::FUNCTION_2,
+    shortcut: 'e',
+    label: "edit a one-off entry, then boot it",
+  },
+  Item {
+    choice: Choice::Shell,
+    function_key: ScanCode::FUNCTION_3,
+    shortcut: 'c',
+    label: "recovery shell",
+  },
+  Item {
+    choice: Choice::Setup,
+    function_key: ScanCode::FUNCTION_4,
+    shortcut: 'f',
+    label: "reboot into firmware setup",
+  },
+  Item {
+    choice: Choice::Exit,
+    function_key: ScanCode::FUNCTION_5,
+    shortcut: 'q',
+    label: "return to the firmware boot manager",
+  },
+];
+
+/// Shows the boot menu, and returns the entry that was chosen.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `console` - the system table to access the console through
+pub fn show(
+  bs: &BootServices,
+  image: Handle,
+  console: &mut SystemTable<Boot>,
+) -> uefi::Result<Choice> {
+  let mut keyboard = Keyboard::open(bs, image, console.stdin());
+  let stdout = console.stdout();
+  let _ = writeln!(
+    stdout,
+    "Use the arrow keys and Enter, a function key, or a shortcut to choose:"
+  );
+  for _ in 0..=ITEMS.len() {
+    let _ = writeln!(stdout);
+  }
+  // The menu is drawn over the lines just written, which are counted back
+  // from the cursor in case writing them scrolled the screen.
+  let top = stdout.cursor_position().1.saturating_sub(ITEMS.len() + 1);
+
+  // SAFETY: the timer has no notification function to be called.
+  let timer =
+    unsafe { bs.create_event(EventType::TIMER, Tpl::APPLICATION, None, None)? };
+  let mut selected = 0;
+  let mut remaining = Some(MENU_TIMEOUT);
+  let result = bs
+    .set_timer(&timer, TimerTrigger::Periodic(TICKS_PER_SECOND))
+    .and_then(|_| loop {
+      draw(console, top, selected, remaining);
+
+      // SAFETY: the timer is only waited on, and outlives the wait.
+      let mut events = [
+        unsafe { timer.unsafe_clone() },
+        keyboard.wait_event(console.stdin()),
+      ];
+      let index = bs
+        .wait_for_event(&mut events)
+        .map_err(|err| err.to_err_without_payload())?;
+      if index == 0 {
+        match remaining {
+          Some(1) => break Ok(Choice::Boot),
+          Some(seconds) => remaining = Some(seconds - 1),
+          None => {}
+        }
+        continue;
+      }
+
+      let Some(press) = keyboard.read(console.stdin())? else {
+        continue;
+      };
+      remaining = None;
+      match press.key {
+        Key::Special(ScanCode::UP) => {
+          selected = (selected + ITEMS.len() - 1) % ITEMS.len();
+        }
+        Key::Special(ScanCode::DOWN) => {
+          selected = (selected + 1) % ITEMS.len();
+        }
+        _ => {
+          if let Some(choice) = choose(&press, selected) {
+            break Ok(choice);
+          }
+          continue;
+        }
+      }
+      // Holding an arrow key down queues repeats faster than the menu is
+      // redrawn; they are dropped, so that the selection stops moving as soon
+      // as the key is released.
+      keyboard.flush(console.stdin())?;
+    });
+  bs.close_event(timer)?;
+
+  let _ = console
+    .stdout()
+    .set_cursor_position(0, top + ITEMS.len() + 1);
+  result
+}
+
+/// Returns the entry chosen by `press`, if any, while the entry at index
+/// `selected` is selected.
+///
+/// # Arguments
+///
+/// * `press` - the key press
+/// * `selected` - the index of the selected entry
+fn choose(press: &KeyPress, selected: usize) -> Option<Choice> {
+  match press.key {
+    Key::Printable(c) if char::from(c) == '\r' => Some(ITEMS[selected].choice),
+    Key::Printable(c) if !press.ctrl && !press.alt => {
+      let c = char::from(c).to_ascii_lowercase();
+      ITEMS
+        .iter()
+        .find(|item| item.shortcut == c)
+        .map(|item| item.choice)
+    }
+    Key::Special(scan_code) => ITEMS
+      .iter()
+      .find(|item| item.function_key == scan_code)
+      .map(|item| item.choice),
+    _ => None,
+  }
+}
+
+/// Draws the menu's entries from the row `top`, followed by the countdown to
+/// booting normally, if it is still running.
+///
+/// # Arguments
+///
+/// * `console` - the system table to access the console through
+/// * `top` - the row of the console that the menu starts at
+/// * `selected` - the index of the selected entry
+/// * `remaining` - the number of seconds left before booting normally, if the
+///   countdown is still running
+fn draw(
+  console: &mut SystemTable<Boot>,
+  top: usize,
+  selected: usize,
+  remaining: Option<usize>,
+) {
+  let stdout = console.stdout();
+  for (i, item) in ITEMS.iter().enumerate() {
+    let marker = if i == selected { '>' } else { ' ' };
+    let _ = stdout.set_cursor_position(0, top + i);
+    let _ = write!(
+      stdout,
+      "{} F{} ({})  {}",
+      marker,
+      i + 1,
+      item.shortcut,
+      item.label
+    );
+  }
+  let _ = stdout.set_cursor_position(0, top + ITEMS.len());
+  match remaining {
+    Some(seconds) => {
+      let _ = write!(stdout, "booting in {} seconds ", seconds);
+    }
+    None => {
+      let _ = write!(stdout, "{:22}", "");
+    }
+  }
+}
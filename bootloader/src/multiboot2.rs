@@ -0,0 +1,770 @@
+//! This module provides booting of kernels that implement the Multiboot2
+//! specification, as an alternative to the native handoff.
+//!
+//! The kernel is loaded at the physical addresses it requests, either from its
+//! ELF program headers or from the address tag of its Multiboot2 header, and
+//! is handed a Multiboot2 information structure allocated below 4 GiB.
+//!
+//! Kernels are entered through their i386 entry point in 32-bit protected
+//! mode with paging disabled, which is reached from long mode through a small
+//! trampoline copied below 4 GiB. Kernels that provide an EFI amd64 entry
+//! point are instead entered in long mode, and may ask for boot services to be
+//! left running.
+
+use crate::elf::Elf;
+use crate::firmware::Quirks;
+use crate::handoff;
+use crate::loader::{LoadedFile, PAGE_SIZE};
+use bootinfo::PixelFormat;
+use kcore::bytes::{read_u16, read_u32};
+use uefi::table::boot::{
+  AllocateType, BootServices, MemoryDescriptor, MemoryMap, MemoryType,
+};
+use uefi::table::{cfg, Boot, SystemTable};
+use uefi::{Handle, Status};
+
+/// The magic number that identifies a Multiboot2 header.
+const HEADER_MAGIC: u32 = 0xe852_50d6;
+
+/// The value passed in `eax` to tell the kernel it was booted by a Multiboot2
+/// bootloader.
+const BOOTLOADER_MAGIC: u32 = 0x36d7_6289;
+
+/// The Multiboot2 header must lie within this many bytes of the start of the
+/// kernel image.
+const SEARCH_LIMIT: usize = 32768;
+
+/// The header architecture for i386 protected mode.
+const ARCHITECTURE_I386: u32 = 0;
+
+/// The highest address that 32-bit kernels can reach.
+const LOW_MEMORY_LIMIT: u64 = 0xffff_ffff;
+
+/// The header tag flag marking a tag as optional.
+const HEADER_TAG_OPTIONAL: u16 = 1;
+
+/// The header tag types that are understood.
+const HEADER_TAG_END: u16 = 0;
+const HEADER_TAG_INFORMATION_REQUEST: u16 = 1;
+const HEADER_TAG_ADDRESS: u16 = 2;
+const HEADER_TAG_ENTRY_ADDRESS: u16 = 3;
+const HEADER_TAG_EFI_BOOT_SERVICES: u16 = 7;
+const HEADER_TAG_ENTRY_ADDRESS_EFI32: u16 = 8;
+const HEADER_TAG_ENTRY_ADDRESS_EFI64: u16 = 9;
+
+/// The information tag types that are produced.
+const TAG_END: u32 = 0;
+const TAG_COMMAND_LINE: u32 = 1;
+const TAG_BOOTLOADER_NAME: u32 = 2;
+const TAG_MODULE: u32 = 3;
+const TAG_BASIC_MEMORY: u32 = 4;
+const TAG_MEMORY_MAP: u32 = 6;
+const TAG_FRAMEBUFFER: u32 = 8;
+const TAG_EFI64_SYSTEM_TABLE: u32 = 12;
+const TAG_ACPI_OLD: u32 = 14;
+const TAG_ACPI_NEW: u32 = 15;
+const TAG_EFI_MEMORY_MAP: u32 = 17;
+const TAG_EFI_BOOT_SERVICES: u32 = 18;
+const TAG_EFI64_IMAGE_HANDLE: u32 = 20;
+
+/// The information tags that a kernel may require.
+const SUPPORTED_TAGS: [u32; 13] = [
+  TAG_END,
+  TAG_COMMAND_LINE,
+  TAG_BOOTLOADER_NAME,
+  TAG_MODULE,
+  TAG_BASIC_MEMORY,
+  TAG_MEMORY_MAP,
+  TAG_FRAMEBUFFER,
+  TAG_EFI64_SYSTEM_TABLE,
+  TAG_ACPI_OLD,
+  TAG_ACPI_NEW,
+  TAG_EFI_MEMORY_MAP,
+  TAG_EFI_BOOT_SERVICES,
+  TAG_EFI64_IMAGE_HANDLE,
+];
+
+/// The Multiboot2 memory map types.
+const MEMORY_AVAILABLE: u32 = 1;
+const MEMORY_RESERVED: u32 = 2;
+const MEMORY_ACPI_RECLAIMABLE: u32 = 3;
+const MEMORY_NVS: u32 = 4;
+const MEMORY_BAD: u32 = 5;
+
+/// The size of each entry of the Multiboot2 memory map.
+const MEMORY_MAP_ENTRY_SIZE: usize = 24;
+
+/// The space reserved for all information tags other than the memory maps.
+const FIXED_TAGS_SIZE: usize = 1024;
+
+/// The name that the bootloader reports itself as.
+const BOOTLOADER_NAME: &str = "untitled";
+
+/// The load addresses given by the address tag of a Multiboot2 header.
+#[derive(Clone, Copy)]
+struct AddressTag {
+  header_addr: u32,
+  load_addr: u32,
+  load_end_addr: u32,
+  bss_end_addr: u32,
+}
+
+/// The parts of a Multiboot2 header that affect how the kernel is booted.
+struct Header {
+  offset: usize,
+  address: Option<AddressTag>,
+  entry: Option<u32>,
+  efi64_entry: Option<u32>,
+  boot_services: bool,
+}
+
+impl Header {
+  /// Finds and parses the Multiboot2 header of the kernel `image`.
+  ///
+  /// Fails with [`Status::LOAD_ERROR`] if there is no valid header, and with
+  /// [`Status::UNSUPPORTED`] if the kernel requires anything that is not
+  /// supported.
+  ///
+  /// # Arguments
+  ///
+  /// * `image` - the kernel image
+  fn find(image: &[u8]) -> uefi::Result<Self> {
+    let limit = image.len().min(SEARCH_LIMIT);
+    let offset = (0..(limit + 1).saturating_sub(16))
+      .step_by(8)
+      .find(|&offset| {
+        let magic = read_u32(image, offset);
+        let architecture = read_u32(image, offset + 4);
+        let length = read_u32(image, offset + 8);
+        let checksum = read_u32(image, offset + 12);
+        magic == HEADER_MAGIC
+          && magic
+            .wrapping_add(architecture)
+            .wrapping_add(length)
+            .wrapping_add(checksum)
+            == 0
+      })
+      .ok_or(Status::LOAD_ERROR)?;
+    if read_u32(image, offset + 4) != ARCHITECTURE_I386 {
+      return Err(Status::UNSUPPORTED.into());
+    }
+    let length = read_u32(image, offset + 8) as usize;
+    let tags = image
+      .get(offset + 16..offset + length)
+      .ok_or(Status::LOAD_ERROR)?;
+
+    let mut header = Self {
+      offset,
+      address: None,
+      entry: None,
+      efi64_entry: None,
+      boot_services: false,
+    };
+    let mut position = 0;
+    while position + 8 <= tags.len() {
+      let ty = read_u16(tags, position);
+      let flags = read_u16(tags, position + 2);
+      let size = read_u32(tags, position + 4) as usize;
+      let tag = tags
+        .get(position..position + size)
+        .filter(|_| size >= 8)
+        .ok_or(Status::LOAD_ERROR)?;
+
+      match ty {
+        HEADER_TAG_END => break,
+        HEADER_TAG_INFORMATION_REQUEST if flags & HEADER_TAG_OPTIONAL == 0 => {
+          let unsupported = tag[8..]
+            .chunks_exact(4)
+            .map(|request| read_u32(request, 0))
+            .any(|request| !SUPPORTED_TAGS.contains(&request));
+          if unsupported {
+            return Err(Status::UNSUPPORTED.into());
+          }
+        }
+        HEADER_TAG_ADDRESS if size >= 24 => {
+          header.address = Some(AddressTag {
+            header_addr: read_u32(tag, 8),
+            load_addr: read_u32(tag, 12),
+            load_end_addr: read_u32(tag, 16),
+            bss_end_addr: read_u32(tag, 20),
+          });
+        }
+        HEADER_TAG_ENTRY_ADDRESS if size >= 12 => {
+          header.entry = Some(read_u32(tag, 8));
+        }
+        HEADER_TAG_ENTRY_ADDRESS_EFI64 if size >= 12 => {
+          header.efi64_entry = Some(read_u32(tag, 8));
+        }
+        HEADER_TAG_EFI_BOOT_SERVICES => header.boot_services = true,
+        // Tags that only express preferences, such as for a framebuffer mode
+        // or module alignment, are satisfied as far as is possible.
+        _ if flags & HEADER_TAG_OPTIONAL == 0
+          && (ty == HEADER_TAG_ENTRY_ADDRESS_EFI32 || ty > 10) =>
+        {
+          return Err(Status::UNSUPPORTED.into());
+        }
+        _ => {}
+      }
+      position += (size + 7) & !7;
+    }
+    Ok(header)
+  }
+}
+
+/// A writer of information tags into the Multiboot2 information structure.
+struct InfoWriter {
+  buffer: &'static mut [u8],
+  len: usize,
+}
+
+impl InfoWriter {
+  /// Appends `bytes` to the structure.
+  ///
+  /// # Arguments
+  ///
+  /// * `bytes` - the bytes to append
+  fn push(&mut self, bytes: &[u8]) -> uefi::Result {
+    self
+      .buffer
+      .get_mut(self.len..self.len + bytes.len())
+      .ok_or(Status::BUFFER_TOO_SMALL)?
+      .copy_from_slice(bytes);
+    self.len += bytes.len();
+    Ok(())
+  }
+
+  /// Appends a tag of type `ty`, with contents written by `body`.
+  ///
+  /// # Arguments
+  ///
+  /// * `ty` - the type of the tag
+  /// * `body` - writes the contents of the tag that follow its header
+  fn tag(
+    &mut self,
+    ty: u32,
+    body: impl FnOnce(&mut Self) -> uefi::Result,
+  ) -> uefi::Result {
+    let start = self.len;
+    self.push(&ty.to_le_bytes())?;
+    self.push(&[0; 4])?;
+    body(self)?;
+
+    let size = (self.len - start) as u32;
+    self.buffer[start + 4..start + 8].copy_from_slice(&size.to_le_bytes());
+    let padding = (8 - self.len % 8) % 8;
+    self.push(&[0; 8][..padding])
+  }
+}
+
+/// Everything prepared for entering a Multiboot2 kernel, which only remains
+/// to be completed once boot services have been exited, or not.
+pub struct Handoff {
+  info: InfoWriter,
+  entry: u32,
+  efi64: bool,
+  boot_services: bool,
+  trampoline: u64,
+  quirks: Quirks,
+}
+
+impl Handoff {
+  /// Loads the Multiboot2 `kernel` at the addresses it requests, and writes
+  /// all of the information structure that does not depend on the final
+  /// memory map.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `system_table` - the system table
+  /// * `image` - the handle of the bootloader image
+  /// * `kernel` - the loaded kernel image
+  /// * `initrd` - the loaded initrd, passed to the kernel as a module
+  /// * `quirks` - the workarounds needed on the firmware
+  pub fn prepare(
+    bs: &BootServices,
+    system_table: &SystemTable<Boot>,
+    image: Handle,
+    kernel: &LoadedFile,
+    initrd: Option<&LoadedFile>,
+    quirks: Quirks,
+  ) -> uefi::Result<Self> {
+    let header = Header::find(kernel.data)?;
+    let entry = match header.address {
+      Some(address) => Self::load_address(bs, kernel.data, &header, address)?,
+      None => Self::load_elf(bs, kernel.data)?,
+    };
+    let efi64 = header.efi64_entry.is_some();
+    let entry = match header.efi64_entry.or(header.entry) {
+      Some(entry) => entry,
+      None => u32::try_from(entry).map_err(|_| Status::LOAD_ERROR)?,
+    };
+    let boot_services = efi64 && header.boot_services;
+
+    let size = bs.memory_map_size();
+    let count = size.map_size / size.entry_size + handoff::SPARE_REGIONS;
+    let capacity = FIXED_TAGS_SIZE
+      + count
+        * (MEMORY_MAP_ENTRY_SIZE + core::mem::size_of::<MemoryDescriptor>());
+    let mut info = InfoWriter {
+      buffer: allocate_low(bs, capacity, MemoryType::LOADER_DATA)?,
+      len: 0,
+    };
+    // The total size and reserved field are written when the structure is
+    // finished.
+    info.push(&[0; 8])?;
+
+    info.tag(TAG_COMMAND_LINE, |info| info.push(&[0]))?;
+    info.tag(TAG_BOOTLOADER_NAME, |info| {
+      info.push(BOOTLOADER_NAME.as_bytes())?;
+      info.push(&[0])
+    })?;
+    if let Some(initrd) = initrd {
+      let module = Self::low_copy(bs, initrd.data)?;
+      info.tag(TAG_MODULE, |info| {
+        let start = module.as_ptr() as u32;
+        info.push(&start.to_le_bytes())?;
+        info.push(&(start + module.len() as u32).to_le_bytes())?;
+        info.push(b"initrd\0")
+      })?;
+    }
+    if let Ok(framebuffer) = handoff::framebuffer(bs, image, quirks) {
+      info.tag(TAG_FRAMEBUFFER, |info| {
+        info.push(&framebuffer.physical_address.to_le_bytes())?;
+        info.push(&(framebuffer.stride * 4).to_le_bytes())?;
+        info.push(&framebuffer.width.to_le_bytes())?;
+        info.push(&framebuffer.height.to_le_bytes())?;
+        // 32 bits per pixel, of the direct RGB type.
+        info.push(&[32, 1, 0, 0])?;
+        // The position and size of the red, green, and blue channels.
+        match framebuffer.format {
+          PixelFormat::BGR => info.push(&[16, 8, 8, 8, 0, 8]),
+          _ => info.push(&[0, 8, 8, 8, 16, 8]),
+        }
+      })?;
+    }
+
+    // SAFETY: `SystemTable` is a transparent wrapper of the pointer to the
+    // firmware's system table.
+    let table: u64 = unsafe { core::mem::transmute_copy(system_table) };
+    info.tag(TAG_EFI64_SYSTEM_TABLE, |info| {
+      info.push(&table.to_le_bytes())
+    })?;
+    if boot_services {
+      let handle = image.as_ptr() as u64;
+      info.tag(TAG_EFI64_IMAGE_HANDLE, |info| {
+        info.push(&handle.to_le_bytes())
+      })?;
+    }
+    for entry in system_table.config_table() {
+      let (ty, len) = match entry.guid {
+        cfg::ACPI_GUID => (TAG_ACPI_OLD, 20),
+        // SAFETY: the firmware's ACPI 2 table is an RSDP, whose length is
+        // held at offset 20.
+        cfg::ACPI2_GUID => (TAG_ACPI_NEW, unsafe {
+          entry
+            .address
+            .cast::<u8>()
+            .add(20)
+            .cast::<u32>()
+            .read_unaligned()
+        } as usize),
+        _ => continue,
+      };
+      // SAFETY: the firmware's configuration table points to an RSDP of at
+      // least `len` bytes.
+      let rsdp =
+        unsafe { core::slice::from_raw_parts(entry.address.cast::<u8>(), len) };
+      info.tag(ty, |info| info.push(rsdp))?;
+    }
+
+    let trampoline = if efi64 {
+      0
+    } else {
+      Self::install_trampoline(bs)?
+    };
+
+    Ok(Self {
+      info,
+      entry,
+      efi64,
+      boot_services,
+      trampoline,
+      quirks,
+    })
+  }
+
+  /// Returns `true` if the kernel asked for boot services to be left running,
+  /// in which case they must not be exited before [`Handoff::enter`].
+  pub fn keeps_boot_services(&self) -> bool {
+    self.boot_services
+  }
+
+  /// Loads the kernel ELF `image` at the physical addresses of its segments,
+  /// returning its entry point.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the kernel image
+  fn load_elf(bs: &BootServices, image: &[u8]) -> uefi::Result<u64> {
+    let elf = Elf::parse(image)?;
+    // Kernels are loaded at the physical addresses they were linked for, so
+    // there is no slide to relocate them by.
+    if elf.is_position_independent() {
+      return Err(Status::UNSUPPORTED.into());
+    }
+    let page = PAGE_SIZE as u64;
+    let (mut start, mut end) = (u64::MAX, 0);
+    for segment in elf.segments() {
+      let segment = segment?;
+      start = start.min(segment.paddr & !(page - 1));
+      end = end.max(segment.paddr + segment.mem_size as u64);
+    }
+    if end <= start || end > LOW_MEMORY_LIMIT {
+      return Err(Status::LOAD_ERROR.into());
+    }
+
+    let memory = allocate_at(bs, start, (end - start) as usize)?;
+    memory.fill(0);
+    for segment in elf.segments() {
+      let segment = segment?;
+      let offset = (segment.paddr - start) as usize;
+      memory[offset..offset + segment.file_size]
+        .copy_from_slice(&image[segment.offset..][..segment.file_size]);
+    }
+    Ok(elf.entry())
+  }
+
+  /// Loads the kernel `image` as described by the `address` tag of its
+  /// `header`, returning the default entry point of the start of the image.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the kernel image
+  /// * `header` - the Multiboot2 header of the kernel
+  /// * `address` - the address tag of the header
+  fn load_address(
+    bs: &BootServices,
+    image: &[u8],
+    header: &Header,
+    address: AddressTag,
+  ) -> uefi::Result<u64> {
+    let AddressTag {
+      header_addr,
+      load_addr,
+      load_end_addr,
+      bss_end_addr,
+    } = address;
+    let offset = (header.offset as u32)
+      .checked_sub(header_addr.wrapping_sub(load_addr))
+      .ok_or(Status::LOAD_ERROR)? as usize;
+    let file_len = match load_end_addr {
+      0 => image.len() - offset,
+      end => end.checked_sub(load_addr).ok_or(Status::LOAD_ERROR)? as usize,
+    };
+    let contents = image
+      .get(offset..offset + file_len)
+      .ok_or(Status::LOAD_ERROR)?;
+    let mem_len = match bss_end_addr {
+      0 => file_len,
+      end => (end.saturating_sub(load_addr) as usize).max(file_len),
+    };
+
+    let start = load_addr as u64 & !(PAGE_SIZE as u64 - 1);
+    let skip = (load_addr as u64 - start) as usize;
+    let memory = allocate_at(bs, start, skip + mem_len)?;
+    memory.fill(0);
+    memory[skip..skip + file_len].copy_from_slice(contents);
+    Ok(load_addr as u64)
+  }
+
+  /// Returns `data`, copied below 4 GiB if it does not already lie there.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `data` - the data to place below 4 GiB
+  fn low_copy<'a>(bs: &BootServices, data: &'a [u8]) -> uefi::Result<&'a [u8]> {
+    if data.as_ptr() as u64 + data.len() as u64 <= LOW_MEMORY_LIMIT {
+      return Ok(data);
+    }
+    let copy = allocate_low(bs, data.len(), MemoryType::LOADER_DATA)?;
+    copy.copy_from_slice(data);
+    Ok(copy)
+  }
+
+  /// Copies the mode-switching trampoline below 4 GiB, returning its address.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  fn install_trampoline(bs: &BootServices) -> uefi::Result<u64> {
+    // SAFETY: the symbols are defined by the trampoline's assembly below,
+    // and only their addresses are taken. Newer compilers no longer require
+    // `unsafe` for this, but the minimum supported one does.
+    #[allow(unused_unsafe)]
+    let (start, end) = unsafe {
+      (
+        core::ptr::addr_of!(multiboot2_trampoline),
+        core::ptr::addr_of!(multiboot2_trampoline_end),
+      )
+    };
+    // SAFETY: the trampoline is a single block of code between the two
+    // symbols.
+    let code = unsafe {
+      core::slice::from_raw_parts(start, end as usize - start as usize)
+    };
+    let copy = allocate_low(bs, code.len(), MemoryType::LOADER_CODE)?;
+    copy.copy_from_slice(code);
+    Ok(copy.as_ptr() as u64)
+  }
+
+  /// Writes the final memory `map` into the information structure, and enters
+  /// the kernel.
+  ///
+  /// # Arguments
+  ///
+  /// * `map` - the final memory map, returned when exiting boot services or
+  ///   read just before entering the kernel if they are kept running
+  pub fn enter(mut self, mut map: MemoryMap) -> ! {
+    map.sort();
+    // Should the information structure be too small despite the space set
+    // aside for the maps, the kernel is entered with whatever did fit.
+    let _ = self.finish(&map);
+    let info = self.info.buffer.as_ptr() as u64;
+
+    if self.efi64 {
+      // SAFETY: the kernel asked to be entered at this address in long mode,
+      // and has been loaded there.
+      unsafe {
+        core::arch::asm!(
+          "mov rbx, rsi",
+          "jmp {entry}",
+          entry = in(reg) self.entry as u64,
+          in("eax") BOOTLOADER_MAGIC,
+          in("rsi") info,
+          options(noreturn),
+        )
+      }
+    }
+    // SAFETY: the trampoline was copied below 4 GiB into memory that the
+    // firmware identity-maps, and the kernel has been loaded at its physical
+    // addresses.
+    unsafe {
+      core::arch::asm!(
+        "jmp {trampoline}",
+        trampoline = in(reg) self.trampoline,
+        in("rdi") self.entry as u64,
+        in("rsi") info,
+        options(noreturn),
+      )
+    }
+  }
+
+  /// Writes the memory map tags and the end tag of the information structure.
+  ///
+  /// # Arguments
+  ///
+  /// * `map` - the sorted final memory map
+  fn finish(&mut self, map: &MemoryMap) -> uefi::Result {
+    let keep_boot_services =
+      self.boot_services || self.quirks.keep_boot_services_memory;
+    let kind = |ty: MemoryType| match ty {
+      MemoryType::BOOT_SERVICES_CODE | MemoryType::BOOT_SERVICES_DATA
+        if keep_boot_services =>
+      {
+        MEMORY_RESERVED
+      }
+      MemoryType::CONVENTIONAL
+      | MemoryType::LOADER_CODE
+      | MemoryType::LOADER_DATA
+      | MemoryType::BOOT_SERVICES_CODE
+      | MemoryType::BOOT_SERVICES_DATA => MEMORY_AVAILABLE,
+      MemoryType::ACPI_RECLAIM => MEMORY_ACPI_RECLAIMABLE,
+      MemoryType::ACPI_NON_VOLATILE => MEMORY_NVS,
+      MemoryType::UNUSABLE => MEMORY_BAD,
+      _ => MEMORY_RESERVED,
+    };
+
+    // The basic memory information gives the amount of contiguous available
+    // memory, in KiB, from 0 and from 1 MiB.
+    let contiguous = |from: u64| {
+      let mut end = from;
+      for descriptor in map.entries() {
+        let start = descriptor.phys_start;
+        let len = descriptor.page_count * PAGE_SIZE as u64;
+        if start <= end
+          && start + len > end
+          && kind(descriptor.ty) == MEMORY_AVAILABLE
+        {
+          end = start + len;
+        }
+      }
+      ((end - from) / 1024) as u32
+    };
+    let lower = contiguous(0).min(640);
+    let upper = contiguous(0x10_0000);
+    self.info.tag(TAG_BASIC_MEMORY, |info| {
+      info.push(&lower.to_le_bytes())?;
+      info.push(&upper.to_le_bytes())
+    })?;
+
+    self.info.tag(TAG_MEMORY_MAP, |info| {
+      info.push(&(MEMORY_MAP_ENTRY_SIZE as u32).to_le_bytes())?;
+      info.push(&0u32.to_le_bytes())?;
+      for descriptor in map.entries() {
+        info.push(&descriptor.phys_start.to_le_bytes())?;
+        let len = descriptor.page_count * PAGE_SIZE as u64;
+        info.push(&len.to_le_bytes())?;
+        info.push(&kind(descriptor.ty).to_le_bytes())?;
+        info.push(&0u32.to_le_bytes())?;
+      }
+      Ok(())
+    })?;
+
+    self.info.tag(TAG_EFI_MEMORY_MAP, |info| {
+      let size = core::mem::size_of::<MemoryDescriptor>() as u32;
+      info.push(&size.to_le_bytes())?;
+      info.push(&MemoryDescriptor::VERSION.to_le_bytes())?;
+      for descriptor in map.entries() {
+        info.push(&descriptor.ty.0.to_le_bytes())?;
+        info.push(&0u32.to_le_bytes())?;
+        info.push(&descriptor.phys_start.to_le_bytes())?;
+        info.push(&descriptor.virt_start.to_le_bytes())?;
+        info.push(&descriptor.page_count.to_le_bytes())?;
+        info.push(&descriptor.att.bits().to_le_bytes())?;
+      }
+      Ok(())
+    })?;
+
+    if self.boot_services {
+      self.info.tag(TAG_EFI_BOOT_SERVICES, |_| Ok(()))?;
+    }
+    self.info.tag(TAG_END, |_| Ok(()))?;
+
+    let total = self.info.len as u32;
+    self.info.buffer[..4].copy_from_slice(&total.to_le_bytes());
+    Ok(())
+  }
+}
+
+/// Reads the current memory map, for kernels that keep boot services running.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+pub fn memory_map(bs: &BootServices) -> uefi::Result<MemoryMap<'static>> {
+  let size = bs.memory_map_size();
+  let buffer = allocate_low(
+    bs,
+    size.map_size + handoff::SPARE_REGIONS * size.entry_size,
+    MemoryType::LOADER_DATA,
+  )?;
+  bs.memory_map(buffer)
+}
+
+/// Allocates `size` bytes of pages of type `ty` below 4 GiB.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `size` - the number of bytes to allocate
+/// * `ty` - the type of memory to allocate
+fn allocate_low(
+  bs: &BootServices,
+  size: usize,
+  ty: MemoryType,
+) -> uefi::Result<&'static mut [u8]> {
+  let pages = (size.max(1) + PAGE_SIZE - 1) / PAGE_SIZE;
+  let address =
+    bs.allocate_pages(AllocateType::MaxAddress(LOW_MEMORY_LIMIT), ty, pages)?;
+  // SAFETY: the firmware returned a unique allocation of `pages` pages, which
+  // is at least `size` bytes long.
+  Ok(unsafe { core::slice::from_raw_parts_mut(address as *mut u8, size) })
+}
+
+/// Allocates `size` bytes of pages at the physical address `address`.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `address` - the page-aligned physical address to allocate at
+/// * `size` - the number of bytes to allocate
+fn allocate_at(
+  bs: &BootServices,
+  address: u64,
+  size: usize,
+) -> uefi::Result<&'static mut [u8]> {
+  let pages = (size.max(1) + PAGE_SIZE - 1) / PAGE_SIZE;
+  bs.allocate_pages(
+    AllocateType::Address(address),
+    MemoryType::LOADER_DATA,
+    pages,
+  )?;
+  // SAFETY: the firmware allocated `pages` pages at `address`, which is at
+  // least `size` bytes long.
+  Ok(unsafe { core::slice::from_raw_parts_mut(address as *mut u8, size) })
+}
+
+extern "C" {
+  static multiboot2_trampoline: u8;
+  static multiboot2_trampoline_end: u8;
+}
+
+// The trampoline from long mode to 32-bit protected mode, entered with the
+// kernel entry point in `rdi` and the information structure in `rsi`. It is
+// position-independent, and is run from a copy below 4 GiB so that it remains
+// reachable with 32-bit addressing.
+//
+// It switches to a 32-bit code segment of its own GDT, then disables paging,
+// which leaves long mode, before jumping to the kernel in the state that the
+// Multiboot2 specification requires.
+core::arch::global_asm!(
+  ".global multiboot2_trampoline",
+  ".global multiboot2_trampoline_end",
+  "multiboot2_trampoline:",
+  "cli",
+  // Load the GDT through a descriptor built on the stack.
+  "lea rax, [rip + multiboot2_gdt]",
+  "sub rsp, 16",
+  "mov word ptr [rsp], 23",
+  "mov [rsp + 2], rax",
+  "lgdt [rsp]",
+  // Far return into the 32-bit code segment.
+  "lea rax, [rip + multiboot2_protected]",
+  "push 0x08",
+  "push rax",
+  "retfq",
+  ".code32",
+  "multiboot2_protected:",
+  "mov ax, 0x10",
+  "mov ds, ax",
+  "mov es, ax",
+  "mov fs, ax",
+  "mov gs, ax",
+  "mov ss, ax",
+  // Disable paging, then long mode and PAE.
+  "mov eax, cr0",
+  "and eax, 0x7fffffff",
+  "mov cr0, eax",
+  "mov ecx, 0xc0000080",
+  "rdmsr",
+  "and eax, 0xfffffeff",
+  "wrmsr",
+  "mov eax, cr4",
+  "and eax, 0xffffffdf",
+  "mov cr4, eax",
+  // The bootloader magic, `BOOTLOADER_MAGIC`.
+  "mov eax, 0x36d76289",
+  "mov ebx, esi",
+  "jmp edi",
+  ".code64",
+  ".balign 8",
+  "multiboot2_gdt:",
+  ".quad 0",
+  // Flat 32-bit code and data segments.
+  ".quad 0x00cf9a000000ffff",
+  ".quad 0x00cf92000000ffff",
+  "multiboot2_trampoline_end:",
+);
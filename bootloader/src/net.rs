@@ -0,0 +1,514 @@
+//! This module provides loading of boot payloads over the network, using the
+//! firmware's PXE Base Code protocol to fetch files from a TFTP server.
+//!
+//! The TFTP server is expected to mirror the layout of the boot volume, rooted
+//! at the directory of the boot file handed out by DHCP.
+//!
+//! Files are transferred block by block over the protocol's UDP interface,
+//! rather than by the firmware's TFTP client, so that each block is hashed as
+//! it arrives and progress is reported as the transfer goes.
+
+use crate::efi::loaded_image;
+use crate::loader::{self, Buffer, Progress, Source, Step, PROGRESS_CHUNK};
+use crypto::{sha256, Hasher};
+use uefi::proto::network::pxe::{BaseCode, UdpOpFlags};
+use uefi::proto::network::IpAddress;
+use uefi::table::boot::{
+  BootServices, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol,
+};
+use uefi::{CStr8, Handle, Status};
+
+/// The DHCP option carrying the name of the TFTP server.
+const DHCP_OPTION_TFTP_SERVER: u8 = 66;
+
+/// The DHCP option carrying the name of the boot file.
+const DHCP_OPTION_BOOT_FILE: u8 = 67;
+
+/// The longest value of a DHCP option, and so of the boot file name.
+const MAX_BOOT_FILE: usize = 255;
+
+/// The number of microseconds to wait between failed TFTP transfers.
+const RETRY_DELAY: usize = 1_000_000;
+
+/// The UDP port that TFTP servers listen for requests on.
+const TFTP_PORT: u16 = 69;
+
+/// The size of the blocks of a TFTP transfer, unless another is negotiated.
+const TFTP_DEFAULT_BLOCK_SIZE: usize = 512;
+
+/// The size of the blocks asked of the server, which keeps a packet within an
+/// Ethernet frame.
+const TFTP_BLOCK_SIZE: usize = 1428;
+
+/// The value of the `blksize` option of a read request: [`TFTP_BLOCK_SIZE`]
+/// in decimal, which the assertion below keeps in step with it.
+const TFTP_BLOCK_SIZE_OPTION: &[u8] = b"1428\0";
+
+const _: () = assert!(
+  decimal(TFTP_BLOCK_SIZE_OPTION) == TFTP_BLOCK_SIZE,
+  "the blksize option differs from TFTP_BLOCK_SIZE"
+);
+
+/// The number of times in a row that waiting for a packet may time out before
+/// the transfer fails.
+const TFTP_MAX_TIMEOUTS: usize = 5;
+
+/// The opcodes of the TFTP packets, of RFC 1350 and RFC 2347.
+const TFTP_READ_REQUEST: u16 = 1;
+const TFTP_DATA: u16 = 3;
+const TFTP_ACK: u16 = 4;
+const TFTP_ERROR: u16 = 5;
+const TFTP_OPTION_ACK: u16 = 6;
+
+/// A [`Source`] that fetches files from a TFTP server.
+pub struct TftpSource<'a> {
+  pxe: ScopedProtocol<'a, BaseCode>,
+  server: IpAddress,
+  prefix: [u8; MAX_BOOT_FILE],
+  prefix_len: usize,
+  retries: usize,
+}
+
+impl<'a> TftpSource<'a> {
+  /// Opens a [`TftpSource`] on the network interface that `image` was loaded
+  /// from, or on the first network interface available if it was not loaded
+  /// over the network.
+  ///
+  /// DHCP is performed if the firmware has not already done so. If no
+  /// `server` is given, the server advertised by DHCP is used.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  /// * `server` - the IPv4 address of the TFTP server, if configured
+  /// * `retries` - the number of times a failed transfer is retried
+  pub fn open(
+    bs: &'a BootServices,
+    image: Handle,
+    server: Option<[u8; 4]>,
+    retries: usize,
+  ) -> uefi::Result<Self> {
+    let pxe = loaded_image::device(bs, image)
+      .and_then(|device| Self::open_base_code(bs, image, device));
+    let mut pxe = pxe.or_else(|_| {
+      let handle = bs.get_handle_for_protocol::<BaseCode>()?;
+      Self::open_base_code(bs, image, handle)
+    })?;
+
+    if !pxe.mode().started {
+      pxe.start(false)?;
+    }
+    if !pxe.mode().dhcp_ack_received {
+      pxe.dhcp(true)?;
+    }
+
+    let ack: &[u8; 1472] = pxe.mode().dhcp_ack.as_ref();
+    let server = server
+      .or_else(|| {
+        let name = dhcp_option(ack, DHCP_OPTION_TFTP_SERVER)?;
+        parse_ipv4(core::str::from_utf8(name).ok()?)
+      })
+      .or_else(|| {
+        // The 'siaddr' field of the BOOTP header holds the next server.
+        let address: [u8; 4] = ack[20..24].try_into().ok()?;
+        (address != [0; 4]).then_some(address)
+      })
+      .ok_or(Status::NOT_FOUND)?;
+
+    // The 'file' field of the BOOTP header holds the boot file, unless it has
+    // been overloaded by the DHCP boot file option.
+    let boot_file = dhcp_option(ack, DHCP_OPTION_BOOT_FILE)
+      .unwrap_or(&ack[108..236])
+      .split(|b| *b == 0)
+      .next()
+      .unwrap_or_default();
+    let prefix_len = boot_file
+      .iter()
+      .rposition(|b| *b == b'/')
+      .map(|i| i + 1)
+      .unwrap_or(0);
+    let mut prefix = [0; MAX_BOOT_FILE];
+    prefix[..prefix_len].copy_from_slice(&boot_file[..prefix_len]);
+
+    Ok(Self {
+      pxe,
+      server: IpAddress::new_v4(server),
+      prefix,
+      prefix_len,
+      retries,
+    })
+  }
+
+  /// Opens the PXE Base Code protocol on `handle`.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  /// * `handle` - the handle of the network interface
+  fn open_base_code(
+    bs: &'a BootServices,
+    image: Handle,
+    handle: Handle,
+  ) -> uefi::Result<ScopedProtocol<'a, BaseCode>> {
+    let params = OpenProtocolParams {
+      handle,
+      agent: image,
+      controller: None,
+    };
+    // SAFETY: The firmware may still hold the protocol open from performing
+    // the network boot, so it cannot be opened exclusively. Nothing else
+    // uses the protocol while the bootloader is running.
+    unsafe {
+      bs.open_protocol::<BaseCode>(params, OpenProtocolAttributes::GetProtocol)
+    }
+  }
+
+  /// Translates a boot volume `path` into a null-terminated TFTP path
+  /// relative to the boot file directory, written into `buffer`.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the boot volume path to translate
+  /// * `buffer` - the buffer to write the TFTP path into
+  fn remote_path<'b>(
+    &self,
+    path: &str,
+    buffer: &'b mut [u8; 256],
+  ) -> uefi::Result<&'b CStr8> {
+    let path = path.trim_start_matches(['\\', '/']);
+    let len = self.prefix_len + path.len();
+    if len >= buffer.len() || !path.is_ascii() {
+      return Err(Status::INVALID_PARAMETER.into());
+    }
+    buffer[..self.prefix_len].copy_from_slice(&self.prefix[..self.prefix_len]);
+    for (dst, src) in buffer[self.prefix_len..len].iter_mut().zip(path.bytes())
+    {
+      *dst = if src == b'\\' { b'/' } else { src };
+    }
+    buffer[len] = 0;
+    CStr8::from_bytes_with_nul(&buffer[..=len])
+      .map_err(|_| Status::INVALID_PARAMETER.into())
+  }
+
+  /// Performs the TFTP operation `op`, retrying it after a delay each time it
+  /// fails until the retry limit is exhausted.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `op` - the operation to perform
+  fn retry<T>(
+    &mut self,
+    bs: &BootServices,
+    mut op: impl FnMut(&mut BaseCode, &IpAddress) -> uefi::Result<T>,
+  ) -> uefi::Result<T> {
+    let mut attempt = 0;
+    loop {
+      match op(&mut self.pxe, &self.server) {
+        Err(_) if attempt < self.retries => {
+          attempt += 1;
+          bs.stall(RETRY_DELAY * attempt);
+        }
+        result => return result,
+      }
+    }
+  }
+}
+
+impl Source for TftpSource<'_> {
+  fn read(
+    &mut self,
+    bs: &BootServices,
+    path: &str,
+    progress: &mut dyn Progress,
+  ) -> uefi::Result<Buffer> {
+    Ok(self.read_hashed(bs, path, progress)?.0)
+  }
+
+  fn read_hashed(
+    &mut self,
+    bs: &BootServices,
+    path: &str,
+    progress: &mut dyn Progress,
+  ) -> uefi::Result<(Buffer, Option<sha256::Digest>)> {
+    let mut buffer = [0; 256];
+    let name = self.remote_path(path, &mut buffer)?;
+
+    let size = self
+      .retry(bs, |pxe, server| pxe.tftp_get_file_size(server, name))?
+      as usize;
+    let mut data = loader::allocate_buffer(bs, size)?;
+    let (read, digest) = self.retry(bs, |pxe, server| {
+      tftp_read(pxe, server, name, &mut data, progress)
+    })?;
+    data.truncate(read);
+    Ok((data, Some(digest)))
+  }
+}
+
+/// Finds the value of the DHCP option `code` within a raw DHCPv4 `packet`.
+///
+/// # Arguments
+///
+/// * `packet` - the raw DHCPv4 packet
+/// * `code` - the option code to search for
+fn dhcp_option(packet: &[u8], code: u8) -> Option<&[u8]> {
+  const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+  if packet.get(236..240)? != MAGIC_COOKIE {
+    return None;
+  }
+  let mut options = &packet[240..];
+  loop {
+    match *options.first()? {
+      // The pad option has no length.
+      0 => options = &options[1..],
+      255 => return None,
+      option => {
+        let len = *options.get(1)? as usize;
+        let value = options.get(2..2 + len)?;
+        if option == code {
+          return Some(value);
+        }
+        options = &options[2 + len..];
+      }
+    }
+  }
+}
+
+/// Reads the file `name` from the TFTP `server` into `data`, hashing each
+/// block as it arrives, and returns its length and digest.
+///
+/// Fails with [`Status::BUFFER_TOO_SMALL`] if the file does not fit in
+/// `data`, [`Status::TFTP_ERROR`] if the server reports an error, or
+/// [`Status::TIMEOUT`] if it stops answering.
+///
+/// # Arguments
+///
+/// * `pxe` - the protocol to transfer the file with
+/// * `server` - the address of the TFTP server
+/// * `name` - the path of the file on the server
+/// * `data` - the buffer to read the file into
+/// * `progress` - the receiver of progress reports for [`Step::Read`]
+fn tftp_read(
+  pxe: &mut BaseCode,
+  server: &IpAddress,
+  name: &CStr8,
+  data: &mut [u8],
+  progress: &mut dyn Progress,
+) -> uefi::Result<(usize, sha256::Digest)> {
+  let mut request = [0; 320];
+  let request = tftp_request(name, &mut request)?;
+  // The firmware picks the port that the transfer is received on.
+  let mut port = 0;
+  pxe.udp_write(
+    UdpOpFlags::ANY_SRC_PORT,
+    server,
+    TFTP_PORT,
+    None,
+    None,
+    Some(&mut port),
+    None,
+    request,
+  )?;
+
+  let mut hasher = sha256::SHA256::new();
+  let mut block_size = TFTP_DEFAULT_BLOCK_SIZE;
+  // The server answers from a port of its own, which the transfer is held to.
+  let mut peer = None;
+  let mut block = 0u16;
+  let mut done = 0;
+  let mut timeouts = 0;
+  let mut packet = [0; 4 + TFTP_BLOCK_SIZE];
+  progress.report(Step::Read, 0, data.len());
+  loop {
+    let mut from_ip = *server;
+    let mut from_port = 0;
+    let received = pxe.udp_read(
+      UdpOpFlags::ANY_DEST_IP | UdpOpFlags::ANY_SRC_PORT,
+      None,
+      Some(&mut port),
+      Some(&mut from_ip),
+      Some(&mut from_port),
+      None,
+      &mut packet,
+    );
+    let len = match received {
+      Ok(len) => len,
+      Err(err) if err.status() == Status::TIMEOUT => {
+        timeouts += 1;
+        if timeouts == TFTP_MAX_TIMEOUTS {
+          return Err(Status::TIMEOUT.into());
+        }
+        // Whatever was sent last was lost, or its answer was.
+        match peer {
+          Some(peer) => tftp_ack(pxe, server, port, peer, block)?,
+          None => pxe.udp_write(
+            UdpOpFlags::empty(),
+            server,
+            TFTP_PORT,
+            None,
+            None,
+            Some(&mut port),
+            None,
+            request,
+          )?,
+        }
+        continue;
+      }
+      Err(err) => return Err(err),
+    };
+    timeouts = 0;
+    if len < 4 || peer.is_some_and(|peer| peer != from_port) {
+      continue;
+    }
+
+    let packet = &packet[..len];
+    let number = u16::from_be_bytes([packet[2], packet[3]]);
+    match u16::from_be_bytes([packet[0], packet[1]]) {
+      TFTP_OPTION_ACK if peer.is_none() => {
+        block_size = tftp_option(&packet[2..], b"blksize")
+          .and_then(|value| core::str::from_utf8(value).ok()?.parse().ok())
+          .filter(|size| (8..=TFTP_BLOCK_SIZE).contains(size))
+          .ok_or(Status::PROTOCOL_ERROR)?;
+        peer = Some(from_port);
+        tftp_ack(pxe, server, port, from_port, 0)?;
+      }
+      TFTP_DATA if number == block.wrapping_add(1) => {
+        peer = Some(from_port);
+        let payload = &packet[4..];
+        let end = done + payload.len();
+        data
+          .get_mut(done..end)
+          .ok_or(Status::BUFFER_TOO_SMALL)?
+          .copy_from_slice(payload);
+        hasher.update(payload);
+        if done / PROGRESS_CHUNK != end / PROGRESS_CHUNK {
+          progress.report(Step::Read, end, data.len());
+        }
+        block = number;
+        done = end;
+        tftp_ack(pxe, server, port, from_port, block)?;
+        // The last block is the first that is not full.
+        if payload.len() < block_size {
+          progress.report(Step::Read, done, data.len());
+          return Ok((done, hasher.digest()));
+        }
+      }
+      // The block was sent again because its acknowledgement was lost.
+      TFTP_DATA if number == block && peer.is_some() => {
+        tftp_ack(pxe, server, port, from_port, block)?;
+      }
+      TFTP_ERROR => return Err(Status::TFTP_ERROR.into()),
+      _ => {}
+    }
+  }
+}
+
+/// Writes the TFTP read request of the file `name` into `buffer`, asking for
+/// blocks of [`TFTP_BLOCK_SIZE`], and returns it.
+///
+/// # Arguments
+///
+/// * `name` - the path of the file on the server
+/// * `buffer` - the buffer to write the request into
+fn tftp_request<'b>(
+  name: &CStr8,
+  buffer: &'b mut [u8; 320],
+) -> uefi::Result<&'b [u8]> {
+  let mut len = 0;
+  let fields: [&[u8]; 5] = [
+    &TFTP_READ_REQUEST.to_be_bytes(),
+    name.as_bytes(),
+    b"octet\0",
+    b"blksize\0",
+    TFTP_BLOCK_SIZE_OPTION,
+  ];
+  for field in fields {
+    buffer
+      .get_mut(len..len + field.len())
+      .ok_or(Status::INVALID_PARAMETER)?
+      .copy_from_slice(field);
+    len += field.len();
+  }
+  Ok(&buffer[..len])
+}
+
+/// Acknowledges the TFTP data `block`, or the options for block 0.
+///
+/// # Arguments
+///
+/// * `pxe` - the protocol to send the acknowledgement with
+/// * `server` - the address of the TFTP server
+/// * `port` - the port that the transfer is received on
+/// * `peer` - the port that the server sends the transfer from
+/// * `block` - the number of the block to acknowledge
+fn tftp_ack(
+  pxe: &mut BaseCode,
+  server: &IpAddress,
+  mut port: u16,
+  peer: u16,
+  block: u16,
+) -> uefi::Result {
+  let [op_high, op_low] = TFTP_ACK.to_be_bytes();
+  let [block_high, block_low] = block.to_be_bytes();
+  let ack = [op_high, op_low, block_high, block_low];
+  pxe.udp_write(
+    UdpOpFlags::empty(),
+    server,
+    peer,
+    None,
+    None,
+    Some(&mut port),
+    None,
+    &ack,
+  )
+}
+
+/// Finds the value of the option `name` within the `options` of a TFTP
+/// option acknowledgement, which are pairs of null-terminated strings.
+///
+/// # Arguments
+///
+/// * `options` - the options of the packet, after its opcode
+/// * `name` - the name of the option to search for
+fn tftp_option<'p>(options: &'p [u8], name: &[u8]) -> Option<&'p [u8]> {
+  let mut fields = options.split(|b| *b == 0);
+  while let (Some(key), Some(value)) = (fields.next(), fields.next()) {
+    if key.eq_ignore_ascii_case(name) {
+      return Some(value);
+    }
+  }
+  None
+}
+
+/// Parses a dotted-decimal IPv4 address, such as `192.168.0.1`.
+///
+/// # Arguments
+///
+/// * `s` - the string to parse
+pub fn parse_ipv4(s: &str) -> Option<[u8; 4]> {
+  let mut address = [0; 4];
+  let mut octets = s.trim_end_matches('\0').split('.');
+  for octet in address.iter_mut() {
+    *octet = octets.next()?.parse().ok()?;
+  }
+  octets.next().is_none().then_some(address)
+}
+
+/// Returns the number that the NUL-terminated decimal `text` spells.
+///
+/// # Arguments
+///
+/// * `text` - the digits, up to the terminating NUL
+const fn decimal(text: &[u8]) -> usize {
+  let mut value = 0;
+  let mut index = 0;
+  while index < text.len() && text[index] != 0 {
+    value = value * 10 + (text[index] - b'0') as usize;
+    index += 1;
+  }
+  value
+}
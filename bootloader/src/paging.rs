@@ -0,0 +1,153 @@
+//! This module provides construction of the page tables that the kernel is
+//! entered with.
+//!
+//! The tables use 4-level paging, walked the same way on every architecture,
+//! with the entries encoded by [`arch::target::paging`]. Page table pages are
+//! taken from a pool that is allocated up front, so that building the tables
+//! does not change the firmware's memory map after it has been read.
+
+use crate::loader::{self, PAGE_SIZE};
+use arch::paging::{ENTRIES, LEVEL_SHIFTS};
+use arch::target::paging as entries;
+use uefi::table::boot::BootServices;
+use uefi::Status;
+
+pub use arch::paging::{PageFlags, HUGE_PAGE_SIZE};
+
+/// A set of 4-level page tables under construction.
+pub struct AddressSpace {
+  pool: &'static mut [u8],
+  used: usize,
+}
+
+impl AddressSpace {
+  /// Constructs an empty [`AddressSpace`], allocating a pool of `tables` page
+  /// tables to build it from.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `tables` - the maximum number of page tables, including the root
+  pub fn new(bs: &BootServices, tables: usize) -> uefi::Result<Self> {
+    let pool = loader::allocate_buffer(bs, tables * PAGE_SIZE)?.leak();
+    pool.fill(0);
+    Ok(Self { pool, used: 1 })
+  }
+
+  /// Returns the physical address of the root table.
+  pub fn root(&self) -> u64 {
+    self.pool.as_ptr() as u64
+  }
+
+  /// Maps `len` bytes of physical memory at `phys` to the virtual address
+  /// `virt`, with the access given by `flags`.
+  ///
+  /// Huge pages are used wherever both addresses are suitably aligned. Pages
+  /// that are already mapped to the same memory have their access widened to
+  /// include `flags`; pages mapped to different memory fail the mapping with
+  /// [`Status::INVALID_PARAMETER`].
+  ///
+  /// # Arguments
+  ///
+  /// * `virt` - the page-aligned virtual address to map at
+  /// * `phys` - the page-aligned physical address to map
+  /// * `len` - the number of bytes to map, rounded up to a whole page
+  /// * `flags` - the access permitted to the mapping
+  pub fn map(
+    &mut self,
+    virt: u64,
+    phys: u64,
+    len: u64,
+    flags: PageFlags,
+  ) -> uefi::Result {
+    let page = PAGE_SIZE as u64;
+    if virt % page != 0 || phys % page != 0 {
+      return Err(Status::INVALID_PARAMETER.into());
+    }
+
+    let mut offset = 0;
+    while offset < len {
+      let (virt, phys) = (virt + offset, phys + offset);
+      let huge = virt % HUGE_PAGE_SIZE == 0
+        && phys % HUGE_PAGE_SIZE == 0
+        && len - offset >= HUGE_PAGE_SIZE;
+      let entry = self.leaf(virt, huge)?;
+
+      let mut new = entries::leaf_entry(phys, flags, huge);
+      if entries::is_present(*entry) {
+        if entries::address(*entry) != phys || entries::is_huge(*entry) != huge
+        {
+          return Err(Status::INVALID_PARAMETER.into());
+        }
+        // Widen the access to cover both mappings.
+        new = entries::widen(*entry, new);
+      }
+      *entry = new;
+      offset += if huge { HUGE_PAGE_SIZE } else { page };
+    }
+    Ok(())
+  }
+
+  /// Returns the leaf entry that maps `virt`, creating any missing tables on
+  /// the way to it.
+  ///
+  /// # Arguments
+  ///
+  /// * `virt` - the virtual address to find the entry of
+  /// * `huge` - whether the leaf is a page directory entry for a huge page
+  fn leaf(&mut self, virt: u64, huge: bool) -> uefi::Result<&mut u64> {
+    let (levels, last) = if huge {
+      (&LEVEL_SHIFTS[..2], LEVEL_SHIFTS[2])
+    } else {
+      (&LEVEL_SHIFTS[..3], LEVEL_SHIFTS[3])
+    };
+    let mut table = self.root();
+    for &shift in levels {
+      let entry = &mut Self::table(table)[index(virt, shift)];
+      if !entries::is_present(*entry) {
+        let address = self.allocate_table()?;
+        *entry = entries::table_entry(address);
+        table = address;
+      } else if entries::is_huge(*entry) {
+        return Err(Status::INVALID_PARAMETER.into());
+      } else {
+        table = entries::address(*entry);
+      }
+    }
+    Ok(&mut Self::table(table)[index(virt, last)])
+  }
+
+  /// Takes the next zeroed table from the pool, returning its physical
+  /// address.
+  fn allocate_table(&mut self) -> uefi::Result<u64> {
+    let table = self
+      .pool
+      .get(self.used * PAGE_SIZE..(self.used + 1) * PAGE_SIZE)
+      .ok_or(Status::OUT_OF_RESOURCES)?;
+    self.used += 1;
+    Ok(table.as_ptr() as u64)
+  }
+
+  /// Returns the page table at the physical address `address`.
+  ///
+  /// # Arguments
+  ///
+  /// * `address` - the physical address of a table from the pool
+  fn table(address: u64) -> &'static mut [u64; ENTRIES] {
+    // SAFETY: tables are only ever taken from the pool, which the firmware
+    // identity-maps, and are only accessed through the address space that
+    // exclusively owns the pool.
+    unsafe { &mut *(address as *mut [u64; ENTRIES]) }
+  }
+}
+
+/// Returns the index into a table at the level translating bits `shift..` of
+/// `virt`.
+///
+/// # Arguments
+///
+/// * `virt` - the virtual address being translated
+/// * `shift` - the lowest bit translated by the level
+fn index(virt: u64, shift: u32) -> usize {
+  ((virt >> shift) as usize) % ENTRIES
+}
@@ -0,0 +1,50 @@
+//! This module provides the bootloader's panic handler, which reports the
+//! panic on the firmware's console with [`kcore::panic::report`] while boot
+//! services are available, and halts, or in the self-test exits QEMU with a
+//! failure through [`kcore::testing::exit`].
+
+use kcore::sync::IrqMutex;
+use uefi::table::{Boot, SystemTable};
+
+/// The system table, whose console panics are reported on.
+struct Console(SystemTable<Boot>);
+
+// SAFETY: the bootloader runs on one processor, so the console is only ever
+// used from the one that installed it.
+unsafe impl Send for Console {}
+
+/// The console that panics are reported on, until boot services are exited.
+static CONSOLE: IrqMutex<Option<Console>> = IrqMutex::new(None);
+
+/// Reports panics on the console of `system_table`.
+///
+/// # Arguments
+///
+/// * `system_table` - the system table
+pub fn install(system_table: &SystemTable<Boot>) {
+  // SAFETY: the clone is only used to access the console, and only until
+  // `uninstall` is called before boot services are exited.
+  let console = unsafe { system_table.unsafe_clone() };
+  *CONSOLE.lock() = Some(Console(console));
+}
+
+/// Stops reporting panics on the console, which must be done before boot
+/// services are exited.
+pub fn uninstall() {
+  CONSOLE.lock().take();
+}
+
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+  // A panic while the console is locked is not reported, rather than waiting
+  // on the lock forever.
+  if let Some(mut console) = CONSOLE.try_lock() {
+    if let Some(Console(system_table)) = console.as_mut() {
+      let _ = kcore::panic::report(info, system_table.stdout());
+    }
+  }
+  #[cfg(feature = "selftest")]
+  kcore::testing::exit(false);
+  #[cfg(not(feature = "selftest"))]
+  arch::halt()
+}
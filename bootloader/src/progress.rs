@@ -0,0 +1,196 @@
+//! This module provides the progress bar shown while payloads are read and
+//! verified.
+//!
+//! The bar is drawn near the bottom of the screen when a graphics output is
+//! active, and is otherwise drawn with text on the current line of the
+//! console, unless the boot is quiet.
+
+use crate::config::Verbosity;
+use crate::loader::{Progress, Step};
+use crate::log::Logger;
+use core::fmt::Write;
+use uefi::proto::console::gop::{BltOp, BltPixel, GraphicsOutput};
+use uefi::proto::console::text::Output;
+use uefi::table::boot::{
+  BootServices, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol,
+};
+use uefi::Handle;
+
+/// The width of the textual bar, in characters.
+const TEXT_WIDTH: usize = 40;
+
+/// The height of the graphical bar, in pixels.
+const BAR_HEIGHT: usize = 8;
+
+/// The distance of the graphical bar from the bottom of the screen, in pixels.
+const BAR_MARGIN: usize = 32;
+
+/// The height of the strip at the bottom of the screen that the graphical bar
+/// is drawn in, which the boot console leaves clear, in pixels.
+pub const RESERVED_HEIGHT: usize = BAR_MARGIN + BAR_HEIGHT;
+
+/// The color of the unfilled part of the graphical bar.
+const TRACK_COLOR: BltPixel = BltPixel::new(0x40, 0x40, 0x40);
+
+/// The color of the graphical bar while a payload is read.
+const READ_COLOR: BltPixel = BltPixel::new(0xff, 0xff, 0xff);
+
+/// The color of the graphical bar while a payload is verified.
+const VERIFY_COLOR: BltPixel = BltPixel::new(0x40, 0xc0, 0x40);
+
+/// The color the graphical bar is cleared to once loading is done.
+const CLEAR_COLOR: BltPixel = BltPixel::new(0, 0, 0);
+
+/// A progress bar for the loading of a single payload.
+pub struct ProgressBar<'a> {
+  stdout: &'a mut Output,
+  gop: Option<ScopedProtocol<'a, GraphicsOutput>>,
+  name: &'a str,
+
+  /// Whether the textual bar is drawn when there is no graphics output.
+  text: bool,
+
+  /// The step and percentage that were last drawn, if any.
+  drawn: Option<(Step, usize)>,
+}
+
+impl<'a> ProgressBar<'a> {
+  /// Constructs a [`ProgressBar`] for the payload `name`, drawn graphically
+  /// if a graphics output is available, and otherwise with text unless `log`
+  /// is [`Verbosity::Quiet`].
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  /// * `log` - the log whose console the textual bar is drawn on
+  /// * `name` - the name of the payload
+  pub fn new(
+    bs: &'a BootServices,
+    image: Handle,
+    log: &'a mut Logger<'_>,
+    name: &'a str,
+  ) -> Self {
+    let text = log.verbosity() != Verbosity::Quiet;
+    let gop = bs
+      .get_handle_for_protocol::<GraphicsOutput>()
+      .and_then(|handle| {
+        let params = OpenProtocolParams {
+          handle,
+          agent: image,
+          controller: None,
+        };
+        // SAFETY: the console driver holds the graphics output open, so it
+        // cannot be opened exclusively. It is only drawn to, never
+        // reconfigured.
+        unsafe {
+          bs.open_protocol::<GraphicsOutput>(
+            params,
+            OpenProtocolAttributes::GetProtocol,
+          )
+        }
+      })
+      .ok();
+    Self {
+      stdout: log.stdout(),
+      gop,
+      name,
+      text,
+      drawn: None,
+    }
+  }
+
+  /// Draws the textual bar for `step` at `percent`, over the one last drawn.
+  ///
+  /// # Arguments
+  ///
+  /// * `step` - the step that progress was made in
+  /// * `percent` - the percentage of the step that is done
+  fn draw_text(&mut self, step: Step, percent: usize) {
+    // Each step is given a line of its own, which is kept once it is done.
+    if self.drawn.is_some_and(|(drawn, _)| drawn != step) {
+      let _ = writeln!(self.stdout);
+    }
+    let verb = match step {
+      Step::Read => "reading",
+      Step::Verify => "verifying",
+    };
+    let filled = TEXT_WIDTH * percent / 100;
+    let _ = write!(self.stdout, "\r{:<9} {} [", verb, self.name);
+    for i in 0..TEXT_WIDTH {
+      let _ = write!(self.stdout, "{}", if i < filled { '#' } else { '-' });
+    }
+    let _ = write!(self.stdout, "] {:>3}%", percent);
+  }
+
+  /// Returns the position and size of the graphical bar on `gop`'s screen.
+  ///
+  /// # Arguments
+  ///
+  /// * `gop` - the graphics output the bar is drawn on
+  fn bounds(gop: &GraphicsOutput) -> ((usize, usize), (usize, usize)) {
+    let (width, height) = gop.current_mode_info().resolution();
+    let y = height.saturating_sub(BAR_MARGIN + BAR_HEIGHT);
+    ((width / 4, y), (width / 2, BAR_HEIGHT.min(height)))
+  }
+}
+
+impl Progress for ProgressBar<'_> {
+  fn report(&mut self, step: Step, done: usize, total: usize) {
+    let percent = match total {
+      0 => 100,
+      _ => (done.min(total) as u64 * 100 / total as u64) as usize,
+    };
+    if self.drawn == Some((step, percent)) {
+      return;
+    }
+
+    match &mut self.gop {
+      Some(gop) => {
+        let (dest, (width, height)) = Self::bounds(gop);
+        let color = match step {
+          Step::Read => READ_COLOR,
+          Step::Verify => VERIFY_COLOR,
+        };
+        let filled = width * percent / 100;
+        let _ = gop.blt(BltOp::VideoFill {
+          color: TRACK_COLOR,
+          dest,
+          dims: (width, height),
+        });
+        if filled > 0 {
+          let _ = gop.blt(BltOp::VideoFill {
+            color,
+            dest,
+            dims: (filled, height),
+          });
+        }
+      }
+      None if self.text => self.draw_text(step, percent),
+      None => {}
+    }
+    self.drawn = Some((step, percent));
+  }
+}
+
+impl Drop for ProgressBar<'_> {
+  fn drop(&mut self) {
+    if self.drawn.is_none() {
+      return;
+    }
+    match &mut self.gop {
+      Some(gop) => {
+        let (dest, dims) = Self::bounds(gop);
+        let _ = gop.blt(BltOp::VideoFill {
+          color: CLEAR_COLOR,
+          dest,
+          dims,
+        });
+      }
+      None if self.text => {
+        let _ = writeln!(self.stdout);
+      }
+      None => {}
+    }
+  }
+}
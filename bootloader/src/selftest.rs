@@ -0,0 +1,266 @@
+//! This module provides the bootloader's self-test, which replaces booting in
+//! builds with the `selftest` feature.
+//!
+//! The self-test exercises the paths that booting relies on against the disk
+//! image generated by `tools/selftest-image.py`: the GUID partition table of
+//! the boot disk, reading and writing files on its FAT system partition, and
+//! verifying and parsing the ELF kernel that `boot.cfg` names. Each result is
+//! reported on the console and the serial port, followed by the results of the
+//! tests of the machine declared with [`os_test!`](kcore::os_test!), which
+//! [`testing::run`] reports in the format of `cargo test`. QEMU is then exited
+//! with the outcome through [`testing::exit`]. The tests also time hot paths
+//! with [`kcore::bench`], whose summaries are reported after the results, to
+//! compare builds by.
+
+use crate::blockio::BlockReader;
+use crate::config::{self, Config};
+use crate::elf::Elf;
+use crate::error;
+use crate::fs;
+use crate::gpt;
+use crate::loader;
+use crate::log::{self, SerialWriter};
+use arch::critical_section::CriticalSection;
+use core::fmt::{self, Write};
+use core::str::FromStr;
+use crypto::sha256;
+use kcore::bench::Bench;
+use kcore::error::Error;
+use kcore::guid::Guid;
+use kcore::serial::SerialPort;
+use kcore::sync::IrqMutex;
+use kcore::testing;
+use kcore::time::CycleCounter;
+use uefi::proto::console::serial::Serial;
+use uefi::table::boot::{BootServices, ScopedProtocol};
+use uefi::table::{Boot, SystemTable};
+use uefi::{Handle, Status};
+
+/// The SHA-256 digest of `abc`, from FIPS 180-2.
+const ABC_SHA256: &str =
+  "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+/// The path of the file that is written and read back by the FAT test.
+const SCRATCH_PATH: &str = r"\EFI\untitled\selftest.txt";
+
+/// The contents written to [`SCRATCH_PATH`].
+const SCRATCH: &[u8] = b"written by the bootloader self-test\n";
+
+/// The delay that the cycle counter is measured against, in microseconds,
+/// where the processor does not report its frequency.
+const CALIBRATION_DELAY: u64 = 1000;
+
+/// The number of times each hot path is timed.
+const BENCH_RUNS: usize = 64;
+
+/// The timing of SHA-256 over a page.
+static SHA256_BENCH: Bench = Bench::new("sha256 of 4 KiB");
+
+/// A test, which succeeds if it returns `Ok`.
+type Test = fn(&BootServices, Handle) -> uefi::Result;
+
+/// The tests, with their names, in the order they are run.
+const TESTS: [(&str, Test); 4] =
+  [("crypto", crypto), ("gpt", gpt), ("fat", fat), ("elf", elf)];
+
+/// The tests of the machine, in the order they are run.
+static MACHINE_TESTS: [&testing::Test; 2] = [
+  &critical_sections_disable_interrupts,
+  &irq_mutexes_disable_interrupts_while_locked,
+];
+
+/// Runs every test, reports the results, and exits QEMU with the outcome.
+///
+/// # Arguments
+///
+/// * `image` - the handle of the bootloader image
+/// * `system_table` - the system table
+pub fn run(image: Handle, system_table: SystemTable<Boot>) -> ! {
+  // SAFETY: the clone is only used to access the console, which nothing
+  // borrowed from `system_table` refers to.
+  let mut console = unsafe { system_table.unsafe_clone() };
+  let bs = system_table.boot_services();
+  let mut report = Report {
+    console: &mut console,
+    serial: log::open_serial(bs, image).ok(),
+  };
+
+  let mut passed = true;
+  for (name, test) in TESTS {
+    let _ = match test(bs, image) {
+      Ok(()) => writeln!(report, "selftest: {} ok", name),
+      Err(err) => {
+        passed = false;
+        writeln!(
+          report,
+          "selftest: {} failed: {} ({:?})",
+          name,
+          error::describe(err.status()),
+          err.status()
+        )
+      }
+    };
+  }
+  passed &= testing::run(&MACHINE_TESTS, &mut report, &clock(bs));
+  for bench in [&SHA256_BENCH] {
+    if let Some(summary) = bench.summary() {
+      let _ = writeln!(report, "selftest: bench {}: {}", bench.name(), summary);
+    }
+  }
+  let outcome = if passed { "pass" } else { "fail" };
+  let _ = writeln!(report, "selftest: {}", outcome);
+
+  drop(report);
+  testing::exit(passed)
+}
+
+/// Checks that SHA-256 produces a known digest, and times it over a page.
+fn crypto(_: &BootServices, _: Handle) -> uefi::Result {
+  let expected =
+    sha256::Digest::from_str(ABC_SHA256).map_err(|_| Status::ABORTED)?;
+  if sha256::hash_bytes(b"abc") != expected {
+    return Err(Status::SECURITY_VIOLATION.into());
+  }
+  let page = [0xa5; loader::PAGE_SIZE];
+  for _ in 0..BENCH_RUNS {
+    SHA256_BENCH.measure(|| sha256::hash_bytes(core::hint::black_box(&page)));
+  }
+  Ok(())
+}
+
+/// Checks that the GUID partition table of the boot disk is valid, and holds
+/// the system partition.
+fn gpt(bs: &BootServices, image: Handle) -> uefi::Result {
+  let mut disk = BlockReader::open_boot_disk(bs, image)?;
+  let partition = gpt::find(&mut disk, &Guid::ESP_TYPE)?;
+  if partition.last_lba < partition.first_lba {
+    return Err(Status::VOLUME_CORRUPTED.into());
+  }
+  Ok(())
+}
+
+/// Checks that `boot.cfg` can be read from the boot volume and parsed, and
+/// that a file can be written to it and read back.
+fn fat(bs: &BootServices, image: Handle) -> uefi::Result {
+  let mut root = fs::open_boot_volume(bs, image)?;
+  let text = fs::read_file(bs, &mut root, config::CONFIG_PATH, &mut ())?;
+  let valid =
+    core::str::from_utf8(&text).is_ok_and(|text| Config::parse(text).is_ok());
+  loader::free_buffer(bs, text)?;
+  if !valid {
+    return Err(Status::LOAD_ERROR.into());
+  }
+
+  fs::update_file(&mut root, SCRATCH_PATH, SCRATCH, None)?;
+  let written = fs::read_file(bs, &mut root, SCRATCH_PATH, &mut ())?;
+  let matches = *written == *SCRATCH;
+  loader::free_buffer(bs, written)?;
+  if !matches {
+    return Err(Status::VOLUME_CORRUPTED.into());
+  }
+  Ok(())
+}
+
+/// Checks that the kernel that `boot.cfg` names matches its digest, and is an
+/// executable for this machine with well-formed loadable segments.
+fn elf(bs: &BootServices, image: Handle) -> uefi::Result {
+  let mut root = fs::open_boot_volume(bs, image)?;
+  let text = fs::read_file(bs, &mut root, config::CONFIG_PATH, &mut ())?;
+  let text = core::str::from_utf8(&text).map_err(|_| Status::LOAD_ERROR)?;
+  let config = Config::parse(text).map_err(|_| Status::LOAD_ERROR)?;
+
+  let kernel = fs::read_file(bs, &mut root, config.kernel, &mut ())?;
+  let expected = config.kernel_sha256.ok_or(Status::NOT_FOUND)?;
+  if sha256::hash_bytes(&kernel) != expected {
+    return Err(Status::SECURITY_VIOLATION.into());
+  }
+  let elf = Elf::parse(&kernel)?;
+  let mut segments = 0;
+  for segment in elf.segments() {
+    segment?;
+    segments += 1;
+  }
+  if segments == 0 {
+    return Err(Status::LOAD_ERROR.into());
+  }
+  Ok(())
+}
+
+/// Returns the cycle counter as a clock for the tests of the machine, at the
+/// frequency that the processor reports for it, or else at the rate measured
+/// against the firmware's stall.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+fn clock(bs: &BootServices) -> CycleCounter {
+  CycleCounter::architectural().unwrap_or_else(|| {
+    let start = arch::cycle_counter();
+    bs.stall(CALIBRATION_DELAY as usize);
+    let ticks = arch::cycle_counter() - start;
+    CycleCounter::new((ticks / CALIBRATION_DELAY).max(1) * 1_000_000)
+  })
+}
+
+kcore::os_test! {
+  /// Checks that a critical section disables the processor's interrupts, so
+  /// that one entered within it finds them disabled, and that leaving it
+  /// restores them.
+  fn critical_sections_disable_interrupts(_context) {
+    let outer = CriticalSection::enter();
+    let enabled = outer.interrupts_were_enabled();
+    let inner = CriticalSection::enter();
+    let disabled = !inner.interrupts_were_enabled();
+    drop(inner);
+    drop(outer);
+    let restored =
+      CriticalSection::enter().interrupts_were_enabled() == enabled;
+    (disabled && restored).then_some(()).ok_or(Error::Corrupted)
+  }
+}
+
+kcore::os_test! {
+  /// Checks that an [`IrqMutex`] disables the processor's interrupts for as
+  /// long as it is locked.
+  fn irq_mutexes_disable_interrupts_while_locked(_context) {
+    static LOCK: IrqMutex<u32> = IrqMutex::new(0);
+
+    let enabled = CriticalSection::enter().interrupts_were_enabled();
+    let mut count = LOCK.lock();
+    *count += 1;
+    let disabled = !CriticalSection::enter().interrupts_were_enabled();
+    drop(count);
+    let restored =
+      CriticalSection::enter().interrupts_were_enabled() == enabled;
+    (disabled && restored).then_some(()).ok_or(Error::Corrupted)
+  }
+}
+
+/// The reporter of results, on the console and the serial port if it could
+/// be opened, which is also the port that [`testing::run`] reports on.
+struct Report<'a> {
+  console: &'a mut SystemTable<Boot>,
+  serial: Option<ScopedProtocol<'a, Serial>>,
+}
+
+impl Write for Report<'_> {
+  fn write_str(&mut self, text: &str) -> fmt::Result {
+    let _ = self.console.stdout().write_str(text);
+    if let Some(serial) = &mut self.serial {
+      let _ = SerialWriter(serial).write_str(text);
+    }
+    Ok(())
+  }
+}
+
+impl SerialPort for Report<'_> {
+  fn write(&mut self, bytes: &[u8]) -> kcore::error::Result<()> {
+    let text =
+      core::str::from_utf8(bytes).map_err(|_| Error::InvalidArgument)?;
+    self.write_str(text).map_err(|_| Error::IoError)
+  }
+
+  fn read_byte(&mut self) -> kcore::error::Result<Option<u8>> {
+    Ok(None)
+  }
+}
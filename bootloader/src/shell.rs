@@ -0,0 +1,608 @@
+//! This module provides the recovery shell, a minimal interactive console for
+//! inspecting the machine and booting a one-off entry when the normal boot
+//! configuration no longer works.
+//!
+//! The shell is entered from the boot menu. A one-off entry is a list of
+//! `boot.cfg` settings that are applied on top of the configuration for a
+//! single boot, unless it is saved, which appends it to `boot.cfg` on the boot
+//! volume for every boot after.
+
+use crate::archive::{Kind, Members};
+use crate::blockio::BlockReader;
+use crate::config::{self, Config};
+use crate::efi::file::{File, SimpleFileSystem};
+use crate::efi::loaded_image;
+use crate::efi::protocol;
+use crate::error;
+use crate::fs;
+use crate::loader;
+use crate::watchdog;
+use core::fmt::Write;
+use kcore::fmt::{ByteSize, HexDump};
+use uefi::proto::console::text::{Key, Output};
+use uefi::table::boot::BootServices;
+use uefi::table::runtime::ResetType;
+use uefi::table::{Boot, SystemTable};
+use uefi::{Handle, Status};
+
+/// The maximum length of a line of input.
+const LINE_SIZE: usize = 256;
+
+/// The maximum size of a one-off entry.
+const ENTRY_SIZE: usize = 4096;
+
+/// The number of bytes a file is dumped for when no length is given.
+const DEFAULT_DUMP_SIZE: usize = 256;
+
+/// The number of bytes shown on each line of a hexdump.
+const DUMP_WIDTH: usize = 16;
+
+/// The layout of the hexdumps of files and blocks.
+const DUMP: HexDump = HexDump::with_width(DUMP_WIDTH);
+
+/// The number of extra memory descriptors to leave room for, in case the
+/// memory map grows while it is being read.
+const SPARE_DESCRIPTORS: usize = 8;
+
+/// The usage of the shell's commands.
+const HELP: &str = "\
+commands:
+  volumes                          list the file system volumes
+  ls [N:]path                      list a directory
+  hexdump [N:]path [offset [len]]  dump the contents of a file
+  members [N:]path                 list the members of a cpio or tar archive
+  lba start [count]                dump blocks of the boot disk
+  memmap                           print the memory map
+  acpi                             list the ACPI tables
+  entry                            show the one-off entry
+  set key = value                  add a setting to the one-off entry
+  clear                            discard the one-off entry
+  save                             append the one-off entry to boot.cfg
+  boot                             boot with the one-off entry, if any
+  reboot                           restart the machine
+paths are on the boot volume, unless prefixed by a volume number";
+
+/// Runs the recovery shell until the user chooses to boot, returning the
+/// one-off entry to boot with.
+///
+/// The returned entry is empty if no settings were given, in which case the
+/// boot configuration is used unchanged.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `console` - the system table to access the console through
+pub fn run(
+  bs: &BootServices,
+  image: Handle,
+  console: &mut SystemTable<Boot>,
+) -> uefi::Result<&'static str> {
+  let mut shell = Shell::new(bs, image, console)?;
+  let _ = writeln!(
+    shell.stdout(),
+    "recovery shell; type 'help' for a list of commands"
+  );
+
+  let mut buffer = [0; LINE_SIZE];
+  loop {
+    let _ = write!(shell.stdout(), "> ");
+    let line = shell.read_line(&mut buffer)?.trim();
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let mut args = rest.split_whitespace();
+    let result = match command {
+      "" => Ok(()),
+      "help" => {
+        let _ = writeln!(shell.stdout(), "{}", HELP);
+        Ok(())
+      }
+      "volumes" => shell.volumes(),
+      "ls" => shell.ls(args.next().unwrap_or_default()),
+      "hexdump" => match args.next() {
+        Some(path) => shell.hexdump(path, args.next(), args.next()),
+        None => Err(Status::INVALID_PARAMETER.into()),
+      },
+      "members" => match args.next() {
+        Some(path) => shell.members(path),
+        None => Err(Status::INVALID_PARAMETER.into()),
+      },
+      "lba" => match args.next() {
+        Some(start) => shell.lba(start, args.next()),
+        None => Err(Status::INVALID_PARAMETER.into()),
+      },
+      "memmap" => shell.memmap(),
+      "acpi" => shell.acpi(),
+      "entry" => shell.show_entry(),
+      "set" => shell.set(rest.trim()),
+      "clear" => {
+        shell.entry_len = 0;
+        Ok(())
+      }
+      "save" => shell.save(),
+      "boot" => break,
+      "reboot" => shell.console.runtime_services().reset(
+        ResetType::COLD,
+        Status::SUCCESS,
+        None,
+      ),
+      _ => {
+        let _ = writeln!(shell.stdout(), "unknown command '{}'", command);
+        Ok(())
+      }
+    };
+    if let Err(err) = result {
+      let _ = writeln!(
+        shell.stdout(),
+        "error: {} ({:?})",
+        error::describe(err.status()),
+        err.status()
+      );
+    }
+  }
+
+  Ok(shell.into_entry())
+}
+
+/// Prompts for the settings of a one-off entry, one per line, until an empty
+/// line is entered, returning the entry to boot with.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `image` - the handle of the bootloader image
+/// * `console` - the system table to access the console through
+pub fn edit(
+  bs: &BootServices,
+  image: Handle,
+  console: &mut SystemTable<Boot>,
+) -> uefi::Result<&'static str> {
+  let mut shell = Shell::new(bs, image, console)?;
+  let _ = writeln!(
+    shell.stdout(),
+    "enter settings of the form 'key = value', then an empty line to boot"
+  );
+
+  let mut buffer = [0; LINE_SIZE];
+  loop {
+    let _ = write!(shell.stdout(), "entry> ");
+    let line = shell.read_line(&mut buffer)?.trim();
+    if line.is_empty() {
+      return Ok(shell.into_entry());
+    }
+    if let Err(err) = shell.set(line) {
+      let _ = writeln!(
+        shell.stdout(),
+        "error: {} ({:?})",
+        error::describe(err.status()),
+        err.status()
+      );
+    }
+  }
+}
+
+/// The state of the recovery shell.
+struct Shell<'a> {
+  bs: &'a BootServices,
+  image: Handle,
+  console: &'a mut SystemTable<Boot>,
+
+  /// The boot disk, opened on first use.
+  disk: Option<BlockReader<'a>>,
+
+  /// The settings of the one-off entry, one per line.
+  entry: &'static mut [u8],
+  entry_len: usize,
+}
+
+impl<'a> Shell<'a> {
+  /// Constructs a [`Shell`] with an empty one-off entry.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  /// * `image` - the handle of the bootloader image
+  /// * `console` - the system table to access the console through
+  fn new(
+    bs: &'a BootServices,
+    image: Handle,
+    console: &'a mut SystemTable<Boot>,
+  ) -> uefi::Result<Self> {
+    // The shell waits on the user indefinitely, so the firmware watchdog must
+    // not reset the machine from under it; it is re-armed once the shell
+    // exits.
+    watchdog::disarm(bs)?;
+
+    Ok(Self {
+      bs,
+      image,
+      console,
+      disk: None,
+      entry: loader::allocate_buffer(bs, ENTRY_SIZE)?.leak(),
+      entry_len: 0,
+    })
+  }
+
+  /// Returns the settings of the one-off entry.
+  fn into_entry(self) -> &'static str {
+    let entry: &'static [u8] = self.entry;
+    // Only printable ASCII is accepted as input, so the entry is always valid.
+    core::str::from_utf8(&entry[..self.entry_len]).unwrap_or_default()
+  }
+
+  fn stdout(&mut self) -> &mut Output {
+    self.console.stdout()
+  }
+
+  /// Waits for a key to be pressed, and returns it.
+  fn read_key(&mut self) -> uefi::Result<Key> {
+    loop {
+      if let Some(key) = self.console.stdin().read_key()? {
+        return Ok(key);
+      }
+      // SAFETY: the event is only waited on, and outlives the wait.
+      let mut events =
+        [unsafe { self.console.stdin().wait_for_key_event().unsafe_clone() }];
+      self
+        .bs
+        .wait_for_event(&mut events)
+        .map_err(|err| err.to_err_without_payload())?;
+    }
+  }
+
+  /// Reads a line of input into `buffer`, echoing it to the console.
+  ///
+  /// Only printable ASCII characters are accepted; anything else is ignored.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer to read the line into
+  fn read_line<'b>(
+    &mut self,
+    buffer: &'b mut [u8; LINE_SIZE],
+  ) -> uefi::Result<&'b str> {
+    let mut len = 0;
+    loop {
+      let Key::Printable(c) = self.read_key()? else {
+        continue;
+      };
+      match char::from(c) {
+        '\r' | '\n' => break,
+        '\u{8}' if len > 0 => {
+          len -= 1;
+          let _ = write!(self.stdout(), "\u{8} \u{8}");
+        }
+        c if (c.is_ascii_graphic() || c == ' ') && len < LINE_SIZE => {
+          buffer[len] = c as u8;
+          len += 1;
+          let _ = write!(self.stdout(), "{}", c);
+        }
+        _ => {}
+      }
+    }
+    let _ = writeln!(self.stdout());
+    Ok(core::str::from_utf8(&buffer[..len]).unwrap_or_default())
+  }
+
+  /// Lists the file system volumes, marking the boot volume.
+  fn volumes(&mut self) -> uefi::Result {
+    let boot = loaded_image::device(self.bs, self.image).ok();
+    let handles = protocol::locate_handles::<SimpleFileSystem>(self.bs)?;
+    for (index, &handle) in handles.iter().enumerate() {
+      let marker = if Some(handle) == boot { '*' } else { ' ' };
+      let root = fs::open_volume(self.bs, self.image, handle);
+      match root.and_then(|mut root| root.volume_info()) {
+        Ok(info) => {
+          let _ = writeln!(
+            self.stdout(),
+            "{}{}: {} bytes, {} free",
+            marker,
+            index,
+            info.volume_size,
+            info.free_space
+          );
+        }
+        Err(err) => {
+          let _ = writeln!(
+            self.stdout(),
+            "{}{}: {}",
+            marker,
+            index,
+            error::describe(err.status())
+          );
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Lists the directory at `path`.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the directory, optionally prefixed by a volume
+  fn ls(&mut self, path: &str) -> uefi::Result {
+    let (mut root, path) = self.open_path(path)?;
+    let mut dir = if path.trim_matches(['\\', '/']).is_empty() {
+      root
+    } else {
+      root.open(path)?
+    };
+    if !dir.info()?.is_directory() {
+      return Err(Status::INVALID_PARAMETER.into());
+    }
+
+    while let Some(entry) = dir.read_entry()? {
+      if entry.is_directory() {
+        let _ = writeln!(self.stdout(), "{:>12}  {}", "<dir>", entry.name());
+      } else {
+        let _ =
+          writeln!(self.stdout(), "{:>12}  {}", entry.file_size, entry.name());
+      }
+    }
+    Ok(())
+  }
+
+  /// Dumps `len` bytes of the file at `path`, starting at `offset`.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the file, optionally prefixed by a volume
+  /// * `offset` - the offset to dump from, defaulting to the start
+  /// * `len` - the number of bytes to dump, defaulting to
+  ///   [`DEFAULT_DUMP_SIZE`]
+  fn hexdump(
+    &mut self,
+    path: &str,
+    offset: Option<&str>,
+    len: Option<&str>,
+  ) -> uefi::Result {
+    let offset = offset.map_or(Some(0), parse_number);
+    let len = len.map_or(Some(DEFAULT_DUMP_SIZE as u64), parse_number);
+    let (Some(offset), Some(len)) = (offset, len) else {
+      return Err(Status::INVALID_PARAMETER.into());
+    };
+
+    let (mut root, path) = self.open_path(path)?;
+    let mut file = root.open(path)?;
+    let info = file.info()?;
+    if info.is_directory() {
+      return Err(Status::INVALID_PARAMETER.into());
+    }
+    // Reading from past the end of a file is an error, rather than reading
+    // nothing.
+    if offset >= info.file_size {
+      return Ok(());
+    }
+
+    // Only the dumped part of the file is read, a row at a time.
+    file.set_position(offset)?;
+    let mut address = offset;
+    let mut remaining = len.min(info.file_size - offset);
+    let mut row = [0; DUMP_WIDTH];
+    while remaining > 0 {
+      let size = DUMP_WIDTH.min(remaining as usize);
+      let read = file.read(&mut row[..size])?;
+      if read == 0 {
+        break;
+      }
+      let _ = DUMP.write_row(&row[..read], address, self.stdout());
+      address += read as u64;
+      remaining -= read as u64;
+    }
+    Ok(())
+  }
+
+  /// Lists the members of the cpio or tar archive at `path`, which may be
+  /// compressed.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the archive, optionally prefixed by a volume
+  fn members(&mut self, path: &str) -> uefi::Result {
+    let (mut root, path) = self.open_path(path)?;
+    let data = loader::unpack(
+      self.bs,
+      fs::read_file(self.bs, &mut root, path, &mut ())?,
+    )?;
+    let result = Members::new(&data).and_then(|members| {
+      for member in members {
+        let member = member?;
+        let stdout = self.stdout();
+        let _ = match member.kind {
+          Kind::File => {
+            writeln!(stdout, "{:>12}  {}", member.data.len(), member.path)
+          }
+          Kind::Directory => {
+            writeln!(stdout, "{:>12}  {}", "<dir>", member.path)
+          }
+          Kind::Link => writeln!(stdout, "{:>12}  {}", "<link>", member.path),
+          Kind::Other => writeln!(stdout, "{:>12}  {}", "<other>", member.path),
+        };
+      }
+      Ok(())
+    });
+    loader::free_buffer(self.bs, data)?;
+    result
+  }
+
+  /// Dumps `count` blocks of the boot disk, starting at block `start`.
+  ///
+  /// # Arguments
+  ///
+  /// * `start` - the first block to dump
+  /// * `count` - the number of blocks to dump, defaulting to one
+  fn lba(&mut self, start: &str, count: Option<&str>) -> uefi::Result {
+    let start = parse_number(start);
+    let count = count.map_or(Some(1), parse_number);
+    let (Some(start), Some(count)) = (start, count) else {
+      return Err(Status::INVALID_PARAMETER.into());
+    };
+
+    let disk = match &mut self.disk {
+      Some(disk) => disk,
+      None => self
+        .disk
+        .insert(BlockReader::open_boot_disk(self.bs, self.image)?),
+    };
+    let block_size = disk.block_size() as u64;
+    let offset = start.saturating_mul(block_size);
+    let end = offset.saturating_add(count.saturating_mul(block_size));
+    let mut row = [0; DUMP_WIDTH];
+    for address in (offset..end).step_by(DUMP_WIDTH) {
+      disk.read(address, &mut row)?;
+      let _ = DUMP.write_row(&row, address, self.console.stdout());
+    }
+    Ok(())
+  }
+
+  /// Prints the firmware's memory map.
+  fn memmap(&mut self) -> uefi::Result {
+    let size = self.bs.memory_map_size();
+    let mut buffer = loader::allocate_buffer(
+      self.bs,
+      size.map_size + SPARE_DESCRIPTORS * size.entry_size,
+    )?;
+    let result = self.bs.memory_map(&mut buffer).map(|memory_map| {
+      for descriptor in memory_map.entries() {
+        let end = descriptor.phys_start
+          + descriptor.page_count * loader::PAGE_SIZE as u64;
+        let _ = writeln!(
+          self.console.stdout(),
+          "{:#014x}-{:#014x} {:>10}  {:?}",
+          descriptor.phys_start,
+          end,
+          ByteSize(end - descriptor.phys_start),
+          descriptor.ty
+        );
+      }
+    });
+
+    loader::free_buffer(self.bs, buffer)?;
+    result
+  }
+
+  /// Lists the ACPI tables given by the firmware.
+  fn acpi(&mut self) -> uefi::Result {
+    let rsdp = crate::acpi::rsdp(self.console).ok_or(Status::NOT_FOUND)?;
+    let tables = crate::acpi::tables(rsdp).ok_or(Status::NOT_FOUND)?;
+    for table in tables.iter() {
+      let signature = core::str::from_utf8(table.signature()).unwrap_or("????");
+      let oem = core::str::from_utf8(table.oem_id()).unwrap_or_default();
+      let _ = writeln!(
+        self.stdout(),
+        "{} at {:#014x}: {} bytes, revision {}, oem '{}'",
+        signature,
+        table.data().as_ptr() as u64,
+        table.data().len(),
+        table.revision(),
+        oem.trim_end()
+      );
+    }
+    Ok(())
+  }
+
+  /// Prints the settings of the one-off entry.
+  fn show_entry(&mut self) -> uefi::Result {
+    if self.entry_len == 0 {
+      let _ = writeln!(self.stdout(), "the one-off entry is empty");
+    } else {
+      let entry = &self.entry[..self.entry_len];
+      let entry = core::str::from_utf8(entry).unwrap_or_default();
+      let _ = write!(self.console.stdout(), "{}", entry);
+    }
+    Ok(())
+  }
+
+  /// Adds `setting` to the one-off entry, where it takes precedence over any
+  /// earlier setting of the same key.
+  ///
+  /// # Arguments
+  ///
+  /// * `setting` - the setting, in the form `key = value`
+  fn set(&mut self, setting: &str) -> uefi::Result {
+    if setting.is_empty() || Config::parse(setting).is_err() {
+      let _ = writeln!(
+        self.stdout(),
+        "'{}' is not a valid setting of the form 'key = value'",
+        setting
+      );
+      return Ok(());
+    }
+    let end = self.entry_len + setting.len() + 1;
+    if end > self.entry.len() {
+      return Err(Status::BUFFER_TOO_SMALL.into());
+    }
+    self.entry[self.entry_len..end - 1].copy_from_slice(setting.as_bytes());
+    self.entry[end - 1] = b'\n';
+    self.entry_len = end;
+    Ok(())
+  }
+
+  /// Appends the settings of the one-off entry to `boot.cfg` on the boot
+  /// volume, where they take precedence over those before them, then empties
+  /// the entry.
+  fn save(&mut self) -> uefi::Result {
+    if self.entry_len == 0 {
+      let _ = writeln!(self.stdout(), "the one-off entry is empty");
+      return Ok(());
+    }
+    let time = self.console.runtime_services().get_time().ok();
+    let mut root = fs::open_boot_volume(self.bs, self.image)?;
+    let config =
+      fs::read_file(self.bs, &mut root, config::CONFIG_PATH, &mut ())?;
+
+    // The entry starts on a line of its own, whether or not the configuration
+    // ends with one.
+    let len = config.len();
+    let result = loader::allocate_buffer(self.bs, len + 1 + self.entry_len)
+      .and_then(|mut data| {
+        data[..len].copy_from_slice(&config);
+        data[len] = b'\n';
+        data[len + 1..].copy_from_slice(&self.entry[..self.entry_len]);
+        let result =
+          fs::update_file(&mut root, config::CONFIG_PATH, &data, time.as_ref());
+        loader::free_buffer(self.bs, data)?;
+        result
+      });
+    loader::free_buffer(self.bs, config)?;
+    result?;
+
+    self.entry_len = 0;
+    let _ = writeln!(self.stdout(), "saved to {}", config::CONFIG_PATH);
+    Ok(())
+  }
+
+  /// Opens the volume named by `path`, returning its root directory and the
+  /// rest of the path.
+  ///
+  /// Paths of the form `N:path` are on the `N`th volume listed by `volumes`;
+  /// all others are on the boot volume.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path, optionally prefixed by a volume
+  fn open_path<'p>(&mut self, path: &'p str) -> uefi::Result<(File, &'p str)> {
+    let Some((volume, path)) = path.split_once(':') else {
+      return Ok((fs::open_boot_volume(self.bs, self.image)?, path));
+    };
+    let index = parse_number(volume).ok_or(Status::INVALID_PARAMETER)?;
+    let handles = protocol::locate_handles::<SimpleFileSystem>(self.bs)?;
+    let handle = usize::try_from(index)
+      .ok()
+      .and_then(|index| handles.get(index))
+      .ok_or(Status::NOT_FOUND)?;
+    Ok((fs::open_volume(self.bs, self.image, *handle)?, path))
+  }
+}
+
+/// Parses a number given in decimal, or in hexadecimal with a `0x` prefix.
+///
+/// # Arguments
+///
+/// * `s` - the string to parse
+fn parse_number(s: &str) -> Option<u64> {
+  match s.strip_prefix("0x") {
+    Some(hex) => u64::from_str_radix(hex, 16).ok(),
+    None => s.parse().ok(),
+  }
+}
@@ -0,0 +1,155 @@
+//! This module provides the timing of the phases of boot, which are
+//! timestamped with the cycle counter, summarized on the console before the
+//! kernel is entered, and handed to the kernel in its
+//! [`BootTimes`](bootinfo::BootTimes).
+
+use crate::loader::{Progress, Step};
+use crate::log::Logger;
+use bootinfo::{BootPhase, BootTimes, Timestamp, MAX_TIMESTAMPS};
+use core::time::Duration;
+use kcore::debug;
+use kcore::fmt::Elapsed;
+use uefi::table::boot::BootServices;
+
+/// The delay that the cycle counter is measured against, in microseconds.
+const CALIBRATION_DELAY: u64 = 1000;
+
+/// The timestamps of the phases of boot so far.
+pub struct Timeline {
+  times: BootTimes,
+}
+
+impl Timeline {
+  /// Constructs a [`Timeline`] that starts with [`BootPhase::FIRMWARE_ENTRY`]
+  /// at the current time.
+  pub fn new() -> Self {
+    let mut timeline = Self {
+      times: BootTimes::EMPTY,
+    };
+    timeline.stamp(BootPhase::FIRMWARE_ENTRY);
+    timeline
+  }
+
+  /// Measures the rate of the cycle counter against the firmware's stall.
+  ///
+  /// # Arguments
+  ///
+  /// * `bs` - the boot services
+  pub fn calibrate(&mut self, bs: &BootServices) {
+    let start = arch::cycle_counter();
+    bs.stall(CALIBRATION_DELAY as usize);
+    let ticks = arch::cycle_counter() - start;
+    self.times.ticks_per_us = (ticks / CALIBRATION_DELAY).max(1);
+  }
+
+  /// Records that `phase` starts now.
+  ///
+  /// Phases past the first [`MAX_TIMESTAMPS`] are not recorded.
+  ///
+  /// # Arguments
+  ///
+  /// * `phase` - the phase that starts
+  pub fn stamp(&mut self, phase: BootPhase) {
+    let Some(timestamp) =
+      self.times.timestamps.get_mut(self.times.len as usize)
+    else {
+      return;
+    };
+    *timestamp = Timestamp {
+      ticks: arch::cycle_counter(),
+      phase,
+      reserved: 0,
+    };
+    self.times.len += 1;
+  }
+
+  /// Returns the timestamps recorded so far.
+  pub fn times(&self) -> &BootTimes {
+    &self.times
+  }
+
+  /// Writes a summary of how long each phase took to `log`, as details that
+  /// are only shown when booting verbosely.
+  ///
+  /// The last phase is still running, and so is reported by when it started.
+  ///
+  /// # Arguments
+  ///
+  /// * `log` - the log to write the summary to
+  pub fn report(&self, log: &mut Logger) {
+    let timestamps = self.times.timestamps();
+    let (Some(first), Some(last)) = (timestamps.first(), timestamps.last())
+    else {
+      return;
+    };
+    let rate = self.times.ticks_per_us.max(1);
+    let elapsed = |ticks: u64| Elapsed(Duration::from_micros(ticks / rate));
+
+    debug!(logger: log, "boot times:");
+    for pair in timestamps.windows(2) {
+      let time = elapsed(pair[1].ticks - pair[0].ticks);
+      let name = pair[0].phase.name();
+      debug!(logger: log, "  {:<16}{:>10.3}", name, time);
+    }
+    debug!(
+      logger: log,
+      "  {} after {:.3} in the bootloader",
+      last.phase.name(),
+      elapsed(last.ticks - first.ticks)
+    );
+    if timestamps.len() == MAX_TIMESTAMPS {
+      debug!(logger: log, "  (later phases were not recorded)");
+    }
+  }
+}
+
+/// A receiver of progress reports that timestamps the steps of loading a
+/// payload as they start, passing the reports on.
+pub struct Stamped<'a> {
+  timeline: &'a mut Timeline,
+  progress: &'a mut dyn Progress,
+  read: BootPhase,
+  verify: BootPhase,
+
+  /// The step that was last reported, if any.
+  step: Option<Step>,
+}
+
+impl<'a> Stamped<'a> {
+  /// Constructs a [`Stamped`] that records reading as `read` and verifying as
+  /// `verify` on `timeline`.
+  ///
+  /// # Arguments
+  ///
+  /// * `timeline` - the timeline to record the steps on
+  /// * `progress` - the receiver to pass reports on to
+  /// * `read` - the phase of reading the payload
+  /// * `verify` - the phase of verifying the payload
+  pub fn new(
+    timeline: &'a mut Timeline,
+    progress: &'a mut dyn Progress,
+    read: BootPhase,
+    verify: BootPhase,
+  ) -> Self {
+    Self {
+      timeline,
+      progress,
+      read,
+      verify,
+      step: None,
+    }
+  }
+}
+
+impl Progress for Stamped<'_> {
+  fn report(&mut self, step: Step, done: usize, total: usize) {
+    if self.step != Some(step) {
+      self.timeline.stamp(match step {
+        Step::Read => self.read,
+        Step::Verify => self.verify,
+      });
+      self.step = Some(step);
+    }
+    self.progress.report(step, done, total);
+  }
+}
@@ -0,0 +1,325 @@
+//! This module provides the null-terminated UCS-2 strings that UEFI protocols
+//! take and return, and their conversions from and to the UTF-8 strings that
+//! the bootloader works with, such as paths written in `boot.cfg`.
+//!
+//! A [`Ucs2Str`] borrows a string, such as one returned by the firmware or a
+//! literal written with [`ucs2!`](crate::ucs2!), which is encoded when the
+//! bootloader is compiled. A [`Ucs2Buf`] owns a string in a fixed-size buffer
+//! on the stack, into which strings are converted, or formatted, and are
+//! rejected rather than truncated when they do not fit. UCS-2 cannot encode
+//! characters beyond the Basic Multilingual Plane, which are rejected as well.
+
+use core::fmt;
+use core::ops::Deref;
+use core::str::FromStr;
+use uefi::{CStr16, Status};
+
+/// The separator of the components of a UEFI path.
+const SEPARATOR: u16 = b'\\' as u16;
+
+/// A borrowed null-terminated UCS-2 string.
+///
+/// The string is displayed as UTF-8, with unpaired surrogates replaced by
+/// [`char::REPLACEMENT_CHARACTER`].
+#[repr(transparent)]
+pub struct Ucs2Str([u16]);
+
+impl Ucs2Str {
+  /// Returns the string of `units`, without checking them.
+  ///
+  /// # Arguments
+  ///
+  /// * `units` - the characters of the string and its null terminator
+  ///
+  /// # Safety
+  ///
+  /// `units` must end with the only null in them.
+  pub const unsafe fn from_units_with_nul_unchecked(units: &[u16]) -> &Self {
+    &*(units as *const [u16] as *const Self)
+  }
+
+  /// Returns a pointer to the null-terminated string.
+  pub const fn as_ptr(&self) -> *const u16 {
+    self.0.as_ptr()
+  }
+
+  /// Returns the characters of the string, without the null terminator.
+  pub fn units(&self) -> &[u16] {
+    &self.0[..self.len()]
+  }
+
+  /// Returns the length of the string, in UCS-2 characters.
+  pub const fn len(&self) -> usize {
+    self.0.len() - 1
+  }
+
+  /// Returns `true` if the string is empty.
+  pub const fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Returns an iterator over the characters of the string, with unpaired
+  /// surrogates replaced by [`char::REPLACEMENT_CHARACTER`].
+  pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+    char::decode_utf16(self.units().iter().copied())
+      .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+  }
+
+  /// Returns the string as the [`CStr16`] that the `uefi` crate's services
+  /// take.
+  pub fn as_cstr16(&self) -> &CStr16 {
+    // SAFETY: the units end with the only null in them.
+    unsafe { CStr16::from_u16_with_nul_unchecked(&self.0) }
+  }
+}
+
+impl<'a> From<&'a CStr16> for &'a Ucs2Str {
+  fn from(string: &'a CStr16) -> Self {
+    // SAFETY: a `CStr16` ends with the only null in it.
+    unsafe {
+      Ucs2Str::from_units_with_nul_unchecked(string.to_u16_slice_with_nul())
+    }
+  }
+}
+
+impl PartialEq for Ucs2Str {
+  fn eq(&self, other: &Self) -> bool {
+    self.0 == other.0
+  }
+}
+
+impl Eq for Ucs2Str {}
+
+impl fmt::Display for Ucs2Str {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.chars().try_for_each(|c| fmt::Write::write_char(f, c))
+  }
+}
+
+impl fmt::Debug for Ucs2Str {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Write::write_char(f, '"')?;
+    for c in self.chars() {
+      write!(f, "{}", c.escape_debug())?;
+    }
+    fmt::Write::write_char(f, '"')
+  }
+}
+
+/// A null-terminated UCS-2 string of fewer than `N` characters, owned in a
+/// fixed-size buffer.
+///
+/// Strings in UTF-8 are converted with [`str::parse`], or appended with
+/// [`push_str`](Self::push_str) or by formatting them into the buffer.
+pub struct Ucs2Buf<const N: usize> {
+  units: [u16; N],
+  len: usize,
+}
+
+impl<const N: usize> Ucs2Buf<N> {
+  /// Constructs an empty string.
+  pub const fn new() -> Self {
+    Self {
+      units: [0; N],
+      len: 0,
+    }
+  }
+
+  /// Converts `path` into the form that UEFI file protocols take.
+  ///
+  /// Both `/` and `\` are accepted as separators, and are written as `\`.
+  /// Runs of separators are collapsed into one, and a trailing separator is
+  /// dropped unless it is the whole path.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path to convert
+  pub fn from_path(path: &str) -> uefi::Result<Self> {
+    let mut buffer = Self::new();
+    for c in path.chars() {
+      match c {
+        '/' | '\\' if buffer.last() == Some(SEPARATOR) => {}
+        '/' | '\\' => buffer.push_unit(SEPARATOR)?,
+        c => buffer.push(c)?,
+      }
+    }
+    if buffer.len > 1 && buffer.last() == Some(SEPARATOR) {
+      buffer.len -= 1;
+      buffer.units[buffer.len] = 0;
+    }
+    Ok(buffer)
+  }
+
+  /// Appends `c` to the string, failing with [`Status::INVALID_PARAMETER`]
+  /// if it is null, cannot be encoded in UCS-2, or does not fit.
+  ///
+  /// # Arguments
+  ///
+  /// * `c` - the character to append
+  pub fn push(&mut self, c: char) -> uefi::Result {
+    self.push_unit(encode(c)?)
+  }
+
+  /// Appends `string` to the string, failing as [`push`](Self::push) does,
+  /// in which case the string is left as it was.
+  ///
+  /// # Arguments
+  ///
+  /// * `string` - the string to append
+  pub fn push_str(&mut self, string: &str) -> uefi::Result {
+    let len = self.len;
+    string
+      .chars()
+      .try_for_each(|c| self.push(c))
+      .map_err(|error| {
+        self.units[len..self.len].fill(0);
+        self.len = len;
+        error
+      })
+  }
+
+  /// Appends the UCS-2 character `unit` to the string, as it was read from
+  /// the firmware, without checking it.
+  ///
+  /// # Arguments
+  ///
+  /// * `unit` - the character to append, which must not be null
+  pub fn push_unit(&mut self, unit: u16) -> uefi::Result {
+    if self.len + 1 >= N {
+      return Err(Status::INVALID_PARAMETER.into());
+    }
+    self.units[self.len] = unit;
+    self.len += 1;
+    Ok(())
+  }
+
+  /// Returns the last character of the string, if any.
+  fn last(&self) -> Option<u16> {
+    self.units[..self.len].last().copied()
+  }
+}
+
+impl<const N: usize> Default for Ucs2Buf<N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<const N: usize> FromStr for Ucs2Buf<N> {
+  type Err = uefi::Error;
+
+  fn from_str(string: &str) -> uefi::Result<Self> {
+    let mut buffer = Self::new();
+    buffer.push_str(string)?;
+    Ok(buffer)
+  }
+}
+
+impl<const N: usize> Deref for Ucs2Buf<N> {
+  type Target = Ucs2Str;
+
+  fn deref(&self) -> &Ucs2Str {
+    // SAFETY: the units up to `len` are not null, and are followed by one.
+    unsafe { Ucs2Str::from_units_with_nul_unchecked(&self.units[..=self.len]) }
+  }
+}
+
+impl<const N: usize> fmt::Write for Ucs2Buf<N> {
+  fn write_str(&mut self, string: &str) -> fmt::Result {
+    self.push_str(string).map_err(|_| fmt::Error)
+  }
+}
+
+impl<const N: usize> fmt::Display for Ucs2Buf<N> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Display::fmt(&**self, f)
+  }
+}
+
+impl<const N: usize> fmt::Debug for Ucs2Buf<N> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Debug::fmt(&**self, f)
+  }
+}
+
+/// Returns the string literal as a `&'static` [`Ucs2Str`], encoded when the
+/// bootloader is compiled, which fails if it holds a null or a character
+/// beyond the Basic Multilingual Plane.
+///
+/// ```ignore
+/// const OS_INDICATIONS: &Ucs2Str = ucs2!("OsIndications");
+/// ```
+#[macro_export]
+macro_rules! ucs2 {
+  ($text:expr) => {{
+    const UNITS: [u16; $crate::ucs2::encoded_len($text)] =
+      $crate::ucs2::encode_literal($text);
+    // SAFETY: `encode_literal` rejects nulls, and ends the units with one.
+    unsafe { $crate::ucs2::Ucs2Str::from_units_with_nul_unchecked(&UNITS) }
+  }};
+}
+
+/// Returns the number of UCS-2 characters that `text` is encoded in by
+/// [`ucs2!`](crate::ucs2!), including the null terminator.
+///
+/// # Arguments
+///
+/// * `text` - the literal to encode
+#[doc(hidden)]
+pub const fn encoded_len(text: &str) -> usize {
+  let bytes = text.as_bytes();
+  let (mut i, mut len) = (0, 1);
+  while i < bytes.len() {
+    // Every character but those beyond the plane, which are rejected, is
+    // one UCS-2 character, and starts with a byte that continues none.
+    len += (bytes[i] & 0xc0 != 0x80) as usize;
+    i += 1;
+  }
+  len
+}
+
+/// Returns `text` encoded in UCS-2 for [`ucs2!`](crate::ucs2!), with a null
+/// terminator, panicking if it cannot be.
+///
+/// # Arguments
+///
+/// * `text` - the literal to encode
+#[doc(hidden)]
+pub const fn encode_literal<const N: usize>(text: &str) -> [u16; N] {
+  let bytes = text.as_bytes();
+  let mut units = [0; N];
+  let (mut i, mut len) = (0, 0);
+  while i < bytes.len() {
+    let (unit, size) = match bytes[i] {
+      byte @ 0x00..=0x7f => (byte as u16, 1),
+      byte @ 0xc0..=0xdf => {
+        ((byte as u16 & 0x1f) << 6 | (bytes[i + 1] as u16 & 0x3f), 2)
+      }
+      byte @ 0xe0..=0xef => (
+        (byte as u16 & 0x0f) << 12
+          | (bytes[i + 1] as u16 & 0x3f) << 6
+          | (bytes[i + 2] as u16 & 0x3f),
+        3,
+      ),
+      _ => panic!("UCS-2 cannot encode characters beyond the BMP"),
+    };
+    assert!(unit != 0, "UCS-2 strings cannot hold a null");
+    units[len] = unit;
+    len += 1;
+    i += size;
+  }
+  units
+}
+
+/// Returns the UCS-2 encoding of `c`, which must be a non-null character of
+/// the Basic Multilingual Plane.
+///
+/// # Arguments
+///
+/// * `c` - the character to encode
+fn encode(c: char) -> uefi::Result<u16> {
+  match u16::try_from(u32::from(c)) {
+    Ok(unit) if unit != 0 => Ok(unit),
+    _ => Err(Status::INVALID_PARAMETER.into()),
+  }
+}
@@ -0,0 +1,34 @@
+//! This module provides management of the firmware watchdog timer.
+//!
+//! The firmware arms a watchdog before starting a boot option, and resets the
+//! machine if it expires. The bootloader re-arms it with its own timeout while
+//! loading, so that a hang resets the machine rather than stalling forever,
+//! and disarms it before control is handed off.
+
+use uefi::table::boot::BootServices;
+
+/// The code the firmware logs if the watchdog expires during loading.
+///
+/// Codes below `0x10000` are reserved for use by the firmware.
+const WATCHDOG_CODE: u64 = 0x10000;
+
+/// Arms the watchdog to reset the machine after `seconds` seconds.
+///
+/// A timeout of `0` disarms the watchdog instead.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+/// * `seconds` - the number of seconds before the machine is reset
+pub fn arm(bs: &BootServices, seconds: usize) -> uefi::Result {
+  bs.set_watchdog_timer(seconds, WATCHDOG_CODE, None)
+}
+
+/// Disarms the watchdog, so that it never resets the machine.
+///
+/// # Arguments
+///
+/// * `bs` - the boot services
+pub fn disarm(bs: &BootServices) -> uefi::Result {
+  arm(bs, 0)
+}
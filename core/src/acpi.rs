@@ -0,0 +1,577 @@
+//! This module provides parsing of the firmware's static ACPI tables as views
+//! over their bytes that copy nothing: the RSDP, the XSDT or RSDT listing the
+//! other tables, and the fixed tables needed before an AML interpreter
+//! exists, which are the MADT, the FADT, the HPET table and the MCFG.
+//!
+//! Tables are found by their physical addresses, which are turned into bytes
+//! by the `map` function given to [`Tables::new`]: the bootloader reads the
+//! firmware's identity mapping, and the kernel its own mapping of physical
+//! memory. Every structure is only used if its checksum is valid; malformed
+//! ones fail with [`Error::Corrupted`].
+
+mod fadt;
+mod hpet;
+mod madt;
+mod mcfg;
+
+pub use fadt::Fadt;
+pub use hpet::Hpet;
+pub use madt::{Madt, MadtEntries, MadtEntry, Processor};
+pub use mcfg::{Mcfg, McfgEntries, McfgEntry};
+
+use crate::bytes::{read_u16, read_u32, read_u64};
+use crate::error::{Error, Result};
+use core::iter::FusedIterator;
+
+/// The size of the header shared by all system description tables.
+pub const HEADER_SIZE: usize = 36;
+
+/// The size of the RSDP of ACPI 1.0.
+const RSDP_V1_SIZE: usize = 20;
+
+/// The size of the RSDP of ACPI 2.0 and later.
+const RSDP_V2_SIZE: usize = 36;
+
+/// The root system description pointer, which the firmware hands over to find
+/// the other tables by.
+#[derive(Clone, Copy, Debug)]
+pub struct Rsdp<'a> {
+  data: &'a [u8],
+}
+
+impl<'a> Rsdp<'a> {
+  /// Parses the RSDP in `data`, which may extend past it, validating its
+  /// signature and checksums.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the bytes starting with the RSDP
+  pub fn parse(data: &'a [u8]) -> Result<Self> {
+    let v1 = data.get(..RSDP_V1_SIZE).ok_or(Error::Corrupted)?;
+    if !v1.starts_with(b"RSD PTR ") || !is_valid(v1) {
+      return Err(Error::Corrupted);
+    }
+    if v1[15] < 2 {
+      return Ok(Self { data: v1 });
+    }
+    // ACPI 2.0 extends the structure, with its length and a checksum over
+    // all of it.
+    let len = (read_u32(data, 20) as usize).max(RSDP_V2_SIZE);
+    let data = data.get(..len).ok_or(Error::Corrupted)?;
+    if !is_valid(data) {
+      return Err(Error::Corrupted);
+    }
+    Ok(Self { data })
+  }
+
+  /// Returns the revision of the RSDP, which is 2 or later from ACPI 2.0 on.
+  pub fn revision(&self) -> u8 {
+    self.data[15]
+  }
+
+  /// Returns the ID of the OEM.
+  pub fn oem_id(&self) -> &'a [u8; 6] {
+    self.data[9..15].try_into().unwrap()
+  }
+
+  /// Returns the physical address of the RSDT.
+  pub fn rsdt_address(&self) -> u32 {
+    read_u32(self.data, 16)
+  }
+
+  /// Returns the physical address of the XSDT, which is only present from
+  /// ACPI 2.0 on.
+  pub fn xsdt_address(&self) -> Option<u64> {
+    Some(read_u64(self.data.get(..RSDP_V2_SIZE)?, 24)).filter(|&a| a != 0)
+  }
+}
+
+/// A system description table, starting with the common header.
+#[derive(Clone, Copy, Debug)]
+pub struct Sdt<'a> {
+  data: &'a [u8],
+}
+
+impl<'a> Sdt<'a> {
+  /// Parses the table in `data`, which may extend past it, validating its
+  /// length and checksum.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the bytes starting with the table
+  pub fn parse(data: &'a [u8]) -> Result<Self> {
+    let header = data.get(..HEADER_SIZE).ok_or(Error::Corrupted)?;
+    let len = read_u32(header, 4) as usize;
+    let data = data
+      .get(..len)
+      .filter(|_| len >= HEADER_SIZE)
+      .ok_or(Error::Corrupted)?;
+    if !is_valid(data) {
+      return Err(Error::Corrupted);
+    }
+    Ok(Self { data })
+  }
+
+  /// Returns the bytes of the whole table.
+  pub fn data(&self) -> &'a [u8] {
+    self.data
+  }
+
+  /// Returns the bytes of the table following the header.
+  pub fn body(&self) -> &'a [u8] {
+    &self.data[HEADER_SIZE..]
+  }
+
+  /// Returns the signature of the table, such as `APIC` for the MADT.
+  pub fn signature(&self) -> &'a [u8; 4] {
+    self.data[..4].try_into().unwrap()
+  }
+
+  /// Returns the revision of the table's format.
+  pub fn revision(&self) -> u8 {
+    self.data[8]
+  }
+
+  /// Returns the ID of the OEM.
+  pub fn oem_id(&self) -> &'a [u8; 6] {
+    self.data[10..16].try_into().unwrap()
+  }
+
+  /// Returns the OEM's ID of the table.
+  pub fn oem_table_id(&self) -> &'a [u8; 8] {
+    self.data[16..24].try_into().unwrap()
+  }
+
+  /// Returns the OEM's revision of the table.
+  pub fn oem_revision(&self) -> u32 {
+    read_u32(self.data, 24)
+  }
+
+  /// Checks that the table has `signature`, failing with
+  /// [`Error::InvalidArgument`] otherwise.
+  ///
+  /// # Arguments
+  ///
+  /// * `signature` - the expected signature
+  fn expect(&self, signature: &[u8; 4]) -> Result<()> {
+    match self.signature() == signature {
+      true => Ok(()),
+      false => Err(Error::InvalidArgument),
+    }
+  }
+}
+
+/// The address of a register, in one of several address spaces, as ACPI
+/// tables describe them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GenericAddress {
+  /// The address space: 0 for memory, 1 for I/O ports, 2 for PCI
+  /// configuration space.
+  pub space: u8,
+
+  /// The width of the register, in bits.
+  pub bit_width: u8,
+
+  /// The offset of the register within the address, in bits.
+  pub bit_offset: u8,
+
+  /// The size of accesses: 1 to 4 for bytes to quadwords, or 0 if
+  /// undefined.
+  pub access_size: u8,
+
+  /// The address of the register within its space.
+  pub address: u64,
+}
+
+impl GenericAddress {
+  /// The address space of memory.
+  pub const SYSTEM_MEMORY: u8 = 0;
+
+  /// The address space of I/O ports.
+  pub const SYSTEM_IO: u8 = 1;
+
+  /// The address space of PCI configuration space.
+  pub const PCI_CONFIG: u8 = 2;
+
+  /// The size of the structure.
+  const SIZE: usize = 12;
+
+  /// Parses the structure at `offset` of `data`, or `None` if `data` is too
+  /// short or the address is zero, which marks an absent register.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the table holding the structure
+  /// * `offset` - the offset of the structure
+  fn parse(data: &[u8], offset: usize) -> Option<Self> {
+    let data = data.get(offset..offset + Self::SIZE)?;
+    let address = Self {
+      space: data[0],
+      bit_width: data[1],
+      bit_offset: data[2],
+      access_size: data[3],
+      address: read_u64(data, 4),
+    };
+    Some(address).filter(|address| address.address != 0)
+  }
+}
+
+/// The tables listed by an RSDP.
+pub struct Tables<'a, M> {
+  rsdp: Rsdp<'a>,
+  root: Sdt<'a>,
+  map: M,
+}
+
+impl<'a, M: Fn(u64, usize) -> Option<&'a [u8]>> Tables<'a, M> {
+  /// Reads the RSDP at `rsdp` and the XSDT, or the RSDT before ACPI 2.0,
+  /// that it points at.
+  ///
+  /// # Arguments
+  ///
+  /// * `rsdp` - the physical address of the RSDP
+  /// * `map` - a function returning the bytes at a physical address and of a
+  ///   length, or `None` if they cannot be read
+  pub fn new(rsdp: u64, map: M) -> Result<Self> {
+    let v1 = map(rsdp, RSDP_V1_SIZE).ok_or(Error::Corrupted)?;
+    let len = match v1.get(15) {
+      Some(&revision) if revision >= 2 => RSDP_V2_SIZE,
+      _ => RSDP_V1_SIZE,
+    };
+    let rsdp = Rsdp::parse(map(rsdp, len).ok_or(Error::Corrupted)?)?;
+    let (address, signature) = match rsdp.xsdt_address() {
+      Some(address) => (address, b"XSDT"),
+      None => (rsdp.rsdt_address() as u64, b"RSDT"),
+    };
+    let root = table(&map, address)?;
+    if root.signature() != signature {
+      return Err(Error::Corrupted);
+    }
+    Ok(Self { rsdp, root, map })
+  }
+
+  /// Returns the RSDP.
+  pub fn rsdp(&self) -> Rsdp<'a> {
+    self.rsdp
+  }
+
+  /// Returns the XSDT, or the RSDT before ACPI 2.0.
+  pub fn root(&self) -> Sdt<'a> {
+    self.root
+  }
+
+  /// Returns an iterator over the tables listed by the root table, skipping
+  /// those that are not valid.
+  pub fn iter(&self) -> TableIter<'_, 'a, M> {
+    let width = match self.root.signature() {
+      b"XSDT" => 8,
+      _ => 4,
+    };
+    TableIter {
+      tables: self,
+      entries: self.root.body().chunks_exact(width),
+    }
+  }
+
+  /// Returns the first valid table with `signature`.
+  ///
+  /// # Arguments
+  ///
+  /// * `signature` - the signature of the table, such as `APIC`
+  pub fn find(&self, signature: &[u8; 4]) -> Option<Sdt<'a>> {
+    self.iter().find(|table| table.signature() == signature)
+  }
+
+  /// Returns the MADT, which describes the interrupt controllers and the
+  /// processors.
+  pub fn madt(&self) -> Option<Madt<'a>> {
+    Madt::parse(self.find(b"APIC")?).ok()
+  }
+
+  /// Returns the FADT, which describes the fixed hardware of ACPI.
+  pub fn fadt(&self) -> Option<Fadt> {
+    Fadt::parse(self.find(b"FACP")?).ok()
+  }
+
+  /// Returns the HPET table, which describes the high precision event timer.
+  pub fn hpet(&self) -> Option<Hpet> {
+    Hpet::parse(self.find(b"HPET")?).ok()
+  }
+
+  /// Returns the MCFG, which describes the memory-mapped PCI Express
+  /// configuration space.
+  pub fn mcfg(&self) -> Option<Mcfg<'a>> {
+    Mcfg::parse(self.find(b"MCFG")?).ok()
+  }
+}
+
+/// An iterator over the valid tables listed by a root table.
+pub struct TableIter<'t, 'a, M> {
+  tables: &'t Tables<'a, M>,
+  entries: core::slice::ChunksExact<'a, u8>,
+}
+
+impl<'a, M: Fn(u64, usize) -> Option<&'a [u8]>> Iterator
+  for TableIter<'_, 'a, M>
+{
+  type Item = Sdt<'a>;
+
+  fn next(&mut self) -> Option<Sdt<'a>> {
+    self.entries.by_ref().find_map(|entry| {
+      let address = match entry.len() {
+        8 => read_u64(entry, 0),
+        _ => read_u32(entry, 0) as u64,
+      };
+      table(&self.tables.map, address).ok()
+    })
+  }
+}
+
+impl<'a, M: Fn(u64, usize) -> Option<&'a [u8]>> FusedIterator
+  for TableIter<'_, 'a, M>
+{
+}
+
+/// Reads the table at the physical `address`.
+///
+/// # Arguments
+///
+/// * `map` - the function returning the bytes at a physical address
+/// * `address` - the physical address of the table
+fn table<'a, M: Fn(u64, usize) -> Option<&'a [u8]>>(
+  map: &M,
+  address: u64,
+) -> Result<Sdt<'a>> {
+  if address == 0 {
+    return Err(Error::Corrupted);
+  }
+  let header = map(address, HEADER_SIZE).ok_or(Error::Corrupted)?;
+  let len = (read_u32(header, 4) as usize).max(HEADER_SIZE);
+  Sdt::parse(map(address, len).ok_or(Error::Corrupted)?)
+}
+
+/// Returns whether the bytes of `data` sum to zero, as the checksums of ACPI
+/// structures require.
+///
+/// # Arguments
+///
+/// * `data` - the structure to check
+fn is_valid(data: &[u8]) -> bool {
+  data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::vec::Vec;
+
+  /// Sets the checksum at `offset` so that the bytes of `data` sum to zero.
+  fn sign(data: &mut [u8], offset: usize) {
+    data[offset] = 0;
+    let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    data[offset] = sum.wrapping_neg();
+  }
+
+  /// Returns a table with `signature` and `body`.
+  fn sdt(signature: &[u8; 4], revision: u8, body: &[u8]) -> Vec<u8> {
+    let mut table = std::vec![0; HEADER_SIZE];
+    table[..4].copy_from_slice(signature);
+    let len = (HEADER_SIZE + body.len()) as u32;
+    table[4..8].copy_from_slice(&len.to_le_bytes());
+    table[8] = revision;
+    table[10..16].copy_from_slice(b"KERNEL");
+    table.extend_from_slice(body);
+    sign(&mut table, 9);
+    table
+  }
+
+  fn madt() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&0xfee0_0000u32.to_le_bytes());
+    body.extend_from_slice(&Madt::PCAT_COMPAT.to_le_bytes());
+    body.extend_from_slice(&[0, 8, 0, 0, 1, 0, 0, 0]);
+    body.extend_from_slice(&[0, 8, 1, 2, 2, 0, 0, 0]);
+    body.extend_from_slice(&[1, 12, 3, 0, 0, 0, 0xc0, 0xfe, 0, 0, 0, 0]);
+    body.extend_from_slice(&[2, 10, 0, 0, 2, 0, 0, 0, 0, 0]);
+    body.extend_from_slice(&[9, 16, 0, 0, 0, 1, 0, 0, 1, 0, 0, 0, 7, 0, 0, 0]);
+    body.extend_from_slice(&[0x80, 4, 1, 2]);
+    sdt(b"APIC", 5, &body)
+  }
+
+  fn fadt() -> Vec<u8> {
+    let mut body = std::vec![0; 244 - HEADER_SIZE];
+    let mut set = |offset: usize, bytes: &[u8]| {
+      body[offset - HEADER_SIZE..][..bytes.len()].copy_from_slice(bytes);
+    };
+    set(40, &0x1000u32.to_le_bytes());
+    set(46, &9u16.to_le_bytes());
+    set(76, &0x608u32.to_le_bytes());
+    set(91, &[4]);
+    set(109, &Fadt::IAPC_8042.to_le_bytes());
+    set(112, &Fadt::FLAG_RESET_REG.to_le_bytes());
+    set(116, &[1, 8, 0, 1, 0xf9, 0x0c, 0, 0, 0, 0, 0, 0]);
+    set(128, &[6]);
+    set(140, &0x2000u64.to_le_bytes());
+    sdt(b"FACP", 6, &body)
+  }
+
+  fn hpet() -> Vec<u8> {
+    let mut body = std::vec![0; 20];
+    body[..4].copy_from_slice(&0x8086_a201u32.to_le_bytes());
+    body[4..16].copy_from_slice(&[0, 64, 0, 0, 0, 0, 0xd0, 0xfe, 0, 0, 0, 0]);
+    body[17..19].copy_from_slice(&0x80u16.to_le_bytes());
+    sdt(b"HPET", 1, &body)
+  }
+
+  fn mcfg() -> Vec<u8> {
+    let mut body = std::vec![0; 8];
+    body.extend_from_slice(&0xb000_0000u64.to_le_bytes());
+    body.extend_from_slice(&[0, 0, 0, 0x7f, 0, 0, 0, 0]);
+    sdt(b"MCFG", 1, &body)
+  }
+
+  /// Returns physical memory holding an ACPI 2.0 RSDP at address 0, and an
+  /// XSDT listing the tables, one of which is corrupted.
+  fn memory() -> Vec<u8> {
+    let mut memory = std::vec![0; 64];
+    let mut addresses = Vec::new();
+    let mut broken = sdt(b"SSDT", 1, &[1, 2, 3]);
+    broken[HEADER_SIZE] ^= 1;
+    let xsdt_len = HEADER_SIZE + 5 * 8;
+    for table in [madt(), fadt(), broken, hpet(), mcfg()] {
+      let address = (64 + xsdt_len + memory.len() - 64) as u64;
+      addresses.extend_from_slice(&address.to_le_bytes());
+      memory.extend_from_slice(&table);
+    }
+    let xsdt = sdt(b"XSDT", 1, &addresses);
+    memory.splice(64..64, xsdt);
+
+    let rsdp = &mut memory[..RSDP_V2_SIZE];
+    rsdp[..8].copy_from_slice(b"RSD PTR ");
+    rsdp[9..15].copy_from_slice(b"KERNEL");
+    rsdp[15] = 2;
+    rsdp[16..20].copy_from_slice(&0xdeadu32.to_le_bytes());
+    rsdp[20..24].copy_from_slice(&(RSDP_V2_SIZE as u32).to_le_bytes());
+    rsdp[24..32].copy_from_slice(&64u64.to_le_bytes());
+    sign(&mut rsdp[..RSDP_V1_SIZE], 8);
+    sign(rsdp, 32);
+    memory
+  }
+
+  fn map<'a>(memory: &'a [u8]) -> impl Fn(u64, usize) -> Option<&'a [u8]> {
+    move |address, len| memory.get(address as usize..)?.get(..len)
+  }
+
+  #[test]
+  fn tables_skip_invalid_tables() {
+    let memory = memory();
+    let tables = Tables::new(0, map(&memory)).unwrap();
+
+    assert_eq!(tables.rsdp().revision(), 2);
+    assert_eq!(tables.root().signature(), b"XSDT");
+    let signatures: Vec<_> =
+      tables.iter().map(|table| *table.signature()).collect();
+    assert_eq!(signatures, [*b"APIC", *b"FACP", *b"HPET", *b"MCFG"]);
+    assert!(tables.find(b"SSDT").is_none());
+  }
+
+  #[test]
+  fn tables_reject_bad_rsdp_checksum() {
+    let mut memory = memory();
+    memory[32] ^= 1;
+
+    assert_eq!(Tables::new(0, map(&memory)).err(), Some(Error::Corrupted));
+  }
+
+  #[test]
+  fn madt_entries_are_typed() {
+    let memory = memory();
+    let madt = Tables::new(0, map(&memory)).unwrap().madt().unwrap();
+    let entries: Vec<_> = madt.entries().collect();
+
+    assert_eq!(madt.local_apic_address(), 0xfee0_0000);
+    assert_eq!(madt.flags(), Madt::PCAT_COMPAT);
+    assert_eq!(entries.len(), 6);
+    assert_eq!(
+      entries[2],
+      MadtEntry::IoApic {
+        id: 3,
+        address: 0xfec0_0000,
+        gsi_base: 0,
+      }
+    );
+    assert_eq!(
+      entries[3],
+      MadtEntry::InterruptSourceOverride {
+        bus: 0,
+        source: 0,
+        gsi: 2,
+        flags: 0,
+      }
+    );
+    assert!(matches!(entries[5], MadtEntry::Other { ty: 0x80, .. }));
+  }
+
+  #[test]
+  fn madt_lists_processors() {
+    let memory = memory();
+    let madt = Tables::new(0, map(&memory)).unwrap().madt().unwrap();
+    let processors: Vec<_> = madt.processors().collect();
+
+    assert_eq!(processors.len(), 3);
+    assert_eq!(
+      processors[1],
+      Processor {
+        uid: 1,
+        id: 2,
+        enabled: false,
+        online_capable: true,
+      }
+    );
+    assert_eq!((processors[2].uid, processors[2].id), (7, 256));
+    assert!(processors[2].enabled);
+  }
+
+  #[test]
+  fn fadt_prefers_extended_addresses() {
+    let memory = memory();
+    let fadt = Tables::new(0, map(&memory)).unwrap().fadt().unwrap();
+
+    assert_eq!(fadt.dsdt, 0x2000);
+    assert_eq!(fadt.sci_interrupt, 9);
+    assert_eq!(fadt.iapc_boot_arch, Fadt::IAPC_8042);
+    let timer = fadt.pm_timer.unwrap();
+    assert_eq!(timer.space, GenericAddress::SYSTEM_IO);
+    assert_eq!((timer.address, timer.bit_width), (0x608, 32));
+    assert_eq!(fadt.reset_register.unwrap().address, 0xcf9);
+    assert_eq!(fadt.reset_value, 6);
+    assert!(fadt.pm1a_event.is_none());
+  }
+
+  #[test]
+  fn hpet_and_mcfg_are_read() {
+    let memory = memory();
+    let tables = Tables::new(0, map(&memory)).unwrap();
+    let hpet = tables.hpet().unwrap();
+    let mcfg = tables.mcfg().unwrap();
+
+    assert_eq!(hpet.address.address, 0xfed0_0000);
+    assert_eq!(hpet.comparator_count(), 3);
+    assert_eq!(hpet.minimum_tick, 0x80);
+    assert_eq!(mcfg.entries().len(), 1);
+    assert_eq!(mcfg.address(0, 1, 2, 3), Some(0xb011_3000));
+    assert_eq!(mcfg.address(0, 0x80, 0, 0), None);
+    assert_eq!(mcfg.address(1, 0, 0, 0), None);
+  }
+
+  #[test]
+  fn typed_tables_check_signatures() {
+    let memory = memory();
+    let tables = Tables::new(0, map(&memory)).unwrap();
+    let hpet = tables.find(b"HPET").unwrap();
+
+    assert_eq!(Madt::parse(hpet).err(), Some(Error::InvalidArgument));
+  }
+}
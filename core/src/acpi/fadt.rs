@@ -0,0 +1,153 @@
+//! This module provides [`Fadt`], the fixed ACPI description table, which
+//! describes the fixed hardware of ACPI and points at the DSDT.
+
+use super::{read_u16, read_u32, read_u64, GenericAddress, Sdt};
+use crate::error::{Error, Result};
+
+/// The size of the FADT of ACPI 1.0, the shortest that is read.
+const MIN_SIZE: usize = 116;
+
+/// The fixed ACPI description table, with the extended 64-bit addresses of
+/// ACPI 2.0 and later preferred over the legacy 32-bit ones.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Fadt {
+  /// The major revision of the table's format.
+  pub revision: u8,
+
+  /// The minor revision of the table's format, or 0 before ACPI 5.1.
+  pub minor_revision: u8,
+
+  /// The physical address of the FACS, or 0 if there is none.
+  pub firmware_ctrl: u64,
+
+  /// The physical address of the DSDT.
+  pub dsdt: u64,
+
+  /// The preferred power management profile, such as 1 for desktops or 2
+  /// for mobile systems.
+  pub preferred_pm_profile: u8,
+
+  /// The interrupt that the SCI is wired to in 8259 mode.
+  pub sci_interrupt: u16,
+
+  /// The I/O port of the SMI command register, or 0 if ACPI is always
+  /// enabled.
+  pub smi_command: u32,
+
+  /// The value written to `smi_command` to enable ACPI.
+  pub acpi_enable: u8,
+
+  /// The value written to `smi_command` to disable ACPI.
+  pub acpi_disable: u8,
+
+  /// The PM1a event register block.
+  pub pm1a_event: Option<GenericAddress>,
+
+  /// The PM1a control register block.
+  pub pm1a_control: Option<GenericAddress>,
+
+  /// The power management timer.
+  pub pm_timer: Option<GenericAddress>,
+
+  /// The index of the century in the RTC's CMOS, or 0 if it has none.
+  pub century: u8,
+
+  /// The `IAPC_*` flags of the legacy devices of x86 systems.
+  pub iapc_boot_arch: u16,
+
+  /// The flags of the boot architecture of Arm systems.
+  pub arm_boot_arch: u16,
+
+  /// The `FLAG_*` fixed feature flags.
+  pub flags: u32,
+
+  /// The register written to reset the system, if [`Fadt::FLAG_RESET_REG`]
+  /// is set.
+  pub reset_register: Option<GenericAddress>,
+
+  /// The value written to `reset_register` to reset the system.
+  pub reset_value: u8,
+}
+
+impl Fadt {
+  /// The flag marking the reset register as supported.
+  pub const FLAG_RESET_REG: u32 = 1 << 10;
+
+  /// The flag marking systems without the fixed hardware of ACPI, whose
+  /// registers are then absent.
+  pub const FLAG_HW_REDUCED_ACPI: u32 = 1 << 20;
+
+  /// The boot architecture flag marking legacy devices on the LPC or ISA
+  /// bus.
+  pub const IAPC_LEGACY_DEVICES: u16 = 1 << 0;
+
+  /// The boot architecture flag marking an 8042 keyboard controller.
+  pub const IAPC_8042: u16 = 1 << 1;
+
+  /// The boot architecture flag marking VGA hardware as absent.
+  pub const IAPC_VGA_NOT_PRESENT: u16 = 1 << 2;
+
+  /// The boot architecture flag marking message signaled interrupts as
+  /// unsupported.
+  pub const IAPC_MSI_NOT_SUPPORTED: u16 = 1 << 3;
+
+  /// The boot architecture flag marking the CMOS RTC as absent.
+  pub const IAPC_CMOS_RTC_NOT_PRESENT: u16 = 1 << 5;
+
+  /// Reads the FADT from `table`, failing with [`Error::InvalidArgument`] if
+  /// it is another table.
+  ///
+  /// # Arguments
+  ///
+  /// * `table` - the table with the signature `FACP`
+  pub fn parse(table: Sdt<'_>) -> Result<Self> {
+    table.expect(b"FACP")?;
+    let data = table.data();
+    if data.len() < MIN_SIZE {
+      return Err(Error::Corrupted);
+    }
+    // Fields added by later revisions read as zero in shorter tables.
+    let byte = |offset: usize| data.get(offset).copied().unwrap_or(0);
+    let wide = |offset: usize, legacy: usize| match data.get(offset..offset + 8)
+    {
+      Some(bytes) if read_u64(bytes, 0) != 0 => read_u64(bytes, 0),
+      _ => read_u32(data, legacy) as u64,
+    };
+    // The extended address of a register block, or else the legacy I/O port
+    // and length.
+    let register = |offset: usize, legacy: usize, len: usize| {
+      GenericAddress::parse(data, offset).or_else(|| {
+        let port = read_u32(data, legacy) as u64;
+        Some(GenericAddress {
+          space: GenericAddress::SYSTEM_IO,
+          bit_width: data[len].wrapping_mul(8),
+          bit_offset: 0,
+          access_size: 0,
+          address: port,
+        })
+        .filter(|_| port != 0)
+      })
+    };
+
+    Ok(Self {
+      revision: table.revision(),
+      minor_revision: byte(131) & 0xf,
+      firmware_ctrl: wide(132, 36),
+      dsdt: wide(140, 40),
+      preferred_pm_profile: data[45],
+      sci_interrupt: read_u16(data, 46),
+      smi_command: read_u32(data, 48),
+      acpi_enable: data[52],
+      acpi_disable: data[53],
+      pm1a_event: register(148, 56, 88),
+      pm1a_control: register(172, 64, 89),
+      pm_timer: register(208, 76, 91),
+      century: data[108],
+      iapc_boot_arch: read_u16(data, 109),
+      arm_boot_arch: data.get(129..131).map_or(0, |arch| read_u16(arch, 0)),
+      flags: read_u32(data, 112),
+      reset_register: GenericAddress::parse(data, 116),
+      reset_value: byte(128),
+    })
+  }
+}
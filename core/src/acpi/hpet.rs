@@ -0,0 +1,58 @@
+//! This module provides [`Hpet`], the table describing the high precision
+//! event timer.
+
+use super::{read_u16, read_u32, GenericAddress, Sdt};
+use crate::error::{Error, Result};
+
+/// The size of the table.
+const SIZE: usize = 56;
+
+/// The high precision event timer description table.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Hpet {
+  /// The hardware ID of the event timer block, holding its number of
+  /// comparators in bits 8 to 12 and its PCI vendor ID in bits 16 to 31.
+  pub event_timer_block_id: u32,
+
+  /// The address of the timer's registers, which are in memory.
+  pub address: GenericAddress,
+
+  /// The sequence number of the timer block.
+  pub number: u8,
+
+  /// The smallest period of the timer in periodic mode that does not lose
+  /// interrupts, in ticks of its main counter.
+  pub minimum_tick: u16,
+
+  /// The protection of the page holding the registers, and the OEM
+  /// attributes.
+  pub page_protection: u8,
+}
+
+impl Hpet {
+  /// Reads the HPET table from `table`, failing with
+  /// [`Error::InvalidArgument`] if it is another table.
+  ///
+  /// # Arguments
+  ///
+  /// * `table` - the table with the signature `HPET`
+  pub fn parse(table: Sdt<'_>) -> Result<Self> {
+    table.expect(b"HPET")?;
+    let data = table.data();
+    if data.len() < SIZE {
+      return Err(Error::Corrupted);
+    }
+    Ok(Self {
+      event_timer_block_id: read_u32(data, 36),
+      address: GenericAddress::parse(data, 40).ok_or(Error::Corrupted)?,
+      number: data[52],
+      minimum_tick: read_u16(data, 53),
+      page_protection: data[55],
+    })
+  }
+
+  /// Returns the number of comparators of the timer block.
+  pub fn comparator_count(&self) -> u8 {
+    ((self.event_timer_block_id >> 8) & 0x1f) as u8 + 1
+  }
+}
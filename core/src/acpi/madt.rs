@@ -0,0 +1,300 @@
+//! This module provides [`Madt`], a view of the multiple APIC description
+//! table, which describes the interrupt controllers and the processors.
+
+use super::{read_u16, read_u32, read_u64, Sdt, HEADER_SIZE};
+use crate::error::{Error, Result};
+use core::iter::FusedIterator;
+
+/// The offset of the first interrupt controller structure.
+const ENTRIES: usize = HEADER_SIZE + 8;
+
+/// The flag of a processor structure marking the processor as enabled.
+const ENABLED: u32 = 1;
+
+/// The flag of a processor structure marking a disabled processor as one
+/// that may be brought online.
+const ONLINE_CAPABLE: u32 = 2;
+
+/// The multiple APIC description table.
+#[derive(Clone, Copy, Debug)]
+pub struct Madt<'a> {
+  table: Sdt<'a>,
+}
+
+impl<'a> Madt<'a> {
+  /// The flag marking systems that also have the dual 8259 PICs, which must
+  /// be masked before the APICs are used.
+  pub const PCAT_COMPAT: u32 = 1;
+
+  /// Reads the MADT from `table`, failing with [`Error::InvalidArgument`] if
+  /// it is another table.
+  ///
+  /// # Arguments
+  ///
+  /// * `table` - the table with the signature `APIC`
+  pub fn parse(table: Sdt<'a>) -> Result<Self> {
+    table.expect(b"APIC")?;
+    if table.data().len() < ENTRIES {
+      return Err(Error::Corrupted);
+    }
+    Ok(Self { table })
+  }
+
+  /// Returns the table.
+  pub fn table(&self) -> Sdt<'a> {
+    self.table
+  }
+
+  /// Returns the physical address of the local APIC of every processor,
+  /// which a [`MadtEntry::LocalApicAddressOverride`] may replace.
+  pub fn local_apic_address(&self) -> u32 {
+    read_u32(self.table.data(), HEADER_SIZE)
+  }
+
+  /// Returns the flags of the table, such as [`Madt::PCAT_COMPAT`].
+  pub fn flags(&self) -> u32 {
+    read_u32(self.table.data(), HEADER_SIZE + 4)
+  }
+
+  /// Returns an iterator over the interrupt controller structures.
+  pub fn entries(&self) -> MadtEntries<'a> {
+    MadtEntries {
+      entries: &self.table.data()[ENTRIES..],
+    }
+  }
+
+  /// Returns an iterator over the processors, whether described by their
+  /// local APIC, local x2APIC or GIC CPU interface.
+  pub fn processors(&self) -> impl Iterator<Item = Processor> + 'a {
+    self.entries().filter_map(|entry| {
+      let (uid, id, flags) = match entry {
+        MadtEntry::LocalApic {
+          uid,
+          apic_id,
+          flags,
+        } => (uid as u32, apic_id as u64, flags),
+        MadtEntry::LocalX2Apic {
+          uid,
+          x2apic_id,
+          flags,
+        } => (uid, x2apic_id as u64, flags),
+        MadtEntry::Gicc {
+          uid, mpidr, flags, ..
+        } => (uid, mpidr, flags),
+        _ => return None,
+      };
+      Some(Processor {
+        uid,
+        id,
+        enabled: flags & ENABLED != 0,
+        online_capable: flags & ONLINE_CAPABLE != 0,
+      })
+    })
+  }
+}
+
+/// A processor described by the MADT.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Processor {
+  /// The ACPI processor UID.
+  pub uid: u32,
+
+  /// The hardware ID of the processor: the ID of its local APIC, or the
+  /// affinity fields of its MPIDR on processors with a GIC.
+  pub id: u64,
+
+  /// Whether the processor is enabled and may be started.
+  pub enabled: bool,
+
+  /// Whether the processor, if disabled, may be brought online later.
+  pub online_capable: bool,
+}
+
+/// An interrupt controller structure of the MADT.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MadtEntry<'a> {
+  /// A processor's local APIC.
+  LocalApic {
+    /// The ACPI processor UID.
+    uid: u8,
+    /// The ID of the local APIC.
+    apic_id: u8,
+    /// The flags, where bit 0 marks the processor as enabled.
+    flags: u32,
+  },
+
+  /// An I/O APIC.
+  IoApic {
+    /// The ID of the I/O APIC.
+    id: u8,
+    /// The physical address of its registers.
+    address: u32,
+    /// The first global system interrupt that it handles.
+    gsi_base: u32,
+  },
+
+  /// A mapping of an ISA interrupt to a global system interrupt that differs
+  /// from the identity.
+  InterruptSourceOverride {
+    /// The bus, which is 0 for ISA.
+    bus: u8,
+    /// The ISA interrupt.
+    source: u8,
+    /// The global system interrupt that it is delivered as.
+    gsi: u32,
+    /// The polarity and trigger mode of the interrupt.
+    flags: u16,
+  },
+
+  /// A global system interrupt wired as a non-maskable interrupt.
+  NmiSource {
+    /// The polarity and trigger mode of the interrupt.
+    flags: u16,
+    /// The global system interrupt.
+    gsi: u32,
+  },
+
+  /// A local APIC input wired as a non-maskable interrupt.
+  LocalApicNmi {
+    /// The ACPI processor UID, or `0xff` for every processor.
+    uid: u8,
+    /// The polarity and trigger mode of the interrupt.
+    flags: u16,
+    /// The local interrupt pin, `LINT0` or `LINT1`.
+    lint: u8,
+  },
+
+  /// The 64-bit physical address of the local APICs, replacing the one of
+  /// the table.
+  LocalApicAddressOverride {
+    /// The physical address.
+    address: u64,
+  },
+
+  /// A processor's local x2APIC, for IDs that do not fit in a byte.
+  LocalX2Apic {
+    /// The ID of the local x2APIC.
+    x2apic_id: u32,
+    /// The flags, where bit 0 marks the processor as enabled.
+    flags: u32,
+    /// The ACPI processor UID.
+    uid: u32,
+  },
+
+  /// A local x2APIC input wired as a non-maskable interrupt.
+  LocalX2ApicNmi {
+    /// The polarity and trigger mode of the interrupt.
+    flags: u16,
+    /// The ACPI processor UID, or `0xffffffff` for every processor.
+    uid: u32,
+    /// The local interrupt pin, `LINT0` or `LINT1`.
+    lint: u8,
+  },
+
+  /// A processor's GIC CPU interface.
+  Gicc {
+    /// The number of the CPU interface.
+    cpu_interface: u32,
+    /// The ACPI processor UID.
+    uid: u32,
+    /// The flags, where bit 0 marks the processor as enabled.
+    flags: u32,
+    /// The physical address of the CPU interface's registers.
+    address: u64,
+    /// The affinity fields of the processor's MPIDR.
+    mpidr: u64,
+  },
+
+  /// The GIC distributor.
+  Gicd {
+    /// The ID of the distributor.
+    id: u32,
+    /// The physical address of its registers.
+    address: u64,
+    /// The version of the GIC, or 0 if it is to be detected.
+    version: u8,
+  },
+
+  /// A structure of a type that is not parsed, or too short for its type.
+  Other {
+    /// The type of the structure.
+    ty: u8,
+    /// The bytes of the structure, including its type and length.
+    data: &'a [u8],
+  },
+}
+
+/// An iterator over the interrupt controller structures of the MADT, which
+/// ends at the first structure extending past the table.
+#[derive(Clone)]
+pub struct MadtEntries<'a> {
+  entries: &'a [u8],
+}
+
+impl<'a> Iterator for MadtEntries<'a> {
+  type Item = MadtEntry<'a>;
+
+  fn next(&mut self) -> Option<MadtEntry<'a>> {
+    let len = *self.entries.get(1)? as usize;
+    let Some(e) = self.entries.get(..len).filter(|_| len >= 2) else {
+      self.entries = &[];
+      return None;
+    };
+    self.entries = &self.entries[len..];
+    Some(match (e[0], len) {
+      (0, 8..) => MadtEntry::LocalApic {
+        uid: e[2],
+        apic_id: e[3],
+        flags: read_u32(e, 4),
+      },
+      (1, 12..) => MadtEntry::IoApic {
+        id: e[2],
+        address: read_u32(e, 4),
+        gsi_base: read_u32(e, 8),
+      },
+      (2, 10..) => MadtEntry::InterruptSourceOverride {
+        bus: e[2],
+        source: e[3],
+        gsi: read_u32(e, 4),
+        flags: read_u16(e, 8),
+      },
+      (3, 8..) => MadtEntry::NmiSource {
+        flags: read_u16(e, 2),
+        gsi: read_u32(e, 4),
+      },
+      (4, 6..) => MadtEntry::LocalApicNmi {
+        uid: e[2],
+        flags: read_u16(e, 3),
+        lint: e[5],
+      },
+      (5, 12..) => MadtEntry::LocalApicAddressOverride {
+        address: read_u64(e, 4),
+      },
+      (9, 16..) => MadtEntry::LocalX2Apic {
+        x2apic_id: read_u32(e, 4),
+        flags: read_u32(e, 8),
+        uid: read_u32(e, 12),
+      },
+      (10, 12..) => MadtEntry::LocalX2ApicNmi {
+        flags: read_u16(e, 2),
+        uid: read_u32(e, 4),
+        lint: e[8],
+      },
+      (11, 76..) => MadtEntry::Gicc {
+        cpu_interface: read_u32(e, 4),
+        uid: read_u32(e, 8),
+        flags: read_u32(e, 12),
+        address: read_u64(e, 32),
+        mpidr: read_u64(e, 68),
+      },
+      (12, 24..) => MadtEntry::Gicd {
+        id: read_u32(e, 4),
+        address: read_u64(e, 8),
+        version: e[20],
+      },
+      (ty, _) => MadtEntry::Other { ty, data: e },
+    })
+  }
+}
+
+impl FusedIterator for MadtEntries<'_> {}
@@ -0,0 +1,138 @@
+//! This module provides [`Mcfg`], the table describing the memory-mapped
+//! configuration space of PCI Express segments.
+
+use super::{read_u16, read_u64, Sdt, HEADER_SIZE};
+use crate::error::{Error, Result};
+use core::iter::FusedIterator;
+
+/// The offset of the first allocation, after 8 reserved bytes.
+const ENTRIES: usize = HEADER_SIZE + 8;
+
+/// The size of an allocation.
+const ENTRY_SIZE: usize = 16;
+
+/// The PCI Express memory-mapped configuration space table.
+#[derive(Clone, Copy, Debug)]
+pub struct Mcfg<'a> {
+  table: Sdt<'a>,
+}
+
+impl<'a> Mcfg<'a> {
+  /// Reads the MCFG from `table`, failing with [`Error::InvalidArgument`] if
+  /// it is another table.
+  ///
+  /// # Arguments
+  ///
+  /// * `table` - the table with the signature `MCFG`
+  pub fn parse(table: Sdt<'a>) -> Result<Self> {
+    table.expect(b"MCFG")?;
+    if table.data().len() < ENTRIES {
+      return Err(Error::Corrupted);
+    }
+    Ok(Self { table })
+  }
+
+  /// Returns an iterator over the configuration space allocations, one per
+  /// range of buses of a segment.
+  pub fn entries(&self) -> McfgEntries<'a> {
+    McfgEntries {
+      entries: self.table.data()[ENTRIES..].chunks_exact(ENTRY_SIZE),
+    }
+  }
+
+  /// Returns the physical address of the configuration space of a function,
+  /// or `None` if no allocation covers its bus.
+  ///
+  /// # Arguments
+  ///
+  /// * `segment` - the PCI segment group
+  /// * `bus` - the bus
+  /// * `device` - the device, below 32
+  /// * `function` - the function, below 8
+  pub fn address(
+    &self,
+    segment: u16,
+    bus: u8,
+    device: u8,
+    function: u8,
+  ) -> Option<u64> {
+    self
+      .entries()
+      .find(|entry| entry.segment == segment && entry.contains(bus))
+      .and_then(|entry| entry.address(bus, device, function))
+  }
+}
+
+/// The configuration space of a range of buses of a PCI segment.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct McfgEntry {
+  /// The physical address of the configuration space of bus 0, even if the
+  /// range starts later.
+  pub base_address: u64,
+
+  /// The PCI segment group.
+  pub segment: u16,
+
+  /// The first bus of the range.
+  pub start_bus: u8,
+
+  /// The last bus of the range.
+  pub end_bus: u8,
+}
+
+impl McfgEntry {
+  /// Returns whether the range holds `bus`.
+  ///
+  /// # Arguments
+  ///
+  /// * `bus` - the bus
+  pub fn contains(&self, bus: u8) -> bool {
+    (self.start_bus..=self.end_bus).contains(&bus)
+  }
+
+  /// Returns the physical address of the 4 KiB configuration space of a
+  /// function, or `None` if the range does not hold its bus or the device or
+  /// function is out of range.
+  ///
+  /// # Arguments
+  ///
+  /// * `bus` - the bus
+  /// * `device` - the device, below 32
+  /// * `function` - the function, below 8
+  pub fn address(&self, bus: u8, device: u8, function: u8) -> Option<u64> {
+    if !self.contains(bus) || device >= 32 || function >= 8 {
+      return None;
+    }
+    let offset =
+      (bus as u64) << 20 | (device as u64) << 15 | (function as u64) << 12;
+    Some(self.base_address + offset)
+  }
+}
+
+/// An iterator over the allocations of the MCFG.
+#[derive(Clone)]
+pub struct McfgEntries<'a> {
+  entries: core::slice::ChunksExact<'a, u8>,
+}
+
+impl Iterator for McfgEntries<'_> {
+  type Item = McfgEntry;
+
+  fn next(&mut self) -> Option<McfgEntry> {
+    let entry = self.entries.next()?;
+    Some(McfgEntry {
+      base_address: read_u64(entry, 0),
+      segment: read_u16(entry, 8),
+      start_bus: entry[10],
+      end_bus: entry[11],
+    })
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.entries.size_hint()
+  }
+}
+
+impl ExactSizeIterator for McfgEntries<'_> {}
+
+impl FusedIterator for McfgEntries<'_> {}
@@ -0,0 +1,138 @@
+//! This module provides the assets built into binaries with
+//! [`embed_asset!`](crate::embed_asset), such as the default console font,
+//! the boot logo, and the public keys and digests that payloads are checked
+//! against.
+//!
+//! Each asset is held by a type that says what the file is, and whose
+//! `const fn embed` checks that it is: since statics are evaluated at
+//! compile time, a file that is not what its type says fails the build
+//! rather than the boot. The types here are [`Font`] and [`Bitmap`]; the
+//! `crypto` crate embeds digests and public keys the same way.
+
+use crate::bmp::{self, Bmp};
+use crate::font;
+
+/// Builds files into the binary as statics of the types that read them.
+///
+/// Each static is given a type and the path of a file, resolved as by
+/// [`include_bytes!`], which is relative to the file that the macro is used
+/// in. The type must have a `const fn embed(data: &'static [u8]) -> Self`
+/// that panics if the file is not one it reads, which fails the build.
+///
+/// ```ignore
+/// kcore::embed_asset! {
+///   /// The font that the console draws text with.
+///   pub static CONSOLE_FONT: kcore::asset::Font = "../assets/console.psf";
+///
+///   /// The digest that the kernel is expected to have.
+///   static KERNEL_SHA256: crypto::sha256::Digest = "../kernel.sha256";
+/// }
+/// ```
+#[macro_export]
+macro_rules! embed_asset {
+  ($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $path:expr;)*) => {
+    $(
+      $(#[$attr])*
+      $vis static $name: $ty = <$ty>::embed(include_bytes!($path));
+    )*
+  };
+}
+
+/// A PC Screen Font built into the binary, which is checked at compile time
+/// to be one that [`font::Font::parse`] reads.
+#[derive(Clone, Copy, Debug)]
+pub struct Font {
+  data: &'static [u8],
+}
+
+impl Font {
+  /// Constructs the asset of the PSF1 or PSF2 font held in `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the font
+  ///
+  /// # Panics
+  ///
+  /// Panics, failing the build when evaluated at compile time, if `data` is
+  /// not a font that is read.
+  pub const fn embed(data: &'static [u8]) -> Self {
+    assert!(
+      font::is_valid(data),
+      "embedded font is not a valid PSF font"
+    );
+    Self { data }
+  }
+
+  /// Returns the font.
+  pub fn font(&self) -> font::Font<'static> {
+    match font::Font::parse(self.data) {
+      Ok(font) => font,
+      Err(_) => unreachable!("embedded fonts are checked when embedded"),
+    }
+  }
+
+  /// Returns the contents of the file.
+  pub const fn as_bytes(&self) -> &'static [u8] {
+    self.data
+  }
+}
+
+/// A BMP image built into the binary, such as a boot logo, which is checked
+/// at compile time to be one that [`Bmp::parse`] reads.
+#[derive(Clone, Copy, Debug)]
+pub struct Bitmap {
+  data: &'static [u8],
+}
+
+impl Bitmap {
+  /// Constructs the asset of the BMP image held in `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the contents of the file
+  ///
+  /// # Panics
+  ///
+  /// Panics, failing the build when evaluated at compile time, if `data` is
+  /// not an image that is read.
+  pub const fn embed(data: &'static [u8]) -> Self {
+    assert!(bmp::is_valid(data), "embedded image is not a readable BMP");
+    Self { data }
+  }
+
+  /// Returns the image.
+  pub fn image(&self) -> Bmp<'static> {
+    match Bmp::parse(self.data) {
+      Ok(image) => image,
+      Err(_) => unreachable!("embedded images are checked when embedded"),
+    }
+  }
+
+  /// Returns the contents of the file.
+  pub const fn as_bytes(&self) -> &'static [u8] {
+    self.data
+  }
+}
+
+#[cfg(test)]
+mod test {
+  /// An asset of any bytes.
+  struct Raw(&'static [u8]);
+
+  impl Raw {
+    const fn embed(data: &'static [u8]) -> Self {
+      Self(data)
+    }
+  }
+
+  crate::embed_asset! {
+    /// The manifest of this crate, found relative to this file.
+    static MANIFEST: Raw = "../Cargo.toml";
+  }
+
+  #[test]
+  fn assets_are_included_relative_to_their_file() {
+    assert!(MANIFEST.0.starts_with(b"[package]"));
+  }
+}
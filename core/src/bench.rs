@@ -0,0 +1,427 @@
+//! This module provides the measurement of hot paths in cycles, to track how
+//! fast code such as hashing, allocation and context switches runs on real
+//! hardware and under QEMU, and to catch it getting slower.
+//!
+//! A [`Bench`] is a named scope, usually a static, whose runs are measured
+//! with [`Bench::measure`] or the guard of [`Bench::scope`]. Each run is timed
+//! with [`arch::cycle_counter_begin`] and [`arch::cycle_counter_end`], which
+//! keep the code around the scope from being counted in it. The count, least,
+//! mean and most cycles of the runs are kept exactly; their percentiles are
+//! estimated from a [`Reservoir`] of a fixed number of runs, picked
+//! uniformly from all of them, so that a bench never allocates however often
+//! it runs.
+//!
+//! Benches are listed by the code that reports them, such as the test build
+//! of a binary, and reported over the logging layer with [`log_reports`].
+//!
+//! ```ignore
+//! static SHA256: Bench = Bench::new("sha256");
+//!
+//! let digest = SHA256.measure(|| sha256::hash_bytes(data));
+//! bench::log_reports(&[&SHA256], &mut *log::global());
+//! ```
+
+use crate::log::{Level, Sink};
+use crate::math::{mul_div_usize, Rounding};
+use crate::sync::IrqMutex;
+use core::fmt;
+
+/// The number of runs that a [`Bench`] keeps for its percentiles, unless it
+/// is given another.
+pub const DEFAULT_SAMPLES: usize = 256;
+
+/// A sample of at most `N` values, picked uniformly from all of those
+/// recorded, however many there are.
+///
+/// Until `N` values have been recorded, the sample is all of them. After
+/// that, each value replaces a random one of the sample, with a chance that
+/// falls as more are recorded, so that every value recorded is equally likely
+/// to be in it. The sample is picked with a fixed seed, so a run of the same
+/// values keeps the same sample.
+#[derive(Clone, Debug)]
+pub struct Reservoir<const N: usize> {
+  samples: [u64; N],
+  len: usize,
+  seen: u64,
+  state: u64,
+}
+
+impl<const N: usize> Reservoir<N> {
+  /// Constructs an empty reservoir.
+  pub const fn new() -> Self {
+    Self {
+      samples: [0; N],
+      len: 0,
+      seen: 0,
+      state: 0x9e37_79b9_7f4a_7c15,
+    }
+  }
+
+  /// Records `value`, which joins the sample if it is not full, and may
+  /// replace one of it otherwise.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to record
+  pub fn record(&mut self, value: u64) {
+    self.seen += 1;
+    if self.len < N {
+      self.samples[self.len] = value;
+      self.len += 1;
+      return;
+    }
+    let index = self.next_random() % self.seen;
+    if let Some(sample) = self.samples.get_mut(index as usize) {
+      *sample = value;
+    }
+  }
+
+  /// Returns the values of the sample, in no particular order.
+  pub fn samples(&self) -> &[u64] {
+    &self.samples[..self.len]
+  }
+
+  /// Returns the number of values recorded, which may be more than the
+  /// sample holds.
+  pub fn seen(&self) -> u64 {
+    self.seen
+  }
+
+  /// Returns the `percentile`th percentile of the sample, by the nearest
+  /// rank, or `None` if nothing has been recorded.
+  ///
+  /// # Arguments
+  ///
+  /// * `percentile` - the percentile, from 0 to 100
+  pub fn percentile(&self, percentile: usize) -> Option<u64> {
+    let mut sorted = self.samples;
+    let sorted = &mut sorted[..self.len];
+    sorted.sort_unstable();
+    percentile_of(sorted, percentile)
+  }
+
+  /// Forgets every value recorded.
+  pub fn clear(&mut self) {
+    self.len = 0;
+    self.seen = 0;
+  }
+
+  /// Returns the next number of the xorshift64* generator that picks the
+  /// values replaced.
+  fn next_random(&mut self) -> u64 {
+    self.state ^= self.state >> 12;
+    self.state ^= self.state << 25;
+    self.state ^= self.state >> 27;
+    self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+  }
+}
+
+impl<const N: usize> Default for Reservoir<N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// The statistics of the runs of a scope, in cycles: their count, least,
+/// most and mean exactly, and their percentiles from a [`Reservoir`] of `N`
+/// of them.
+#[derive(Clone, Debug)]
+pub struct Stats<const N: usize = DEFAULT_SAMPLES> {
+  count: u64,
+  total: u128,
+  min: u64,
+  max: u64,
+  reservoir: Reservoir<N>,
+}
+
+impl<const N: usize> Stats<N> {
+  /// Constructs the statistics of no runs.
+  pub const fn new() -> Self {
+    Self {
+      count: 0,
+      total: 0,
+      min: u64::MAX,
+      max: 0,
+      reservoir: Reservoir::new(),
+    }
+  }
+
+  /// Records a run of `cycles` cycles.
+  ///
+  /// # Arguments
+  ///
+  /// * `cycles` - the length of the run
+  pub fn record(&mut self, cycles: u64) {
+    self.count += 1;
+    self.total += cycles as u128;
+    self.min = self.min.min(cycles);
+    self.max = self.max.max(cycles);
+    self.reservoir.record(cycles);
+  }
+
+  /// Returns the number of runs recorded.
+  pub fn count(&self) -> u64 {
+    self.count
+  }
+
+  /// Returns the sample of runs that percentiles are estimated from.
+  pub fn reservoir(&self) -> &Reservoir<N> {
+    &self.reservoir
+  }
+
+  /// Returns the summary of the runs, or `None` if there have been none.
+  pub fn summary(&self) -> Option<Summary> {
+    if self.count == 0 {
+      return None;
+    }
+    let mut sorted = self.reservoir.samples;
+    let sorted = &mut sorted[..self.reservoir.len];
+    sorted.sort_unstable();
+    let percentile = |p| percentile_of(sorted, p).unwrap_or_default();
+    Some(Summary {
+      count: self.count,
+      min: self.min,
+      mean: (self.total / self.count as u128) as u64,
+      max: self.max,
+      p50: percentile(50),
+      p90: percentile(90),
+      p99: percentile(99),
+    })
+  }
+
+  /// Forgets every run recorded.
+  pub fn clear(&mut self) {
+    *self = Self::new();
+  }
+}
+
+impl<const N: usize> Default for Stats<N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// The summary of the runs of a scope, in cycles.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Summary {
+  /// The number of runs.
+  pub count: u64,
+
+  /// The fewest cycles of a run.
+  pub min: u64,
+
+  /// The mean cycles of the runs, rounded down.
+  pub mean: u64,
+
+  /// The most cycles of a run.
+  pub max: u64,
+
+  /// The median, estimated from the sample.
+  pub p50: u64,
+
+  /// The 90th percentile, estimated from the sample.
+  pub p90: u64,
+
+  /// The 99th percentile, estimated from the sample.
+  pub p99: u64,
+}
+
+impl fmt::Display for Summary {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} runs, cycles min {} mean {} p50 {} p90 {} p99 {} max {}",
+      self.count, self.min, self.mean, self.p50, self.p90, self.p99, self.max
+    )
+  }
+}
+
+/// A named scope whose runs are measured in cycles, which may be shared by
+/// every processor.
+///
+/// Runs are recorded under a lock with interrupts disabled, after they have
+/// been measured, so the lock is not counted in them.
+pub struct Bench<const N: usize = DEFAULT_SAMPLES> {
+  name: &'static str,
+  stats: IrqMutex<Stats<N>>,
+}
+
+impl<const N: usize> Bench<N> {
+  /// Constructs a bench with no runs.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - the name that the bench is reported by
+  pub const fn new(name: &'static str) -> Self {
+    Self {
+      name,
+      stats: IrqMutex::new(Stats::new()),
+    }
+  }
+
+  /// Returns the name of the bench.
+  pub fn name(&self) -> &'static str {
+    self.name
+  }
+
+  /// Runs `f`, recording how many cycles it took, and returns its result.
+  ///
+  /// # Arguments
+  ///
+  /// * `f` - the code to measure
+  pub fn measure<R>(&self, f: impl FnOnce() -> R) -> R {
+    let start = arch::cycle_counter_begin();
+    let result = f();
+    let end = arch::cycle_counter_end();
+    self.record(end.wrapping_sub(start));
+    result
+  }
+
+  /// Returns a guard that records the cycles from now until it is dropped,
+  /// for scopes that do not fit in a closure.
+  pub fn scope(&self) -> Scope<'_, N> {
+    Scope {
+      bench: self,
+      start: arch::cycle_counter_begin(),
+    }
+  }
+
+  /// Records a run of `cycles` cycles, measured by other means.
+  ///
+  /// # Arguments
+  ///
+  /// * `cycles` - the length of the run
+  pub fn record(&self, cycles: u64) {
+    self.stats.lock().record(cycles);
+  }
+
+  /// Returns the summary of the runs, or `None` if there have been none.
+  pub fn summary(&self) -> Option<Summary> {
+    self.stats.lock().summary()
+  }
+
+  /// Forgets every run recorded, such as those of warming up.
+  pub fn reset(&self) {
+    self.stats.lock().clear();
+  }
+}
+
+/// A guard that records a run of a [`Bench`] when it is dropped; see
+/// [`Bench::scope`].
+#[must_use = "the run ends when the guard is dropped"]
+pub struct Scope<'a, const N: usize> {
+  bench: &'a Bench<N>,
+  start: u64,
+}
+
+impl<const N: usize> Drop for Scope<'_, N> {
+  fn drop(&mut self) {
+    let end = arch::cycle_counter_end();
+    self.bench.record(end.wrapping_sub(self.start));
+  }
+}
+
+/// Logs the summary of each of `benches` to `logger`, as a record of
+/// [`Level::Info`] targeted at this module, or that it has not run.
+///
+/// The summaries are taken before anything is logged, so that the global
+/// logger may be given while it is locked, as by
+/// [`log::global`](crate::log::global).
+///
+/// # Arguments
+///
+/// * `benches` - the benches to report
+/// * `logger` - the sink to log the reports to
+pub fn log_reports<const N: usize>(
+  benches: &[&Bench<N>],
+  logger: &mut dyn Sink,
+) {
+  for bench in benches {
+    match bench.summary() {
+      Some(summary) => {
+        crate::log!(logger: logger, Level::Info, "{}: {}", bench.name, summary)
+      }
+      None => {
+        crate::log!(logger: logger, Level::Info, "{}: no runs", bench.name)
+      }
+    }
+  }
+}
+
+/// Returns the `percentile`th percentile of `sorted` by the nearest rank, or
+/// `None` if it is empty.
+///
+/// # Arguments
+///
+/// * `sorted` - the values, in ascending order
+/// * `percentile` - the percentile, from 0 to 100
+fn percentile_of(sorted: &[u64], percentile: usize) -> Option<u64> {
+  let rank =
+    mul_div_usize(percentile.min(100), sorted.len(), 100, Rounding::Up)?;
+  sorted.get(rank.saturating_sub(1)).copied()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn stats_summarize_runs() {
+    let mut stats = Stats::<128>::new();
+    assert_eq!(stats.summary(), None);
+    for cycles in (1..=100).rev() {
+      stats.record(cycles);
+    }
+
+    assert_eq!(
+      stats.summary(),
+      Some(Summary {
+        count: 100,
+        min: 1,
+        mean: 50,
+        max: 100,
+        p50: 50,
+        p90: 90,
+        p99: 99,
+      })
+    );
+    stats.clear();
+    assert_eq!(stats.count(), 0);
+  }
+
+  #[test]
+  fn reservoirs_keep_a_uniform_sample() {
+    let mut reservoir = Reservoir::<64>::new();
+    for value in 0..10_000 {
+      reservoir.record(value);
+    }
+
+    assert_eq!((reservoir.samples().len(), reservoir.seen()), (64, 10_000));
+    // Half of the values are below 5000, so the median of a uniform sample
+    // is near it; one of only the first values would be far below.
+    let median = reservoir.percentile(50).unwrap();
+    assert!((3000..7000).contains(&median), "median {}", median);
+    assert_eq!(Reservoir::<4>::new().percentile(50), None);
+  }
+
+  #[test]
+  fn benches_record_measured_runs() {
+    let bench = Bench::<8>::new("test");
+    assert_eq!(bench.summary(), None);
+
+    assert_eq!(bench.measure(|| 42), 42);
+    drop(bench.scope());
+    bench.record(7);
+    assert_eq!(bench.summary().map(|summary| summary.count), Some(3));
+    bench.reset();
+    assert_eq!(bench.summary(), None);
+  }
+
+  #[test]
+  fn serialized_reads_advance() {
+    let start = arch::cycle_counter_begin();
+    let end = arch::cycle_counter_end();
+
+    assert!(end >= start);
+  }
+}
@@ -0,0 +1,342 @@
+//! This module provides the [`bitflags!`](crate::bitflags!) macro, which
+//! declares a type for a set of flags, each of which is one or more bits of an
+//! integer, such as the flags of a page table entry, of a control register or
+//! of opening a file.
+//!
+//! ```ignore
+//! kcore::bitflags! {
+//!   /// The modes that a file can be opened in.
+//!   pub struct OpenMode: u64 {
+//!     /// The file is read from.
+//!     const READ = 0x1;
+//!     /// The file is written to.
+//!     const WRITE = 0x2;
+//!   }
+//! }
+//!
+//! let mode = OpenMode::READ | OpenMode::WRITE;
+//! assert!(mode.contains(OpenMode::READ));
+//! ```
+//!
+//! The type is a transparent wrapper around the integer, so that it can be
+//! used directly in structures shared with hardware or firmware. Its `Debug`
+//! output names the flags that are set, followed by any bits that are not
+//! those of a flag.
+
+/// Declares a type for a set of flags; see the [module](crate::bitflags).
+#[macro_export]
+macro_rules! bitflags {
+  (
+    $(#[$meta:meta])*
+    $vis:vis struct $name:ident: $bits:ty {
+      $(
+        $(#[$flag_meta:meta])*
+        const $flag:ident = $value:expr;
+      )*
+    }
+  ) => {
+    $(#[$meta])*
+    #[repr(transparent)]
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+    $vis struct $name($bits);
+
+    #[allow(dead_code)]
+    impl $name {
+      $(
+        $(#[$flag_meta])*
+        pub const $flag: Self = Self($value);
+      )*
+
+      /// Every flag, with its name, in the order they are declared.
+      pub const FLAGS: &'static [(&'static str, Self)] =
+        &[$((stringify!($flag), Self::$flag)),*];
+
+      /// Returns the set of no flags.
+      pub const fn empty() -> Self {
+        Self(0)
+      }
+
+      /// Returns the set of every flag.
+      pub const fn all() -> Self {
+        Self(0 $(| $value)*)
+      }
+
+      /// Returns the bits of the set.
+      pub const fn bits(self) -> $bits {
+        self.0
+      }
+
+      /// Returns the set with `bits`, or `None` if any of them are not those
+      /// of a flag.
+      ///
+      /// # Arguments
+      ///
+      /// * `bits` - the bits of the set
+      pub const fn from_bits(bits: $bits) -> Option<Self> {
+        if bits & !Self::all().0 == 0 {
+          Some(Self(bits))
+        } else {
+          None
+        }
+      }
+
+      /// Returns the set with `bits`, dropping any that are not those of a
+      /// flag.
+      ///
+      /// # Arguments
+      ///
+      /// * `bits` - the bits of the set
+      pub const fn from_bits_truncate(bits: $bits) -> Self {
+        Self(bits & Self::all().0)
+      }
+
+      /// Returns the set with `bits`, keeping any that are not those of a
+      /// flag, such as bits defined by a newer version of a specification.
+      ///
+      /// # Arguments
+      ///
+      /// * `bits` - the bits of the set
+      pub const fn from_bits_retain(bits: $bits) -> Self {
+        Self(bits)
+      }
+
+      /// Returns whether no flags are set.
+      pub const fn is_empty(self) -> bool {
+        self.0 == 0
+      }
+
+      /// Returns whether every flag is set.
+      pub const fn is_all(self) -> bool {
+        self.0 & Self::all().0 == Self::all().0
+      }
+
+      /// Returns whether every flag of `other` is set.
+      ///
+      /// # Arguments
+      ///
+      /// * `other` - the flags to look for
+      pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+      }
+
+      /// Returns whether any flag of `other` is set.
+      ///
+      /// # Arguments
+      ///
+      /// * `other` - the flags to look for
+      pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+      }
+
+      /// Returns the flags set in either `self` or `other`.
+      ///
+      /// # Arguments
+      ///
+      /// * `other` - the other flags
+      pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+      }
+
+      /// Returns the flags set in both `self` and `other`.
+      ///
+      /// # Arguments
+      ///
+      /// * `other` - the other flags
+      pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+      }
+
+      /// Returns the flags set in `self` but not in `other`.
+      ///
+      /// # Arguments
+      ///
+      /// * `other` - the other flags
+      pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+      }
+
+      /// Returns the flags not set in `self`.
+      pub const fn complement(self) -> Self {
+        Self(!self.0 & Self::all().0)
+      }
+
+      /// Sets the flags of `other`.
+      ///
+      /// # Arguments
+      ///
+      /// * `other` - the flags to set
+      pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+      }
+
+      /// Clears the flags of `other`.
+      ///
+      /// # Arguments
+      ///
+      /// * `other` - the flags to clear
+      pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+      }
+
+      /// Flips the flags of `other`.
+      ///
+      /// # Arguments
+      ///
+      /// * `other` - the flags to flip
+      pub fn toggle(&mut self, other: Self) {
+        self.0 ^= other.0;
+      }
+
+      /// Sets the flags of `other` if `value` is `true`, and otherwise clears
+      /// them.
+      ///
+      /// # Arguments
+      ///
+      /// * `other` - the flags to set or clear
+      /// * `value` - whether to set them
+      pub fn set(&mut self, other: Self, value: bool) {
+        if value {
+          self.insert(other);
+        } else {
+          self.remove(other);
+        }
+      }
+    }
+
+    impl ::core::ops::BitOr for $name {
+      type Output = Self;
+
+      fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+      }
+    }
+
+    impl ::core::ops::BitOrAssign for $name {
+      fn bitor_assign(&mut self, rhs: Self) {
+        self.insert(rhs);
+      }
+    }
+
+    impl ::core::ops::BitAnd for $name {
+      type Output = Self;
+
+      fn bitand(self, rhs: Self) -> Self {
+        self.intersection(rhs)
+      }
+    }
+
+    impl ::core::ops::BitAndAssign for $name {
+      fn bitand_assign(&mut self, rhs: Self) {
+        *self = self.intersection(rhs);
+      }
+    }
+
+    impl ::core::ops::BitXor for $name {
+      type Output = Self;
+
+      fn bitxor(self, rhs: Self) -> Self {
+        Self(self.0 ^ rhs.0)
+      }
+    }
+
+    impl ::core::ops::BitXorAssign for $name {
+      fn bitxor_assign(&mut self, rhs: Self) {
+        self.toggle(rhs);
+      }
+    }
+
+    impl ::core::ops::Sub for $name {
+      type Output = Self;
+
+      fn sub(self, rhs: Self) -> Self {
+        self.difference(rhs)
+      }
+    }
+
+    impl ::core::ops::SubAssign for $name {
+      fn sub_assign(&mut self, rhs: Self) {
+        self.remove(rhs);
+      }
+    }
+
+    impl ::core::ops::Not for $name {
+      type Output = Self;
+
+      fn not(self) -> Self {
+        self.complement()
+      }
+    }
+
+    impl ::core::fmt::Debug for $name {
+      fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        write!(f, "{}(", stringify!($name))?;
+        let mut rest = self.0;
+        let mut separator = "";
+        for &(name, flag) in Self::FLAGS {
+          if flag.0 != 0 && self.contains(flag) && rest & flag.0 != 0 {
+            write!(f, "{}{}", separator, name)?;
+            rest &= !flag.0;
+            separator = " | ";
+          }
+        }
+        if rest != 0 {
+          write!(f, "{}{:#x}", separator, rest)?;
+        } else if separator.is_empty() {
+          f.write_str("empty")?;
+        }
+        f.write_str(")")
+      }
+    }
+  };
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use std::format;
+
+  crate::bitflags! {
+    /// Flags for testing.
+    struct Flags: u8 {
+      const A = 0x1;
+      const B = 0x2;
+      const AB = 0x3;
+      const C = 0x8;
+    }
+  }
+
+  #[test]
+  fn sets_are_combined_and_queried() {
+    let mut flags = Flags::A | Flags::C;
+    assert!(flags.contains(Flags::A) && !flags.contains(Flags::AB));
+    assert!(flags.intersects(Flags::AB));
+    flags.insert(Flags::B);
+    assert!(flags.contains(Flags::AB) && flags.is_all());
+    flags.remove(Flags::A);
+    flags.set(Flags::C, false);
+    assert_eq!(flags, Flags::B);
+    assert_eq!(!flags, Flags::A | Flags::C);
+    assert_eq!(Flags::all() - Flags::AB, Flags::C);
+    assert!(Flags::empty().is_empty());
+  }
+
+  #[test]
+  fn unknown_bits_are_rejected_or_dropped() {
+    assert_eq!(Flags::from_bits(0x9), Some(Flags::A | Flags::C));
+    assert_eq!(Flags::from_bits(0x14), None);
+    assert_eq!(Flags::from_bits_truncate(0x14).bits(), 0);
+    assert_eq!(Flags::from_bits_retain(0x14).bits(), 0x14);
+  }
+
+  #[test]
+  fn debug_names_the_flags_set() {
+    assert_eq!(format!("{:?}", Flags::empty()), "Flags(empty)");
+    assert_eq!(format!("{:?}", Flags::A | Flags::C), "Flags(A | C)");
+    assert_eq!(format!("{:?}", Flags::AB), "Flags(A | B)");
+    assert_eq!(
+      format!("{:?}", Flags::from_bits_retain(0x19)),
+      "Flags(A | C | 0x10)"
+    );
+  }
+}
@@ -0,0 +1,150 @@
+//! This module provides [`BlockDevice`], the interface to storage that is
+//! read and written in whole blocks, such as disks through the firmware's
+//! Block I/O protocol in the bootloader, or AHCI and virtio in the kernel.
+//!
+//! The formats stored on such devices, such as the GUID partition tables in
+//! [`gpt`](crate::gpt), are written once against this interface.
+
+use crate::error::{Error, Result};
+
+/// A device read and written in blocks of a fixed size, addressed by their
+/// logical block address (LBA).
+pub trait BlockDevice {
+  /// Returns the size of the device's blocks, in bytes.
+  fn block_size(&self) -> usize;
+
+  /// Returns the number of blocks of the device.
+  fn block_count(&self) -> u64;
+
+  /// Reads whole blocks starting at `lba` into `buffer`, whose length is a
+  /// multiple of the block size.
+  ///
+  /// # Arguments
+  ///
+  /// * `lba` - the first block to read
+  /// * `buffer` - the buffer to read the blocks into
+  fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<()>;
+
+  /// Writes whole blocks starting at `lba` from `buffer`, whose length is a
+  /// multiple of the block size.
+  ///
+  /// Read-only devices keep the default, which fails with
+  /// [`Error::PermissionDenied`].
+  ///
+  /// # Arguments
+  ///
+  /// * `lba` - the first block to write
+  /// * `buffer` - the contents of the blocks
+  fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> Result<()> {
+    let _ = (lba, buffer);
+    Err(Error::PermissionDenied)
+  }
+
+  /// Waits for the blocks written so far to reach the medium.
+  fn flush(&mut self) -> Result<()> {
+    Ok(())
+  }
+}
+
+impl<D: BlockDevice + ?Sized> BlockDevice for &mut D {
+  fn block_size(&self) -> usize {
+    (**self).block_size()
+  }
+
+  fn block_count(&self) -> u64 {
+    (**self).block_count()
+  }
+
+  fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<()> {
+    (**self).read_blocks(lba, buffer)
+  }
+
+  fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> Result<()> {
+    (**self).write_blocks(lba, buffer)
+  }
+
+  fn flush(&mut self) -> Result<()> {
+    (**self).flush()
+  }
+}
+
+/// A contiguous range of the blocks of a device, such as a partition,
+/// addressed from its first block.
+pub struct Region<D> {
+  device: D,
+  start: u64,
+  count: u64,
+}
+
+impl<D: BlockDevice> Region<D> {
+  /// Constructs the region of `count` blocks of `device` starting at `start`.
+  ///
+  /// # Arguments
+  ///
+  /// * `device` - the device holding the region
+  /// * `start` - the first block of the region
+  /// * `count` - the number of blocks of the region
+  ///
+  /// # Panics
+  ///
+  /// Panics if the region extends past the end of `device`.
+  pub fn new(device: D, start: u64, count: u64) -> Self {
+    assert!(
+      start
+        .checked_add(count)
+        .is_some_and(|end| end <= device.block_count()),
+      "region extends past the end of the device"
+    );
+    Self {
+      device,
+      start,
+      count,
+    }
+  }
+
+  /// Returns the device holding the region.
+  pub fn into_inner(self) -> D {
+    self.device
+  }
+
+  /// Checks that the `len` bytes at `lba` are whole blocks of the region.
+  ///
+  /// # Arguments
+  ///
+  /// * `lba` - the first block, relative to the region
+  /// * `len` - the number of bytes
+  fn check(&self, lba: u64, len: usize) -> Result<()> {
+    let size = self.device.block_size();
+    let blocks = (len / size) as u64;
+    if len % size != 0
+      || lba.checked_add(blocks).map_or(true, |end| end > self.count)
+    {
+      return Err(Error::InvalidArgument);
+    }
+    Ok(())
+  }
+}
+
+impl<D: BlockDevice> BlockDevice for Region<D> {
+  fn block_size(&self) -> usize {
+    self.device.block_size()
+  }
+
+  fn block_count(&self) -> u64 {
+    self.count
+  }
+
+  fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<()> {
+    self.check(lba, buffer.len())?;
+    self.device.read_blocks(self.start + lba, buffer)
+  }
+
+  fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> Result<()> {
+    self.check(lba, buffer.len())?;
+    self.device.write_blocks(self.start + lba, buffer)
+  }
+
+  fn flush(&mut self) -> Result<()> {
+    self.device.flush()
+  }
+}
@@ -0,0 +1,426 @@
+//! This module provides decoding of BMP images, for splash screens and other
+//! pictures drawn before there is anything to decode richer formats with.
+//!
+//! Images are read in place and nothing is allocated: rows of pixels are
+//! decoded as they are iterated over, from the top of the image down whether
+//! the file stores them top-down or bottom-up. Only uncompressed images of 24
+//! or 32 bits per pixel are read.
+//!
+//! Malformed images fail with [`Error::Corrupted`], and images in a form that
+//! is not read, such as compressed or palette images, with
+//! [`Error::Unsupported`].
+
+use crate::error::{Error, Result};
+use core::iter::FusedIterator;
+
+/// The magic number that BMP files start with.
+const MAGIC: &[u8; 2] = b"BM";
+
+/// The size of the file header, which the info header follows.
+const FILE_HEADER_SIZE: usize = 14;
+
+/// The size of the smallest info header that is read, `BITMAPINFOHEADER`.
+/// Later versions extend it.
+const INFO_HEADER_SIZE: usize = 40;
+
+/// The compression of uncompressed images, `BI_RGB`.
+const COMPRESSION_NONE: u32 = 0;
+
+/// The largest width or height of an image that is decoded, in pixels.
+pub const MAX_DIMENSION: usize = 1 << 15;
+
+/// The color of a pixel.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Pixel {
+  /// The red component of the color.
+  pub red: u8,
+
+  /// The green component of the color.
+  pub green: u8,
+
+  /// The blue component of the color.
+  pub blue: u8,
+}
+
+/// A BMP image.
+#[derive(Clone, Copy, Debug)]
+pub struct Bmp<'a> {
+  pixels: &'a [u8],
+  width: usize,
+  height: usize,
+  bytes_per_pixel: usize,
+  stride: usize,
+  top_down: bool,
+}
+
+impl<'a> Bmp<'a> {
+  /// Reads the BMP image held in `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the contents of the file
+  pub fn parse(data: &'a [u8]) -> Result<Self> {
+    let header = data
+      .get(..FILE_HEADER_SIZE + INFO_HEADER_SIZE)
+      .ok_or(Error::Corrupted)?;
+    if &header[..2] != MAGIC {
+      return Err(Error::Corrupted);
+    }
+    let offset = read_u32(header, 10) as usize;
+    let info_size = read_u32(header, 14) as usize;
+    if info_size < INFO_HEADER_SIZE {
+      // The 12-byte `BITMAPCOREHEADER` of OS/2 images.
+      return Err(Error::Unsupported);
+    }
+    let width = read_u32(header, 18) as i32;
+    let height = read_u32(header, 22) as i32;
+    let planes = read_u16(header, 26);
+    let bits_per_pixel = read_u16(header, 28);
+    let compression = read_u32(header, 30);
+    if planes != 1 || width <= 0 || height == 0 {
+      return Err(Error::Corrupted);
+    }
+    if !matches!(bits_per_pixel, 24 | 32) || compression != COMPRESSION_NONE {
+      return Err(Error::Unsupported);
+    }
+    let (width, top_down) = (width as usize, height < 0);
+    let height = height.unsigned_abs() as usize;
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+      return Err(Error::Unsupported);
+    }
+
+    // Rows are padded to whole 32-bit words.
+    let bytes_per_pixel = bits_per_pixel as usize / 8;
+    let stride = (width * bytes_per_pixel + 3) & !3;
+    let end = offset
+      .checked_add(stride * height)
+      .ok_or(Error::Corrupted)?;
+    if offset < FILE_HEADER_SIZE + info_size {
+      return Err(Error::Corrupted);
+    }
+    let pixels = data.get(offset..end).ok_or(Error::Corrupted)?;
+    Ok(Self {
+      pixels,
+      width,
+      height,
+      bytes_per_pixel,
+      stride,
+      top_down,
+    })
+  }
+
+  /// Returns the width of the image, in pixels.
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  /// Returns the height of the image, in pixels.
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
+  /// Returns the number of bits of each pixel, 24 or 32.
+  pub fn bits_per_pixel(&self) -> usize {
+    self.bytes_per_pixel * 8
+  }
+
+  /// Returns the row `y`, or [`None`] if the image is not that tall.
+  ///
+  /// # Arguments
+  ///
+  /// * `y` - the row, from the top of the image
+  pub fn row(&self, y: usize) -> Option<Row<'a>> {
+    if y >= self.height {
+      return None;
+    }
+    let index = if self.top_down {
+      y
+    } else {
+      self.height - 1 - y
+    };
+    let row = &self.pixels[index * self.stride..][..self.stride];
+    Some(Row {
+      pixels: row[..self.width * self.bytes_per_pixel]
+        .chunks_exact(self.bytes_per_pixel),
+    })
+  }
+
+  /// Returns the pixel at `x` and `y`, or [`None`] if it is outside of the
+  /// image.
+  ///
+  /// # Arguments
+  ///
+  /// * `x` - the column, from the left of the image
+  /// * `y` - the row, from the top of the image
+  pub fn pixel(&self, x: usize, y: usize) -> Option<Pixel> {
+    self.row(y)?.nth(x)
+  }
+
+  /// Returns an iterator over the rows of the image, from the top down.
+  pub fn rows(&self) -> Rows<'a> {
+    Rows {
+      image: *self,
+      range: 0..self.height,
+    }
+  }
+}
+
+/// An iterator over the rows of an image.
+#[derive(Clone)]
+pub struct Rows<'a> {
+  image: Bmp<'a>,
+  range: core::ops::Range<usize>,
+}
+
+impl<'a> Iterator for Rows<'a> {
+  type Item = Row<'a>;
+
+  fn next(&mut self) -> Option<Row<'a>> {
+    self.image.row(self.range.next()?)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.range.size_hint()
+  }
+}
+
+impl DoubleEndedIterator for Rows<'_> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    self.image.row(self.range.next_back()?)
+  }
+}
+
+impl ExactSizeIterator for Rows<'_> {}
+
+impl FusedIterator for Rows<'_> {}
+
+/// An iterator over the pixels of a row of an image, from the left.
+///
+/// The fourth byte of pixels of 32 bits is reserved, and ignored.
+#[derive(Clone)]
+pub struct Row<'a> {
+  pixels: core::slice::ChunksExact<'a, u8>,
+}
+
+impl Iterator for Row<'_> {
+  type Item = Pixel;
+
+  fn next(&mut self) -> Option<Pixel> {
+    self.pixels.next().map(pixel)
+  }
+
+  fn nth(&mut self, n: usize) -> Option<Pixel> {
+    self.pixels.nth(n).map(pixel)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.pixels.size_hint()
+  }
+}
+
+impl DoubleEndedIterator for Row<'_> {
+  fn next_back(&mut self) -> Option<Pixel> {
+    self.pixels.next_back().map(pixel)
+  }
+}
+
+impl ExactSizeIterator for Row<'_> {}
+
+impl FusedIterator for Row<'_> {}
+
+/// Decodes the pixel stored in `bytes`, in blue, green, red order.
+///
+/// # Arguments
+///
+/// * `bytes` - the bytes of the pixel
+fn pixel(bytes: &[u8]) -> Pixel {
+  Pixel {
+    red: bytes[2],
+    green: bytes[1],
+    blue: bytes[0],
+  }
+}
+
+/// Returns `true` if [`Bmp::parse`] reads `data`, for checking images built
+/// into binaries at compile time.
+///
+/// # Arguments
+///
+/// * `data` - the contents of the file
+pub(crate) const fn is_valid(data: &[u8]) -> bool {
+  if data.len() < FILE_HEADER_SIZE + INFO_HEADER_SIZE
+    || data[0] != MAGIC[0]
+    || data[1] != MAGIC[1]
+  {
+    return false;
+  }
+  let offset = read_u32(data, 10) as usize;
+  let info_size = read_u32(data, 14) as usize;
+  let width = read_u32(data, 18) as i32;
+  let height = read_u32(data, 22) as i32;
+  let bits_per_pixel = read_u16(data, 28);
+  if info_size < INFO_HEADER_SIZE
+    || read_u16(data, 26) != 1
+    || width <= 0
+    || height == 0
+    || !matches!(bits_per_pixel, 24 | 32)
+    || read_u32(data, 30) != COMPRESSION_NONE
+  {
+    return false;
+  }
+  let (width, height) = (width as usize, height.unsigned_abs() as usize);
+  if width > MAX_DIMENSION || height > MAX_DIMENSION {
+    return false;
+  }
+  let stride = (width * (bits_per_pixel as usize / 8) + 3) & !3;
+  match offset.checked_add(stride * height) {
+    Some(end) => offset >= FILE_HEADER_SIZE + info_size && end <= data.len(),
+    None => false,
+  }
+}
+
+const fn read_u16(data: &[u8], offset: usize) -> u16 {
+  u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+const fn read_u32(data: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes([
+    data[offset],
+    data[offset + 1],
+    data[offset + 2],
+    data[offset + 3],
+  ])
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::vec::Vec;
+
+  const RED: Pixel = Pixel {
+    red: 0xff,
+    green: 0,
+    blue: 0,
+  };
+
+  const GREEN: Pixel = Pixel {
+    red: 0,
+    green: 0xff,
+    blue: 0,
+  };
+
+  const BLUE: Pixel = Pixel {
+    red: 0,
+    green: 0,
+    blue: 0xff,
+  };
+
+  const WHITE: Pixel = Pixel {
+    red: 0xff,
+    green: 0xff,
+    blue: 0xff,
+  };
+
+  /// Returns an image of `height` rows, negative for top-down ones, of the
+  /// already encoded `rows`.
+  fn bmp(width: i32, height: i32, bits: u16, rows: &[&[u8]]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(MAGIC);
+    let offset = (FILE_HEADER_SIZE + INFO_HEADER_SIZE) as u32;
+    let size: usize = rows.iter().map(|row| row.len()).sum();
+    data.extend_from_slice(&(offset + size as u32).to_le_bytes());
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&offset.to_le_bytes());
+    data.extend_from_slice(&(INFO_HEADER_SIZE as u32).to_le_bytes());
+    data.extend_from_slice(&width.to_le_bytes());
+    data.extend_from_slice(&height.to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&bits.to_le_bytes());
+    data.resize(offset as usize, 0);
+    for row in rows {
+      data.extend_from_slice(row);
+    }
+    data
+  }
+
+  #[test]
+  fn bottom_up_rows_are_read_from_the_top() {
+    // Rows of two 24-bit pixels are padded from 6 to 8 bytes.
+    let data = bmp(
+      2,
+      2,
+      24,
+      &[
+        &[0xff, 0, 0, 0xff, 0xff, 0xff, 0, 0],
+        &[0, 0, 0xff, 0, 0xff, 0, 0, 0],
+      ],
+    );
+    let image = Bmp::parse(&data).unwrap();
+
+    assert_eq!((image.width(), image.height()), (2, 2));
+    assert_eq!(image.bits_per_pixel(), 24);
+    let rows: Vec<Vec<_>> = image.rows().map(|row| row.collect()).collect();
+    assert_eq!(rows, [[RED, GREEN], [BLUE, WHITE]]);
+    assert_eq!(image.pixel(1, 1), Some(WHITE));
+    assert_eq!(image.pixel(2, 1), None);
+    assert!(image.row(2).is_none());
+  }
+
+  #[test]
+  fn top_down_rows_are_read_in_order() {
+    let data = bmp(1, -2, 32, &[&[0, 0, 0xff, 0x80], &[0xff, 0, 0, 0x80]]);
+    let image = Bmp::parse(&data).unwrap();
+
+    let pixels: Vec<_> = image.rows().flatten().collect();
+    assert_eq!(pixels, [RED, BLUE]);
+    let last = image.rows().next_back().unwrap().collect::<Vec<_>>();
+    assert_eq!(last, [BLUE]);
+  }
+
+  #[test]
+  fn other_forms_are_unsupported() {
+    let mut data = bmp(1, 1, 8, &[&[0, 0, 0, 0]]);
+    assert_eq!(Bmp::parse(&data).err(), Some(Error::Unsupported));
+
+    data[28] = 24;
+    data[30] = 1;
+    assert_eq!(Bmp::parse(&data).err(), Some(Error::Unsupported));
+  }
+
+  #[test]
+  fn malformed_images_are_rejected() {
+    let data = bmp(2, 2, 24, &[&[0; 8], &[0; 8]]);
+
+    let short = &data[..data.len() - 1];
+    assert_eq!(Bmp::parse(short).err(), Some(Error::Corrupted));
+    let mut other = data.clone();
+    other[0] = b'X';
+    assert_eq!(Bmp::parse(&other).err(), Some(Error::Corrupted));
+    let mut empty = data;
+    empty[22..26].copy_from_slice(&0u32.to_le_bytes());
+    assert_eq!(Bmp::parse(&empty).err(), Some(Error::Corrupted));
+  }
+
+  #[test]
+  fn images_are_valid_as_they_parse() {
+    let data = bmp(2, 2, 24, &[&[0; 8], &[0; 8]]);
+    let mut paletted = bmp(1, 1, 8, &[&[0, 0, 0, 0]]);
+    paletted[28] = 8;
+    let mut empty = data.clone();
+    empty[22..26].copy_from_slice(&0u32.to_le_bytes());
+    let top_down = bmp(1, -2, 32, &[&[0; 4], &[0; 4]]);
+
+    for data in [
+      &data[..],
+      &data[..data.len() - 1],
+      &paletted,
+      &empty,
+      &top_down,
+    ] {
+      assert_eq!(is_valid(data), Bmp::parse(data).is_ok());
+    }
+    assert!(is_valid(&top_down));
+  }
+}
@@ -0,0 +1,523 @@
+//! This module provides byte buffers with a guaranteed alignment, for memory
+//! that hardware or algorithms need aligned, such as the targets of DMA, the
+//! sectors read from a disk with an I/O alignment, and the blocks of ciphers
+//! and hashes.
+//!
+//! [`AlignedBuf`] holds its bytes inline, aligned to a power of two of up to
+//! 4096 given as a const parameter, so that it may live on the stack or in a
+//! static. `AlignedVec` allocates them instead, to a size and alignment
+//! chosen at runtime, and is only built with the `alloc` feature.
+//!
+//! Either can zero its bytes when it is dropped, for buffers that held keys
+//! or other secrets: the zeroing is written so that the compiler does not
+//! leave it out because the bytes are never read again.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{compiler_fence, Ordering};
+
+#[cfg(any(feature = "alloc", test))]
+use crate::error::{Error, Result};
+#[cfg(any(feature = "alloc", test))]
+use core::ptr::NonNull;
+
+/// An alignment that an [`AlignedBuf`] can have, which is implemented for
+/// [`Align`] of each power of two from 1 to 4096.
+pub trait Alignment: private::Sealed {
+  /// A zero-sized type with the alignment.
+  #[doc(hidden)]
+  type Marker: Copy;
+}
+
+/// The alignment of `N` bytes, which is an [`Alignment`] if `N` is a power of
+/// two of at most 4096.
+pub struct Align<const N: usize>;
+
+/// A buffer of `N` bytes aligned to `ALIGN` bytes, which zeroes them when it
+/// is dropped if `WIPE` is `true`.
+///
+/// The buffer dereferences to a slice of its bytes. Its size is `N` rounded
+/// up to a multiple of `ALIGN`, and nothing else, so that an array of them is
+/// an array of aligned blocks.
+#[repr(C)]
+pub struct AlignedBuf<
+  const N: usize,
+  const ALIGN: usize,
+  const WIPE: bool = false,
+> where
+  Align<ALIGN>: Alignment,
+{
+  align: [<Align<ALIGN> as Alignment>::Marker; 0],
+  bytes: [u8; N],
+}
+
+/// An [`AlignedBuf`] that zeroes its bytes when it is dropped, for keys and
+/// other secrets.
+pub type SecretBuf<const N: usize, const ALIGN: usize> =
+  AlignedBuf<N, ALIGN, true>;
+
+impl<const N: usize, const ALIGN: usize, const WIPE: bool>
+  AlignedBuf<N, ALIGN, WIPE>
+where
+  Align<ALIGN>: Alignment,
+{
+  /// Constructs a buffer of zeros.
+  pub const fn new() -> Self {
+    Self::from_bytes([0; N])
+  }
+
+  /// Constructs a buffer holding `bytes`.
+  ///
+  /// # Arguments
+  ///
+  /// * `bytes` - the bytes to hold
+  pub const fn from_bytes(bytes: [u8; N]) -> Self {
+    Self { align: [], bytes }
+  }
+
+  /// Returns the number of bytes in the buffer, `N`.
+  pub const fn len(&self) -> usize {
+    N
+  }
+
+  /// Returns whether the buffer holds no bytes.
+  pub const fn is_empty(&self) -> bool {
+    N == 0
+  }
+
+  /// Returns the alignment of the buffer, `ALIGN`.
+  pub const fn align(&self) -> usize {
+    ALIGN
+  }
+
+  /// Returns the bytes of the buffer.
+  pub const fn as_slice(&self) -> &[u8] {
+    &self.bytes
+  }
+
+  /// Returns the bytes of the buffer, to write.
+  pub fn as_mut_slice(&mut self) -> &mut [u8] {
+    &mut self.bytes
+  }
+
+  /// Returns the bytes of the buffer as an array.
+  pub const fn as_array(&self) -> &[u8; N] {
+    &self.bytes
+  }
+
+  /// Returns the bytes of the buffer as an array, to write.
+  pub fn as_mut_array(&mut self) -> &mut [u8; N] {
+    &mut self.bytes
+  }
+
+  /// Returns a pointer to the first byte, which is aligned to `ALIGN`.
+  pub const fn as_ptr(&self) -> *const u8 {
+    self.bytes.as_ptr()
+  }
+
+  /// Returns a pointer to the first byte, which is aligned to `ALIGN`, to
+  /// write through, such as by a device.
+  pub fn as_mut_ptr(&mut self) -> *mut u8 {
+    self.bytes.as_mut_ptr()
+  }
+
+  /// Sets every byte of the buffer to zero, in a way that is not left out
+  /// because the bytes are not read afterwards.
+  pub fn wipe(&mut self) {
+    wipe(&mut self.bytes);
+  }
+}
+
+impl<const N: usize, const ALIGN: usize, const WIPE: bool> Drop
+  for AlignedBuf<N, ALIGN, WIPE>
+where
+  Align<ALIGN>: Alignment,
+{
+  fn drop(&mut self) {
+    if WIPE {
+      self.wipe();
+    }
+  }
+}
+
+impl<const N: usize, const ALIGN: usize, const WIPE: bool> Default
+  for AlignedBuf<N, ALIGN, WIPE>
+where
+  Align<ALIGN>: Alignment,
+{
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<const N: usize, const ALIGN: usize, const WIPE: bool> Clone
+  for AlignedBuf<N, ALIGN, WIPE>
+where
+  Align<ALIGN>: Alignment,
+{
+  fn clone(&self) -> Self {
+    Self::from_bytes(self.bytes)
+  }
+}
+
+impl<const N: usize, const ALIGN: usize, const WIPE: bool> Deref
+  for AlignedBuf<N, ALIGN, WIPE>
+where
+  Align<ALIGN>: Alignment,
+{
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    &self.bytes
+  }
+}
+
+impl<const N: usize, const ALIGN: usize, const WIPE: bool> DerefMut
+  for AlignedBuf<N, ALIGN, WIPE>
+where
+  Align<ALIGN>: Alignment,
+{
+  fn deref_mut(&mut self) -> &mut [u8] {
+    &mut self.bytes
+  }
+}
+
+impl<const N: usize, const ALIGN: usize, const WIPE: bool> AsRef<[u8]>
+  for AlignedBuf<N, ALIGN, WIPE>
+where
+  Align<ALIGN>: Alignment,
+{
+  fn as_ref(&self) -> &[u8] {
+    &self.bytes
+  }
+}
+
+impl<const N: usize, const ALIGN: usize, const WIPE: bool> AsMut<[u8]>
+  for AlignedBuf<N, ALIGN, WIPE>
+where
+  Align<ALIGN>: Alignment,
+{
+  fn as_mut(&mut self) -> &mut [u8] {
+    &mut self.bytes
+  }
+}
+
+impl<const N: usize, const ALIGN: usize, const WIPE: bool> fmt::Debug
+  for AlignedBuf<N, ALIGN, WIPE>
+where
+  Align<ALIGN>: Alignment,
+{
+  /// Writes the size and alignment of the buffer, but not its bytes, which
+  /// may be secret.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("AlignedBuf")
+      .field("len", &N)
+      .field("align", &ALIGN)
+      .finish_non_exhaustive()
+  }
+}
+
+/// A buffer of bytes allocated from the global allocator, aligned to an
+/// alignment chosen at runtime, such as the I/O alignment that a disk
+/// reports.
+#[cfg(any(feature = "alloc", test))]
+pub struct AlignedVec {
+  ptr: NonNull<u8>,
+  len: usize,
+  align: usize,
+  wipe: bool,
+}
+
+#[cfg(any(feature = "alloc", test))]
+// SAFETY: the buffer owns its bytes, as a `Vec<u8>` does.
+unsafe impl Send for AlignedVec {}
+#[cfg(any(feature = "alloc", test))]
+// SAFETY: the bytes are only written through a mutable reference.
+unsafe impl Sync for AlignedVec {}
+
+#[cfg(any(feature = "alloc", test))]
+impl AlignedVec {
+  /// Allocates a buffer of `len` zeros aligned to `align` bytes.
+  ///
+  /// Returns [`Error::InvalidArgument`] if `align` is not a power of two, or
+  /// the size overflows, and [`Error::NoMemory`] if the allocation fails.
+  ///
+  /// # Arguments
+  ///
+  /// * `len` - the number of bytes
+  /// * `align` - the alignment of the first byte
+  pub fn zeroed(len: usize, align: usize) -> Result<Self> {
+    let ptr = allocate(len, align)?;
+    Ok(Self {
+      ptr,
+      len,
+      align,
+      wipe: false,
+    })
+  }
+
+  /// Returns the buffer, set to zero its bytes when it is dropped or
+  /// resized.
+  pub fn zero_on_drop(mut self) -> Self {
+    self.wipe = true;
+    self
+  }
+
+  /// Returns the number of bytes in the buffer.
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  /// Returns whether the buffer holds no bytes.
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Returns the alignment of the buffer.
+  pub fn align(&self) -> usize {
+    self.align
+  }
+
+  /// Returns the bytes of the buffer.
+  pub fn as_slice(&self) -> &[u8] {
+    // SAFETY: the allocation holds `len` initialized bytes.
+    unsafe { core::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+  }
+
+  /// Returns the bytes of the buffer, to write.
+  pub fn as_mut_slice(&mut self) -> &mut [u8] {
+    // SAFETY: the allocation holds `len` initialized bytes, and is borrowed
+    // mutably.
+    unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+  }
+
+  /// Returns a pointer to the first byte, which is aligned to the alignment.
+  pub fn as_ptr(&self) -> *const u8 {
+    self.ptr.as_ptr()
+  }
+
+  /// Returns a pointer to the first byte, which is aligned to the alignment,
+  /// to write through, such as by a device.
+  pub fn as_mut_ptr(&mut self) -> *mut u8 {
+    self.ptr.as_ptr()
+  }
+
+  /// Changes the length of the buffer to `len`, keeping the bytes that it
+  /// had up to there, and zeroing any that it gains.
+  ///
+  /// The bytes are moved to a new allocation, with the same alignment, and
+  /// the old one is zeroed first if the buffer zeroes on drop. On failure,
+  /// the buffer is unchanged.
+  ///
+  /// # Arguments
+  ///
+  /// * `len` - the new number of bytes
+  pub fn resize(&mut self, len: usize) -> Result<()> {
+    let mut resized = Self {
+      ptr: allocate(len, self.align)?,
+      len,
+      align: self.align,
+      wipe: self.wipe,
+    };
+    let kept = len.min(self.len);
+    resized.as_mut_slice()[..kept].copy_from_slice(&self.as_slice()[..kept]);
+    core::mem::swap(self, &mut resized);
+    Ok(())
+  }
+
+  /// Sets every byte of the buffer to zero, in a way that is not left out
+  /// because the bytes are not read afterwards.
+  pub fn wipe(&mut self) {
+    wipe(self.as_mut_slice());
+  }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl Drop for AlignedVec {
+  fn drop(&mut self) {
+    if self.wipe {
+      self.wipe();
+    }
+    if self.len != 0 {
+      // SAFETY: the layout was checked when the bytes were allocated with
+      // it.
+      unsafe {
+        let layout =
+          core::alloc::Layout::from_size_align_unchecked(self.len, self.align);
+        alloc::alloc::dealloc(self.ptr.as_ptr(), layout);
+      }
+    }
+  }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl Clone for AlignedVec {
+  fn clone(&self) -> Self {
+    let mut clone = Self::zeroed(self.len, self.align)
+      .expect("failed to allocate a clone of an AlignedVec");
+    clone.wipe = self.wipe;
+    clone.as_mut_slice().copy_from_slice(self.as_slice());
+    clone
+  }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl Deref for AlignedVec {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    self.as_slice()
+  }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl DerefMut for AlignedVec {
+  fn deref_mut(&mut self) -> &mut [u8] {
+    self.as_mut_slice()
+  }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl AsRef<[u8]> for AlignedVec {
+  fn as_ref(&self) -> &[u8] {
+    self.as_slice()
+  }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl AsMut<[u8]> for AlignedVec {
+  fn as_mut(&mut self) -> &mut [u8] {
+    self.as_mut_slice()
+  }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl fmt::Debug for AlignedVec {
+  /// Writes the size and alignment of the buffer, but not its bytes, which
+  /// may be secret.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("AlignedVec")
+      .field("len", &self.len)
+      .field("align", &self.align)
+      .finish_non_exhaustive()
+  }
+}
+
+/// Implements [`Alignment`] for each of the given alignments.
+macro_rules! alignments {
+  ($($align:literal => $marker:ident),* $(,)?) => {
+    $(
+      #[doc(hidden)]
+      #[derive(Clone, Copy)]
+      #[repr(align($align))]
+      pub struct $marker;
+
+      impl private::Sealed for Align<$align> {}
+
+      impl Alignment for Align<$align> {
+        type Marker = $marker;
+      }
+    )*
+  };
+}
+
+alignments! {
+  1 => Align1,
+  2 => Align2,
+  4 => Align4,
+  8 => Align8,
+  16 => Align16,
+  32 => Align32,
+  64 => Align64,
+  128 => Align128,
+  256 => Align256,
+  512 => Align512,
+  1024 => Align1024,
+  2048 => Align2048,
+  4096 => Align4096,
+}
+
+mod private {
+  /// Keeps [`Alignment`](super::Alignment) from being implemented outside
+  /// this module.
+  pub trait Sealed {}
+}
+
+/// Sets every byte of `bytes` to zero with volatile writes, so that the
+/// compiler does not remove them as dead stores.
+///
+/// # Arguments
+///
+/// * `bytes` - the bytes to zero
+fn wipe(bytes: &mut [u8]) {
+  for byte in bytes.iter_mut() {
+    // SAFETY: the byte is borrowed mutably, and so valid to write.
+    unsafe { core::ptr::write_volatile(byte, 0) };
+  }
+  compiler_fence(Ordering::SeqCst);
+}
+
+/// Allocates `len` zeros aligned to `align` bytes, or returns a dangling,
+/// aligned pointer if `len` is zero.
+///
+/// # Arguments
+///
+/// * `len` - the number of bytes
+/// * `align` - the alignment of the first byte
+#[cfg(any(feature = "alloc", test))]
+fn allocate(len: usize, align: usize) -> Result<NonNull<u8>> {
+  let layout = core::alloc::Layout::from_size_align(len, align)
+    .map_err(|_| Error::InvalidArgument)?;
+  if len == 0 {
+    // An alignment is never zero, and so is a valid, dangling address.
+    return NonNull::new(align as *mut u8).ok_or(Error::InvalidArgument);
+  }
+  // SAFETY: the layout is not empty.
+  let ptr = unsafe { alloc::alloc::alloc_zeroed(layout) };
+  NonNull::new(ptr).ok_or(Error::NoMemory)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn buffers_are_aligned() {
+    let buffers = [AlignedBuf::<3, 512>::new(), AlignedBuf::new()];
+
+    assert_eq!(core::mem::size_of::<AlignedBuf<4096, 4096>>(), 4096);
+    assert_eq!(core::mem::size_of::<AlignedBuf<3, 512>>(), 512);
+    assert_eq!(buffers[0].as_ptr() as usize % 512, 0);
+    assert_eq!(buffers[1].as_ptr() as usize % 512, 0);
+    assert_eq!(buffers[0].align(), 512);
+  }
+
+  #[test]
+  fn buffers_are_slices() {
+    let mut buffer = SecretBuf::<4, 16>::from_bytes([1, 2, 3, 4]);
+    buffer[1..3].copy_from_slice(&[9, 9]);
+
+    assert_eq!(&*buffer, &[1, 9, 9, 4]);
+    assert_eq!(buffer.len(), 4);
+    buffer.wipe();
+    assert_eq!(buffer.as_array(), &[0; 4]);
+  }
+
+  #[test]
+  fn vecs_are_aligned_and_resize() {
+    let mut vec = AlignedVec::zeroed(100, 256).unwrap().zero_on_drop();
+    vec[99] = 7;
+
+    assert_eq!(vec.as_ptr() as usize % 256, 0);
+    vec.resize(200).unwrap();
+    assert_eq!(vec.as_ptr() as usize % 256, 0);
+    assert_eq!((vec[99], vec[199], vec.len()), (7, 0, 200));
+    vec.resize(0).unwrap();
+    assert!(vec.is_empty());
+    assert_eq!(vec.clone().align(), 256);
+    assert_eq!(
+      AlignedVec::zeroed(1, 3).unwrap_err(),
+      Error::InvalidArgument
+    );
+  }
+}
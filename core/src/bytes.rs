@@ -0,0 +1,73 @@
+//! This module provides the reading of little-endian integers at offsets of
+//! byte slices, for the parsers of binary formats such as [`elf`](crate::elf)
+//! and [`pe`](crate::pe).
+//!
+//! The readers panic when the integer does not lie within the slice, so the
+//! parsers check the bounds of each structure once, when they first read it,
+//! rather than for every field of it.
+
+/// Returns the little-endian [`u16`] at `offset` of `data`.
+///
+/// # Arguments
+///
+/// * `data` - the bytes to read from
+/// * `offset` - the offset of the integer
+///
+/// # Panics
+///
+/// Panics if the integer extends past the end of `data`.
+pub fn read_u16(data: &[u8], offset: usize) -> u16 {
+  u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+/// Returns the little-endian [`u32`] at `offset` of `data`.
+///
+/// # Arguments
+///
+/// * `data` - the bytes to read from
+/// * `offset` - the offset of the integer
+///
+/// # Panics
+///
+/// Panics if the integer extends past the end of `data`.
+pub fn read_u32(data: &[u8], offset: usize) -> u32 {
+  let mut bytes = [0; 4];
+  bytes.copy_from_slice(&data[offset..offset + 4]);
+  u32::from_le_bytes(bytes)
+}
+
+/// Returns the little-endian [`u64`] at `offset` of `data`.
+///
+/// # Arguments
+///
+/// * `data` - the bytes to read from
+/// * `offset` - the offset of the integer
+///
+/// # Panics
+///
+/// Panics if the integer extends past the end of `data`.
+pub fn read_u64(data: &[u8], offset: usize) -> u64 {
+  let mut bytes = [0; 8];
+  bytes.copy_from_slice(&data[offset..offset + 8]);
+  u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn integers_are_read_little_endian_at_any_offset() {
+    let data = [0xff, 1, 2, 3, 4, 5, 6, 7, 8];
+
+    assert_eq!(read_u16(&data, 1), 0x0201);
+    assert_eq!(read_u32(&data, 5), 0x0807_0605);
+    assert_eq!(read_u64(&data, 1), 0x0807_0605_0403_0201);
+  }
+
+  #[test]
+  #[should_panic]
+  fn integers_past_the_end_panic() {
+    read_u32(&[0; 4], 1);
+  }
+}
@@ -0,0 +1,331 @@
+//! This module provides the checksums that on-disk and on-wire formats use to
+//! detect corruption, such as the CRC32 of GUID partition tables and gzip, the
+//! CRC32C of ext4 and iSCSI, and the Adler-32 of zlib streams.
+//!
+//! Every checksum implements [`Checksum`], so code that reads a format which
+//! lets its writer pick the algorithm is generic over it. The `crypto` crate
+//! adapts its hashers to it as well, for formats that protect their data with
+//! a digest.
+
+/// The lookup table for the CRC32 with the reflected polynomial `0xedb88320`.
+const CRC32_TABLE: [u32; 256] = crc32_table(0xedb8_8320);
+
+/// The lookup table for the CRC32C with the reflected polynomial
+/// `0x82f63b78`, of Castagnoli.
+const CRC32C_TABLE: [u32; 256] = crc32_table(0x82f6_3b78);
+
+/// An algorithm that checksums data incrementally, for data that is not in
+/// memory all at once.
+pub trait Checksum: Default {
+  /// The checksum of the data, such as a `u32` or a digest.
+  type Output: PartialEq;
+
+  /// Adds `data` to the checksum.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the next bytes to checksum
+  fn update(&mut self, data: &[u8]);
+
+  /// Returns the checksum of the data added so far.
+  fn finish(&self) -> Self::Output;
+
+  /// Computes the checksum of `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the data to checksum
+  fn checksum(data: &[u8]) -> Self::Output {
+    let mut checksum = Self::default();
+    checksum.update(data);
+    checksum.finish()
+  }
+
+  /// Returns `true` if the checksum of `data` is `expected`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the data to checksum
+  /// * `expected` - the checksum stored with the data
+  fn verify(data: &[u8], expected: &Self::Output) -> bool {
+    Self::checksum(data) == *expected
+  }
+}
+
+/// An incremental CRC32, as used by gzip, zlib's PNG chunks and GUID
+/// partition tables, for data that is not in memory all at once.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Crc32 {
+  crc: u32,
+}
+
+impl Crc32 {
+  /// Constructs the CRC32 of no data.
+  pub const fn new() -> Self {
+    Self { crc: !0 }
+  }
+
+  /// Adds `data` to the checksum.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the next bytes to checksum
+  pub fn update(&mut self, data: &[u8]) {
+    self.crc = crc32_update(&CRC32_TABLE, self.crc, data);
+  }
+
+  /// Returns the CRC32 of the data added so far.
+  pub const fn finish(&self) -> u32 {
+    !self.crc
+  }
+}
+
+impl Default for Crc32 {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Checksum for Crc32 {
+  type Output = u32;
+
+  fn update(&mut self, data: &[u8]) {
+    Crc32::update(self, data)
+  }
+
+  fn finish(&self) -> u32 {
+    Crc32::finish(self)
+  }
+}
+
+/// Computes the CRC32 of `data`.
+///
+/// # Arguments
+///
+/// * `data` - the data to checksum
+pub fn crc32(data: &[u8]) -> u32 {
+  Crc32::checksum(data)
+}
+
+/// An incremental CRC32C, the CRC32 of Castagnoli, as used by the metadata
+/// of ext4 and by iSCSI, for data that is not in memory all at once.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Crc32c {
+  crc: u32,
+}
+
+impl Crc32c {
+  /// Constructs the CRC32C of no data.
+  pub const fn new() -> Self {
+    Self { crc: !0 }
+  }
+
+  /// Adds `data` to the checksum.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the next bytes to checksum
+  pub fn update(&mut self, data: &[u8]) {
+    self.crc = crc32_update(&CRC32C_TABLE, self.crc, data);
+  }
+
+  /// Returns the CRC32C of the data added so far.
+  pub const fn finish(&self) -> u32 {
+    !self.crc
+  }
+}
+
+impl Default for Crc32c {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Checksum for Crc32c {
+  type Output = u32;
+
+  fn update(&mut self, data: &[u8]) {
+    Crc32c::update(self, data)
+  }
+
+  fn finish(&self) -> u32 {
+    Crc32c::finish(self)
+  }
+}
+
+/// Computes the CRC32C of `data`.
+///
+/// # Arguments
+///
+/// * `data` - the data to checksum
+pub fn crc32c(data: &[u8]) -> u32 {
+  Crc32c::checksum(data)
+}
+
+/// The modulus of the sums of Adler-32, the largest prime below 2^16.
+const ADLER32_MODULUS: u32 = 65521;
+
+/// The most bytes that can be added to the sums of Adler-32 before they must
+/// be reduced, so that they cannot overflow.
+const ADLER32_BLOCK: usize = 5552;
+
+/// An incremental Adler-32, as used by zlib, for data that is not in memory
+/// all at once.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Adler32 {
+  a: u32,
+  b: u32,
+}
+
+impl Adler32 {
+  /// Constructs the Adler-32 of no data.
+  pub const fn new() -> Self {
+    Self { a: 1, b: 0 }
+  }
+
+  /// Adds `data` to the checksum.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the next bytes to checksum
+  pub fn update(&mut self, data: &[u8]) {
+    for block in data.chunks(ADLER32_BLOCK) {
+      for &byte in block {
+        self.a += byte as u32;
+        self.b += self.a;
+      }
+      self.a %= ADLER32_MODULUS;
+      self.b %= ADLER32_MODULUS;
+    }
+  }
+
+  /// Returns the Adler-32 of the data added so far.
+  pub const fn finish(&self) -> u32 {
+    self.b << 16 | self.a
+  }
+}
+
+impl Default for Adler32 {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Checksum for Adler32 {
+  type Output = u32;
+
+  fn update(&mut self, data: &[u8]) {
+    Adler32::update(self, data)
+  }
+
+  fn finish(&self) -> u32 {
+    Adler32::finish(self)
+  }
+}
+
+/// Computes the Adler-32 of `data`.
+///
+/// # Arguments
+///
+/// * `data` - the data to checksum
+pub fn adler32(data: &[u8]) -> u32 {
+  Adler32::checksum(data)
+}
+
+/// Adds `data` to the reflected CRC32 `crc`, with the lookup table of its
+/// polynomial.
+///
+/// # Arguments
+///
+/// * `table` - the lookup table of the polynomial
+/// * `crc` - the CRC32 so far, before it is inverted
+/// * `data` - the next bytes to checksum
+fn crc32_update(table: &[u32; 256], crc: u32, data: &[u8]) -> u32 {
+  data.iter().fold(crc, |crc, byte| {
+    table[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+  })
+}
+
+/// Builds the lookup table of a CRC32 with the reflected `polynomial`.
+///
+/// # Arguments
+///
+/// * `polynomial` - the reflected polynomial of the CRC32
+const fn crc32_table(polynomial: u32) -> [u32; 256] {
+  let mut table = [0; 256];
+  let mut i = 0;
+  while i < 256 {
+    let mut crc = i as u32;
+    let mut bit = 0;
+    while bit < 8 {
+      crc = if crc & 1 != 0 {
+        (crc >> 1) ^ polynomial
+      } else {
+        crc >> 1
+      };
+      bit += 1;
+    }
+    table[i] = crc;
+    i += 1;
+  }
+  table
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+
+  #[test]
+  fn crc32_matches_the_check_value() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+  }
+
+  #[test]
+  fn crc32_is_incremental() {
+    let mut crc = Crc32::new();
+    crc.update(b"1234");
+    crc.update(b"");
+    crc.update(b"56789");
+    assert_eq!(crc.finish(), crc32(b"123456789"));
+  }
+
+  #[test]
+  fn crc32c_matches_the_check_value() {
+    assert_eq!(crc32c(b""), 0);
+    assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+  }
+
+  #[test]
+  fn checksums_are_generic() {
+    fn split<C: Checksum>(data: &[u8]) -> C::Output {
+      let mut checksum = C::default();
+      data.chunks(3).for_each(|chunk| checksum.update(chunk));
+      checksum.finish()
+    }
+
+    assert_eq!(split::<Crc32>(b"123456789"), crc32(b"123456789"));
+    assert_eq!(split::<Crc32c>(b"123456789"), crc32c(b"123456789"));
+    assert!(Adler32::verify(b"Wikipedia", &0x11e6_0398));
+    assert!(!Crc32::verify(b"Wikipedia", &0x11e6_0398));
+  }
+
+  #[test]
+  fn adler32_matches_the_check_value() {
+    assert_eq!(adler32(b""), 1);
+    assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+  }
+
+  #[test]
+  fn adler32_does_not_overflow() {
+    let data = std::vec![0xff; 3 * ADLER32_BLOCK + 1];
+    let mut adler = Adler32::new();
+    for chunk in data.chunks(1000) {
+      adler.update(chunk);
+    }
+    assert_eq!(adler.finish(), adler32(&data));
+    assert_eq!(adler.finish(), 0xfd50_d3b0);
+  }
+}
@@ -0,0 +1,31 @@
+//! This module provides collections, most of which need no allocator, for code
+//! that runs before there is one, or where allocating is not allowed, such as
+//! interrupt handlers.
+//!
+//! `HashMap`, `HashSet` and `SlotMap` are the exception: they allocate from
+//! the global allocator, and so are only built with the `alloc` feature. The
+//! iterators of the first two are in the `hash_map` and `hash_set` modules.
+
+mod array_vec;
+mod binary_heap;
+mod bitmap;
+#[cfg(any(feature = "alloc", test))]
+pub mod hash_map;
+#[cfg(any(feature = "alloc", test))]
+pub mod hash_set;
+mod range_tree;
+mod record_ring;
+mod slot_map;
+
+pub use array_vec::{ArrayVec, IntoIter};
+pub use binary_heap::{BinaryHeap, Max, Min, Order};
+pub use bitmap::Bitmap;
+#[cfg(any(feature = "alloc", test))]
+pub use hash_map::HashMap;
+#[cfg(any(feature = "alloc", test))]
+pub use hash_set::HashSet;
+pub use range_tree::{Overlapping, RangeLink, RangeNode, RangeTree};
+pub use record_ring::{RecordRing, RecordRingHeader, Records, WRAP};
+#[cfg(any(feature = "alloc", test))]
+pub use slot_map::SlotMap;
+pub use slot_map::{ArraySlotMap, Handle};
@@ -0,0 +1,422 @@
+//! This module provides [`ArrayVec`], a vector whose elements are stored
+//! inline in an array of fixed capacity, for building small lists, such as
+//! memory regions, processors and modules, without an allocator.
+
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+
+/// A vector of up to `N` elements, stored inline.
+pub struct ArrayVec<T, const N: usize> {
+  elements: [MaybeUninit<T>; N],
+  len: usize,
+}
+
+impl<T, const N: usize> ArrayVec<T, N> {
+  /// Creates an empty vector.
+  pub const fn new() -> Self {
+    Self {
+      // SAFETY: an array of `MaybeUninit` needs no initialization.
+      elements: unsafe { MaybeUninit::uninit().assume_init() },
+      len: 0,
+    }
+  }
+
+  /// Returns the number of elements that the vector can hold.
+  pub const fn capacity(&self) -> usize {
+    N
+  }
+
+  /// Returns the number of elements in the vector.
+  pub const fn len(&self) -> usize {
+    self.len
+  }
+
+  /// Returns whether the vector holds no elements.
+  pub const fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Returns whether the vector holds as many elements as it can.
+  pub const fn is_full(&self) -> bool {
+    self.len == N
+  }
+
+  /// Appends `value` to the end of the vector.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the element to append
+  ///
+  /// # Panics
+  ///
+  /// Panics if the vector is full.
+  pub fn push(&mut self, value: T) {
+    if self.try_push(value).is_err() {
+      panic!("ArrayVec is full ({} elements)", N);
+    }
+  }
+
+  /// Appends `value` to the end of the vector, or returns it if the vector is
+  /// full.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the element to append
+  pub fn try_push(&mut self, value: T) -> Result<(), T> {
+    if self.is_full() {
+      return Err(value);
+    }
+    self.elements[self.len].write(value);
+    self.len += 1;
+    Ok(())
+  }
+
+  /// Removes and returns the last element of the vector, if any.
+  pub fn pop(&mut self) -> Option<T> {
+    if self.is_empty() {
+      return None;
+    }
+    self.len -= 1;
+    // SAFETY: the element was initialized, and is no longer counted.
+    Some(unsafe { self.elements[self.len].assume_init_read() })
+  }
+
+  /// Inserts `value` at `index`, shifting the elements after it along, or
+  /// returns it if the vector is full.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the position to insert at
+  /// * `value` - the element to insert
+  ///
+  /// # Panics
+  ///
+  /// Panics if `index` is greater than the length.
+  pub fn try_insert(&mut self, index: usize, value: T) -> Result<(), T> {
+    assert!(index <= self.len, "insertion index out of bounds");
+    if self.is_full() {
+      return Err(value);
+    }
+    // SAFETY: there is room for one more element, so the shifted elements
+    // stay within the array.
+    unsafe {
+      let at = self.as_mut_ptr().add(index);
+      ptr::copy(at, at.add(1), self.len - index);
+      ptr::write(at, value);
+    }
+    self.len += 1;
+    Ok(())
+  }
+
+  /// Removes and returns the element at `index`, shifting the elements after
+  /// it back.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the position of the element to remove
+  ///
+  /// # Panics
+  ///
+  /// Panics if `index` is out of bounds.
+  pub fn remove(&mut self, index: usize) -> T {
+    assert!(index < self.len, "removal index out of bounds");
+    // SAFETY: the element is initialized, and the elements after it are
+    // moved back over it once it has been read out.
+    unsafe {
+      let at = self.as_mut_ptr().add(index);
+      let value = ptr::read(at);
+      ptr::copy(at.add(1), at, self.len - index - 1);
+      self.len -= 1;
+      value
+    }
+  }
+
+  /// Removes and returns the element at `index`, replacing it with the last
+  /// element, which is faster than [`remove`](Self::remove) but does not keep
+  /// the order.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the position of the element to remove
+  ///
+  /// # Panics
+  ///
+  /// Panics if `index` is out of bounds.
+  pub fn swap_remove(&mut self, index: usize) -> T {
+    assert!(index < self.len, "removal index out of bounds");
+    let last = self.len - 1;
+    self.as_mut_slice().swap(index, last);
+    self.pop().unwrap()
+  }
+
+  /// Shortens the vector to `len` elements, dropping the rest. Does nothing
+  /// if the vector is already no longer than `len`.
+  ///
+  /// # Arguments
+  ///
+  /// * `len` - the length to shorten the vector to
+  pub fn truncate(&mut self, len: usize) {
+    while self.len > len {
+      drop(self.pop());
+    }
+  }
+
+  /// Removes and drops every element.
+  pub fn clear(&mut self) {
+    self.truncate(0);
+  }
+
+  /// Keeps only the elements for which `f` returns `true`, in order.
+  ///
+  /// # Arguments
+  ///
+  /// * `f` - the predicate of the elements to keep
+  pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+    let mut index = 0;
+    while index < self.len {
+      if f(&self[index]) {
+        index += 1;
+      } else {
+        drop(self.remove(index));
+      }
+    }
+  }
+
+  /// Returns the elements as a slice.
+  pub fn as_slice(&self) -> &[T] {
+    // SAFETY: the first `len` elements are initialized.
+    unsafe { core::slice::from_raw_parts(self.as_ptr(), self.len) }
+  }
+
+  /// Returns the elements as a mutable slice.
+  pub fn as_mut_slice(&mut self) -> &mut [T] {
+    // SAFETY: the first `len` elements are initialized.
+    unsafe { core::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+  }
+
+  fn as_ptr(&self) -> *const T {
+    self.elements.as_ptr().cast()
+  }
+
+  fn as_mut_ptr(&mut self) -> *mut T {
+    self.elements.as_mut_ptr().cast()
+  }
+}
+
+impl<T, const N: usize> Drop for ArrayVec<T, N> {
+  fn drop(&mut self) {
+    // SAFETY: the slice holds exactly the initialized elements, which are
+    // never used again.
+    unsafe { ptr::drop_in_place(self.as_mut_slice()) };
+  }
+}
+
+impl<T, const N: usize> Default for ArrayVec<T, N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T, const N: usize> Deref for ArrayVec<T, N> {
+  type Target = [T];
+
+  fn deref(&self) -> &[T] {
+    self.as_slice()
+  }
+}
+
+impl<T, const N: usize> DerefMut for ArrayVec<T, N> {
+  fn deref_mut(&mut self) -> &mut [T] {
+    self.as_mut_slice()
+  }
+}
+
+impl<T: Clone, const N: usize> Clone for ArrayVec<T, N> {
+  fn clone(&self) -> Self {
+    self.iter().cloned().collect()
+  }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for ArrayVec<T, N> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_list().entries(self.iter()).finish()
+  }
+}
+
+impl<T: PartialEq, const N: usize, const M: usize> PartialEq<ArrayVec<T, M>>
+  for ArrayVec<T, N>
+{
+  fn eq(&self, other: &ArrayVec<T, M>) -> bool {
+    self.as_slice() == other.as_slice()
+  }
+}
+
+impl<T: Eq, const N: usize> Eq for ArrayVec<T, N> {}
+
+impl<T: PartialEq, const N: usize> PartialEq<[T]> for ArrayVec<T, N> {
+  fn eq(&self, other: &[T]) -> bool {
+    self.as_slice() == other
+  }
+}
+
+impl<T, const N: usize> Extend<T> for ArrayVec<T, N> {
+  /// Appends every element of `iter`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the vector fills up before `iter` is exhausted.
+  fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+    for value in iter {
+      self.push(value);
+    }
+  }
+}
+
+impl<T, const N: usize> FromIterator<T> for ArrayVec<T, N> {
+  /// Collects the elements of `iter`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if `iter` has more than `N` elements.
+  fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+    let mut vec = Self::new();
+    vec.extend(iter);
+    vec
+  }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a ArrayVec<T, N> {
+  type Item = &'a T;
+  type IntoIter = core::slice::Iter<'a, T>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter()
+  }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut ArrayVec<T, N> {
+  type Item = &'a mut T;
+  type IntoIter = core::slice::IterMut<'a, T>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter_mut()
+  }
+}
+
+impl<T, const N: usize> IntoIterator for ArrayVec<T, N> {
+  type Item = T;
+  type IntoIter = IntoIter<T, N>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    IntoIter { vec: self, next: 0 }
+  }
+}
+
+/// An iterator that moves the elements out of an [`ArrayVec`].
+pub struct IntoIter<T, const N: usize> {
+  /// The vector, whose elements before `next` have been moved out already.
+  vec: ArrayVec<T, N>,
+  next: usize,
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    if self.next == self.vec.len {
+      return None;
+    }
+    // SAFETY: the element is initialized, and is skipped from now on.
+    let value = unsafe { self.vec.elements[self.next].assume_init_read() };
+    self.next += 1;
+    Some(value)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let remaining = self.vec.len - self.next;
+    (remaining, Some(remaining))
+  }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for IntoIter<T, N> {
+  fn next_back(&mut self) -> Option<T> {
+    if self.next == self.vec.len {
+      return None;
+    }
+    self.vec.pop()
+  }
+}
+
+impl<T, const N: usize> ExactSizeIterator for IntoIter<T, N> {}
+
+impl<T, const N: usize> Drop for IntoIter<T, N> {
+  fn drop(&mut self) {
+    let remaining = &mut self.vec.as_mut_slice()[self.next..];
+    // SAFETY: the remaining elements are initialized, and the vector is
+    // emptied before it drops, so that they are only dropped once.
+    unsafe { ptr::drop_in_place(remaining) };
+    self.vec.len = 0;
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::ArrayVec;
+
+  #[test]
+  fn push_and_pop() {
+    let mut vec = ArrayVec::<u32, 3>::new();
+    assert!(vec.is_empty());
+    vec.push(1);
+    vec.push(2);
+    assert_eq!(vec.try_push(3), Ok(()));
+    assert!(vec.is_full());
+    assert_eq!(vec.try_push(4), Err(4));
+    assert_eq!(*vec, [1, 2, 3]);
+    assert_eq!(vec.pop(), Some(3));
+    assert_eq!(vec.len(), 2);
+  }
+
+  #[test]
+  #[should_panic]
+  fn push_panics_when_full() {
+    let mut vec = ArrayVec::<u32, 1>::new();
+    vec.push(1);
+    vec.push(2);
+  }
+
+  #[test]
+  fn insert_and_remove_keep_order() {
+    let mut vec: ArrayVec<u32, 8> = [1, 3, 5].into_iter().collect();
+    vec.try_insert(1, 2).unwrap();
+    vec.try_insert(4, 6).unwrap();
+    assert_eq!(*vec, [1, 2, 3, 5, 6]);
+    assert_eq!(vec.remove(3), 5);
+    assert_eq!(vec.swap_remove(0), 1);
+    assert_eq!(*vec, [6, 2, 3]);
+    vec.retain(|&value| value != 2);
+    assert_eq!(*vec, [6, 3]);
+  }
+
+  #[test]
+  fn elements_are_dropped_once() {
+    extern crate std;
+    use std::rc::Rc;
+
+    let value = Rc::new(());
+    let mut vec = ArrayVec::<_, 4>::new();
+    vec.extend(core::iter::repeat(value.clone()).take(4));
+    vec.truncate(3);
+    assert_eq!(Rc::strong_count(&value), 4);
+
+    let mut iter = vec.clone().into_iter();
+    drop(iter.next());
+    drop(iter.next_back());
+    assert_eq!(Rc::strong_count(&value), 5);
+    drop(iter);
+    drop(vec);
+    assert_eq!(Rc::strong_count(&value), 1);
+  }
+}
@@ -0,0 +1,330 @@
+//! This module provides [`BinaryHeap`], a priority queue of fixed capacity,
+//! stored inline, for queues that are used where allocating is not allowed,
+//! such as the timers and deadlines handled in interrupt context.
+//!
+//! The element taken first is decided by an [`Order`]: [`Max`] takes the
+//! greatest first, [`Min`] the least, and any function comparing two elements,
+//! such as one comparing the deadlines of timers, takes whichever it orders
+//! greatest.
+
+use super::ArrayVec;
+use core::cmp::Ordering;
+use core::fmt;
+
+/// An order of the elements of a [`BinaryHeap`], which takes the greatest
+/// element in this order first.
+pub trait Order<T> {
+  /// Compares `a` to `b`.
+  ///
+  /// # Arguments
+  ///
+  /// * `a` - the first element
+  /// * `b` - the second element
+  fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+/// The order that takes the greatest element first.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Max;
+
+/// The order that takes the least element first.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Min;
+
+impl<T: Ord> Order<T> for Max {
+  fn compare(&self, a: &T, b: &T) -> Ordering {
+    a.cmp(b)
+  }
+}
+
+impl<T: Ord> Order<T> for Min {
+  fn compare(&self, a: &T, b: &T) -> Ordering {
+    b.cmp(a)
+  }
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> Order<T> for F {
+  fn compare(&self, a: &T, b: &T) -> Ordering {
+    self(a, b)
+  }
+}
+
+/// A priority queue of up to `N` elements, stored inline, which takes the
+/// greatest element in the order `O` first.
+pub struct BinaryHeap<T, const N: usize, O = Max> {
+  elements: ArrayVec<T, N>,
+  order: O,
+}
+
+impl<T: Ord, const N: usize> BinaryHeap<T, N> {
+  /// Constructs an empty heap that takes the greatest element first.
+  pub const fn new() -> Self {
+    Self::with_order(Max)
+  }
+}
+
+impl<T, const N: usize, O> BinaryHeap<T, N, O> {
+  /// Constructs an empty heap that takes the greatest element in `order`
+  /// first.
+  ///
+  /// # Arguments
+  ///
+  /// * `order` - the order of the elements
+  pub const fn with_order(order: O) -> Self {
+    Self {
+      elements: ArrayVec::new(),
+      order,
+    }
+  }
+
+  /// Returns the number of elements that the heap can hold.
+  pub const fn capacity(&self) -> usize {
+    N
+  }
+
+  /// Returns the number of elements in the heap.
+  pub const fn len(&self) -> usize {
+    self.elements.len()
+  }
+
+  /// Returns whether the heap holds no elements.
+  pub const fn is_empty(&self) -> bool {
+    self.elements.is_empty()
+  }
+
+  /// Returns whether the heap holds as many elements as it can.
+  pub const fn is_full(&self) -> bool {
+    self.elements.is_full()
+  }
+
+  /// Returns the element that would be taken next, if there is one.
+  pub fn peek(&self) -> Option<&T> {
+    self.elements.first()
+  }
+
+  /// Returns the elements, in no particular order.
+  pub fn as_slice(&self) -> &[T] {
+    &self.elements
+  }
+
+  /// Returns an iterator over the elements, in no particular order.
+  pub fn iter(&self) -> core::slice::Iter<'_, T> {
+    self.elements.iter()
+  }
+
+  /// Removes every element.
+  pub fn clear(&mut self) {
+    self.elements.clear();
+  }
+}
+
+impl<T, const N: usize, O: Order<T>> BinaryHeap<T, N, O> {
+  /// Adds `value` to the heap.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the element to add
+  ///
+  /// # Panics
+  ///
+  /// Panics if the heap is full.
+  pub fn push(&mut self, value: T) {
+    if self.try_push(value).is_err() {
+      panic!("BinaryHeap is full ({} elements)", N);
+    }
+  }
+
+  /// Adds `value` to the heap, or returns it if the heap is full.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the element to add
+  pub fn try_push(&mut self, value: T) -> Result<(), T> {
+    self.elements.try_push(value)?;
+    self.sift_up(self.elements.len() - 1);
+    Ok(())
+  }
+
+  /// Removes and returns the greatest element in the heap's order, if there
+  /// is one.
+  pub fn pop(&mut self) -> Option<T> {
+    if self.elements.is_empty() {
+      return None;
+    }
+    let value = self.elements.swap_remove(0);
+    self.sift_down(0);
+    Some(value)
+  }
+
+  /// Removes and returns the greatest element in the heap's order if `f`
+  /// returns `true` for it, such as a timer whose deadline has passed.
+  ///
+  /// # Arguments
+  ///
+  /// * `f` - decides whether to take the element
+  pub fn pop_if(&mut self, f: impl FnOnce(&T) -> bool) -> Option<T> {
+    match self.peek() {
+      Some(value) if f(value) => self.pop(),
+      _ => None,
+    }
+  }
+
+  /// Removes every element for which `keep` returns `false`, such as
+  /// cancelled timers.
+  ///
+  /// # Arguments
+  ///
+  /// * `keep` - decides whether to keep each element
+  pub fn retain(&mut self, keep: impl FnMut(&T) -> bool) {
+    self.elements.retain(keep);
+    for index in (0..self.elements.len() / 2).rev() {
+      self.sift_down(index);
+    }
+  }
+
+  /// Moves the element at `index` up until its parent is not less than it.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the element
+  fn sift_up(&mut self, mut index: usize) {
+    while index > 0 {
+      let parent = (index - 1) / 2;
+      if !self.less(parent, index) {
+        break;
+      }
+      self.elements.swap(parent, index);
+      index = parent;
+    }
+  }
+
+  /// Moves the element at `index` down until neither child is greater than
+  /// it.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the element
+  fn sift_down(&mut self, mut index: usize) {
+    loop {
+      let mut greatest = index;
+      for child in [2 * index + 1, 2 * index + 2] {
+        if child < self.elements.len() && self.less(greatest, child) {
+          greatest = child;
+        }
+      }
+      if greatest == index {
+        break;
+      }
+      self.elements.swap(index, greatest);
+      index = greatest;
+    }
+  }
+
+  /// Returns whether the element at `a` is less than that at `b`.
+  ///
+  /// # Arguments
+  ///
+  /// * `a` - the index of the first element
+  /// * `b` - the index of the second element
+  fn less(&self, a: usize, b: usize) -> bool {
+    self.order.compare(&self.elements[a], &self.elements[b]) == Ordering::Less
+  }
+}
+
+impl<T: Ord, const N: usize> Default for BinaryHeap<T, N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T: fmt::Debug, const N: usize, O> fmt::Debug for BinaryHeap<T, N, O> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_list().entries(self.iter()).finish()
+  }
+}
+
+impl<T, const N: usize, O: Order<T>> Extend<T> for BinaryHeap<T, N, O> {
+  /// Adds every element of `iter`.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the heap fills up.
+  fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+    for value in iter {
+      self.push(value);
+    }
+  }
+}
+
+impl<'a, T, const N: usize, O> IntoIterator for &'a BinaryHeap<T, N, O> {
+  type Item = &'a T;
+  type IntoIter = core::slice::Iter<'a, T>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{BinaryHeap, Min};
+  use core::cmp::Ordering;
+
+  /// Pops every element of `heap` into an array, in order.
+  fn drain<O: super::Order<u32>, const N: usize>(
+    heap: &mut BinaryHeap<u32, N, O>,
+  ) -> [u32; N] {
+    let mut values = [0; N];
+    for value in values.iter_mut() {
+      *value = heap.pop().unwrap();
+    }
+    assert!(heap.pop().is_none());
+    values
+  }
+
+  #[test]
+  fn elements_are_popped_greatest_first() {
+    let mut heap = BinaryHeap::<u32, 8>::new();
+    heap.extend([5, 1, 8, 3, 9, 2, 7, 4]);
+    assert!(heap.is_full());
+    assert_eq!(heap.try_push(6), Err(6));
+    assert_eq!(heap.peek(), Some(&9));
+    assert_eq!(drain(&mut heap), [9, 8, 7, 5, 4, 3, 2, 1]);
+  }
+
+  #[test]
+  fn orders_decide_what_is_popped_first() {
+    let mut heap = BinaryHeap::<u32, 5, _>::with_order(Min);
+    heap.extend([5, 1, 8, 3, 9]);
+    assert_eq!(drain(&mut heap), [1, 3, 5, 8, 9]);
+
+    // Orders by the number of set bits, then by value.
+    let by_bits = |a: &u32, b: &u32| -> Ordering {
+      a.count_ones().cmp(&b.count_ones()).then(a.cmp(b))
+    };
+    let mut heap = BinaryHeap::<u32, 4, _>::with_order(by_bits);
+    heap.extend([7, 8, 3, 16]);
+    assert_eq!(drain(&mut heap), [7, 3, 16, 8]);
+  }
+
+  #[test]
+  fn elements_are_popped_conditionally_and_retained() {
+    let mut heap = BinaryHeap::<u32, 8, _>::with_order(Min);
+    heap.extend([40, 10, 30, 20, 50]);
+    assert_eq!(heap.pop_if(|&deadline| deadline <= 15), Some(10));
+    assert_eq!(heap.pop_if(|&deadline| deadline <= 15), None);
+    heap.retain(|&deadline| deadline != 30);
+    assert_eq!(heap.len(), 3);
+    assert_eq!(heap.pop(), Some(20));
+    assert_eq!(heap.pop(), Some(40));
+    assert_eq!(heap.pop(), Some(50));
+  }
+
+  #[test]
+  #[should_panic]
+  fn push_panics_when_full() {
+    let mut heap = BinaryHeap::<u32, 1>::new();
+    heap.push(1);
+    heap.push(2);
+  }
+}
@@ -0,0 +1,377 @@
+//! This module provides [`Bitmap`], a set of bits backed by words of memory
+//! that it does not own, for allocators of frames, clusters, inodes and
+//! interrupt vectors.
+//!
+//! Bits are numbered from the least significant bit of the first word, and
+//! scans look at a whole word at a time, skipping full or empty words without
+//! looking at their bits.
+
+/// The number of bits in each word of a [`Bitmap`].
+const WORD_BITS: usize = usize::BITS as usize;
+
+/// A set of `len` bits, stored in borrowed words.
+pub struct Bitmap<'a> {
+  words: &'a mut [usize],
+  len: usize,
+}
+
+impl<'a> Bitmap<'a> {
+  /// Returns the number of words needed to hold `len` bits.
+  ///
+  /// # Arguments
+  ///
+  /// * `len` - the number of bits
+  pub const fn words_for(len: usize) -> usize {
+    (len + WORD_BITS - 1) / WORD_BITS
+  }
+
+  /// Constructs a bitmap of `len` bits in `words`, with every bit clear.
+  ///
+  /// # Arguments
+  ///
+  /// * `words` - the words to hold the bits
+  /// * `len` - the number of bits
+  ///
+  /// # Panics
+  ///
+  /// Panics if `words` is too short to hold `len` bits.
+  pub fn new(words: &'a mut [usize], len: usize) -> Self {
+    let bitmap = Self::from_words(words, len);
+    bitmap.words.fill(0);
+    bitmap
+  }
+
+  /// Constructs a bitmap of `len` bits from the bits already in `words`.
+  ///
+  /// # Arguments
+  ///
+  /// * `words` - the words holding the bits
+  /// * `len` - the number of bits
+  ///
+  /// # Panics
+  ///
+  /// Panics if `words` is too short to hold `len` bits.
+  pub fn from_words(words: &'a mut [usize], len: usize) -> Self {
+    let count = Self::words_for(len);
+    assert!(words.len() >= count, "too few words for the bitmap");
+    let bitmap = Self {
+      words: &mut words[..count],
+      len,
+    };
+    // Bits beyond the end are kept clear, so that they never count.
+    if let Some(last) = bitmap.words.last_mut() {
+      *last &= tail_mask(len);
+    }
+    bitmap
+  }
+
+  /// Returns the number of bits in the bitmap.
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  /// Returns whether the bitmap has no bits.
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Returns whether bit `index` is set.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the bit
+  ///
+  /// # Panics
+  ///
+  /// Panics if `index` is out of bounds.
+  pub fn test(&self, index: usize) -> bool {
+    self.check(index);
+    self.words[index / WORD_BITS] & bit(index) != 0
+  }
+
+  /// Sets bit `index`.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the bit
+  ///
+  /// # Panics
+  ///
+  /// Panics if `index` is out of bounds.
+  pub fn set(&mut self, index: usize) {
+    self.check(index);
+    self.words[index / WORD_BITS] |= bit(index);
+  }
+
+  /// Clears bit `index`.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the bit
+  ///
+  /// # Panics
+  ///
+  /// Panics if `index` is out of bounds.
+  pub fn clear(&mut self, index: usize) {
+    self.check(index);
+    self.words[index / WORD_BITS] &= !bit(index);
+  }
+
+  /// Sets every bit of `range`.
+  ///
+  /// # Arguments
+  ///
+  /// * `range` - the indices of the bits
+  ///
+  /// # Panics
+  ///
+  /// Panics if `range` extends beyond the bitmap.
+  pub fn set_range(&mut self, range: core::ops::Range<usize>) {
+    self.update_range(range, |word, mask| *word |= mask);
+  }
+
+  /// Clears every bit of `range`.
+  ///
+  /// # Arguments
+  ///
+  /// * `range` - the indices of the bits
+  ///
+  /// # Panics
+  ///
+  /// Panics if `range` extends beyond the bitmap.
+  pub fn clear_range(&mut self, range: core::ops::Range<usize>) {
+    self.update_range(range, |word, mask| *word &= !mask);
+  }
+
+  /// Returns the index of the first clear bit, if any.
+  pub fn find_first_zero(&self) -> Option<usize> {
+    self.find_zero_from(0)
+  }
+
+  /// Returns the index of the first clear bit at or after `start`, if any.
+  ///
+  /// # Arguments
+  ///
+  /// * `start` - the index to start scanning from
+  pub fn find_zero_from(&self, start: usize) -> Option<usize> {
+    self.find_from(start, |word| !word)
+  }
+
+  /// Returns the index of the first set bit, if any.
+  pub fn find_first_one(&self) -> Option<usize> {
+    self.find_one_from(0)
+  }
+
+  /// Returns the index of the first set bit at or after `start`, if any.
+  ///
+  /// # Arguments
+  ///
+  /// * `start` - the index to start scanning from
+  pub fn find_one_from(&self, start: usize) -> Option<usize> {
+    self.find_from(start, |word| word)
+  }
+
+  /// Returns the index of the first run of `count` clear bits, if any.
+  ///
+  /// # Arguments
+  ///
+  /// * `count` - the number of clear bits needed
+  pub fn find_zeros(&self, count: usize) -> Option<usize> {
+    let mut start = self.find_first_zero()?;
+    loop {
+      let end = start.checked_add(count)?;
+      if end > self.len {
+        return None;
+      }
+      match self.find_one_from(start).filter(|&one| one < end) {
+        None => return Some(start),
+        Some(one) => start = self.find_zero_from(one)?,
+      }
+    }
+  }
+
+  /// Returns the number of set bits.
+  pub fn count_ones(&self) -> usize {
+    self
+      .words
+      .iter()
+      .map(|word| word.count_ones() as usize)
+      .sum()
+  }
+
+  /// Returns the number of clear bits.
+  pub fn count_zeros(&self) -> usize {
+    self.len - self.count_ones()
+  }
+
+  /// Returns the words holding the bits.
+  pub fn as_words(&self) -> &[usize] {
+    self.words
+  }
+
+  /// Panics if `index` is out of bounds.
+  fn check(&self, index: usize) {
+    assert!(index < self.len, "bit {} out of bounds", index);
+  }
+
+  /// Returns the index of the first bit at or after `start` that is set in
+  /// the words as transformed by `f`.
+  ///
+  /// # Arguments
+  ///
+  /// * `start` - the index to start scanning from
+  /// * `f` - the transformation of each word, such that the bits sought are
+  ///   set
+  fn find_from(
+    &self,
+    start: usize,
+    f: impl Fn(usize) -> usize,
+  ) -> Option<usize> {
+    if start >= self.len {
+      return None;
+    }
+    let first = start / WORD_BITS;
+    let skipped = bit(start) - 1;
+    self.words[first..]
+      .iter()
+      .enumerate()
+      .map(|(i, &word)| {
+        let word = f(word);
+        (i + first, if i == 0 { word & !skipped } else { word })
+      })
+      .find(|&(_, word)| word != 0)
+      .map(|(i, word)| i * WORD_BITS + word.trailing_zeros() as usize)
+      .filter(|&index| index < self.len)
+  }
+
+  /// Applies `f` to each word covering `range`, with the mask of the bits of
+  /// the word that are within it.
+  ///
+  /// # Arguments
+  ///
+  /// * `range` - the indices of the bits
+  /// * `f` - the update of each word
+  fn update_range(
+    &mut self,
+    range: core::ops::Range<usize>,
+    f: impl Fn(&mut usize, usize),
+  ) {
+    assert!(
+      range.start <= range.end && range.end <= self.len,
+      "bit range {:?} out of bounds",
+      range
+    );
+    if range.is_empty() {
+      return;
+    }
+    let first = range.start / WORD_BITS;
+    let last = (range.end - 1) / WORD_BITS;
+    for i in first..=last {
+      let mut mask = usize::MAX;
+      if i == first {
+        mask &= !(bit(range.start) - 1);
+      }
+      if i == last {
+        mask &= tail_mask(range.end);
+      }
+      f(&mut self.words[i], mask);
+    }
+  }
+}
+
+/// Returns the mask of bit `index` within its word.
+///
+/// # Arguments
+///
+/// * `index` - the index of the bit
+fn bit(index: usize) -> usize {
+  1 << (index % WORD_BITS)
+}
+
+/// Returns the mask of the bits of the last word of a bitmap of `len` bits
+/// that are within the bitmap.
+///
+/// # Arguments
+///
+/// * `len` - the number of bits
+fn tail_mask(len: usize) -> usize {
+  match len % WORD_BITS {
+    0 => usize::MAX,
+    bits => (1 << bits) - 1,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Bitmap, WORD_BITS};
+
+  #[test]
+  fn set_clear_and_test() {
+    let mut words = [usize::MAX; 2];
+    let mut bitmap = Bitmap::new(&mut words, 70);
+    assert_eq!(bitmap.count_ones(), 0);
+    bitmap.set(0);
+    bitmap.set(69);
+    assert!(bitmap.test(0) && bitmap.test(69) && !bitmap.test(1));
+    bitmap.clear(0);
+    assert!(!bitmap.test(0));
+    assert_eq!(bitmap.count_ones(), 1);
+    assert_eq!(bitmap.count_zeros(), 69);
+  }
+
+  #[test]
+  #[should_panic]
+  fn out_of_bounds_bits_panic() {
+    let mut words = [0; 1];
+    Bitmap::new(&mut words, 10).set(10);
+  }
+
+  #[test]
+  fn ranges_span_words() {
+    let mut words = [0; 3];
+    let mut bitmap = Bitmap::new(&mut words, 3 * WORD_BITS);
+    bitmap.set_range(5..2 * WORD_BITS + 3);
+    assert_eq!(bitmap.count_ones(), 2 * WORD_BITS - 2);
+    assert!(!bitmap.test(4) && bitmap.test(5));
+    assert!(bitmap.test(2 * WORD_BITS + 2) && !bitmap.test(2 * WORD_BITS + 3));
+    bitmap.clear_range(WORD_BITS..WORD_BITS + 1);
+    assert!(!bitmap.test(WORD_BITS));
+    bitmap.set_range(7..7);
+    assert_eq!(bitmap.count_ones(), 2 * WORD_BITS - 3);
+  }
+
+  #[test]
+  fn scans_find_the_first_match() {
+    let mut words = [0; 3];
+    let mut bitmap = Bitmap::new(&mut words, 2 * WORD_BITS + 4);
+    assert_eq!(bitmap.find_first_one(), None);
+    bitmap.set_range(0..WORD_BITS + 3);
+    assert_eq!(bitmap.find_first_zero(), Some(WORD_BITS + 3));
+    assert_eq!(bitmap.find_one_from(2), Some(2));
+    bitmap.set_range(WORD_BITS + 3..2 * WORD_BITS + 4);
+    assert_eq!(bitmap.find_first_zero(), None);
+    bitmap.clear(2 * WORD_BITS + 3);
+    assert_eq!(bitmap.find_zero_from(1), Some(2 * WORD_BITS + 3));
+  }
+
+  #[test]
+  fn runs_of_zeros_are_found() {
+    let mut words = [0; 2];
+    let mut bitmap = Bitmap::new(&mut words, 2 * WORD_BITS);
+    bitmap.set(3);
+    bitmap.set(10);
+    assert_eq!(bitmap.find_zeros(3), Some(0));
+    assert_eq!(bitmap.find_zeros(4), Some(4));
+    assert_eq!(bitmap.find_zeros(7), Some(11));
+    assert_eq!(bitmap.find_zeros(2 * WORD_BITS - 11), Some(11));
+    assert_eq!(bitmap.find_zeros(2 * WORD_BITS - 10), None);
+  }
+
+  #[test]
+  fn bits_beyond_the_end_are_ignored() {
+    let mut words = [usize::MAX];
+    let bitmap = Bitmap::from_words(&mut words, 3);
+    assert_eq!(bitmap.count_ones(), 3);
+    assert_eq!(bitmap.find_first_zero(), None);
+  }
+}
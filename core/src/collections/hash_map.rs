@@ -0,0 +1,639 @@
+//! This module provides [`HashMap`], a hash table with open addressing, whose
+//! entries are stored in a single allocation from the global allocator, along
+//! with its iterators.
+//!
+//! Collisions are resolved by linear probing, and removed entries leave a
+//! tombstone that later insertions reuse. The table grows to keep at most
+//! seven eighths of its slots in use, including tombstones, so that every
+//! probe ends at an empty slot.
+
+use crate::hash::RandomState;
+use alloc::vec::{self, Vec};
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::iter::FusedIterator;
+use core::mem;
+use core::slice;
+
+/// The fewest slots of a table that holds anything.
+const MIN_SLOTS: usize = 8;
+
+/// A map from keys to values, hashed by `S`, which is keyed SipHash by
+/// default.
+#[derive(Clone)]
+pub struct HashMap<K, V, S = RandomState> {
+  slots: Vec<Slot<K, V>>,
+  len: usize,
+  deleted: usize,
+  hasher: S,
+}
+
+/// A slot of a table.
+#[derive(Clone)]
+enum Slot<K, V> {
+  /// The slot has never held an entry since the table was last rebuilt.
+  Empty,
+
+  /// The slot held an entry that has been removed.
+  Deleted,
+
+  /// The slot holds an entry.
+  Full(K, V),
+}
+
+impl<K, V> HashMap<K, V, RandomState> {
+  /// Constructs an empty map, which does not allocate until an entry is
+  /// inserted.
+  pub fn new() -> Self {
+    Self::with_hasher(RandomState::new())
+  }
+
+  /// Constructs an empty map with room for at least `capacity` entries.
+  ///
+  /// # Arguments
+  ///
+  /// * `capacity` - the number of entries to make room for
+  pub fn with_capacity(capacity: usize) -> Self {
+    Self::with_capacity_and_hasher(capacity, RandomState::new())
+  }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+  /// Constructs an empty map that hashes keys with `hasher`, which does not
+  /// allocate until an entry is inserted.
+  ///
+  /// # Arguments
+  ///
+  /// * `hasher` - the builder of the hashers of keys
+  pub const fn with_hasher(hasher: S) -> Self {
+    Self {
+      slots: Vec::new(),
+      len: 0,
+      deleted: 0,
+      hasher,
+    }
+  }
+
+  /// Constructs an empty map that hashes keys with `hasher`, with room for at
+  /// least `capacity` entries.
+  ///
+  /// # Arguments
+  ///
+  /// * `capacity` - the number of entries to make room for
+  /// * `hasher` - the builder of the hashers of keys
+  pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+    let mut map = Self::with_hasher(hasher);
+    if capacity > 0 {
+      map.slots = empty_slots(slots_for(capacity));
+    }
+    map
+  }
+
+  /// Returns the builder of the hashers of keys.
+  pub fn hasher(&self) -> &S {
+    &self.hasher
+  }
+
+  /// Returns the number of entries that the map can hold without growing.
+  pub fn capacity(&self) -> usize {
+    self.slots.len() / 8 * 7
+  }
+
+  /// Returns the number of entries in the map.
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  /// Returns whether the map holds no entries.
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Removes every entry, keeping the memory for reuse.
+  pub fn clear(&mut self) {
+    self.slots.fill_with(|| Slot::Empty);
+    self.len = 0;
+    self.deleted = 0;
+  }
+
+  /// Returns an iterator over the entries, in an unspecified order.
+  pub fn iter(&self) -> Iter<'_, K, V> {
+    Iter {
+      slots: self.slots.iter(),
+      len: self.len,
+    }
+  }
+
+  /// Returns an iterator over the entries, with mutable values, in an
+  /// unspecified order.
+  pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+    IterMut {
+      slots: self.slots.iter_mut(),
+      len: self.len,
+    }
+  }
+
+  /// Returns an iterator over the keys, in an unspecified order.
+  pub fn keys(&self) -> impl ExactSizeIterator<Item = &K> + '_ {
+    self.iter().map(|(key, _)| key)
+  }
+
+  /// Returns an iterator over the values, in an unspecified order.
+  pub fn values(&self) -> impl ExactSizeIterator<Item = &V> + '_ {
+    self.iter().map(|(_, value)| value)
+  }
+
+  /// Returns an iterator over the values, mutably, in an unspecified order.
+  pub fn values_mut(&mut self) -> impl ExactSizeIterator<Item = &mut V> + '_ {
+    self.iter_mut().map(|(_, value)| value)
+  }
+
+  /// Removes every entry for which `keep` returns `false`.
+  ///
+  /// # Arguments
+  ///
+  /// * `keep` - decides whether to keep each entry
+  pub fn retain(&mut self, mut keep: impl FnMut(&K, &mut V) -> bool) {
+    for slot in self.slots.iter_mut() {
+      if let Slot::Full(key, value) = slot {
+        if !keep(key, value) {
+          *slot = Slot::Deleted;
+          self.len -= 1;
+          self.deleted += 1;
+        }
+      }
+    }
+  }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> HashMap<K, V, S> {
+  /// Makes room for at least `additional` more entries.
+  ///
+  /// # Arguments
+  ///
+  /// * `additional` - the number of entries to make room for
+  pub fn reserve(&mut self, additional: usize) {
+    let used = self.len + self.deleted + additional;
+    if used * 8 > self.slots.len() * 7 {
+      // Tables only grow, so that churning through entries does not shrink
+      // them only to grow them again.
+      self.rebuild(slots_for(self.len + additional).max(self.slots.len()));
+    }
+  }
+
+  /// Returns the value of `key`, if it is in the map.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - the key to look up
+  pub fn get<Q>(&self, key: &Q) -> Option<&V>
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self.get_key_value(key).map(|(_, value)| value)
+  }
+
+  /// Returns the entry of `key`, if it is in the map.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - the key to look up
+  pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    match &self.slots[self.find(key)?] {
+      Slot::Full(key, value) => Some((key, value)),
+      _ => None,
+    }
+  }
+
+  /// Returns the value of `key` mutably, if it is in the map.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - the key to look up
+  pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    let index = self.find(key)?;
+    match &mut self.slots[index] {
+      Slot::Full(_, value) => Some(value),
+      _ => None,
+    }
+  }
+
+  /// Returns whether `key` is in the map.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - the key to look up
+  pub fn contains_key<Q>(&self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self.find(key).is_some()
+  }
+
+  /// Inserts `value` for `key`, returning the value that it replaces, if
+  /// any.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - the key to insert
+  /// * `value` - the value to insert
+  pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+    if let Some(old) = self.get_mut(&key) {
+      return Some(mem::replace(old, value));
+    }
+    self.insert_new(key, value);
+    None
+  }
+
+  /// Returns the value of `key`, first inserting the result of `default` if
+  /// `key` is not in the map.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - the key to look up
+  /// * `default` - makes the value to insert
+  pub fn get_or_insert_with(
+    &mut self,
+    key: K,
+    default: impl FnOnce() -> V,
+  ) -> &mut V {
+    let index = match self.find(&key) {
+      Some(index) => index,
+      None => self.insert_new(key, default()),
+    };
+    match &mut self.slots[index] {
+      Slot::Full(_, value) => value,
+      _ => unreachable!("the entry was just found or inserted"),
+    }
+  }
+
+  /// Removes `key`, returning its value, if it was in the map.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - the key to remove
+  pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self.remove_entry(key).map(|(_, value)| value)
+  }
+
+  /// Removes `key`, returning its entry, if it was in the map.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - the key to remove
+  pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    let index = self.find(key)?;
+    // A slot followed by an empty one ends no probe but its own, so it can be
+    // emptied rather than left as a tombstone.
+    let next = (index + 1) & (self.slots.len() - 1);
+    let vacated = if matches!(self.slots[next], Slot::Empty) {
+      Slot::Empty
+    } else {
+      self.deleted += 1;
+      Slot::Deleted
+    };
+    self.len -= 1;
+    match mem::replace(&mut self.slots[index], vacated) {
+      Slot::Full(key, value) => Some((key, value)),
+      _ => None,
+    }
+  }
+
+  /// Returns the index of the slot holding `key`, if it is in the map.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - the key to look up
+  fn find<Q>(&self, key: &Q) -> Option<usize>
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    if self.len == 0 {
+      return None;
+    }
+    let mask = self.slots.len() - 1;
+    let mut index = self.hasher.hash_one(key) as usize & mask;
+    loop {
+      match &self.slots[index] {
+        Slot::Empty => return None,
+        Slot::Full(k, _) if k.borrow() == key => return Some(index),
+        _ => index = (index + 1) & mask,
+      }
+    }
+  }
+
+  /// Inserts `value` for `key`, which is not in the map, returning the index
+  /// of the slot it is put in.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - the key to insert
+  /// * `value` - the value to insert
+  fn insert_new(&mut self, key: K, value: V) -> usize {
+    self.reserve(1);
+    let index = self.vacant(&key);
+    if matches!(self.slots[index], Slot::Deleted) {
+      self.deleted -= 1;
+    }
+    self.slots[index] = Slot::Full(key, value);
+    self.len += 1;
+    index
+  }
+
+  /// Returns the index of the first slot without an entry on the probe of
+  /// `key`.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - the key to find a slot for
+  fn vacant(&self, key: &K) -> usize {
+    let mask = self.slots.len() - 1;
+    let mut index = self.hasher.hash_one(key) as usize & mask;
+    while let Slot::Full(..) = self.slots[index] {
+      index = (index + 1) & mask;
+    }
+    index
+  }
+
+  /// Moves every entry into a new table of `slots` slots, dropping the
+  /// tombstones.
+  ///
+  /// # Arguments
+  ///
+  /// * `slots` - the number of slots, which is a power of two
+  fn rebuild(&mut self, slots: usize) {
+    let old = mem::replace(&mut self.slots, empty_slots(slots));
+    self.deleted = 0;
+    for slot in old {
+      if let Slot::Full(key, value) = slot {
+        let index = self.vacant(&key);
+        self.slots[index] = Slot::Full(key, value);
+      }
+    }
+  }
+}
+
+impl<K, V, S: Default> Default for HashMap<K, V, S> {
+  fn default() -> Self {
+    Self::with_hasher(S::default())
+  }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for HashMap<K, V, S> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_map().entries(self.iter()).finish()
+  }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> Extend<(K, V)> for HashMap<K, V, S> {
+  fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+    for (key, value) in iter {
+      self.insert(key, value);
+    }
+  }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V)>
+  for HashMap<K, V, S>
+{
+  fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+    let mut map = Self::default();
+    map.extend(iter);
+    map
+  }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a HashMap<K, V, S> {
+  type Item = (&'a K, &'a V);
+  type IntoIter = Iter<'a, K, V>;
+
+  fn into_iter(self) -> Iter<'a, K, V> {
+    self.iter()
+  }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut HashMap<K, V, S> {
+  type Item = (&'a K, &'a mut V);
+  type IntoIter = IterMut<'a, K, V>;
+
+  fn into_iter(self) -> IterMut<'a, K, V> {
+    self.iter_mut()
+  }
+}
+
+impl<K, V, S> IntoIterator for HashMap<K, V, S> {
+  type Item = (K, V);
+  type IntoIter = IntoIter<K, V>;
+
+  fn into_iter(self) -> IntoIter<K, V> {
+    IntoIter {
+      slots: self.slots.into_iter(),
+      len: self.len,
+    }
+  }
+}
+
+/// An iterator over the entries of a [`HashMap`].
+pub struct Iter<'a, K, V> {
+  slots: slice::Iter<'a, Slot<K, V>>,
+  len: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+  type Item = (&'a K, &'a V);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let entry = self.slots.find_map(|slot| match slot {
+      Slot::Full(key, value) => Some((key, value)),
+      _ => None,
+    })?;
+    self.len -= 1;
+    Some(entry)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+/// An iterator over the entries of a [`HashMap`], with mutable values.
+pub struct IterMut<'a, K, V> {
+  slots: slice::IterMut<'a, Slot<K, V>>,
+  len: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+  type Item = (&'a K, &'a mut V);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let entry = self.slots.find_map(|slot| match slot {
+      Slot::Full(key, value) => Some((&*key, value)),
+      _ => None,
+    })?;
+    self.len -= 1;
+    Some(entry)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> FusedIterator for IterMut<'_, K, V> {}
+
+/// An iterator that moves the entries out of a [`HashMap`].
+pub struct IntoIter<K, V> {
+  slots: vec::IntoIter<Slot<K, V>>,
+  len: usize,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+  type Item = (K, V);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let entry = self.slots.find_map(|slot| match slot {
+      Slot::Full(key, value) => Some((key, value)),
+      _ => None,
+    })?;
+    self.len -= 1;
+    Some(entry)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V> FusedIterator for IntoIter<K, V> {}
+
+/// Returns the number of slots of a table holding up to `capacity` entries.
+///
+/// # Arguments
+///
+/// * `capacity` - the number of entries
+fn slots_for(capacity: usize) -> usize {
+  (capacity * 8 / 7 + 1).next_power_of_two().max(MIN_SLOTS)
+}
+
+/// Returns `len` empty slots.
+///
+/// # Arguments
+///
+/// * `len` - the number of slots
+fn empty_slots<K, V>(len: usize) -> Vec<Slot<K, V>> {
+  let mut slots = Vec::with_capacity(len);
+  slots.resize_with(len, || Slot::Empty);
+  slots
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::HashMap;
+  use crate::hash::RandomState;
+  use std::format;
+  use std::string::{String, ToString};
+  use std::vec::Vec;
+
+  #[test]
+  fn entries_are_inserted_replaced_and_removed() {
+    let mut map = HashMap::new();
+    assert_eq!(map.insert("a".to_string(), 1), None);
+    assert_eq!(map.insert("b".to_string(), 2), None);
+    assert_eq!(map.insert("a".to_string(), 3), Some(1));
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get("a"), Some(&3));
+    assert_eq!(map.remove("a"), Some(3));
+    assert_eq!(map.remove("a"), None);
+    assert!(!map.contains_key("a") && map.contains_key("b"));
+    *map.get_mut("b").unwrap() += 10;
+    assert_eq!(map.get_key_value("b"), Some((&"b".to_string(), &12)));
+    assert_eq!(map.len(), 1);
+  }
+
+  #[test]
+  fn the_table_grows_and_reuses_tombstones() {
+    let mut map = HashMap::with_hasher(RandomState::with_keys(1, 2));
+    for i in 0..1000 {
+      map.insert(i, i * 2);
+    }
+    assert!(map.capacity() >= 1000);
+    for i in (0..1000).step_by(2) {
+      assert_eq!(map.remove(&i), Some(i * 2));
+    }
+    let capacity = map.capacity();
+    // Churning through removed keys refills tombstones rather than growing.
+    for round in 0..10 {
+      for i in (0..1000).step_by(2) {
+        map.insert(i, round);
+      }
+      for i in (0..1000).step_by(2) {
+        map.remove(&i);
+      }
+    }
+    assert_eq!(map.capacity(), capacity);
+    assert_eq!(map.len(), 500);
+    assert!((1..1000).step_by(2).all(|i| map.get(&i) == Some(&(i * 2))));
+  }
+
+  #[test]
+  fn iteration_visits_every_entry_once() {
+    let mut map: HashMap<u32, u32> = (0..100).map(|i| (i, i)).collect();
+    for (_, value) in map.iter_mut() {
+      *value += 1;
+    }
+    map.retain(|key, _| key % 3 == 0);
+    let mut entries: Vec<_> = map.iter().map(|(&k, &v)| (k, v)).collect();
+    entries.sort();
+    assert_eq!(entries.len(), map.len());
+    assert!(entries.iter().all(|&(key, value)| value == key + 1));
+    assert_eq!(map.keys().len(), 34);
+    let mut owned: Vec<_> = map.into_iter().collect();
+    owned.sort();
+    assert_eq!(owned, entries);
+  }
+
+  #[test]
+  fn missing_values_are_inserted_on_demand() {
+    let mut map: HashMap<&str, Vec<String>> = HashMap::default();
+    map
+      .get_or_insert_with("irq1", Vec::new)
+      .push("kbd".to_string());
+    map
+      .get_or_insert_with("irq1", Vec::new)
+      .push("mouse".to_string());
+    assert_eq!(map.get("irq1").unwrap(), &["kbd", "mouse"]);
+    map.clear();
+    assert!(map.is_empty() && map.get("irq1").is_none());
+    assert_eq!(format!("{:?}", map), "{}");
+  }
+}
@@ -0,0 +1,290 @@
+//! This module provides [`HashSet`], a set of values stored as the keys of a
+//! [`HashMap`], along with its iterators.
+
+use super::hash_map::{self, HashMap};
+use crate::hash::RandomState;
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+use core::iter::FusedIterator;
+
+/// A set of values, hashed by `S`, which is keyed SipHash by default.
+#[derive(Clone)]
+pub struct HashSet<T, S = RandomState> {
+  map: HashMap<T, (), S>,
+}
+
+impl<T> HashSet<T, RandomState> {
+  /// Constructs an empty set, which does not allocate until a value is
+  /// inserted.
+  pub fn new() -> Self {
+    Self {
+      map: HashMap::new(),
+    }
+  }
+
+  /// Constructs an empty set with room for at least `capacity` values.
+  ///
+  /// # Arguments
+  ///
+  /// * `capacity` - the number of values to make room for
+  pub fn with_capacity(capacity: usize) -> Self {
+    Self {
+      map: HashMap::with_capacity(capacity),
+    }
+  }
+}
+
+impl<T, S> HashSet<T, S> {
+  /// Constructs an empty set that hashes values with `hasher`, which does not
+  /// allocate until a value is inserted.
+  ///
+  /// # Arguments
+  ///
+  /// * `hasher` - the builder of the hashers of values
+  pub const fn with_hasher(hasher: S) -> Self {
+    Self {
+      map: HashMap::with_hasher(hasher),
+    }
+  }
+
+  /// Constructs an empty set that hashes values with `hasher`, with room for
+  /// at least `capacity` values.
+  ///
+  /// # Arguments
+  ///
+  /// * `capacity` - the number of values to make room for
+  /// * `hasher` - the builder of the hashers of values
+  pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+    Self {
+      map: HashMap::with_capacity_and_hasher(capacity, hasher),
+    }
+  }
+
+  /// Returns the builder of the hashers of values.
+  pub fn hasher(&self) -> &S {
+    self.map.hasher()
+  }
+
+  /// Returns the number of values that the set can hold without growing.
+  pub fn capacity(&self) -> usize {
+    self.map.capacity()
+  }
+
+  /// Returns the number of values in the set.
+  pub fn len(&self) -> usize {
+    self.map.len()
+  }
+
+  /// Returns whether the set holds no values.
+  pub fn is_empty(&self) -> bool {
+    self.map.is_empty()
+  }
+
+  /// Removes every value, keeping the memory for reuse.
+  pub fn clear(&mut self) {
+    self.map.clear();
+  }
+
+  /// Returns an iterator over the values, in an unspecified order.
+  pub fn iter(&self) -> Iter<'_, T> {
+    Iter(self.map.iter())
+  }
+
+  /// Removes every value for which `keep` returns `false`.
+  ///
+  /// # Arguments
+  ///
+  /// * `keep` - decides whether to keep each value
+  pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+    self.map.retain(|value, _| keep(value));
+  }
+}
+
+impl<T: Hash + Eq, S: BuildHasher> HashSet<T, S> {
+  /// Makes room for at least `additional` more values.
+  ///
+  /// # Arguments
+  ///
+  /// * `additional` - the number of values to make room for
+  pub fn reserve(&mut self, additional: usize) {
+    self.map.reserve(additional);
+  }
+
+  /// Returns whether `value` is in the set.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to look up
+  pub fn contains<Q>(&self, value: &Q) -> bool
+  where
+    T: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self.map.contains_key(value)
+  }
+
+  /// Returns the value in the set equal to `value`, if there is one.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to look up
+  pub fn get<Q>(&self, value: &Q) -> Option<&T>
+  where
+    T: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self.map.get_key_value(value).map(|(value, _)| value)
+  }
+
+  /// Inserts `value`, returning `false` if it was already in the set.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to insert
+  pub fn insert(&mut self, value: T) -> bool {
+    self.map.insert(value, ()).is_none()
+  }
+
+  /// Removes `value`, returning whether it was in the set.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to remove
+  pub fn remove<Q>(&mut self, value: &Q) -> bool
+  where
+    T: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self.map.remove(value).is_some()
+  }
+
+  /// Removes and returns the value in the set equal to `value`, if there is
+  /// one.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to remove
+  pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+  where
+    T: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self.map.remove_entry(value).map(|(value, _)| value)
+  }
+}
+
+impl<T, S: Default> Default for HashSet<T, S> {
+  fn default() -> Self {
+    Self::with_hasher(S::default())
+  }
+}
+
+impl<T: fmt::Debug, S> fmt::Debug for HashSet<T, S> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_set().entries(self.iter()).finish()
+  }
+}
+
+impl<T: Hash + Eq, S: BuildHasher> Extend<T> for HashSet<T, S> {
+  fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+    self.map.extend(iter.into_iter().map(|value| (value, ())));
+  }
+}
+
+impl<T: Hash + Eq, S: BuildHasher + Default> FromIterator<T> for HashSet<T, S> {
+  fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+    let mut set = Self::default();
+    set.extend(iter);
+    set
+  }
+}
+
+impl<'a, T, S> IntoIterator for &'a HashSet<T, S> {
+  type Item = &'a T;
+  type IntoIter = Iter<'a, T>;
+
+  fn into_iter(self) -> Iter<'a, T> {
+    self.iter()
+  }
+}
+
+impl<T, S> IntoIterator for HashSet<T, S> {
+  type Item = T;
+  type IntoIter = IntoIter<T>;
+
+  fn into_iter(self) -> IntoIter<T> {
+    IntoIter(self.map.into_iter())
+  }
+}
+
+/// An iterator over the values of a [`HashSet`].
+pub struct Iter<'a, T>(hash_map::Iter<'a, T, ()>);
+
+impl<'a, T> Iterator for Iter<'a, T> {
+  type Item = &'a T;
+
+  fn next(&mut self) -> Option<&'a T> {
+    self.0.next().map(|(value, _)| value)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.0.size_hint()
+  }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> FusedIterator for Iter<'_, T> {}
+
+/// An iterator that moves the values out of a [`HashSet`].
+pub struct IntoIter<T>(hash_map::IntoIter<T, ()>);
+
+impl<T> Iterator for IntoIter<T> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    self.0.next().map(|(value, _)| value)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.0.size_hint()
+  }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> FusedIterator for IntoIter<T> {}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::HashSet;
+  use std::string::ToString;
+  use std::vec::Vec;
+
+  #[test]
+  fn values_are_inserted_once() {
+    let mut set = HashSet::new();
+    assert!(set.insert("/dev".to_string()));
+    assert!(set.insert("/boot".to_string()));
+    assert!(!set.insert("/dev".to_string()));
+    assert_eq!(set.len(), 2);
+    assert!(set.contains("/dev"));
+    assert!(set.remove("/dev"));
+    assert!(!set.remove("/dev"));
+    assert_eq!(set.take("/boot").as_deref(), Some("/boot"));
+    assert!(set.is_empty());
+  }
+
+  #[test]
+  fn sets_are_collected_and_iterated() {
+    let mut set: HashSet<u32> = [5, 1, 5, 3, 1].into_iter().collect();
+    assert_eq!(set.iter().len(), 3);
+    set.retain(|&value| value > 1);
+    let mut values: Vec<_> = set.into_iter().collect();
+    values.sort();
+    assert_eq!(values, [3, 5]);
+  }
+}
@@ -0,0 +1,624 @@
+//! This module provides [`RangeTree`], an intrusive AVL tree of disjoint
+//! address ranges, for the map of a virtual address space or the registry of
+//! MMIO regions, where ranges are looked up by the addresses they hold and in
+//! order.
+//!
+//! The tree allocates nothing: every node is a value, owned elsewhere, that
+//! embeds a [`RangeLink`] through which the tree links it, and that it names
+//! by implementing [`RangeNode`]. The tree borrows its nodes for as long as it
+//! lives, so they can neither move nor be dropped while they are linked, and
+//! unlinks them when it is dropped, so they can be inserted again.
+//!
+//! Since the ranges are disjoint, their ends are in the same order as their
+//! starts, so every lookup is a single descent by end: the range holding an
+//! address is the first that ends after it, if it starts at or before it.
+
+use crate::error::{Error, Result};
+use core::cell::Cell;
+use core::cmp::Ordering;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Range;
+use core::ptr::NonNull;
+
+/// A value that can be linked into a [`RangeTree`].
+pub trait RangeNode: Sized {
+  /// Returns the link that the tree links the value through.
+  fn link(&self) -> &RangeLink<Self>;
+}
+
+/// The link of a [`RangeNode`], which holds its range and its place in the
+/// tree it is linked into.
+pub struct RangeLink<T> {
+  start: Cell<u64>,
+  end: Cell<u64>,
+  left: Cell<Option<NonNull<T>>>,
+  right: Cell<Option<NonNull<T>>>,
+  height: Cell<u8>,
+  linked: Cell<bool>,
+  _node: PhantomData<T>,
+}
+
+impl<T> RangeLink<T> {
+  /// Constructs the link of a node that is in no tree.
+  pub const fn new() -> Self {
+    Self {
+      start: Cell::new(0),
+      end: Cell::new(0),
+      left: Cell::new(None),
+      right: Cell::new(None),
+      height: Cell::new(0),
+      linked: Cell::new(false),
+      _node: PhantomData,
+    }
+  }
+
+  /// Returns the range of the node, or `None` if it is in no tree.
+  pub fn range(&self) -> Option<Range<u64>> {
+    self.linked.get().then(|| self.start.get()..self.end.get())
+  }
+
+  /// Returns `true` if the node is in a tree.
+  pub fn is_linked(&self) -> bool {
+    self.linked.get()
+  }
+}
+
+impl<T> Default for RangeLink<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T> fmt::Debug for RangeLink<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_tuple("RangeLink").field(&self.range()).finish()
+  }
+}
+
+/// An AVL tree of disjoint, non-empty ranges, each held by a node borrowed
+/// for `'a`.
+pub struct RangeTree<'a, T: RangeNode> {
+  root: Option<NonNull<T>>,
+  len: usize,
+  _nodes: PhantomData<&'a T>,
+}
+
+impl<'a, T: RangeNode> RangeTree<'a, T> {
+  /// Constructs an empty tree.
+  pub const fn new() -> Self {
+    Self {
+      root: None,
+      len: 0,
+      _nodes: PhantomData,
+    }
+  }
+
+  /// Returns the number of nodes in the tree.
+  pub const fn len(&self) -> usize {
+    self.len
+  }
+
+  /// Returns `true` if the tree holds no nodes.
+  pub const fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Links `node` into the tree as holding `range`.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if `range` is empty, and with
+  /// [`Error::AlreadyExists`] if `node` is in a tree already, or `range`
+  /// overlaps the range of another node.
+  ///
+  /// # Arguments
+  ///
+  /// * `range` - the range that the node holds
+  /// * `node` - the node to link
+  pub fn insert(&mut self, range: Range<u64>, node: &'a T) -> Result<()> {
+    if range.is_empty() {
+      return Err(Error::InvalidArgument);
+    }
+    let link = node.link();
+    if link.linked.get() || self.overlapping(range.clone()).next().is_some() {
+      return Err(Error::AlreadyExists);
+    }
+    link.start.set(range.start);
+    link.end.set(range.end);
+    link.left.set(None);
+    link.right.set(None);
+    link.height.set(1);
+    link.linked.set(true);
+    self.root = Some(self.insert_below(self.root, NonNull::from(node)));
+    self.len += 1;
+    Ok(())
+  }
+
+  /// Unlinks `node` from the tree, returning `false` if it is not in it.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node to unlink
+  pub fn remove(&mut self, node: &T) -> bool {
+    let Some(range) = node.link().range() else {
+      return false;
+    };
+    match self.find(range.start) {
+      Some(found) if core::ptr::eq(found, node) => {}
+      _ => return false,
+    }
+    self.root = self.remove_below(self.root, range.start);
+    self.unlink(node);
+    self.len -= 1;
+    true
+  }
+
+  /// Returns the node whose range holds `address`, if any.
+  ///
+  /// # Arguments
+  ///
+  /// * `address` - the address to look up
+  pub fn find(&self, address: u64) -> Option<&'a T> {
+    self
+      .first_ending_after(address)
+      .filter(|node| node.link().start.get() <= address)
+  }
+
+  /// Returns an iterator over the nodes whose ranges overlap `range`, in
+  /// order.
+  ///
+  /// # Arguments
+  ///
+  /// * `range` - the range to look up
+  pub fn overlapping(&self, range: Range<u64>) -> Overlapping<'_, 'a, T> {
+    let next = if range.is_empty() {
+      None
+    } else {
+      self.first_ending_after(range.start)
+    };
+    Overlapping {
+      tree: self,
+      next,
+      end: range.end,
+    }
+  }
+
+  /// Returns an iterator over every node of the tree, in order.
+  pub fn iter(&self) -> impl Iterator<Item = &'a T> + '_ {
+    let first = self.root.map(|root| self.leftmost(root));
+    core::iter::successors(first, |node| self.successor(node))
+  }
+
+  /// Returns the lowest address in `within`, aligned to `align`, at which
+  /// `size` bytes hold no node, or `None` if there is none.
+  ///
+  /// # Arguments
+  ///
+  /// * `within` - the range to search
+  /// * `size` - the number of bytes to find, which must not be `0`
+  /// * `align` - the alignment of the address, which must be a power of two
+  pub fn find_gap(
+    &self,
+    within: Range<u64>,
+    size: u64,
+    align: u64,
+  ) -> Option<u64> {
+    let mut start = align_up(within.start, align)?;
+    for node in self.overlapping(within.clone()) {
+      let link = node.link();
+      if start.checked_add(size)? <= link.start.get() {
+        break;
+      }
+      start = start.max(align_up(link.end.get(), align)?);
+    }
+    (start.checked_add(size)? <= within.end).then_some(start)
+  }
+
+  /// Returns the node with the lowest range that ends after `address`.
+  ///
+  /// # Arguments
+  ///
+  /// * `address` - the address that the range must end after
+  fn first_ending_after(&self, address: u64) -> Option<&'a T> {
+    let mut found = None;
+    let mut next = self.root;
+    while let Some(node) = next.map(|node| self.node(node)) {
+      let link = node.link();
+      if link.end.get() > address {
+        found = Some(node);
+        next = link.left.get();
+      } else {
+        next = link.right.get();
+      }
+    }
+    found
+  }
+
+  /// Returns the node after `node`, in order.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - a node of the tree
+  fn successor(&self, node: &T) -> Option<&'a T> {
+    self.first_ending_after(node.link().end.get())
+  }
+
+  /// Links `node` into the subtree at `root`, returning its new root.
+  ///
+  /// # Arguments
+  ///
+  /// * `root` - the root of the subtree
+  /// * `node` - the node to link, whose range overlaps no other
+  fn insert_below(
+    &self,
+    root: Option<NonNull<T>>,
+    node: NonNull<T>,
+  ) -> NonNull<T> {
+    let Some(root) = root else {
+      return node;
+    };
+    let link = self.node(root).link();
+    if self.node(node).link().start.get() < link.start.get() {
+      link
+        .left
+        .set(Some(self.insert_below(link.left.get(), node)));
+    } else {
+      link
+        .right
+        .set(Some(self.insert_below(link.right.get(), node)));
+    }
+    self.rebalance(root)
+  }
+
+  /// Unlinks the node whose range starts at `start` from the subtree at
+  /// `root`, returning its new root.
+  ///
+  /// # Arguments
+  ///
+  /// * `root` - the root of the subtree, which holds the node
+  /// * `start` - the start of the range of the node
+  fn remove_below(
+    &self,
+    root: Option<NonNull<T>>,
+    start: u64,
+  ) -> Option<NonNull<T>> {
+    let root = root?;
+    let link = self.node(root).link();
+    match start.cmp(&link.start.get()) {
+      Ordering::Less => {
+        link.left.set(self.remove_below(link.left.get(), start));
+      }
+      Ordering::Greater => {
+        link.right.set(self.remove_below(link.right.get(), start));
+      }
+      Ordering::Equal => {
+        let Some(right) = link.right.get() else {
+          return link.left.get();
+        };
+        // The node is replaced by the lowest node of its right subtree.
+        let (right, lowest) = self.remove_lowest(right);
+        let lowest_link = self.node(lowest).link();
+        lowest_link.left.set(link.left.get());
+        lowest_link.right.set(right);
+        return Some(self.rebalance(lowest));
+      }
+    }
+    Some(self.rebalance(root))
+  }
+
+  /// Unlinks the lowest node of the subtree at `root`, returning the new root
+  /// of the subtree and the node.
+  ///
+  /// # Arguments
+  ///
+  /// * `root` - the root of the subtree
+  fn remove_lowest(
+    &self,
+    root: NonNull<T>,
+  ) -> (Option<NonNull<T>>, NonNull<T>) {
+    let link = self.node(root).link();
+    let Some(left) = link.left.get() else {
+      return (link.right.get(), root);
+    };
+    let (left, lowest) = self.remove_lowest(left);
+    link.left.set(left);
+    (Some(self.rebalance(root)), lowest)
+  }
+
+  /// Restores the balance of the subtree at `root`, whose subtrees are
+  /// balanced and differ in height by at most two, returning its new root.
+  ///
+  /// # Arguments
+  ///
+  /// * `root` - the root of the subtree
+  fn rebalance(&self, root: NonNull<T>) -> NonNull<T> {
+    let link = self.node(root).link();
+    let balance = self.balance(root);
+    let root = if balance > 1 {
+      let left = link.left.get().unwrap();
+      if self.balance(left) < 0 {
+        link.left.set(Some(self.rotate_left(left)));
+      }
+      self.rotate_right(root)
+    } else if balance < -1 {
+      let right = link.right.get().unwrap();
+      if self.balance(right) > 0 {
+        link.right.set(Some(self.rotate_right(right)));
+      }
+      self.rotate_left(root)
+    } else {
+      root
+    };
+    self.update_height(root);
+    root
+  }
+
+  /// Rotates the subtree at `root` to the right, returning its new root.
+  ///
+  /// # Arguments
+  ///
+  /// * `root` - the root of the subtree, which has a left child
+  fn rotate_right(&self, root: NonNull<T>) -> NonNull<T> {
+    let link = self.node(root).link();
+    let pivot = link.left.get().unwrap();
+    let pivot_link = self.node(pivot).link();
+    link.left.set(pivot_link.right.get());
+    pivot_link.right.set(Some(root));
+    self.update_height(root);
+    self.update_height(pivot);
+    pivot
+  }
+
+  /// Rotates the subtree at `root` to the left, returning its new root.
+  ///
+  /// # Arguments
+  ///
+  /// * `root` - the root of the subtree, which has a right child
+  fn rotate_left(&self, root: NonNull<T>) -> NonNull<T> {
+    let link = self.node(root).link();
+    let pivot = link.right.get().unwrap();
+    let pivot_link = self.node(pivot).link();
+    link.right.set(pivot_link.left.get());
+    pivot_link.left.set(Some(root));
+    self.update_height(root);
+    self.update_height(pivot);
+    pivot
+  }
+
+  /// Returns the height of the left subtree of `root` less that of its right.
+  ///
+  /// # Arguments
+  ///
+  /// * `root` - the root of the subtree
+  fn balance(&self, root: NonNull<T>) -> i16 {
+    let link = self.node(root).link();
+    self.height(link.left.get()) as i16 - self.height(link.right.get()) as i16
+  }
+
+  /// Recomputes the height of `root` from those of its subtrees.
+  ///
+  /// # Arguments
+  ///
+  /// * `root` - the root of the subtree
+  fn update_height(&self, root: NonNull<T>) {
+    let link = self.node(root).link();
+    let height = self
+      .height(link.left.get())
+      .max(self.height(link.right.get()));
+    link.height.set(height + 1);
+  }
+
+  /// Returns the height of the subtree at `root`, which is `0` if empty.
+  ///
+  /// # Arguments
+  ///
+  /// * `root` - the root of the subtree
+  fn height(&self, root: Option<NonNull<T>>) -> u8 {
+    root.map_or(0, |root| self.node(root).link().height.get())
+  }
+
+  /// Returns the lowest node of the subtree at `root`.
+  ///
+  /// # Arguments
+  ///
+  /// * `root` - the root of the subtree
+  fn leftmost(&self, mut root: NonNull<T>) -> &'a T {
+    while let Some(left) = self.node(root).link().left.get() {
+      root = left;
+    }
+    self.node(root)
+  }
+
+  /// Clears the link of `node`, which has been removed from the tree.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the removed node
+  fn unlink(&self, node: &T) {
+    let link = node.link();
+    link.left.set(None);
+    link.right.set(None);
+    link.height.set(0);
+    link.linked.set(false);
+  }
+
+  /// Clears the links of every node of the subtree at `root`, as the tree is
+  /// dropped.
+  ///
+  /// # Arguments
+  ///
+  /// * `root` - the root of the subtree
+  fn unlink_below(&self, root: Option<NonNull<T>>) {
+    let Some(root) = root.map(|root| self.node(root)) else {
+      return;
+    };
+    let link = root.link();
+    let (left, right) = (link.left.get(), link.right.get());
+    self.unlink(root);
+    self.unlink_below(left);
+    self.unlink_below(right);
+  }
+
+  /// Returns the node that `node` points to.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - a pointer to a node of the tree
+  fn node(&self, node: NonNull<T>) -> &'a T {
+    // SAFETY: every node of the tree was borrowed for `'a`.
+    unsafe { node.as_ref() }
+  }
+}
+
+impl<'a, T: RangeNode> Default for RangeTree<'a, T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<'a, T: RangeNode> Drop for RangeTree<'a, T> {
+  fn drop(&mut self) {
+    self.unlink_below(self.root);
+  }
+}
+
+impl<'a, T: RangeNode + fmt::Debug> fmt::Debug for RangeTree<'a, T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_list().entries(self.iter()).finish()
+  }
+}
+
+/// An iterator over the nodes of a [`RangeTree`] that overlap a range.
+pub struct Overlapping<'t, 'a, T: RangeNode> {
+  tree: &'t RangeTree<'a, T>,
+  next: Option<&'a T>,
+  end: u64,
+}
+
+impl<'t, 'a, T: RangeNode> Iterator for Overlapping<'t, 'a, T> {
+  type Item = &'a T;
+
+  fn next(&mut self) -> Option<&'a T> {
+    let node = self
+      .next
+      .filter(|node| node.link().start.get() < self.end)?;
+    self.next = self.tree.successor(node);
+    Some(node)
+  }
+}
+
+/// Returns `address` rounded up to a multiple of `align`, or `None` if that
+/// overflows.
+///
+/// # Arguments
+///
+/// * `address` - the address to round up
+/// * `align` - the alignment, which must be a power of two
+fn align_up(address: u64, align: u64) -> Option<u64> {
+  Some(address.checked_add(align - 1)? & !(align - 1))
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::vec::Vec;
+
+  #[derive(Default)]
+  struct Mapping {
+    link: RangeLink<Mapping>,
+    id: usize,
+  }
+
+  impl RangeNode for Mapping {
+    fn link(&self) -> &RangeLink<Self> {
+      &self.link
+    }
+  }
+
+  fn ids<'a>(nodes: impl Iterator<Item = &'a Mapping>) -> Vec<usize> {
+    nodes.map(|node| node.id).collect()
+  }
+
+  #[test]
+  fn ranges_are_found_by_address() {
+    let nodes: Vec<_> = (0..64)
+      .map(|id| Mapping {
+        id,
+        ..Mapping::default()
+      })
+      .collect();
+    let mut tree = RangeTree::new();
+    // Inserted out of order, as ranges [id * 0x100, id * 0x100 + 0x80).
+    for node in nodes.iter().rev().step_by(2).chain(nodes.iter().step_by(2)) {
+      let start = node.id as u64 * 0x100;
+      tree.insert(start..start + 0x80, node).unwrap();
+    }
+
+    assert_eq!(tree.len(), 64);
+    assert_eq!(ids(tree.iter()), (0..64).collect::<Vec<_>>());
+    assert_eq!(tree.find(0x27f).map(|node| node.id), Some(2));
+    assert!(tree.find(0x280).is_none());
+    assert_eq!(ids(tree.overlapping(0x180..0x401)), [2, 3, 4]);
+    assert!(tree.root.map_or(0, |root| tree.height(Some(root))) <= 8);
+  }
+
+  #[test]
+  fn overlaps_and_relinks_are_rejected() {
+    let (first, second) = (Mapping::default(), Mapping::default());
+    let mut tree = RangeTree::new();
+    tree.insert(0x1000..0x2000, &first).unwrap();
+
+    assert_eq!(
+      tree.insert(0x1000..0x2000, &first),
+      Err(Error::AlreadyExists)
+    );
+    assert_eq!(
+      tree.insert(0x1fff..0x3000, &second),
+      Err(Error::AlreadyExists)
+    );
+    assert_eq!(
+      tree.insert(0x3000..0x3000, &second),
+      Err(Error::InvalidArgument)
+    );
+    assert!(tree.insert(0x2000..0x3000, &second).is_ok());
+    assert_eq!(second.link.range(), Some(0x2000..0x3000));
+  }
+
+  #[test]
+  fn removal_keeps_the_order() {
+    let nodes: Vec<_> = (0..32)
+      .map(|id| Mapping {
+        id,
+        ..Mapping::default()
+      })
+      .collect();
+    let mut tree = RangeTree::new();
+    for node in &nodes {
+      let start = node.id as u64 * 0x10;
+      tree.insert(start..start + 0x10, node).unwrap();
+    }
+    for node in nodes.iter().filter(|node| node.id % 3 != 0) {
+      assert!(tree.remove(node));
+      assert!(!tree.remove(node));
+    }
+
+    assert_eq!(ids(tree.iter()), (0..32).step_by(3).collect::<Vec<_>>());
+    assert!(!nodes[1].link.is_linked());
+    drop(tree);
+    assert!(!nodes[0].link.is_linked());
+  }
+
+  #[test]
+  fn gaps_are_found_between_ranges() {
+    let (first, second) = (Mapping::default(), Mapping::default());
+    let mut tree = RangeTree::new();
+    tree.insert(0x1000..0x2000, &first).unwrap();
+    tree.insert(0x3000..0x4000, &second).unwrap();
+
+    assert_eq!(tree.find_gap(0x1000..0x10000, 0x1000, 0x1000), Some(0x2000));
+    assert_eq!(tree.find_gap(0x1000..0x10000, 0x1001, 0x1000), Some(0x4000));
+    assert_eq!(tree.find_gap(0..0x1000, 0x800, 0x100), Some(0));
+    assert_eq!(tree.find_gap(0x1000..0x4000, 0x1001, 1), None);
+  }
+}
@@ -0,0 +1,305 @@
+//! This module provides [`RecordRing`], a circular buffer of byte records that
+//! drops whole records, oldest first, to make room for new ones, for logs such
+//! as the kernel's and the early boot log that the bootloader hands over.
+//!
+//! A ring is a [`RecordRingHeader`] directly followed by its data, so that it
+//! can be handed between binaries as a single block of memory. Each record in
+//! the data is framed by its length, as a little-endian `u16`, followed by
+//! that many bytes. Records never wrap around the end of the data, so a
+//! reader always sees each one whole; a length of [`WRAP`], or fewer than two
+//! bytes before the end, means that the next record starts at the beginning
+//! of the data.
+
+/// The length of a record that marks the rest of the data as unused, with the
+/// next record starting at the beginning of the data.
+pub const WRAP: u16 = u16::MAX;
+
+/// The size of a record's length, in bytes.
+const FRAME_SIZE: usize = core::mem::size_of::<u16>();
+
+/// The header of a record ring, which directly precedes its data.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RecordRingHeader {
+  /// The size of the data, in bytes.
+  pub capacity: u32,
+
+  /// The offset within the data of the oldest record.
+  pub head: u32,
+
+  /// The number of bytes of the data in use, starting from `head`, including
+  /// any left unused at the end of the data.
+  pub len: u32,
+
+  /// The number of records that were dropped to make room for newer ones.
+  pub dropped: u32,
+}
+
+crate::const_assert_size!(RecordRingHeader, 16);
+crate::const_assert_offset!(
+  RecordRingHeader,
+  capacity: 0,
+  head: 4,
+  len: 8,
+  dropped: 12,
+);
+
+/// A writer of records to a ring in a buffer.
+pub struct RecordRing<'a> {
+  header: &'a mut RecordRingHeader,
+  data: &'a mut [u8],
+}
+
+impl<'a> RecordRing<'a> {
+  /// Constructs a [`RecordRing`] for an empty ring in `buffer`, returning
+  /// `None` if the buffer is too small or misaligned to hold a ring.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the memory to hold the ring, including its header
+  pub fn new(buffer: &'a mut [u8]) -> Option<Self> {
+    let header_size = core::mem::size_of::<RecordRingHeader>();
+    let align = core::mem::align_of::<RecordRingHeader>();
+    let aligned = buffer.as_ptr() as usize % align;
+    if aligned != 0 || buffer.len() <= header_size + FRAME_SIZE {
+      return None;
+    }
+    let (header, data) = buffer.split_at_mut(header_size);
+    let capacity = data.len().min(u32::MAX as usize);
+    let data = &mut data[..capacity];
+
+    // SAFETY: the header is large enough and aligned for a
+    // `RecordRingHeader`, which is plain integers, and is borrowed
+    // exclusively for the writer.
+    let header =
+      unsafe { &mut *header.as_mut_ptr().cast::<RecordRingHeader>() };
+    *header = RecordRingHeader {
+      capacity: capacity as u32,
+      head: 0,
+      len: 0,
+      dropped: 0,
+    };
+    Some(Self { header, data })
+  }
+
+  /// Appends `record` to the ring, dropping the oldest records if there is
+  /// not enough room for it.
+  ///
+  /// Records longer than the ring can hold are truncated.
+  ///
+  /// # Arguments
+  ///
+  /// * `record` - the bytes of the record
+  pub fn push(&mut self, record: &[u8]) {
+    let capacity = self.data.len();
+    let max = (capacity - FRAME_SIZE).min(WRAP as usize - 1);
+    let record = &record[..record.len().min(max)];
+    let frame = FRAME_SIZE + record.len();
+
+    if self.header.len == 0 {
+      self.header.head = 0;
+    }
+    let tail = self.tail();
+    if capacity - tail < frame {
+      // Records never wrap, so the rest of the data is left unused.
+      let unused = capacity - tail;
+      self.reserve(unused);
+      if unused >= FRAME_SIZE {
+        self.data[tail..tail + FRAME_SIZE].copy_from_slice(&WRAP.to_le_bytes());
+      }
+      self.header.len += unused as u32;
+    }
+
+    self.reserve(frame);
+    let tail = self.tail();
+    let len = (record.len() as u16).to_le_bytes();
+    self.data[tail..tail + FRAME_SIZE].copy_from_slice(&len);
+    self.data[tail + FRAME_SIZE..tail + frame].copy_from_slice(record);
+    self.header.len += frame as u32;
+  }
+
+  /// Drops every record.
+  pub fn clear(&mut self) {
+    self.header.head = 0;
+    self.header.len = 0;
+  }
+
+  /// Returns whether the ring holds no records.
+  pub fn is_empty(&self) -> bool {
+    self.header.len == 0
+  }
+
+  /// Returns the number of records that were dropped to make room for newer
+  /// ones.
+  pub fn dropped(&self) -> u32 {
+    self.header.dropped
+  }
+
+  /// Returns an iterator over the records of the ring, from oldest to newest.
+  pub fn records(&self) -> Records<'_> {
+    Records::new(self.header, self.data)
+  }
+
+  /// Returns the offset within the data that the next record is written at.
+  fn tail(&self) -> usize {
+    (self.header.head as usize + self.header.len as usize) % self.data.len()
+  }
+
+  /// Drops the oldest records until at least `size` bytes are free.
+  ///
+  /// # Arguments
+  ///
+  /// * `size` - the number of bytes to free
+  fn reserve(&mut self, size: usize) {
+    let capacity = self.data.len();
+    while capacity - (self.header.len as usize) < size {
+      let head = self.header.head as usize;
+      let used = match frame(self.data, head) {
+        Some(len) => {
+          self.header.dropped += 1;
+          FRAME_SIZE + len
+        }
+        None => capacity - head,
+      };
+      self.header.head = ((head + used) % capacity) as u32;
+      self.header.len -= used as u32;
+    }
+  }
+}
+
+/// An iterator over the records of a ring, from oldest to newest.
+#[derive(Clone)]
+pub struct Records<'a> {
+  data: &'a [u8],
+  offset: usize,
+  remaining: usize,
+}
+
+impl<'a> Records<'a> {
+  /// An iterator over no records.
+  pub const EMPTY: Self = Self {
+    data: &[],
+    offset: 0,
+    remaining: 0,
+  };
+
+  /// Constructs an iterator over the records of the ring described by
+  /// `header`, whose data is `data`.
+  ///
+  /// A corrupt header or data ends the iteration early, rather than reading
+  /// outside of `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `header` - the header of the ring
+  /// * `data` - the data of the ring
+  pub fn new(header: &RecordRingHeader, data: &'a [u8]) -> Self {
+    Self {
+      data,
+      offset: header.head as usize,
+      remaining: (header.len as usize).min(data.len()),
+    }
+  }
+}
+
+impl<'a> Iterator for Records<'a> {
+  type Item = &'a [u8];
+
+  fn next(&mut self) -> Option<Self::Item> {
+    while self.remaining > 0 {
+      let len = match frame(self.data, self.offset) {
+        Some(len) => len,
+        None => {
+          self.remaining = self
+            .remaining
+            .checked_sub(self.data.len().checked_sub(self.offset)?)?;
+          self.offset = 0;
+          continue;
+        }
+      };
+      let start = self.offset + FRAME_SIZE;
+      let record = self.data.get(start..start + len)?;
+      self.remaining = self.remaining.checked_sub(FRAME_SIZE + len)?;
+      self.offset = (start + len) % self.data.len();
+      return Some(record);
+    }
+    None
+  }
+}
+
+/// Returns the length of the record framed at `offset` of `data`, or `None`
+/// if the rest of the data is unused.
+///
+/// # Arguments
+///
+/// * `data` - the data of the ring
+/// * `offset` - the offset of the record's frame
+fn frame(data: &[u8], offset: usize) -> Option<usize> {
+  let bytes = data.get(offset..offset + FRAME_SIZE)?;
+  match u16::from_le_bytes([bytes[0], bytes[1]]) {
+    WRAP => None,
+    len => Some(len as usize),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{RecordRing, RecordRingHeader};
+
+  /// A buffer aligned for a ring's header.
+  #[repr(C, align(4))]
+  struct Buffer<const N: usize>([u8; N]);
+
+  fn collect<'a>(ring: &'a RecordRing) -> [&'a [u8]; 8] {
+    let mut records = [&[][..]; 8];
+    for (slot, record) in records.iter_mut().zip(ring.records()) {
+      *slot = record;
+    }
+    records
+  }
+
+  #[test]
+  fn records_are_kept_in_order() {
+    let mut buffer = Buffer([0; 64]);
+    let mut ring = RecordRing::new(&mut buffer.0).unwrap();
+    assert!(ring.is_empty());
+    ring.push(b"first");
+    ring.push(b"second");
+    assert_eq!(ring.records().count(), 2);
+    assert_eq!(collect(&ring)[..2], [&b"first"[..], b"second"]);
+  }
+
+  #[test]
+  fn oldest_records_are_dropped_whole() {
+    let size = core::mem::size_of::<RecordRingHeader>() + 16;
+    let mut buffer = Buffer([0; 64]);
+    let mut ring = RecordRing::new(&mut buffer.0[..size]).unwrap();
+    for record in [b"aaaa", b"bbbb", b"cccc", b"dddd"] {
+      ring.push(record);
+    }
+    // Each record takes six bytes, so only the last two fit, and the third
+    // one wrapped to the start.
+    assert_eq!(ring.dropped(), 2);
+    assert_eq!(ring.records().count(), 2);
+    assert_eq!(collect(&ring)[..2], [&b"cccc"[..], b"dddd"]);
+  }
+
+  #[test]
+  fn long_records_are_truncated() {
+    let size = core::mem::size_of::<RecordRingHeader>() + 8;
+    let mut buffer = Buffer([0; 64]);
+    let mut ring = RecordRing::new(&mut buffer.0[..size]).unwrap();
+    ring.push(b"much too long to fit");
+    assert_eq!(collect(&ring)[0], b"much t");
+    ring.clear();
+    assert!(ring.is_empty());
+    assert_eq!(ring.records().count(), 0);
+  }
+
+  #[test]
+  fn small_or_misaligned_buffers_are_rejected() {
+    let mut buffer = Buffer([0; 64]);
+    assert!(RecordRing::new(&mut buffer.0[..17]).is_none());
+    assert!(RecordRing::new(&mut buffer.0[1..]).is_none());
+  }
+}
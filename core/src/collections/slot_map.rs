@@ -0,0 +1,610 @@
+//! This module provides [`ArraySlotMap`] and [`SlotMap`], arenas that hand
+//! out a [`Handle`] for each value inserted, for kernel objects that are
+//! referred to from elsewhere, such as open files, tasks and timers.
+//!
+//! A handle is the index of the slot that holds its value, along with the
+//! generation of the slot when the value was inserted. Removing a value bumps
+//! the generation of its slot, so the handles of removed values are stale, and
+//! find nothing, even once their slot holds another value. Inserting, finding
+//! and removing values take constant time: vacant slots are kept in a list
+//! threaded through them, and reused before the storage grows.
+//!
+//! A slot whose generation would wrap is retired rather than reused, so that a
+//! stale handle can never come to name a new value.
+
+use super::ArrayVec;
+#[cfg(any(feature = "alloc", test))]
+use alloc::vec::Vec;
+use core::fmt;
+
+/// The index that ends the list of vacant slots.
+const NONE: u32 = u32::MAX;
+
+/// A handle to a value of an [`ArraySlotMap`] or a [`SlotMap`].
+///
+/// Handles are only meaningful to the map that made them.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Handle {
+  index: u32,
+  generation: u32,
+}
+
+impl Handle {
+  /// Returns the index of the slot of the value.
+  pub const fn index(self) -> usize {
+    self.index as usize
+  }
+
+  /// Returns the generation of the slot when the value was inserted.
+  pub const fn generation(self) -> u32 {
+    self.generation
+  }
+
+  /// Returns the handle packed into 64 bits, such as to hand to user space,
+  /// with the generation in the high half and the index in the low half.
+  pub const fn to_bits(self) -> u64 {
+    (self.generation as u64) << 32 | self.index as u64
+  }
+
+  /// Returns the handle packed into `bits` by [`to_bits`](Self::to_bits).
+  ///
+  /// # Arguments
+  ///
+  /// * `bits` - the packed handle
+  pub const fn from_bits(bits: u64) -> Self {
+    Self {
+      index: bits as u32,
+      generation: (bits >> 32) as u32,
+    }
+  }
+}
+
+/// A map of up to `N` values, stored inline, found by the [`Handle`]s that
+/// inserting them returns.
+pub struct ArraySlotMap<T, const N: usize> {
+  slots: ArrayVec<Slot<T>, N>,
+  vacant: Vacant,
+}
+
+impl<T, const N: usize> ArraySlotMap<T, N> {
+  /// Creates an empty map.
+  pub const fn new() -> Self {
+    Self {
+      slots: ArrayVec::new(),
+      vacant: Vacant::new(),
+    }
+  }
+
+  /// Returns the number of values that the map can hold.
+  pub const fn capacity(&self) -> usize {
+    N
+  }
+
+  /// Returns the number of values in the map.
+  pub const fn len(&self) -> usize {
+    self.vacant.len
+  }
+
+  /// Returns whether the map holds no values.
+  pub const fn is_empty(&self) -> bool {
+    self.vacant.len == 0
+  }
+
+  /// Inserts `value`, returning its handle, or returns `value` if the map has
+  /// no vacant slot left.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to insert
+  pub fn try_insert(&mut self, value: T) -> Result<Handle, T> {
+    let value = match self.vacant.reuse(&mut self.slots, value) {
+      Ok(handle) => return Ok(handle),
+      Err(value) => value,
+    };
+    if self.slots.is_full() {
+      return Err(value);
+    }
+    let handle = self.vacant.push(self.slots.len());
+    self.slots.push(Slot::new(value));
+    Ok(handle)
+  }
+
+  /// Returns the value of `handle`, or `None` if it was removed.
+  ///
+  /// # Arguments
+  ///
+  /// * `handle` - the handle of the value
+  pub fn get(&self, handle: Handle) -> Option<&T> {
+    get(&self.slots, handle)
+  }
+
+  /// Returns the value of `handle` to modify, or `None` if it was removed.
+  ///
+  /// # Arguments
+  ///
+  /// * `handle` - the handle of the value
+  pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+    get_mut(&mut self.slots, handle)
+  }
+
+  /// Returns whether `handle` names a value of the map.
+  ///
+  /// # Arguments
+  ///
+  /// * `handle` - the handle of the value
+  pub fn contains(&self, handle: Handle) -> bool {
+    self.get(handle).is_some()
+  }
+
+  /// Removes the value of `handle` and returns it, or returns `None` if it
+  /// was removed already.
+  ///
+  /// # Arguments
+  ///
+  /// * `handle` - the handle of the value
+  pub fn remove(&mut self, handle: Handle) -> Option<T> {
+    self.vacant.remove(&mut self.slots, handle)
+  }
+
+  /// Removes the values for which `keep` returns `false`.
+  ///
+  /// # Arguments
+  ///
+  /// * `keep` - the function that decides which values to keep
+  pub fn retain(&mut self, keep: impl FnMut(Handle, &mut T) -> bool) {
+    self.vacant.retain(&mut self.slots, keep)
+  }
+
+  /// Removes every value, leaving their handles stale.
+  pub fn clear(&mut self) {
+    self.retain(|_, _| false)
+  }
+
+  /// Returns an iterator over the handles and values of the map, in the
+  /// order of their slots.
+  pub fn iter(&self) -> impl Iterator<Item = (Handle, &T)> + '_ {
+    iter(&self.slots)
+  }
+
+  /// Returns an iterator over the handles and values of the map, to modify
+  /// the values, in the order of their slots.
+  pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle, &mut T)> + '_ {
+    iter_mut(&mut self.slots)
+  }
+}
+
+impl<T, const N: usize> Default for ArraySlotMap<T, N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for ArraySlotMap<T, N> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_map().entries(self.iter()).finish()
+  }
+}
+
+/// A map of values, stored in a single allocation from the global allocator
+/// that grows as needed, found by the [`Handle`]s that inserting them
+/// returns.
+#[cfg(any(feature = "alloc", test))]
+pub struct SlotMap<T> {
+  slots: Vec<Slot<T>>,
+  vacant: Vacant,
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<T> SlotMap<T> {
+  /// Creates an empty map, which does not allocate until a value is
+  /// inserted.
+  pub const fn new() -> Self {
+    Self {
+      slots: Vec::new(),
+      vacant: Vacant::new(),
+    }
+  }
+
+  /// Creates an empty map with room for `capacity` values.
+  ///
+  /// # Arguments
+  ///
+  /// * `capacity` - the number of values to make room for
+  pub fn with_capacity(capacity: usize) -> Self {
+    Self {
+      slots: Vec::with_capacity(capacity),
+      vacant: Vacant::new(),
+    }
+  }
+
+  /// Returns the number of values in the map.
+  pub fn len(&self) -> usize {
+    self.vacant.len
+  }
+
+  /// Returns whether the map holds no values.
+  pub fn is_empty(&self) -> bool {
+    self.vacant.len == 0
+  }
+
+  /// Inserts `value`, returning its handle.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to insert
+  ///
+  /// # Panics
+  ///
+  /// Panics if the map has run out of indices for slots.
+  pub fn insert(&mut self, value: T) -> Handle {
+    let value = match self.vacant.reuse(&mut self.slots, value) {
+      Ok(handle) => return handle,
+      Err(value) => value,
+    };
+    assert!(self.slots.len() < NONE as usize, "SlotMap is out of slots");
+    let handle = self.vacant.push(self.slots.len());
+    self.slots.push(Slot::new(value));
+    handle
+  }
+
+  /// Returns the value of `handle`, or `None` if it was removed.
+  ///
+  /// # Arguments
+  ///
+  /// * `handle` - the handle of the value
+  pub fn get(&self, handle: Handle) -> Option<&T> {
+    get(&self.slots, handle)
+  }
+
+  /// Returns the value of `handle` to modify, or `None` if it was removed.
+  ///
+  /// # Arguments
+  ///
+  /// * `handle` - the handle of the value
+  pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+    get_mut(&mut self.slots, handle)
+  }
+
+  /// Returns whether `handle` names a value of the map.
+  ///
+  /// # Arguments
+  ///
+  /// * `handle` - the handle of the value
+  pub fn contains(&self, handle: Handle) -> bool {
+    self.get(handle).is_some()
+  }
+
+  /// Removes the value of `handle` and returns it, or returns `None` if it
+  /// was removed already.
+  ///
+  /// # Arguments
+  ///
+  /// * `handle` - the handle of the value
+  pub fn remove(&mut self, handle: Handle) -> Option<T> {
+    self.vacant.remove(&mut self.slots, handle)
+  }
+
+  /// Removes the values for which `keep` returns `false`.
+  ///
+  /// # Arguments
+  ///
+  /// * `keep` - the function that decides which values to keep
+  pub fn retain(&mut self, keep: impl FnMut(Handle, &mut T) -> bool) {
+    self.vacant.retain(&mut self.slots, keep)
+  }
+
+  /// Removes every value, leaving their handles stale.
+  pub fn clear(&mut self) {
+    self.retain(|_, _| false)
+  }
+
+  /// Returns an iterator over the handles and values of the map, in the
+  /// order of their slots.
+  pub fn iter(&self) -> impl Iterator<Item = (Handle, &T)> + '_ {
+    iter(&self.slots)
+  }
+
+  /// Returns an iterator over the handles and values of the map, to modify
+  /// the values, in the order of their slots.
+  pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle, &mut T)> + '_ {
+    iter_mut(&mut self.slots)
+  }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<T> Default for SlotMap<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(any(feature = "alloc", test))]
+impl<T: fmt::Debug> fmt::Debug for SlotMap<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_map().entries(self.iter()).finish()
+  }
+}
+
+/// A slot of a map, and the generation of the value it holds, or of the
+/// next value it will hold.
+struct Slot<T> {
+  generation: u32,
+  entry: Entry<T>,
+}
+
+/// The contents of a [`Slot`].
+enum Entry<T> {
+  /// The slot holds a value.
+  Occupied(T),
+
+  /// The slot is vacant, and the next vacant slot is at this index, or at
+  /// [`NONE`].
+  Vacant(u32),
+}
+
+impl<T> Slot<T> {
+  /// Constructs a new slot holding `value`.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value of the slot
+  fn new(value: T) -> Self {
+    Self {
+      generation: 0,
+      entry: Entry::Occupied(value),
+    }
+  }
+}
+
+/// The list of the vacant slots of a map, and the number of values in it.
+struct Vacant {
+  head: u32,
+  len: usize,
+}
+
+impl Vacant {
+  /// Constructs the list of a map with no slots.
+  const fn new() -> Self {
+    Self { head: NONE, len: 0 }
+  }
+
+  /// Counts the value of the slot about to be pushed at `index`, and returns
+  /// its handle.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the new slot
+  fn push(&mut self, index: usize) -> Handle {
+    self.len += 1;
+    Handle {
+      index: index as u32,
+      generation: 0,
+    }
+  }
+
+  /// Puts `value` in the first vacant slot of `slots` and returns its
+  /// handle, or returns `value` if none are vacant.
+  ///
+  /// # Arguments
+  ///
+  /// * `slots` - the slots of the map
+  /// * `value` - the value to insert
+  fn reuse<T>(&mut self, slots: &mut [Slot<T>], value: T) -> Result<Handle, T> {
+    let Some(slot) = slots.get_mut(self.head as usize) else {
+      return Err(value);
+    };
+    let index = self.head;
+    let Entry::Vacant(next) = slot.entry else {
+      unreachable!("the list of vacant slots holds an occupied slot");
+    };
+    self.head = next;
+    self.len += 1;
+    slot.entry = Entry::Occupied(value);
+    Ok(Handle {
+      index,
+      generation: slot.generation,
+    })
+  }
+
+  /// Removes the value of `handle` from `slots`, vacating its slot.
+  ///
+  /// # Arguments
+  ///
+  /// * `slots` - the slots of the map
+  /// * `handle` - the handle of the value
+  fn remove<T>(&mut self, slots: &mut [Slot<T>], handle: Handle) -> Option<T> {
+    get(slots, handle)?;
+    Some(self.vacate(slots, handle.index))
+  }
+
+  /// Removes the values of `slots` for which `keep` returns `false`.
+  ///
+  /// # Arguments
+  ///
+  /// * `slots` - the slots of the map
+  /// * `keep` - the function that decides which values to keep
+  fn retain<T>(
+    &mut self,
+    slots: &mut [Slot<T>],
+    mut keep: impl FnMut(Handle, &mut T) -> bool,
+  ) {
+    for index in 0..slots.len() as u32 {
+      let slot = &mut slots[index as usize];
+      let handle = Handle {
+        index,
+        generation: slot.generation,
+      };
+      if let Entry::Occupied(value) = &mut slot.entry {
+        if !keep(handle, value) {
+          self.vacate(slots, index);
+        }
+      }
+    }
+  }
+
+  /// Vacates the occupied slot at `index` of `slots`, returning its value.
+  ///
+  /// # Arguments
+  ///
+  /// * `slots` - the slots of the map
+  /// * `index` - the index of the slot
+  fn vacate<T>(&mut self, slots: &mut [Slot<T>], index: u32) -> T {
+    let slot = &mut slots[index as usize];
+    slot.generation = slot.generation.wrapping_add(1);
+    // A slot whose generation would wrap next time is retired instead.
+    let next = if slot.generation == u32::MAX {
+      NONE
+    } else {
+      core::mem::replace(&mut self.head, index)
+    };
+    self.len -= 1;
+    match core::mem::replace(&mut slot.entry, Entry::Vacant(next)) {
+      Entry::Occupied(value) => value,
+      Entry::Vacant(_) => unreachable!("only occupied slots are vacated"),
+    }
+  }
+}
+
+/// Returns the value of `handle` in `slots`, if it is still there.
+///
+/// # Arguments
+///
+/// * `slots` - the slots of the map
+/// * `handle` - the handle of the value
+fn get<T>(slots: &[Slot<T>], handle: Handle) -> Option<&T> {
+  match slots.get(handle.index as usize)? {
+    Slot {
+      generation,
+      entry: Entry::Occupied(value),
+    } if *generation == handle.generation => Some(value),
+    _ => None,
+  }
+}
+
+/// Returns the value of `handle` in `slots` to modify, if it is still there.
+///
+/// # Arguments
+///
+/// * `slots` - the slots of the map
+/// * `handle` - the handle of the value
+fn get_mut<T>(slots: &mut [Slot<T>], handle: Handle) -> Option<&mut T> {
+  match slots.get_mut(handle.index as usize)? {
+    Slot {
+      generation,
+      entry: Entry::Occupied(value),
+    } if *generation == handle.generation => Some(value),
+    _ => None,
+  }
+}
+
+/// Returns an iterator over the handles and values of `slots`.
+///
+/// # Arguments
+///
+/// * `slots` - the slots of the map
+fn iter<T>(slots: &[Slot<T>]) -> impl Iterator<Item = (Handle, &T)> + '_ {
+  slots
+    .iter()
+    .enumerate()
+    .filter_map(|(index, slot)| match &slot.entry {
+      Entry::Occupied(value) => Some((
+        Handle {
+          index: index as u32,
+          generation: slot.generation,
+        },
+        value,
+      )),
+      Entry::Vacant(_) => None,
+    })
+}
+
+/// Returns an iterator over the handles and values of `slots`, to modify the
+/// values.
+///
+/// # Arguments
+///
+/// * `slots` - the slots of the map
+fn iter_mut<T>(
+  slots: &mut [Slot<T>],
+) -> impl Iterator<Item = (Handle, &mut T)> + '_ {
+  slots.iter_mut().enumerate().filter_map(|(index, slot)| {
+    match &mut slot.entry {
+      Entry::Occupied(value) => Some((
+        Handle {
+          index: index as u32,
+          generation: slot.generation,
+        },
+        value,
+      )),
+      Entry::Vacant(_) => None,
+    }
+  })
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::vec::Vec;
+
+  #[test]
+  fn stale_handles_find_nothing() {
+    let mut map = ArraySlotMap::<&str, 4>::new();
+    let first = map.try_insert("first").unwrap();
+    let second = map.try_insert("second").unwrap();
+
+    assert_eq!(map.remove(first), Some("first"));
+    assert_eq!(map.remove(first), None);
+    let third = map.try_insert("third").unwrap();
+    assert_eq!(third.index(), first.index());
+    assert_ne!(third, first);
+    assert_eq!(map.get(first), None);
+    assert_eq!(map.get(third), Some(&"third"));
+    assert_eq!(map.get(second), Some(&"second"));
+    assert_eq!(map.len(), 2);
+  }
+
+  #[test]
+  fn array_slot_map_fills_up() {
+    let mut map = ArraySlotMap::<u32, 2>::new();
+    let handles: Vec<_> = (0..2).map(|i| map.try_insert(i).unwrap()).collect();
+
+    assert_eq!(map.try_insert(2), Err(2));
+    map.clear();
+    assert!(map.is_empty());
+    assert!(handles.iter().all(|&handle| !map.contains(handle)));
+    assert!(map.try_insert(3).is_ok());
+  }
+
+  #[test]
+  fn slot_map_grows_and_retains() {
+    let mut map = SlotMap::new();
+    let handles: Vec<_> = (0..100).map(|i| map.insert(i)).collect();
+
+    map.retain(|_, value| *value % 2 == 0);
+    map.iter_mut().for_each(|(_, value)| *value += 1);
+    assert_eq!(map.len(), 50);
+    assert_eq!(map.get(handles[4]), Some(&5));
+    assert_eq!(map.get(handles[5]), None);
+    assert!(map
+      .iter()
+      .all(|(handle, value)| handles[*value - 1] == handle));
+  }
+
+  #[test]
+  fn slots_retire_before_their_generation_wraps() {
+    let mut map = ArraySlotMap::<u32, 2>::new();
+    let handle = map.try_insert(0).unwrap();
+    map.slots[0].generation = u32::MAX - 1;
+    let handle = Handle {
+      generation: u32::MAX - 1,
+      ..handle
+    };
+
+    assert_eq!(map.remove(handle), Some(0));
+    assert_eq!(map.try_insert(1).unwrap().index(), 1);
+    assert_eq!(map.try_insert(2), Err(2));
+    assert_eq!(Handle::from_bits(handle.to_bits()), handle);
+  }
+}
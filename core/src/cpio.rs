@@ -0,0 +1,340 @@
+//! This module provides reading of cpio archives in the "newc" format, the
+//! format of initramfs images, as written by `gen_init_cpio` and
+//! `cpio -H newc`.
+//!
+//! Archives are read in place. Several archives may be concatenated, as when
+//! early microcode is prepended to an initramfs, and the trailers that end
+//! each one are skipped, along with the zeros that pad them; anything that
+//! follows the last one and is not another archive, such as a compressed
+//! one, is not read. The checksums of members in the "crc" variant of the
+//! format are verified.
+//!
+//! Malformed archives fail with [`Error::Corrupted`].
+
+use crate::error::{Error, Result};
+use crate::vfs::FileType;
+use core::iter::FusedIterator;
+
+/// The magic number of a header.
+const MAGIC: &[u8; 6] = b"070701";
+
+/// The magic number of a header whose member has a checksum.
+const CRC_MAGIC: &[u8; 6] = b"070702";
+
+/// The size of a header, before the name.
+const HEADER_SIZE: usize = 110;
+
+/// The name of the member that ends an archive.
+const TRAILER: &str = "TRAILER!!!";
+
+/// The bits of a mode that hold the type of the member.
+const MODE_TYPE: u32 = 0o170_000;
+
+/// The mode type of a regular file.
+const MODE_FILE: u32 = 0o100_000;
+
+/// The mode type of a directory.
+const MODE_DIRECTORY: u32 = 0o040_000;
+
+/// The mode type of a symbolic link.
+const MODE_SYMLINK: u32 = 0o120_000;
+
+/// Returns `true` if `data` starts with a cpio "newc" header.
+///
+/// # Arguments
+///
+/// * `data` - the data to check
+pub fn is_cpio(data: &[u8]) -> bool {
+  data.starts_with(MAGIC) || data.starts_with(CRC_MAGIC)
+}
+
+/// A member of an archive.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Entry<'a> {
+  /// The path of the member, as written in the archive, such as `bin/sh` or
+  /// `./init`.
+  pub name: &'a str,
+
+  /// The inode number of the member, which hard links share.
+  pub ino: u32,
+
+  /// The type and permissions of the member.
+  pub mode: u32,
+
+  /// The owner of the member.
+  pub uid: u32,
+
+  /// The group of the member.
+  pub gid: u32,
+
+  /// The number of links to the member.
+  pub nlink: u32,
+
+  /// The time the member was last modified, in seconds since the Unix epoch.
+  pub mtime: u32,
+
+  /// The major and minor numbers of the device holding the member.
+  pub dev: (u32, u32),
+
+  /// The major and minor numbers of the device that the member is, for
+  /// device files.
+  pub rdev: (u32, u32),
+
+  /// The contents of the member, or the target of a symbolic link.
+  pub data: &'a [u8],
+}
+
+impl Entry<'_> {
+  /// Returns the type of the member.
+  pub fn file_type(&self) -> FileType {
+    match self.mode & MODE_TYPE {
+      MODE_FILE => FileType::File,
+      MODE_DIRECTORY => FileType::Directory,
+      MODE_SYMLINK => FileType::Symlink,
+      _ => FileType::Other,
+    }
+  }
+
+  /// Returns the permission bits of the mode of the member.
+  pub fn permissions(&self) -> u32 {
+    self.mode & !MODE_TYPE
+  }
+}
+
+/// An iterator over the members of one or more concatenated archives, which
+/// ends after the first malformed one.
+#[derive(Clone)]
+pub struct Entries<'a> {
+  data: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> Entries<'a> {
+  /// Constructs an iterator over the members of the archives in `data`,
+  /// failing with [`Error::Corrupted`] if it does not start with one.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the archives
+  pub fn new(data: &'a [u8]) -> Result<Self> {
+    if !is_cpio(data) {
+      return Err(Error::Corrupted);
+    }
+    Ok(Self { data, offset: 0 })
+  }
+
+  /// Returns the offset of the header of the next member, or the length of
+  /// the data once the last archive has been read.
+  pub fn offset(&self) -> usize {
+    self.offset
+  }
+
+  /// Reads the member at the current offset, skipping trailers.
+  fn read(&mut self) -> Result<Option<Entry<'a>>> {
+    loop {
+      if self.offset >= self.data.len() {
+        return Ok(None);
+      }
+      let header = self
+        .data
+        .get(self.offset..self.offset + HEADER_SIZE)
+        .ok_or(Error::Corrupted)?;
+      if !is_cpio(header) {
+        return Err(Error::Corrupted);
+      }
+      let field = |index: usize| {
+        parse_hex(&header[6 + index * 8..][..8]).ok_or(Error::Corrupted)
+      };
+      let size = field(6)? as usize;
+      let name_size = field(11)? as usize;
+
+      // The name and the contents are each padded to a multiple of four
+      // bytes from the start of the header.
+      let name_start = self.offset + HEADER_SIZE;
+      let data_start = self.offset + align(HEADER_SIZE + name_size);
+      let name = self
+        .data
+        .get(name_start..name_start + name_size)
+        .and_then(|name| name.strip_suffix(&[0]))
+        .ok_or(Error::Corrupted)?;
+      let name = core::str::from_utf8(name).map_err(|_| Error::Corrupted)?;
+      let data = data_start
+        .checked_add(size)
+        .and_then(|end| self.data.get(data_start..end))
+        .ok_or(Error::Corrupted)?;
+      self.offset = data_start + align(size);
+
+      if name == TRAILER {
+        // Another archive may follow, after padding.
+        let rest = self.data.get(self.offset..).unwrap_or_default();
+        self.offset += rest.iter().take_while(|&&byte| byte == 0).count();
+        if !is_cpio(self.data.get(self.offset..).unwrap_or_default()) {
+          self.offset = self.data.len();
+        }
+        continue;
+      }
+
+      // The checksum is the sum of the bytes of the contents.
+      if header.starts_with(CRC_MAGIC) {
+        let sum = data
+          .iter()
+          .fold(0u32, |sum, &byte| sum.wrapping_add(byte as u32));
+        if sum != field(12)? {
+          return Err(Error::Corrupted);
+        }
+      }
+      return Ok(Some(Entry {
+        name,
+        ino: field(0)?,
+        mode: field(1)?,
+        uid: field(2)?,
+        gid: field(3)?,
+        nlink: field(4)?,
+        mtime: field(5)?,
+        dev: (field(7)?, field(8)?),
+        rdev: (field(9)?, field(10)?),
+        data,
+      }));
+    }
+  }
+}
+
+impl<'a> Iterator for Entries<'a> {
+  type Item = Result<Entry<'a>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let result = self.read();
+    if result.is_err() {
+      self.offset = self.data.len();
+    }
+    result.transpose()
+  }
+}
+
+impl FusedIterator for Entries<'_> {}
+
+/// Parses the hexadecimal field of a header.
+///
+/// # Arguments
+///
+/// * `field` - the field
+fn parse_hex(field: &[u8]) -> Option<u32> {
+  let field = core::str::from_utf8(field).ok()?;
+  u32::from_str_radix(field, 16).ok()
+}
+
+fn align(value: usize) -> usize {
+  (value + 3) & !3
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::format;
+  use std::vec::Vec;
+
+  /// Appends the member `name` of `mode` holding `data` to `archive`, with
+  /// a checksum if `crc` is given.
+  fn member(
+    archive: &mut Vec<u8>,
+    name: &str,
+    mode: u32,
+    data: &[u8],
+    crc: Option<u32>,
+  ) {
+    let magic = if crc.is_some() { CRC_MAGIC } else { MAGIC };
+    archive.extend_from_slice(magic);
+    let fields = [
+      7,
+      mode,
+      1000,
+      100,
+      1,
+      0x5f00_0000,
+      data.len() as u32,
+      8,
+      1,
+      0,
+      0,
+      name.len() as u32 + 1,
+      crc.unwrap_or(0),
+    ];
+    for field in fields {
+      archive.extend_from_slice(format!("{:08X}", field).as_bytes());
+    }
+    archive.extend_from_slice(name.as_bytes());
+    archive.push(0);
+    archive.resize(align(archive.len()), 0);
+    archive.extend_from_slice(data);
+    archive.resize(align(archive.len()), 0);
+  }
+
+  fn archive() -> Vec<u8> {
+    let mut archive = Vec::new();
+    member(&mut archive, "bin", 0o040_755, &[], None);
+    member(&mut archive, "bin/init", 0o100_644, b"#!/bin/sh\n", None);
+    member(&mut archive, "sh", 0o120_777, b"bin/init", None);
+    member(&mut archive, TRAILER, 0, &[], None);
+    archive
+  }
+
+  #[test]
+  fn members_are_read() {
+    let data = archive();
+    let entries: Vec<_> = Entries::new(&data)
+      .unwrap()
+      .map(|entry| entry.unwrap())
+      .collect();
+
+    let names: Vec<_> = entries.iter().map(|entry| entry.name).collect();
+    assert_eq!(names, ["bin", "bin/init", "sh"]);
+    let types: Vec<_> = entries.iter().map(|entry| entry.file_type()).collect();
+    assert_eq!(
+      types,
+      [FileType::Directory, FileType::File, FileType::Symlink]
+    );
+    let init = entries[1];
+    assert_eq!(init.data, b"#!/bin/sh\n");
+    assert_eq!(init.permissions(), 0o644);
+    assert_eq!(
+      (init.ino, init.uid, init.gid, init.nlink),
+      (7, 1000, 100, 1)
+    );
+    assert_eq!(
+      (init.mtime, init.dev, init.rdev),
+      (0x5f00_0000, (8, 1), (0, 0))
+    );
+  }
+
+  #[test]
+  fn concatenated_archives_are_read_in_turn() {
+    let mut data = archive();
+    data.extend_from_slice(&[0; 512]);
+    member(&mut data, "init", 0o100_755, b"abc", Some(0x126));
+    member(&mut data, TRAILER, 0, &[], None);
+    data.extend_from_slice(b"\x1f\x8b compressed");
+
+    let mut entries = Entries::new(&data).unwrap();
+    let names: Vec<_> =
+      entries.by_ref().map(|entry| entry.unwrap().name).collect();
+    assert_eq!(names, ["bin", "bin/init", "sh", "init"]);
+    assert_eq!(entries.offset(), data.len());
+  }
+
+  #[test]
+  fn malformed_archives_are_rejected() {
+    let mut data = Vec::new();
+    member(&mut data, "init", 0o100_755, b"abc", Some(0x127));
+    let mut entries = Entries::new(&data).unwrap();
+    assert_eq!(entries.next(), Some(Err(Error::Corrupted)));
+    assert_eq!(entries.next(), None);
+
+    let data = archive();
+    let truncated = &data[..200];
+    let results: Vec<_> = Entries::new(truncated).unwrap().collect();
+    assert_eq!(results.last(), Some(&Err(Error::Corrupted)));
+    assert_eq!(Entries::new(b"not cpio").err(), Some(Error::Corrupted));
+  }
+}
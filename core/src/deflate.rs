@@ -0,0 +1,432 @@
+//! This module provides decompression of raw DEFLATE streams, as described by
+//! RFC 1951.
+//!
+//! The decoder favours simplicity over speed: Huffman codes are decoded one
+//! bit at a time from their canonical form, which is fast enough for the
+//! handful of payloads decompressed at boot.
+
+use crate::error::{Error, Result};
+
+/// The maximum number of bits in a Huffman code.
+const MAX_BITS: usize = 15;
+
+/// The number of literal/length codes, including the two unused ones.
+const LITERAL_CODES: usize = 288;
+
+/// The number of distance codes, including the two unused ones.
+const DISTANCE_CODES: usize = 30;
+
+/// The base lengths of the length codes `257..=285`.
+const LENGTH_BASE: [u16; 29] = [
+  3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67,
+  83, 99, 115, 131, 163, 195, 227, 258,
+];
+
+/// The number of extra bits following each of the length codes.
+const LENGTH_EXTRA: [u8; 29] = [
+  0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5,
+  5, 5, 0,
+];
+
+/// The base distances of the distance codes.
+const DISTANCE_BASE: [u16; DISTANCE_CODES] = [
+  1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513,
+  769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+/// The number of extra bits following each of the distance codes.
+const DISTANCE_EXTRA: [u8; DISTANCE_CODES] = [
+  0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11,
+  11, 12, 12, 13, 13,
+];
+
+/// The order in which code length code lengths are stored in a dynamic block.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+  16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Decompresses the raw DEFLATE stream at the start of `input` into `output`.
+///
+/// Returns the number of bytes of `input` that the stream occupied, rounded
+/// up to a whole byte, and the number of bytes written to `output`. Fails with
+/// [`Error::Corrupted`] if the stream is malformed, and with
+/// [`Error::BufferTooSmall`] if it does not fit in `output`.
+///
+/// # Arguments
+///
+/// * `input` - the compressed stream
+/// * `output` - the buffer to decompress into
+pub fn inflate(input: &[u8], output: &mut [u8]) -> Result<(usize, usize)> {
+  let mut inflater = Inflater {
+    input: Bits::new(input),
+    output,
+    position: 0,
+  };
+  loop {
+    let last = inflater.input.bits(1)? == 1;
+    match inflater.input.bits(2)? {
+      0 => inflater.stored()?,
+      1 => inflater.fixed()?,
+      2 => inflater.dynamic()?,
+      _ => return Err(Error::Corrupted),
+    }
+    if last {
+      return Ok((inflater.input.consumed(), inflater.position));
+    }
+  }
+}
+
+/// A reader of the bits of a DEFLATE stream, least significant bit first.
+struct Bits<'a> {
+  data: &'a [u8],
+  offset: usize,
+  buffer: u32,
+  count: u32,
+}
+
+impl<'a> Bits<'a> {
+  /// Constructs a [`Bits`] reading from the start of `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the data to read
+  fn new(data: &'a [u8]) -> Self {
+    Self {
+      data,
+      offset: 0,
+      buffer: 0,
+      count: 0,
+    }
+  }
+
+  /// Reads the next `n` bits, where `n` is at most 16.
+  ///
+  /// # Arguments
+  ///
+  /// * `n` - the number of bits to read
+  fn bits(&mut self, n: u32) -> Result<u32> {
+    while self.count < n {
+      let byte = *self.data.get(self.offset).ok_or(Error::Corrupted)?;
+      self.buffer |= (byte as u32) << self.count;
+      self.offset += 1;
+      self.count += 8;
+    }
+    let value = self.buffer & ((1 << n) - 1);
+    self.buffer >>= n;
+    self.count -= n;
+    Ok(value)
+  }
+
+  /// Discards any bits remaining in the current byte.
+  fn align(&mut self) {
+    self.buffer = 0;
+    self.count = 0;
+  }
+
+  /// Returns the number of whole bytes read so far.
+  fn consumed(&self) -> usize {
+    self.offset
+  }
+
+  /// Reads the next `len` bytes, which must start on a byte boundary.
+  ///
+  /// # Arguments
+  ///
+  /// * `len` - the number of bytes to read
+  fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+    let bytes = self
+      .data
+      .get(self.offset..self.offset + len)
+      .ok_or(Error::Corrupted)?;
+    self.offset += len;
+    Ok(bytes)
+  }
+}
+
+/// A canonical Huffman code, described by the number of codes of each length
+/// and the symbols in code order.
+struct Huffman<const N: usize> {
+  counts: [u16; MAX_BITS + 1],
+  symbols: [u16; N],
+}
+
+impl<const N: usize> Huffman<N> {
+  /// Constructs the canonical Huffman code in which symbol `i` has a code of
+  /// `lengths[i]` bits, or no code if the length is zero.
+  ///
+  /// Fails with [`Error::Corrupted`] if the lengths describe more codes
+  /// than can exist. Incomplete codes are permitted; reading one of their
+  /// unassigned codes fails instead.
+  ///
+  /// # Arguments
+  ///
+  /// * `lengths` - the code length of each symbol
+  fn new(lengths: &[u8]) -> Result<Self> {
+    let mut counts = [0; MAX_BITS + 1];
+    for &len in lengths {
+      counts[len as usize] += 1;
+    }
+
+    // Every code length may use twice as many codes as the last, less those
+    // already taken by shorter codes.
+    let mut left = 1i32;
+    for &count in &counts[1..] {
+      left = (left << 1) - count as i32;
+      if left < 0 {
+        return Err(Error::Corrupted);
+      }
+    }
+
+    let mut offsets = [0; MAX_BITS + 1];
+    for len in 1..MAX_BITS {
+      offsets[len + 1] = offsets[len] + counts[len];
+    }
+    let mut symbols = [0; N];
+    for (symbol, &len) in lengths.iter().enumerate() {
+      if len != 0 {
+        symbols[offsets[len as usize] as usize] = symbol as u16;
+        offsets[len as usize] += 1;
+      }
+    }
+    counts[0] = 0;
+    Ok(Self { counts, symbols })
+  }
+
+  /// Reads and decodes the next symbol from `input`.
+  ///
+  /// # Arguments
+  ///
+  /// * `input` - the stream to read from
+  fn decode(&self, input: &mut Bits) -> Result<u16> {
+    // `first` is the first code of the current length, and `index` the index
+    // of its symbol.
+    let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+    for &count in &self.counts[1..] {
+      code |= input.bits(1)? as i32;
+      let count = count as i32;
+      if code - first < count {
+        return Ok(self.symbols[(index + code - first) as usize]);
+      }
+      index += count;
+      first = (first + count) << 1;
+      code <<= 1;
+    }
+    Err(Error::Corrupted)
+  }
+}
+
+/// The state of a DEFLATE stream being decompressed.
+struct Inflater<'a, 'b> {
+  input: Bits<'a>,
+  output: &'b mut [u8],
+  position: usize,
+}
+
+impl Inflater<'_, '_> {
+  /// Copies a stored, uncompressed block to the output.
+  fn stored(&mut self) -> Result<()> {
+    self.input.align();
+    let header = self.input.bytes(4)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+    if len != !complement {
+      return Err(Error::Corrupted);
+    }
+    let bytes = self.input.bytes(len as usize)?;
+    self
+      .output
+      .get_mut(self.position..self.position + bytes.len())
+      .ok_or(Error::BufferTooSmall)?
+      .copy_from_slice(bytes);
+    self.position += bytes.len();
+    Ok(())
+  }
+
+  /// Decodes a block compressed with the fixed Huffman codes.
+  fn fixed(&mut self) -> Result<()> {
+    let mut lengths = [0; LITERAL_CODES];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let literals = Huffman::<LITERAL_CODES>::new(&lengths)?;
+    let distances = Huffman::<DISTANCE_CODES>::new(&[5; DISTANCE_CODES])?;
+    self.codes(&literals, &distances)
+  }
+
+  /// Decodes a block compressed with Huffman codes described at its start.
+  fn dynamic(&mut self) -> Result<()> {
+    let literal_count = self.input.bits(5)? as usize + 257;
+    let distance_count = self.input.bits(5)? as usize + 1;
+    let length_count = self.input.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > DISTANCE_CODES {
+      return Err(Error::Corrupted);
+    }
+
+    let mut lengths = [0; 19];
+    for &index in &CODE_LENGTH_ORDER[..length_count] {
+      lengths[index] = self.input.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::<19>::new(&lengths)?;
+
+    // The literal/length and distance code lengths are run-length encoded as
+    // a single sequence.
+    let mut lengths = [0; LITERAL_CODES + DISTANCE_CODES];
+    let total = literal_count + distance_count;
+    let mut index = 0;
+    while index < total {
+      let symbol = code_lengths.decode(&mut self.input)?;
+      let (value, repeat) = match symbol {
+        0..=15 => (symbol as u8, 1),
+        16 => {
+          let previous = index
+            .checked_sub(1)
+            .map(|i| lengths[i])
+            .ok_or(Error::Corrupted)?;
+          (previous, 3 + self.input.bits(2)? as usize)
+        }
+        17 => (0, 3 + self.input.bits(3)? as usize),
+        _ => (0, 11 + self.input.bits(7)? as usize),
+      };
+      lengths
+        .get_mut(index..index + repeat)
+        .filter(|_| index + repeat <= total)
+        .ok_or(Error::Corrupted)?
+        .fill(value);
+      index += repeat;
+    }
+    if lengths[256] == 0 {
+      return Err(Error::Corrupted);
+    }
+
+    let literals = Huffman::<LITERAL_CODES>::new(&lengths[..literal_count])?;
+    let distances =
+      Huffman::<DISTANCE_CODES>::new(&lengths[literal_count..total])?;
+    self.codes(&literals, &distances)
+  }
+
+  /// Decodes the symbols of a compressed block until its end-of-block code.
+  ///
+  /// # Arguments
+  ///
+  /// * `literals` - the literal/length code
+  /// * `distances` - the distance code
+  fn codes(
+    &mut self,
+    literals: &Huffman<LITERAL_CODES>,
+    distances: &Huffman<DISTANCE_CODES>,
+  ) -> Result<()> {
+    loop {
+      let symbol = literals.decode(&mut self.input)? as usize;
+      match symbol {
+        0..=255 => {
+          *self
+            .output
+            .get_mut(self.position)
+            .ok_or(Error::BufferTooSmall)? = symbol as u8;
+          self.position += 1;
+        }
+        256 => return Ok(()),
+        _ => {
+          let symbol = symbol - 257;
+          let extra = *LENGTH_EXTRA.get(symbol).ok_or(Error::Corrupted)?;
+          let len = LENGTH_BASE[symbol] as usize
+            + self.input.bits(extra as u32)? as usize;
+
+          let symbol = distances.decode(&mut self.input)? as usize;
+          let extra = *DISTANCE_EXTRA.get(symbol).ok_or(Error::Corrupted)?;
+          let distance = DISTANCE_BASE[symbol] as usize
+            + self.input.bits(extra as u32)? as usize;
+
+          if distance > self.position {
+            return Err(Error::Corrupted);
+          }
+          if self.position + len > self.output.len() {
+            return Err(Error::BufferTooSmall);
+          }
+          // Matches may overlap the bytes they produce, so they are copied one
+          // byte at a time.
+          for i in self.position..self.position + len {
+            self.output[i] = self.output[i - distance];
+          }
+          self.position += len;
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::vec::Vec;
+
+  /// `abc`, in a stored block followed by a byte that is not part of it.
+  const STORED: [u8; 9] = [0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c', 0];
+
+  /// A sentence with repetitions, in a block with the fixed codes.
+  const FIXED: [u8; 20] = [
+    0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x75, 0x14, 0x52,
+    0x52, 0xd3, 0x72, 0x12, 0x4b, 0x52, 0x15, 0x01,
+  ];
+
+  /// [`dynamic_text`], in a block with dynamic codes.
+  const DYNAMIC: [u8; 72] = [
+    0xb5, 0xca, 0xb7, 0x11, 0x80, 0x30, 0x10, 0x00, 0xc1, 0x56, 0xbe, 0x0f,
+    0xaa, 0x91, 0xf7, 0x7a, 0x79, 0x57, 0x3d, 0x0c, 0x39, 0x21, 0xe1, 0xcd,
+    0x5e, 0xd3, 0x02, 0x72, 0x37, 0xcc, 0x01, 0x2d, 0x38, 0x23, 0x48, 0x5c,
+    0x60, 0x7b, 0x48, 0x15, 0x70, 0x88, 0x02, 0xed, 0x61, 0x4f, 0xce, 0x06,
+    0x8e, 0xea, 0x7a, 0xeb, 0x9f, 0x99, 0x50, 0xc6, 0x85, 0x54, 0xda, 0x58,
+    0xe7, 0x43, 0xc4, 0x94, 0x4b, 0x6d, 0x7d, 0xcc, 0xb5, 0xcf, 0xb7, 0xdc,
+  ];
+
+  fn dynamic_text() -> Vec<u8> {
+    let mut text = b"the quick brown fox jumps over the lazy dog; ".repeat(3);
+    for _ in 0..2 {
+      text.extend(b'a'..=b'z');
+    }
+    text
+  }
+
+  #[test]
+  fn stored_blocks_are_copied() {
+    let mut output = [0; 3];
+
+    assert_eq!(inflate(&STORED, &mut output), Ok((8, 3)));
+    assert_eq!(&output, b"abc");
+  }
+
+  #[test]
+  fn fixed_blocks_are_decoded() {
+    let mut output = [0; 64];
+
+    let (consumed, len) = inflate(&FIXED, &mut output).unwrap();
+    assert_eq!(consumed, FIXED.len());
+    assert_eq!(&output[..len], b"hello hello hello hello, deflate!");
+  }
+
+  #[test]
+  fn dynamic_blocks_are_decoded() {
+    let mut output = [0; 256];
+
+    let (consumed, len) = inflate(&DYNAMIC, &mut output).unwrap();
+    assert_eq!(consumed, DYNAMIC.len());
+    assert_eq!(&output[..len], dynamic_text());
+  }
+
+  #[test]
+  fn failures_are_told_apart() {
+    let mut output = [0; 2];
+    assert_eq!(inflate(&STORED, &mut output), Err(Error::BufferTooSmall));
+    assert_eq!(inflate(&FIXED, &mut output), Err(Error::BufferTooSmall));
+
+    let mut output = [0; 64];
+    assert_eq!(inflate(&FIXED[..10], &mut output), Err(Error::Corrupted));
+    let mut stored = STORED;
+    stored[3] = 0;
+    assert_eq!(inflate(&stored, &mut output), Err(Error::Corrupted));
+    assert_eq!(inflate(&[0x07], &mut output), Err(Error::Corrupted));
+  }
+}
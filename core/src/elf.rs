@@ -0,0 +1,789 @@
+//! This module provides parsing of ELF files, as views over their bytes that
+//! copy nothing: the file header, program headers, section headers, symbol
+//! tables and relocations.
+//!
+//! [`Elf::parse`] validates the file header and that the program and section
+//! header tables lie within the file, so that the headers can be read without
+//! further checks; the contents they point at are checked as they are asked
+//! for. Both 32-bit and 64-bit little-endian files are read, of any type and
+//! for any machine; what is acceptable to load is for the loader to decide.
+//!
+//! Malformed files fail with [`Error::Corrupted`], and files in a form that is
+//! not read, such as big-endian files, with [`Error::Unsupported`].
+
+mod relocations;
+mod symbols;
+
+pub use relocations::{Dynamic, Rela, Relocations};
+pub use symbols::{Symbol, SymbolTable, Symbols};
+
+use crate::bytes::{read_u16, read_u32, read_u64};
+use crate::error::{Error, Result};
+use core::iter::FusedIterator;
+use core::ops::Range;
+
+/// The type of a program header describing a loadable segment.
+pub const PT_LOAD: u32 = 1;
+
+/// The type of a program header describing the dynamic section.
+pub const PT_DYNAMIC: u32 = 2;
+
+/// The type of a program header describing the thread-local storage template.
+pub const PT_TLS: u32 = 7;
+
+/// A segment flag marking the segment as executable.
+pub const PF_X: u32 = 1;
+
+/// A segment flag marking the segment as writable.
+pub const PF_W: u32 = 2;
+
+/// A segment flag marking the segment as readable.
+pub const PF_R: u32 = 4;
+
+/// The type of an ELF file that is a relocatable object, such as a kernel
+/// module.
+pub const ET_REL: u16 = 1;
+
+/// The type of an ELF file that is an executable.
+pub const ET_EXEC: u16 = 2;
+
+/// The type of an ELF file that is a shared object, which
+/// position-independent executables are.
+pub const ET_DYN: u16 = 3;
+
+/// The machine type of i386 files.
+pub const EM_386: u16 = 3;
+
+/// The machine type of x86-64 files.
+pub const EM_X86_64: u16 = 62;
+
+/// The machine type of AArch64 files.
+pub const EM_AARCH64: u16 = 183;
+
+/// The type of a section holding a symbol table.
+pub const SHT_SYMTAB: u32 = 2;
+
+/// The type of a section holding a string table.
+pub const SHT_STRTAB: u32 = 3;
+
+/// The type of a section holding relocations with addends.
+pub const SHT_RELA: u32 = 4;
+
+/// The type of a section that occupies no space in the file.
+pub const SHT_NOBITS: u32 = 8;
+
+/// The type of a section holding the dynamic linking symbol table.
+pub const SHT_DYNSYM: u32 = 11;
+
+/// The binding of a symbol that is local to its file.
+pub const STB_LOCAL: u8 = 0;
+
+/// The binding of a symbol that is visible to every file linked with its own.
+pub const STB_GLOBAL: u8 = 1;
+
+/// The binding of a global symbol that others of the same name override.
+pub const STB_WEAK: u8 = 2;
+
+/// The type of a symbol of a data object.
+pub const STT_OBJECT: u8 = 1;
+
+/// The type of a symbol of a function.
+pub const STT_FUNC: u8 = 2;
+
+/// The index of the section of a symbol that is undefined.
+pub const SHN_UNDEF: u16 = 0;
+
+/// The dynamic tag ending the dynamic section.
+pub const DT_NULL: u64 = 0;
+
+/// The dynamic tag holding the address of the relocations with addends.
+pub const DT_RELA: u64 = 7;
+
+/// The dynamic tag holding the size of the relocations with addends.
+pub const DT_RELASZ: u64 = 8;
+
+/// The dynamic tag holding the size of a relocation with an addend.
+pub const DT_RELAENT: u64 = 9;
+
+/// The dynamic tag holding the address of the relocations without addends.
+pub const DT_REL: u64 = 17;
+
+/// The dynamic tag holding the address of the relative relocations in the
+/// compact format.
+pub const DT_RELR: u64 = 36;
+
+/// The relocation type that does nothing, on every machine.
+pub const R_NONE: u32 = 0;
+
+/// The word size of an ELF file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Class {
+  /// A 32-bit ELF file.
+  Elf32,
+
+  /// A 64-bit ELF file.
+  Elf64,
+}
+
+impl Class {
+  /// Returns the size of the file header.
+  pub const fn header_size(self) -> usize {
+    match self {
+      Class::Elf32 => 52,
+      Class::Elf64 => 64,
+    }
+  }
+
+  /// Returns the size of a program header.
+  pub const fn program_header_size(self) -> usize {
+    match self {
+      Class::Elf32 => 32,
+      Class::Elf64 => 56,
+    }
+  }
+
+  /// Returns the size of a section header.
+  pub const fn section_header_size(self) -> usize {
+    match self {
+      Class::Elf32 => 40,
+      Class::Elf64 => 64,
+    }
+  }
+
+  /// Returns the size of a symbol table entry.
+  pub const fn symbol_size(self) -> usize {
+    match self {
+      Class::Elf32 => 16,
+      Class::Elf64 => 24,
+    }
+  }
+
+  /// Returns the size of a relocation with an addend.
+  pub const fn rela_size(self) -> usize {
+    match self {
+      Class::Elf32 => 12,
+      Class::Elf64 => 24,
+    }
+  }
+
+  /// Returns the size of a dynamic section entry.
+  pub const fn dynamic_size(self) -> usize {
+    2 * self.word_size()
+  }
+
+  /// Returns the size of an address-sized word.
+  pub const fn word_size(self) -> usize {
+    match self {
+      Class::Elf32 => 4,
+      Class::Elf64 => 8,
+    }
+  }
+
+  /// Reads an address-sized word at `offset` of `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the data to read from
+  /// * `offset` - the offset of the word
+  fn read_word(self, data: &[u8], offset: usize) -> u64 {
+    match self {
+      Class::Elf32 => read_u32(data, offset) as u64,
+      Class::Elf64 => read_u64(data, offset),
+    }
+  }
+}
+
+/// A program header, which describes a segment.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ProgramHeader {
+  /// The `PT_*` type of the segment.
+  pub ty: u32,
+
+  /// The `PF_*` permission flags of the segment.
+  pub flags: u32,
+
+  /// The offset of the segment's contents within the file.
+  pub offset: u64,
+
+  /// The virtual address the segment is loaded at.
+  pub vaddr: u64,
+
+  /// The physical address the segment is loaded at.
+  pub paddr: u64,
+
+  /// The number of bytes of the segment stored in the file.
+  pub file_size: u64,
+
+  /// The number of bytes the segment occupies in memory; anything past
+  /// [`ProgramHeader::file_size`] is zero-filled.
+  pub mem_size: u64,
+
+  /// The alignment of the segment, in memory and in the file.
+  pub align: u64,
+}
+
+/// A section header, which describes a section.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SectionHeader {
+  /// The offset of the section's name in the section name string table.
+  pub name: u32,
+
+  /// The `SHT_*` type of the section.
+  pub ty: u32,
+
+  /// The `SHF_*` flags of the section.
+  pub flags: u64,
+
+  /// The virtual address of the section, if it is loaded.
+  pub addr: u64,
+
+  /// The offset of the section's contents within the file.
+  pub offset: u64,
+
+  /// The size of the section.
+  pub size: u64,
+
+  /// The index of a related section, such as the string table of a symbol
+  /// table.
+  pub link: u32,
+
+  /// Further information, which depends on the type of the section.
+  pub info: u32,
+
+  /// The alignment of the section.
+  pub align: u64,
+
+  /// The size of each entry, for sections that are tables.
+  pub entry_size: u64,
+}
+
+/// A parsed ELF file.
+#[derive(Clone)]
+pub struct Elf<'a> {
+  data: &'a [u8],
+  class: Class,
+  ty: u16,
+  machine: u16,
+  entry: u64,
+  program_headers: Range<usize>,
+  section_headers: Range<usize>,
+  section_names: u16,
+}
+
+impl<'a> Elf<'a> {
+  /// Parses and validates the file header of `data`, and the bounds of its
+  /// header tables.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the contents of the ELF file
+  pub fn parse(data: &'a [u8]) -> Result<Self> {
+    let ident = data.get(..16).ok_or(Error::Corrupted)?;
+    if ident[..4] != *b"\x7fELF" {
+      return Err(Error::Corrupted);
+    }
+    let class = match ident[4] {
+      1 => Class::Elf32,
+      2 => Class::Elf64,
+      _ => return Err(Error::Unsupported),
+    };
+    // Little-endian, version 1.
+    if ident[5] != 1 || ident[6] != 1 {
+      return Err(Error::Unsupported);
+    }
+
+    let header = data.get(..class.header_size()).ok_or(Error::Corrupted)?;
+    // The entry point and the offsets of the header tables are the
+    // address-sized fields following the fixed fields; the flags, the size of
+    // the file header, then the entry size and count of each table follow.
+    let word = class.word_size();
+    let (entry, phoff, shoff) = (24, 24 + word, 24 + 2 * word);
+    let phentsize = 24 + 3 * word + 6;
+    let table = |offset, entry_size, sizes| -> Result<Range<usize>> {
+      let count = read_u16(header, sizes + 2) as usize;
+      if count == 0 {
+        return Ok(0..0);
+      }
+      if read_u16(header, sizes) as usize != entry_size {
+        return Err(Error::Corrupted);
+      }
+      let start = class.read_word(header, offset);
+      let size = count * entry_size;
+      contents(data, start, size as u64)?;
+      Ok(start as usize..start as usize + size)
+    };
+
+    Ok(Self {
+      data,
+      class,
+      ty: read_u16(header, 16),
+      machine: read_u16(header, 18),
+      entry: class.read_word(header, entry),
+      program_headers: table(phoff, class.program_header_size(), phentsize)?,
+      section_headers: table(
+        shoff,
+        class.section_header_size(),
+        phentsize + 4,
+      )?,
+      section_names: read_u16(header, phentsize + 8),
+    })
+  }
+
+  /// Returns the raw contents of the ELF file.
+  pub fn data(&self) -> &'a [u8] {
+    self.data
+  }
+
+  /// Returns the word size of the file.
+  pub fn class(&self) -> Class {
+    self.class
+  }
+
+  /// Returns the `ET_*` type of the file.
+  pub fn file_type(&self) -> u16 {
+    self.ty
+  }
+
+  /// Returns the `EM_*` machine type that the file targets.
+  pub fn machine(&self) -> u16 {
+    self.machine
+  }
+
+  /// Returns the virtual address of the entry point.
+  pub fn entry(&self) -> u64 {
+    self.entry
+  }
+
+  /// Returns an iterator over the program headers.
+  pub fn program_headers(&self) -> ProgramHeaders<'a> {
+    ProgramHeaders {
+      class: self.class,
+      headers: self.data[self.program_headers.clone()]
+        .chunks_exact(self.class.program_header_size()),
+    }
+  }
+
+  /// Returns an iterator over the loadable segments, failing with
+  /// [`Error::Corrupted`] for any that extend past the end of the file, or
+  /// are larger in the file than in memory.
+  pub fn segments(&self) -> impl Iterator<Item = Result<ProgramHeader>> + 'a {
+    let data = self.data;
+    self
+      .program_headers()
+      .filter(|header| header.ty == PT_LOAD)
+      .map(move |header| {
+        contents(data, header.offset, header.file_size)?;
+        if header.file_size > header.mem_size {
+          return Err(Error::Corrupted);
+        }
+        Ok(header)
+      })
+  }
+
+  /// Returns the contents in the file of the segment of `header`, failing
+  /// with [`Error::Corrupted`] if they extend past the end of the file.
+  ///
+  /// # Arguments
+  ///
+  /// * `header` - the program header of the segment
+  pub fn segment_data(&self, header: &ProgramHeader) -> Result<&'a [u8]> {
+    contents(self.data, header.offset, header.file_size)
+  }
+
+  /// Returns an iterator over the section headers.
+  pub fn section_headers(&self) -> SectionHeaders<'a> {
+    SectionHeaders {
+      class: self.class,
+      headers: self.data[self.section_headers.clone()]
+        .chunks_exact(self.class.section_header_size()),
+    }
+  }
+
+  /// Returns the section header at `index`, if there is one.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the section
+  pub fn section_header(&self, index: usize) -> Option<SectionHeader> {
+    self.section_headers().nth(index)
+  }
+
+  /// Returns the contents of the section of `header`, which are empty for
+  /// sections that occupy no space in the file, failing with
+  /// [`Error::Corrupted`] if they extend past the end of the file.
+  ///
+  /// # Arguments
+  ///
+  /// * `header` - the section header of the section
+  pub fn section_data(&self, header: &SectionHeader) -> Result<&'a [u8]> {
+    if header.ty == SHT_NOBITS {
+      return Ok(&[]);
+    }
+    contents(self.data, header.offset, header.size)
+  }
+
+  /// Returns the name of the section of `header`, failing with
+  /// [`Error::Corrupted`] if the file has no section name string table, or
+  /// the name is not in it.
+  ///
+  /// # Arguments
+  ///
+  /// * `header` - the section header of the section
+  pub fn section_name(&self, header: &SectionHeader) -> Result<&'a str> {
+    let names = self
+      .section_header(self.section_names as usize)
+      .ok_or(Error::Corrupted)?;
+    string(self.section_data(&names)?, header.name)
+  }
+
+  /// Returns the header of the first section named `name`, if there is one.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - the name of the section, such as `.text`
+  pub fn section_by_name(&self, name: &str) -> Result<Option<SectionHeader>> {
+    for header in self.section_headers() {
+      if self.section_name(&header)? == name {
+        return Ok(Some(header));
+      }
+    }
+    Ok(None)
+  }
+
+  /// Returns the symbol table of the file, along with the string table of
+  /// its names, or `None` if it has none.
+  pub fn symbol_table(&self) -> Result<Option<SymbolTable<'a>>> {
+    self.symbols_of(SHT_SYMTAB)
+  }
+
+  /// Returns the dynamic linking symbol table of the file, along with the
+  /// string table of its names, or `None` if it has none.
+  pub fn dynamic_symbol_table(&self) -> Result<Option<SymbolTable<'a>>> {
+    self.symbols_of(SHT_DYNSYM)
+  }
+
+  /// Returns the relocations with addends of the section of `header`.
+  ///
+  /// # Arguments
+  ///
+  /// * `header` - the section header of a section of type [`SHT_RELA`]
+  pub fn relocations(&self, header: &SectionHeader) -> Result<Relocations<'a>> {
+    Relocations::new(
+      self.class,
+      self.section_data(header)?,
+      header.entry_size as usize,
+    )
+  }
+
+  /// Returns the first symbol table section of type `ty`, if there is one.
+  ///
+  /// # Arguments
+  ///
+  /// * `ty` - the type of the section
+  fn symbols_of(&self, ty: u32) -> Result<Option<SymbolTable<'a>>> {
+    let Some(symbols) = self.section_headers().find(|header| header.ty == ty)
+    else {
+      return Ok(None);
+    };
+    let strings = self
+      .section_header(symbols.link as usize)
+      .ok_or(Error::Corrupted)?;
+    Ok(Some(SymbolTable::new(
+      self.class,
+      self.section_data(&symbols)?,
+      self.section_data(&strings)?,
+    )?))
+  }
+}
+
+/// An iterator over the program headers of an [`Elf`] file.
+#[derive(Clone)]
+pub struct ProgramHeaders<'a> {
+  class: Class,
+  headers: core::slice::ChunksExact<'a, u8>,
+}
+
+impl Iterator for ProgramHeaders<'_> {
+  type Item = ProgramHeader;
+
+  fn next(&mut self) -> Option<ProgramHeader> {
+    let header = self.headers.next()?;
+    let word = |offset| self.class.read_word(header, offset);
+    Some(match self.class {
+      Class::Elf32 => ProgramHeader {
+        ty: read_u32(header, 0),
+        offset: word(4),
+        vaddr: word(8),
+        paddr: word(12),
+        file_size: word(16),
+        mem_size: word(20),
+        flags: read_u32(header, 24),
+        align: word(28),
+      },
+      Class::Elf64 => ProgramHeader {
+        ty: read_u32(header, 0),
+        flags: read_u32(header, 4),
+        offset: word(8),
+        vaddr: word(16),
+        paddr: word(24),
+        file_size: word(32),
+        mem_size: word(40),
+        align: word(48),
+      },
+    })
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.headers.size_hint()
+  }
+}
+
+impl ExactSizeIterator for ProgramHeaders<'_> {}
+
+impl FusedIterator for ProgramHeaders<'_> {}
+
+/// An iterator over the section headers of an [`Elf`] file.
+#[derive(Clone)]
+pub struct SectionHeaders<'a> {
+  class: Class,
+  headers: core::slice::ChunksExact<'a, u8>,
+}
+
+impl Iterator for SectionHeaders<'_> {
+  type Item = SectionHeader;
+
+  fn next(&mut self) -> Option<SectionHeader> {
+    let header = self.headers.next()?;
+    // The address-sized fields follow the name and type, and are followed by
+    // the link and information, then the alignment and entry size.
+    let size = self.class.word_size();
+    let word = |index| self.class.read_word(header, 8 + index * size);
+    Some(SectionHeader {
+      name: read_u32(header, 0),
+      ty: read_u32(header, 4),
+      flags: word(0),
+      addr: word(1),
+      offset: word(2),
+      size: word(3),
+      link: read_u32(header, 8 + 4 * size),
+      info: read_u32(header, 12 + 4 * size),
+      align: self.class.read_word(header, 16 + 4 * size),
+      entry_size: self.class.read_word(header, 16 + 5 * size),
+    })
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.headers.size_hint()
+  }
+}
+
+impl ExactSizeIterator for SectionHeaders<'_> {}
+
+impl FusedIterator for SectionHeaders<'_> {}
+
+/// Returns the `size` bytes of `data` at `offset`, failing with
+/// [`Error::Corrupted`] if they extend past its end.
+///
+/// # Arguments
+///
+/// * `data` - the contents of the file
+/// * `offset` - the offset of the bytes
+/// * `size` - the number of bytes
+fn contents(data: &[u8], offset: u64, size: u64) -> Result<&[u8]> {
+  let start = usize::try_from(offset).map_err(|_| Error::Corrupted)?;
+  let size = usize::try_from(size).map_err(|_| Error::Corrupted)?;
+  start
+    .checked_add(size)
+    .and_then(|end| data.get(start..end))
+    .ok_or(Error::Corrupted)
+}
+
+/// Returns the NUL-terminated string at `offset` of the string table
+/// `strings`, failing with [`Error::Corrupted`] if it is not within the table
+/// or not UTF-8.
+///
+/// # Arguments
+///
+/// * `strings` - the contents of a string table
+/// * `offset` - the offset of the string
+fn string(strings: &[u8], offset: u32) -> Result<&str> {
+  let rest = strings.get(offset as usize..).ok_or(Error::Corrupted)?;
+  let len = rest
+    .iter()
+    .position(|&byte| byte == 0)
+    .ok_or(Error::Corrupted)?;
+  core::str::from_utf8(&rest[..len]).map_err(|_| Error::Corrupted)
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::vec::Vec;
+
+  /// The names of the sections of the test file, and their offsets.
+  const SECTION_NAMES: &[u8] =
+    b"\0.text\0.symtab\0.strtab\0.shstrtab\0.rela.text\0";
+  const TEXT: u32 = 1;
+  const SYMTAB: u32 = 7;
+  const STRTAB: u32 = 15;
+  const SHSTRTAB: u32 = 23;
+  const RELA_TEXT: u32 = 33;
+
+  /// Appends `bytes` to `data` at the next multiple of 8, returning their
+  /// offset.
+  fn push(data: &mut Vec<u8>, bytes: &[u8]) -> u64 {
+    data.resize((data.len() + 7) & !7, 0);
+    data.extend_from_slice(bytes);
+    (data.len() - bytes.len()) as u64
+  }
+
+  /// Overwrites the bytes of `data` at `offset` with `bytes`.
+  fn set(data: &mut [u8], offset: usize, bytes: &[u8]) {
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+  }
+
+  /// Returns a 64-bit section header.
+  fn section(name: u32, ty: u32, offset: u64, size: u64, link: u32) -> Vec<u8> {
+    let entry_size: u64 = match ty {
+      SHT_SYMTAB => 24,
+      SHT_RELA => 24,
+      _ => 0,
+    };
+    let mut header = std::vec![0; 64];
+    set(&mut header, 0, &name.to_le_bytes());
+    set(&mut header, 4, &ty.to_le_bytes());
+    set(&mut header, 24, &offset.to_le_bytes());
+    set(&mut header, 32, &size.to_le_bytes());
+    set(&mut header, 40, &link.to_le_bytes());
+    set(&mut header, 56, &entry_size.to_le_bytes());
+    header
+  }
+
+  /// Returns a 64-bit x86-64 executable with one segment of code, a symbol
+  /// `main` and a relocation against it.
+  fn executable() -> Vec<u8> {
+    let mut data = std::vec![0; 64 + 56];
+    set(&mut data, 0, b"\x7fELF\x02\x01\x01");
+    set(&mut data, 16, &ET_EXEC.to_le_bytes());
+    set(&mut data, 18, &EM_X86_64.to_le_bytes());
+    set(&mut data, 24, &0x1000u64.to_le_bytes());
+    set(&mut data, 32, &64u64.to_le_bytes());
+    set(&mut data, 54, &[56, 0, 1, 0, 64, 0, 6, 0, 4, 0]);
+
+    let text = push(&mut data, &[0x90; 16]);
+    let strings = push(&mut data, b"\0main\0");
+    let names = push(&mut data, SECTION_NAMES);
+    let mut main = [0; 24];
+    set(&mut main, 0, &1u32.to_le_bytes());
+    main[4] = STB_GLOBAL << 4 | STT_FUNC;
+    set(&mut main, 6, &1u16.to_le_bytes());
+    set(&mut main, 8, &0x1000u64.to_le_bytes());
+    set(&mut main, 16, &16u64.to_le_bytes());
+    let symbols = push(&mut data, &[[0; 24], main].concat());
+    let mut rela = [0; 24];
+    set(&mut rela, 0, &0x1008u64.to_le_bytes());
+    set(&mut rela, 8, &(1u64 << 32 | 2).to_le_bytes());
+    set(&mut rela, 16, &(-4i64).to_le_bytes());
+    let relocations = push(&mut data, &rela);
+
+    // The program header of the code.
+    set(&mut data, 64, &PT_LOAD.to_le_bytes());
+    set(&mut data, 68, &(PF_R | PF_X).to_le_bytes());
+    set(&mut data, 72, &text.to_le_bytes());
+    set(&mut data, 80, &0x1000u64.to_le_bytes());
+    set(&mut data, 96, &16u64.to_le_bytes());
+    set(&mut data, 104, &32u64.to_le_bytes());
+
+    let headers = [
+      section(0, 0, 0, 0, 0),
+      section(TEXT, 1, text, 16, 0),
+      section(SYMTAB, SHT_SYMTAB, symbols, 48, 3),
+      section(STRTAB, SHT_STRTAB, strings, 6, 0),
+      section(SHSTRTAB, SHT_STRTAB, names, SECTION_NAMES.len() as u64, 0),
+      section(RELA_TEXT, SHT_RELA, relocations, 24, 2),
+    ]
+    .concat();
+    let shoff = push(&mut data, &headers);
+    set(&mut data, 40, &shoff.to_le_bytes());
+    data
+  }
+
+  #[test]
+  fn headers_and_segments_are_read() {
+    let data = executable();
+    let elf = Elf::parse(&data).unwrap();
+    assert_eq!(elf.class(), Class::Elf64);
+    assert_eq!(elf.file_type(), ET_EXEC);
+    assert_eq!(elf.machine(), EM_X86_64);
+    assert_eq!(elf.entry(), 0x1000);
+    assert_eq!(elf.program_headers().len(), 1);
+    let segment = elf.segments().next().unwrap().unwrap();
+    assert_eq!((segment.vaddr, segment.mem_size), (0x1000, 32));
+    assert_eq!(segment.flags, PF_R | PF_X);
+    assert_eq!(elf.segment_data(&segment).unwrap(), [0x90; 16]);
+  }
+
+  #[test]
+  fn sections_symbols_and_relocations_are_read() {
+    let data = executable();
+    let elf = Elf::parse(&data).unwrap();
+    assert_eq!(elf.section_headers().len(), 6);
+    let text = elf.section_by_name(".text").unwrap().unwrap();
+    assert_eq!(elf.section_data(&text).unwrap(), [0x90; 16]);
+    assert!(elf.section_by_name(".data").unwrap().is_none());
+
+    let symbols = elf.symbol_table().unwrap().unwrap();
+    assert_eq!(symbols.len(), 2);
+    let main = symbols.get(1).unwrap();
+    assert_eq!(symbols.name(&main), Ok("main"));
+    assert_eq!((main.bind(), main.ty()), (STB_GLOBAL, STT_FUNC));
+    assert_eq!((main.value, main.size, main.section), (0x1000, 16, 1));
+    assert!(elf.dynamic_symbol_table().unwrap().is_none());
+
+    let rela = elf.section_by_name(".rela.text").unwrap().unwrap();
+    let relocations: Vec<_> = elf.relocations(&rela).unwrap().collect();
+    assert_eq!(
+      relocations,
+      [Rela {
+        offset: 0x1008,
+        symbol: 1,
+        ty: 2,
+        addend: -4,
+      }]
+    );
+  }
+
+  #[test]
+  fn malformed_files_are_rejected() {
+    let data = executable();
+    assert_eq!(Elf::parse(&data[..100]).err(), Some(Error::Corrupted));
+    let mut bad = data.clone();
+    bad[1] = b'X';
+    assert_eq!(Elf::parse(&bad).err(), Some(Error::Corrupted));
+    let mut bad = data.clone();
+    bad[5] = 2;
+    assert_eq!(Elf::parse(&bad).err(), Some(Error::Unsupported));
+
+    // A segment larger in the file than in memory, then one past the end.
+    let mut bad = data.clone();
+    set(&mut bad, 96, &64u64.to_le_bytes());
+    let elf = Elf::parse(&bad).unwrap();
+    assert_eq!(elf.segments().next(), Some(Err(Error::Corrupted)));
+    set(&mut bad, 96, &(1u64 << 40).to_le_bytes());
+    set(&mut bad, 104, &(1u64 << 40).to_le_bytes());
+    let elf = Elf::parse(&bad).unwrap();
+    assert_eq!(elf.segments().next(), Some(Err(Error::Corrupted)));
+  }
+
+  #[test]
+  fn dynamic_entries_end_at_the_null_entry() {
+    let mut data = Vec::new();
+    for word in [DT_RELA, 0x2000, DT_RELASZ, 48, DT_NULL, 0, DT_RELA, 1] {
+      data.extend_from_slice(&word.to_le_bytes());
+    }
+    let entries: Vec<_> = Dynamic::new(Class::Elf64, &data).collect();
+    assert_eq!(entries, [(DT_RELA, 0x2000), (DT_RELASZ, 48)]);
+  }
+}
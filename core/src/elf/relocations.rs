@@ -0,0 +1,126 @@
+//! This module provides views of relocations with addends, and of the entries
+//! of dynamic sections, which say where the relocations of a loaded
+//! executable are.
+
+use super::{read_u32, read_u64, Class, DT_NULL};
+use crate::error::{Error, Result};
+use core::iter::FusedIterator;
+
+/// A relocation with an addend.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rela {
+  /// The offset or address of the location to relocate.
+  pub offset: u64,
+
+  /// The index of the symbol that the relocation refers to.
+  pub symbol: u32,
+
+  /// The machine-specific type of the relocation.
+  pub ty: u32,
+
+  /// The addend of the relocation.
+  pub addend: i64,
+}
+
+/// An iterator over a table of relocations with addends.
+#[derive(Clone)]
+pub struct Relocations<'a> {
+  class: Class,
+  entries: core::slice::ChunksExact<'a, u8>,
+}
+
+impl<'a> Relocations<'a> {
+  /// Constructs a view of the relocations in `data`, failing with
+  /// [`Error::Corrupted`] if `entry_size` is too small for a relocation.
+  ///
+  /// # Arguments
+  ///
+  /// * `class` - the word size of the file
+  /// * `data` - the contents of the table of relocations
+  /// * `entry_size` - the size of each relocation
+  pub fn new(class: Class, data: &'a [u8], entry_size: usize) -> Result<Self> {
+    if entry_size < class.rela_size() {
+      return Err(Error::Corrupted);
+    }
+    Ok(Self {
+      class,
+      entries: data.chunks_exact(entry_size),
+    })
+  }
+}
+
+impl Iterator for Relocations<'_> {
+  type Item = Rela;
+
+  fn next(&mut self) -> Option<Rela> {
+    let entry = self.entries.next()?;
+    Some(match self.class {
+      Class::Elf32 => {
+        let info = read_u32(entry, 4);
+        Rela {
+          offset: read_u32(entry, 0) as u64,
+          symbol: info >> 8,
+          ty: info & 0xff,
+          addend: read_u32(entry, 8) as i32 as i64,
+        }
+      }
+      Class::Elf64 => {
+        let info = read_u64(entry, 8);
+        Rela {
+          offset: read_u64(entry, 0),
+          symbol: (info >> 32) as u32,
+          ty: info as u32,
+          addend: read_u64(entry, 16) as i64,
+        }
+      }
+    })
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.entries.size_hint()
+  }
+}
+
+impl ExactSizeIterator for Relocations<'_> {}
+
+impl FusedIterator for Relocations<'_> {}
+
+/// An iterator over the `(tag, value)` entries of a dynamic section, up to
+/// the entry tagged [`DT_NULL`].
+#[derive(Clone)]
+pub struct Dynamic<'a> {
+  class: Class,
+  entries: core::slice::ChunksExact<'a, u8>,
+}
+
+impl<'a> Dynamic<'a> {
+  /// Constructs a view of the dynamic section `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `class` - the word size of the file
+  /// * `data` - the contents of the dynamic section
+  pub fn new(class: Class, data: &'a [u8]) -> Self {
+    Self {
+      class,
+      entries: data.chunks_exact(class.dynamic_size()),
+    }
+  }
+}
+
+impl Iterator for Dynamic<'_> {
+  type Item = (u64, u64);
+
+  fn next(&mut self) -> Option<(u64, u64)> {
+    let entry = self.entries.next()?;
+    let size = self.class.word_size();
+    let tag = self.class.read_word(entry, 0);
+    if tag == DT_NULL {
+      self.entries = [].chunks_exact(self.class.dynamic_size());
+      return None;
+    }
+    Some((tag, self.class.read_word(entry, size)))
+  }
+}
+
+impl FusedIterator for Dynamic<'_> {}
@@ -0,0 +1,166 @@
+//! This module provides [`SymbolTable`], a view of a symbol table section and
+//! the string table of its names.
+
+use super::{read_u16, read_u32, string, Class};
+use crate::error::{Error, Result};
+use core::iter::FusedIterator;
+
+/// An entry of a symbol table.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Symbol {
+  /// The offset of the symbol's name in the string table.
+  pub name: u32,
+
+  /// The binding and type of the symbol.
+  pub info: u8,
+
+  /// The visibility of the symbol.
+  pub other: u8,
+
+  /// The index of the section that the symbol is defined in, or
+  /// [`SHN_UNDEF`](super::SHN_UNDEF).
+  pub section: u16,
+
+  /// The value of the symbol, which is an address for symbols of functions
+  /// and data.
+  pub value: u64,
+
+  /// The size of the object that the symbol refers to.
+  pub size: u64,
+}
+
+impl Symbol {
+  /// Returns the `STB_*` binding of the symbol.
+  pub fn bind(&self) -> u8 {
+    self.info >> 4
+  }
+
+  /// Returns the `STT_*` type of the symbol.
+  pub fn ty(&self) -> u8 {
+    self.info & 0xf
+  }
+}
+
+/// A symbol table, along with the string table of its names.
+#[derive(Clone, Copy)]
+pub struct SymbolTable<'a> {
+  class: Class,
+  symbols: &'a [u8],
+  strings: &'a [u8],
+}
+
+impl<'a> SymbolTable<'a> {
+  /// Constructs a view of the symbol table `symbols`, whose names are in
+  /// `strings`, failing with [`Error::Corrupted`] if it is not a whole number
+  /// of entries.
+  ///
+  /// # Arguments
+  ///
+  /// * `class` - the word size of the file
+  /// * `symbols` - the contents of the symbol table
+  /// * `strings` - the contents of the string table
+  pub fn new(
+    class: Class,
+    symbols: &'a [u8],
+    strings: &'a [u8],
+  ) -> Result<Self> {
+    if symbols.len() % class.symbol_size() != 0 {
+      return Err(Error::Corrupted);
+    }
+    Ok(Self {
+      class,
+      symbols,
+      strings,
+    })
+  }
+
+  /// Returns the raw contents of the symbol table.
+  pub fn symbols(&self) -> &'a [u8] {
+    self.symbols
+  }
+
+  /// Returns the raw contents of the string table.
+  pub fn strings(&self) -> &'a [u8] {
+    self.strings
+  }
+
+  /// Returns the number of symbols, including the null symbol that starts
+  /// every table.
+  pub fn len(&self) -> usize {
+    self.symbols.len() / self.class.symbol_size()
+  }
+
+  /// Returns whether there are no symbols.
+  pub fn is_empty(&self) -> bool {
+    self.symbols.is_empty()
+  }
+
+  /// Returns the symbol at `index`, if there is one.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the symbol
+  pub fn get(&self, index: usize) -> Option<Symbol> {
+    self.iter().nth(index)
+  }
+
+  /// Returns an iterator over the symbols.
+  pub fn iter(&self) -> Symbols<'a> {
+    Symbols {
+      class: self.class,
+      entries: self.symbols.chunks_exact(self.class.symbol_size()),
+    }
+  }
+
+  /// Returns the name of `symbol`, failing with [`Error::Corrupted`] if it is
+  /// not in the string table.
+  ///
+  /// # Arguments
+  ///
+  /// * `symbol` - a symbol of this table
+  pub fn name(&self, symbol: &Symbol) -> Result<&'a str> {
+    string(self.strings, symbol.name)
+  }
+}
+
+/// An iterator over the symbols of a [`SymbolTable`].
+#[derive(Clone)]
+pub struct Symbols<'a> {
+  class: Class,
+  entries: core::slice::ChunksExact<'a, u8>,
+}
+
+impl Iterator for Symbols<'_> {
+  type Item = Symbol;
+
+  fn next(&mut self) -> Option<Symbol> {
+    let entry = self.entries.next()?;
+    let word = |offset| self.class.read_word(entry, offset);
+    Some(match self.class {
+      Class::Elf32 => Symbol {
+        name: read_u32(entry, 0),
+        value: word(4),
+        size: word(8),
+        info: entry[12],
+        other: entry[13],
+        section: read_u16(entry, 14),
+      },
+      Class::Elf64 => Symbol {
+        name: read_u32(entry, 0),
+        info: entry[4],
+        other: entry[5],
+        section: read_u16(entry, 6),
+        value: word(8),
+        size: word(16),
+      },
+    })
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.entries.size_hint()
+  }
+}
+
+impl ExactSizeIterator for Symbols<'_> {}
+
+impl FusedIterator for Symbols<'_> {}
@@ -0,0 +1,278 @@
+//! This module provides [`Error`], the kinds of failure that the bootloader,
+//! the file systems and the kernel report to each other across their APIs.
+//!
+//! Each subsystem is free to keep its own, more detailed, errors internally;
+//! those are converted into an [`Error`] where they cross into another
+//! subsystem, so that callers handle one set of failures rather than one per
+//! callee. The conversions from the errors of [`core`] live here; those from
+//! the errors of other crates live beside the errors themselves.
+//!
+//! Checks of data and arguments fail with an [`Error`] through
+//! [`ensure!`](crate::ensure!) and [`bail!`](crate::bail!), rather than
+//! panicking, so that a malformed table or file is reported and skipped
+//! instead of hanging the machine; [`debug_ensure!`](crate::debug_ensure!)
+//! makes checks that are only worth their cost in debug builds.
+
+use core::alloc::LayoutError;
+use core::array::TryFromSliceError;
+use core::fmt;
+use core::num::{ParseIntError, TryFromIntError};
+use core::str::Utf8Error;
+
+/// A specialized [`Result`](core::result::Result) for failures reported with
+/// an [`Error`].
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// A kind of failure.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Error {
+  /// The item, such as a file, device or table, does not exist.
+  NotFound,
+
+  /// The item to be created already exists.
+  AlreadyExists,
+
+  /// There is not enough memory, or another resource, to complete the
+  /// operation.
+  NoMemory,
+
+  /// An argument, or data passed in, is malformed or out of range.
+  InvalidArgument,
+
+  /// The operation, or a format it was given, is not supported.
+  Unsupported,
+
+  /// A device failed to complete the operation.
+  IoError,
+
+  /// The caller is not permitted to perform the operation.
+  PermissionDenied,
+
+  /// The item is in use, and the operation may succeed if retried later.
+  Busy,
+
+  /// The operation did not complete in time.
+  Timeout,
+
+  /// Data read, such as a file system or a table, is inconsistent or fails
+  /// its checksum.
+  Corrupted,
+
+  /// A buffer is too small for the data to be written to it.
+  BufferTooSmall,
+}
+
+impl Error {
+  /// Returns a description of the failure, for reporting to the user.
+  pub const fn description(self) -> &'static str {
+    match self {
+      Error::NotFound => "not found",
+      Error::AlreadyExists => "already exists",
+      Error::NoMemory => "out of memory",
+      Error::InvalidArgument => "invalid argument",
+      Error::Unsupported => "not supported",
+      Error::IoError => "device error",
+      Error::PermissionDenied => "permission denied",
+      Error::Busy => "busy",
+      Error::Timeout => "timed out",
+      Error::Corrupted => "data is corrupted",
+      Error::BufferTooSmall => "buffer too small",
+    }
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.description())
+  }
+}
+
+impl From<fmt::Error> for Error {
+  fn from(_: fmt::Error) -> Self {
+    Error::IoError
+  }
+}
+
+impl From<LayoutError> for Error {
+  fn from(_: LayoutError) -> Self {
+    Error::InvalidArgument
+  }
+}
+
+impl From<ParseIntError> for Error {
+  fn from(_: ParseIntError) -> Self {
+    Error::InvalidArgument
+  }
+}
+
+impl From<TryFromIntError> for Error {
+  fn from(_: TryFromIntError) -> Self {
+    Error::InvalidArgument
+  }
+}
+
+impl From<TryFromSliceError> for Error {
+  fn from(_: TryFromSliceError) -> Self {
+    Error::InvalidArgument
+  }
+}
+
+impl From<Utf8Error> for Error {
+  fn from(_: Utf8Error) -> Self {
+    Error::InvalidArgument
+  }
+}
+
+/// Returns early with `error`, converted into the error type of the
+/// function.
+///
+/// The error may be followed by a message, formatted as by [`format_args!`],
+/// which is logged as a warning with the error as context, and preceded by
+/// `logger:` with a mutable reference to the [`Sink`](crate::log::Sink) to
+/// log it to.
+///
+/// ```ignore
+/// bail!(Error::Unsupported);
+/// bail!(Error::Corrupted, "partition entries of {} bytes", size);
+/// ```
+#[macro_export]
+macro_rules! bail {
+  (logger: $logger:expr, $error:expr, $($arg:tt)+) => {{
+    let error: $crate::error::Error = $error;
+    $crate::log!(
+      logger: $logger,
+      $crate::log::Level::Warn,
+      "{}: {}",
+      format_args!($($arg)+),
+      error
+    );
+    return Err(error.into());
+  }};
+  ($error:expr, $($arg:tt)+) => {{
+    let error: $crate::error::Error = $error;
+    $crate::log!(
+      $crate::log::Level::Warn,
+      "{}: {}",
+      format_args!($($arg)+),
+      error
+    );
+    return Err(error.into());
+  }};
+  ($error:expr $(,)?) => {{
+    let error: $crate::error::Error = $error;
+    return Err(error.into());
+  }};
+}
+
+/// Returns early with an error unless `condition` holds; the error and any
+/// message follow the condition, as for [`bail!`](crate::bail!).
+///
+/// ```ignore
+/// ensure!(header.revision >= 2, Error::Unsupported);
+/// ensure!(len <= data.len(), Error::Corrupted, "table of {} bytes", len);
+/// ```
+#[macro_export]
+macro_rules! ensure {
+  (logger: $logger:expr, $condition:expr, $($rest:tt)+) => {
+    if !$condition {
+      $crate::bail!(logger: $logger, $($rest)+);
+    }
+  };
+  ($condition:expr, $($rest:tt)+) => {
+    if !$condition {
+      $crate::bail!($($rest)+);
+    }
+  };
+}
+
+/// Checks as [`ensure!`](crate::ensure!) does, in debug builds only.
+///
+/// Like [`debug_assert!`], the check is compiled in release builds too, but
+/// never evaluated, so it must still be valid code.
+#[macro_export]
+macro_rules! debug_ensure {
+  ($($arg:tt)+) => {
+    if cfg!(debug_assertions) {
+      $crate::ensure!($($arg)+);
+    }
+  };
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::{Error, Result};
+  use crate::collections::RecordRing;
+  use crate::log::RingSink;
+  use std::string::ToString;
+
+  #[test]
+  fn core_errors_convert_with_the_question_mark() {
+    fn parse(text: &[u8]) -> Result<u8> {
+      Ok(core::str::from_utf8(text)?.parse()?)
+    }
+    fn length(bytes: &[u8]) -> Result<u16> {
+      let bytes = bytes.get(..2).ok_or(Error::Corrupted)?;
+      Ok(u16::from_le_bytes(bytes.try_into()?))
+    }
+    assert_eq!(parse(b"42"), Ok(42));
+    assert_eq!(parse(b"\xff"), Err(Error::InvalidArgument));
+    assert_eq!(parse(b"256"), Err(Error::InvalidArgument));
+    assert_eq!(length(&[1, 2, 3]), Ok(0x201));
+    assert_eq!(length(&[1]), Err(Error::Corrupted));
+  }
+
+  #[test]
+  fn checks_return_early_with_the_error() {
+    fn check(value: u32) -> Result<u32> {
+      crate::ensure!(value != 0, Error::InvalidArgument);
+      crate::debug_ensure!(value < 100, Error::Unsupported);
+      if value == 7 {
+        crate::bail!(Error::Busy);
+      }
+      Ok(value)
+    }
+    assert_eq!(check(3), Ok(3));
+    assert_eq!(check(0), Err(Error::InvalidArgument));
+    assert_eq!(check(7), Err(Error::Busy));
+    let expected = if cfg!(debug_assertions) {
+      Err(Error::Unsupported)
+    } else {
+      Ok(100)
+    };
+    assert_eq!(check(100), expected);
+  }
+
+  #[test]
+  fn failed_checks_log_their_message() {
+    let mut buffer = [0u8; 256];
+    let mut ring = RingSink(RecordRing::new(&mut buffer).unwrap());
+    let check = |sink: &mut RingSink, len: usize| -> Result<()> {
+      crate::ensure!(
+        logger: sink,
+        len <= 4,
+        Error::Corrupted,
+        "table of {} bytes",
+        len
+      );
+      Ok(())
+    };
+    assert_eq!(check(&mut ring, 4), Ok(()));
+    assert_eq!(check(&mut ring, 9), Err(Error::Corrupted));
+    let mut records = ring.0.records();
+    assert_eq!(
+      records.next(),
+      Some(
+        &b"WARN  kcore::error::test: table of 9 bytes: data is corrupted"[..]
+      )
+    );
+    assert_eq!(records.next(), None);
+  }
+
+  #[test]
+  fn errors_display_their_description() {
+    assert_eq!(Error::NoMemory.to_string(), "out of memory");
+    assert_eq!(Error::PermissionDenied.to_string(), "permission denied");
+  }
+}
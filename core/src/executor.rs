@@ -0,0 +1,340 @@
+//! This module provides a cooperative executor of futures that needs no
+//! allocator, so that operations that wait on devices, such as disk reads,
+//! network fetches and timers, are written as `async fn`s rather than as
+//! state machines driven by hand.
+//!
+//! An [`Executor`] holds up to `N` tasks in a fixed arena. Each task is a
+//! future pinned by its spawner, usually on the stack with [`core::pin::pin!`],
+//! and borrowed by the executor until it completes. The executor only polls
+//! the tasks that have been woken, and [`Executor::run_until_stalled`] returns
+//! once none are left awake, so that the caller may wait for an interrupt.
+//!
+//! Tasks are woken through flags in a static bitmap rather than through the
+//! executor, so that a waker that outlives its executor, such as one left
+//! with an interrupt handler, is harmless: waking it at worst makes another
+//! task be polled spuriously. The bitmap limits the tasks alive at any one
+//! time, across every executor, to [`MAX_TASKS`].
+
+use crate::error::{Error, Result};
+use arch::critical_section::CriticalSection;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+/// The most tasks that may be alive at one time, across every executor.
+pub const MAX_TASKS: usize = 64;
+
+/// The identifiers of the tasks that are alive, one bit each.
+static CLAIMED: AtomicU64 = AtomicU64::new(0);
+
+/// The identifiers of the tasks that have been woken since they were last
+/// polled, one bit each.
+static WOKEN: AtomicU64 = AtomicU64::new(0);
+
+/// The functions of the wakers of tasks, whose data is the identifier of
+/// their task.
+static VTABLE: RawWakerVTable =
+  RawWakerVTable::new(clone_waker, wake, wake, drop_waker);
+
+/// A task of an [`Executor`]: a future that outputs nothing, and the
+/// identifier that its wakers set the flag of.
+struct Task<'a> {
+  id: usize,
+  future: Pin<&'a mut (dyn Future<Output = ()> + 'a)>,
+}
+
+/// A cooperative executor of up to `N` tasks, which borrow their futures for
+/// `'a`.
+pub struct Executor<'a, const N: usize> {
+  tasks: [Option<Task<'a>>; N],
+}
+
+impl<'a, const N: usize> Executor<'a, N> {
+  const EMPTY: Option<Task<'a>> = None;
+
+  /// Constructs an executor with no tasks.
+  pub const fn new() -> Self {
+    Self {
+      tasks: [Self::EMPTY; N],
+    }
+  }
+
+  /// Adds `future` as a task, to be polled on the next run, failing with
+  /// [`Error::NoMemory`] if the executor holds `N` tasks already, and with
+  /// [`Error::Busy`] if [`MAX_TASKS`] are alive across every executor.
+  ///
+  /// # Arguments
+  ///
+  /// * `future` - the future to run, pinned for as long as it is borrowed
+  pub fn spawn<F>(&mut self, future: Pin<&'a mut F>) -> Result<()>
+  where
+    F: Future<Output = ()> + 'a,
+  {
+    let slot = self
+      .tasks
+      .iter_mut()
+      .find(|task| task.is_none())
+      .ok_or(Error::NoMemory)?;
+    let id = claim().ok_or(Error::Busy)?;
+    WOKEN.fetch_or(1 << id, Ordering::AcqRel);
+    *slot = Some(Task { id, future });
+    Ok(())
+  }
+
+  /// Returns the number of tasks that have not completed.
+  pub fn len(&self) -> usize {
+    self.tasks.iter().filter(|task| task.is_some()).count()
+  }
+
+  /// Returns `true` if every task has completed.
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Polls the tasks that have been woken, including those woken while they
+  /// are polled, until none are left awake, and returns the number of tasks
+  /// that have not completed.
+  pub fn run_until_stalled(&mut self) -> usize {
+    loop {
+      let ids = self.ids();
+      let woken = WOKEN.fetch_and(!ids, Ordering::AcqRel) & ids;
+      if woken == 0 {
+        return self.len();
+      }
+      for slot in &mut self.tasks {
+        let Some(task) = slot else { continue };
+        if woken & (1 << task.id) == 0 {
+          continue;
+        }
+        let waker = waker(task.id);
+        let mut context = Context::from_waker(&waker);
+        if task.future.as_mut().poll(&mut context).is_ready() {
+          release(task.id);
+          *slot = None;
+        }
+      }
+    }
+  }
+
+  /// Runs the tasks until every one has completed, calling `idle` whenever
+  /// they are all waiting, such as to halt until the next interrupt.
+  ///
+  /// # Arguments
+  ///
+  /// * `idle` - the function to call while every task waits
+  pub fn run(&mut self, mut idle: impl FnMut()) {
+    while self.run_until_stalled() != 0 {
+      idle();
+    }
+  }
+
+  /// Runs the tasks until every one has completed, waiting for the next
+  /// interrupt whenever they are all waiting, for tasks that are woken by
+  /// interrupt handlers, such as through a [`WaitQueue`] or an [`Event`].
+  ///
+  /// Interrupts must be enabled, or the processor waits forever.
+  ///
+  /// [`WaitQueue`]: crate::sync::WaitQueue
+  /// [`Event`]: crate::sync::Event
+  pub fn run_with_interrupts(&mut self) {
+    while self.run_until_stalled() != 0 {
+      // A handler that wakes a task after the check ends the wait at once.
+      let _section = CriticalSection::enter();
+      if !self.is_woken() {
+        arch::wait_for_interrupt();
+      }
+    }
+  }
+
+  /// Returns `true` if any task of the executor has been woken since it was
+  /// last polled.
+  pub fn is_woken(&self) -> bool {
+    WOKEN.load(Ordering::Acquire) & self.ids() != 0
+  }
+
+  /// Returns the bits of the identifiers of the tasks of the executor.
+  fn ids(&self) -> u64 {
+    self
+      .tasks
+      .iter()
+      .flatten()
+      .fold(0, |ids, task| ids | 1 << task.id)
+  }
+}
+
+impl<'a, const N: usize> Default for Executor<'a, N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<'a, const N: usize> Drop for Executor<'a, N> {
+  fn drop(&mut self) {
+    self
+      .tasks
+      .iter()
+      .flatten()
+      .for_each(|task| release(task.id));
+  }
+}
+
+/// Runs `future` to completion on the current thread, and returns its
+/// output, calling `idle` each time it is waiting.
+///
+/// The future is polled again after every call of `idle`, whether or not it
+/// was woken, so `idle` should wait for something to happen, such as the
+/// next interrupt.
+///
+/// # Arguments
+///
+/// * `future` - the future to run
+/// * `idle` - the function to call while the future waits
+pub fn block_on<F: Future>(future: F, mut idle: impl FnMut()) -> F::Output {
+  let mut future = core::pin::pin!(future);
+  // The future is polled regardless, so its wakers need set no flag.
+  let waker = waker(MAX_TASKS);
+  let mut context = Context::from_waker(&waker);
+  loop {
+    if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+      return output;
+    }
+    idle();
+  }
+}
+
+/// Returns a future that is pending the first time it is polled, after
+/// waking its task, so that a long-running task lets the others run.
+pub fn yield_now() -> impl Future<Output = ()> {
+  let mut yielded = false;
+  core::future::poll_fn(move |context| {
+    if yielded {
+      return Poll::Ready(());
+    }
+    yielded = true;
+    context.waker().wake_by_ref();
+    Poll::Pending
+  })
+}
+
+/// Claims the identifier of a new task, returning `None` if there are
+/// [`MAX_TASKS`] alive.
+fn claim() -> Option<usize> {
+  let mut claimed = CLAIMED.load(Ordering::Acquire);
+  loop {
+    let id = (!claimed).trailing_zeros() as usize;
+    if id >= MAX_TASKS {
+      return None;
+    }
+    match CLAIMED.compare_exchange_weak(
+      claimed,
+      claimed | 1 << id,
+      Ordering::AcqRel,
+      Ordering::Acquire,
+    ) {
+      Ok(_) => return Some(id),
+      Err(current) => claimed = current,
+    }
+  }
+}
+
+/// Releases the identifier of a task that has completed or been dropped.
+///
+/// # Arguments
+///
+/// * `id` - the identifier of the task
+fn release(id: usize) {
+  CLAIMED.fetch_and(!(1 << id), Ordering::AcqRel);
+}
+
+/// Returns a waker that sets the flag of the task `id`, or of none if `id`
+/// is [`MAX_TASKS`].
+///
+/// # Arguments
+///
+/// * `id` - the identifier of the task
+fn waker(id: usize) -> Waker {
+  // SAFETY: the functions of the table only read the identifier, which is
+  // not a pointer, so they uphold the contract of `RawWaker`.
+  unsafe { Waker::from_raw(RawWaker::new(id as *const (), &VTABLE)) }
+}
+
+/// Clones the waker of the task `data`.
+fn clone_waker(data: *const ()) -> RawWaker {
+  RawWaker::new(data, &VTABLE)
+}
+
+/// Sets the flag of the task `data`, so its executor polls it again.
+fn wake(data: *const ()) {
+  let id = data as usize;
+  if id < MAX_TASKS {
+    WOKEN.fetch_or(1 << id, Ordering::AcqRel);
+  }
+}
+
+/// Drops the waker of the task `data`, which owns nothing.
+fn drop_waker(_: *const ()) {}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use core::cell::Cell;
+  use core::pin::pin;
+
+  /// Waits until `flag` is set, yielding in between.
+  async fn wait_for(flag: &Cell<bool>) {
+    while !flag.get() {
+      yield_now().await;
+    }
+  }
+
+  #[test]
+  fn tasks_run_until_they_complete() {
+    let (flag, steps) = (Cell::new(false), Cell::new(0));
+    let waiter = pin!(async {
+      wait_for(&flag).await;
+      steps.set(steps.get() + 1);
+    });
+    let setter = pin!(async {
+      yield_now().await;
+      flag.set(true);
+      steps.set(steps.get() + 1);
+    });
+    let mut executor = Executor::<2>::new();
+    executor.spawn(waiter).unwrap();
+    executor.spawn(setter).unwrap();
+
+    assert_eq!(executor.run_until_stalled(), 0);
+    assert_eq!(steps.get(), 2);
+    assert!(executor.is_empty());
+  }
+
+  #[test]
+  fn spawn_fails_when_the_arena_is_full() {
+    let (first, second) = (pin!(async {}), pin!(async {}));
+    let mut executor = Executor::<1>::new();
+
+    executor.spawn(first).unwrap();
+    assert_eq!(executor.spawn(second), Err(Error::NoMemory));
+    executor.run(|| {});
+    assert!(executor.is_empty());
+  }
+
+  #[test]
+  fn block_on_returns_the_output() {
+    let mut idles = 0;
+    let output = block_on(
+      async {
+        yield_now().await;
+        yield_now().await;
+        7
+      },
+      || idles += 1,
+    );
+
+    assert_eq!(output, 7);
+    assert_eq!(idles, 2);
+  }
+}
@@ -0,0 +1,1112 @@
+//! This module provides a driver for FAT12, FAT16 and FAT32 file systems over
+//! any [`BlockDevice`], such as the EFI system partition through the
+//! firmware's Block I/O protocol in the bootloader, or AHCI and virtio disks
+//! in the kernel.
+//!
+//! Reading is always available: directories are listed with their long names,
+//! paths are looked up case-insensitively, and files are read and seeked
+//! within. Creating, writing, truncating and removing files is built with the
+//! `fat-write` feature; only files with short, 8.3 names are created.
+//!
+//! [`Fat`] is also a [`FileSystem`], whose files are [`OpenFile`]s.
+//!
+//! Nothing is allocated: metadata is read through a cache of one sector, and
+//! sectors are at most [`MAX_SECTOR_SIZE`] bytes, the size of the device's
+//! blocks.
+
+mod dir;
+#[cfg(any(feature = "fat-write", test))]
+mod write;
+
+pub use dir::{Entry, ReadDir, MAX_NAME_LEN};
+
+use crate::block::BlockDevice;
+use crate::bytes::{read_u16, read_u32};
+use crate::error::{Error, Result};
+use crate::path::{Component, Path, PathBuf, MAX_PATH_LEN};
+use crate::vfs::{self, DirEntry, FileSystem, FileType, Metadata, SeekFrom};
+use core::iter::Map;
+
+/// The largest sector size of the file systems that are read.
+pub const MAX_SECTOR_SIZE: usize = 4096;
+
+/// An attribute marking a file as read-only.
+pub const ATTR_READ_ONLY: u8 = 0x01;
+
+/// An attribute marking a file as hidden from listings.
+pub const ATTR_HIDDEN: u8 = 0x02;
+
+/// An attribute marking a file as belonging to the operating system.
+pub const ATTR_SYSTEM: u8 = 0x04;
+
+/// An attribute marking the entry holding the label of the volume.
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+
+/// An attribute marking a directory.
+pub const ATTR_DIRECTORY: u8 = 0x10;
+
+/// An attribute marking a file as changed since it was last backed up.
+pub const ATTR_ARCHIVE: u8 = 0x20;
+
+/// The size of a directory entry.
+const DIR_ENTRY_SIZE: usize = 32;
+
+/// The most entries that a directory may hold.
+const MAX_DIR_ENTRIES: u32 = 65536;
+
+/// The fewest clusters of a FAT16 file system.
+const MIN_FAT16_CLUSTERS: u32 = 4085;
+
+/// The fewest clusters of a FAT32 file system.
+const MIN_FAT32_CLUSTERS: u32 = 65525;
+
+/// The variant of a FAT file system, given by its number of clusters.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FatType {
+  /// A file system with 12-bit table entries, of fewer than 4085 clusters.
+  Fat12,
+
+  /// A file system with 16-bit table entries, of fewer than 65525 clusters.
+  Fat16,
+
+  /// A file system with 28-bit table entries.
+  Fat32,
+}
+
+/// A directory of a [`Fat`] file system.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Dir {
+  /// The first cluster of the directory, or zero for the root directory of
+  /// FAT12 and FAT16 file systems, which is not in a cluster.
+  cluster: u32,
+}
+
+/// An open file of a [`Fat`] file system, with a position that reads and
+/// writes start at.
+#[derive(Clone, Debug)]
+pub struct File {
+  #[cfg_attr(not(any(feature = "fat-write", test)), allow(dead_code))]
+  location: Location,
+  cluster: u32,
+  size: u32,
+  position: u64,
+  cursor: Cursor,
+}
+
+impl File {
+  /// Returns the size of the file.
+  pub fn len(&self) -> u64 {
+    self.size as u64
+  }
+
+  /// Returns whether the file is empty.
+  pub fn is_empty(&self) -> bool {
+    self.size == 0
+  }
+
+  /// Returns the position that the next read or write starts at.
+  pub fn position(&self) -> u64 {
+    self.position
+  }
+
+  /// Moves the position that the next read or write starts at, which may be
+  /// past the end of the file.
+  ///
+  /// # Arguments
+  ///
+  /// * `position` - the new position
+  pub fn seek(&mut self, position: u64) {
+    self.position = position;
+  }
+}
+
+/// The place of a directory entry: its directory, and its index within it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Location {
+  dir: Dir,
+  index: u32,
+}
+
+/// A position within a cluster chain, so that walking it sequentially does
+/// not start from its first cluster every time.
+#[derive(Clone, Copy, Default, Debug)]
+struct Cursor {
+  /// The index of the cluster within the chain.
+  index: u64,
+
+  /// The cluster, or zero if the cursor is not yet in the chain.
+  cluster: u32,
+}
+
+/// The cache of one sector.
+struct Cache {
+  sector: Option<u64>,
+  data: [u8; MAX_SECTOR_SIZE],
+}
+
+/// A FAT file system.
+pub struct Fat<D> {
+  device: D,
+  ty: FatType,
+  sector_size: usize,
+  cluster_sectors: u64,
+  fat_start: u64,
+  fat_sectors: u64,
+  #[cfg_attr(not(any(feature = "fat-write", test)), allow(dead_code))]
+  fat_count: u8,
+  active_fat: Option<u8>,
+  root_start: u64,
+  root_entries: u32,
+  data_start: u64,
+  cluster_count: u32,
+  root_cluster: u32,
+  #[cfg_attr(not(any(feature = "fat-write", test)), allow(dead_code))]
+  fs_info: u64,
+  cache: Cache,
+  #[cfg(any(feature = "fat-write", test))]
+  next_free: u32,
+  #[cfg(any(feature = "fat-write", test))]
+  fs_info_stale: bool,
+}
+
+impl<D: BlockDevice> Fat<D> {
+  /// Mounts the file system on `device`, which starts with its boot sector.
+  ///
+  /// Fails with [`Error::Corrupted`] if `device` does not hold a FAT file
+  /// system, and with [`Error::Unsupported`] if its sectors are not the size
+  /// of the device's blocks.
+  ///
+  /// # Arguments
+  ///
+  /// * `device` - the volume, such as a [`Region`](crate::block::Region) of a
+  ///   disk
+  pub fn mount(mut device: D) -> Result<Self> {
+    let sector_size = device.block_size();
+    if !(512..=MAX_SECTOR_SIZE).contains(&sector_size) {
+      return Err(Error::Unsupported);
+    }
+    let mut cache = Cache {
+      sector: None,
+      data: [0; MAX_SECTOR_SIZE],
+    };
+    device.read_blocks(0, &mut cache.data[..sector_size])?;
+    cache.sector = Some(0);
+    let boot = &cache.data[..sector_size];
+    if boot[510..512] != [0x55, 0xaa] {
+      return Err(Error::Corrupted);
+    }
+
+    let bytes_per_sector = read_u16(boot, 11) as usize;
+    if !bytes_per_sector.is_power_of_two() || bytes_per_sector < 512 {
+      return Err(Error::Corrupted);
+    }
+    if bytes_per_sector != sector_size {
+      return Err(Error::Unsupported);
+    }
+    let cluster_sectors = boot[13] as u64;
+    let reserved = read_u16(boot, 14) as u64;
+    let fat_count = boot[16];
+    let root_entries = read_u16(boot, 17) as u32;
+    let total = match read_u16(boot, 19) {
+      0 => read_u32(boot, 32) as u64,
+      total => total as u64,
+    };
+    let fat_sectors = match read_u16(boot, 22) {
+      0 => read_u32(boot, 36) as u64,
+      sectors => sectors as u64,
+    };
+    if !cluster_sectors.is_power_of_two() || reserved == 0 || fat_count == 0 {
+      return Err(Error::Corrupted);
+    }
+
+    let root_bytes = root_entries as u64 * DIR_ENTRY_SIZE as u64;
+    let root_sectors =
+      (root_bytes + sector_size as u64 - 1) / sector_size as u64;
+    let root_start = reserved + fat_count as u64 * fat_sectors;
+    let data_start = root_start + root_sectors;
+    if data_start >= total || total > device.block_count() {
+      return Err(Error::Corrupted);
+    }
+    let cluster_count = u32::try_from((total - data_start) / cluster_sectors)
+      .map_err(|_| Error::Corrupted)?;
+    let ty = if cluster_count < MIN_FAT16_CLUSTERS {
+      FatType::Fat12
+    } else if cluster_count < MIN_FAT32_CLUSTERS {
+      FatType::Fat16
+    } else {
+      FatType::Fat32
+    };
+
+    // The tables must have an entry for every cluster, after the two
+    // reserved entries.
+    let bits = match ty {
+      FatType::Fat12 => 12,
+      FatType::Fat16 => 16,
+      FatType::Fat32 => 32,
+    };
+    if fat_sectors * sector_size as u64 * 8 / bits < cluster_count as u64 + 2 {
+      return Err(Error::Corrupted);
+    }
+
+    let (mut root_cluster, mut fs_info, mut active_fat) = (0, 0, None);
+    if ty == FatType::Fat32 {
+      root_cluster = read_u32(boot, 44);
+      fs_info = read_u16(boot, 48) as u64;
+      // Without mirroring, only the active table is used.
+      let flags = read_u16(boot, 40);
+      if flags & 0x80 != 0 {
+        active_fat = Some((flags & 0xf) as u8).filter(|&fat| fat < fat_count);
+        active_fat.ok_or(Error::Corrupted)?;
+      }
+      if root_entries != 0
+        || !(2..cluster_count as u64 + 2).contains(&(root_cluster as u64))
+      {
+        return Err(Error::Corrupted);
+      }
+    }
+
+    Ok(Self {
+      device,
+      ty,
+      sector_size,
+      cluster_sectors,
+      fat_start: reserved,
+      fat_sectors,
+      fat_count,
+      active_fat,
+      root_start,
+      root_entries,
+      data_start,
+      cluster_count,
+      root_cluster,
+      fs_info,
+      cache,
+      #[cfg(any(feature = "fat-write", test))]
+      next_free: 2,
+      #[cfg(any(feature = "fat-write", test))]
+      fs_info_stale: false,
+    })
+  }
+
+  /// Returns the variant of the file system.
+  pub fn fat_type(&self) -> FatType {
+    self.ty
+  }
+
+  /// Returns the size of a cluster, in bytes.
+  pub fn cluster_size(&self) -> u64 {
+    self.cluster_sectors * self.sector_size as u64
+  }
+
+  /// Returns the device that the file system is on.
+  pub fn device(&self) -> &D {
+    &self.device
+  }
+
+  /// Returns the device that the file system is on, unmounting it.
+  pub fn into_inner(self) -> D {
+    self.device
+  }
+
+  /// Returns the root directory.
+  pub fn root(&self) -> Dir {
+    self.dir_at(0)
+  }
+
+  /// Returns an iterator over the entries of `dir`, other than `.` and `..`.
+  ///
+  /// # Arguments
+  ///
+  /// * `dir` - the directory to list
+  pub fn read_dir(&mut self, dir: Dir) -> ReadDir<'_, D> {
+    ReadDir::new(self, dir)
+  }
+
+  /// Returns the entry of `dir` named `name`, compared case-insensitively
+  /// against both its long and short names, failing with
+  /// [`Error::NotFound`] if there is none.
+  ///
+  /// # Arguments
+  ///
+  /// * `dir` - the directory to look in
+  /// * `name` - the name of the entry
+  pub fn lookup(&mut self, dir: Dir, name: &str) -> Result<Entry> {
+    for entry in self.read_dir(dir) {
+      let entry = entry?;
+      if entry.name_eq(name) {
+        return Ok(entry);
+      }
+    }
+    Err(Error::NotFound)
+  }
+
+  /// Returns the entry at `path`, whose components are separated by `/` or
+  /// `\`, failing with [`Error::NotFound`] if there is none, and with
+  /// [`Error::InvalidArgument`] if `path` names the root directory, which has
+  /// no entry.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the entry, from the root directory
+  pub fn entry(&mut self, path: impl AsRef<Path>) -> Result<Entry> {
+    let path = normalize(path)?;
+    let (dir, name) = self.parent(&path)?;
+    self.lookup(dir, name.ok_or(Error::InvalidArgument)?)
+  }
+
+  /// Opens the directory at `path`, failing with [`Error::NotFound`] if there
+  /// is no such directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the directory, from the root directory
+  pub fn open_dir(&mut self, path: impl AsRef<Path>) -> Result<Dir> {
+    let path = normalize(path)?;
+    match self.parent(&path)? {
+      (dir, None) => Ok(dir),
+      (dir, Some(name)) => {
+        let entry = self.lookup(dir, name)?;
+        self.dir(&entry).map_err(|_| Error::NotFound)
+      }
+    }
+  }
+
+  /// Opens the file at `path`, failing with [`Error::NotFound`] if there is
+  /// none, and with [`Error::InvalidArgument`] if it is a directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the file, from the root directory
+  pub fn open_file(&mut self, path: impl AsRef<Path>) -> Result<File> {
+    let entry = self.entry(path)?;
+    self.file(&entry)
+  }
+
+  /// Returns the directory of `entry`, failing with
+  /// [`Error::InvalidArgument`] if it is not a directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `entry` - an entry of the file system
+  pub fn dir(&self, entry: &Entry) -> Result<Dir> {
+    if !entry.is_directory() {
+      return Err(Error::InvalidArgument);
+    }
+    Ok(self.dir_at(entry.cluster()))
+  }
+
+  /// Opens the file of `entry`, failing with [`Error::InvalidArgument`] if it
+  /// is a directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `entry` - an entry of the file system
+  pub fn file(&self, entry: &Entry) -> Result<File> {
+    if entry.is_directory() {
+      return Err(Error::InvalidArgument);
+    }
+    Ok(File {
+      location: entry.location,
+      cluster: entry.cluster(),
+      size: entry.size(),
+      position: 0,
+      cursor: Cursor::default(),
+    })
+  }
+
+  /// Reads from `file` at its position into `buffer`, advancing the position
+  /// and returning the number of bytes read, which is only less than the
+  /// length of `buffer` at the end of the file.
+  ///
+  /// # Arguments
+  ///
+  /// * `file` - the file to read
+  /// * `buffer` - the buffer to read into
+  pub fn read(&mut self, file: &mut File, buffer: &mut [u8]) -> Result<usize> {
+    let sector_size = self.sector_size as u64;
+    let cluster_size = self.cluster_size();
+    let mut done = 0;
+    while done < buffer.len() && file.position < file.len() {
+      let cluster = self
+        .seek_cluster(
+          file.cluster,
+          &mut file.cursor,
+          file.position / cluster_size,
+        )?
+        .ok_or(Error::Corrupted)?;
+      let within = file.position % cluster_size;
+      let sector = self.cluster_sector(cluster) + within / sector_size;
+      let offset = (within % sector_size) as usize;
+      let remaining =
+        (buffer.len() - done).min((file.len() - file.position) as usize);
+
+      // Whole sectors are read straight into the buffer, up to the end of the
+      // cluster.
+      let whole = (remaining / self.sector_size) as u64;
+      let len = if offset == 0 && whole > 0 {
+        let count = whole.min(self.cluster_sectors - within / sector_size);
+        let len = (count * sector_size) as usize;
+        self
+          .device
+          .read_blocks(sector, &mut buffer[done..done + len])?;
+        len
+      } else {
+        let len = remaining.min(self.sector_size - offset);
+        let data = self.load(sector)?;
+        buffer[done..done + len].copy_from_slice(&data[offset..offset + len]);
+        len
+      };
+      done += len;
+      file.position += len as u64;
+    }
+    Ok(done)
+  }
+
+  /// Returns the directory whose first cluster is `cluster`, where zero is
+  /// the root directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `cluster` - the first cluster of the directory
+  fn dir_at(&self, cluster: u32) -> Dir {
+    match cluster {
+      0 => Dir {
+        cluster: self.root_cluster,
+      },
+      cluster => Dir { cluster },
+    }
+  }
+
+  /// Returns the directory holding the entry at `path`, and the name of the
+  /// entry, which is `None` if `path` names the root directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the normalized path of the entry
+  fn parent<'p>(
+    &mut self,
+    path: &'p PathBuf<MAX_PATH_LEN>,
+  ) -> Result<(Dir, Option<&'p str>)> {
+    let mut components = path.components().filter_map(Component::name);
+    let mut dir = self.root();
+    let Some(mut name) = components.next() else {
+      return Ok((dir, None));
+    };
+    for next in components {
+      let entry = self.lookup(dir, name)?;
+      dir = self.dir(&entry).map_err(|_| Error::NotFound)?;
+      name = next;
+    }
+    Ok((dir, Some(name)))
+  }
+
+  /// Returns the sector and offset within it of the entry at `index` of
+  /// `dir`, or `None` past the end of the directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `dir` - the directory
+  /// * `index` - the index of the entry
+  /// * `cursor` - the position within the chain of the directory
+  fn dir_sector(
+    &mut self,
+    dir: Dir,
+    index: u32,
+    cursor: &mut Cursor,
+  ) -> Result<Option<(u64, usize)>> {
+    let sector_size = self.sector_size as u64;
+    let offset = index as u64 * DIR_ENTRY_SIZE as u64;
+    if dir.cluster == 0 {
+      if index >= self.root_entries {
+        return Ok(None);
+      }
+      let sector = self.root_start + offset / sector_size;
+      return Ok(Some((sector, (offset % sector_size) as usize)));
+    }
+    if index >= MAX_DIR_ENTRIES {
+      return Ok(None);
+    }
+    let cluster_size = self.cluster_size();
+    let Some(cluster) =
+      self.seek_cluster(dir.cluster, cursor, offset / cluster_size)?
+    else {
+      return Ok(None);
+    };
+    let within = offset % cluster_size;
+    let sector = self.cluster_sector(cluster) + within / sector_size;
+    Ok(Some((sector, (within % sector_size) as usize)))
+  }
+
+  /// Returns the cluster at `index` of the chain starting at `start`, or
+  /// `None` if the chain is shorter.
+  ///
+  /// # Arguments
+  ///
+  /// * `start` - the first cluster of the chain, or zero for an empty chain
+  /// * `cursor` - the position within the chain, which is updated
+  /// * `index` - the index of the cluster within the chain
+  fn seek_cluster(
+    &mut self,
+    start: u32,
+    cursor: &mut Cursor,
+    index: u64,
+  ) -> Result<Option<u32>> {
+    if start == 0 {
+      return Ok(None);
+    }
+    if !self.is_cluster(start) {
+      return Err(Error::Corrupted);
+    }
+    if cursor.cluster == 0 || index < cursor.index {
+      *cursor = Cursor {
+        index: 0,
+        cluster: start,
+      };
+    }
+    while cursor.index < index {
+      let Some(next) = self.next_cluster(cursor.cluster)? else {
+        return Ok(None);
+      };
+      cursor.cluster = next;
+      cursor.index += 1;
+    }
+    Ok(Some(cursor.cluster))
+  }
+
+  /// Returns the cluster following `cluster` in its chain, or `None` if it is
+  /// the last, failing with [`Error::Corrupted`] if the table entry is not a
+  /// cluster.
+  ///
+  /// # Arguments
+  ///
+  /// * `cluster` - a cluster in a chain
+  fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>> {
+    let next = self.fat_entry(cluster)?;
+    if next >= self.end_of_chain() {
+      return Ok(None);
+    }
+    if !self.is_cluster(next) {
+      return Err(Error::Corrupted);
+    }
+    Ok(Some(next))
+  }
+
+  /// Returns the entry of the file allocation table for `cluster`.
+  ///
+  /// # Arguments
+  ///
+  /// * `cluster` - the cluster
+  fn fat_entry(&mut self, cluster: u32) -> Result<u32> {
+    let mut bytes = [0; 4];
+    let (offset, len) = self.fat_offset(cluster);
+    let start =
+      self.fat_start + self.active_fat.unwrap_or(0) as u64 * self.fat_sectors;
+    self.read_bytes(start, offset, &mut bytes[..len])?;
+    let value = u32::from_le_bytes(bytes);
+    Ok(match self.ty {
+      FatType::Fat12 if cluster & 1 != 0 => value >> 4,
+      FatType::Fat12 => value & 0xfff,
+      FatType::Fat16 => value,
+      FatType::Fat32 => value & 0x0fff_ffff,
+    })
+  }
+
+  /// Returns the byte offset within a file allocation table of the entry for
+  /// `cluster`, and the number of bytes that hold it.
+  ///
+  /// # Arguments
+  ///
+  /// * `cluster` - the cluster
+  fn fat_offset(&self, cluster: u32) -> (u64, usize) {
+    let cluster = cluster as u64;
+    match self.ty {
+      FatType::Fat12 => (cluster + cluster / 2, 2),
+      FatType::Fat16 => (cluster * 2, 2),
+      FatType::Fat32 => (cluster * 4, 4),
+    }
+  }
+
+  /// Returns the smallest table entry that ends a chain.
+  fn end_of_chain(&self) -> u32 {
+    match self.ty {
+      FatType::Fat12 => 0xff8,
+      FatType::Fat16 => 0xfff8,
+      FatType::Fat32 => 0x0fff_fff8,
+    }
+  }
+
+  /// Returns whether `cluster` is a cluster of the data region.
+  ///
+  /// # Arguments
+  ///
+  /// * `cluster` - the number to check
+  fn is_cluster(&self, cluster: u32) -> bool {
+    (2..self.cluster_count as u64 + 2).contains(&(cluster as u64))
+  }
+
+  /// Returns the first sector of `cluster`.
+  ///
+  /// # Arguments
+  ///
+  /// * `cluster` - a cluster of the data region
+  fn cluster_sector(&self, cluster: u32) -> u64 {
+    self.data_start + (cluster as u64 - 2) * self.cluster_sectors
+  }
+
+  /// Reads `out.len()` bytes at byte `offset` from the sector `start`,
+  /// through the cache.
+  ///
+  /// # Arguments
+  ///
+  /// * `start` - the sector that `offset` is relative to
+  /// * `offset` - the byte offset of the bytes
+  /// * `out` - the buffer to read into
+  fn read_bytes(
+    &mut self,
+    start: u64,
+    offset: u64,
+    out: &mut [u8],
+  ) -> Result<()> {
+    let sector_size = self.sector_size as u64;
+    for (i, byte) in out.iter_mut().enumerate() {
+      let offset = offset + i as u64;
+      let sector = self.load(start + offset / sector_size)?;
+      *byte = sector[(offset % sector_size) as usize];
+    }
+    Ok(())
+  }
+
+  /// Returns the contents of `sector`, reading it into the cache if it is not
+  /// already there.
+  ///
+  /// # Arguments
+  ///
+  /// * `sector` - the sector to read
+  fn load(&mut self, sector: u64) -> Result<&mut [u8]> {
+    let data = &mut self.cache.data[..self.sector_size];
+    if self.cache.sector != Some(sector) {
+      self.cache.sector = None;
+      self.device.read_blocks(sector, data)?;
+      self.cache.sector = Some(sector);
+    }
+    Ok(data)
+  }
+}
+
+/// A [`File`] opened through the [`FileSystem`] interface, which borrows its
+/// file system.
+pub struct OpenFile<'a, D> {
+  fat: &'a mut Fat<D>,
+  file: File,
+}
+
+impl<D: BlockDevice> vfs::File for OpenFile<'_, D> {
+  fn metadata(&self) -> Metadata {
+    Metadata {
+      ty: FileType::File,
+      size: self.file.len(),
+    }
+  }
+
+  fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+    self.fat.read(&mut self.file, buffer)
+  }
+
+  fn seek(&mut self, position: SeekFrom) -> Result<u64> {
+    let position = position.resolve(self.file.position(), self.file.len())?;
+    self.file.seek(position);
+    Ok(position)
+  }
+}
+
+impl<D: BlockDevice> FileSystem for Fat<D> {
+  type Device = D;
+  type File<'a>
+    = OpenFile<'a, D>
+  where
+    Self: 'a;
+  type Dir<'a>
+    = Map<ReadDir<'a, D>, fn(Result<Entry>) -> Result<DirEntry>>
+  where
+    Self: 'a;
+
+  fn mount(device: D) -> Result<Self> {
+    Fat::mount(device)
+  }
+
+  fn open(&mut self, path: impl AsRef<Path>) -> Result<OpenFile<'_, D>> {
+    let file = self.open_file(path)?;
+    Ok(OpenFile { fat: self, file })
+  }
+
+  fn read_dir(&mut self, path: impl AsRef<Path>) -> Result<Self::Dir<'_>> {
+    let dir = self.open_dir(path)?;
+    let entries = Fat::read_dir(self, dir);
+    Ok(entries.map(|entry| entry?.dir_entry()))
+  }
+
+  fn metadata(&mut self, path: impl AsRef<Path>) -> Result<Metadata> {
+    let path = normalize(path)?;
+    match self.parent(&path)? {
+      (_, None) => Ok(Metadata {
+        ty: FileType::Directory,
+        size: 0,
+      }),
+      (dir, Some(name)) => Ok(self.lookup(dir, name)?.metadata()),
+    }
+  }
+}
+
+fn normalize(path: impl AsRef<Path>) -> Result<PathBuf<MAX_PATH_LEN>> {
+  path.as_ref().normalize()
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::string::String;
+  use std::vec::Vec;
+
+  const SECTOR_SIZE: usize = 512;
+
+  /// The first sector of the data region, which is cluster 2.
+  const DATA_START: usize = 4;
+
+  /// A disk in memory.
+  struct Memory {
+    blocks: Vec<u8>,
+  }
+
+  impl BlockDevice for Memory {
+    fn block_size(&self) -> usize {
+      SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+      (self.blocks.len() / SECTOR_SIZE) as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<()> {
+      let start = lba as usize * SECTOR_SIZE;
+      let blocks = self.blocks.get(start..start + buffer.len());
+      buffer.copy_from_slice(blocks.ok_or(Error::InvalidArgument)?);
+      Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> Result<()> {
+      let start = lba as usize * SECTOR_SIZE;
+      let blocks = self.blocks.get_mut(start..start + buffer.len());
+      blocks
+        .ok_or(Error::InvalidArgument)?
+        .copy_from_slice(buffer);
+      Ok(())
+    }
+  }
+
+  /// Sets the entry for `cluster` of both tables of `image`.
+  fn set_fat12(image: &mut [u8], cluster: usize, value: u16) {
+    for fat in 1..3 {
+      let offset = fat * SECTOR_SIZE + cluster * 3 / 2;
+      let old = u16::from_le_bytes([image[offset], image[offset + 1]]);
+      let new = if cluster & 1 != 0 {
+        old & 0x000f | value << 4
+      } else {
+        old & 0xf000 | value & 0x0fff
+      };
+      image[offset..offset + 2].copy_from_slice(&new.to_le_bytes());
+    }
+  }
+
+  /// Returns a short directory entry.
+  fn short_entry(
+    name: &[u8; 11],
+    attributes: u8,
+    cluster: u16,
+    size: u32,
+  ) -> [u8; 32] {
+    let mut raw = [0; 32];
+    raw[..11].copy_from_slice(name);
+    raw[11] = attributes;
+    raw[26..28].copy_from_slice(&cluster.to_le_bytes());
+    raw[28..32].copy_from_slice(&size.to_le_bytes());
+    raw
+  }
+
+  /// Returns the single long name entry of `name` for `short_name`.
+  fn long_entry(name: &str, short_name: &[u8; 11]) -> [u8; 32] {
+    let mut raw = [0; 32];
+    raw[0] = 0x41;
+    raw[11] = dir::ATTR_LONG_NAME;
+    raw[13] = dir::checksum(short_name);
+    let units = name
+      .encode_utf16()
+      .chain([0])
+      .chain(core::iter::repeat(0xffff));
+    let offsets = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+    for (offset, unit) in offsets.into_iter().zip(units) {
+      raw[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+    }
+    raw
+  }
+
+  /// Returns a FAT12 volume of 64 sectors of one cluster each, with a root
+  /// directory of 16 entries holding `README.TXT` of 600 bytes in clusters 2
+  /// and 3, and `BOOT`, in cluster 4, holding `kernel.elf` of 10 bytes in
+  /// cluster 5.
+  fn image() -> Vec<u8> {
+    let mut image = std::vec![0; 64 * SECTOR_SIZE];
+    let boot = &mut image[..SECTOR_SIZE];
+    boot[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+    boot[13] = 1;
+    boot[14..16].copy_from_slice(&1u16.to_le_bytes());
+    boot[16] = 2;
+    boot[17..19].copy_from_slice(&16u16.to_le_bytes());
+    boot[19..21].copy_from_slice(&64u16.to_le_bytes());
+    boot[21] = 0xf8;
+    boot[22..24].copy_from_slice(&1u16.to_le_bytes());
+    boot[510..512].copy_from_slice(&[0x55, 0xaa]);
+
+    set_fat12(&mut image, 0, 0xff8);
+    set_fat12(&mut image, 1, 0xfff);
+    set_fat12(&mut image, 2, 3);
+    set_fat12(&mut image, 3, 0xfff);
+    set_fat12(&mut image, 4, 0xfff);
+    set_fat12(&mut image, 5, 0xfff);
+
+    let root = 3 * SECTOR_SIZE;
+    let readme = short_entry(b"README  TXT", ATTR_ARCHIVE, 2, 600);
+    let boot = short_entry(b"BOOT       ", ATTR_DIRECTORY, 4, 0);
+    image[root..root + 32].copy_from_slice(&short_entry(
+      b"VOLUME     ",
+      ATTR_VOLUME_ID,
+      0,
+      0,
+    ));
+    image[root + 32..root + 64].copy_from_slice(&readme);
+    image[root + 64..root + 96].copy_from_slice(&boot);
+
+    let dir = (DATA_START + 2) * SECTOR_SIZE;
+    let kernel = b"KERNEL  ELF";
+    image[dir..dir + 32].copy_from_slice(&short_entry(
+      b".          ",
+      ATTR_DIRECTORY,
+      4,
+      0,
+    ));
+    image[dir + 32..dir + 64].copy_from_slice(&short_entry(
+      b"..         ",
+      ATTR_DIRECTORY,
+      0,
+      0,
+    ));
+    image[dir + 64..dir + 96]
+      .copy_from_slice(&long_entry("kernel.elf", kernel));
+    image[dir + 96..dir + 128].copy_from_slice(&short_entry(
+      kernel,
+      ATTR_ARCHIVE,
+      5,
+      10,
+    ));
+
+    for (i, byte) in image[DATA_START * SECTOR_SIZE..][..2 * SECTOR_SIZE]
+      .iter_mut()
+      .enumerate()
+    {
+      *byte = i as u8;
+    }
+    image[(DATA_START + 3) * SECTOR_SIZE..][..10]
+      .copy_from_slice(b"\x7fELF kerne");
+    image
+  }
+
+  fn mount() -> Fat<Memory> {
+    Fat::mount(Memory { blocks: image() }).unwrap()
+  }
+
+  fn names(fat: &mut Fat<Memory>, dir: Dir) -> Vec<String> {
+    fat
+      .read_dir(dir)
+      .map(|entry| entry.unwrap().name().collect())
+      .collect()
+  }
+
+  #[test]
+  fn mount_detects_fat12() {
+    let fat = mount();
+
+    assert_eq!(fat.fat_type(), FatType::Fat12);
+    assert_eq!(fat.cluster_size(), SECTOR_SIZE as u64);
+  }
+
+  #[test]
+  fn mount_rejects_missing_signature() {
+    let mut image = image();
+    image[510] = 0;
+
+    assert_eq!(
+      Fat::mount(Memory { blocks: image }).err(),
+      Some(Error::Corrupted)
+    );
+  }
+
+  #[test]
+  fn read_dir_lists_long_and_short_names() {
+    let mut fat = mount();
+    let root = fat.root();
+
+    assert_eq!(names(&mut fat, root), ["README.TXT", "BOOT"]);
+    let boot = fat.open_dir("/boot").unwrap();
+    assert_eq!(names(&mut fat, boot), ["kernel.elf"]);
+  }
+
+  #[test]
+  fn read_follows_cluster_chain() {
+    let mut fat = mount();
+    let mut file = fat.open_file("readme.txt").unwrap();
+    let mut data = std::vec![0; 700];
+
+    assert_eq!(file.len(), 600);
+    assert_eq!(fat.read(&mut file, &mut data).unwrap(), 600);
+    assert!(data[..600].iter().enumerate().all(|(i, &b)| b == i as u8));
+    assert_eq!(fat.read(&mut file, &mut data).unwrap(), 0);
+
+    file.seek(510);
+    assert_eq!(fat.read(&mut file, &mut data[..4]).unwrap(), 4);
+    assert_eq!(data[..4], [254, 255, 0, 1]);
+  }
+
+  #[test]
+  fn open_file_looks_up_paths_case_insensitively() {
+    let mut fat = mount();
+    let mut file = fat.open_file("\\BOOT\\Kernel.ELF").unwrap();
+    let mut data = [0; 16];
+
+    assert_eq!(fat.read(&mut file, &mut data).unwrap(), 10);
+    assert_eq!(&data[..10], b"\x7fELF kerne");
+    assert!(fat.open_file("/boot/KERNEL.ELF").is_ok());
+    assert_eq!(fat.open_file("/boot/missing").err(), Some(Error::NotFound));
+    assert_eq!(fat.open_file("/readme.txt/x").err(), Some(Error::NotFound));
+    assert_eq!(fat.open_file("/boot").err(), Some(Error::InvalidArgument));
+  }
+
+  #[test]
+  fn file_system_opens_and_lists_by_path() {
+    use vfs::File as _;
+
+    let mut fat = mount();
+    let names: Vec<String> = FileSystem::read_dir(&mut fat, "/boot")
+      .unwrap()
+      .map(|entry| String::from(entry.unwrap().name.as_str()))
+      .collect();
+    assert_eq!(names, ["kernel.elf"]);
+    assert!(FileSystem::metadata(&mut fat, "/").unwrap().is_dir());
+    assert_eq!(
+      FileSystem::metadata(&mut fat, "/readme.txt").unwrap().size,
+      600
+    );
+
+    let mut file = fat.open("/boot/kernel.elf").unwrap();
+    let mut data = [0; 4];
+    assert_eq!(file.seek(SeekFrom::End(-6)).unwrap(), 4);
+    file.read_exact(&mut data).unwrap();
+    assert_eq!(&data, b" ker");
+    assert_eq!(file.read_exact(&mut data).err(), Some(Error::Corrupted));
+  }
+
+  #[test]
+  fn long_name_with_wrong_checksum_is_ignored() {
+    let mut image = image();
+    image[(DATA_START + 2) * SECTOR_SIZE + 64 + 13] ^= 1;
+    let mut fat = Fat::mount(Memory { blocks: image }).unwrap();
+    let boot = fat.open_dir("boot").unwrap();
+
+    assert_eq!(names(&mut fat, boot), ["KERNEL.ELF"]);
+  }
+
+  #[test]
+  fn write_creates_and_extends_files() {
+    let mut fat = mount();
+    let boot = fat.open_dir("boot").unwrap();
+    let data: Vec<u8> = (0..1500).map(|i| (i % 251) as u8).collect();
+
+    let mut file = fat.create(boot, "new.txt").unwrap();
+    fat.write(&mut file, &data[..100]).unwrap();
+    fat.write(&mut file, &data[100..]).unwrap();
+
+    let mut file = fat.open_file("boot/NEW.TXT").unwrap();
+    let mut read = std::vec![0; 1500];
+    assert_eq!(file.len(), 1500);
+    assert_eq!(fat.read(&mut file, &mut read).unwrap(), 1500);
+    assert_eq!(read, data);
+    assert_eq!(
+      fat.create(boot, "NEW.TXT").err(),
+      Some(Error::AlreadyExists)
+    );
+    assert_eq!(
+      fat.create(boot, "long name.txt").err(),
+      Some(Error::InvalidArgument)
+    );
+
+    // Both tables are kept the same.
+    let image = fat.into_inner().blocks;
+    assert_eq!(
+      image[SECTOR_SIZE..2 * SECTOR_SIZE],
+      image[2 * SECTOR_SIZE..3 * SECTOR_SIZE]
+    );
+  }
+
+  #[test]
+  fn write_past_end_fills_with_zeros() {
+    let mut fat = mount();
+    let root = fat.root();
+    let mut file = fat.create(root, "GAP").unwrap();
+
+    file.seek(1000);
+    fat.write(&mut file, b"end").unwrap();
+    file.seek(0);
+    let mut read = std::vec![0xff; 1003];
+
+    assert_eq!(fat.read(&mut file, &mut read).unwrap(), 1003);
+    assert!(read[..1000].iter().all(|&b| b == 0));
+    assert_eq!(&read[1000..], b"end");
+  }
+
+  #[test]
+  fn truncate_and_remove_free_clusters() {
+    let mut fat = mount();
+    let mut file = fat.open_file("README.TXT").unwrap();
+
+    fat.truncate(&mut file, 100).unwrap();
+    assert_eq!(fat.open_file("README.TXT").unwrap().len(), 100);
+    assert_eq!(fat.fat_entry(3).unwrap(), 0);
+
+    fat.remove("/boot/kernel.elf").unwrap();
+    assert_eq!(
+      fat.open_file("/boot/kernel.elf").err(),
+      Some(Error::NotFound)
+    );
+    assert_eq!(fat.fat_entry(5).unwrap(), 0);
+    let boot = fat.open_dir("boot").unwrap();
+    assert!(names(&mut fat, boot).is_empty());
+
+    // Freed clusters are allocated again.
+    let root = fat.root();
+    let mut file = fat.create(root, "A.BIN").unwrap();
+    fat.write(&mut file, &[1; 1024]).unwrap();
+    assert_eq!(fat.fat_entry(3).unwrap(), 5);
+  }
+
+  #[test]
+  fn create_fails_when_root_directory_is_full() {
+    let mut fat = mount();
+    let root = fat.root();
+
+    for i in 0..13 {
+      let name: String = std::format!("F{i}");
+      fat.create(root, &name).unwrap();
+    }
+    assert_eq!(fat.create(root, "LAST").err(), Some(Error::NoMemory));
+  }
+}
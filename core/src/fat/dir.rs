@@ -0,0 +1,380 @@
+//! This module provides [`ReadDir`], the listing of a directory, and
+//! [`Entry`], its entries with their long names.
+
+use super::{
+  read_u16, read_u32, Cursor, Dir, Fat, Location, ATTR_DIRECTORY,
+  ATTR_VOLUME_ID, DIR_ENTRY_SIZE,
+};
+use crate::block::BlockDevice;
+use crate::error::Result;
+use crate::vfs::{DirEntry, FileType, Metadata, Name};
+use core::iter::FusedIterator;
+
+/// The longest name of an entry, in UTF-16 code units.
+pub const MAX_NAME_LEN: usize = 255;
+
+/// The attributes of the entries holding parts of a long name.
+pub(super) const ATTR_LONG_NAME: u8 = 0x0f;
+
+/// The first byte of a deleted entry.
+pub(super) const DELETED: u8 = 0xe5;
+
+/// The number of UTF-16 code units held by each long name entry.
+const LONG_NAME_UNITS: usize = 13;
+
+/// The most long name entries of one name.
+const MAX_LONG_NAME_ENTRIES: usize = 20;
+
+/// The offsets of the code units within a long name entry.
+const LONG_NAME_OFFSETS: [usize; LONG_NAME_UNITS] =
+  [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// An entry of a directory.
+#[derive(Clone)]
+pub struct Entry {
+  name: [u16; MAX_NAME_LEN],
+  name_len: u8,
+  short_name: [u8; 11],
+  attributes: u8,
+  cluster: u32,
+  size: u32,
+  pub(super) location: Location,
+  pub(super) first_index: u32,
+}
+
+impl Entry {
+  /// Returns the name of the entry, which is its long name if it has one,
+  /// with unpaired surrogates replaced.
+  pub fn name(&self) -> impl Iterator<Item = char> + '_ {
+    char::decode_utf16(self.name[..self.name_len as usize].iter().copied())
+      .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+  }
+
+  /// Returns whether the name of the entry, or its short name, is `name`,
+  /// ignoring the case of ASCII letters.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - the name to compare against
+  pub fn name_eq(&self, name: &str) -> bool {
+    let units = &self.name[..self.name_len as usize];
+    let long = name
+      .encode_utf16()
+      .map(fold)
+      .eq(units.iter().map(|&c| fold(c)));
+    long || short_name(name) == Some(self.short_name)
+  }
+
+  /// Returns the short, 8.3 name of the entry, padded with spaces.
+  pub fn short_name(&self) -> &[u8; 11] {
+    &self.short_name
+  }
+
+  /// Returns the `ATTR_*` attributes of the entry.
+  pub fn attributes(&self) -> u8 {
+    self.attributes
+  }
+
+  /// Returns whether the entry is a directory.
+  pub fn is_directory(&self) -> bool {
+    self.attributes & ATTR_DIRECTORY != 0
+  }
+
+  /// Returns the size of the file, which is zero for directories.
+  pub fn size(&self) -> u32 {
+    self.size
+  }
+
+  /// Returns the first cluster of the entry's contents, or zero if it has
+  /// none.
+  pub fn cluster(&self) -> u32 {
+    self.cluster
+  }
+
+  /// Returns the metadata of the entry.
+  pub fn metadata(&self) -> Metadata {
+    let ty = match self.is_directory() {
+      true => FileType::Directory,
+      false => FileType::File,
+    };
+    Metadata {
+      ty,
+      size: self.size as u64,
+    }
+  }
+
+  /// Returns the entry as an entry of the [`FileSystem`] interface.
+  ///
+  /// [`FileSystem`]: crate::vfs::FileSystem
+  pub fn dir_entry(&self) -> Result<DirEntry> {
+    Ok(DirEntry {
+      name: Name::from_chars(self.name())?,
+      metadata: self.metadata(),
+    })
+  }
+
+  /// Constructs the entry from its raw directory entry `raw` and the long
+  /// name preceding it.
+  ///
+  /// # Arguments
+  ///
+  /// * `raw` - the directory entry
+  /// * `location` - the place of the directory entry
+  /// * `long` - the long name entries preceding it
+  fn new(raw: &[u8], location: Location, long: &LongName) -> Self {
+    let mut short_name = [0; 11];
+    short_name.copy_from_slice(&raw[..11]);
+    if short_name[0] == 0x05 {
+      short_name[0] = DELETED;
+    }
+    let mut entry = Self {
+      name: [0; MAX_NAME_LEN],
+      name_len: 0,
+      short_name,
+      attributes: raw[11],
+      cluster: (read_u16(raw, 20) as u32) << 16 | read_u16(raw, 26) as u32,
+      size: read_u32(raw, 28),
+      location,
+      first_index: location.index,
+    };
+    if let Some(first_index) = long.first_index(checksum(&short_name)) {
+      let len = long.len();
+      entry.name[..len].copy_from_slice(&long.units[..len]);
+      entry.name_len = len as u8;
+      entry.first_index = first_index;
+    } else {
+      entry.set_short_display_name(raw[12]);
+    }
+    entry
+  }
+
+  /// Sets the name of the entry to its short name, as `BASE.EXT`, with the
+  /// parts in lowercase that `case` marks.
+  ///
+  /// # Arguments
+  ///
+  /// * `case` - the flags of the parts of the short name that are lowercase
+  fn set_short_display_name(&mut self, case: u8) {
+    let (base, ext) = self.short_name.split_at(8);
+    let base = trim(base).iter().map(|&c| lower(c, case & 0x08 != 0));
+    let ext = trim(ext).iter().map(|&c| lower(c, case & 0x10 != 0));
+    let dot = Some(b'.').filter(|_| ext.len() != 0);
+    let name = base.chain(dot).chain(ext);
+    for (unit, c) in self.name.iter_mut().zip(name) {
+      *unit = c as u16;
+      self.name_len += 1;
+    }
+  }
+}
+
+/// An iterator over the entries of a directory, other than `.`, `..` and the
+/// volume label.
+pub struct ReadDir<'a, D> {
+  fat: &'a mut Fat<D>,
+  dir: Dir,
+  index: u32,
+  cursor: Cursor,
+  done: bool,
+}
+
+impl<'a, D: BlockDevice> ReadDir<'a, D> {
+  /// Constructs the listing of `dir`.
+  ///
+  /// # Arguments
+  ///
+  /// * `fat` - the file system
+  /// * `dir` - the directory to list
+  pub(super) fn new(fat: &'a mut Fat<D>, dir: Dir) -> Self {
+    Self {
+      fat,
+      dir,
+      index: 0,
+      cursor: Cursor::default(),
+      done: false,
+    }
+  }
+
+  /// Returns the next entry, or `None` at the end of the directory.
+  fn next_entry(&mut self) -> Result<Option<Entry>> {
+    let mut long = LongName::new();
+    let mut raw = [0; DIR_ENTRY_SIZE];
+    loop {
+      let Some((sector, offset)) =
+        self
+          .fat
+          .dir_sector(self.dir, self.index, &mut self.cursor)?
+      else {
+        return Ok(None);
+      };
+      raw.copy_from_slice(&self.fat.load(sector)?[offset..][..DIR_ENTRY_SIZE]);
+      let location = Location {
+        dir: self.dir,
+        index: self.index,
+      };
+      self.index += 1;
+
+      match raw[0] {
+        // No entries follow the first free one.
+        0 => return Ok(None),
+        DELETED => long.reset(),
+        _ if raw[11] & 0x3f == ATTR_LONG_NAME => {
+          long.push(location.index, &raw)
+        }
+        _ if raw[11] & ATTR_VOLUME_ID != 0 || raw[0] == b'.' => long.reset(),
+        _ => return Ok(Some(Entry::new(&raw, location, &long))),
+      }
+    }
+  }
+}
+
+impl<D: BlockDevice> Iterator for ReadDir<'_, D> {
+  type Item = Result<Entry>;
+
+  fn next(&mut self) -> Option<Result<Entry>> {
+    if self.done {
+      return None;
+    }
+    let entry = self.next_entry().transpose();
+    self.done = !matches!(entry, Some(Ok(_)));
+    entry
+  }
+}
+
+impl<D: BlockDevice> FusedIterator for ReadDir<'_, D> {}
+
+/// A long name being assembled from its entries, which precede the short
+/// entry in reverse order.
+struct LongName {
+  units: [u16; LONG_NAME_UNITS * MAX_LONG_NAME_ENTRIES],
+  /// The index of the first entry, or `None` if there is no valid name.
+  first: Option<u32>,
+  /// The order of the last entry pushed, which is one once complete.
+  order: u8,
+  checksum: u8,
+}
+
+impl LongName {
+  /// Constructs an empty long name.
+  fn new() -> Self {
+    Self {
+      units: [0; LONG_NAME_UNITS * MAX_LONG_NAME_ENTRIES],
+      first: None,
+      order: 0,
+      checksum: 0,
+    }
+  }
+
+  /// Discards the long name.
+  fn reset(&mut self) {
+    self.first = None;
+  }
+
+  /// Adds the long name entry `raw` at `index`, discarding the name if the
+  /// entry is not the one expected.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the entry within its directory
+  /// * `raw` - the long name entry
+  fn push(&mut self, index: u32, raw: &[u8]) {
+    let order = raw[0] & 0x1f;
+    if raw[0] & 0x40 != 0 {
+      self.first = Some(index);
+      self.checksum = raw[13];
+    } else if order + 1 != self.order || raw[13] != self.checksum {
+      self.first = None;
+    }
+    if order == 0 || order as usize > MAX_LONG_NAME_ENTRIES {
+      self.first = None;
+    }
+    if self.first.is_none() {
+      return;
+    }
+    self.order = order;
+    let units = &mut self.units[(order as usize - 1) * LONG_NAME_UNITS..];
+    for (unit, &offset) in units.iter_mut().zip(LONG_NAME_OFFSETS.iter()) {
+      *unit = read_u16(raw, offset);
+    }
+  }
+
+  /// Returns the index of the first entry of the name if it is complete and
+  /// belongs to the short entry whose name has `checksum`.
+  ///
+  /// # Arguments
+  ///
+  /// * `checksum` - the checksum of the short name
+  fn first_index(&self, checksum: u8) -> Option<u32> {
+    let len = self.len();
+    let valid = self.order == 1 && self.checksum == checksum;
+    self
+      .first
+      .filter(|_| valid && len > 0 && len <= MAX_NAME_LEN)
+  }
+
+  /// Returns the length of the name, which ends at the first NUL.
+  fn len(&self) -> usize {
+    self
+      .units
+      .iter()
+      .position(|&unit| unit == 0)
+      .unwrap_or(self.units.len())
+  }
+}
+
+/// Returns the checksum of `short_name` that its long name entries hold.
+///
+/// # Arguments
+///
+/// * `short_name` - the short name, padded with spaces
+pub(super) fn checksum(short_name: &[u8; 11]) -> u8 {
+  short_name
+    .iter()
+    .fold(0u8, |sum, &c| sum.rotate_right(1).wrapping_add(c))
+}
+
+/// Returns `name` as a short name padded with spaces, in uppercase, or
+/// `None` if it is not a valid 8.3 name.
+///
+/// # Arguments
+///
+/// * `name` - the name
+pub(super) fn short_name(name: &str) -> Option<[u8; 11]> {
+  let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+  let valid = |part: &str, max: usize| {
+    part.len() <= max
+      && part
+        .bytes()
+        .all(|c| c.is_ascii_alphanumeric() || b"!#$%&'()-@^_`{}~".contains(&c))
+  };
+  if base.is_empty() || !valid(base, 8) || !valid(ext, 3) {
+    return None;
+  }
+  let mut short = [b' '; 11];
+  short[..base.len()].copy_from_slice(base.as_bytes());
+  short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+  short.make_ascii_uppercase();
+  Some(short)
+}
+
+/// Returns `part` of a short name without its padding.
+fn trim(part: &[u8]) -> &[u8] {
+  let len = part.iter().rposition(|&c| c != b' ').map_or(0, |i| i + 1);
+  &part[..len]
+}
+
+/// Returns `c` in lowercase if `lowercase` is set.
+fn lower(c: u8, lowercase: bool) -> u8 {
+  if lowercase {
+    c.to_ascii_lowercase()
+  } else {
+    c
+  }
+}
+
+/// Returns `unit` with ASCII letters in uppercase.
+fn fold(unit: u16) -> u16 {
+  match u8::try_from(unit) {
+    Ok(c) => c.to_ascii_uppercase() as u16,
+    Err(_) => unit,
+  }
+}
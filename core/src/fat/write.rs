@@ -0,0 +1,393 @@
+//! This module provides the operations that change a [`Fat`] file system,
+//! which are built with the `fat-write` feature.
+
+use super::dir::{self, DELETED};
+use super::{
+  Cursor, Dir, Fat, FatType, File, Location, ATTR_ARCHIVE, DIR_ENTRY_SIZE,
+};
+use crate::block::BlockDevice;
+use crate::error::{Error, Result};
+use crate::path::Path;
+
+/// The date of 1980-01-01, the earliest that entries hold, which created
+/// files are given in the absence of a clock.
+const EPOCH_DATE: u16 = 0x0021;
+
+/// The signatures of the FAT32 information sector, at offsets 0, 484 and
+/// 508.
+const FS_INFO_SIGNATURES: [(usize, u32); 3] =
+  [(0, 0x4161_5252), (484, 0x6141_7272), (508, 0xaa55_0000)];
+
+impl<D: BlockDevice> Fat<D> {
+  /// Creates an empty file named `name` in `dir`, and opens it.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if `name` is not a valid 8.3
+  /// name, with [`Error::AlreadyExists`] if `dir` has an entry of that name,
+  /// and with [`Error::NoMemory`] if there is no space for the entry.
+  ///
+  /// # Arguments
+  ///
+  /// * `dir` - the directory to create the file in
+  /// * `name` - the short name of the file, such as `CONFIG.TXT`
+  pub fn create(&mut self, dir: Dir, name: &str) -> Result<File> {
+    let short_name = dir::short_name(name).ok_or(Error::InvalidArgument)?;
+    match self.lookup(dir, name) {
+      Ok(_) => return Err(Error::AlreadyExists),
+      Err(Error::NotFound) => {}
+      Err(error) => return Err(error),
+    }
+
+    let index = self.free_dir_entry(dir)?;
+    let (sector, offset) = self
+      .dir_sector(dir, index, &mut Cursor::default())?
+      .ok_or(Error::Corrupted)?;
+    let raw = &mut self.load(sector)?[offset..][..DIR_ENTRY_SIZE];
+    raw.fill(0);
+    raw[..11].copy_from_slice(&short_name);
+    raw[11] = ATTR_ARCHIVE;
+    for offset in [16, 18, 24] {
+      raw[offset..offset + 2].copy_from_slice(&EPOCH_DATE.to_le_bytes());
+    }
+    self.store(sector)?;
+
+    Ok(File {
+      location: Location { dir, index },
+      cluster: 0,
+      size: 0,
+      position: 0,
+      cursor: Cursor::default(),
+    })
+  }
+
+  /// Writes `data` to `file` at its position, advancing the position and
+  /// extending the file as needed, with zeros filling any gap between its end
+  /// and the position.
+  ///
+  /// Fails with [`Error::NoMemory`] if the file system is full, and with
+  /// [`Error::InvalidArgument`] if the file would reach 4 GiB, the most a FAT
+  /// file may hold.
+  ///
+  /// # Arguments
+  ///
+  /// * `file` - the file to write
+  /// * `data` - the bytes to write
+  pub fn write(&mut self, file: &mut File, data: &[u8]) -> Result<()> {
+    let end = file.position.checked_add(data.len() as u64);
+    if end.map_or(true, |end| end > u32::MAX as u64) {
+      return Err(Error::InvalidArgument);
+    }
+    let zeros = [0; 64];
+    while file.position > file.len() {
+      let position = file.position;
+      let len = (position - file.len()).min(zeros.len() as u64) as usize;
+      file.position = file.len();
+      let result = self.write_at(file, &zeros[..len]);
+      file.position = position;
+      result?;
+    }
+    self.write_at(file, data)
+  }
+
+  /// Shrinks `file` to `len` bytes, freeing the clusters past its new end,
+  /// and leaves it unchanged if it is not longer.
+  ///
+  /// # Arguments
+  ///
+  /// * `file` - the file to truncate
+  /// * `len` - the new length of the file
+  pub fn truncate(&mut self, file: &mut File, len: u64) -> Result<()> {
+    if len >= file.len() {
+      return Ok(());
+    }
+    let clusters = (len + self.cluster_size() - 1) / self.cluster_size();
+    file.cursor = Cursor::default();
+    if clusters == 0 {
+      self.free_chain(file.cluster)?;
+      file.cluster = 0;
+    } else {
+      let last = self
+        .seek_cluster(file.cluster, &mut file.cursor, clusters - 1)?
+        .ok_or(Error::Corrupted)?;
+      if let Some(next) = self.next_cluster(last)? {
+        self.set_fat_entry(last, self.end_of_chain_marker())?;
+        self.free_chain(next)?;
+      }
+    }
+    file.size = len as u32;
+    self.update_entry(file)
+  }
+
+  /// Removes the file at `path`, freeing its clusters.
+  ///
+  /// Fails with [`Error::NotFound`] if there is no such file, and with
+  /// [`Error::InvalidArgument`] if it is a directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the file, from the root directory
+  pub fn remove(&mut self, path: impl AsRef<Path>) -> Result<()> {
+    let entry = self.entry(path)?;
+    if entry.is_directory() {
+      return Err(Error::InvalidArgument);
+    }
+    let dir = entry.location.dir;
+    let mut cursor = Cursor::default();
+    for index in entry.first_index..=entry.location.index {
+      let (sector, offset) = self
+        .dir_sector(dir, index, &mut cursor)?
+        .ok_or(Error::Corrupted)?;
+      self.load(sector)?[offset] = DELETED;
+      self.store(sector)?;
+    }
+    self.free_chain(entry.cluster())
+  }
+
+  /// Waits for the changes so far to reach the device's medium.
+  pub fn flush(&mut self) -> Result<()> {
+    self.device.flush()
+  }
+
+  /// Writes `data` to `file` at its position, which is at most its length.
+  ///
+  /// # Arguments
+  ///
+  /// * `file` - the file to write
+  /// * `data` - the bytes to write
+  fn write_at(&mut self, file: &mut File, data: &[u8]) -> Result<()> {
+    let sector_size = self.sector_size as u64;
+    let cluster_size = self.cluster_size();
+    let (size, cluster) = (file.size, file.cluster);
+    let mut done = 0;
+    while done < data.len() {
+      let index = file.position / cluster_size;
+      let Some(cluster) =
+        self.seek_cluster(file.cluster, &mut file.cursor, index)?
+      else {
+        // The position is at the end of the chain, so extend it by one.
+        let previous = match index {
+          0 => None,
+          index => Some(
+            self
+              .seek_cluster(file.cluster, &mut file.cursor, index - 1)?
+              .ok_or(Error::Corrupted)?,
+          ),
+        };
+        let cluster = self.allocate_cluster(previous)?;
+        if file.cluster == 0 {
+          file.cluster = cluster;
+        }
+        continue;
+      };
+      let within = file.position % cluster_size;
+      let sector = self.cluster_sector(cluster) + within / sector_size;
+      let offset = (within % sector_size) as usize;
+      let len = (data.len() - done).min(self.sector_size - offset);
+      self.load(sector)?[offset..offset + len]
+        .copy_from_slice(&data[done..done + len]);
+      self.store(sector)?;
+      done += len;
+      file.position += len as u64;
+      file.size = file.size.max(file.position as u32);
+    }
+    if (size, cluster) != (file.size, file.cluster) {
+      self.update_entry(file)?;
+    }
+    Ok(())
+  }
+
+  /// Writes the first cluster and size of `file` to its directory entry.
+  ///
+  /// # Arguments
+  ///
+  /// * `file` - the file whose entry to update
+  fn update_entry(&mut self, file: &File) -> Result<()> {
+    let Location { dir, index } = file.location;
+    let (sector, offset) = self
+      .dir_sector(dir, index, &mut Cursor::default())?
+      .ok_or(Error::Corrupted)?;
+    let raw = &mut self.load(sector)?[offset..][..DIR_ENTRY_SIZE];
+    raw[20..22].copy_from_slice(&((file.cluster >> 16) as u16).to_le_bytes());
+    raw[26..28].copy_from_slice(&(file.cluster as u16).to_le_bytes());
+    raw[28..32].copy_from_slice(&file.size.to_le_bytes());
+    self.store(sector)
+  }
+
+  /// Returns the index of a free entry of `dir`, extending it by a cluster if
+  /// it has none.
+  ///
+  /// # Arguments
+  ///
+  /// * `dir` - the directory
+  fn free_dir_entry(&mut self, dir: Dir) -> Result<u32> {
+    let mut cursor = Cursor::default();
+    let mut index = 0;
+    while let Some((sector, offset)) =
+      self.dir_sector(dir, index, &mut cursor)?
+    {
+      if matches!(self.load(sector)?[offset], 0 | DELETED) {
+        return Ok(index);
+      }
+      index += 1;
+    }
+    // The root directory of FAT12 and FAT16 file systems cannot grow.
+    if dir.cluster == 0 || index >= super::MAX_DIR_ENTRIES {
+      return Err(Error::NoMemory);
+    }
+    let last = cursor.cluster;
+    let cluster = self.allocate_cluster(Some(last))?;
+    let first = self.cluster_sector(cluster);
+    for sector in first..first + self.cluster_sectors {
+      self.load(sector)?.fill(0);
+      self.store(sector)?;
+    }
+    Ok(index)
+  }
+
+  /// Allocates a free cluster, ending its chain, and appends it to the chain
+  /// ending at `previous` if there is one.
+  ///
+  /// # Arguments
+  ///
+  /// * `previous` - the last cluster of the chain to extend
+  fn allocate_cluster(&mut self, previous: Option<u32>) -> Result<u32> {
+    let count = self.cluster_count;
+    for i in 0..count {
+      let cluster = 2 + (self.next_free - 2 + i) % count;
+      if self.fat_entry(cluster)? != 0 {
+        continue;
+      }
+      self.set_fat_entry(cluster, self.end_of_chain_marker())?;
+      if let Some(previous) = previous {
+        self.set_fat_entry(previous, cluster)?;
+      }
+      self.next_free = 2 + (cluster - 1) % count;
+      self.invalidate_fs_info()?;
+      return Ok(cluster);
+    }
+    Err(Error::NoMemory)
+  }
+
+  /// Frees the clusters of the chain starting at `start`.
+  ///
+  /// # Arguments
+  ///
+  /// * `start` - the first cluster of the chain, or zero for an empty chain
+  fn free_chain(&mut self, start: u32) -> Result<()> {
+    let mut next = Some(start).filter(|&cluster| cluster != 0);
+    // A chain visits each cluster once, unless it is corrupted into a loop.
+    for _ in 0..self.cluster_count {
+      let Some(cluster) = next else {
+        return self.invalidate_fs_info();
+      };
+      if !self.is_cluster(cluster) {
+        return Err(Error::Corrupted);
+      }
+      next = self.next_cluster(cluster)?;
+      self.set_fat_entry(cluster, 0)?;
+    }
+    Err(Error::Corrupted)
+  }
+
+  /// Sets the entry of every file allocation table for `cluster` to `value`.
+  ///
+  /// # Arguments
+  ///
+  /// * `cluster` - the cluster
+  /// * `value` - the next cluster, zero if free, or the end of chain marker
+  fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<()> {
+    let (offset, len) = self.fat_offset(cluster);
+    let fats = match self.active_fat {
+      Some(fat) => fat..fat + 1,
+      None => 0..self.fat_count,
+    };
+    for fat in fats {
+      let start = self.fat_start + fat as u64 * self.fat_sectors;
+      let mut bytes = [0; 4];
+      self.read_bytes(start, offset, &mut bytes[..len])?;
+      let old = u32::from_le_bytes(bytes);
+      let new = match self.ty {
+        FatType::Fat12 if cluster & 1 != 0 => old & 0x000f | value << 4,
+        FatType::Fat12 => old & 0xf000 | value & 0x0fff,
+        FatType::Fat16 => value,
+        // The top four bits are reserved, and kept.
+        FatType::Fat32 => old & 0xf000_0000 | value & 0x0fff_ffff,
+      };
+      self.write_bytes(start, offset, &new.to_le_bytes()[..len])?;
+    }
+    Ok(())
+  }
+
+  /// Returns the table entry that ends a chain.
+  fn end_of_chain_marker(&self) -> u32 {
+    match self.ty {
+      FatType::Fat12 => 0xfff,
+      FatType::Fat16 => 0xffff,
+      FatType::Fat32 => 0x0fff_ffff,
+    }
+  }
+
+  /// Marks the free cluster count and hint of the FAT32 information sector
+  /// as unknown, the first time that clusters are allocated or freed, since
+  /// they are not kept up to date.
+  fn invalidate_fs_info(&mut self) -> Result<()> {
+    if self.ty != FatType::Fat32 || self.fs_info == 0 || self.fs_info_stale {
+      return Ok(());
+    }
+    self.fs_info_stale = true;
+    let sector = self.fs_info;
+    let data = self.load(sector)?;
+    let signed = FS_INFO_SIGNATURES
+      .iter()
+      .all(|&(offset, signature)| super::read_u32(data, offset) == signature);
+    if signed {
+      data[488..496].fill(0xff);
+      self.store(sector)?;
+    }
+    Ok(())
+  }
+
+  /// Writes `bytes` at byte `offset` from the sector `start`, through the
+  /// cache.
+  ///
+  /// # Arguments
+  ///
+  /// * `start` - the sector that `offset` is relative to
+  /// * `offset` - the byte offset of the bytes
+  /// * `bytes` - the bytes to write
+  fn write_bytes(
+    &mut self,
+    start: u64,
+    offset: u64,
+    bytes: &[u8],
+  ) -> Result<()> {
+    let sector_size = self.sector_size as u64;
+    let mut done = 0;
+    while done < bytes.len() {
+      let offset = offset + done as u64;
+      let sector = start + offset / sector_size;
+      let within = (offset % sector_size) as usize;
+      let len = (bytes.len() - done).min(self.sector_size - within);
+      self.load(sector)?[within..within + len]
+        .copy_from_slice(&bytes[done..done + len]);
+      self.store(sector)?;
+      done += len;
+    }
+    Ok(())
+  }
+
+  /// Writes the cached contents of `sector` to the device.
+  ///
+  /// # Arguments
+  ///
+  /// * `sector` - the sector, which is the one in the cache
+  fn store(&mut self, sector: u64) -> Result<()> {
+    debug_assert_eq!(self.cache.sector, Some(sector));
+    let data = &self.cache.data[..self.sector_size];
+    let result = self.device.write_blocks(sector, data);
+    if result.is_err() {
+      // The cache no longer matches the device.
+      self.cache.sector = None;
+    }
+    result
+  }
+}
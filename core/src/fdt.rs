@@ -0,0 +1,961 @@
+//! This module provides parsing of flattened device trees, as views over
+//! their bytes that copy nothing, and the application of device tree overlays
+//! to them.
+//!
+//! A [`DeviceTree`] is validated when it is constructed, and its structure
+//! and strings blocks are read as they are asked for: from its [`Node`]s,
+//! their [`Property`]s and subnodes, and the decoding of their `reg`,
+//! `ranges` and `interrupts` properties, which depends on the cells that
+//! their parents declare. Malformed trees fail with [`Error::Corrupted`].
+//!
+//! Trees are edited in place within a buffer that has room for them to grow,
+//! in the layout that `libfdt` calls sequential: the header, then the memory
+//! reservations, the structure block, and the strings block, with no gaps.
+//! Overlays are applied the way `libfdt` applies them: their phandles are
+//! moved past those of the base tree, their references to labels of the base
+//! tree are resolved through its `__symbols__` node, and the contents of the
+//! `__overlay__` node of each fragment are merged into the fragment's target.
+//!
+//! Labels defined by an overlay are not added to the `__symbols__` node of
+//! the base tree, so overlays may only refer to labels of the firmware's tree.
+
+mod node;
+mod overlay;
+
+pub use node::{
+  AddressRange, Cells, Children, Interrupts, Node, Properties, Property,
+  Ranges, Reg, Translation,
+};
+
+use crate::error::{Error, Result};
+use core::iter::FusedIterator;
+
+/// The magic number that every device tree starts with.
+pub const MAGIC: u32 = 0xd00d_feed;
+
+/// The size of the device tree header.
+pub const HEADER_SIZE: usize = 40;
+
+/// The oldest version of the device tree format that is understood.
+const MIN_VERSION: u32 = 16;
+
+/// The token starting a node.
+const BEGIN_NODE: u32 = 1;
+
+/// The token ending a node.
+const END_NODE: u32 = 2;
+
+/// The token starting a property.
+const PROP: u32 = 3;
+
+/// The token that is ignored.
+const NOP: u32 = 4;
+
+/// The offsets of the header fields.
+const TOTAL_SIZE: usize = 4;
+const STRUCT_OFFSET: usize = 8;
+const STRINGS_OFFSET: usize = 12;
+const STRINGS_SIZE: usize = 32;
+const STRUCT_SIZE: usize = 36;
+const RESERVATIONS_OFFSET: usize = 16;
+
+/// The number of cells in an address when a node does not say.
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+
+/// The number of cells in a size when a node does not say.
+const DEFAULT_SIZE_CELLS: u32 = 1;
+
+/// The longest path that is resolved.
+const MAX_PATH: usize = 256;
+
+/// A processor described by the `cpus` node.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cpu {
+  /// The hardware ID of the processor, given by the first address of its
+  /// `reg` property, such as its MPIDR affinity on AArch64.
+  pub id: u64,
+
+  /// Whether the processor is available to be started.
+  pub enabled: bool,
+}
+
+/// The offsets of a property of a node within the tree.
+#[derive(Clone, Copy)]
+struct RawProperty {
+  /// The offset of the property's name in the strings block.
+  name: u32,
+
+  /// The offset of the property's value.
+  value: usize,
+
+  /// The length of the property's value.
+  len: usize,
+
+  /// The offset of the token following the property.
+  next: usize,
+}
+
+/// A flattened device tree, held in `T`, a buffer that it may grow within
+/// when the tree is mutable.
+#[derive(Clone, Copy)]
+pub struct DeviceTree<T> {
+  data: T,
+}
+
+impl<'a> DeviceTree<&'a [u8]> {
+  /// Constructs a read-only [`DeviceTree`] over the tree held in `data`.
+  ///
+  /// Fails with [`Error::Corrupted`] if `data` does not hold a well-formed
+  /// device tree of a supported version.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the device tree, which may extend past it
+  pub fn parse(data: &'a [u8]) -> Result<Self> {
+    validate(data)?;
+    let size = read_u32(data, TOTAL_SIZE) as usize;
+    Ok(Self {
+      data: &data[..size],
+    })
+  }
+
+  /// Returns the size of the tree whose header is `header`, so that the rest
+  /// of a tree found in memory can be read, failing with
+  /// [`Error::Corrupted`] if it is not a device tree header.
+  ///
+  /// # Arguments
+  ///
+  /// * `header` - the first [`HEADER_SIZE`] bytes of the tree
+  pub fn size_of(header: &[u8]) -> Result<usize> {
+    if header.len() < HEADER_SIZE || read_u32(header, 0) != MAGIC {
+      return Err(Error::Corrupted);
+    }
+    Ok(read_u32(header, TOTAL_SIZE) as usize)
+  }
+}
+
+impl<T: AsRef<[u8]>> DeviceTree<T> {
+  /// Returns the contents of the tree.
+  pub fn as_bytes(&self) -> &[u8] {
+    &self.bytes()[..self.field(TOTAL_SIZE)]
+  }
+
+  /// Returns the version of the tree's format.
+  pub fn version(&self) -> u32 {
+    self.field(20) as u32
+  }
+
+  /// Returns the physical ID of the processor that booted, from version 2
+  /// of the format on.
+  pub fn boot_cpu_id(&self) -> u32 {
+    self.field(28) as u32
+  }
+
+  /// Returns the root node.
+  pub fn root(&self) -> Result<Node<'_>> {
+    let tree = self.view();
+    Ok(Node::new(tree, tree.root_offset()?, Node::ROOT_CONTEXT))
+  }
+
+  /// Returns the node at `path`, or [`None`] if there is none.
+  ///
+  /// Paths that do not start with `/` start with an alias, and names without
+  /// a unit address match nodes with any unit address.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the node
+  pub fn find_node(&self, path: &str) -> Result<Option<Node<'_>>> {
+    let root = self.root()?;
+    let (mut node, rest) = match path.strip_prefix('/') {
+      Some(rest) => (root, rest),
+      None => {
+        let (alias, rest) = path.split_once('/').unwrap_or((path, ""));
+        let Some(aliases) = root.subnode("aliases")? else {
+          return Ok(None);
+        };
+        let Some(target) = aliases.property(alias)? else {
+          return Ok(None);
+        };
+        let target = target.as_str()?;
+        if !target.starts_with('/') || target.len() > MAX_PATH {
+          return Ok(None);
+        }
+        match self.find_node(target)? {
+          Some(node) => (node, rest),
+          None => return Ok(None),
+        }
+      }
+    };
+    for name in rest.split('/').filter(|name| !name.is_empty()) {
+      node = match node.subnode(name)? {
+        Some(node) => node,
+        None => return Ok(None),
+      };
+    }
+    Ok(Some(node))
+  }
+
+  /// Returns the node with the phandle `phandle`, or [`None`] if there is
+  /// none.
+  ///
+  /// # Arguments
+  ///
+  /// * `phandle` - the phandle of the node
+  pub fn find_phandle(&self, phandle: u32) -> Result<Option<Node<'_>>> {
+    self.root()?.find_phandle(phandle)
+  }
+
+  /// Returns an iterator over the memory reservations, the ranges of
+  /// physical memory that the operating system must not use.
+  pub fn reservations(&self) -> Reservations<'_> {
+    let start = self.field(RESERVATIONS_OFFSET);
+    let end = self.field(STRUCT_OFFSET);
+    Reservations {
+      entries: self.bytes().get(start..end).unwrap_or_default(),
+    }
+  }
+
+  /// Returns an iterator over the processors described by the `cpus` node.
+  ///
+  /// Malformed parts of the tree end the iteration early.
+  pub fn cpus(&self) -> impl Iterator<Item = Cpu> + '_ {
+    let cpus = self
+      .root_offset()
+      .ok()
+      .and_then(|root| self.subnode(root, b"cpus").ok().flatten());
+    let cells = cpus
+      .and_then(|cpus| self.property(cpus, b"#address-cells").ok().flatten())
+      .and_then(|cells| read_cell(cells).ok())
+      .unwrap_or(DEFAULT_ADDRESS_CELLS) as usize;
+    let mut at = cpus.and_then(|cpus| self.after_properties(cpus).ok());
+    core::iter::from_fn(move || loop {
+      let node = self.node_at(at?).ok()??;
+      at = self.skip_node(node).ok();
+      // Other subnodes, such as `cpu-map`, describe the topology.
+      let device_type = self.property(node, b"device_type").ok()?;
+      if device_type.and_then(|value| c_str(value).ok()) != Some("cpu") {
+        continue;
+      }
+      let reg = self.property(node, b"reg").ok()??;
+      let id = reg
+        .get(..cells * 4)?
+        .chunks_exact(4)
+        .fold(0, |id, cell| id << 32 | read_u32(cell, 0) as u64);
+      let status = self.property(node, b"status").ok()?;
+      let enabled = status
+        .and_then(|value| c_str(value).ok())
+        .map_or(true, |status| status == "okay" || status == "ok");
+      return Some(Cpu { id, enabled });
+    })
+  }
+
+  /// Returns a read-only view of the tree.
+  fn view(&self) -> DeviceTree<&[u8]> {
+    DeviceTree { data: self.bytes() }
+  }
+
+  /// Returns the contents of the tree.
+  fn bytes(&self) -> &[u8] {
+    self.data.as_ref()
+  }
+
+  /// Returns the offset of the root node.
+  fn root_offset(&self) -> Result<usize> {
+    self
+      .node_at(self.field(STRUCT_OFFSET))?
+      .ok_or(Error::Corrupted)
+  }
+
+  /// Returns the offset of the node at `path`, or [`None`] if there is none.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the node
+  fn path_node(&self, path: &str) -> Result<Option<usize>> {
+    Ok(self.find_node(path)?.map(|node| node.offset()))
+  }
+
+  /// Returns the offset of the node under `node` with the phandle `phandle`,
+  /// including `node` itself, or [`None`] if there is none.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node to search under
+  /// * `phandle` - the phandle of the node to find
+  fn phandle_node(&self, node: usize, phandle: u32) -> Result<Option<usize>> {
+    if self.phandle(node)? == Some(phandle) {
+      return Ok(Some(node));
+    }
+    let mut at = self.after_properties(node)?;
+    while let Some(child) = self.node_at(at)? {
+      if let Some(found) = self.phandle_node(child, phandle)? {
+        return Ok(Some(found));
+      }
+      at = self.skip_node(child)?;
+    }
+    Ok(None)
+  }
+
+  /// Returns the greatest phandle under `node`, including `node` itself, or
+  /// `0` if there is none.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node to search under
+  fn max_phandle(&self, node: usize) -> Result<u32> {
+    let mut max = self.phandle(node)?.unwrap_or(0);
+    let mut at = self.after_properties(node)?;
+    while let Some(child) = self.node_at(at)? {
+      max = max.max(self.max_phandle(child)?);
+      at = self.skip_node(child)?;
+    }
+    Ok(max)
+  }
+
+  /// Returns the phandle of the node `node`, if it has one.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node
+  fn phandle(&self, node: usize) -> Result<Option<u32>> {
+    let mut at = self.after_name(node)?;
+    while let Some(property) = self.property_at(at)? {
+      at = property.next;
+      if is_phandle(self.string(property.name)?) && property.len == 4 {
+        return Ok(Some(read_u32(self.bytes(), property.value)));
+      }
+    }
+    Ok(None)
+  }
+
+  /// Returns the offset of the subnode `name` of the node `node`, or
+  /// [`None`] if there is none.
+  ///
+  /// Names without a unit address match subnodes with any unit address.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node to look in
+  /// * `name` - the name of the subnode
+  fn subnode(&self, node: usize, name: &[u8]) -> Result<Option<usize>> {
+    let mut at = self.after_properties(node)?;
+    while let Some(child) = self.node_at(at)? {
+      let child_name = self.name(child)?;
+      let matches = child_name == name
+        || !name.contains(&b'@')
+          && child_name.split(|&c| c == b'@').next() == Some(name);
+      if matches {
+        return Ok(Some(child));
+      }
+      at = self.skip_node(child)?;
+    }
+    Ok(None)
+  }
+
+  /// Returns the value of the property `name` of the node `node`, if it has
+  /// one.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node
+  /// * `name` - the name of the property
+  fn property(&self, node: usize, name: &[u8]) -> Result<Option<&[u8]>> {
+    Ok(
+      self
+        .find_property(node, name)?
+        .map(|property| &self.bytes()[property.value..][..property.len]),
+    )
+  }
+
+  /// Returns the property `name` of the node `node`, if it has one.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node
+  /// * `name` - the name of the property
+  fn find_property(
+    &self,
+    node: usize,
+    name: &[u8],
+  ) -> Result<Option<RawProperty>> {
+    let mut at = self.after_name(node)?;
+    while let Some(property) = self.property_at(at)? {
+      if self.string(property.name)? == name {
+        return Ok(Some(property));
+      }
+      at = property.next;
+    }
+    Ok(None)
+  }
+
+  /// Returns the name of the node `node`.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node
+  fn name(&self, node: usize) -> Result<&[u8]> {
+    let name = self.bytes().get(node + 4..).ok_or(Error::Corrupted)?;
+    let end = name.iter().position(|&c| c == 0).ok_or(Error::Corrupted)?;
+    Ok(&name[..end])
+  }
+
+  /// Returns the offset of the first token after the name of the node
+  /// `node`.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node
+  fn after_name(&self, node: usize) -> Result<usize> {
+    Ok(node + 4 + align(self.name(node)?.len() + 1))
+  }
+
+  /// Returns the offset of the first token after the properties of the node
+  /// `node`.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node
+  fn after_properties(&self, node: usize) -> Result<usize> {
+    let mut at = self.after_name(node)?;
+    while let Some(property) = self.property_at(at)? {
+      at = property.next;
+    }
+    Ok(at)
+  }
+
+  /// Returns the offset of the token after the end of the node `node`.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node
+  fn skip_node(&self, node: usize) -> Result<usize> {
+    let mut at = self.after_properties(node)?;
+    while let Some(child) = self.node_at(at)? {
+      at = self.skip_node(child)?;
+    }
+    let at = self.skip_nops(at)?;
+    if self.token(at)? != END_NODE {
+      return Err(Error::Corrupted);
+    }
+    Ok(at + 4)
+  }
+
+  /// Returns the property at `at`, skipping any [`NOP`] tokens, or [`None`]
+  /// if the next token is not a property.
+  ///
+  /// # Arguments
+  ///
+  /// * `at` - the offset of the token
+  fn property_at(&self, at: usize) -> Result<Option<RawProperty>> {
+    let at = self.skip_nops(at)?;
+    if self.token(at)? != PROP {
+      return Ok(None);
+    }
+    let len = self.token(at + 4)? as usize;
+    let value = at + 12;
+    let next = value
+      .checked_add(align(len))
+      .filter(|&next| next <= self.struct_end())
+      .ok_or(Error::Corrupted)?;
+    Ok(Some(RawProperty {
+      name: self.token(at + 8)?,
+      value,
+      len,
+      next,
+    }))
+  }
+
+  /// Returns the offset of the node at `at`, skipping any [`NOP`] tokens, or
+  /// [`None`] if the next token does not start a node.
+  ///
+  /// # Arguments
+  ///
+  /// * `at` - the offset of the token
+  fn node_at(&self, at: usize) -> Result<Option<usize>> {
+    let at = self.skip_nops(at)?;
+    Ok(Some(at).filter(|_| self.token(at).ok() == Some(BEGIN_NODE)))
+  }
+
+  /// Returns the offset of the first token at or after `at` that is not a
+  /// [`NOP`].
+  ///
+  /// # Arguments
+  ///
+  /// * `at` - the offset of the token
+  fn skip_nops(&self, mut at: usize) -> Result<usize> {
+    while self.token(at)? == NOP {
+      at += 4;
+    }
+    Ok(at)
+  }
+
+  /// Returns the big-endian word of the structure block at `at`.
+  ///
+  /// # Arguments
+  ///
+  /// * `at` - the offset of the word
+  fn token(&self, at: usize) -> Result<u32> {
+    if at + 4 > self.struct_end() || at < self.field(STRUCT_OFFSET) {
+      return Err(Error::Corrupted);
+    }
+    Ok(read_u32(self.bytes(), at))
+  }
+
+  /// Returns the string at `offset` in the strings block.
+  ///
+  /// # Arguments
+  ///
+  /// * `offset` - the offset of the string
+  fn string(&self, offset: u32) -> Result<&[u8]> {
+    let start = self.field(STRINGS_OFFSET);
+    let strings = &self.bytes()[start..start + self.field(STRINGS_SIZE)];
+    let string = strings.get(offset as usize..).ok_or(Error::Corrupted)?;
+    let end = string
+      .iter()
+      .position(|&c| c == 0)
+      .ok_or(Error::Corrupted)?;
+    Ok(&string[..end])
+  }
+
+  /// Returns the offset of the end of the structure block.
+  fn struct_end(&self) -> usize {
+    self.field(STRUCT_OFFSET) + self.field(STRUCT_SIZE)
+  }
+
+  /// Returns the header field at `offset`.
+  ///
+  /// # Arguments
+  ///
+  /// * `offset` - the offset of the field
+  fn field(&self, offset: usize) -> usize {
+    read_u32(self.bytes(), offset) as usize
+  }
+}
+
+/// An iterator over the memory reservations of a device tree, which ends at
+/// the entry of zeros that terminates them.
+#[derive(Clone)]
+pub struct Reservations<'a> {
+  entries: &'a [u8],
+}
+
+impl Iterator for Reservations<'_> {
+  type Item = AddressRange;
+
+  fn next(&mut self) -> Option<AddressRange> {
+    let entry = self.entries.get(..16)?;
+    let range = AddressRange {
+      address: read_u64(entry, 0),
+      size: read_u64(entry, 8),
+    };
+    if range.address == 0 && range.size == 0 {
+      self.entries = &[];
+      return None;
+    }
+    self.entries = &self.entries[16..];
+    Some(range)
+  }
+}
+
+impl FusedIterator for Reservations<'_> {}
+
+/// Checks that `data` holds a well-formed device tree of a supported version,
+/// whose blocks lie within it.
+///
+/// Fails with [`Error::Corrupted`] otherwise.
+///
+/// # Arguments
+///
+/// * `data` - the device tree
+fn validate(data: &[u8]) -> Result<()> {
+  if data.len() < HEADER_SIZE || read_u32(data, 0) != MAGIC {
+    return Err(Error::Corrupted);
+  }
+  let field = |offset| read_u32(data, offset) as usize;
+  let total = field(TOTAL_SIZE);
+  let in_bounds = |offset: usize, size: usize| {
+    offset >= HEADER_SIZE
+      && offset.checked_add(size).is_some_and(|end| end <= total)
+  };
+  let valid = total <= data.len()
+    && read_u32(data, 20) >= MIN_VERSION
+    && in_bounds(field(STRUCT_OFFSET), field(STRUCT_SIZE))
+    && in_bounds(field(STRINGS_OFFSET), field(STRINGS_SIZE))
+    && field(STRUCT_OFFSET) % 4 == 0
+    && field(STRUCT_SIZE) % 4 == 0;
+  if !valid {
+    return Err(Error::Corrupted);
+  }
+  Ok(())
+}
+
+/// Returns `true` if the property `name` holds the phandle of its node.
+///
+/// # Arguments
+///
+/// * `name` - the name of the property
+fn is_phandle(name: &[u8]) -> bool {
+  name == b"phandle" || name == b"linux,phandle"
+}
+
+/// Returns the single cell that the property `value` holds.
+///
+/// # Arguments
+///
+/// * `value` - the value of the property
+fn read_cell(value: &[u8]) -> Result<u32> {
+  if value.len() != 4 {
+    return Err(Error::Corrupted);
+  }
+  Ok(read_u32(value, 0))
+}
+
+/// Returns the string that the property `value` holds.
+///
+/// # Arguments
+///
+/// * `value` - the value of the property
+fn c_str(value: &[u8]) -> Result<&str> {
+  let end = value.iter().position(|&c| c == 0).unwrap_or(value.len());
+  core::str::from_utf8(&value[..end]).map_err(|_| Error::Corrupted)
+}
+
+fn align(len: usize) -> usize {
+  (len + 3) & !3
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+  let mut bytes = [0; 4];
+  bytes.copy_from_slice(&data[offset..offset + 4]);
+  u32::from_be_bytes(bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+  let mut bytes = [0; 8];
+  bytes.copy_from_slice(&data[offset..offset + 8]);
+  u64::from_be_bytes(bytes)
+}
+
+fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+  data[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::vec::Vec;
+
+  /// Builds device trees for the tests.
+  #[derive(Default)]
+  struct Builder {
+    reservations: Vec<u8>,
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+  }
+
+  impl Builder {
+    fn reserve(mut self, address: u64, size: u64) -> Self {
+      self.reservations.extend_from_slice(&address.to_be_bytes());
+      self.reservations.extend_from_slice(&size.to_be_bytes());
+      self
+    }
+
+    fn begin(mut self, name: &str) -> Self {
+      self.token(BEGIN_NODE);
+      self.structure.extend_from_slice(name.as_bytes());
+      self.structure.push(0);
+      self.pad();
+      self
+    }
+
+    fn end(mut self) -> Self {
+      self.token(END_NODE);
+      self
+    }
+
+    fn property(mut self, name: &str, value: &[u8]) -> Self {
+      let offset = self.strings.len() as u32;
+      self.strings.extend_from_slice(name.as_bytes());
+      self.strings.push(0);
+      self.token(PROP);
+      self.token(value.len() as u32);
+      self.token(offset);
+      self.structure.extend_from_slice(value);
+      self.pad();
+      self
+    }
+
+    fn cells(self, name: &str, cells: &[u32]) -> Self {
+      let value: Vec<u8> =
+        cells.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+      self.property(name, &value)
+    }
+
+    fn string(self, name: &str, value: &str) -> Self {
+      let mut bytes = value.as_bytes().to_vec();
+      bytes.push(0);
+      self.property(name, &bytes)
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+      self.token(9);
+      let reservations = HEADER_SIZE;
+      let structure = reservations + self.reservations.len() + 16;
+      let strings = structure + self.structure.len();
+      let total = strings + self.strings.len();
+      let mut data = std::vec![0; total];
+      for (offset, value) in [
+        (0, MAGIC as usize),
+        (TOTAL_SIZE, total),
+        (STRUCT_OFFSET, structure),
+        (STRINGS_OFFSET, strings),
+        (RESERVATIONS_OFFSET, reservations),
+        (20, 17),
+        (24, 16),
+        (28, 3),
+        (STRINGS_SIZE, self.strings.len()),
+        (STRUCT_SIZE, self.structure.len()),
+      ] {
+        write_u32(&mut data, offset, value as u32);
+      }
+      data[reservations..][..self.reservations.len()]
+        .copy_from_slice(&self.reservations);
+      data[structure..strings].copy_from_slice(&self.structure);
+      data[strings..].copy_from_slice(&self.strings);
+      data
+    }
+
+    fn token(&mut self, value: u32) {
+      self.structure.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn pad(&mut self) {
+      self.structure.resize(align(self.structure.len()), 0);
+    }
+  }
+
+  fn tree() -> Vec<u8> {
+    Builder::default()
+      .reserve(0x8000_0000, 0x1000)
+      .begin("")
+      .cells("#address-cells", &[1])
+      .cells("#size-cells", &[1])
+      .cells("interrupt-parent", &[1])
+      .begin("aliases")
+      .string("serial0", "/soc/uart@1000")
+      .end()
+      .begin("cpus")
+      .cells("#address-cells", &[1])
+      .cells("#size-cells", &[0])
+      .begin("cpu@0")
+      .string("device_type", "cpu")
+      .cells("reg", &[0])
+      .end()
+      .begin("cpu@1")
+      .string("device_type", "cpu")
+      .cells("reg", &[1])
+      .string("status", "disabled")
+      .end()
+      .end()
+      .begin("intc@8000")
+      .property("compatible", b"arm,gic-400\0arm,cortex-a15-gic\0")
+      .cells("#interrupt-cells", &[3])
+      .cells("phandle", &[1])
+      .cells("reg", &[0x8000, 0x1000])
+      .end()
+      .begin("soc")
+      .cells("#address-cells", &[1])
+      .cells("#size-cells", &[1])
+      .cells("ranges", &[0, 0x4000_0000, 0x10_0000])
+      .begin("uart@1000")
+      .string("compatible", "arm,pl011")
+      .cells("reg", &[0x1000, 0x100])
+      .cells("interrupts", &[0, 33, 4])
+      .end()
+      .end()
+      .end()
+      .finish()
+  }
+
+  #[test]
+  fn parse_rejects_other_data() {
+    let mut data = tree();
+    data[0] = 0;
+
+    assert_eq!(DeviceTree::parse(&data).err(), Some(Error::Corrupted));
+    assert_eq!(DeviceTree::size_of(&data).err(), Some(Error::Corrupted));
+  }
+
+  #[test]
+  fn header_is_read() {
+    let data = tree();
+    let tree = DeviceTree::parse(&data).unwrap();
+
+    assert_eq!(DeviceTree::size_of(&data), Ok(data.len()));
+    assert_eq!(tree.version(), 17);
+    assert_eq!(tree.boot_cpu_id(), 3);
+    assert_eq!(tree.as_bytes(), &data[..]);
+  }
+
+  #[test]
+  fn children_and_properties_are_listed() {
+    let data = tree();
+    let tree = DeviceTree::parse(&data).unwrap();
+    let root = tree.root().unwrap();
+
+    let names: Vec<_> = root.children().map(|n| n.name().unwrap()).collect();
+    assert_eq!(names, ["aliases", "cpus", "intc@8000", "soc"]);
+    let names: Vec<_> = root.properties().map(|p| p.name).collect();
+    assert_eq!(names, ["#address-cells", "#size-cells", "interrupt-parent"]);
+    assert_eq!(root.name(), Ok(""));
+  }
+
+  #[test]
+  fn find_node_follows_paths_and_aliases() {
+    let data = tree();
+    let tree = DeviceTree::parse(&data).unwrap();
+
+    let uart = tree.find_node("/soc/uart").unwrap().unwrap();
+    assert_eq!(uart.name(), Ok("uart@1000"));
+    assert_eq!(uart.unit_address(), Ok(Some("1000")));
+    let alias = tree.find_node("serial0").unwrap().unwrap();
+    assert_eq!(alias.offset(), uart.offset());
+    assert!(tree.find_node("serial0/missing").unwrap().is_none());
+    assert!(tree.find_node("/missing").unwrap().is_none());
+    assert!(tree.find_node("serial1").unwrap().is_none());
+  }
+
+  #[test]
+  fn property_values_are_decoded() {
+    let data = tree();
+    let tree = DeviceTree::parse(&data).unwrap();
+    let intc = tree.find_node("/intc").unwrap().unwrap();
+
+    let compatible = intc.property("compatible").unwrap().unwrap();
+    let models: Vec<_> = compatible.strings().collect();
+    assert_eq!(models, ["arm,gic-400", "arm,cortex-a15-gic"]);
+    assert_eq!(compatible.as_str(), Ok("arm,gic-400"));
+    assert!(intc.is_compatible("arm,cortex-a15-gic"));
+    assert!(!intc.is_compatible("arm,pl011"));
+    let reg = intc.property("reg").unwrap().unwrap();
+    assert_eq!(reg.as_u64(), Ok(0x8000_0000_1000));
+    assert_eq!(reg.as_u32(), Err(Error::Corrupted));
+    assert_eq!(reg.cells().collect::<Vec<_>>(), [0x8000, 0x1000]);
+    assert!(intc.property("missing").unwrap().is_none());
+  }
+
+  #[test]
+  fn reg_and_ranges_use_the_parents_cells() {
+    let data = tree();
+    let tree = DeviceTree::parse(&data).unwrap();
+    let soc = tree.find_node("/soc").unwrap().unwrap();
+    let uart = soc.subnode("uart@1000").unwrap().unwrap();
+
+    let reg: Vec<_> = uart.reg().unwrap().collect();
+    assert_eq!(
+      reg,
+      [AddressRange {
+        address: 0x1000,
+        size: 0x100
+      }]
+    );
+    let ranges: Vec<_> = soc.ranges().unwrap().collect();
+    assert_eq!(
+      ranges,
+      [Translation {
+        child_address: 0,
+        parent_address: 0x4000_0000,
+        size: 0x10_0000,
+      }]
+    );
+    assert_eq!(soc.reg().unwrap().count(), 0);
+    assert_eq!(uart.ranges().unwrap().count(), 0);
+  }
+
+  #[test]
+  fn interrupts_use_the_inherited_interrupt_parent() {
+    let data = tree();
+    let tree = DeviceTree::parse(&data).unwrap();
+    let uart = tree.find_node("serial0").unwrap().unwrap();
+
+    let parent = uart.interrupt_parent().unwrap().unwrap();
+    assert_eq!(parent.name(), Ok("intc@8000"));
+    let interrupts: Vec<Vec<u32>> =
+      uart.interrupts().unwrap().map(|i| i.collect()).collect();
+    assert_eq!(interrupts, [[0, 33, 4]]);
+    assert_eq!(parent.interrupts().unwrap().len(), 0);
+  }
+
+  #[test]
+  fn find_phandle_finds_the_node() {
+    let data = tree();
+    let tree = DeviceTree::parse(&data).unwrap();
+
+    let intc = tree.find_phandle(1).unwrap().unwrap();
+    assert_eq!(intc.name(), Ok("intc@8000"));
+    assert_eq!(intc.phandle(), Ok(Some(1)));
+    assert!(tree.find_phandle(2).unwrap().is_none());
+  }
+
+  #[test]
+  fn reservations_and_cpus_are_listed() {
+    let data = tree();
+    let tree = DeviceTree::parse(&data).unwrap();
+
+    let reservations: Vec<_> = tree.reservations().collect();
+    assert_eq!(
+      reservations,
+      [AddressRange {
+        address: 0x8000_0000,
+        size: 0x1000
+      }]
+    );
+    let cpus: Vec<_> = tree.cpus().collect();
+    assert_eq!(
+      cpus,
+      [
+        Cpu {
+          id: 0,
+          enabled: true
+        },
+        Cpu {
+          id: 1,
+          enabled: false
+        }
+      ]
+    );
+  }
+
+  #[test]
+  fn apply_merges_overlays() {
+    let data = tree();
+    let mut overlay = Builder::default()
+      .begin("")
+      .begin("fragment@0")
+      .string("target-path", "/soc")
+      .begin("__overlay__")
+      .string("status", "disabled")
+      .begin("timer@2000")
+      .cells("reg", &[0x2000, 0x10])
+      .end()
+      .end()
+      .end()
+      .end()
+      .finish();
+    let mut buffer = std::vec![0; data.len() + 256];
+    let mut tree = DeviceTree::copy(&data, &mut buffer).unwrap();
+
+    let mut overlay = DeviceTree::new(&mut overlay).unwrap();
+    tree.apply(&mut overlay).unwrap();
+
+    let tree = DeviceTree::parse(tree.into_bytes()).unwrap();
+    let soc = tree.find_node("/soc").unwrap().unwrap();
+    assert!(!soc.is_enabled());
+    let timer = soc.subnode("timer").unwrap().unwrap();
+    assert_eq!(timer.reg().unwrap().next().map(|r| r.address), Some(0x2000));
+    assert!(tree.find_node("serial0").unwrap().unwrap().is_enabled());
+  }
+}
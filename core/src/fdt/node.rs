@@ -0,0 +1,603 @@
+//! This module provides [`Node`], a node of a device tree, with its
+//! [`Property`]s and subnodes, and the decoding of the properties whose
+//! layout its parents declare.
+
+use super::{
+  c_str, read_cell, read_u32, DeviceTree, DEFAULT_ADDRESS_CELLS,
+  DEFAULT_SIZE_CELLS, STRINGS_OFFSET,
+};
+use crate::error::{Error, Result};
+use core::iter::FusedIterator;
+
+/// The most cells of an address or size that are decoded, such as the three
+/// cells of PCI addresses.
+const MAX_CELLS: u32 = 4;
+
+/// What a node inherits from its parent to decode its properties.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(super) struct Context {
+  /// The number of cells of the addresses of the node's `reg` property.
+  address_cells: u32,
+
+  /// The number of cells of the sizes of the node's `reg` property.
+  size_cells: u32,
+
+  /// The phandle of the interrupt controller that the node's interrupts are
+  /// delivered to, unless it names another.
+  interrupt_parent: Option<u32>,
+}
+
+/// A node of a device tree.
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+  tree: DeviceTree<&'a [u8]>,
+  offset: usize,
+  parent: Context,
+}
+
+impl<'a> Node<'a> {
+  /// What the root node inherits, which are the defaults of the format.
+  pub(super) const ROOT_CONTEXT: Context = Context {
+    address_cells: DEFAULT_ADDRESS_CELLS,
+    size_cells: DEFAULT_SIZE_CELLS,
+    interrupt_parent: None,
+  };
+
+  /// Constructs the node at `offset` of `tree`.
+  ///
+  /// # Arguments
+  ///
+  /// * `tree` - the tree holding the node
+  /// * `offset` - the offset of the node's token
+  /// * `parent` - what the node inherits from its parent
+  pub(super) fn new(
+    tree: DeviceTree<&'a [u8]>,
+    offset: usize,
+    parent: Context,
+  ) -> Self {
+    Self {
+      tree,
+      offset,
+      parent,
+    }
+  }
+
+  /// Returns the offset of the node's token.
+  pub(super) fn offset(&self) -> usize {
+    self.offset
+  }
+
+  /// Returns the name of the node, including its unit address, such as
+  /// `memory@80000000`.
+  pub fn name(&self) -> Result<&'a str> {
+    let len = self.tree.name(self.offset)?.len();
+    let name = &self.tree.data[self.offset + 4..][..len];
+    core::str::from_utf8(name).map_err(|_| Error::Corrupted)
+  }
+
+  /// Returns the unit address of the node's name, the part after its `@`.
+  pub fn unit_address(&self) -> Result<Option<&'a str>> {
+    Ok(self.name()?.split_once('@').map(|(_, address)| address))
+  }
+
+  /// Returns an iterator over the node's properties, which ends early at
+  /// malformed parts of the tree.
+  pub fn properties(&self) -> Properties<'a> {
+    Properties {
+      tree: self.tree,
+      at: self.tree.after_name(self.offset).ok(),
+    }
+  }
+
+  /// Returns the property `name` of the node, or [`None`] if it has none.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - the name of the property
+  pub fn property(&self, name: &str) -> Result<Option<Property<'a>>> {
+    let Some(raw) = self.tree.find_property(self.offset, name.as_bytes())?
+    else {
+      return Ok(None);
+    };
+    Ok(Some(Property {
+      name: self.tree.string_str(raw.name)?,
+      value: &self.tree.data[raw.value..][..raw.len],
+    }))
+  }
+
+  /// Returns an iterator over the node's subnodes, which ends early at
+  /// malformed parts of the tree.
+  pub fn children(&self) -> Children<'a> {
+    let context = self.child_context();
+    Children {
+      tree: self.tree,
+      at: context
+        .is_ok()
+        .then(|| self.tree.after_properties(self.offset).ok())
+        .flatten(),
+      context: context.unwrap_or(Self::ROOT_CONTEXT),
+    }
+  }
+
+  /// Returns the subnode `name` of the node, or [`None`] if there is none.
+  ///
+  /// Names without a unit address match subnodes with any unit address.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - the name of the subnode
+  pub fn subnode(&self, name: &str) -> Result<Option<Node<'a>>> {
+    let Some(child) = self.tree.subnode(self.offset, name.as_bytes())? else {
+      return Ok(None);
+    };
+    Ok(Some(Node::new(self.tree, child, self.child_context()?)))
+  }
+
+  /// Returns the node's phandle, by which other nodes refer to it, if it has
+  /// one.
+  pub fn phandle(&self) -> Result<Option<u32>> {
+    self.tree.phandle(self.offset)
+  }
+
+  /// Returns the node or the descendant of it with the phandle `phandle`, or
+  /// [`None`] if there is none.
+  ///
+  /// # Arguments
+  ///
+  /// * `phandle` - the phandle of the node to find
+  pub fn find_phandle(&self, phandle: u32) -> Result<Option<Node<'a>>> {
+    if self.phandle()? == Some(phandle) {
+      return Ok(Some(*self));
+    }
+    let context = self.child_context()?;
+    let mut at = self.tree.after_properties(self.offset)?;
+    while let Some(child) = self.tree.node_at(at)? {
+      let child = Node::new(self.tree, child, context);
+      if let Some(found) = child.find_phandle(phandle)? {
+        return Ok(Some(found));
+      }
+      at = self.tree.skip_node(child.offset)?;
+    }
+    Ok(None)
+  }
+
+  /// Returns whether the node's `compatible` property lists `compatible`.
+  ///
+  /// # Arguments
+  ///
+  /// * `compatible` - the model to look for, such as `arm,pl011`
+  pub fn is_compatible(&self, compatible: &str) -> bool {
+    let property = self.property("compatible").ok().flatten();
+    property.is_some_and(|property| {
+      property.strings().any(|model| model == compatible)
+    })
+  }
+
+  /// Returns whether the node's device is enabled, which it is unless its
+  /// `status` property says otherwise.
+  pub fn is_enabled(&self) -> bool {
+    let status = self.property("status").ok().flatten();
+    status
+      .and_then(|status| status.as_str().ok())
+      .map_or(true, |status| status == "okay" || status == "ok")
+  }
+
+  /// Returns the number of cells of the addresses of the subnodes' `reg`
+  /// properties, and of the child addresses of the node's `ranges`.
+  pub fn address_cells(&self) -> Result<u32> {
+    self.cells("#address-cells", DEFAULT_ADDRESS_CELLS)
+  }
+
+  /// Returns the number of cells of the sizes of the subnodes' `reg`
+  /// properties, and of the node's `ranges`.
+  pub fn size_cells(&self) -> Result<u32> {
+    self.cells("#size-cells", DEFAULT_SIZE_CELLS)
+  }
+
+  /// Returns an iterator over the address ranges of the node's `reg`
+  /// property, in the address space of its parent, which is empty if it has
+  /// none.
+  ///
+  /// Addresses and sizes of more than two cells keep their last two.
+  pub fn reg(&self) -> Result<Reg<'a>> {
+    let (address, size) = (self.parent.address_cells, self.parent.size_cells);
+    let value = self.value("reg")?;
+    Ok(Reg {
+      entries: Entries::new(value, [address, size, 0])?,
+    })
+  }
+
+  /// Returns an iterator over the translations of the node's `ranges`
+  /// property, from the address space of its subnodes to that of its
+  /// parent.
+  ///
+  /// Empty `ranges`, which translate addresses to themselves, and absent
+  /// ones, where addresses are not translated, both yield no translations.
+  pub fn ranges(&self) -> Result<Ranges<'a>> {
+    let cells = [
+      self.address_cells()?,
+      self.parent.address_cells,
+      self.size_cells()?,
+    ];
+    Ok(Ranges {
+      entries: Entries::new(self.value("ranges")?, cells)?,
+    })
+  }
+
+  /// Returns the interrupt controller that the node's interrupts are
+  /// delivered to, named by its `interrupt-parent` property or that of its
+  /// nearest ancestor with one.
+  pub fn interrupt_parent(&self) -> Result<Option<Node<'a>>> {
+    let Some(phandle) = self.interrupt_phandle()? else {
+      return Ok(None);
+    };
+    let root = self.tree.root_offset()?;
+    Node::new(self.tree, root, Self::ROOT_CONTEXT).find_phandle(phandle)
+  }
+
+  /// Returns an iterator over the specifiers of the node's interrupts, each
+  /// of the number of cells that its interrupt parent declares, which is
+  /// empty if it has none.
+  pub fn interrupts(&self) -> Result<Interrupts<'a>> {
+    let value = self.value("interrupts")?;
+    if value.is_empty() {
+      return Ok(Interrupts {
+        specifiers: [].chunks_exact(4),
+      });
+    }
+    let parent = self.interrupt_parent()?.ok_or(Error::Corrupted)?;
+    let cells = parent.cells("#interrupt-cells", 0)?;
+    if cells == 0
+      || cells > MAX_CELLS
+      || value.len() % (cells as usize * 4) != 0
+    {
+      return Err(Error::Corrupted);
+    }
+    Ok(Interrupts {
+      specifiers: value.chunks_exact(cells as usize * 4),
+    })
+  }
+
+  /// Returns what the node's subnodes inherit from it.
+  fn child_context(&self) -> Result<Context> {
+    Ok(Context {
+      address_cells: self.address_cells()?,
+      size_cells: self.size_cells()?,
+      interrupt_parent: self.interrupt_phandle()?,
+    })
+  }
+
+  /// Returns the phandle of the node's interrupt parent, its own or the one
+  /// it inherits.
+  fn interrupt_phandle(&self) -> Result<Option<u32>> {
+    match self.property("interrupt-parent")? {
+      Some(property) => property.as_u32().map(Some),
+      None => Ok(self.parent.interrupt_parent),
+    }
+  }
+
+  /// Returns the single cell of the property `name`, or `default` if the
+  /// node does not have it.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - the name of the property
+  /// * `default` - the value when the property is absent
+  fn cells(&self, name: &str, default: u32) -> Result<u32> {
+    match self.property(name)? {
+      Some(property) => property.as_u32(),
+      None => Ok(default),
+    }
+  }
+
+  /// Returns the value of the property `name`, which is empty if the node
+  /// does not have it.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - the name of the property
+  fn value(&self, name: &str) -> Result<&'a [u8]> {
+    Ok(
+      self
+        .property(name)?
+        .map_or(&[][..], |property| property.value),
+    )
+  }
+}
+
+impl<'a> DeviceTree<&'a [u8]> {
+  /// Returns the string at `offset` in the strings block, for as long as the
+  /// tree is borrowed.
+  ///
+  /// # Arguments
+  ///
+  /// * `offset` - the offset of the string
+  fn string_str(&self, offset: u32) -> Result<&'a str> {
+    let len = self.string(offset)?.len();
+    let start = self.field(STRINGS_OFFSET) + offset as usize;
+    let string = &self.data[start..][..len];
+    core::str::from_utf8(string).map_err(|_| Error::Corrupted)
+  }
+}
+
+/// A property of a node.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Property<'a> {
+  /// The name of the property.
+  pub name: &'a str,
+
+  /// The value of the property.
+  pub value: &'a [u8],
+}
+
+impl<'a> Property<'a> {
+  /// Returns the value as a single cell, failing with [`Error::Corrupted`] if
+  /// it is not one.
+  pub fn as_u32(&self) -> Result<u32> {
+    read_cell(self.value)
+  }
+
+  /// Returns the value as two cells, failing with [`Error::Corrupted`] if it
+  /// is not.
+  pub fn as_u64(&self) -> Result<u64> {
+    match self.value.len() {
+      8 => Ok(Cells::new(self.value).to_u64()),
+      _ => Err(Error::Corrupted),
+    }
+  }
+
+  /// Returns the value as a string, up to its terminating NUL.
+  pub fn as_str(&self) -> Result<&'a str> {
+    c_str(self.value)
+  }
+
+  /// Returns an iterator over the strings of a value holding a list of them,
+  /// such as `compatible`, skipping any that are not UTF-8.
+  pub fn strings(&self) -> impl Iterator<Item = &'a str> + 'a {
+    let value = self.value.strip_suffix(&[0]).unwrap_or(self.value);
+    value
+      .split(|&c| c == 0)
+      .filter(|_| !value.is_empty())
+      .filter_map(|string| core::str::from_utf8(string).ok())
+  }
+
+  /// Returns the cells of the value.
+  pub fn cells(&self) -> Cells<'a> {
+    Cells::new(self.value)
+  }
+}
+
+/// A sequence of big-endian 32-bit cells, such as an interrupt specifier.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cells<'a> {
+  data: &'a [u8],
+}
+
+impl<'a> Cells<'a> {
+  /// Constructs the cells held in `data`, ignoring any trailing bytes.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the bytes of the cells
+  fn new(data: &'a [u8]) -> Self {
+    Self {
+      data: &data[..data.len() & !3],
+    }
+  }
+
+  /// Returns the cell at `index`, or [`None`] if there are not that many.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the cell
+  pub fn get(&self, index: usize) -> Option<u32> {
+    let offset = index.checked_mul(4)?;
+    self
+      .data
+      .get(offset..offset + 4)
+      .map(|cell| read_u32(cell, 0))
+  }
+
+  /// Returns the cells as one number, keeping the last two if there are
+  /// more.
+  pub fn to_u64(&self) -> u64 {
+    self.fold(0, |value, cell| value << 32 | cell as u64)
+  }
+}
+
+impl Iterator for Cells<'_> {
+  type Item = u32;
+
+  fn next(&mut self) -> Option<u32> {
+    let cell = self.get(0)?;
+    self.data = &self.data[4..];
+    Some(cell)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let len = self.data.len() / 4;
+    (len, Some(len))
+  }
+}
+
+impl ExactSizeIterator for Cells<'_> {}
+
+impl FusedIterator for Cells<'_> {}
+
+/// An iterator over the properties of a node.
+#[derive(Clone)]
+pub struct Properties<'a> {
+  tree: DeviceTree<&'a [u8]>,
+  at: Option<usize>,
+}
+
+impl<'a> Iterator for Properties<'a> {
+  type Item = Property<'a>;
+
+  fn next(&mut self) -> Option<Property<'a>> {
+    let raw = self.tree.property_at(self.at?).ok().flatten();
+    let name = raw.and_then(|raw| self.tree.string_str(raw.name).ok());
+    let (Some(raw), Some(name)) = (raw, name) else {
+      self.at = None;
+      return None;
+    };
+    self.at = Some(raw.next);
+    Some(Property {
+      name,
+      value: &self.tree.data[raw.value..][..raw.len],
+    })
+  }
+}
+
+impl FusedIterator for Properties<'_> {}
+
+/// An iterator over the subnodes of a node.
+#[derive(Clone)]
+pub struct Children<'a> {
+  tree: DeviceTree<&'a [u8]>,
+  at: Option<usize>,
+  context: Context,
+}
+
+impl<'a> Iterator for Children<'a> {
+  type Item = Node<'a>;
+
+  fn next(&mut self) -> Option<Node<'a>> {
+    let Some(node) = self.tree.node_at(self.at?).ok().flatten() else {
+      self.at = None;
+      return None;
+    };
+    self.at = self.tree.skip_node(node).ok();
+    Some(Node::new(self.tree, node, self.context))
+  }
+}
+
+impl FusedIterator for Children<'_> {}
+
+/// A range of addresses, and its size.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AddressRange {
+  /// The first address of the range.
+  pub address: u64,
+
+  /// The size of the range.
+  pub size: u64,
+}
+
+/// A translation of a range of addresses of a node's subnodes to the
+/// address space of the node's parent.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Translation {
+  /// The first address of the range, in the address space of the subnodes.
+  pub child_address: u64,
+
+  /// The first address of the range, in the address space of the parent.
+  pub parent_address: u64,
+
+  /// The size of the range.
+  pub size: u64,
+}
+
+/// The fields of the entries of a property made of several numbers of
+/// cells.
+#[derive(Clone)]
+struct Entries<'a> {
+  entries: core::slice::ChunksExact<'a, u8>,
+  cells: [usize; 3],
+}
+
+impl<'a> Entries<'a> {
+  /// Constructs the entries of `value`, each made of three numbers of
+  /// `cells` cells, failing with [`Error::Corrupted`] if it is not made of
+  /// whole entries.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value of the property
+  /// * `cells` - the number of cells of each field of an entry, which may be
+  ///   zero
+  fn new(value: &'a [u8], cells: [u32; 3]) -> Result<Self> {
+    if cells.iter().any(|&cells| cells > MAX_CELLS) {
+      return Err(Error::Unsupported);
+    }
+    let cells = cells.map(|cells| cells as usize);
+    let size = cells.iter().sum::<usize>() * 4;
+    if size == 0 && !value.is_empty() || size != 0 && value.len() % size != 0 {
+      return Err(Error::Corrupted);
+    }
+    Ok(Self {
+      entries: value.chunks_exact(size.max(4)),
+      cells,
+    })
+  }
+
+  /// Returns the fields of the next entry.
+  fn next(&mut self) -> Option<[u64; 3]> {
+    let mut entry = self.entries.next()?;
+    Some(self.cells.map(|cells| {
+      let (field, rest) = entry.split_at(cells * 4);
+      entry = rest;
+      Cells::new(field).to_u64()
+    }))
+  }
+}
+
+/// An iterator over the address ranges of a `reg` property.
+#[derive(Clone)]
+pub struct Reg<'a> {
+  entries: Entries<'a>,
+}
+
+impl Iterator for Reg<'_> {
+  type Item = AddressRange;
+
+  fn next(&mut self) -> Option<AddressRange> {
+    let [address, size, _] = self.entries.next()?;
+    Some(AddressRange { address, size })
+  }
+}
+
+impl FusedIterator for Reg<'_> {}
+
+/// An iterator over the translations of a `ranges` property.
+#[derive(Clone)]
+pub struct Ranges<'a> {
+  entries: Entries<'a>,
+}
+
+impl Iterator for Ranges<'_> {
+  type Item = Translation;
+
+  fn next(&mut self) -> Option<Translation> {
+    let [child_address, parent_address, size] = self.entries.next()?;
+    Some(Translation {
+      child_address,
+      parent_address,
+      size,
+    })
+  }
+}
+
+impl FusedIterator for Ranges<'_> {}
+
+/// An iterator over the interrupt specifiers of an `interrupts` property.
+#[derive(Clone)]
+pub struct Interrupts<'a> {
+  specifiers: core::slice::ChunksExact<'a, u8>,
+}
+
+impl<'a> Iterator for Interrupts<'a> {
+  type Item = Cells<'a>;
+
+  fn next(&mut self) -> Option<Cells<'a>> {
+    self.specifiers.next().map(Cells::new)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.specifiers.size_hint()
+  }
+}
+
+impl ExactSizeIterator for Interrupts<'_> {}
+
+impl FusedIterator for Interrupts<'_> {}
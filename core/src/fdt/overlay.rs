@@ -0,0 +1,418 @@
+//! This module provides the editing of device trees in place, and the
+//! application of overlays to them.
+
+use super::{
+  align, c_str, is_phandle, read_cell, read_u32, validate, write_u32,
+  DeviceTree, BEGIN_NODE, END_NODE, MAX_PATH, PROP, STRINGS_OFFSET,
+  STRINGS_SIZE, STRUCT_OFFSET, STRUCT_SIZE, TOTAL_SIZE,
+};
+use crate::error::{Error, Result};
+
+impl<'a> DeviceTree<&'a mut [u8]> {
+  /// Constructs a [`DeviceTree`] over the tree held in `data`, which may only
+  /// be edited without changing its size.
+  ///
+  /// Fails with [`Error::Corrupted`] if `data` does not hold a well-formed
+  /// device tree of a supported version.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the device tree
+  pub fn new(data: &'a mut [u8]) -> Result<Self> {
+    validate(data)?;
+    let size = read_u32(data, TOTAL_SIZE) as usize;
+    Ok(Self {
+      data: &mut data[..size],
+    })
+  }
+
+  /// Constructs a [`DeviceTree`] from a copy of the tree `tree`, made in
+  /// `buffer`, which it may grow to fill.
+  ///
+  /// Fails with [`Error::Corrupted`] if `tree` is not a well-formed device
+  /// tree of a supported version, and with [`Error::BufferTooSmall`] if
+  /// `buffer` cannot hold it.
+  ///
+  /// # Arguments
+  ///
+  /// * `tree` - the device tree to copy
+  /// * `buffer` - the buffer to copy it to
+  pub fn copy(tree: &[u8], buffer: &'a mut [u8]) -> Result<Self> {
+    validate(tree)?;
+    let field = |offset| read_u32(tree, offset) as usize;
+    let (struct_offset, struct_size) =
+      (field(STRUCT_OFFSET), field(STRUCT_SIZE));
+    let (strings_offset, strings_size) =
+      (field(STRINGS_OFFSET), field(STRINGS_SIZE));
+    // Everything before the structure block, the header and the memory
+    // reservations, is copied as it is.
+    let end = struct_offset + struct_size + strings_size;
+    if end > buffer.len() {
+      return Err(Error::BufferTooSmall);
+    }
+
+    buffer[..struct_offset].copy_from_slice(&tree[..struct_offset]);
+    buffer[struct_offset..][..struct_size]
+      .copy_from_slice(&tree[struct_offset..][..struct_size]);
+    buffer[struct_offset + struct_size..end]
+      .copy_from_slice(&tree[strings_offset..][..strings_size]);
+    let mut tree = Self { data: buffer };
+    tree.set_field(STRINGS_OFFSET, struct_offset + struct_size);
+    tree.set_field(TOTAL_SIZE, end);
+    Ok(tree)
+  }
+
+  /// Returns the contents of the tree, giving up the room to grow it.
+  pub fn into_bytes(self) -> &'a [u8] {
+    let size = self.field(TOTAL_SIZE);
+    let data: &'a [u8] = self.data;
+    &data[..size]
+  }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> DeviceTree<T> {
+  /// Applies `overlay` to the tree.
+  ///
+  /// The overlay is edited in place to resolve its phandles. Fails with
+  /// [`Error::NotFound`] if the overlay refers to a label or targets a node
+  /// that the tree does not have, with [`Error::Corrupted`] if it is
+  /// malformed, and with [`Error::BufferTooSmall`] if the tree has no
+  /// room left to grow.
+  ///
+  /// # Arguments
+  ///
+  /// * `overlay` - the overlay to apply
+  pub fn apply<U: AsRef<[u8]> + AsMut<[u8]>>(
+    &mut self,
+    overlay: &mut DeviceTree<U>,
+  ) -> Result<()> {
+    let root = self.root_offset()?;
+    let delta = self.max_phandle(root)?;
+    let overlay_root = overlay.root_offset()?;
+    overlay.adjust_phandles(overlay_root, delta)?;
+    if let Some(fixups) = overlay.subnode(overlay_root, b"__local_fixups__")? {
+      overlay.adjust_local_fixups(fixups, overlay_root, delta)?;
+    }
+    if let Some(fixups) = overlay.subnode(overlay_root, b"__fixups__")? {
+      overlay.resolve_fixups(fixups, self)?;
+    }
+
+    let mut at = overlay.after_properties(overlay_root)?;
+    while let Some(fragment) = overlay.node_at(at)? {
+      at = overlay.skip_node(fragment)?;
+      let Some(contents) = overlay.subnode(fragment, b"__overlay__")? else {
+        continue;
+      };
+      let target = match overlay.property(fragment, b"target")? {
+        Some(phandle) => self.phandle_node(root, read_cell(phandle)?)?,
+        None => {
+          let path = overlay
+            .property(fragment, b"target-path")?
+            .ok_or(Error::Corrupted)?;
+          self.path_node(c_str(path)?)?
+        }
+      };
+      self.merge(target.ok_or(Error::NotFound)?, overlay, contents)?;
+    }
+    Ok(())
+  }
+
+  /// Merges the properties and subnodes of the node `source` of `overlay`
+  /// into the node `target`, replacing properties of the same name.
+  ///
+  /// # Arguments
+  ///
+  /// * `target` - the node to merge into
+  /// * `overlay` - the tree holding the node to merge
+  /// * `source` - the node to merge
+  fn merge<U: AsRef<[u8]>>(
+    &mut self,
+    target: usize,
+    overlay: &DeviceTree<U>,
+    source: usize,
+  ) -> Result<()> {
+    let mut at = overlay.after_name(source)?;
+    while let Some(property) = overlay.property_at(at)? {
+      at = property.next;
+      let name = overlay.string(property.name)?;
+      let value = &overlay.bytes()[property.value..][..property.len];
+      self.set_property(target, name, value)?;
+    }
+    while let Some(child) = overlay.node_at(at)? {
+      at = overlay.skip_node(child)?;
+      let name = overlay.name(child)?;
+      let node = match self.subnode(target, name)? {
+        Some(node) => node,
+        None => self.add_subnode(target, name)?,
+      };
+      self.merge(node, overlay, child)?;
+    }
+    Ok(())
+  }
+
+  /// Adds `delta` to the phandles of the node `node` and its descendants.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node to adjust
+  /// * `delta` - the amount to move phandles by
+  fn adjust_phandles(&mut self, node: usize, delta: u32) -> Result<()> {
+    let mut at = self.after_name(node)?;
+    while let Some(property) = self.property_at(at)? {
+      at = property.next;
+      if is_phandle(self.string(property.name)?) && property.len == 4 {
+        let phandle = read_u32(self.bytes(), property.value);
+        if phandle != 0 && phandle != u32::MAX {
+          write_u32(
+            self.bytes_mut(),
+            property.value,
+            phandle.wrapping_add(delta),
+          );
+        }
+      }
+    }
+    while let Some(child) = self.node_at(at)? {
+      at = self.skip_node(child)?;
+      self.adjust_phandles(child, delta)?;
+    }
+    Ok(())
+  }
+
+  /// Adds `delta` to the references to phandles of the overlay itself that
+  /// the node `fixups` of `__local_fixups__` lists for the node `node`, and
+  /// likewise for their subnodes.
+  ///
+  /// # Arguments
+  ///
+  /// * `fixups` - the node listing the references of `node`
+  /// * `node` - the node holding the references
+  /// * `delta` - the amount to move phandles by
+  fn adjust_local_fixups(
+    &mut self,
+    fixups: usize,
+    node: usize,
+    delta: u32,
+  ) -> Result<()> {
+    let mut at = self.after_name(fixups)?;
+    while let Some(fixup) = self.property_at(at)? {
+      at = fixup.next;
+      let name = self.string(fixup.name)?;
+      let target = self.find_property(node, name)?.ok_or(Error::Corrupted)?;
+      for index in 0..fixup.len / 4 {
+        let offset = read_u32(self.bytes(), fixup.value + index * 4) as usize;
+        if offset + 4 > target.len {
+          return Err(Error::Corrupted);
+        }
+        let position = target.value + offset;
+        let phandle = read_u32(self.bytes(), position);
+        write_u32(self.bytes_mut(), position, phandle.wrapping_add(delta));
+      }
+    }
+    while let Some(child) = self.node_at(at)? {
+      at = self.skip_node(child)?;
+      let target = self
+        .subnode(node, self.name(child)?)?
+        .ok_or(Error::Corrupted)?;
+      self.adjust_local_fixups(child, target, delta)?;
+    }
+    Ok(())
+  }
+
+  /// Writes the phandles of the labels of `base` that the node `fixups` of
+  /// `__fixups__` lists, as `label = "path:property:offset", ...`, into the
+  /// references to them.
+  ///
+  /// # Arguments
+  ///
+  /// * `fixups` - the `__fixups__` node
+  /// * `base` - the tree the labels are defined by
+  fn resolve_fixups<U: AsRef<[u8]>>(
+    &mut self,
+    fixups: usize,
+    base: &DeviceTree<U>,
+  ) -> Result<()> {
+    let base_root = base.root_offset()?;
+    let symbols = base
+      .subnode(base_root, b"__symbols__")?
+      .ok_or(Error::NotFound)?;
+    let mut at = self.after_name(fixups)?;
+    while let Some(fixup) = self.property_at(at)? {
+      at = fixup.next;
+      let label = self.string(fixup.name)?;
+      let path = base.property(symbols, label)?.ok_or(Error::NotFound)?;
+      let node = base.path_node(c_str(path)?)?.ok_or(Error::NotFound)?;
+      let phandle = base.phandle(node)?.ok_or(Error::NotFound)?.to_be_bytes();
+
+      let mut offset = 0;
+      while offset < fixup.len {
+        let entry = &self.bytes()[fixup.value + offset..][..fixup.len - offset];
+        let entry = match entry.iter().position(|&c| c == 0) {
+          Some(end) => &entry[..end],
+          None => entry,
+        };
+        offset += entry.len() + 1;
+        let position = self.fixup_position(entry)?;
+        self.bytes_mut()[position..position + 4].copy_from_slice(&phandle);
+      }
+    }
+    Ok(())
+  }
+
+  /// Returns the offset of the reference described by the `__fixups__` entry
+  /// `entry`, of the form `path:property:offset`.
+  ///
+  /// # Arguments
+  ///
+  /// * `entry` - the entry describing the reference
+  fn fixup_position(&self, entry: &[u8]) -> Result<usize> {
+    let entry = core::str::from_utf8(entry).map_err(|_| Error::Corrupted)?;
+    let mut fields = entry.rsplitn(3, ':');
+    let (Some(offset), Some(property), Some(path)) =
+      (fields.next(), fields.next(), fields.next())
+    else {
+      return Err(Error::Corrupted);
+    };
+    let offset = offset.parse::<usize>().map_err(|_| Error::Corrupted)?;
+    let node = self.path_node(path)?.ok_or(Error::Corrupted)?;
+    let property = self
+      .find_property(node, property.as_bytes())?
+      .ok_or(Error::Corrupted)?;
+    if offset + 4 > property.len {
+      return Err(Error::Corrupted);
+    }
+    Ok(property.value + offset)
+  }
+
+  /// Sets the property `name` of the node `node` to `value`, adding it if
+  /// the node does not have it.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node to set the property of
+  /// * `name` - the name of the property
+  /// * `value` - the value of the property
+  fn set_property(
+    &mut self,
+    node: usize,
+    name: &[u8],
+    value: &[u8],
+  ) -> Result<()> {
+    if let Some(property) = self.find_property(node, name)? {
+      self.splice(property.value, align(property.len), align(value.len()))?;
+      write_u32(self.bytes_mut(), property.value - 8, value.len() as u32);
+      self.write_padded(property.value, value);
+      return Ok(());
+    }
+
+    let name = self.add_string(name)?;
+    let at = self.after_properties(node)?;
+    let size = 12 + align(value.len());
+    self.splice(at, 0, size)?;
+    write_u32(self.bytes_mut(), at, PROP);
+    write_u32(self.bytes_mut(), at + 4, value.len() as u32);
+    write_u32(self.bytes_mut(), at + 8, name);
+    self.write_padded(at + 12, value);
+    Ok(())
+  }
+
+  /// Adds an empty subnode `name` to the node `node`, returning its offset.
+  ///
+  /// # Arguments
+  ///
+  /// * `node` - the node to add the subnode to
+  /// * `name` - the name of the subnode
+  fn add_subnode(&mut self, node: usize, name: &[u8]) -> Result<usize> {
+    let at = self.skip_node(node)? - 4;
+    let size = 4 + align(name.len() + 1) + 4;
+    self.splice(at, 0, size)?;
+    write_u32(self.bytes_mut(), at, BEGIN_NODE);
+    let mut padded = [0; MAX_PATH];
+    let padded = padded.get_mut(..name.len() + 1).ok_or(Error::Corrupted)?;
+    padded[..name.len()].copy_from_slice(name);
+    self.write_padded(at + 4, padded);
+    write_u32(self.bytes_mut(), at + size - 4, END_NODE);
+    Ok(at)
+  }
+
+  /// Returns the offset in the strings block of the string `name`, adding it
+  /// if the block does not hold it.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - the string
+  fn add_string(&mut self, name: &[u8]) -> Result<u32> {
+    let (start, size) = (self.field(STRINGS_OFFSET), self.field(STRINGS_SIZE));
+    let strings = &self.bytes()[start..start + size];
+    let found = strings.windows(name.len() + 1).position(|candidate| {
+      candidate[..name.len()] == *name && candidate[name.len()] == 0
+    });
+    if let Some(offset) = found {
+      return Ok(offset as u32);
+    }
+
+    let end = start + size;
+    if end + name.len() + 1 > self.bytes().len() {
+      return Err(Error::BufferTooSmall);
+    }
+    self.bytes_mut()[end..end + name.len()].copy_from_slice(name);
+    self.bytes_mut()[end + name.len()] = 0;
+    self.set_field(STRINGS_SIZE, size + name.len() + 1);
+    self.set_field(TOTAL_SIZE, end + name.len() + 1);
+    Ok(size as u32)
+  }
+
+  /// Replaces `removed` bytes of the structure block at `at` with `inserted`
+  /// bytes, which are left for the caller to fill in.
+  ///
+  /// # Arguments
+  ///
+  /// * `at` - the offset of the bytes to replace
+  /// * `removed` - the number of bytes to remove
+  /// * `inserted` - the number of bytes to insert
+  fn splice(
+    &mut self,
+    at: usize,
+    removed: usize,
+    inserted: usize,
+  ) -> Result<()> {
+    let total = self.field(TOTAL_SIZE);
+    let new_total = total - removed + inserted;
+    if new_total > self.bytes().len() {
+      return Err(Error::BufferTooSmall);
+    }
+    self
+      .bytes_mut()
+      .copy_within(at + removed..total, at + inserted);
+    let grow = |value: usize| value - removed + inserted;
+    self.set_field(STRUCT_SIZE, grow(self.field(STRUCT_SIZE)));
+    self.set_field(STRINGS_OFFSET, grow(self.field(STRINGS_OFFSET)));
+    self.set_field(TOTAL_SIZE, new_total);
+    Ok(())
+  }
+
+  /// Writes `value` at `at`, padded with zeros to a multiple of four bytes.
+  ///
+  /// # Arguments
+  ///
+  /// * `at` - the offset to write at
+  /// * `value` - the value to write
+  fn write_padded(&mut self, at: usize, value: &[u8]) {
+    self.bytes_mut()[at..at + value.len()].copy_from_slice(value);
+    self.bytes_mut()[at + value.len()..at + align(value.len())].fill(0);
+  }
+
+  /// Sets the header field at `offset` to `value`.
+  ///
+  /// # Arguments
+  ///
+  /// * `offset` - the offset of the field
+  /// * `value` - the value of the field
+  fn set_field(&mut self, offset: usize, value: usize) {
+    write_u32(self.bytes_mut(), offset, value as u32);
+  }
+
+  /// Returns the contents of the tree, for editing.
+  fn bytes_mut(&mut self) -> &mut [u8] {
+    self.data.as_mut()
+  }
+}
@@ -0,0 +1,337 @@
+//! This module provides [`Fixed`], unsigned fixed-point numbers, for ratios
+//! that must be exact to a known precision but cannot use floating point,
+//! which the kernel does not save across interrupts, such as the calibration
+//! of a clock in cycles per nanosecond, or the shares of a scheduler.
+//!
+//! A `Fixed<I, F>` has `I` integer bits and `F` fractional bits, held in a
+//! `u64`, so it steps by 2^-F up to just below 2^I. Arithmetic is done in 128
+//! bits and truncated, like integer division: products and quotients round
+//! toward zero. The operators panic on overflow, as those of integers do in
+//! debug builds; the `checked_` and `saturating_` methods do not.
+
+use core::fmt;
+use core::ops::{Add, Div, Mul, Sub};
+
+/// An unsigned fixed-point number with `I` integer bits and `F` fractional
+/// bits, where `I + F` is at most 64.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Fixed<const I: u32, const F: u32>(u64);
+
+/// A fixed-point number with 32 integer and 32 fractional bits.
+pub type U32F32 = Fixed<32, 32>;
+
+impl<const I: u32, const F: u32> Fixed<I, F> {
+  /// Fails to compile for numbers of more than 64 bits.
+  const VALID: () = assert!(I + F <= 64 && F < 64, "Fixed has over 64 bits");
+
+  /// The largest raw value.
+  const MAX_BITS: u64 = u64::MAX >> (64 - I - F);
+
+  /// Zero.
+  pub const ZERO: Self = Self(0);
+
+  /// One, or the largest number if it cannot hold one.
+  pub const ONE: Self = Self(if I == 0 { Self::MAX_BITS } else { 1 << F });
+
+  /// The smallest step between numbers, 2^-F.
+  pub const EPSILON: Self = Self(1);
+
+  /// The largest number, just below 2^I.
+  pub const MAX: Self = Self(Self::MAX_BITS);
+
+  /// Returns the number whose raw value is `bits`, that is `bits` / 2^F, or
+  /// `None` if it is too large.
+  ///
+  /// # Arguments
+  ///
+  /// * `bits` - the raw value
+  pub const fn from_bits(bits: u64) -> Option<Self> {
+    #[allow(clippy::let_unit_value)]
+    let () = Self::VALID;
+    if bits > Self::MAX_BITS {
+      return None;
+    }
+    Some(Self(bits))
+  }
+
+  /// Returns the raw value of the number, that is the number times 2^F.
+  pub const fn to_bits(self) -> u64 {
+    self.0
+  }
+
+  /// Returns `value` as a number, or `None` if it is too large.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the integer
+  pub const fn from_int(value: u64) -> Option<Self> {
+    if F > 0 && value >> (64 - F) != 0 {
+      return None;
+    }
+    Self::from_bits(value << F)
+  }
+
+  /// Returns `numerator` / `denominator`, rounded toward zero, or `None` if
+  /// it is too large or `denominator` is `0`.
+  ///
+  /// # Arguments
+  ///
+  /// * `numerator` - the dividend
+  /// * `denominator` - the divisor
+  pub const fn from_ratio(numerator: u64, denominator: u64) -> Option<Self> {
+    if denominator == 0 {
+      return None;
+    }
+    Self::from_wide(((numerator as u128) << F) / denominator as u128)
+  }
+
+  /// Returns the integer part of the number.
+  pub const fn to_int(self) -> u64 {
+    self.0 >> F
+  }
+
+  /// Returns the number rounded to the nearest integer, with halves rounded
+  /// up.
+  pub const fn round(self) -> u64 {
+    ((self.0 as u128 + Self::half()) >> F) as u64
+  }
+
+  /// Returns the fractional part of the number.
+  pub const fn fract(self) -> Self {
+    Self(self.0 & Self::fract_mask())
+  }
+
+  /// Returns `value` times the number, rounded toward zero, or `None` if the
+  /// product does not fit in a `u64`, such as to convert a count of cycles
+  /// into nanoseconds by the nanoseconds per cycle.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the integer to scale
+  pub const fn mul_int(self, value: u64) -> Option<u64> {
+    let product = (self.0 as u128 * value as u128) >> F;
+    if product > u64::MAX as u128 {
+      return None;
+    }
+    Some(product as u64)
+  }
+
+  /// Returns the sum of the numbers, or `None` if it is too large.
+  ///
+  /// # Arguments
+  ///
+  /// * `other` - the number to add
+  pub const fn checked_add(self, other: Self) -> Option<Self> {
+    Self::from_wide(self.0 as u128 + other.0 as u128)
+  }
+
+  /// Returns the difference of the numbers, or `None` if it is negative.
+  ///
+  /// # Arguments
+  ///
+  /// * `other` - the number to subtract
+  pub const fn checked_sub(self, other: Self) -> Option<Self> {
+    match self.0.checked_sub(other.0) {
+      Some(bits) => Some(Self(bits)),
+      None => None,
+    }
+  }
+
+  /// Returns the product of the numbers, rounded toward zero, or `None` if
+  /// it is too large.
+  ///
+  /// # Arguments
+  ///
+  /// * `other` - the number to multiply by
+  pub const fn checked_mul(self, other: Self) -> Option<Self> {
+    Self::from_wide((self.0 as u128 * other.0 as u128) >> F)
+  }
+
+  /// Returns the quotient of the numbers, rounded toward zero, or `None` if
+  /// it is too large or `other` is zero.
+  ///
+  /// # Arguments
+  ///
+  /// * `other` - the number to divide by
+  pub const fn checked_div(self, other: Self) -> Option<Self> {
+    if other.0 == 0 {
+      return None;
+    }
+    Self::from_wide(((self.0 as u128) << F) / other.0 as u128)
+  }
+
+  /// Returns the sum of the numbers, or [`MAX`](Self::MAX) if it is too
+  /// large.
+  ///
+  /// # Arguments
+  ///
+  /// * `other` - the number to add
+  pub const fn saturating_add(self, other: Self) -> Self {
+    match self.checked_add(other) {
+      Some(sum) => sum,
+      None => Self::MAX,
+    }
+  }
+
+  /// Returns the difference of the numbers, or zero if it is negative.
+  ///
+  /// # Arguments
+  ///
+  /// * `other` - the number to subtract
+  pub const fn saturating_sub(self, other: Self) -> Self {
+    Self(self.0.saturating_sub(other.0))
+  }
+
+  /// Returns the product of the numbers, or [`MAX`](Self::MAX) if it is too
+  /// large.
+  ///
+  /// # Arguments
+  ///
+  /// * `other` - the number to multiply by
+  pub const fn saturating_mul(self, other: Self) -> Self {
+    match self.checked_mul(other) {
+      Some(product) => product,
+      None => Self::MAX,
+    }
+  }
+
+  /// Returns the number with the raw value `bits`, or `None` if it is too
+  /// large.
+  ///
+  /// # Arguments
+  ///
+  /// * `bits` - the raw value, which may be wider than the number
+  const fn from_wide(bits: u128) -> Option<Self> {
+    if bits > Self::MAX_BITS as u128 {
+      return None;
+    }
+    Self::from_bits(bits as u64)
+  }
+
+  /// Returns the raw value of one half, which is `0` if there is no
+  /// fractional part.
+  const fn half() -> u128 {
+    (1 << F) >> 1
+  }
+
+  /// Returns the mask of the fractional bits of a raw value.
+  const fn fract_mask() -> u64 {
+    if F == 0 {
+      0
+    } else {
+      u64::MAX >> (64 - F)
+    }
+  }
+}
+
+impl<const I: u32, const F: u32> Add for Fixed<I, F> {
+  type Output = Self;
+
+  fn add(self, other: Self) -> Self {
+    self
+      .checked_add(other)
+      .expect("fixed-point addition overflowed")
+  }
+}
+
+impl<const I: u32, const F: u32> Sub for Fixed<I, F> {
+  type Output = Self;
+
+  fn sub(self, other: Self) -> Self {
+    self
+      .checked_sub(other)
+      .expect("fixed-point subtraction overflowed")
+  }
+}
+
+impl<const I: u32, const F: u32> Mul for Fixed<I, F> {
+  type Output = Self;
+
+  fn mul(self, other: Self) -> Self {
+    self
+      .checked_mul(other)
+      .expect("fixed-point multiplication overflowed")
+  }
+}
+
+impl<const I: u32, const F: u32> Div for Fixed<I, F> {
+  type Output = Self;
+
+  fn div(self, other: Self) -> Self {
+    self
+      .checked_div(other)
+      .expect("fixed-point division overflowed or divided by zero")
+  }
+}
+
+impl<const I: u32, const F: u32> fmt::Display for Fixed<I, F> {
+  /// Writes the number in decimal, with as many fractional digits as the
+  /// precision asks for, or with enough to tell it from its neighbours.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // Every step of 2^-F has at most F decimal digits, and 19 are as many as
+    // a `u64` scaled by ten holds.
+    let digits = f.precision().unwrap_or((F as usize).min(19));
+    let mut fract = self.fract().0 as u128;
+    write!(f, "{}", self.to_int())?;
+    if digits > 0 {
+      f.write_str(".")?;
+    }
+    for _ in 0..digits {
+      fract *= 10;
+      write!(f, "{}", fract >> F)?;
+      fract &= Self::fract_mask() as u128;
+    }
+    Ok(())
+  }
+}
+
+impl<const I: u32, const F: u32> fmt::Debug for Fixed<I, F> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Display::fmt(self, f)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::format;
+
+  #[test]
+  fn arithmetic_rounds_toward_zero() {
+    let third = U32F32::from_ratio(1, 3).unwrap();
+    let three = U32F32::from_int(3).unwrap();
+
+    assert_eq!((third * three).round(), 1);
+    assert_eq!((third * three).to_int(), 0);
+    assert_eq!(three / three, U32F32::ONE);
+    assert_eq!((three - U32F32::ONE).to_int(), 2);
+    assert_eq!(three.checked_div(U32F32::ZERO), None);
+    assert_eq!(U32F32::ZERO.checked_sub(U32F32::EPSILON), None);
+    assert_eq!(U32F32::MAX.saturating_add(three), U32F32::MAX);
+  }
+
+  #[test]
+  fn ratios_convert_clock_ticks() {
+    // A 2.4 GHz counter: 1/2.4 nanoseconds per cycle.
+    let nanos_per_cycle = U32F32::from_ratio(1_000_000_000, 2_400_000_000);
+    let nanos_per_cycle = nanos_per_cycle.unwrap();
+
+    assert_eq!(nanos_per_cycle.mul_int(2_400_000_000), Some(999_999_999));
+    assert_eq!(nanos_per_cycle.mul_int(24), Some(9));
+    assert_eq!(U32F32::from_int(1 << 32), None);
+    assert_eq!(Fixed::<8, 8>::from_ratio(255, 1).unwrap().to_bits(), 0xff00);
+    assert_eq!(Fixed::<8, 8>::from_ratio(256, 1), None);
+  }
+
+  #[test]
+  fn numbers_display_in_decimal() {
+    let number = Fixed::<8, 8>::from_ratio(13, 8).unwrap();
+
+    assert_eq!(format!("{}", number), "1.62500000");
+    assert_eq!(format!("{:.3}", number), "1.625");
+    assert_eq!(format!("{:.0}", Fixed::<8, 8>::ONE), "1");
+    assert_eq!(format!("{}", Fixed::<16, 0>::from_int(7).unwrap()), "7");
+  }
+}
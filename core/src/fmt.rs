@@ -0,0 +1,359 @@
+//! This module provides formatting into buffers on the stack, for code that
+//! cannot allocate, such as panic and exception handlers.
+//!
+//! [`fmt_to_slice`] formats text into a byte buffer and returns what fit;
+//! [`SliceWriter`] is the writer underneath it, for text built up from more
+//! than one piece.
+//!
+//! [`ByteSize`] and [`Elapsed`] display quantities in the largest unit that
+//! they reach, such as `1.5 MiB` or `250.0 µs`, in place of raw counts. Both
+//! round to one decimal unless the format asks for another precision, and
+//! honour its width, fill, and alignment, aligning right by default like
+//! numbers.
+//!
+//! [`hexdump`] writes bytes as the lines of a hexdump, for the recovery shell
+//! and for looking at memory while debugging.
+
+mod hexdump;
+
+pub use hexdump::{hexdump, Dump, HexDump, DEFAULT_WIDTH};
+
+use crate::math::{self, Rounding};
+use core::fmt::{self, Write};
+use core::time::Duration;
+
+/// The units of [`ByteSize`], each 1024 times the last.
+const BYTE_UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// The units of [`Elapsed`], each 1000 times the last.
+const TIME_UNITS: [&str; 4] = ["ns", "µs", "ms", "s"];
+
+/// The most decimals that a quantity is displayed with.
+const MAX_PRECISION: usize = 9;
+
+/// How much of the text formatted into a buffer fit.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Formatted {
+  /// All of the text fit.
+  Complete,
+
+  /// The buffer filled up, and the text was cut short between characters.
+  Truncated,
+
+  /// A value failed to format, and the text stops where it did.
+  Failed,
+}
+
+/// A writer of formatted text into a byte buffer, which keeps as many whole
+/// characters as fit, and fails once the buffer is full.
+pub struct SliceWriter<'a> {
+  buffer: &'a mut [u8],
+  len: usize,
+  truncated: bool,
+}
+
+impl<'a> SliceWriter<'a> {
+  /// Constructs a writer into `buffer`, starting at its beginning.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer to write into
+  pub fn new(buffer: &'a mut [u8]) -> Self {
+    Self {
+      buffer,
+      len: 0,
+      truncated: false,
+    }
+  }
+
+  /// Returns the number of bytes written.
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  /// Returns whether nothing has been written.
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Returns whether text has been cut short for lack of room.
+  pub fn is_truncated(&self) -> bool {
+    self.truncated
+  }
+
+  /// Returns the text written.
+  pub fn as_str(&self) -> &str {
+    // SAFETY: only whole characters of `str`s are ever written.
+    unsafe { core::str::from_utf8_unchecked(&self.buffer[..self.len]) }
+  }
+
+  /// Consumes the writer, returning the text written.
+  pub fn into_str(self) -> &'a str {
+    // SAFETY: only whole characters of `str`s are ever written.
+    unsafe { core::str::from_utf8_unchecked(&self.buffer[..self.len]) }
+  }
+}
+
+impl fmt::Write for SliceWriter<'_> {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    let mut end = s.len().min(self.buffer.len() - self.len);
+    while !s.is_char_boundary(end) {
+      end -= 1;
+    }
+    self.buffer[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+    self.len += end;
+    if end < s.len() {
+      self.truncated = true;
+      return Err(fmt::Error);
+    }
+    Ok(())
+  }
+}
+
+/// Formats `args` into `buffer`, returning as much of the text as fit.
+///
+/// # Arguments
+///
+/// * `buffer` - the buffer to format into
+/// * `args` - the text to format, as made by [`format_args!`]
+pub fn fmt_to_slice<'a>(
+  buffer: &'a mut [u8],
+  args: fmt::Arguments<'_>,
+) -> &'a str {
+  fmt_to_slice_checked(buffer, args).0
+}
+
+/// Formats `args` into `buffer`, returning as much of the text as fit, and
+/// whether that is all of it.
+///
+/// # Arguments
+///
+/// * `buffer` - the buffer to format into
+/// * `args` - the text to format, as made by [`format_args!`]
+pub fn fmt_to_slice_checked<'a>(
+  buffer: &'a mut [u8],
+  args: fmt::Arguments<'_>,
+) -> (&'a str, Formatted) {
+  let mut writer = SliceWriter::new(buffer);
+  let formatted = match writer.write_fmt(args) {
+    Ok(()) => Formatted::Complete,
+    Err(_) if writer.is_truncated() => Formatted::Truncated,
+    Err(_) => Formatted::Failed,
+  };
+  (writer.into_str(), formatted)
+}
+
+/// A count of bytes, displayed in binary units, such as `4.0 KiB`.
+///
+/// Counts below 1 KiB are displayed exactly, as in `512 B`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ByteSize(pub u64);
+
+impl fmt::Display for ByteSize {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write_quantity(f, self.0, 1024, &BYTE_UNITS)
+  }
+}
+
+/// A span of time, displayed in the unit of nanoseconds, microseconds,
+/// milliseconds, or seconds that it reaches, such as `1.2 ms`.
+///
+/// Spans below a microsecond are displayed exactly, as in `800 ns`, and
+/// spans beyond `u64::MAX` nanoseconds are displayed as that many.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Elapsed(pub Duration);
+
+impl fmt::Display for Elapsed {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let nanos = u64::try_from(self.0.as_nanos()).unwrap_or(u64::MAX);
+    write_quantity(f, nanos, 1000, &TIME_UNITS)
+  }
+}
+
+/// Writes `value` in the largest of `units` that it reaches after rounding,
+/// padded as `f` asks.
+///
+/// # Arguments
+///
+/// * `f` - the formatter to write to
+/// * `value` - the quantity, in the first of `units`
+/// * `base` - the ratio between consecutive units
+/// * `units` - the names of the units, from the smallest
+fn write_quantity(
+  f: &mut fmt::Formatter<'_>,
+  value: u64,
+  base: u64,
+  units: &[&str],
+) -> fmt::Result {
+  let precision = f.precision().unwrap_or(1).min(MAX_PRECISION);
+  let scale = 10u64.pow(precision as u32);
+
+  let mut unit = 0;
+  let mut divisor = 1;
+  while unit + 1 < units.len() && value / divisor >= base {
+    unit += 1;
+    divisor *= base;
+  }
+  // The value rounds to a whole `base` of its unit, as 1023.96 KiB does to
+  // 1024.0 KiB, so it is displayed as one of the next unit instead.
+  let mut scaled = in_steps(value, scale, divisor);
+  if unit > 0 && unit + 1 < units.len() && scaled >= base * scale {
+    unit += 1;
+    divisor *= base;
+    scaled = in_steps(value, scale, divisor);
+  }
+
+  let mut buffer = [0; 48];
+  let mut writer = SliceWriter::new(&mut buffer);
+  if unit == 0 {
+    write!(writer, "{} {}", value, units[0])?;
+  } else if precision == 0 {
+    write!(writer, "{} {}", scaled, units[unit])?;
+  } else {
+    let (whole, fract) = (scaled / scale, scaled % scale);
+    write!(writer, "{}.{:03$} {}", whole, fract, units[unit], precision)?;
+  }
+  pad(f, writer.as_str())
+}
+
+/// Returns `value` / `divisor` in steps of 1 / `scale`, rounded to the
+/// nearest.
+///
+/// # Arguments
+///
+/// * `value` - the quantity
+/// * `scale` - the steps per unit
+/// * `divisor` - the size of the unit
+fn in_steps(value: u64, scale: u64, divisor: u64) -> u64 {
+  // Below the largest unit, the quotient is under a thousand or so times
+  // `scale`, and so fits; in it, a quotient too large to show saturates.
+  math::mul_div_u64(value, scale, divisor, Rounding::Nearest)
+    .unwrap_or(u64::MAX)
+}
+
+/// Writes `text` padded to the width of `f`, with its fill and alignment,
+/// which default to spaces on the left.
+///
+/// Unlike [`fmt::Formatter::pad`], this does not treat the precision as the
+/// most characters to write, since quantities use it for their decimals.
+///
+/// # Arguments
+///
+/// * `f` - the formatter to write to
+/// * `text` - the text to pad
+fn pad(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+  let padding = f.width().unwrap_or(0).saturating_sub(text.chars().count());
+  let (before, after) = match f.align() {
+    Some(fmt::Alignment::Left) => (0, padding),
+    Some(fmt::Alignment::Center) => (padding / 2, padding - padding / 2),
+    Some(fmt::Alignment::Right) | None => (padding, 0),
+  };
+  let fill = f.fill();
+  for _ in 0..before {
+    f.write_char(fill)?;
+  }
+  f.write_str(text)?;
+  for _ in 0..after {
+    f.write_char(fill)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::{
+    fmt_to_slice, fmt_to_slice_checked, ByteSize, Elapsed, Formatted,
+    SliceWriter,
+  };
+  use core::fmt::{self, Write};
+  use core::time::Duration;
+  use std::format;
+
+  /// A value that always fails to format.
+  struct Failing;
+
+  impl fmt::Display for Failing {
+    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+      Err(fmt::Error)
+    }
+  }
+
+  #[test]
+  fn text_that_fits_is_complete() {
+    let mut buffer = [0; 16];
+    let (text, formatted) =
+      fmt_to_slice_checked(&mut buffer, format_args!("{} + {}", 1, 2));
+    assert_eq!((text, formatted), ("1 + 2", Formatted::Complete));
+  }
+
+  #[test]
+  fn text_is_truncated_between_characters() {
+    let mut buffer = [0; 5];
+    let (text, formatted) =
+      fmt_to_slice_checked(&mut buffer, format_args!("{}", "abcdéf"));
+    assert_eq!((text, formatted), ("abcd", Formatted::Truncated));
+    let mut buffer = [0; 4];
+    assert_eq!(fmt_to_slice(&mut buffer, format_args!("{:>6}", 1)), "    ");
+  }
+
+  #[test]
+  fn failures_are_told_apart_from_truncation() {
+    let mut buffer = [0; 16];
+    let (text, formatted) =
+      fmt_to_slice_checked(&mut buffer, format_args!("a{}b", Failing));
+    assert_eq!((text, formatted), ("a", Formatted::Failed));
+  }
+
+  #[test]
+  fn writers_accumulate_pieces() {
+    let mut buffer = [0; 8];
+    let mut writer = SliceWriter::new(&mut buffer);
+    assert!(writer.is_empty());
+    assert!(write!(writer, "{}", 12).is_ok());
+    assert!(write!(writer, "{}", 345).is_ok());
+    assert!(!writer.is_truncated());
+    assert!(write!(writer, "{}", 6789).is_err());
+    assert!(writer.is_truncated());
+    assert_eq!(writer.as_str(), "12345678");
+    assert_eq!(writer.len(), 8);
+  }
+
+  #[test]
+  fn sizes_display_in_binary_units() {
+    assert_eq!(format!("{}", ByteSize(512)), "512 B");
+    assert_eq!(format!("{}", ByteSize(4096)), "4.0 KiB");
+    assert_eq!(format!("{}", ByteSize(3 << 19)), "1.5 MiB");
+    assert_eq!(format!("{:.2}", ByteSize(5 << 30)), "5.00 GiB");
+    assert_eq!(format!("{:.0}", ByteSize(1536)), "2 KiB");
+    assert_eq!(format!("{}", ByteSize((1 << 20) - 1)), "1.0 MiB");
+    assert_eq!(format!("{}", ByteSize(u64::MAX)), "16.0 EiB");
+  }
+
+  #[test]
+  fn durations_display_in_the_unit_they_reach() {
+    assert_eq!(format!("{}", Elapsed(Duration::from_nanos(800))), "800 ns");
+    assert_eq!(
+      format!("{}", Elapsed(Duration::from_micros(250))),
+      "250.0 µs"
+    );
+    assert_eq!(
+      format!("{:.3}", Elapsed(Duration::from_micros(1234))),
+      "1.234 ms"
+    );
+    assert_eq!(format!("{}", Elapsed(Duration::from_secs(90))), "90.0 s");
+    assert_eq!(format!("{}", Elapsed(Duration::MAX)), "18446744073.7 s");
+  }
+
+  #[test]
+  fn quantities_are_padded() {
+    assert_eq!(format!("{:>10}", ByteSize(2048)), "   2.0 KiB");
+    assert_eq!(format!("{:<9}|", ByteSize(1)), "1 B      |");
+    assert_eq!(format!("{:*^9.0}", ByteSize(1024)), "**1 KiB**");
+    assert_eq!(
+      format!("{:8}", Elapsed(Duration::from_millis(5))),
+      "  5.0 ms"
+    );
+  }
+}
@@ -0,0 +1,184 @@
+//! This module provides hexdumps of bytes in the classic layout: each line
+//! gives the address of its first byte, the bytes in hexadecimal, and the
+//! bytes again as ASCII, with a `.` for each that is not printable.
+//!
+//! ```text
+//! 00001000  7f 45 4c 46 02 01 01 00 00 00 00 00 00 00 00 00  .ELF............
+//! ```
+
+use core::fmt::{self, Write};
+
+/// The number of bytes on each line of a hexdump, unless another is given.
+pub const DEFAULT_WIDTH: usize = 16;
+
+/// The layout of a hexdump.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HexDump {
+  width: usize,
+}
+
+impl HexDump {
+  /// Constructs the layout with [`DEFAULT_WIDTH`] bytes on each line.
+  pub const fn new() -> Self {
+    Self::with_width(DEFAULT_WIDTH)
+  }
+
+  /// Constructs the layout with `width` bytes on each line, or one if
+  /// `width` is zero.
+  ///
+  /// # Arguments
+  ///
+  /// * `width` - the number of bytes on each line
+  pub const fn with_width(width: usize) -> Self {
+    Self {
+      width: if width == 0 { 1 } else { width },
+    }
+  }
+
+  /// Returns the number of bytes on each line.
+  pub const fn width(&self) -> usize {
+    self.width
+  }
+
+  /// Writes the hexdump of `data`, whose first byte is at `base`, to `out`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the bytes to dump
+  /// * `base` - the address or offset of the first byte
+  /// * `out` - the writer to write the lines to
+  pub fn write(
+    &self,
+    data: &[u8],
+    base: u64,
+    out: &mut dyn Write,
+  ) -> fmt::Result {
+    let mut address = base;
+    for row in data.chunks(self.width) {
+      self.write_row(row, address, out)?;
+      address = address.wrapping_add(row.len() as u64);
+    }
+    Ok(())
+  }
+
+  /// Writes a line of a hexdump, of the bytes `row` at `address`, to `out`,
+  /// for dumps that are read a line at a time, such as from a disk.
+  ///
+  /// A `row` shorter than the width is padded, so that its ASCII lines up
+  /// with that of the lines above; one longer than the width is cut short.
+  ///
+  /// # Arguments
+  ///
+  /// * `row` - the bytes of the line
+  /// * `address` - the address or offset of the first byte
+  /// * `out` - the writer to write the line to
+  pub fn write_row(
+    &self,
+    row: &[u8],
+    address: u64,
+    out: &mut dyn Write,
+  ) -> fmt::Result {
+    let row = &row[..row.len().min(self.width)];
+    write!(out, "{:08x} ", address)?;
+    for i in 0..self.width {
+      match row.get(i) {
+        Some(byte) => write!(out, " {:02x}", byte)?,
+        None => out.write_str("   ")?,
+      }
+    }
+    out.write_str("  ")?;
+    for &byte in row {
+      let c = if byte.is_ascii_graphic() || byte == b' ' {
+        byte as char
+      } else {
+        '.'
+      };
+      out.write_char(c)?;
+    }
+    out.write_char('\n')
+  }
+
+  /// Returns the hexdump of `data`, whose first byte is at `base`, as a
+  /// value to format, such as into a log message.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the bytes to dump
+  /// * `base` - the address or offset of the first byte
+  pub const fn display<'a>(&self, data: &'a [u8], base: u64) -> Dump<'a> {
+    Dump {
+      layout: *self,
+      data,
+      base,
+    }
+  }
+}
+
+impl Default for HexDump {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// The hexdump of some bytes, which formats as its lines; see
+/// [`HexDump::display`].
+#[derive(Clone, Copy, Debug)]
+pub struct Dump<'a> {
+  layout: HexDump,
+  data: &'a [u8],
+  base: u64,
+}
+
+impl fmt::Display for Dump<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.layout.write(self.data, self.base, f)
+  }
+}
+
+/// Writes the hexdump of `data`, whose first byte is at `base`, to `out`,
+/// with [`DEFAULT_WIDTH`] bytes on each line.
+///
+/// # Arguments
+///
+/// * `data` - the bytes to dump
+/// * `base` - the address or offset of the first byte
+/// * `out` - the writer to write the lines to
+pub fn hexdump(data: &[u8], base: u64, out: &mut dyn Write) -> fmt::Result {
+  HexDump::new().write(data, base, out)
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::format;
+  use std::string::String;
+
+  #[test]
+  fn lines_give_address_hex_and_ascii() {
+    let mut text = String::new();
+    hexdump(b"\x7fELF\x02\x01\x01\0 hello, world!\n", 0x1000, &mut text)
+      .unwrap();
+
+    assert_eq!(
+      text,
+      "00001000  7f 45 4c 46 02 01 01 00 20 68 65 6c 6c 6f 2c 20  \
+       .ELF.... hello, \n\
+       00001010  77 6f 72 6c 64 21 0a                             \
+       world!.\n"
+    );
+  }
+
+  #[test]
+  fn width_is_configurable() {
+    let layout = HexDump::with_width(4);
+
+    assert_eq!(
+      format!("{}", layout.display(b"abcdef", 8)),
+      "00000008  61 62 63 64  abcd\n0000000c  65 66        ef\n"
+    );
+    assert_eq!(HexDump::with_width(0).width(), 1);
+    assert_eq!(format!("{}", layout.display(b"", 0)), "");
+  }
+}
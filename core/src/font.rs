@@ -0,0 +1,553 @@
+//! This module provides parsing of PC Screen Fonts, the bitmap fonts of the
+//! Linux console, in both their PSF1 and PSF2 forms, and a font built in for
+//! consoles to draw text with until another is loaded.
+//!
+//! Fonts are read in place. The glyph of a character is found through the
+//! font's Unicode table if it has one, and is otherwise the glyph at the
+//! character's code point. Sequences of characters that map to a single
+//! glyph are ignored.
+//!
+//! Malformed fonts fail with [`Error::Corrupted`], and PSF2 fonts of later
+//! versions with [`Error::Unsupported`].
+
+mod builtin;
+
+use crate::error::{Error, Result};
+
+/// The magic number that PSF1 fonts start with.
+const PSF1_MAGIC: u16 = 0x0436;
+
+/// The size of the header of PSF1 fonts.
+const PSF1_HEADER_SIZE: usize = 4;
+
+/// The PSF1 mode flag for fonts of 512 glyphs rather than 256.
+const PSF1_MODE_512: u8 = 0x01;
+
+/// The PSF1 mode flags for fonts with a Unicode table.
+const PSF1_MODE_HAS_TABLE: u8 = 0x06;
+
+/// The PSF1 Unicode table entry that starts the sequences of a glyph.
+const PSF1_START_SEQUENCE: u16 = 0xfffe;
+
+/// The PSF1 Unicode table entry that ends the characters of a glyph.
+const PSF1_SEPARATOR: u16 = 0xffff;
+
+/// The magic number that PSF2 fonts start with.
+const PSF2_MAGIC: u32 = 0x864a_b572;
+
+/// The size of the header of PSF2 fonts.
+const PSF2_HEADER_SIZE: usize = 32;
+
+/// The PSF2 flag for fonts with a Unicode table.
+const PSF2_HAS_TABLE: u32 = 0x01;
+
+/// The PSF2 Unicode table byte that starts the sequences of a glyph.
+const PSF2_START_SEQUENCE: u8 = 0xfe;
+
+/// The PSF2 Unicode table byte that ends the characters of a glyph.
+const PSF2_SEPARATOR: u8 = 0xff;
+
+/// The glyph drawn for characters that a font does not cover, if it has one.
+const REPLACEMENT: char = '\u{fffd}';
+
+/// The table mapping characters to the glyphs of a font.
+#[derive(Clone, Copy, Debug)]
+enum Table<'a> {
+  /// Characters are drawn with the glyph at their code point.
+  None,
+
+  /// The PSF1 table, of little-endian UCS-2 characters.
+  Psf1(&'a [u8]),
+
+  /// The PSF2 table, of UTF-8 characters.
+  Psf2(&'a [u8]),
+}
+
+/// A PC Screen Font.
+#[derive(Clone, Copy, Debug)]
+pub struct Font<'a> {
+  glyphs: &'a [u8],
+  count: usize,
+  width: usize,
+  height: usize,
+  glyph_size: usize,
+  table: Table<'a>,
+}
+
+impl<'a> Font<'a> {
+  /// Reads the PSF1 or PSF2 font held in `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the font
+  pub fn parse(data: &'a [u8]) -> Result<Self> {
+    if data.len() >= 2 && read_u16(data, 0) == PSF1_MAGIC {
+      Self::parse_psf1(data)
+    } else if data.len() >= 4 && read_u32(data, 0) == PSF2_MAGIC {
+      Self::parse_psf2(data)
+    } else {
+      Err(Error::Corrupted)
+    }
+  }
+
+  /// Returns the font built in, which covers ASCII with glyphs of eight by
+  /// eight pixels.
+  pub fn builtin() -> Font<'static> {
+    match Font::parse(&builtin::PSF) {
+      Ok(font) => font,
+      Err(_) => unreachable!("the built-in font is well-formed"),
+    }
+  }
+
+  /// Returns the width of the glyphs, in pixels.
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  /// Returns the height of the glyphs, in pixels.
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
+  /// Returns the number of glyphs of the font.
+  pub fn len(&self) -> usize {
+    self.count
+  }
+
+  /// Returns `true` if the font has no glyphs.
+  pub fn is_empty(&self) -> bool {
+    self.count == 0
+  }
+
+  /// Returns the glyph at `index`, or [`None`] if the font does not have that
+  /// many.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the glyph
+  pub fn glyph_at(&self, index: usize) -> Option<Glyph<'a>> {
+    if index >= self.count {
+      return None;
+    }
+    Some(Glyph {
+      data: &self.glyphs[index * self.glyph_size..][..self.glyph_size],
+      width: self.width,
+      height: self.height,
+    })
+  }
+
+  /// Returns the index of the glyph that `c` is drawn with, or [`None`] if
+  /// the font does not cover it.
+  ///
+  /// # Arguments
+  ///
+  /// * `c` - the character to draw
+  pub fn index_of(&self, c: char) -> Option<usize> {
+    match self.table {
+      Table::None => Some(c as usize).filter(|&index| index < self.count),
+      Table::Psf1(table) => {
+        let c = u16::try_from(c as u32).ok()?;
+        let mut entries = table.chunks_exact(2).map(|entry| read_u16(entry, 0));
+        (0..self.count).find(|_| {
+          let mut found = false;
+          let mut characters = true;
+          for entry in entries.by_ref() {
+            match entry {
+              PSF1_SEPARATOR => break,
+              PSF1_START_SEQUENCE => characters = false,
+              entry => found |= characters && entry == c,
+            }
+          }
+          found
+        })
+      }
+      Table::Psf2(table) => {
+        let mut entries = table.split(|&byte| byte == PSF2_SEPARATOR);
+        (0..self.count).find(|_| {
+          let entry = entries.next().unwrap_or_default();
+          let end = entry
+            .iter()
+            .position(|&byte| byte == PSF2_START_SEQUENCE)
+            .unwrap_or(entry.len());
+          // Characters after malformed UTF-8 are ignored.
+          let characters = match core::str::from_utf8(&entry[..end]) {
+            Ok(characters) => characters,
+            Err(error) => core::str::from_utf8(&entry[..error.valid_up_to()])
+              .unwrap_or_default(),
+          };
+          characters.contains(c)
+        })
+      }
+    }
+  }
+
+  /// Returns the glyph that `c` is drawn with, or [`None`] if the font does
+  /// not cover it.
+  ///
+  /// # Arguments
+  ///
+  /// * `c` - the character to draw
+  pub fn glyph(&self, c: char) -> Option<Glyph<'a>> {
+    self.glyph_at(self.index_of(c)?)
+  }
+
+  /// Returns the glyph that `c` is drawn with, falling back on the glyph of
+  /// the replacement character, of `?`, or on the first glyph for characters
+  /// that the font does not cover.
+  ///
+  /// Returns [`None`] only if the font has no glyphs.
+  ///
+  /// # Arguments
+  ///
+  /// * `c` - the character to draw
+  pub fn glyph_or_replacement(&self, c: char) -> Option<Glyph<'a>> {
+    self
+      .glyph(c)
+      .or_else(|| self.glyph(REPLACEMENT))
+      .or_else(|| self.glyph('?'))
+      .or_else(|| self.glyph_at(0))
+  }
+
+  /// Reads the PSF1 font held in `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the font, which starts with the PSF1 magic number
+  fn parse_psf1(data: &'a [u8]) -> Result<Self> {
+    let header = data.get(..PSF1_HEADER_SIZE).ok_or(Error::Corrupted)?;
+    let (mode, height) = (header[2], header[3] as usize);
+    let count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+    let table = mode & PSF1_MODE_HAS_TABLE != 0;
+    Self::new(&data[PSF1_HEADER_SIZE..], count, 8, height, height).map(
+      |(mut font, rest)| {
+        if table {
+          font.table = Table::Psf1(rest);
+        }
+        font
+      },
+    )
+  }
+
+  /// Reads the PSF2 font held in `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the font, which starts with the PSF2 magic number
+  fn parse_psf2(data: &'a [u8]) -> Result<Self> {
+    let header = data.get(..PSF2_HEADER_SIZE).ok_or(Error::Corrupted)?;
+    let field = |index: usize| read_u32(header, index * 4) as usize;
+    if field(1) != 0 {
+      return Err(Error::Unsupported);
+    }
+    let glyphs = data.get(field(2).max(PSF2_HEADER_SIZE)..);
+    let (count, glyph_size) = (field(4), field(5));
+    let (height, width) = (field(6), field(7));
+    if glyph_size < height * ((width + 7) / 8) {
+      return Err(Error::Corrupted);
+    }
+    let table = field(3) as u32 & PSF2_HAS_TABLE != 0;
+    let glyphs = glyphs.ok_or(Error::Corrupted)?;
+    Self::new(glyphs, count, width, height, glyph_size).map(
+      |(mut font, rest)| {
+        if table {
+          font.table = Table::Psf2(rest);
+        }
+        font
+      },
+    )
+  }
+
+  /// Constructs the font of `count` glyphs at the start of `data`, and
+  /// returns it with the rest of `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the glyphs, and what follows them
+  /// * `count` - the number of glyphs
+  /// * `width` - the width of the glyphs, in pixels
+  /// * `height` - the height of the glyphs, in pixels
+  /// * `glyph_size` - the size of a glyph, in bytes
+  fn new(
+    data: &'a [u8],
+    count: usize,
+    width: usize,
+    height: usize,
+    glyph_size: usize,
+  ) -> Result<(Self, &'a [u8])> {
+    if width == 0 || height == 0 {
+      return Err(Error::Corrupted);
+    }
+    let size = count.checked_mul(glyph_size).ok_or(Error::Corrupted)?;
+    if size > data.len() {
+      return Err(Error::Corrupted);
+    }
+    let (glyphs, rest) = data.split_at(size);
+    let font = Self {
+      glyphs,
+      count,
+      width,
+      height,
+      glyph_size,
+      table: Table::None,
+    };
+    Ok((font, rest))
+  }
+}
+
+/// The bitmap of a character, of rows of pixels padded to whole bytes, with
+/// the leftmost pixel of a row in the most significant bit of its first byte.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Glyph<'a> {
+  data: &'a [u8],
+  width: usize,
+  height: usize,
+}
+
+impl<'a> Glyph<'a> {
+  /// Returns the width of the glyph, in pixels.
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  /// Returns the height of the glyph, in pixels.
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
+  /// Returns the bytes of the row `y`.
+  ///
+  /// # Arguments
+  ///
+  /// * `y` - the row, from the top, below the height of the glyph
+  pub fn row(&self, y: usize) -> &'a [u8] {
+    let stride = (self.width + 7) / 8;
+    &self.data[y * stride..][..stride]
+  }
+
+  /// Returns `true` if the pixel at `x` and `y` is set.
+  ///
+  /// # Arguments
+  ///
+  /// * `x` - the column, from the left, below the width of the glyph
+  /// * `y` - the row, from the top, below the height of the glyph
+  pub fn pixel(&self, x: usize, y: usize) -> bool {
+    self.row(y)[x / 8] & (0x80 >> (x % 8)) != 0
+  }
+}
+
+/// Returns `true` if [`Font::parse`] reads `data`, for checking fonts built
+/// into binaries at compile time.
+///
+/// # Arguments
+///
+/// * `data` - the font
+pub(crate) const fn is_valid(data: &[u8]) -> bool {
+  if data.len() >= 2 && read_u16(data, 0) == PSF1_MAGIC {
+    if data.len() < PSF1_HEADER_SIZE {
+      return false;
+    }
+    let height = data[3] as usize;
+    let count = if data[2] & PSF1_MODE_512 != 0 {
+      512
+    } else {
+      256
+    };
+    glyphs_fit(data.len() - PSF1_HEADER_SIZE, count, 8, height, height)
+  } else if data.len() >= 4 && read_u32(data, 0) == PSF2_MAGIC {
+    if data.len() < PSF2_HEADER_SIZE || read_u32(data, 4) != 0 {
+      return false;
+    }
+    let offset = read_u32(data, 8) as usize;
+    let offset = if offset < PSF2_HEADER_SIZE {
+      PSF2_HEADER_SIZE
+    } else {
+      offset
+    };
+    let (count, glyph_size) =
+      (read_u32(data, 16) as usize, read_u32(data, 20) as usize);
+    let (height, width) =
+      (read_u32(data, 24) as usize, read_u32(data, 28) as usize);
+    let row_size = (width + 7) / 8;
+    offset <= data.len()
+      && glyph_size >= height.saturating_mul(row_size)
+      && glyphs_fit(data.len() - offset, count, width, height, glyph_size)
+  } else {
+    false
+  }
+}
+
+/// Returns `true` if `count` glyphs fit in `len` bytes, as [`Font::new`]
+/// checks.
+///
+/// # Arguments
+///
+/// * `len` - the number of bytes after the header
+/// * `count` - the number of glyphs
+/// * `width` - the width of the glyphs, in pixels
+/// * `height` - the height of the glyphs, in pixels
+/// * `glyph_size` - the size of a glyph, in bytes
+const fn glyphs_fit(
+  len: usize,
+  count: usize,
+  width: usize,
+  height: usize,
+  glyph_size: usize,
+) -> bool {
+  if width == 0 || height == 0 {
+    return false;
+  }
+  match count.checked_mul(glyph_size) {
+    Some(size) => size <= len,
+    None => false,
+  }
+}
+
+const fn read_u16(data: &[u8], offset: usize) -> u16 {
+  u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+const fn read_u32(data: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes([
+    data[offset],
+    data[offset + 1],
+    data[offset + 2],
+    data[offset + 3],
+  ])
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::vec::Vec;
+
+  /// Returns a PSF2 font of glyphs of 10 by 2 pixels, each filled with its
+  /// index, and the Unicode table `table`.
+  fn psf2(count: usize, table: &[u8]) -> Vec<u8> {
+    let header = [
+      PSF2_MAGIC,
+      0,
+      PSF2_HEADER_SIZE as u32,
+      !table.is_empty() as u32,
+      count as u32,
+      4,
+      2,
+      10,
+    ];
+    let mut font: Vec<u8> = header
+      .iter()
+      .flat_map(|field| field.to_le_bytes())
+      .collect();
+    for glyph in 0..count {
+      font.extend_from_slice(&[glyph as u8; 4]);
+    }
+    font.extend_from_slice(table);
+    font
+  }
+
+  #[test]
+  fn builtin_font_covers_ascii() {
+    let font = Font::builtin();
+
+    assert_eq!((font.width(), font.height(), font.len()), (8, 8, 128));
+    let glyph = font.glyph('A').unwrap();
+    assert_eq!(glyph.row(0), [0x38]);
+    assert!(!glyph.pixel(1, 0) && glyph.pixel(2, 0));
+    assert_eq!(font.glyph('\u{e9}'), None);
+    assert_eq!(font.glyph_or_replacement('\u{e9}'), font.glyph('?'));
+  }
+
+  #[test]
+  fn psf1_glyphs_are_found_through_the_table() {
+    let mut font = std::vec![0x36, 0x04, PSF1_MODE_HAS_TABLE, 1];
+    font.extend((0..=255).map(|glyph| glyph as u8));
+    let mut table = std::vec![PSF1_SEPARATOR; 256];
+    table[0] = 0x263a;
+    table[1] = 0x41;
+    table[2] = PSF1_SEPARATOR;
+    table[3] = PSF1_START_SEQUENCE;
+    table[4] = 0x42;
+    table[5] = PSF1_SEPARATOR;
+    table.push(PSF1_SEPARATOR);
+    table.push(PSF1_SEPARATOR);
+    font.extend(table.iter().flat_map(|entry| entry.to_le_bytes()));
+    let font = Font::parse(&font).unwrap();
+
+    assert_eq!((font.width(), font.height(), font.len()), (8, 1, 256));
+    assert_eq!(font.index_of('A'), Some(0));
+    assert_eq!(font.index_of('\u{263a}'), Some(0));
+    assert_eq!(font.index_of('B'), None);
+    assert_eq!(font.index_of('\u{1f600}'), None);
+  }
+
+  #[test]
+  fn psf2_glyphs_are_found_through_the_table() {
+    let mut table = Vec::new();
+    table.extend_from_slice(b"a\xff");
+    table.extend_from_slice("b\u{e9}\u{fffd}".as_bytes());
+    table.extend_from_slice(b"\xfeab");
+    table.push(PSF2_SEPARATOR);
+    table.extend_from_slice(b"\xfec\xff");
+    let data = psf2(3, &table);
+    let font = Font::parse(&data).unwrap();
+
+    assert_eq!(font.index_of('a'), Some(0));
+    assert_eq!(font.index_of('\u{e9}'), Some(1));
+    assert_eq!(font.index_of('c'), None);
+    let glyph = font.glyph_or_replacement('z').unwrap();
+    assert_eq!(glyph.row(1), [1, 1]);
+    assert_eq!((glyph.width(), glyph.height()), (10, 2));
+  }
+
+  #[test]
+  fn glyphs_are_at_their_code_points_without_a_table() {
+    let data = psf2(3, &[]);
+    let font = Font::parse(&data).unwrap();
+
+    assert_eq!(
+      font.glyph('\u{2}').map(|glyph| glyph.row(0)),
+      Some(&[2, 2][..])
+    );
+    assert_eq!(font.glyph('\u{3}'), None);
+    assert_eq!(font.glyph_or_replacement('\u{3}'), font.glyph_at(0));
+  }
+
+  #[test]
+  fn malformed_fonts_are_rejected() {
+    let font = psf2(3, &[]);
+
+    assert_eq!(Font::parse(&font[..40]).err(), Some(Error::Corrupted));
+    assert_eq!(Font::parse(&font[1..]).err(), Some(Error::Corrupted));
+    let mut later = font.clone();
+    later[4] = 1;
+    assert_eq!(Font::parse(&later).err(), Some(Error::Unsupported));
+    let mut small = font;
+    small[20] = 1;
+    assert_eq!(Font::parse(&small).err(), Some(Error::Corrupted));
+  }
+
+  #[test]
+  fn fonts_are_valid_as_they_parse() {
+    let font = psf2(3, &[]);
+    let mut later = font.clone();
+    later[4] = 1;
+    let mut small = font.clone();
+    small[20] = 1;
+    let psf1 = [&[0x36, 0x04, 0, 1][..], &[0; 256]].concat();
+
+    for data in [
+      &builtin::PSF[..],
+      &font,
+      &font[..40],
+      &font[1..],
+      &later,
+      &small,
+      &psf1,
+      &psf1[..259],
+    ] {
+      assert_eq!(is_valid(data), Font::parse(data).is_ok());
+    }
+    assert!(is_valid(&builtin::PSF));
+  }
+}
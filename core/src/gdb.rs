@@ -0,0 +1,971 @@
+//! This module provides a stub of the GDB remote serial protocol, so that
+//! code that stops in an exception handler can be inspected and controlled by
+//! a stock `gdb` over a [`SerialPort`], with `target remote`.
+//!
+//! The [`Stub`] reads the packets that the debugger sends, `$data#checksum`,
+//! acknowledges them, and answers them through a [`Target`], the callbacks
+//! that read and write the registers and memory of the code that stopped.
+//! Software breakpoints are inserted by the stub itself, by writing the
+//! architecture's breakpoint instruction over the code; hardware breakpoints
+//! and watchpoints are left to the target, which may keep them in
+//! [`HardwareBreakpoints`].
+//!
+//! The stub only runs while the code is stopped: the exception handlers for
+//! breakpoints, single steps and debug registers call [`Stub::handle`], and
+//! resume the code as the [`Resume`] it returns asks.
+
+use crate::error::{Error, Result};
+use crate::serial::SerialPort;
+use arch::debug::Watch;
+use arch::target::debug as hardware;
+
+/// The largest packet that the stub exchanges, in bytes of data.
+pub const PACKET_SIZE: usize = 2048;
+
+/// The number of software breakpoints that may be inserted at once.
+pub const SOFTWARE_BREAKPOINTS: usize = 32;
+
+/// The signal that reports a breakpoint or a single step, `SIGTRAP`.
+pub const SIGTRAP: u8 = 5;
+
+/// The length of the breakpoint instruction.
+const INSTRUCTION_LEN: usize = hardware::BREAKPOINT_INSTRUCTION.len();
+
+/// The digits of hexadecimal numbers.
+const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// The kinds of breakpoint that the debugger inserts, by the number it gives
+/// them in `Z` packets.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Breakpoint {
+  /// A breakpoint written into the code, `Z0`.
+  Software,
+
+  /// A breakpoint on execution in the debug registers, `Z1`.
+  Hardware,
+
+  /// A watchpoint on writes, `Z2`.
+  Write,
+
+  /// A watchpoint on reads, `Z3`.
+  Read,
+
+  /// A watchpoint on reads and writes, `Z4`.
+  Access,
+}
+
+/// How the stopped code is resumed once the debugger is done with it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Resume {
+  /// Continue running, at the address if one is given.
+  Continue(Option<u64>),
+
+  /// Execute one instruction, at the address if one is given, and stop
+  /// again, by setting the trap flag or the software step bit.
+  Step(Option<u64>),
+
+  /// Continue running without the debugger, which has detached.
+  Detach,
+
+  /// Stop the machine, as the debugger has killed it.
+  Kill,
+}
+
+/// The code that a [`Stub`] debugs, while it is stopped.
+///
+/// Registers are exchanged as the raw bytes that `gdb` expects for the
+/// architecture: in the order of its target description, each in the
+/// byte order of the target.
+pub trait Target {
+  /// Writes the registers into `buffer`, returning their length.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer to write the registers into
+  fn registers(&mut self, buffer: &mut [u8]) -> Result<usize>;
+
+  /// Replaces the registers with `data`, as [`registers`](Self::registers)
+  /// wrote them.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the registers
+  fn set_registers(&mut self, data: &[u8]) -> Result<()>;
+
+  /// Writes the register `index` into `buffer`, returning its length, or
+  /// fails with [`Error::Unsupported`], so that the debugger reads them all.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the number of the register
+  /// * `buffer` - the buffer to write the register into
+  fn register(&mut self, index: usize, buffer: &mut [u8]) -> Result<usize> {
+    let _ = (index, buffer);
+    Err(Error::Unsupported)
+  }
+
+  /// Replaces the register `index` with `data`, or fails with
+  /// [`Error::Unsupported`], so that the debugger writes them all.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the number of the register
+  /// * `data` - the value of the register
+  fn set_register(&mut self, index: usize, data: &[u8]) -> Result<()> {
+    let _ = (index, data);
+    Err(Error::Unsupported)
+  }
+
+  /// Reads the memory at `address` into `buffer`, failing if any of it is
+  /// not mapped.
+  ///
+  /// # Arguments
+  ///
+  /// * `address` - the virtual address to read from
+  /// * `buffer` - the buffer to read into
+  fn read_memory(&mut self, address: u64, buffer: &mut [u8]) -> Result<()>;
+
+  /// Writes `data` to the memory at `address`, failing if any of it is not
+  /// mapped. Writes to code must be made visible to instruction fetches.
+  ///
+  /// # Arguments
+  ///
+  /// * `address` - the virtual address to write to
+  /// * `data` - the bytes to write
+  fn write_memory(&mut self, address: u64, data: &[u8]) -> Result<()>;
+
+  /// Inserts the breakpoint of `kind` on the `len` bytes at `address`, or
+  /// fails with [`Error::Unsupported`] if the target cannot.
+  ///
+  /// Software breakpoints are inserted by the stub, and never reach this.
+  ///
+  /// # Arguments
+  ///
+  /// * `kind` - the kind of breakpoint
+  /// * `address` - the virtual address to break on
+  /// * `len` - the number of bytes to watch
+  fn set_breakpoint(
+    &mut self,
+    kind: Breakpoint,
+    address: u64,
+    len: usize,
+  ) -> Result<()> {
+    let _ = (kind, address, len);
+    Err(Error::Unsupported)
+  }
+
+  /// Removes the breakpoint of `kind` at `address`.
+  ///
+  /// # Arguments
+  ///
+  /// * `kind` - the kind of breakpoint
+  /// * `address` - the virtual address it breaks on
+  fn clear_breakpoint(&mut self, kind: Breakpoint, address: u64) -> Result<()> {
+    let _ = (kind, address);
+    Err(Error::Unsupported)
+  }
+}
+
+/// The hardware breakpoints of the processor, allocated to the breakpoints
+/// and watchpoints that the debugger inserts, for [`Target`]s to insert them
+/// with.
+pub struct HardwareBreakpoints {
+  slots: [Option<(Breakpoint, u64)>; hardware::BREAKPOINTS],
+}
+
+impl HardwareBreakpoints {
+  /// Constructs the allocator of the hardware breakpoints, all of which are
+  /// free.
+  ///
+  /// # Safety
+  ///
+  /// This must only be used at the privilege of the kernel, with handlers
+  /// for debug exceptions installed, and no other user of the debug
+  /// registers.
+  pub const unsafe fn new() -> Self {
+    Self {
+      slots: [None; hardware::BREAKPOINTS],
+    }
+  }
+
+  /// Inserts the breakpoint of `kind` on the `len` bytes at `address`,
+  /// failing with [`Error::Busy`] if every hardware breakpoint is in use,
+  /// or [`Error::Unsupported`] if the processor cannot watch the range.
+  ///
+  /// # Arguments
+  ///
+  /// * `kind` - the kind of breakpoint
+  /// * `address` - the virtual address to break on
+  /// * `len` - the number of bytes to watch
+  pub fn insert(
+    &mut self,
+    kind: Breakpoint,
+    address: u64,
+    len: usize,
+  ) -> Result<()> {
+    let watch = match kind {
+      Breakpoint::Hardware => Watch::Execute,
+      Breakpoint::Write => Watch::Write,
+      Breakpoint::Access => Watch::ReadWrite,
+      Breakpoint::Software | Breakpoint::Read => {
+        return Err(Error::Unsupported)
+      }
+    };
+    let index = self
+      .slots
+      .iter()
+      .position(Option::is_none)
+      .ok_or(Error::Busy)?;
+    // SAFETY: the caller of `new` runs this with the privilege to program
+    // the debug registers, and the slot is not in use.
+    if !unsafe { hardware::set_breakpoint(index, address, watch, len) } {
+      return Err(Error::Unsupported);
+    }
+    self.slots[index] = Some((kind, address));
+    Ok(())
+  }
+
+  /// Removes the breakpoint of `kind` at `address`, failing with
+  /// [`Error::NotFound`] if there is none.
+  ///
+  /// # Arguments
+  ///
+  /// * `kind` - the kind of breakpoint
+  /// * `address` - the virtual address it breaks on
+  pub fn remove(&mut self, kind: Breakpoint, address: u64) -> Result<()> {
+    let index = self
+      .slots
+      .iter()
+      .position(|&slot| slot == Some((kind, address)))
+      .ok_or(Error::NotFound)?;
+    // SAFETY: as in `insert`.
+    unsafe { hardware::clear_breakpoint(index) };
+    self.slots[index] = None;
+    Ok(())
+  }
+}
+
+/// A software breakpoint, and the code that it was written over.
+#[derive(Clone, Copy)]
+struct Inserted {
+  address: u64,
+  original: [u8; INSTRUCTION_LEN],
+}
+
+/// A stub of the GDB remote serial protocol over a [`SerialPort`].
+pub struct Stub<P> {
+  port: P,
+  packet: [u8; PACKET_SIZE],
+  reply: [u8; PACKET_SIZE],
+  reply_len: usize,
+  breakpoints: [Option<Inserted>; SOFTWARE_BREAKPOINTS],
+  resumed: bool,
+}
+
+impl<P: SerialPort> Stub<P> {
+  /// Constructs a stub that talks to the debugger over `port`.
+  ///
+  /// # Arguments
+  ///
+  /// * `port` - the byte stream to the debugger
+  pub fn new(port: P) -> Self {
+    Self {
+      port,
+      packet: [0; PACKET_SIZE],
+      reply: [0; PACKET_SIZE],
+      reply_len: 0,
+      breakpoints: [None; SOFTWARE_BREAKPOINTS],
+      resumed: false,
+    }
+  }
+
+  /// Returns the port to the debugger.
+  pub fn port(&mut self) -> &mut P {
+    &mut self.port
+  }
+
+  /// Answers the debugger about `target`, which stopped with `signal`, until
+  /// it resumes it, returning how.
+  ///
+  /// The stop is reported to the debugger if it resumed the target before;
+  /// otherwise it is waiting to ask why the target stopped. Fails only if
+  /// the port does.
+  ///
+  /// # Arguments
+  ///
+  /// * `target` - the code that stopped
+  /// * `signal` - the signal that describes why, such as [`SIGTRAP`]
+  pub fn handle(
+    &mut self,
+    target: &mut impl Target,
+    signal: u8,
+  ) -> Result<Resume> {
+    if self.resumed {
+      self.resumed = false;
+      self.reply_stop(signal);
+      self.send()?;
+    }
+    loop {
+      let len = self.receive()?;
+      self.reply_len = 0;
+      if let Some(resume) = self.dispatch(target, signal, len) {
+        if resume == Resume::Detach {
+          self.remove_breakpoints(target);
+          self.push(b"OK");
+          self.send()?;
+        }
+        self.resumed = true;
+        return Ok(resume);
+      }
+      self.send()?;
+    }
+  }
+
+  /// Answers the packet of `len` bytes into the reply, returning how to
+  /// resume the target if it asks to.
+  ///
+  /// # Arguments
+  ///
+  /// * `target` - the code that stopped
+  /// * `signal` - the signal that describes why
+  /// * `len` - the length of the packet
+  fn dispatch(
+    &mut self,
+    target: &mut impl Target,
+    signal: u8,
+    len: usize,
+  ) -> Option<Resume> {
+    let (&command, arguments) = self.packet[..len].split_first()?;
+    let result = match command {
+      b'?' => {
+        self.reply_stop(signal);
+        Ok(())
+      }
+      b'c' => return Some(Resume::Continue(parse_hex(arguments))),
+      b's' => return Some(Resume::Step(parse_hex(arguments))),
+      b'D' => return Some(Resume::Detach),
+      b'k' => return Some(Resume::Kill),
+      b'g' => self.read_registers(target, None),
+      b'p' => match parse_hex(arguments) {
+        Some(index) => self.read_registers(target, Some(index as usize)),
+        None => Err(Error::InvalidArgument),
+      },
+      b'G' | b'P' => self.write_registers(target, command, len),
+      b'm' => self.read_memory(target, len),
+      b'M' => self.write_memory(target, len),
+      b'Z' | b'z' => self.change_breakpoint(target, command == b'Z', len),
+      b'q' if arguments.starts_with(b"Supported") => {
+        self.push(b"PacketSize=");
+        self.push_number(PACKET_SIZE as u64);
+        Ok(())
+      }
+      b'q' if arguments == b"Attached" => {
+        self.push(b"1");
+        Ok(())
+      }
+      _ => Ok(()),
+    };
+    match result {
+      Ok(()) => {}
+      // An empty reply tells the debugger that the packet is not supported.
+      Err(Error::Unsupported) => self.reply_len = 0,
+      Err(error) => {
+        self.reply_len = 0;
+        self.push(match error {
+          Error::InvalidArgument => b"E16",
+          Error::Busy => b"E10",
+          _ => b"E05",
+        });
+      }
+    }
+    None
+  }
+
+  /// Replies with the registers of `target`, or the one at `index`.
+  ///
+  /// # Arguments
+  ///
+  /// * `target` - the code that stopped
+  /// * `index` - the number of the register, if only one is read
+  fn read_registers(
+    &mut self,
+    target: &mut impl Target,
+    index: Option<usize>,
+  ) -> Result<()> {
+    let buffer = &mut self.reply[..PACKET_SIZE / 2];
+    let len = match index {
+      Some(index) => target.register(index, buffer)?,
+      None => target.registers(buffer)?,
+    };
+    self.reply_len = expand_hex(&mut self.reply, len.min(PACKET_SIZE / 2));
+    Ok(())
+  }
+
+  /// Writes the registers of `target` from the `G` packet, or the one from
+  /// the `P` packet, of `len` bytes.
+  ///
+  /// # Arguments
+  ///
+  /// * `target` - the code that stopped
+  /// * `command` - the packet, `G` or `P`
+  /// * `len` - the length of the packet
+  fn write_registers(
+    &mut self,
+    target: &mut impl Target,
+    command: u8,
+    len: usize,
+  ) -> Result<()> {
+    let (index, data) = if command == b'G' {
+      (None, 1)
+    } else {
+      let equals = self.find(b'=', len)?;
+      let index = parse_hex(&self.packet[1..equals]);
+      (Some(index.ok_or(Error::InvalidArgument)?), equals + 1)
+    };
+    let data = decode_hex(&mut self.packet[data..len])?;
+    match index {
+      Some(index) => target.set_register(index as usize, data)?,
+      None => target.set_registers(data)?,
+    }
+    self.push(b"OK");
+    Ok(())
+  }
+
+  /// Replies with the memory that the `m` packet of `len` bytes asks for.
+  ///
+  /// # Arguments
+  ///
+  /// * `target` - the code that stopped
+  /// * `len` - the length of the packet
+  fn read_memory(
+    &mut self,
+    target: &mut impl Target,
+    len: usize,
+  ) -> Result<()> {
+    let (address, size) = self.parse_range(1, len)?;
+    let size = (size as usize).min(PACKET_SIZE / 2);
+    target.read_memory(address, &mut self.reply[..size])?;
+    self.reply_len = expand_hex(&mut self.reply, size);
+    Ok(())
+  }
+
+  /// Writes the memory that the `M` packet of `len` bytes holds.
+  ///
+  /// # Arguments
+  ///
+  /// * `target` - the code that stopped
+  /// * `len` - the length of the packet
+  fn write_memory(
+    &mut self,
+    target: &mut impl Target,
+    len: usize,
+  ) -> Result<()> {
+    let colon = self.find(b':', len)?;
+    let (address, size) = self.parse_range(1, colon)?;
+    let data = decode_hex(&mut self.packet[colon + 1..len])?;
+    if data.len() as u64 != size {
+      return Err(Error::InvalidArgument);
+    }
+    target.write_memory(address, data)?;
+    self.push(b"OK");
+    Ok(())
+  }
+
+  /// Inserts or removes the breakpoint that the `Z` or `z` packet of `len`
+  /// bytes describes.
+  ///
+  /// # Arguments
+  ///
+  /// * `target` - the code that stopped
+  /// * `insert` - whether the breakpoint is inserted
+  /// * `len` - the length of the packet
+  fn change_breakpoint(
+    &mut self,
+    target: &mut impl Target,
+    insert: bool,
+    len: usize,
+  ) -> Result<()> {
+    let kind = match self.packet.get(1..3) {
+      Some(b"0,") => Breakpoint::Software,
+      Some(b"1,") => Breakpoint::Hardware,
+      Some(b"2,") => Breakpoint::Write,
+      Some(b"3,") => Breakpoint::Read,
+      Some(b"4,") => Breakpoint::Access,
+      _ => return Err(Error::Unsupported),
+    };
+    // Conditions and commands after a `;` are evaluated by the debugger.
+    let end = self.find(b';', len).unwrap_or(len);
+    let (address, size) = self.parse_range(3, end)?;
+    match (kind, insert) {
+      (Breakpoint::Software, true) => {
+        self.insert_breakpoint(target, address)?
+      }
+      (Breakpoint::Software, false) => {
+        self.remove_breakpoint(target, address)?
+      }
+      (_, true) => target.set_breakpoint(kind, address, size as usize)?,
+      (_, false) => target.clear_breakpoint(kind, address)?,
+    }
+    self.push(b"OK");
+    Ok(())
+  }
+
+  /// Writes a software breakpoint over the code at `address`, failing with
+  /// [`Error::Busy`] if there are too many already.
+  ///
+  /// # Arguments
+  ///
+  /// * `target` - the code that stopped
+  /// * `address` - the virtual address of the instruction
+  fn insert_breakpoint(
+    &mut self,
+    target: &mut impl Target,
+    address: u64,
+  ) -> Result<()> {
+    let breakpoints = self.breakpoints.iter().flatten();
+    if breakpoints
+      .clone()
+      .any(|inserted| inserted.address == address)
+    {
+      return Ok(());
+    }
+    let slot = self
+      .breakpoints
+      .iter()
+      .position(Option::is_none)
+      .ok_or(Error::Busy)?;
+    let mut original = [0; INSTRUCTION_LEN];
+    target.read_memory(address, &mut original)?;
+    target.write_memory(address, hardware::BREAKPOINT_INSTRUCTION)?;
+    self.breakpoints[slot] = Some(Inserted { address, original });
+    Ok(())
+  }
+
+  /// Restores the code under the software breakpoint at `address`.
+  ///
+  /// # Arguments
+  ///
+  /// * `target` - the code that stopped
+  /// * `address` - the virtual address of the instruction
+  fn remove_breakpoint(
+    &mut self,
+    target: &mut impl Target,
+    address: u64,
+  ) -> Result<()> {
+    let slot = self.breakpoints.iter().position(|inserted| {
+      matches!(inserted, Some(inserted) if inserted.address == address)
+    });
+    let Some(inserted) = slot.and_then(|slot| self.breakpoints[slot].take())
+    else {
+      return Err(Error::NotFound);
+    };
+    target.write_memory(address, &inserted.original)
+  }
+
+  /// Restores the code under every software breakpoint, ignoring failures,
+  /// as the debugger is going away.
+  ///
+  /// # Arguments
+  ///
+  /// * `target` - the code that stopped
+  fn remove_breakpoints(&mut self, target: &mut impl Target) {
+    for inserted in self.breakpoints.iter_mut().filter_map(Option::take) {
+      let _ = target.write_memory(inserted.address, &inserted.original);
+    }
+  }
+
+  /// Reads packets until one arrives intact, which is acknowledged and left
+  /// in `packet`, returning its length.
+  ///
+  /// Packets that fail their checksum are asked for again, and packets that
+  /// are too long are dropped; bytes between packets, such as the interrupt
+  /// that the debugger sends to stop running code, are ignored.
+  fn receive(&mut self) -> Result<usize> {
+    loop {
+      while self.read_byte()? != b'$' {}
+      let (mut len, mut checksum, mut escaped) = (0, 0u8, false);
+      let mut overflowed = false;
+      loop {
+        let byte = self.read_byte()?;
+        if byte == b'#' {
+          break;
+        }
+        checksum = checksum.wrapping_add(byte);
+        if byte == b'}' && !escaped {
+          escaped = true;
+          continue;
+        }
+        let byte = if escaped { byte ^ 0x20 } else { byte };
+        escaped = false;
+        match self.packet.get_mut(len) {
+          Some(slot) => *slot = byte,
+          None => overflowed = true,
+        }
+        len += 1;
+      }
+      let high = hex_digit(self.read_byte()?);
+      let low = hex_digit(self.read_byte()?);
+      let expected = high.zip(low).map(|(high, low)| high << 4 | low);
+      if expected == Some(checksum) && !overflowed {
+        self.port.write(b"+")?;
+        return Ok(len);
+      }
+      self.port.write(b"-")?;
+    }
+  }
+
+  /// Sends the reply until the debugger acknowledges it.
+  fn send(&mut self) -> Result<()> {
+    let reply = &self.reply[..self.reply_len];
+    let checksum = reply.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    let trailer = [
+      b'#',
+      DIGITS[(checksum >> 4) as usize],
+      DIGITS[(checksum & 0xf) as usize],
+    ];
+    loop {
+      self.port.write(b"$")?;
+      self.port.write(&self.reply[..self.reply_len])?;
+      self.port.write(&trailer)?;
+      loop {
+        match self.read_byte()? {
+          b'+' => return Ok(()),
+          b'-' => break,
+          _ => {}
+        }
+      }
+    }
+  }
+
+  /// Waits for the next byte from the debugger.
+  fn read_byte(&mut self) -> Result<u8> {
+    loop {
+      if let Some(byte) = self.port.read_byte()? {
+        return Ok(byte);
+      }
+      core::hint::spin_loop();
+    }
+  }
+
+  /// Returns the index of the first `byte` in the packet of `len` bytes,
+  /// failing with [`Error::InvalidArgument`] if there is none.
+  ///
+  /// # Arguments
+  ///
+  /// * `byte` - the byte to find
+  /// * `len` - the length of the packet
+  fn find(&self, byte: u8, len: usize) -> Result<usize> {
+    self.packet[..len]
+      .iter()
+      .position(|&other| other == byte)
+      .ok_or(Error::InvalidArgument)
+  }
+
+  /// Parses the `address,length` in the packet from `start` to `end`.
+  ///
+  /// # Arguments
+  ///
+  /// * `start` - the index of the address
+  /// * `end` - the index after the length
+  fn parse_range(&self, start: usize, end: usize) -> Result<(u64, u64)> {
+    let range = &self.packet[start..end];
+    let comma = range.iter().position(|&byte| byte == b',');
+    let comma = comma.ok_or(Error::InvalidArgument)?;
+    let address = parse_hex(&range[..comma]);
+    let len = parse_hex(&range[comma + 1..]);
+    address.zip(len).ok_or(Error::InvalidArgument)
+  }
+
+  /// Replies that the target stopped with `signal`.
+  ///
+  /// # Arguments
+  ///
+  /// * `signal` - the signal that describes why
+  fn reply_stop(&mut self, signal: u8) {
+    self.push(&[
+      b'S',
+      DIGITS[(signal >> 4) as usize],
+      DIGITS[(signal & 0xf) as usize],
+    ]);
+  }
+
+  /// Appends `bytes` to the reply, dropping any that do not fit.
+  ///
+  /// # Arguments
+  ///
+  /// * `bytes` - the bytes to append
+  fn push(&mut self, bytes: &[u8]) {
+    for &byte in bytes {
+      if let Some(slot) = self.reply.get_mut(self.reply_len) {
+        *slot = byte;
+        self.reply_len += 1;
+      }
+    }
+  }
+
+  /// Appends `value` to the reply in hexadecimal.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the number to append
+  fn push_number(&mut self, value: u64) {
+    let digits = ((64 - value.leading_zeros()).max(1) + 3) / 4;
+    for digit in (0..digits).rev() {
+      self.push(&[DIGITS[(value >> (4 * digit) & 0xf) as usize]]);
+    }
+  }
+}
+
+/// Parses a number in hexadecimal, as the debugger writes addresses and
+/// lengths.
+///
+/// # Arguments
+///
+/// * `digits` - the digits of the number
+fn parse_hex(digits: &[u8]) -> Option<u64> {
+  if digits.is_empty() || digits.len() > 16 {
+    return None;
+  }
+  digits.iter().try_fold(0, |value, &digit| {
+    Some(value << 4 | hex_digit(digit)? as u64)
+  })
+}
+
+/// Decodes the pairs of hexadecimal digits in `data` in place, returning the
+/// bytes, which are at its start.
+///
+/// # Arguments
+///
+/// * `data` - the digits to decode
+fn decode_hex(data: &mut [u8]) -> Result<&[u8]> {
+  if data.len() % 2 != 0 {
+    return Err(Error::InvalidArgument);
+  }
+  for index in 0..data.len() / 2 {
+    let high = hex_digit(data[2 * index]);
+    let low = hex_digit(data[2 * index + 1]);
+    let (high, low) = high.zip(low).ok_or(Error::InvalidArgument)?;
+    data[index] = high << 4 | low;
+  }
+  Ok(&data[..data.len() / 2])
+}
+
+/// Encodes the first `len` bytes of `buffer` as pairs of hexadecimal digits
+/// in place, returning the length of the digits.
+///
+/// # Arguments
+///
+/// * `buffer` - the buffer holding the bytes, at least twice as long
+/// * `len` - the number of bytes
+fn expand_hex(buffer: &mut [u8], len: usize) -> usize {
+  // The digits are written from the end, so that no byte is overwritten
+  // before it is encoded.
+  for index in (0..len).rev() {
+    let byte = buffer[index];
+    buffer[2 * index] = DIGITS[(byte >> 4) as usize];
+    buffer[2 * index + 1] = DIGITS[(byte & 0xf) as usize];
+  }
+  2 * len
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+  (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::collections::VecDeque;
+  use std::format;
+  use std::string::String;
+  use std::vec::Vec;
+
+  /// A port that reads the debugger's bytes from `input`, failing when there
+  /// are none left, and collects the stub's in `output`.
+  #[derive(Default)]
+  struct Port {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+  }
+
+  impl SerialPort for Port {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+      self.output.extend_from_slice(bytes);
+      Ok(())
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+      self.input.pop_front().map(Some).ok_or(Error::Timeout)
+    }
+  }
+
+  /// A target with 16 bytes of registers and 64 bytes of memory at 0x1000.
+  struct Fake {
+    registers: [u8; 16],
+    memory: [u8; 64],
+  }
+
+  impl Fake {
+    fn new() -> Self {
+      Self {
+        registers: core::array::from_fn(|index| index as u8),
+        memory: core::array::from_fn(|index| 0x40 + index as u8),
+      }
+    }
+
+    fn range(&mut self, address: u64, len: usize) -> Result<&mut [u8]> {
+      let start = address.checked_sub(0x1000).ok_or(Error::InvalidArgument)?;
+      let start = start as usize;
+      self
+        .memory
+        .get_mut(start..start + len)
+        .ok_or(Error::InvalidArgument)
+    }
+  }
+
+  impl Target for Fake {
+    fn registers(&mut self, buffer: &mut [u8]) -> Result<usize> {
+      buffer[..16].copy_from_slice(&self.registers);
+      Ok(16)
+    }
+
+    fn set_registers(&mut self, data: &[u8]) -> Result<()> {
+      self
+        .registers
+        .get_mut(..data.len())
+        .ok_or(Error::InvalidArgument)?
+        .copy_from_slice(data);
+      Ok(())
+    }
+
+    fn read_memory(&mut self, address: u64, buffer: &mut [u8]) -> Result<()> {
+      buffer.copy_from_slice(self.range(address, buffer.len())?);
+      Ok(())
+    }
+
+    fn write_memory(&mut self, address: u64, data: &[u8]) -> Result<()> {
+      self.range(address, data.len())?.copy_from_slice(data);
+      Ok(())
+    }
+  }
+
+  fn packet(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${}#{:02x}", data, checksum)
+  }
+
+  /// Feeds `commands` to a stub, acknowledging every reply, and returns how
+  /// it resumed the target and the replies it sent.
+  fn run(
+    stub: &mut Stub<Port>,
+    target: &mut Fake,
+    commands: &[&str],
+  ) -> (Result<Resume>, Vec<String>) {
+    for command in commands {
+      stub.port.input.extend(packet(command).bytes());
+      stub.port.input.push_back(b'+');
+    }
+    let resume = stub.handle(target, SIGTRAP);
+    let output = String::from_utf8(core::mem::take(&mut stub.port.output));
+    let replies = output
+      .unwrap()
+      .split('$')
+      .skip(1)
+      .map(|reply| String::from(reply.split('#').next().unwrap()))
+      .collect();
+    (resume, replies)
+  }
+
+  #[test]
+  fn packets_are_acknowledged_and_answered() {
+    let mut stub = Stub::new(Port::default());
+    let mut target = Fake::new();
+    stub.port.input.push_back(0x03);
+    let (resume, replies) = run(&mut stub, &mut target, &["?", "qAttached"]);
+    assert_eq!(resume, Err(Error::Timeout));
+    assert_eq!(replies, ["S05", "1"]);
+
+    let (_, replies) = run(&mut stub, &mut target, &["vMustReplyEmpty"]);
+    assert_eq!(replies, [""]);
+    let (_, replies) = run(&mut stub, &mut target, &["qSupported:swbreak+"]);
+    assert_eq!(replies, ["PacketSize=800"]);
+  }
+
+  #[test]
+  fn corrupted_packets_are_asked_for_again() {
+    let mut stub = Stub::new(Port::default());
+    stub.port.input.extend(b"$?#00$?#3f+".iter());
+    assert_eq!(stub.handle(&mut Fake::new(), SIGTRAP), Err(Error::Timeout));
+    assert_eq!(stub.port.output, b"-+$S05#b8");
+  }
+
+  #[test]
+  fn registers_and_memory_are_read_and_written() {
+    let mut stub = Stub::new(Port::default());
+    let mut target = Fake::new();
+    let (_, replies) = run(
+      &mut stub,
+      &mut target,
+      &[
+        "g",
+        "Gffee",
+        "p1",
+        "m1004,4",
+        "M1000,2:aabb",
+        "m1000,3",
+        "m2000,1",
+        "M1000,3:aa",
+      ],
+    );
+    assert_eq!(
+      replies,
+      [
+        "000102030405060708090a0b0c0d0e0f",
+        "OK",
+        "",
+        "44454647",
+        "OK",
+        "aabb42",
+        "E16",
+        "E16",
+      ]
+    );
+    assert_eq!(target.registers[..3], [0xff, 0xee, 0x02]);
+  }
+
+  #[test]
+  fn software_breakpoints_are_written_over_the_code() {
+    let mut stub = Stub::new(Port::default());
+    let mut target = Fake::new();
+    let instruction = hardware::BREAKPOINT_INSTRUCTION;
+    let (resume, replies) = run(
+      &mut stub,
+      &mut target,
+      &["Z0,1008,1", "Z0,1010,1", "z0,1010,1", "z0,1020,1", "c"],
+    );
+    assert_eq!(resume, Ok(Resume::Continue(None)));
+    assert_eq!(replies, ["OK", "OK", "OK", "E05"]);
+    assert_eq!(&target.memory[8..8 + instruction.len()], instruction);
+    assert_eq!(target.memory[0x10], 0x50);
+
+    // The stop is reported when the debugger resumed the target before.
+    let (resume, replies) = run(&mut stub, &mut target, &["D"]);
+    assert_eq!(resume, Ok(Resume::Detach));
+    assert_eq!(replies, ["S05", "OK"]);
+    assert_eq!(target.memory[8], 0x48);
+  }
+
+  #[test]
+  fn resumption_may_give_an_address() {
+    let mut stub = Stub::new(Port::default());
+    let mut target = Fake::new();
+    let (resume, _) = run(&mut stub, &mut target, &["s1004"]);
+    assert_eq!(resume, Ok(Resume::Step(Some(0x1004))));
+    let (resume, _) = run(&mut stub, &mut target, &["k"]);
+    assert_eq!(resume, Ok(Resume::Kill));
+    let (_, replies) = run(&mut stub, &mut target, &["Z1,1000,1"]);
+    assert_eq!(replies, ["S05", ""]);
+  }
+}
@@ -0,0 +1,903 @@
+//! This module provides reading, creation and modification of GUID partition
+//! tables over any [`BlockDevice`].
+//!
+//! A disk holds two copies of its table: the primary, whose header is in the
+//! second block and whose entries follow it, and the backup, whose entries
+//! precede its header in the last block. Both headers and both entry arrays
+//! are checked against their CRC32s when a table is opened, and the backup is
+//! read if the primary is damaged. A table with a damaged copy is repaired
+//! before it is next changed, and every change is written to both copies, the
+//! backup first, so that a write that is interrupted leaves the primary
+//! intact.
+//!
+//! Entry arrays are streamed a block at a time, so that nothing is allocated;
+//! blocks are at most [`MAX_BLOCK_SIZE`] bytes.
+
+use crate::block::BlockDevice;
+use crate::bytes::{read_u32, read_u64};
+use crate::checksum::Crc32;
+use crate::error::{Error, Result};
+use crate::guid::Guid;
+
+/// The largest block size of the devices that tables are read from.
+pub const MAX_BLOCK_SIZE: usize = 4096;
+
+/// The size of the partition entries of the tables that are created.
+pub const ENTRY_SIZE: u32 = 128;
+
+/// The number of partition entries of the tables that partitioning tools
+/// create, which fill 16 KiB.
+pub const DEFAULT_ENTRY_COUNT: u32 = 128;
+
+/// A partition attribute marking the partition as required by the platform.
+pub const ATTRIBUTE_REQUIRED: u64 = 1 << 0;
+
+/// A partition attribute telling firmware not to produce Block I/O for the
+/// partition.
+pub const ATTRIBUTE_NO_BLOCK_IO: u64 = 1 << 1;
+
+/// A partition attribute marking the partition as bootable by legacy BIOS.
+pub const ATTRIBUTE_LEGACY_BIOS_BOOTABLE: u64 = 1 << 2;
+
+/// The signature that starts a GPT header.
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+
+/// The revision of the headers that are written, 1.0.
+const REVISION: u32 = 0x0001_0000;
+
+/// The size of the fields of a GPT header, which may be followed by reserved
+/// space.
+const HEADER_SIZE: usize = 92;
+
+/// The offset of the header's own CRC32 within it.
+const HEADER_CRC_OFFSET: usize = 16;
+
+/// The smallest size of a partition entry.
+const MIN_ENTRY_SIZE: u32 = 128;
+
+/// The block of the primary header.
+const PRIMARY_LBA: u64 = 1;
+
+/// The number of UTF-16 code units of the name of a partition.
+const NAME_LEN: usize = 36;
+
+/// The header of one copy of a GUID partition table.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Header {
+  /// The block holding this header.
+  pub current_lba: u64,
+
+  /// The block holding the other copy's header.
+  pub backup_lba: u64,
+
+  /// The first block that partitions may use.
+  pub first_usable_lba: u64,
+
+  /// The last block that partitions may use, inclusive.
+  pub last_usable_lba: u64,
+
+  /// The GUID of the disk.
+  pub disk_guid: Guid,
+
+  /// The first block of this copy's partition entries.
+  pub entries_lba: u64,
+
+  /// The number of partition entries.
+  pub entry_count: u32,
+
+  /// The size of each partition entry.
+  pub entry_size: u32,
+
+  /// The CRC32 of the partition entries.
+  pub entries_crc: u32,
+}
+
+impl Header {
+  /// Parses the header at the start of `block`, failing with
+  /// [`Error::Corrupted`] if it has no signature, or its CRC32 does not match.
+  ///
+  /// # Arguments
+  ///
+  /// * `block` - the block holding the header
+  pub fn parse(block: &[u8]) -> Result<Self> {
+    if block.len() < HEADER_SIZE || block[..8] != *SIGNATURE {
+      return Err(Error::Corrupted);
+    }
+    let size = read_u32(block, 12) as usize;
+    if !(HEADER_SIZE..=block.len()).contains(&size) {
+      return Err(Error::Corrupted);
+    }
+    // The CRC32 covers the header with its own CRC32 zeroed.
+    let mut crc = Crc32::new();
+    crc.update(&block[..HEADER_CRC_OFFSET]);
+    crc.update(&[0; 4]);
+    crc.update(&block[HEADER_CRC_OFFSET + 4..size]);
+    if crc.finish() != read_u32(block, HEADER_CRC_OFFSET) {
+      return Err(Error::Corrupted);
+    }
+
+    Ok(Self {
+      current_lba: read_u64(block, 24),
+      backup_lba: read_u64(block, 32),
+      first_usable_lba: read_u64(block, 40),
+      last_usable_lba: read_u64(block, 48),
+      disk_guid: Guid::from_bytes(read_guid(block, 56)),
+      entries_lba: read_u64(block, 72),
+      entry_count: read_u32(block, 80),
+      entry_size: read_u32(block, 84),
+      entries_crc: read_u32(block, 88),
+    })
+  }
+
+  /// Writes the header, with its CRC32, to the start of `block`, zeroing the
+  /// rest of it.
+  ///
+  /// # Arguments
+  ///
+  /// * `block` - the block to hold the header
+  ///
+  /// # Panics
+  ///
+  /// Panics if `block` is smaller than a header.
+  pub fn write(&self, block: &mut [u8]) {
+    block.fill(0);
+    block[..8].copy_from_slice(SIGNATURE);
+    block[8..12].copy_from_slice(&REVISION.to_le_bytes());
+    block[12..16].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+    block[24..32].copy_from_slice(&self.current_lba.to_le_bytes());
+    block[32..40].copy_from_slice(&self.backup_lba.to_le_bytes());
+    block[40..48].copy_from_slice(&self.first_usable_lba.to_le_bytes());
+    block[48..56].copy_from_slice(&self.last_usable_lba.to_le_bytes());
+    block[56..72].copy_from_slice(&self.disk_guid.to_bytes());
+    block[72..80].copy_from_slice(&self.entries_lba.to_le_bytes());
+    block[80..84].copy_from_slice(&self.entry_count.to_le_bytes());
+    block[84..88].copy_from_slice(&self.entry_size.to_le_bytes());
+    block[88..92].copy_from_slice(&self.entries_crc.to_le_bytes());
+    let mut crc = Crc32::new();
+    crc.update(&block[..HEADER_SIZE]);
+    block[16..20].copy_from_slice(&crc.finish().to_le_bytes());
+  }
+
+  /// Returns the number of blocks of `block_size` bytes that the partition
+  /// entries fill.
+  ///
+  /// # Arguments
+  ///
+  /// * `block_size` - the size of the device's blocks
+  pub fn entry_blocks(&self, block_size: usize) -> u64 {
+    let size = self.entry_count as u64 * self.entry_size as u64;
+    (size + block_size as u64 - 1) / block_size as u64
+  }
+
+  /// Returns the header of the other copy of the table, whose entries are
+  /// laid out as partitioning tools lay them out: right after the primary
+  /// header, and right before the backup header.
+  pub fn mirror(&self) -> Self {
+    let entries_lba = if self.current_lba == PRIMARY_LBA {
+      self.last_usable_lba + 1
+    } else {
+      PRIMARY_LBA + 1
+    };
+    Self {
+      current_lba: self.backup_lba,
+      backup_lba: self.current_lba,
+      entries_lba,
+      ..*self
+    }
+  }
+}
+
+/// An entry of a GUID partition table, describing a partition as a range of
+/// blocks.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Partition {
+  /// The GUID of the type of the partition, or [`Guid::NULL`] if the entry is
+  /// unused.
+  pub type_guid: Guid,
+
+  /// The GUID of the partition itself.
+  pub unique_guid: Guid,
+
+  /// The first block of the partition.
+  pub first_lba: u64,
+
+  /// The last block of the partition, inclusive.
+  pub last_lba: u64,
+
+  /// The `ATTRIBUTE_*` attributes of the partition.
+  pub attributes: u64,
+
+  /// The name of the partition, in UTF-16, padded with NULs.
+  pub name: [u16; NAME_LEN],
+}
+
+impl Partition {
+  /// An unused entry.
+  pub const EMPTY: Self = Self::new(Guid::NULL, Guid::NULL, 0, 0);
+
+  /// Constructs an unnamed partition without attributes.
+  ///
+  /// # Arguments
+  ///
+  /// * `type_guid` - the GUID of the type of the partition
+  /// * `unique_guid` - the GUID of the partition itself
+  /// * `first_lba` - the first block of the partition
+  /// * `last_lba` - the last block of the partition, inclusive
+  pub const fn new(
+    type_guid: Guid,
+    unique_guid: Guid,
+    first_lba: u64,
+    last_lba: u64,
+  ) -> Self {
+    Self {
+      type_guid,
+      unique_guid,
+      first_lba,
+      last_lba,
+      attributes: 0,
+      name: [0; NAME_LEN],
+    }
+  }
+
+  /// Parses the partition entry `entry`.
+  ///
+  /// # Arguments
+  ///
+  /// * `entry` - the partition entry, of at least 128 bytes
+  pub fn parse(entry: &[u8]) -> Self {
+    let mut name = [0; NAME_LEN];
+    for (unit, bytes) in name.iter_mut().zip(entry[56..128].chunks_exact(2)) {
+      *unit = u16::from_le_bytes([bytes[0], bytes[1]]);
+    }
+    Self {
+      type_guid: Guid::from_bytes(read_guid(entry, 0)),
+      unique_guid: Guid::from_bytes(read_guid(entry, 16)),
+      first_lba: read_u64(entry, 32),
+      last_lba: read_u64(entry, 40),
+      attributes: read_u64(entry, 48),
+      name,
+    }
+  }
+
+  /// Writes the partition entry to `entry`, zeroing anything past its fields.
+  ///
+  /// # Arguments
+  ///
+  /// * `entry` - the partition entry, of at least 128 bytes
+  pub fn write(&self, entry: &mut [u8]) {
+    entry.fill(0);
+    entry[..16].copy_from_slice(&self.type_guid.to_bytes());
+    entry[16..32].copy_from_slice(&self.unique_guid.to_bytes());
+    entry[32..40].copy_from_slice(&self.first_lba.to_le_bytes());
+    entry[40..48].copy_from_slice(&self.last_lba.to_le_bytes());
+    entry[48..56].copy_from_slice(&self.attributes.to_le_bytes());
+    for (bytes, unit) in entry[56..128].chunks_exact_mut(2).zip(self.name) {
+      bytes.copy_from_slice(&unit.to_le_bytes());
+    }
+  }
+
+  /// Returns whether the entry describes a partition.
+  pub fn is_used(&self) -> bool {
+    !self.type_guid.is_null()
+  }
+
+  /// Returns the number of blocks of the partition.
+  pub fn block_count(&self) -> u64 {
+    (self.last_lba + 1).saturating_sub(self.first_lba)
+  }
+
+  /// Returns an iterator over the characters of the name of the partition,
+  /// with any that are not valid UTF-16 replaced.
+  pub fn name(&self) -> impl Iterator<Item = char> + '_ {
+    let len = self.name.iter().position(|&unit| unit == 0);
+    char::decode_utf16(self.name[..len.unwrap_or(NAME_LEN)].iter().copied())
+      .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+  }
+
+  /// Sets the name of the partition, failing with [`Error::InvalidArgument`]
+  /// if it is longer than 36 UTF-16 code units.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - the name of the partition
+  pub fn set_name(&mut self, name: &str) -> Result<()> {
+    let mut units = [0; NAME_LEN];
+    let mut encoded = name.encode_utf16();
+    for (unit, encoded) in units.iter_mut().zip(&mut encoded) {
+      *unit = encoded;
+    }
+    if encoded.next().is_some() {
+      return Err(Error::InvalidArgument);
+    }
+    self.name = units;
+    Ok(())
+  }
+}
+
+/// A GUID partition table of a block device.
+pub struct Gpt<D> {
+  device: D,
+  block_size: usize,
+  header: Header,
+  damaged: bool,
+}
+
+impl<D: BlockDevice> Gpt<D> {
+  /// Opens the partition table of `device`, reading the backup if the
+  /// primary is damaged.
+  ///
+  /// The table is [damaged](Gpt::is_damaged) if either copy fails its checks,
+  /// or if the backup does not mirror the primary.
+  ///
+  /// Fails with [`Error::Corrupted`] if neither copy is valid, and with
+  /// [`Error::Unsupported`] if the device's blocks are smaller than 512 bytes
+  /// or larger than [`MAX_BLOCK_SIZE`].
+  ///
+  /// # Arguments
+  ///
+  /// * `device` - the whole disk
+  pub fn open(mut device: D) -> Result<Self> {
+    let block_size = block_size(&device)?;
+    let (header, damaged) = match read_copy(&mut device, PRIMARY_LBA) {
+      Ok(header) => {
+        let backup = match read_copy(&mut device, header.backup_lba) {
+          Ok(backup) => Some(backup),
+          Err(Error::Corrupted) => None,
+          Err(error) => return Err(error),
+        };
+        (header, backup != Some(header.mirror()))
+      }
+      Err(Error::Corrupted) => {
+        let last = device.block_count().saturating_sub(1);
+        (read_copy(&mut device, last)?, true)
+      }
+      Err(error) => return Err(error),
+    };
+    Ok(Self {
+      device,
+      block_size,
+      header,
+      damaged,
+    })
+  }
+
+  /// Creates an empty partition table on `device`, with a protective MBR, and
+  /// opens it.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if the device is too small for
+  /// the table, and with [`Error::Unsupported`] if its blocks are smaller than
+  /// 512 bytes or larger than [`MAX_BLOCK_SIZE`].
+  ///
+  /// # Arguments
+  ///
+  /// * `device` - the whole disk, whose partitioning is lost
+  /// * `disk_guid` - the GUID of the disk
+  /// * `entry_count` - the number of partition entries, such as
+  ///   [`DEFAULT_ENTRY_COUNT`]
+  pub fn create(
+    mut device: D,
+    disk_guid: Guid,
+    entry_count: u32,
+  ) -> Result<Self> {
+    let block_size = block_size(&device)?;
+    let last = device
+      .block_count()
+      .checked_sub(1)
+      .ok_or(Error::InvalidArgument)?;
+    let mut header = Header {
+      current_lba: PRIMARY_LBA,
+      backup_lba: last,
+      first_usable_lba: 0,
+      last_usable_lba: 0,
+      disk_guid,
+      entries_lba: PRIMARY_LBA + 1,
+      entry_count,
+      entry_size: ENTRY_SIZE,
+      entries_crc: 0,
+    };
+    let entry_blocks = header.entry_blocks(block_size);
+    header.first_usable_lba = PRIMARY_LBA + 1 + entry_blocks;
+    header.last_usable_lba = last
+      .checked_sub(entry_blocks + 1)
+      .filter(|&lba| lba >= header.first_usable_lba)
+      .ok_or(Error::InvalidArgument)?;
+
+    let mut block = [0; MAX_BLOCK_SIZE];
+    let block = &mut block[..block_size];
+    write_protective_mbr(block, last);
+    device.write_blocks(0, block)?;
+
+    let mut gpt = Self {
+      device,
+      block_size,
+      header,
+      damaged: false,
+    };
+    block.fill(0);
+    for copy in gpt.copies() {
+      for lba in copy.entries_lba..copy.entries_lba + entry_blocks {
+        gpt.device.write_blocks(lba, block)?;
+      }
+    }
+    gpt.header.entries_crc = entries_crc(&mut gpt.device, &gpt.header)?;
+    gpt.write_headers()?;
+    gpt.device.flush()?;
+    Ok(gpt)
+  }
+
+  /// Returns the header of the copy of the table being read, which is the
+  /// primary unless it is [damaged](Gpt::is_damaged).
+  pub fn header(&self) -> &Header {
+    &self.header
+  }
+
+  /// Returns whether one copy of the table is damaged, in which case it is
+  /// [repaired](Gpt::repair) before the table is next changed.
+  pub fn is_damaged(&self) -> bool {
+    self.damaged
+  }
+
+  /// Returns the device that the table is on.
+  pub fn device(&self) -> &D {
+    &self.device
+  }
+
+  /// Returns the device that the table is on, closing the table.
+  pub fn into_inner(self) -> D {
+    self.device
+  }
+
+  /// Returns the partition entry at `index`, which may be unused, failing
+  /// with [`Error::InvalidArgument`] if there is no such entry.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the entry
+  pub fn partition(&mut self, index: u32) -> Result<Partition> {
+    let mut block = [0; MAX_BLOCK_SIZE];
+    let (lba, offset) = self.locate(&self.header, index)?;
+    let block = &mut block[..self.block_size];
+    self.device.read_blocks(lba, block)?;
+    Ok(Partition::parse(&block[offset..]))
+  }
+
+  /// Returns the index and entry of the first used partition for which `f`
+  /// returns `true`, if there is one.
+  ///
+  /// # Arguments
+  ///
+  /// * `f` - decides whether the partition is the one to find
+  pub fn find(
+    &mut self,
+    mut f: impl FnMut(&Partition) -> bool,
+  ) -> Result<Option<(u32, Partition)>> {
+    self.scan(|_, partition| partition.is_used() && f(partition))
+  }
+
+  /// Sets the partition entry at `index` to `partition`, in both copies of
+  /// the table, failing with [`Error::InvalidArgument`] if there is no such
+  /// entry.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the entry
+  /// * `partition` - the new contents of the entry
+  pub fn set_partition(
+    &mut self,
+    index: u32,
+    partition: &Partition,
+  ) -> Result<()> {
+    self.locate(&self.header, index)?;
+    if self.damaged {
+      self.repair()?;
+    }
+    let mut block = [0; MAX_BLOCK_SIZE];
+    let block = &mut block[..self.block_size];
+    let entry_size = self.header.entry_size as usize;
+    for copy in self.copies() {
+      let (lba, offset) = self.locate(&copy, index)?;
+      self.device.read_blocks(lba, block)?;
+      partition.write(&mut block[offset..offset + entry_size]);
+      self.device.write_blocks(lba, block)?;
+    }
+    self.header.entries_crc = entries_crc(&mut self.device, &self.header)?;
+    self.write_headers()?;
+    self.device.flush()
+  }
+
+  /// Adds `partition` to the first unused entry, returning its index.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if the partition is unused, or not
+  /// within the usable blocks of the disk, with [`Error::AlreadyExists`] if
+  /// it overlaps another partition, and with [`Error::NoMemory`] if every
+  /// entry is used.
+  ///
+  /// # Arguments
+  ///
+  /// * `partition` - the partition to add
+  pub fn add_partition(&mut self, partition: &Partition) -> Result<u32> {
+    let usable = self.header.first_usable_lba..=self.header.last_usable_lba;
+    if !partition.is_used()
+      || partition.first_lba > partition.last_lba
+      || !usable.contains(&partition.first_lba)
+      || !usable.contains(&partition.last_lba)
+    {
+      return Err(Error::InvalidArgument);
+    }
+    let mut free = None;
+    let overlap = self.scan(|index, existing| {
+      if !existing.is_used() {
+        free.get_or_insert(index);
+        return false;
+      }
+      existing.first_lba <= partition.last_lba
+        && partition.first_lba <= existing.last_lba
+    })?;
+    if overlap.is_some() {
+      return Err(Error::AlreadyExists);
+    }
+    let index = free.ok_or(Error::NoMemory)?;
+    self.set_partition(index, partition)?;
+    Ok(index)
+  }
+
+  /// Marks the partition entry at `index` unused, failing with
+  /// [`Error::InvalidArgument`] if there is no such entry.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the index of the entry
+  pub fn remove_partition(&mut self, index: u32) -> Result<()> {
+    self.set_partition(index, &Partition::EMPTY)
+  }
+
+  /// Rewrites the damaged copy of the table from the one being read.
+  pub fn repair(&mut self) -> Result<()> {
+    let mut block = [0; MAX_BLOCK_SIZE];
+    let block = &mut block[..self.block_size];
+    let other = self.header.mirror();
+    for i in 0..self.header.entry_blocks(self.block_size) {
+      self
+        .device
+        .read_blocks(self.header.entries_lba + i, block)?;
+      self.device.write_blocks(other.entries_lba + i, block)?;
+    }
+    self.write_headers()?;
+    self.damaged = false;
+    self.device.flush()
+  }
+
+  /// Returns the headers of both copies of the table, the backup first.
+  fn copies(&self) -> [Header; 2] {
+    if self.header.current_lba == PRIMARY_LBA {
+      [self.header.mirror(), self.header]
+    } else {
+      [self.header, self.header.mirror()]
+    }
+  }
+
+  /// Writes the headers of both copies of the table, the backup first.
+  fn write_headers(&mut self) -> Result<()> {
+    let mut block = [0; MAX_BLOCK_SIZE];
+    let block = &mut block[..self.block_size];
+    for copy in self.copies() {
+      copy.write(block);
+      self.device.write_blocks(copy.current_lba, block)?;
+    }
+    Ok(())
+  }
+
+  /// Returns the block holding the entry at `index` of the copy of `header`,
+  /// and the offset of the entry within it.
+  ///
+  /// # Arguments
+  ///
+  /// * `header` - the header of a copy of the table
+  /// * `index` - the index of the entry
+  fn locate(&self, header: &Header, index: u32) -> Result<(u64, usize)> {
+    if index >= header.entry_count {
+      return Err(Error::InvalidArgument);
+    }
+    let offset = index as u64 * header.entry_size as u64;
+    let block_size = self.block_size as u64;
+    Ok((
+      header.entries_lba + offset / block_size,
+      (offset % block_size) as usize,
+    ))
+  }
+
+  /// Calls `f` with the index and contents of each partition entry, used or
+  /// not, until it returns `true`, returning that entry.
+  ///
+  /// # Arguments
+  ///
+  /// * `f` - decides whether to stop at the entry
+  fn scan(
+    &mut self,
+    mut f: impl FnMut(u32, &Partition) -> bool,
+  ) -> Result<Option<(u32, Partition)>> {
+    let mut block = [0; MAX_BLOCK_SIZE];
+    let block = &mut block[..self.block_size];
+    let entry_size = self.header.entry_size as usize;
+    let mut index = 0;
+    for i in 0..self.header.entry_blocks(self.block_size) {
+      self
+        .device
+        .read_blocks(self.header.entries_lba + i, block)?;
+      for entry in block.chunks_exact(entry_size) {
+        if index == self.header.entry_count {
+          break;
+        }
+        let partition = Partition::parse(entry);
+        if f(index, &partition) {
+          return Ok(Some((index, partition)));
+        }
+        index += 1;
+      }
+    }
+    Ok(None)
+  }
+}
+
+/// Returns the block size of `device`, failing with [`Error::Unsupported`] if
+/// it is not a power of two from 512 to [`MAX_BLOCK_SIZE`] bytes.
+///
+/// # Arguments
+///
+/// * `device` - the device
+fn block_size(device: &impl BlockDevice) -> Result<usize> {
+  let size = device.block_size();
+  if !size.is_power_of_two() || !(512..=MAX_BLOCK_SIZE).contains(&size) {
+    return Err(Error::Unsupported);
+  }
+  Ok(size)
+}
+
+/// Reads and validates the copy of the table whose header is at `lba`,
+/// failing with [`Error::Corrupted`] if it is damaged.
+///
+/// # Arguments
+///
+/// * `device` - the whole disk
+/// * `lba` - the block of the header of the copy
+fn read_copy(device: &mut impl BlockDevice, lba: u64) -> Result<Header> {
+  let block_size = device.block_size();
+  let mut block = [0; MAX_BLOCK_SIZE];
+  let block = &mut block[..block_size];
+  device.read_blocks(lba, block)?;
+  let header = Header::parse(block)?;
+
+  let entry_size = header.entry_size;
+  let valid = header.current_lba == lba
+    && entry_size >= MIN_ENTRY_SIZE
+    && entry_size.is_power_of_two()
+    && entry_size as usize <= block_size
+    && header.first_usable_lba <= header.last_usable_lba.saturating_add(1)
+    && header.last_usable_lba < device.block_count()
+    && header
+      .entries_lba
+      .checked_add(header.entry_blocks(block_size))
+      .is_some_and(|end| end <= device.block_count());
+  if !valid || entries_crc(device, &header)? != header.entries_crc {
+    return Err(Error::Corrupted);
+  }
+  Ok(header)
+}
+
+/// Computes the CRC32 of the partition entries of the copy of `header`.
+///
+/// # Arguments
+///
+/// * `device` - the whole disk
+/// * `header` - the header of a copy of the table
+fn entries_crc(device: &mut impl BlockDevice, header: &Header) -> Result<u32> {
+  let block_size = device.block_size();
+  let mut block = [0; MAX_BLOCK_SIZE];
+  let block = &mut block[..block_size];
+  let mut crc = Crc32::new();
+  let mut remaining = header.entry_count as usize * header.entry_size as usize;
+  for i in 0..header.entry_blocks(block_size) {
+    device.read_blocks(header.entries_lba + i, block)?;
+    let len = remaining.min(block_size);
+    crc.update(&block[..len]);
+    remaining -= len;
+  }
+  Ok(crc.finish())
+}
+
+/// Fills `block` with a protective MBR, which marks the whole disk as used by
+/// a single partition of type `0xee` for tools that do not know GPT.
+///
+/// # Arguments
+///
+/// * `block` - the first block of the disk
+/// * `last` - the last block of the disk
+fn write_protective_mbr(block: &mut [u8], last: u64) {
+  block.fill(0);
+  let entry = &mut block[446..462];
+  entry[..8].copy_from_slice(&[0x00, 0x00, 0x02, 0x00, 0xee, 0xff, 0xff, 0xff]);
+  entry[8..12].copy_from_slice(&(PRIMARY_LBA as u32).to_le_bytes());
+  entry[12..16]
+    .copy_from_slice(&(last.min(u32::MAX as u64) as u32).to_le_bytes());
+  block[510..512].copy_from_slice(&[0x55, 0xaa]);
+}
+
+fn read_guid(data: &[u8], offset: usize) -> [u8; 16] {
+  let mut bytes = [0; 16];
+  bytes.copy_from_slice(&data[offset..offset + 16]);
+  bytes
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::string::String;
+  use std::vec::Vec;
+
+  /// A disk in memory.
+  struct Memory {
+    blocks: Vec<u8>,
+    block_size: usize,
+  }
+
+  impl Memory {
+    fn new(block_size: usize, block_count: usize) -> Self {
+      Self {
+        blocks: std::vec![0; block_size * block_count],
+        block_size,
+      }
+    }
+  }
+
+  impl BlockDevice for Memory {
+    fn block_size(&self) -> usize {
+      self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+      (self.blocks.len() / self.block_size) as u64
+    }
+
+    fn read_blocks(&mut self, lba: u64, buffer: &mut [u8]) -> Result<()> {
+      let start = lba as usize * self.block_size;
+      let blocks = self.blocks.get(start..start + buffer.len());
+      buffer.copy_from_slice(blocks.ok_or(Error::InvalidArgument)?);
+      Ok(())
+    }
+
+    fn write_blocks(&mut self, lba: u64, buffer: &[u8]) -> Result<()> {
+      let start = lba as usize * self.block_size;
+      let blocks = self.blocks.get_mut(start..start + buffer.len());
+      blocks
+        .ok_or(Error::InvalidArgument)?
+        .copy_from_slice(buffer);
+      Ok(())
+    }
+  }
+
+  const DISK: Guid = Guid::parse("6f3a1d0c-5b2e-4c8a-9e7f-1a2b3c4d5e6f");
+  const ROOT: Guid = Guid::parse("0fc63daf-8483-4772-8e79-3d69d8477de4");
+
+  #[test]
+  fn created_tables_are_opened() {
+    let mut disk = Memory::new(512, 1024);
+    let gpt = Gpt::create(&mut disk, DISK, DEFAULT_ENTRY_COUNT).unwrap();
+    let header = *gpt.header();
+    assert_eq!((header.current_lba, header.backup_lba), (1, 1023));
+    assert_eq!((header.first_usable_lba, header.last_usable_lba), (34, 990));
+    assert_eq!(disk.blocks[450], 0xee);
+    assert_eq!(disk.blocks[510..512], [0x55, 0xaa]);
+
+    let mut gpt = Gpt::open(&mut disk).unwrap();
+    assert_eq!(*gpt.header(), header);
+    assert!(!gpt.is_damaged());
+    assert_eq!(gpt.find(|_| true), Ok(None));
+    assert_eq!(gpt.partition(127), Ok(Partition::EMPTY));
+    assert_eq!(gpt.partition(128), Err(Error::InvalidArgument));
+  }
+
+  #[test]
+  fn partitions_are_added_found_and_removed() {
+    let mut disk = Memory::new(512, 1024);
+    let mut gpt = Gpt::create(&mut disk, DISK, 4).unwrap();
+    let mut esp = Partition::new(Guid::ESP_TYPE, DISK, 34, 99);
+    esp.set_name("EFI system partition").unwrap();
+    assert_eq!(gpt.add_partition(&esp), Ok(0));
+    let root = Partition::new(ROOT, ROOT, 100, 500);
+    assert_eq!(gpt.add_partition(&root), Ok(1));
+
+    let overlapping = Partition::new(ROOT, ROOT, 500, 600);
+    assert_eq!(gpt.add_partition(&overlapping), Err(Error::AlreadyExists));
+    let outside = Partition::new(ROOT, ROOT, 600, 1022);
+    assert_eq!(gpt.add_partition(&outside), Err(Error::InvalidArgument));
+
+    let mut gpt = Gpt::open(gpt.into_inner()).unwrap();
+    let (index, found) = gpt
+      .find(|partition| partition.type_guid == Guid::ESP_TYPE)
+      .unwrap()
+      .unwrap();
+    assert_eq!((index, found.block_count()), (0, 66));
+    assert_eq!(found.name().collect::<String>(), "EFI system partition");
+
+    gpt.remove_partition(0).unwrap();
+    assert_eq!(gpt.find(|p| p.type_guid == Guid::ESP_TYPE), Ok(None));
+    assert_eq!(gpt.add_partition(&esp), Ok(0));
+  }
+
+  #[test]
+  fn the_backup_is_read_and_repaired() {
+    let mut disk = Memory::new(512, 256);
+    let mut gpt = Gpt::create(&mut disk, DISK, DEFAULT_ENTRY_COUNT).unwrap();
+    gpt
+      .add_partition(&Partition::new(ROOT, ROOT, 40, 80))
+      .unwrap();
+
+    // Damage the primary entries.
+    disk.blocks[2 * 512] ^= 0xff;
+    let mut gpt = Gpt::open(&mut disk).unwrap();
+    assert!(gpt.is_damaged());
+    assert_eq!(gpt.header().current_lba, 255);
+    assert_eq!(gpt.partition(0).unwrap().first_lba, 40);
+    gpt.repair().unwrap();
+
+    let mut gpt = Gpt::open(&mut disk).unwrap();
+    assert!(!gpt.is_damaged());
+    assert_eq!(gpt.partition(0).unwrap().first_lba, 40);
+
+    // Damage both headers.
+    disk.blocks[512] ^= 0xff;
+    disk.blocks[255 * 512] ^= 0xff;
+    assert_eq!(Gpt::open(&mut disk).err(), Some(Error::Corrupted));
+  }
+
+  #[test]
+  fn damaged_backups_are_repaired_before_changes() {
+    let mut disk = Memory::new(512, 256);
+    let mut gpt = Gpt::create(&mut disk, DISK, DEFAULT_ENTRY_COUNT).unwrap();
+    gpt
+      .add_partition(&Partition::new(ROOT, ROOT, 40, 80))
+      .unwrap();
+
+    // Damage the backup entries.
+    disk.blocks[223 * 512] ^= 0xff;
+    let mut gpt = Gpt::open(&mut disk).unwrap();
+    assert!(gpt.is_damaged());
+    assert_eq!(gpt.header().current_lba, 1);
+    gpt
+      .add_partition(&Partition::new(ROOT, ROOT, 100, 120))
+      .unwrap();
+
+    // Damage the primary header, leaving the repaired backup.
+    disk.blocks[512] ^= 0xff;
+    let mut gpt = Gpt::open(&mut disk).unwrap();
+    assert!(gpt.is_damaged());
+    assert_eq!(gpt.header().current_lba, 255);
+    assert_eq!(gpt.partition(0).unwrap().first_lba, 40);
+    assert_eq!(gpt.partition(1).unwrap().first_lba, 100);
+  }
+
+  #[test]
+  fn headers_ending_at_the_last_lba_are_rejected() {
+    let mut disk = Memory::new(512, 256);
+    let gpt = Gpt::create(&mut disk, DISK, DEFAULT_ENTRY_COUNT).unwrap();
+    let mut header = *gpt.header();
+    header.last_usable_lba = u64::MAX;
+    header.write(&mut disk.blocks[512..1024]);
+
+    let gpt = Gpt::open(&mut disk).unwrap();
+    assert!(gpt.is_damaged());
+    assert_eq!(gpt.header().current_lba, 255);
+  }
+
+  #[test]
+  fn names_longer_than_an_entry_are_rejected() {
+    let mut partition = Partition::EMPTY;
+    assert!(partition.set_name(&"x".repeat(36)).is_ok());
+    assert_eq!(
+      partition.set_name(&"x".repeat(37)),
+      Err(Error::InvalidArgument)
+    );
+  }
+}
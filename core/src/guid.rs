@@ -0,0 +1,279 @@
+//! This module provides the [`Guid`] type, which identifies protocols and
+//! other items in UEFI, partitions and their types in GUID partition tables,
+//! and file systems.
+//!
+//! A GUID is written in its canonical form as 32 hexadecimal digits in
+//! hyphenated groups, most significant first, but is stored in memory and on
+//! disk with its first three groups little-endian; see [`Guid::from_bytes`].
+
+use core::fmt;
+
+/// A globally unique identifier, laid out as in the UEFI specification.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Guid {
+  data1: u32,
+  data2: u16,
+  data3: u16,
+  data4: [u8; 8],
+}
+
+crate::const_assert_size!(Guid, 16);
+crate::const_assert_offset!(Guid, data1: 0, data2: 4, data3: 6, data4: 8);
+
+impl Guid {
+  /// The all-zero GUID, which marks unused entries, such as those of a GUID
+  /// partition table.
+  pub const NULL: Self = Self {
+    data1: 0,
+    data2: 0,
+    data3: 0,
+    data4: [0; 8],
+  };
+
+  /// The partition type GUID of the EFI system partition, which holds the
+  /// bootloader.
+  pub const ESP_TYPE: Self =
+    Self::parse("c12a7328-f81f-11d2-ba4b-00a0c93ec93b");
+
+  /// The partition type GUID of the partition holding the kernel's root file
+  /// system, which the bootloader can read the kernel from.
+  pub const KERNEL_PARTITION_TYPE: Self =
+    Self::parse("9df4d67f-01a9-49ec-9be6-183bda7d09c5");
+
+  /// Parses a [`Guid`] from its canonical form, such as
+  /// `"964e5b22-6459-11d2-8e39-00a0c969723b"`.
+  ///
+  /// This is meant for constants, where a malformed GUID fails to compile.
+  ///
+  /// # Arguments
+  ///
+  /// * `text` - the GUID in its canonical form
+  ///
+  /// # Panics
+  ///
+  /// Panics if `text` is not a GUID in its canonical form.
+  pub const fn parse(text: &str) -> Self {
+    match Self::try_parse(text) {
+      Some(guid) => guid,
+      None => panic!("a GUID is 32 hexadecimal digits in hyphenated groups"),
+    }
+  }
+
+  /// Parses a [`Guid`] from its canonical form, such as
+  /// `"964e5b22-6459-11d2-8e39-00a0c969723b"`, returning [`None`] if `text`
+  /// is not a GUID in its canonical form.
+  ///
+  /// # Arguments
+  ///
+  /// * `text` - the GUID in its canonical form
+  pub const fn try_parse(text: &str) -> Option<Self> {
+    let text = text.as_bytes();
+    if text.len() != 36
+      || text[8] != b'-'
+      || text[13] != b'-'
+      || text[18] != b'-'
+      || text[23] != b'-'
+    {
+      return None;
+    }
+
+    // The bytes are collected in the order they are written, which is
+    // big-endian for every group.
+    let mut bytes = [0; 16];
+    let (mut i, mut position) = (0, 0);
+    while i < 16 {
+      if text[position] == b'-' {
+        position += 1;
+      }
+      let (high, low) = (digit(text[position]), digit(text[position + 1]));
+      if high > 0xf || low > 0xf {
+        return None;
+      }
+      bytes[i] = high << 4 | low;
+      position += 2;
+      i += 1;
+    }
+
+    let mut data4 = [0; 8];
+    let mut i = 0;
+    while i < 8 {
+      data4[i] = bytes[8 + i];
+      i += 1;
+    }
+    Some(Self {
+      data1: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+      data2: u16::from_be_bytes([bytes[4], bytes[5]]),
+      data3: u16::from_be_bytes([bytes[6], bytes[7]]),
+      data4,
+    })
+  }
+
+  /// Constructs a [`Guid`] from the 16 bytes it is stored as in memory and on
+  /// disk, such as in a GUID partition table, where the first three groups are
+  /// little-endian.
+  ///
+  /// # Arguments
+  ///
+  /// * `bytes` - the stored bytes of the GUID
+  pub const fn from_bytes(bytes: [u8; 16]) -> Self {
+    let mut data4 = [0; 8];
+    let mut i = 0;
+    while i < 8 {
+      data4[i] = bytes[8 + i];
+      i += 1;
+    }
+    Self {
+      data1: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+      data2: u16::from_le_bytes([bytes[4], bytes[5]]),
+      data3: u16::from_le_bytes([bytes[6], bytes[7]]),
+      data4,
+    }
+  }
+
+  /// Constructs a random, version 4, [`Guid`] from `random`.
+  ///
+  /// Six of the bits are overwritten to mark the version and the variant, so
+  /// that 122 bits of `random` are kept.
+  ///
+  /// # Arguments
+  ///
+  /// * `random` - 16 bytes from a cryptographically secure random source
+  pub const fn new_v4(random: [u8; 16]) -> Self {
+    let mut guid = Self::from_bytes(random);
+    guid.data3 = (guid.data3 & 0x0fff) | 0x4000;
+    guid.data4[0] = (guid.data4[0] & 0x3f) | 0x80;
+    guid
+  }
+
+  /// Returns the 16 bytes that the [`Guid`] is stored as in memory and on
+  /// disk; the inverse of [`from_bytes`](Self::from_bytes).
+  pub const fn to_bytes(&self) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    let (data1, data2, data3) = (
+      self.data1.to_le_bytes(),
+      self.data2.to_le_bytes(),
+      self.data3.to_le_bytes(),
+    );
+    let mut i = 0;
+    while i < 4 {
+      bytes[i] = data1[i];
+      i += 1;
+    }
+    bytes[4] = data2[0];
+    bytes[5] = data2[1];
+    bytes[6] = data3[0];
+    bytes[7] = data3[1];
+    let mut i = 0;
+    while i < 8 {
+      bytes[8 + i] = self.data4[i];
+      i += 1;
+    }
+    bytes
+  }
+
+  /// Returns the version of the [`Guid`], from its most significant bits of
+  /// the third group, such as `4` for a random one.
+  pub const fn version(&self) -> u8 {
+    (self.data3 >> 12) as u8
+  }
+
+  /// Returns whether this is [`Guid::NULL`].
+  pub const fn is_null(&self) -> bool {
+    self.data1 == 0
+      && self.data2 == 0
+      && self.data3 == 0
+      && u64::from_ne_bytes(self.data4) == 0
+  }
+}
+
+impl Default for Guid {
+  fn default() -> Self {
+    Self::NULL
+  }
+}
+
+impl fmt::Display for Guid {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let d = &self.data4;
+    write!(
+      f,
+      "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-",
+      self.data1, self.data2, self.data3, d[0], d[1]
+    )?;
+    for byte in &d[2..] {
+      write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Debug for Guid {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Display::fmt(self, f)
+  }
+}
+
+/// Returns the value of the hexadecimal digit `c`, or a value above `0xf` if
+/// it is not one.
+///
+/// # Arguments
+///
+/// * `c` - the character of the digit
+const fn digit(c: u8) -> u8 {
+  match c {
+    b'0'..=b'9' => c - b'0',
+    b'a'..=b'f' => c - b'a' + 10,
+    b'A'..=b'F' => c - b'A' + 10,
+    _ => u8::MAX,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::Guid;
+  use std::string::ToString;
+
+  const TEXT: &str = "964e5b22-6459-11d2-8e39-00a0c969723b";
+
+  #[test]
+  fn canonical_form_round_trips() {
+    let guid = Guid::parse(TEXT);
+    assert_eq!(guid.to_string(), TEXT);
+    assert_eq!(
+      Guid::try_parse("964E5B22-6459-11D2-8E39-00A0C969723B"),
+      Some(guid)
+    );
+    assert_eq!(Guid::try_parse("964e5b22-6459-11d2-8e39-00a0c969723"), None);
+    assert_eq!(
+      Guid::try_parse("964e5b22-6459-11d2-8e39+00a0c969723b"),
+      None
+    );
+    assert_eq!(
+      Guid::try_parse("964e5b22-6459-11d2-8e39-00a0c969723g"),
+      None
+    );
+  }
+
+  #[test]
+  fn stored_bytes_are_mixed_endian() {
+    let bytes = [
+      0x22, 0x5b, 0x4e, 0x96, 0x59, 0x64, 0xd2, 0x11, 0x8e, 0x39, 0x00, 0xa0,
+      0xc9, 0x69, 0x72, 0x3b,
+    ];
+    assert_eq!(Guid::from_bytes(bytes), Guid::parse(TEXT));
+    assert_eq!(Guid::parse(TEXT).to_bytes(), bytes);
+  }
+
+  #[test]
+  fn random_guids_are_version_4() {
+    let guid = Guid::new_v4([0xff; 16]);
+    assert_eq!(guid.to_string(), "ffffffff-ffff-4fff-bfff-ffffffffffff");
+    assert_eq!(guid.version(), 4);
+    assert_eq!(Guid::new_v4([0; 16]).version(), 4);
+    assert_eq!(Guid::KERNEL_PARTITION_TYPE.version(), 4);
+    assert!(Guid::NULL.is_null() && !Guid::new_v4([0; 16]).is_null());
+  }
+}
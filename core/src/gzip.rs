@@ -0,0 +1,164 @@
+//! This module provides decompression of gzip files, as described by RFC 1952,
+//! such as compressed initrd images.
+//!
+//! Only the first member of a gzip file is decompressed. Its CRC32 and size
+//! are verified against the trailer.
+
+use crate::checksum::crc32;
+use crate::deflate;
+use crate::error::{Error, Result};
+
+/// The magic number that every gzip member begins with.
+pub const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The compression method for DEFLATE, the only one defined.
+const METHOD_DEFLATE: u8 = 8;
+
+/// The header flag indicating a CRC16 of the header follows it.
+const FLAG_HEADER_CRC: u8 = 1 << 1;
+
+/// The header flag indicating the header holds an extra field.
+const FLAG_EXTRA: u8 = 1 << 2;
+
+/// The header flag indicating the header holds the original file name.
+const FLAG_NAME: u8 = 1 << 3;
+
+/// The header flag indicating the header holds a comment.
+const FLAG_COMMENT: u8 = 1 << 4;
+
+/// The size of the fixed part of the header.
+const HEADER_SIZE: usize = 10;
+
+/// The size of the trailer, holding the CRC32 and size of the data.
+const TRAILER_SIZE: usize = 8;
+
+/// Returns `true` if `data` begins with a gzip member.
+///
+/// # Arguments
+///
+/// * `data` - the data to check
+pub fn is_gzip(data: &[u8]) -> bool {
+  data.starts_with(&MAGIC)
+}
+
+/// Returns the number of bytes that the gzip file in `data` decompresses to,
+/// as recorded in its trailer.
+///
+/// # Arguments
+///
+/// * `data` - the gzip file
+pub fn decompressed_len(data: &[u8]) -> Result<usize> {
+  let trailer = trailer(data, data.len().saturating_sub(TRAILER_SIZE))?;
+  Ok(trailer.1 as usize)
+}
+
+/// Decompresses the gzip file in `data` into `output`, returning the number of
+/// bytes written.
+///
+/// Fails with [`Error::Corrupted`] if the file is malformed or the
+/// decompressed data does not match the CRC32 or size recorded in the
+/// trailer, and with [`Error::BufferTooSmall`] if it does not fit in
+/// `output`.
+///
+/// # Arguments
+///
+/// * `data` - the gzip file
+/// * `output` - the buffer to decompress into
+pub fn decompress(data: &[u8], output: &mut [u8]) -> Result<usize> {
+  let start = header_len(data)?;
+  let (consumed, len) = deflate::inflate(&data[start..], output)?;
+  let (crc, size) = trailer(data, start + consumed)?;
+  if crc32(&output[..len]) != crc || size != len as u32 {
+    return Err(Error::Corrupted);
+  }
+  Ok(len)
+}
+
+/// Returns the length of the header of the gzip member in `data`.
+///
+/// # Arguments
+///
+/// * `data` - the gzip file
+fn header_len(data: &[u8]) -> Result<usize> {
+  let header = data.get(..HEADER_SIZE).ok_or(Error::Corrupted)?;
+  if !is_gzip(header) || header[2] != METHOD_DEFLATE {
+    return Err(Error::Corrupted);
+  }
+  let flags = header[3];
+
+  let mut offset = HEADER_SIZE;
+  if flags & FLAG_EXTRA != 0 {
+    let len = data.get(offset..offset + 2).ok_or(Error::Corrupted)?;
+    offset += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+  }
+  for flag in [FLAG_NAME, FLAG_COMMENT] {
+    if flags & flag != 0 {
+      let field = data.get(offset..).ok_or(Error::Corrupted)?;
+      offset += field.iter().position(|b| *b == 0).ok_or(Error::Corrupted)? + 1;
+    }
+  }
+  if flags & FLAG_HEADER_CRC != 0 {
+    offset += 2;
+  }
+  if offset > data.len() {
+    return Err(Error::Corrupted);
+  }
+  Ok(offset)
+}
+
+/// Returns the CRC32 and size modulo 2^32 recorded in the trailer at `offset`
+/// of `data`.
+///
+/// # Arguments
+///
+/// * `data` - the gzip file
+/// * `offset` - the offset of the trailer
+fn trailer(data: &[u8], offset: usize) -> Result<(u32, u32)> {
+  let trailer = data
+    .get(offset..offset + TRAILER_SIZE)
+    .ok_or(Error::Corrupted)?;
+  let crc =
+    u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+  let size =
+    u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+  Ok((crc, size))
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  /// `hello, gzip!`, as compressed by gzip.
+  const FILE: [u8; 32] = [
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48,
+    0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0x48, 0xaf, 0xca, 0x2c, 0x50, 0x04, 0x00,
+    0xc6, 0xe6, 0x3e, 0x08, 0x0c, 0x00, 0x00, 0x00,
+  ];
+
+  #[test]
+  fn files_are_decompressed() {
+    let mut output = [0; 32];
+
+    assert!(is_gzip(&FILE));
+    assert_eq!(decompressed_len(&FILE), Ok(12));
+    let len = decompress(&FILE, &mut output).unwrap();
+    assert_eq!(&output[..len], b"hello, gzip!");
+  }
+
+  #[test]
+  fn mismatched_trailers_are_rejected() {
+    let mut output = [0; 32];
+
+    let mut file = FILE;
+    file[24] ^= 1;
+    assert_eq!(decompress(&file, &mut output), Err(Error::Corrupted));
+    let mut file = FILE;
+    file[28] = 13;
+    assert_eq!(decompress(&file, &mut output), Err(Error::Corrupted));
+    assert_eq!(
+      decompress(&FILE, &mut output[..4]),
+      Err(Error::BufferTooSmall)
+    );
+    assert_eq!(decompress(&FILE[..8], &mut output), Err(Error::Corrupted));
+  }
+}
@@ -0,0 +1,211 @@
+//! This module provides [`SipHasher13`], a keyed hash function for hash
+//! tables, and [`RandomState`], which builds hashers with a key of its own.
+//!
+//! SipHash keeps an attacker who can choose the keys of a table, such as the
+//! paths looked up in a file system, from making them collide without knowing
+//! the key. It is the default hash of the `HashMap` and `HashSet` of
+//! [`collections`](crate::collections), with the same 1 compression and 3
+//! finalization rounds as the standard library's.
+
+use core::hash::{BuildHasher, Hasher};
+
+/// The SipHash-1-3 keyed hash function.
+#[derive(Clone, Debug)]
+pub struct SipHasher13 {
+  v: [u64; 4],
+
+  /// The bytes written since the last whole word, in its low bytes.
+  tail: u64,
+
+  /// The number of bytes written.
+  len: usize,
+}
+
+impl SipHasher13 {
+  /// Constructs a hasher with the 128-bit key `(k0, k1)`.
+  ///
+  /// # Arguments
+  ///
+  /// * `k0` - the low half of the key
+  /// * `k1` - the high half of the key
+  pub const fn new_with_keys(k0: u64, k1: u64) -> Self {
+    Self {
+      v: [
+        k0 ^ 0x736f_6d65_7073_6575,
+        k1 ^ 0x646f_7261_6e64_6f6d,
+        k0 ^ 0x6c79_6765_6e65_7261,
+        k1 ^ 0x7465_6462_7974_6573,
+      ],
+      tail: 0,
+      len: 0,
+    }
+  }
+
+  /// Mixes the whole word `m` into the state.
+  ///
+  /// # Arguments
+  ///
+  /// * `m` - the next 8 bytes of the message, little-endian
+  fn compress(&mut self, m: u64) {
+    self.v[3] ^= m;
+    self.round();
+    self.v[0] ^= m;
+  }
+
+  /// Performs one SipRound on the state.
+  fn round(&mut self) {
+    let [v0, v1, v2, v3] = &mut self.v;
+    *v0 = v0.wrapping_add(*v1);
+    *v1 = v1.rotate_left(13) ^ *v0;
+    *v0 = v0.rotate_left(32);
+    *v2 = v2.wrapping_add(*v3);
+    *v3 = v3.rotate_left(16) ^ *v2;
+    *v0 = v0.wrapping_add(*v3);
+    *v3 = v3.rotate_left(21) ^ *v0;
+    *v2 = v2.wrapping_add(*v1);
+    *v1 = v1.rotate_left(17) ^ *v2;
+    *v2 = v2.rotate_left(32);
+  }
+}
+
+impl Hasher for SipHasher13 {
+  fn write(&mut self, mut bytes: &[u8]) {
+    let filled = self.len % 8;
+    self.len += bytes.len();
+
+    // Complete the word begun by earlier writes.
+    if filled != 0 {
+      let take = bytes.len().min(8 - filled);
+      self.tail |= load(&bytes[..take]) << (8 * filled);
+      bytes = &bytes[take..];
+      if filled + take < 8 {
+        return;
+      }
+      self.compress(self.tail);
+      self.tail = 0;
+    }
+
+    let mut words = bytes.chunks_exact(8);
+    for word in &mut words {
+      self.compress(load(word));
+    }
+    self.tail = load(words.remainder());
+  }
+
+  fn finish(&self) -> u64 {
+    let mut state = self.clone();
+    state.compress((self.len as u64) << 56 | self.tail);
+    state.v[2] ^= 0xff;
+    for _ in 0..3 {
+      state.round();
+    }
+    state.v.iter().fold(0, |hash, v| hash ^ v)
+  }
+}
+
+/// A builder of [`SipHasher13`]s that all share one key, chosen when it is
+/// constructed.
+#[derive(Clone, Debug)]
+pub struct RandomState {
+  k0: u64,
+  k1: u64,
+}
+
+impl RandomState {
+  /// Constructs a builder with a key taken from the cycle counter.
+  ///
+  /// The cycle counter is not a source of real randomness: it differs
+  /// between boots and between tables, but may be guessed by an attacker who
+  /// can time the system. Tables holding keys from untrusted sources should
+  /// use [`Rng::gen_random_state`](crate::rand::Rng::gen_random_state) with
+  /// a random number generator where there is one.
+  pub fn new() -> Self {
+    let seed = arch::cycle_counter();
+    Self::with_keys(seed, seed.rotate_left(32) ^ 0x9e37_79b9_7f4a_7c15)
+  }
+
+  /// Constructs a builder with the 128-bit key `(k0, k1)`.
+  ///
+  /// # Arguments
+  ///
+  /// * `k0` - the low half of the key
+  /// * `k1` - the high half of the key
+  pub const fn with_keys(k0: u64, k1: u64) -> Self {
+    Self { k0, k1 }
+  }
+}
+
+impl Default for RandomState {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl BuildHasher for RandomState {
+  type Hasher = SipHasher13;
+
+  fn build_hasher(&self) -> SipHasher13 {
+    SipHasher13::new_with_keys(self.k0, self.k1)
+  }
+}
+
+/// Returns up to 8 `bytes` as a little-endian word.
+///
+/// # Arguments
+///
+/// * `bytes` - the bytes of the word, from the least significant
+fn load(bytes: &[u8]) -> u64 {
+  let mut word = [0; 8];
+  word[..bytes.len()].copy_from_slice(bytes);
+  u64::from_le_bytes(word)
+}
+
+#[cfg(test)]
+mod test {
+  use super::SipHasher13;
+  use core::hash::Hasher;
+
+  /// The key of the reference test vectors.
+  const K0: u64 = 0x0706_0504_0302_0100;
+  const K1: u64 = 0x0f0e_0d0c_0b0a_0908;
+
+  /// The hashes of the messages `0, 1, ..., n - 1` with the reference key.
+  const VECTORS: [(usize, u64); 5] = [
+    (0, 0xabac_0158_050f_c4dc),
+    (1, 0xc9f4_9bf3_7d57_ca93),
+    (7, 0xd392_7d98_9bb1_1140),
+    (8, 0x3690_9511_8d29_9a8e),
+    (63, 0x9d19_9062_b7bb_b3a8),
+  ];
+
+  fn message(len: usize) -> [u8; 64] {
+    let mut message = [0; 64];
+    for (i, byte) in message.iter_mut().enumerate().take(len) {
+      *byte = i as u8;
+    }
+    message
+  }
+
+  #[test]
+  fn hashes_match_the_reference() {
+    for (len, expected) in VECTORS {
+      let mut hasher = SipHasher13::new_with_keys(K0, K1);
+      hasher.write(&message(len)[..len]);
+      assert_eq!(hasher.finish(), expected, "message of {} bytes", len);
+    }
+  }
+
+  #[test]
+  fn hashes_do_not_depend_on_how_the_message_is_split() {
+    let message = message(63);
+    let mut whole = SipHasher13::new_with_keys(K0, K1);
+    whole.write(&message[..63]);
+    for split in [1, 3, 8, 13] {
+      let mut pieces = SipHasher13::new_with_keys(K0, K1);
+      for piece in message[..63].chunks(split) {
+        pieces.write(piece);
+      }
+      assert_eq!(pieces.finish(), whole.finish(), "pieces of {}", split);
+    }
+  }
+}
@@ -0,0 +1,24 @@
+//! This module provides the heap allocators, which implement
+//! [`GlobalAlloc`](core::alloc::GlobalAlloc) over memory that they are handed.
+//!
+//! Each allocator is split in two: the allocator itself, which works through
+//! `&mut self`, and a version of it behind an
+//! [`IrqMutex`](crate::sync::IrqMutex) that can be shared, and installed as the
+//! `#[global_allocator]`.
+
+mod bump;
+mod linked_list;
+
+pub use bump::{Bump, BumpAllocator};
+pub use linked_list::{
+  Heap, LinkedListAllocator, ALLOCATED_POISON, FREED_POISON,
+};
+
+/// The global allocator, when the `global-bump` feature is enabled, which is
+/// empty until given memory with [`BumpAllocator::init`].
+///
+/// Tests allocate from the host's allocator instead, since nothing gives this
+/// one memory before the test harness needs it.
+#[cfg(all(feature = "global-bump", not(test)))]
+#[global_allocator]
+pub static GLOBAL: BumpAllocator = BumpAllocator::empty();
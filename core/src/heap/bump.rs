@@ -0,0 +1,229 @@
+//! This module provides [`BumpAllocator`], the simplest heap there is, for
+//! early boot code that needs one before the real allocator is up.
+//!
+//! Allocating moves a pointer through a fixed region, and freeing does
+//! nothing but count; the region is only reclaimed once everything allocated
+//! from it has been freed, or when it is explicitly [`reset`](Bump::reset).
+
+use crate::sync::IrqMutex;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ops::Range;
+use core::ptr::{self, NonNull};
+
+/// A bump allocator over a region, which must be locked to be shared; see
+/// [`BumpAllocator`].
+pub struct Bump {
+  region: Range<usize>,
+
+  /// The address that the next allocation starts from.
+  next: usize,
+
+  /// The number of allocations that have not been freed.
+  allocations: usize,
+}
+
+impl Bump {
+  /// Constructs a bump allocator with no memory, which fails every
+  /// allocation.
+  pub const fn empty() -> Self {
+    Self {
+      region: 0..0,
+      next: 0,
+      allocations: 0,
+    }
+  }
+
+  /// Constructs a bump allocator over `region`.
+  ///
+  /// # Arguments
+  ///
+  /// * `region` - the addresses of the memory to allocate from
+  ///
+  /// # Safety
+  ///
+  /// The memory must be writable, and unused by anything else for as long as
+  /// the allocator is used.
+  pub unsafe fn new(region: Range<usize>) -> Self {
+    Self {
+      next: region.start,
+      region,
+      allocations: 0,
+    }
+  }
+
+  /// Allocates memory for `layout`, returning `None` if the region is
+  /// exhausted.
+  ///
+  /// # Arguments
+  ///
+  /// * `layout` - the size and alignment of the memory
+  pub fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+    let start =
+      self.next.checked_add(layout.align() - 1)? & !(layout.align() - 1);
+    let end = start.checked_add(layout.size())?;
+    if end > self.region.end {
+      return None;
+    }
+    self.next = end;
+    self.allocations += 1;
+    NonNull::new(start as *mut u8)
+  }
+
+  /// Records that an allocation was freed, reclaiming the whole region once
+  /// none are left.
+  pub fn deallocate(&mut self) {
+    self.allocations = self.allocations.saturating_sub(1);
+    if self.allocations == 0 {
+      self.next = self.region.start;
+    }
+  }
+
+  /// Reclaims the whole region, whether or not its allocations were freed.
+  ///
+  /// # Safety
+  ///
+  /// Nothing allocated from the region may be used again.
+  pub unsafe fn reset(&mut self) {
+    self.next = self.region.start;
+    self.allocations = 0;
+  }
+
+  /// Returns the number of bytes allocated from the region, including any
+  /// padding for alignment.
+  pub fn used(&self) -> usize {
+    self.next - self.region.start
+  }
+
+  /// Returns the number of bytes left in the region.
+  pub fn remaining(&self) -> usize {
+    self.region.end - self.next
+  }
+}
+
+/// A [`Bump`] allocator behind an [`IrqMutex`], which can serve as the
+/// `#[global_allocator]`.
+pub struct BumpAllocator {
+  bump: IrqMutex<Bump>,
+}
+
+impl BumpAllocator {
+  /// Constructs an allocator with no memory, to be given some with
+  /// [`init`](Self::init).
+  pub const fn empty() -> Self {
+    Self {
+      bump: IrqMutex::new(Bump::empty()),
+    }
+  }
+
+  /// Gives the allocator `region` to allocate from, forgetting any region it
+  /// had.
+  ///
+  /// # Arguments
+  ///
+  /// * `region` - the addresses of the memory to allocate from
+  ///
+  /// # Safety
+  ///
+  /// As for [`Bump::new`], and nothing allocated from any previous region may
+  /// be used again.
+  pub unsafe fn init(&self, region: Range<usize>) {
+    *self.bump.lock() = Bump::new(region);
+  }
+
+  /// Reclaims the whole region; see [`Bump::reset`].
+  ///
+  /// # Safety
+  ///
+  /// As for [`Bump::reset`].
+  pub unsafe fn reset(&self) {
+    self.bump.lock().reset();
+  }
+
+  /// Returns the number of bytes allocated; see [`Bump::used`].
+  pub fn used(&self) -> usize {
+    self.bump.lock().used()
+  }
+
+  /// Returns the number of bytes left; see [`Bump::remaining`].
+  pub fn remaining(&self) -> usize {
+    self.bump.lock().remaining()
+  }
+}
+
+unsafe impl GlobalAlloc for BumpAllocator {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    self
+      .bump
+      .lock()
+      .allocate(layout)
+      .map_or(ptr::null_mut(), NonNull::as_ptr)
+  }
+
+  unsafe fn dealloc(&self, _: *mut u8, _: Layout) {
+    self.bump.lock().deallocate();
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Bump, BumpAllocator};
+  use core::alloc::{GlobalAlloc, Layout};
+
+  #[test]
+  fn allocations_are_aligned_and_disjoint() {
+    let mut memory = [0u64; 16];
+    let start = memory.as_mut_ptr() as usize;
+    // SAFETY: the memory is unused.
+    let mut bump = unsafe { Bump::new(start..start + 128) };
+
+    let byte = bump.allocate(Layout::new::<u8>()).unwrap();
+    let word = bump.allocate(Layout::new::<u64>()).unwrap();
+    assert_eq!(byte.as_ptr() as usize, start);
+    assert_eq!(word.as_ptr() as usize, start + 8);
+    assert_eq!(bump.used(), 16);
+    assert_eq!(bump.remaining(), 112);
+    assert!(bump
+      .allocate(Layout::from_size_align(113, 1).unwrap())
+      .is_none());
+  }
+
+  #[test]
+  fn region_is_reclaimed_once_everything_is_freed() {
+    let mut memory = [0u64; 16];
+    let start = memory.as_mut_ptr() as usize;
+    // SAFETY: the memory is unused.
+    let mut bump = unsafe { Bump::new(start..start + 128) };
+
+    bump.allocate(Layout::new::<u32>()).unwrap();
+    bump.allocate(Layout::new::<u32>()).unwrap();
+    bump.deallocate();
+    assert_eq!(bump.used(), 8);
+    bump.deallocate();
+    assert_eq!(bump.used(), 0);
+
+    bump.allocate(Layout::new::<u32>()).unwrap();
+    // SAFETY: the allocation is not used again.
+    unsafe { bump.reset() };
+    assert_eq!(bump.remaining(), 128);
+  }
+
+  #[test]
+  fn global_allocator_allocates_from_its_region() {
+    let mut memory = [0u64; 16];
+    let start = memory.as_mut_ptr() as usize;
+    let allocator = BumpAllocator::empty();
+    let layout = Layout::new::<u64>();
+    // SAFETY: the allocator has no region to allocate from.
+    assert!(unsafe { allocator.alloc(layout) }.is_null());
+    // SAFETY: the memory is unused.
+    unsafe { allocator.init(start..start + 128) };
+
+    // SAFETY: the layout is not zero-sized, and the memory is freed once.
+    let word = unsafe { allocator.alloc(layout) };
+    assert_eq!(word as usize, start);
+    assert_eq!(allocator.used(), 8);
+    // SAFETY: `word` was allocated with `layout`, and is not used again.
+    unsafe { allocator.dealloc(word, layout) };
+    assert_eq!(allocator.remaining(), 128);
+  }
+}
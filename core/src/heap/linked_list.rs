@@ -0,0 +1,364 @@
+//! This module provides [`LinkedListAllocator`], a general-purpose heap that
+//! serves as the kernel's first heap, before any more specialized allocators
+//! exist.
+//!
+//! Free memory is kept on a list of blocks in address order, linked through
+//! the blocks themselves. Allocating takes the first block that fits, splitting
+//! off what is left over on either side; freeing puts the block back in order,
+//! merging it with its neighbours when they are adjacent.
+//!
+//! With the `heap-poison` feature, allocations are filled with
+//! [`ALLOCATED_POISON`] and freed memory with [`FREED_POISON`], so that reads of
+//! uninitialized or freed memory stand out.
+
+use crate::sync::IrqMutex;
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::{align_of, size_of};
+use core::ops::Range;
+use core::ptr::{self, NonNull};
+
+/// The byte that new allocations are filled with under `heap-poison`.
+pub const ALLOCATED_POISON: u8 = 0xcd;
+
+/// The byte that freed memory is filled with under `heap-poison`.
+pub const FREED_POISON: u8 = 0xdd;
+
+/// The address of no block, which ends the free list.
+const NONE: usize = 0;
+
+/// The links of a free block, stored at its start.
+#[repr(C)]
+struct Node {
+  /// The size of the block, including this node, in bytes.
+  size: usize,
+
+  /// The address of the next free block, which is higher than this one.
+  next: usize,
+}
+
+/// The smallest block, which is one that can hold its node.
+const MIN_BLOCK: usize = size_of::<Node>();
+
+/// The alignment of every block, so that each can hold its node.
+const BLOCK_ALIGN: usize = align_of::<Node>();
+
+/// A first-fit heap over the memory it is given, which must be locked to be
+/// shared; see [`LinkedListAllocator`].
+pub struct Heap {
+  /// The address of the lowest free block.
+  head: usize,
+
+  /// The number of bytes free, including those of the nodes.
+  free: usize,
+}
+
+// SAFETY: the heap only refers to memory that it was handed to manage.
+unsafe impl Send for Heap {}
+
+impl Heap {
+  /// Constructs a heap with no memory, which fails every allocation.
+  pub const fn empty() -> Self {
+    Self {
+      head: NONE,
+      free: 0,
+    }
+  }
+
+  /// Adds the memory of `region` to the heap. Regions too small to hold a
+  /// block are ignored.
+  ///
+  /// # Arguments
+  ///
+  /// * `region` - the addresses of the memory
+  ///
+  /// # Safety
+  ///
+  /// The memory must be writable, must not overlap any other region added,
+  /// and must be unused by anything else for as long as the heap is used.
+  pub unsafe fn add_region(&mut self, region: Range<usize>) {
+    let start = align_up(region.start, BLOCK_ALIGN);
+    let end = region.end & !(BLOCK_ALIGN - 1);
+    if end > start && end - start >= MIN_BLOCK {
+      self.insert(start, end - start);
+    }
+  }
+
+  /// Allocates memory for `layout`, returning `None` if no free block fits.
+  ///
+  /// # Arguments
+  ///
+  /// * `layout` - the size and alignment of the memory
+  pub fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+    let size = block_size(layout);
+    let align = layout.align().max(BLOCK_ALIGN);
+
+    let mut prev = NONE;
+    let mut current = self.head;
+    while current != NONE {
+      // SAFETY: the blocks on the list are free, and hold their nodes.
+      let Node { size: len, next } = unsafe { ptr::read(current as *mut Node) };
+      if let Some(start) = fit(current, len, size, align) {
+        self.unlink(prev, next);
+        self.free -= len;
+        // SAFETY: the space on either side of the allocation is free, and is
+        // large enough to hold a block if it is not empty.
+        unsafe {
+          if start > current {
+            self.insert(current, start - current);
+          }
+          if current + len > start + size {
+            self.insert(start + size, current + len - (start + size));
+          }
+          if cfg!(feature = "heap-poison") {
+            ptr::write_bytes(start as *mut u8, ALLOCATED_POISON, size);
+          }
+        }
+        return NonNull::new(start as *mut u8);
+      }
+      prev = current;
+      current = next;
+    }
+    None
+  }
+
+  /// Frees the memory at `ptr`.
+  ///
+  /// # Arguments
+  ///
+  /// * `ptr` - the memory, as returned by [`allocate`](Self::allocate)
+  /// * `layout` - the layout that the memory was allocated with
+  ///
+  /// # Safety
+  ///
+  /// The memory must have been allocated by this heap with `layout`, and must
+  /// not be used again.
+  pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+    let size = block_size(layout);
+    if cfg!(feature = "heap-poison") {
+      ptr::write_bytes(ptr.as_ptr(), FREED_POISON, size);
+    }
+    self.insert(ptr.as_ptr() as usize, size);
+  }
+
+  /// Returns the number of bytes free, some of which are lost to the links of
+  /// the free blocks and to fragmentation.
+  pub fn free(&self) -> usize {
+    self.free
+  }
+
+  /// Puts the block of `size` bytes at `address` on the free list, in address
+  /// order, merging it with its neighbours where they are adjacent.
+  ///
+  /// # Safety
+  ///
+  /// The block must be free, aligned to [`BLOCK_ALIGN`], and at least
+  /// [`MIN_BLOCK`] bytes long.
+  unsafe fn insert(&mut self, address: usize, size: usize) {
+    self.free += size;
+    let mut prev = NONE;
+    let mut next = self.head;
+    while next != NONE && next < address {
+      prev = next;
+      next = (*(next as *mut Node)).next;
+    }
+
+    let mut node = Node { size, next };
+    if next != NONE && address + size == next {
+      let absorbed = ptr::read(next as *mut Node);
+      node = Node {
+        size: size + absorbed.size,
+        next: absorbed.next,
+      };
+    }
+    if prev != NONE {
+      let before = &mut *(prev as *mut Node);
+      if prev + before.size == address {
+        before.size += node.size;
+        before.next = node.next;
+        return;
+      }
+      before.next = address;
+    } else {
+      self.head = address;
+    }
+    ptr::write(address as *mut Node, node);
+  }
+
+  /// Unlinks the block after `prev`, or at the head if `prev` is [`NONE`],
+  /// whose successor is `next`.
+  fn unlink(&mut self, prev: usize, next: usize) {
+    match prev {
+      NONE => self.head = next,
+      // SAFETY: `prev` is a free block on the list.
+      prev => unsafe { (*(prev as *mut Node)).next = next },
+    }
+  }
+}
+
+/// A [`Heap`] behind an [`IrqMutex`], which can serve as the
+/// `#[global_allocator]`.
+pub struct LinkedListAllocator {
+  heap: IrqMutex<Heap>,
+}
+
+impl LinkedListAllocator {
+  /// Constructs an allocator with no memory, to be given some with
+  /// [`add_region`](Self::add_region).
+  pub const fn empty() -> Self {
+    Self {
+      heap: IrqMutex::new(Heap::empty()),
+    }
+  }
+
+  /// Adds the memory of `region` to the heap; see [`Heap::add_region`].
+  ///
+  /// # Safety
+  ///
+  /// As for [`Heap::add_region`].
+  pub unsafe fn add_region(&self, region: Range<usize>) {
+    self.heap.lock().add_region(region);
+  }
+
+  /// Returns the number of bytes free; see [`Heap::free`].
+  pub fn free(&self) -> usize {
+    self.heap.lock().free()
+  }
+}
+
+unsafe impl GlobalAlloc for LinkedListAllocator {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    self
+      .heap
+      .lock()
+      .allocate(layout)
+      .map_or(ptr::null_mut(), NonNull::as_ptr)
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    if let Some(ptr) = NonNull::new(ptr) {
+      self.heap.lock().deallocate(ptr, layout);
+    }
+  }
+}
+
+/// Returns where an allocation of `size` bytes aligned to `align` starts
+/// within the free block of `len` bytes at `block`, if it fits with the space
+/// left on either side either empty or large enough to be a block.
+///
+/// # Arguments
+///
+/// * `block` - the address of the free block
+/// * `len` - the size of the free block
+/// * `size` - the size of the allocation, as a block
+/// * `align` - the alignment of the allocation
+fn fit(block: usize, len: usize, size: usize, align: usize) -> Option<usize> {
+  let mut start = align_up(block, align);
+  if start != block && start - block < MIN_BLOCK {
+    start = align_up(block.checked_add(MIN_BLOCK)?, align);
+  }
+  let end = start.checked_add(size)?;
+  let block_end = block + len;
+  let after = block_end.checked_sub(end)?;
+  (after == 0 || after >= MIN_BLOCK).then_some(start)
+}
+
+/// Returns the size of the block that an allocation of `layout` takes.
+///
+/// # Arguments
+///
+/// * `layout` - the layout of the allocation
+fn block_size(layout: Layout) -> usize {
+  align_up(layout.size(), BLOCK_ALIGN).max(MIN_BLOCK)
+}
+
+/// Returns `value` rounded up to a multiple of `align`, a power of two.
+fn align_up(value: usize, align: usize) -> usize {
+  (value + align - 1) & !(align - 1)
+}
+
+#[cfg(test)]
+mod test {
+  use super::Heap;
+  use core::alloc::Layout;
+
+  /// Memory for a heap, aligned more than any test needs.
+  #[repr(C, align(64))]
+  struct Memory([u8; 1024]);
+
+  fn heap(memory: &mut Memory) -> Heap {
+    let start = memory.0.as_mut_ptr() as usize;
+    let mut heap = Heap::empty();
+    // SAFETY: the memory is unused for the lifetime of the heap.
+    unsafe { heap.add_region(start..start + memory.0.len()) };
+    heap
+  }
+
+  #[test]
+  fn allocations_are_aligned_and_fill_gaps() {
+    let mut memory = Memory([0; 1024]);
+    let start = memory.0.as_ptr() as usize;
+    let mut heap = heap(&mut memory);
+    let small = Layout::from_size_align(3, 1).unwrap();
+    let aligned = Layout::from_size_align(40, 64).unwrap();
+
+    let a = heap.allocate(small).unwrap().as_ptr() as usize;
+    let b = heap.allocate(aligned).unwrap().as_ptr() as usize;
+    // The first fit is in the gap left by aligning the second allocation.
+    let c = heap.allocate(small).unwrap().as_ptr() as usize;
+    assert_eq!((a, b, c), (start, start + 64, start + 16));
+    assert_eq!(heap.free(), 1024 - 16 - 40 - 16);
+  }
+
+  #[test]
+  fn freed_blocks_merge() {
+    let mut memory = Memory([0; 1024]);
+    let mut heap = heap(&mut memory);
+    let layout = Layout::from_size_align(100, 8).unwrap();
+
+    let mut blocks = [None; 9];
+    for block in &mut blocks {
+      *block = heap.allocate(layout);
+    }
+    assert!(blocks.iter().all(Option::is_some));
+    assert!(heap.allocate(layout).is_none());
+    // Freeing out of order still leaves a single block.
+    for i in [1, 3, 0, 8, 2, 5, 4, 7, 6] {
+      // SAFETY: the block was allocated with the same layout.
+      unsafe { heap.deallocate(blocks[i].unwrap(), layout) };
+    }
+    assert_eq!(heap.free(), 1024);
+    let whole = Layout::from_size_align(1024, 8).unwrap();
+    assert!(heap.allocate(whole).is_some());
+  }
+
+  #[test]
+  fn exhausted_heap_fails() {
+    let mut memory = Memory([0; 1024]);
+    let mut heap = heap(&mut memory);
+    assert!(heap
+      .allocate(Layout::from_size_align(1025, 8).unwrap())
+      .is_none());
+    assert!(Heap::empty().allocate(Layout::new::<u8>()).is_none());
+  }
+
+  #[test]
+  #[cfg(feature = "heap-poison")]
+  fn allocated_and_freed_memory_is_poisoned() {
+    use super::{ALLOCATED_POISON, FREED_POISON, MIN_BLOCK};
+
+    let mut memory = Memory([0; 1024]);
+    let start = memory.0.as_ptr() as usize;
+    let mut heap = heap(&mut memory);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+
+    let block = heap.allocate(layout).unwrap();
+    assert_eq!(block.as_ptr() as usize, start);
+    assert!(memory.0[..64].iter().all(|&byte| byte == ALLOCATED_POISON));
+    // SAFETY: the block was allocated with the same layout.
+    unsafe { heap.deallocate(block, layout) };
+    // The start of the freed block holds its links.
+    assert!(memory.0[MIN_BLOCK..64]
+      .iter()
+      .all(|&byte| byte == FREED_POISON));
+  }
+}
@@ -0,0 +1,80 @@
+//! This module provides assertions on the layout of types, checked when the
+//! crate that makes them is compiled.
+//!
+//! Structures that are shared with another binary, the firmware or a device,
+//! such as the boot information or a table read from disk, must keep the
+//! layout that the other side expects; reordering or resizing a field breaks
+//! the other side silently. Stating the size of such a structure with
+//! [`const_assert_size!`](crate::const_assert_size!) and the offsets of its
+//! fields with [`const_assert_offset!`](crate::const_assert_offset!) turns
+//! that into a compile error.
+
+/// Asserts, at compile time, that the type is the given number of bytes.
+///
+/// ```ignore
+/// kcore::const_assert_size!(PhysRange, 16);
+/// ```
+#[macro_export]
+macro_rules! const_assert_size {
+  ($type:ty, $size:expr $(,)?) => {
+    const _: () = assert!(
+      ::core::mem::size_of::<$type>() == $size,
+      concat!("the size of `", stringify!($type), "` changed")
+    );
+  };
+}
+
+/// Asserts, at compile time, that each of the named fields of the structure
+/// is at the given offset, in bytes.
+///
+/// The structure may not hold cells, such as the registers of a device, since
+/// their fields cannot be addressed in constants; only their size can be
+/// asserted.
+///
+/// ```ignore
+/// kcore::const_assert_offset!(PhysRange, start: 0, len: 8);
+/// ```
+#[macro_export]
+macro_rules! const_assert_offset {
+  ($type:ty, $($field:ident: $offset:expr),+ $(,)?) => {
+    $(
+      const _: () = {
+        let value = ::core::mem::MaybeUninit::<$type>::uninit();
+        let base = value.as_ptr();
+        // SAFETY: only the address of the field is taken, which is within
+        // the value, and nothing is read through it.
+        let offset = unsafe {
+          ::core::ptr::addr_of!((*base).$field)
+            .cast::<u8>()
+            .offset_from(base.cast::<u8>())
+        };
+        assert!(
+          offset as usize == $offset,
+          concat!(
+            "the offset of `",
+            stringify!($type),
+            "::",
+            stringify!($field),
+            "` changed"
+          )
+        );
+      };
+    )+
+  };
+}
+
+#[cfg(test)]
+mod test {
+  #[repr(C)]
+  #[allow(dead_code)]
+  struct Header {
+    magic: u32,
+    flags: u8,
+    length: u64,
+    trailer: [u8; 3],
+  }
+
+  crate::const_assert_size!(Header, 24);
+  crate::const_assert_offset!(Header, magic: 0, flags: 4, length: 8);
+  crate::const_assert_offset!(Header, trailer: 16,);
+}
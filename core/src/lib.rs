@@ -0,0 +1,87 @@
+//! This crate provides the core primitives shared between the bootloader and
+//! the kernel that do not belong to any one architecture:
+//!
+//! * concurrency - the locks in [`sync`], and the cooperative running of
+//!   futures in [`executor`]
+//! * memory - the physical memory allocator in [`memory`], the heap
+//!   allocators in [`heap`], the containers in [`collections`] and the
+//!   aligned byte buffers in [`buffer`]
+//! * diagnostics - the logging in [`log`](mod@log), the reliable framing of
+//!   serial lines in [`serial`], the debugging over them with the GDB remote
+//!   protocol in [`gdb`], the reports of panics in [`panic`](mod@panic), the
+//!   symbol maps that name their backtraces in [`symbols`], the harness for
+//!   tests on the machine in [`testing`] and the errors reported across
+//!   subsystems in [`error`](mod@error)
+//! * time and arithmetic - the measurement of time in [`time`] and of hot
+//!   paths in [`bench`](mod@bench), the formatting without an allocator and
+//!   of sizes and durations in [`fmt`], the fixed-point arithmetic in
+//!   [`fixed`] and the wide multiplication and division in [`math`]
+//! * executables and firmware - the parsing of ELF files in [`elf`] and of
+//!   PE32+ images in [`pe`], the static ACPI tables in [`acpi`], the device
+//!   trees in [`fdt`] and the versions and build information of binaries in
+//!   [`version`]
+//! * assets - the console fonts in [`font`], the assets built into binaries
+//!   in [`asset`], the decoding of BMP and PNG images in [`bmp`] and [`png`],
+//!   the decompression of DEFLATE streams and their gzip and zlib wrappers in
+//!   [`deflate`], [`gzip`] and [`zlib`] and the reading of cpio archives in
+//!   [`cpio`]
+//! * storage - the block devices in [`block`], the GUID partition tables and
+//!   FAT file systems on them in [`gpt`] and [`fat`], the interface to file
+//!   systems in [`vfs`] and the paths they take in [`path`]
+//! * encoding - the reading of little-endian integers in [`bytes`], the
+//!   checksums in [`checksum`], the GUIDs of UEFI and partition tables in
+//!   [`guid`], the keyed hashing of hash tables in [`hash`], the interface to
+//!   random number generators in [`rand`], the binary encoding of structures
+//!   in [`serialize`] and of extensible lists of records in [`tlv`], the
+//!   compile-time checks of the layouts of structures in [`layout`] and the
+//!   declaration of sets of flags in [`bitflags`](mod@bitflags)
+#![no_std]
+
+#[cfg(any(feature = "alloc", test))]
+extern crate alloc;
+
+pub mod acpi;
+pub mod asset;
+pub mod bench;
+pub mod bitflags;
+pub mod block;
+pub mod bmp;
+pub mod buffer;
+pub mod bytes;
+pub mod checksum;
+pub mod collections;
+pub mod cpio;
+pub mod deflate;
+pub mod elf;
+pub mod error;
+pub mod executor;
+pub mod fat;
+pub mod fdt;
+pub mod fixed;
+pub mod fmt;
+pub mod font;
+pub mod gdb;
+pub mod gpt;
+pub mod guid;
+pub mod gzip;
+pub mod hash;
+pub mod heap;
+pub mod layout;
+pub mod log;
+pub mod math;
+pub mod memory;
+pub mod panic;
+pub mod path;
+pub mod pe;
+pub mod png;
+pub mod rand;
+pub mod serial;
+pub mod serialize;
+pub mod symbols;
+pub mod sync;
+pub mod testing;
+pub mod time;
+pub mod tlv;
+pub mod version;
+pub mod vfs;
+pub mod zlib;
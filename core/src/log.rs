@@ -0,0 +1,193 @@
+//! This module provides logging, for code that runs without an allocator.
+//!
+//! Records are logged with the [`log!`](crate::log!) macro, or with one of
+//! [`error!`](crate::error!), [`warn!`](crate::warn!),
+//! [`info!`](crate::info!), [`debug!`](crate::debug!) and
+//! [`trace!`](crate::trace!). Each record has a [`Level`] and a target, which
+//! is the path of the module that logged it unless given otherwise, so that
+//! what is logged can be chosen per module.
+//!
+//! Records are handed to the [global logger](global) unless another
+//! [`Sink`] is given with `logger:`. A [`Logger`] timestamps records from a
+//! clock that is plugged into it, filters them, and hands them to each of its
+//! sinks, such as a [`FormatSink`] over a serial port or a framebuffer
+//! console, or a [`RingSink`] over the early boot log.
+//!
+//! ```ignore
+//! info!("found {} processors", count);
+//! warn!(target: "acpi", "no MADT; assuming a single processor");
+//! debug!(logger: &mut logger, "mapped {:#x}", address);
+//! ```
+
+mod logger;
+mod record;
+mod sink;
+
+pub use logger::{Logger, MAX_SINKS, MAX_TARGETS};
+pub use record::{Level, Record};
+pub use sink::{FormatSink, RingSink, Sink, LINE_SIZE};
+
+use crate::sync::{IrqMutex, IrqMutexGuard};
+
+/// The logger that records are handed to when no other is given.
+static GLOBAL: IrqMutex<Logger<'static>> = IrqMutex::new(Logger::new());
+
+/// Locks the global logger, to register sinks with it or to configure it.
+///
+/// The logger stays locked, with interrupts disabled, until the guard is
+/// dropped, and anything logged meanwhile on the same processor never
+/// returns.
+pub fn global() -> IrqMutexGuard<'static, Logger<'static>> {
+  GLOBAL.lock()
+}
+
+/// Hands `record` to the global logger.
+///
+/// # Arguments
+///
+/// * `record` - the record to log
+pub fn dispatch(record: &Record<'_>) {
+  GLOBAL.lock().log(record);
+}
+
+/// Logs a message of the given [`Level`].
+///
+/// The message is formatted as by [`format_args!`], and may be preceded by
+/// `logger:` with a mutable reference to the [`Sink`] to log to, and by
+/// `target:` with the target of the record.
+#[macro_export]
+macro_rules! log {
+  (logger: $logger:expr, target: $target:expr, $level:expr, $($arg:tt)+) => {
+    $crate::log::Sink::log(
+      &mut *$logger,
+      &$crate::log::Record::new($level, $target, format_args!($($arg)+)),
+    )
+  };
+  (logger: $logger:expr, $level:expr, $($arg:tt)+) => {
+    $crate::log!(
+      logger: $logger,
+      target: module_path!(),
+      $level,
+      $($arg)+
+    )
+  };
+  (target: $target:expr, $level:expr, $($arg:tt)+) => {
+    $crate::log::dispatch(
+      &$crate::log::Record::new($level, $target, format_args!($($arg)+)),
+    )
+  };
+  ($level:expr, $($arg:tt)+) => {
+    $crate::log!(target: module_path!(), $level, $($arg)+)
+  };
+}
+
+/// Logs a message of [`Level::Error`]; see [`log!`](crate::log!).
+#[macro_export]
+macro_rules! error {
+  (logger: $logger:expr, $($arg:tt)+) => {
+    $crate::log!(logger: $logger, $crate::log::Level::Error, $($arg)+)
+  };
+  (target: $target:expr, $($arg:tt)+) => {
+    $crate::log!(target: $target, $crate::log::Level::Error, $($arg)+)
+  };
+  ($($arg:tt)+) => {
+    $crate::log!($crate::log::Level::Error, $($arg)+)
+  };
+}
+
+/// Logs a message of [`Level::Warn`]; see [`log!`](crate::log!).
+#[macro_export]
+macro_rules! warn {
+  (logger: $logger:expr, $($arg:tt)+) => {
+    $crate::log!(logger: $logger, $crate::log::Level::Warn, $($arg)+)
+  };
+  (target: $target:expr, $($arg:tt)+) => {
+    $crate::log!(target: $target, $crate::log::Level::Warn, $($arg)+)
+  };
+  ($($arg:tt)+) => {
+    $crate::log!($crate::log::Level::Warn, $($arg)+)
+  };
+}
+
+/// Logs a message of [`Level::Info`]; see [`log!`](crate::log!).
+#[macro_export]
+macro_rules! info {
+  (logger: $logger:expr, $($arg:tt)+) => {
+    $crate::log!(logger: $logger, $crate::log::Level::Info, $($arg)+)
+  };
+  (target: $target:expr, $($arg:tt)+) => {
+    $crate::log!(target: $target, $crate::log::Level::Info, $($arg)+)
+  };
+  ($($arg:tt)+) => {
+    $crate::log!($crate::log::Level::Info, $($arg)+)
+  };
+}
+
+/// Logs a message of [`Level::Debug`]; see [`log!`](crate::log!).
+#[macro_export]
+macro_rules! debug {
+  (logger: $logger:expr, $($arg:tt)+) => {
+    $crate::log!(logger: $logger, $crate::log::Level::Debug, $($arg)+)
+  };
+  (target: $target:expr, $($arg:tt)+) => {
+    $crate::log!(target: $target, $crate::log::Level::Debug, $($arg)+)
+  };
+  ($($arg:tt)+) => {
+    $crate::log!($crate::log::Level::Debug, $($arg)+)
+  };
+}
+
+/// Logs a message of [`Level::Trace`]; see [`log!`](crate::log!).
+#[macro_export]
+macro_rules! trace {
+  (logger: $logger:expr, $($arg:tt)+) => {
+    $crate::log!(logger: $logger, $crate::log::Level::Trace, $($arg)+)
+  };
+  (target: $target:expr, $($arg:tt)+) => {
+    $crate::log!(target: $target, $crate::log::Level::Trace, $($arg)+)
+  };
+  ($($arg:tt)+) => {
+    $crate::log!($crate::log::Level::Trace, $($arg)+)
+  };
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::{Level, Logger, Record, RingSink, Sink, LINE_SIZE};
+  use crate::collections::RecordRing;
+
+  #[test]
+  fn macros_log_to_the_given_sink() {
+    let mut buffer = [0u8; 512];
+    let mut ring = RingSink(RecordRing::new(&mut buffer).unwrap());
+    {
+      let mut logger = Logger::new();
+      assert!(logger.register(&mut ring));
+      logger.set_level(Level::Debug);
+
+      let logger = &mut logger;
+      crate::info!(logger: logger, "{} + {}", 1, 2);
+      crate::trace!(logger: logger, "hidden");
+      crate::log!(logger: logger, target: "acpi", Level::Warn, "no MADT");
+    }
+    let mut records = ring.0.records();
+    assert_eq!(records.next(), Some(&b"INFO  kcore::log::test: 1 + 2"[..]));
+    assert_eq!(records.next(), Some(&b"WARN  acpi: no MADT"[..]));
+    assert_eq!(records.next(), None);
+  }
+
+  #[test]
+  fn long_records_are_truncated_between_characters() {
+    let mut buffer = [0u8; 1024];
+    let mut ring = RingSink(RecordRing::new(&mut buffer).unwrap());
+    let message = "é".repeat(LINE_SIZE);
+    ring.log(&Record::new(Level::Info, "t", format_args!("{}", message)));
+    let record = ring.0.records().next().unwrap();
+    // "INFO  t: " is nine bytes, leaving an odd number for the two-byte
+    // characters.
+    assert_eq!(record.len(), LINE_SIZE - 1);
+    assert!(core::str::from_utf8(record).is_ok());
+  }
+}
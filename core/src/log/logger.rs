@@ -0,0 +1,226 @@
+//! This module provides [`Logger`], which filters records and hands those that
+//! pass to each of its sinks.
+
+use super::{Level, Record, Sink};
+use crate::collections::ArrayVec;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+/// The most sinks that a [`Logger`] can hand records to.
+pub const MAX_SINKS: usize = 8;
+
+/// The most targets that a [`Logger`] can have levels for.
+pub const MAX_TARGETS: usize = 16;
+
+/// A logger, which timestamps records from its clock, filters them by their
+/// level and target, and hands those that pass to each of its sinks.
+///
+/// A logger is itself a [`Sink`], so that records can be logged to it
+/// directly, and so that loggers can be chained.
+pub struct Logger<'a> {
+  /// The sinks, which are borrowed for `'a`, but are held as pointers so that
+  /// a logger can be constructed in a constant.
+  sinks: ArrayVec<NonNull<dyn Sink + Send + 'a>, MAX_SINKS>,
+  _sinks: PhantomData<&'a ()>,
+
+  /// The clock that timestamps records, in nanoseconds since boot.
+  clock: Option<fn() -> u64>,
+
+  /// The least important level logged for targets without a level of their
+  /// own.
+  level: Level,
+
+  /// The least important level logged for each target, and the targets
+  /// within it.
+  targets: ArrayVec<(&'static str, Level), MAX_TARGETS>,
+}
+
+// SAFETY: the sinks are only used through `&mut self`, and are `Send`.
+unsafe impl Send for Logger<'_> {}
+
+impl<'a> Logger<'a> {
+  /// Constructs a logger with no sinks and no clock, which logs records of
+  /// [`Level::Info`] and above.
+  pub const fn new() -> Self {
+    Self {
+      sinks: ArrayVec::new(),
+      _sinks: PhantomData,
+      clock: None,
+      level: Level::Info,
+      targets: ArrayVec::new(),
+    }
+  }
+
+  /// Adds `sink` to those that records are handed to, returning `false` if
+  /// there are already [`MAX_SINKS`].
+  ///
+  /// # Arguments
+  ///
+  /// * `sink` - the sink
+  pub fn register(&mut self, sink: &'a mut (dyn Sink + Send)) -> bool {
+    self.sinks.try_push(NonNull::from(sink)).is_ok()
+  }
+
+  /// Sets the clock that records are timestamped with from now on.
+  ///
+  /// # Arguments
+  ///
+  /// * `clock` - a function returning the nanoseconds since boot
+  pub fn set_clock(&mut self, clock: fn() -> u64) {
+    self.clock = Some(clock);
+  }
+
+  /// Sets the least important level logged for targets without a level of
+  /// their own.
+  ///
+  /// # Arguments
+  ///
+  /// * `level` - the level
+  pub fn set_level(&mut self, level: Level) {
+    self.level = level;
+  }
+
+  /// Sets the least important level logged for `target`, and for the targets
+  /// within it that have no level of their own, returning `false` if there
+  /// are already levels for [`MAX_TARGETS`] other targets.
+  ///
+  /// # Arguments
+  ///
+  /// * `target` - the target, such as a module path
+  /// * `level` - the level
+  pub fn set_target_level(
+    &mut self,
+    target: &'static str,
+    level: Level,
+  ) -> bool {
+    match self.targets.iter_mut().find(|(t, _)| *t == target) {
+      Some(entry) => {
+        entry.1 = level;
+        true
+      }
+      None => self.targets.try_push((target, level)).is_ok(),
+    }
+  }
+
+  /// Returns whether records of `level` about `target` are logged.
+  ///
+  /// # Arguments
+  ///
+  /// * `level` - the level of the record
+  /// * `target` - the target of the record
+  pub fn enabled(&self, level: Level, target: &str) -> bool {
+    // The most specific target that the record is about decides.
+    let max = self
+      .targets
+      .iter()
+      .filter(|(prefix, _)| within(target, prefix))
+      .max_by_key(|(prefix, _)| prefix.len())
+      .map_or(self.level, |&(_, level)| level);
+    level <= max
+  }
+}
+
+impl Default for Logger<'_> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Sink for Logger<'_> {
+  fn log(&mut self, record: &Record<'_>) {
+    if !self.enabled(record.level, record.target) {
+      return;
+    }
+    let record = Record {
+      timestamp: record.timestamp.or_else(|| self.clock.map(|clock| clock())),
+      ..*record
+    };
+    for sink in self.sinks.iter_mut() {
+      // SAFETY: the sink was mutably borrowed for as long as the logger lives.
+      unsafe { sink.as_mut().log(&record) };
+    }
+  }
+}
+
+/// Returns whether `target` is `prefix`, or a module path within it.
+///
+/// # Arguments
+///
+/// * `target` - the target of a record
+/// * `prefix` - the target that a level is set for
+fn within(target: &str, prefix: &str) -> bool {
+  match target.strip_prefix(prefix) {
+    Some(rest) => rest.is_empty() || rest.starts_with("::"),
+    None => false,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::Logger;
+  use crate::log::{Level, Record, Sink};
+  use std::string::{String, ToString};
+  use std::vec::Vec;
+
+  /// A sink that keeps every record it is handed, as text.
+  #[derive(Default)]
+  struct Lines(Vec<String>);
+
+  impl Sink for Lines {
+    fn log(&mut self, record: &Record<'_>) {
+      self.0.push(record.to_string());
+    }
+  }
+
+  fn log(logger: &mut Logger, level: Level, target: &str, message: &str) {
+    logger.log(&Record::new(level, target, format_args!("{}", message)));
+  }
+
+  #[test]
+  fn records_are_filtered_by_level_and_target() {
+    let mut lines = Lines::default();
+    let mut logger = Logger::new();
+    assert!(logger.register(&mut lines));
+    logger.set_level(Level::Warn);
+    assert!(logger.set_target_level("kernel::memory", Level::Trace));
+    assert!(logger.set_target_level("kernel::memory::buddy", Level::Error));
+
+    log(&mut logger, Level::Info, "kernel", "a");
+    log(&mut logger, Level::Warn, "kernel", "b");
+    log(&mut logger, Level::Debug, "kernel::memory", "c");
+    log(&mut logger, Level::Debug, "kernel::memoryless", "d");
+    log(&mut logger, Level::Warn, "kernel::memory::buddy", "e");
+    log(
+      &mut logger,
+      Level::Error,
+      "kernel::memory::buddy::free",
+      "f",
+    );
+    drop(logger);
+    assert_eq!(
+      lines.0,
+      [
+        "WARN  kernel: b",
+        "DEBUG kernel::memory: c",
+        "ERROR kernel::memory::buddy::free: f",
+      ]
+    );
+  }
+
+  #[test]
+  fn records_are_timestamped_and_handed_to_every_sink() {
+    let mut first = Lines::default();
+    let mut second = Lines::default();
+    let mut logger = Logger::new();
+    assert!(logger.register(&mut first));
+    assert!(logger.register(&mut second));
+    logger.set_clock(|| 12_345_678_901);
+
+    log(&mut logger, Level::Error, "acpi", "no tables");
+    drop(logger);
+    assert_eq!(first.0, ["[   12.345678] ERROR acpi: no tables"]);
+    assert_eq!(first.0, second.0);
+  }
+}
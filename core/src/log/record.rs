@@ -0,0 +1,96 @@
+//! This module defines the [`Record`]s that are logged, and their [`Level`]s.
+
+use core::fmt;
+
+/// The importance of a [`Record`], from the most important to the least.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Level {
+  /// Something failed.
+  Error = 1,
+
+  /// Something unexpected happened, but was recovered from.
+  Warn,
+
+  /// Something happened that is worth knowing about.
+  Info,
+
+  /// Details that help to debug what happened.
+  Debug,
+
+  /// Every detail of what happened.
+  Trace,
+}
+
+impl Level {
+  /// Returns the name of the level, in capitals.
+  pub const fn name(self) -> &'static str {
+    match self {
+      Self::Error => "ERROR",
+      Self::Warn => "WARN",
+      Self::Info => "INFO",
+      Self::Debug => "DEBUG",
+      Self::Trace => "TRACE",
+    }
+  }
+}
+
+impl fmt::Display for Level {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.pad(self.name())
+  }
+}
+
+/// A message to be logged.
+///
+/// A record displays as a single line, without a line ending, of its
+/// timestamp, if any, its level, its target and its message.
+#[derive(Clone, Copy, Debug)]
+pub struct Record<'a> {
+  /// The importance of the message.
+  pub level: Level,
+
+  /// What the message is about, which is the path of the module that logged
+  /// it unless given otherwise.
+  pub target: &'a str,
+
+  /// The time that the message was logged at, in nanoseconds since boot, if
+  /// there is a clock to tell.
+  pub timestamp: Option<u64>,
+
+  /// The message.
+  pub args: fmt::Arguments<'a>,
+}
+
+impl<'a> Record<'a> {
+  /// Constructs a [`Record`] without a timestamp, which a
+  /// [`Logger`](super::Logger) adds from its clock.
+  ///
+  /// # Arguments
+  ///
+  /// * `level` - the importance of the message
+  /// * `target` - what the message is about
+  /// * `args` - the message
+  pub const fn new(
+    level: Level,
+    target: &'a str,
+    args: fmt::Arguments<'a>,
+  ) -> Self {
+    Self {
+      level,
+      target,
+      timestamp: None,
+      args,
+    }
+  }
+}
+
+impl fmt::Display for Record<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(nanos) = self.timestamp {
+      let seconds = nanos / 1_000_000_000;
+      let micros = nanos % 1_000_000_000 / 1000;
+      write!(f, "[{:>5}.{:06}] ", seconds, micros)?;
+    }
+    write!(f, "{:<5} {}: {}", self.level, self.target, self.args)
+  }
+}
@@ -0,0 +1,45 @@
+//! This module defines [`Sink`]s, the destinations of logged records, along
+//! with the sinks that need nothing but a writer or memory to write to.
+
+use super::Record;
+use crate::collections::RecordRing;
+use crate::fmt::fmt_to_slice;
+use core::fmt;
+
+/// The most bytes of a record that a [`RingSink`] keeps; the rest of a longer
+/// record is dropped.
+pub const LINE_SIZE: usize = 256;
+
+/// A destination of logged records, such as a serial port, a console or a
+/// buffer in memory.
+pub trait Sink {
+  /// Writes `record` out.
+  ///
+  /// # Arguments
+  ///
+  /// * `record` - the record to write
+  fn log(&mut self, record: &Record<'_>);
+}
+
+/// A sink that writes each record as a line of text to a writer, such as a
+/// serial port or a framebuffer console.
+pub struct FormatSink<W: fmt::Write>(pub W);
+
+impl<W: fmt::Write> Sink for FormatSink<W> {
+  fn log(&mut self, record: &Record<'_>) {
+    let _ = writeln!(self.0, "{}", record);
+  }
+}
+
+/// A sink that pushes each record as text onto a [`RecordRing`], such as the
+/// early boot log, truncating records longer than [`LINE_SIZE`].
+pub struct RingSink<'a>(pub RecordRing<'a>);
+
+impl Sink for RingSink<'_> {
+  fn log(&mut self, record: &Record<'_>) {
+    let mut line = [0; LINE_SIZE];
+    self
+      .0
+      .push(fmt_to_slice(&mut line, format_args!("{}", record)).as_bytes());
+  }
+}
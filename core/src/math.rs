@@ -0,0 +1,105 @@
+//! This module provides the arithmetic of integers wider than they are held
+//! in, such as `a * b / c` for clock conversions and the sizes of allocations,
+//! where the product overflows 64 bits but the quotient does not.
+//!
+//! [`mul_div_u64`] keeps the whole product, and divides it with the fastest
+//! division that the architecture has, which x86-64 does in a single
+//! instruction. Its result is rounded as asked by a [`Rounding`], and is
+//! `None` rather than wrapped when it does not fit.
+
+/// The direction that a quotient with a remainder is rounded in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rounding {
+  /// Toward zero, as integer division does.
+  Down,
+
+  /// Away from zero, such as for the ticks of a timeout, which must not end
+  /// early.
+  Up,
+
+  /// To the nearest integer, with halves rounded up.
+  Nearest,
+}
+
+/// Returns `a * b / c`, rounded as `rounding` asks, or `None` if `c` is zero
+/// or the quotient does not fit in a `u64`.
+///
+/// # Arguments
+///
+/// * `a` - the first factor
+/// * `b` - the second factor
+/// * `c` - the divisor
+/// * `rounding` - the direction to round the quotient in
+pub fn mul_div_u64(a: u64, b: u64, c: u64, rounding: Rounding) -> Option<u64> {
+  let (quotient, remainder) = arch::mul_div(a, b, c)?;
+  let round_up = match rounding {
+    Rounding::Down => false,
+    Rounding::Up => remainder != 0,
+    // The remainder is at least half the divisor, without doubling it.
+    Rounding::Nearest => remainder != 0 && remainder >= c - remainder,
+  };
+  quotient.checked_add(round_up as u64)
+}
+
+/// Returns `a * b / c`, rounded as `rounding` asks, or `None` if `c` is zero
+/// or the quotient does not fit in a `usize`, such as to scale the size of an
+/// allocation.
+///
+/// # Arguments
+///
+/// * `a` - the first factor
+/// * `b` - the second factor
+/// * `c` - the divisor
+/// * `rounding` - the direction to round the quotient in
+pub fn mul_div_usize(
+  a: usize,
+  b: usize,
+  c: usize,
+  rounding: Rounding,
+) -> Option<usize> {
+  let quotient = mul_div_u64(a as u64, b as u64, c as u64, rounding)?;
+  usize::try_from(quotient).ok()
+}
+
+/// Returns the full product of `a` and `b`, as its low and high halves.
+///
+/// # Arguments
+///
+/// * `a` - the first factor
+/// * `b` - the second factor
+pub const fn mul_wide(a: u64, b: u64) -> (u64, u64) {
+  let product = a as u128 * b as u128;
+  (product as u64, (product >> 64) as u64)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn quotients_keep_the_whole_product() {
+    let big = 1 << 40;
+
+    assert_eq!(mul_div_u64(big, big, big, Rounding::Down), Some(big));
+    assert_eq!(
+      mul_div_u64(u64::MAX, u64::MAX, u64::MAX, Rounding::Up),
+      Some(u64::MAX)
+    );
+    assert_eq!(mul_div_u64(big, big, 1, Rounding::Down), None);
+    assert_eq!(mul_div_u64(1, 1, 0, Rounding::Down), None);
+    assert_eq!(mul_wide(u64::MAX, 2), (u64::MAX - 1, 1));
+  }
+
+  #[test]
+  fn quotients_round_as_asked() {
+    assert_eq!(mul_div_u64(7, 1, 2, Rounding::Down), Some(3));
+    assert_eq!(mul_div_u64(7, 1, 2, Rounding::Up), Some(4));
+    assert_eq!(mul_div_u64(7, 1, 2, Rounding::Nearest), Some(4));
+    assert_eq!(mul_div_u64(10, 1, 3, Rounding::Nearest), Some(3));
+    assert_eq!(mul_div_u64(6, 1, 3, Rounding::Up), Some(2));
+    assert_eq!(mul_div_u64(u64::MAX, 3, 3, Rounding::Up), Some(u64::MAX));
+    assert_eq!(mul_div_u64(u64::MAX, 2, 2, Rounding::Up), Some(u64::MAX));
+    assert_eq!(mul_div_u64(u64::MAX - 1, 3, 2, Rounding::Up), None);
+    assert_eq!(mul_div_usize(5, 3, 4, Rounding::Nearest), Some(4));
+  }
+}
@@ -0,0 +1,8 @@
+//! This module provides the management of physical memory, which the kernel
+//! takes over from the memory map that the bootloader hands it.
+
+mod buddy;
+mod region_set;
+
+pub use buddy::{BuddyAllocator, MAX_REGIONS, ORDERS};
+pub use region_set::{Region, RegionSet};
@@ -0,0 +1,479 @@
+//! This module provides [`BuddyAllocator`], the allocator of physical memory,
+//! which hands out blocks of a power of two of pages.
+//!
+//! A block of order `k` is `2^k` pages long, and is aligned to its own size in
+//! physical memory, so that any allocation is naturally aligned. Each free
+//! block of order `k` has a buddy, the other half of the block of order
+//! `k + 1` that contains it; freeing a block whose buddy is also free merges
+//! the two, repeatedly, so that memory does not stay fragmented.
+//!
+//! The allocator needs no memory of its own. Free blocks are kept on lists
+//! linked through the blocks themselves, and whether each block is free is
+//! kept in a [`Bitmap`] per order, carved from the start of each region.
+
+use crate::collections::{ArrayVec, Bitmap};
+use arch::paging::{Frame, PAGE_SIZE};
+use core::ops::Range;
+
+/// The number of orders of blocks, such that the largest block is
+/// `2^(ORDERS - 1)` pages long.
+pub const ORDERS: usize = 20;
+
+/// The most regions of memory that an allocator can manage.
+pub const MAX_REGIONS: usize = 64;
+
+/// The address of no block, which ends a free list.
+const NONE: u64 = u64::MAX;
+
+/// The links of a free block, stored at its start.
+#[repr(C)]
+struct FreeBlock {
+  next: u64,
+  prev: u64,
+}
+
+/// A region of memory managed by a [`BuddyAllocator`].
+struct Region {
+  /// The physical address of the first page that is managed, after the
+  /// bitmaps.
+  start: u64,
+
+  /// The physical address of the end of the region.
+  end: u64,
+
+  /// The virtual address of the words of the free bitmaps of every order,
+  /// one after the other.
+  words: u64,
+}
+
+/// The location of the free bit of a block within its region's bitmaps.
+struct BitmapSpan {
+  /// The offset of the order's words from the region's first word.
+  offset: usize,
+
+  /// The index of the block that the order's first bit is for.
+  first: u64,
+
+  /// The number of bits of the order.
+  len: usize,
+}
+
+impl Region {
+  /// Returns where the bitmap of `order` lies for a region whose managed pages
+  /// span `start..end`.
+  ///
+  /// # Arguments
+  ///
+  /// * `start` - the physical address of the first managed page
+  /// * `end` - the physical address of the end of the region
+  /// * `order` - the order of the bitmap
+  fn span(start: u64, end: u64, order: usize) -> BitmapSpan {
+    let mut offset = 0;
+    for k in 0..=order {
+      let first = start >> block_shift(k);
+      let len = ((end - 1) >> block_shift(k)) - first + 1;
+      if k == order {
+        return BitmapSpan {
+          offset,
+          first,
+          len: len as usize,
+        };
+      }
+      offset += Bitmap::words_for(len as usize);
+    }
+    unreachable!()
+  }
+
+  /// Returns the number of words of the bitmaps of every order.
+  ///
+  /// # Arguments
+  ///
+  /// * `start` - the physical address of the first managed page
+  /// * `end` - the physical address of the end of the region
+  fn bitmap_words(start: u64, end: u64) -> usize {
+    let last = Self::span(start, end, ORDERS - 1);
+    last.offset + Bitmap::words_for(last.len)
+  }
+
+  /// Returns whether the block of `order` at `address` is within this region.
+  ///
+  /// # Arguments
+  ///
+  /// * `address` - the physical address of the block
+  /// * `order` - the order of the block
+  fn contains(&self, address: u64, order: usize) -> bool {
+    address >= self.start
+      && address
+        .checked_add(block_size(order))
+        .is_some_and(|end| end <= self.end)
+  }
+
+  /// Returns the bitmap of the free blocks of `order`, with the index within
+  /// it of the block at `address`.
+  ///
+  /// # Arguments
+  ///
+  /// * `address` - the physical address of a block within the region
+  /// * `order` - the order of the block
+  fn bitmap(&mut self, address: u64, order: usize) -> (Bitmap<'_>, usize) {
+    let span = Self::span(self.start, self.end, order);
+    // SAFETY: the words were carved from the region for its bitmaps when it
+    // was added, and are only ever reached through it.
+    let words = unsafe {
+      core::slice::from_raw_parts_mut(
+        (self.words as *mut usize).add(span.offset),
+        Bitmap::words_for(span.len),
+      )
+    };
+    let index = (address >> block_shift(order)) - span.first;
+    (Bitmap::from_words(words, span.len), index as usize)
+  }
+}
+
+/// A buddy allocator of physical memory.
+pub struct BuddyAllocator {
+  /// The physical address of the first free block of each order.
+  free_lists: [u64; ORDERS],
+
+  regions: ArrayVec<Region, MAX_REGIONS>,
+
+  /// The offset from the physical address of memory to the virtual address
+  /// that it is mapped at.
+  offset: u64,
+
+  free_pages: u64,
+  total_pages: u64,
+}
+
+// SAFETY: the allocator only refers to memory that it was handed to manage.
+unsafe impl Send for BuddyAllocator {}
+
+impl BuddyAllocator {
+  /// Constructs an allocator that manages no memory.
+  ///
+  /// # Arguments
+  ///
+  /// * `offset` - the offset from the physical address of memory to the
+  ///   virtual address that it is mapped at, which is `0` when it is
+  ///   identity-mapped
+  pub const fn new(offset: u64) -> Self {
+    Self {
+      free_lists: [NONE; ORDERS],
+      regions: ArrayVec::new(),
+      offset,
+      free_pages: 0,
+      total_pages: 0,
+    }
+  }
+
+  /// Returns the order of the smallest block of at least `pages` pages, if
+  /// there is one.
+  ///
+  /// # Arguments
+  ///
+  /// * `pages` - the number of pages
+  pub fn order_for(pages: usize) -> Option<usize> {
+    let order = pages.max(1).checked_next_power_of_two()?.trailing_zeros();
+    Some(order as usize).filter(|&order| order < ORDERS)
+  }
+
+  /// Adds the memory of `range` to the allocator, returning whether any of it
+  /// could be managed.
+  ///
+  /// The range is shrunk to whole pages, and the bitmaps of the region are
+  /// carved from its start. Ranges too small to hold their bitmaps and a page,
+  /// or beyond [`MAX_REGIONS`], are ignored.
+  ///
+  /// # Arguments
+  ///
+  /// * `range` - the physical addresses of the memory
+  ///
+  /// # Safety
+  ///
+  /// The memory must be unused, must not overlap any other range added, and
+  /// must be mapped writable at its physical address plus the offset of the
+  /// allocator for as long as the allocator is used.
+  pub unsafe fn add_region(&mut self, range: Range<u64>) -> bool {
+    let start = align_up(range.start, PAGE_SIZE);
+    let end = range.end & !(PAGE_SIZE - 1);
+    if start >= end || self.regions.is_full() {
+      return false;
+    }
+    let words = Region::bitmap_words(start, end);
+    let bitmap_size = (words * core::mem::size_of::<usize>()) as u64;
+    let managed = start + align_up(bitmap_size, PAGE_SIZE);
+    if managed >= end {
+      return false;
+    }
+
+    // The bitmaps for the smaller managed range fit in those for the whole.
+    let words_address = start + self.offset;
+    core::slice::from_raw_parts_mut(words_address as *mut usize, words).fill(0);
+    self.regions.push(Region {
+      start: managed,
+      end,
+      words: words_address,
+    });
+    self.total_pages += (end - managed) / PAGE_SIZE;
+
+    let region = self.regions.len() - 1;
+    let mut address = managed;
+    while address < end {
+      let order = (0..ORDERS)
+        .rev()
+        .find(|&order| {
+          address % block_size(order) == 0 && address + block_size(order) <= end
+        })
+        .unwrap_or(0);
+      self.insert(region, address, order);
+      address += block_size(order);
+    }
+    true
+  }
+
+  /// Allocates a block of `2^order` pages, aligned to its size, returning its
+  /// physical address.
+  ///
+  /// # Arguments
+  ///
+  /// * `order` - the order of the block
+  pub fn allocate(&mut self, order: usize) -> Option<u64> {
+    let found = (order..ORDERS).find(|&k| self.free_lists[k] != NONE)?;
+    let address = self.free_lists[found];
+    let region = self.region_of(address, found)?;
+    self.remove(region, address, found);
+
+    // The upper halves of the block are freed until it is the right size.
+    for k in (order..found).rev() {
+      self.insert(region, address + block_size(k), k);
+    }
+    Some(address)
+  }
+
+  /// Allocates a block of at least `pages` pages, aligned to its size, which
+  /// is `pages` rounded up to a power of two, returning its physical address.
+  ///
+  /// # Arguments
+  ///
+  /// * `pages` - the number of pages
+  pub fn allocate_pages(&mut self, pages: usize) -> Option<u64> {
+    self.allocate(Self::order_for(pages)?)
+  }
+
+  /// Allocates a single frame.
+  pub fn allocate_frame(&mut self) -> Option<Frame> {
+    self.allocate(0).map(Frame::containing_address)
+  }
+
+  /// Frees the block of `2^order` pages at `address`, merging it with its
+  /// buddy, repeatedly, while that is free.
+  ///
+  /// # Arguments
+  ///
+  /// * `address` - the physical address of the block
+  /// * `order` - the order that the block was allocated with
+  ///
+  /// # Safety
+  ///
+  /// The block must have been allocated by this allocator with `order`, and
+  /// must not be used again.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the block is not within any region of the allocator.
+  pub unsafe fn free(&mut self, address: u64, order: usize) {
+    let region = self
+      .region_of(address, order)
+      .expect("freed block is not managed by the allocator");
+    let mut address = address;
+    let mut order = order;
+    while order + 1 < ORDERS {
+      let buddy = address ^ block_size(order);
+      if !self.is_free(region, buddy, order) {
+        break;
+      }
+      self.remove(region, buddy, order);
+      address = address.min(buddy);
+      order += 1;
+    }
+    self.insert(region, address, order);
+  }
+
+  /// Returns the number of pages that are free.
+  pub fn free_pages(&self) -> u64 {
+    self.free_pages
+  }
+
+  /// Returns the number of pages that are managed, whether free or not.
+  pub fn total_pages(&self) -> u64 {
+    self.total_pages
+  }
+
+  /// Returns the index of the region holding the block of `order` at
+  /// `address`, if any.
+  fn region_of(&self, address: u64, order: usize) -> Option<usize> {
+    self
+      .regions
+      .iter()
+      .position(|region| region.contains(address, order))
+  }
+
+  /// Returns whether the block of `order` at `address` is free, which is
+  /// never so for blocks beyond the region.
+  fn is_free(&mut self, region: usize, address: u64, order: usize) -> bool {
+    let region = &mut self.regions[region];
+    if !region.contains(address, order) {
+      return false;
+    }
+    let (bitmap, index) = region.bitmap(address, order);
+    bitmap.test(index)
+  }
+
+  /// Marks the block of `order` at `address` as free, and links it onto the
+  /// front of its free list.
+  fn insert(&mut self, region: usize, address: u64, order: usize) {
+    let (mut bitmap, index) = self.regions[region].bitmap(address, order);
+    bitmap.set(index);
+
+    let next = self.free_lists[order];
+    // SAFETY: free blocks belong to the allocator, and are mapped at their
+    // physical address plus the offset.
+    unsafe {
+      *self.block(address) = FreeBlock { next, prev: NONE };
+      if next != NONE {
+        (*self.block(next)).prev = address;
+      }
+    }
+    self.free_lists[order] = address;
+    self.free_pages += 1 << order;
+  }
+
+  /// Marks the free block of `order` at `address` as allocated, and unlinks
+  /// it from its free list.
+  fn remove(&mut self, region: usize, address: u64, order: usize) {
+    let (mut bitmap, index) = self.regions[region].bitmap(address, order);
+    bitmap.clear(index);
+
+    // SAFETY: as for `insert`; the block and its neighbours are free.
+    unsafe {
+      let FreeBlock { next, prev } = *self.block(address);
+      match prev {
+        NONE => self.free_lists[order] = next,
+        prev => (*self.block(prev)).next = next,
+      }
+      if next != NONE {
+        (*self.block(next)).prev = prev;
+      }
+    }
+    self.free_pages -= 1 << order;
+  }
+
+  /// Returns a pointer to the links of the free block at `address`.
+  fn block(&self, address: u64) -> *mut FreeBlock {
+    (address + self.offset) as *mut FreeBlock
+  }
+}
+
+/// Returns `value` rounded up to a multiple of `align`, a power of two.
+fn align_up(value: u64, align: u64) -> u64 {
+  (value + align - 1) & !(align - 1)
+}
+
+/// Returns the size of a block of `order`, in bytes.
+fn block_size(order: usize) -> u64 {
+  PAGE_SIZE << order
+}
+
+/// Returns the shift from the physical address of a block of `order` to its
+/// index.
+fn block_shift(order: usize) -> u32 {
+  PAGE_SIZE.trailing_zeros() + order as u32
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::{BuddyAllocator, PAGE_SIZE};
+  use std::alloc::{self, Layout};
+
+  /// The size of the memory that each test manages.
+  const SIZE: usize = 4 << 20;
+
+  /// Runs `f` with an allocator managing `SIZE` bytes of memory, minus its
+  /// first page, so that the region is not aligned to any large block.
+  fn with_allocator(f: impl FnOnce(&mut BuddyAllocator, u64)) {
+    let layout = Layout::from_size_align(SIZE, SIZE).unwrap();
+    // SAFETY: the layout is not zero-sized.
+    let memory = unsafe { alloc::alloc(layout) };
+    assert!(!memory.is_null());
+    let base = memory as u64;
+
+    let mut allocator = BuddyAllocator::new(0);
+    // SAFETY: the memory is unused, and is identity-mapped in the test.
+    assert!(unsafe {
+      allocator.add_region(base + PAGE_SIZE + 123..base + SIZE as u64)
+    });
+    f(&mut allocator, base);
+    // SAFETY: the memory was allocated with the layout above.
+    unsafe { alloc::dealloc(memory, layout) };
+  }
+
+  #[test]
+  fn blocks_are_aligned_to_their_size() {
+    with_allocator(|allocator, _| {
+      for pages in [1, 2, 3, 8, 64] {
+        let order = BuddyAllocator::order_for(pages).unwrap();
+        let address = allocator.allocate_pages(pages).unwrap();
+        assert_eq!(address % (PAGE_SIZE << order), 0);
+      }
+    });
+  }
+
+  #[test]
+  fn allocations_do_not_overlap() {
+    with_allocator(|allocator, base| {
+      let total = allocator.total_pages();
+      assert!(total > 0 && total < (SIZE as u64) / PAGE_SIZE - 1);
+      assert_eq!(allocator.free_pages(), total);
+
+      let mut seen = [false; SIZE / PAGE_SIZE as usize];
+      while let Some(address) = allocator.allocate(0) {
+        let page = ((address - base) / PAGE_SIZE) as usize;
+        assert!(!seen[page]);
+        seen[page] = true;
+      }
+      assert_eq!(allocator.free_pages(), 0);
+      assert_eq!(seen.iter().filter(|&&seen| seen).count() as u64, total);
+    });
+  }
+
+  #[test]
+  fn freed_blocks_coalesce() {
+    with_allocator(|allocator, _| {
+      let total = allocator.free_pages();
+      let largest = allocator.allocate(9).unwrap();
+      // SAFETY: the block was just allocated with the same order.
+      unsafe { allocator.free(largest, 9) };
+
+      let mut pages = [0; 512];
+      for page in &mut pages {
+        *page = allocator.allocate(0).unwrap();
+      }
+      for &page in pages.iter().rev() {
+        // SAFETY: as above.
+        unsafe { allocator.free(page, 0) };
+      }
+      assert_eq!(allocator.free_pages(), total);
+      assert_eq!(allocator.allocate(9), Some(largest));
+    });
+  }
+
+  #[test]
+  fn exhausted_orders_fail() {
+    with_allocator(|allocator, _| {
+      assert_eq!(allocator.allocate(11), None);
+      assert_eq!(BuddyAllocator::order_for(1 << 20), None);
+    });
+  }
+}
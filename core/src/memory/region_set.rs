@@ -0,0 +1,301 @@
+//! This module provides [`RegionSet`], a set of disjoint ranges of physical
+//! memory, each of some kind, such as usable, reserved, MMIO or in use by the
+//! loader.
+//!
+//! The bootloader uses it to normalize the firmware's memory map, and the
+//! kernel to track which memory is its own to manage. Ranges are kept in
+//! address order, with adjacent ranges of the same kind merged, so that the
+//! set is always as small as it can be.
+
+use crate::collections::ArrayVec;
+use core::ops::Range;
+
+/// A range of physical memory, and its kind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region<K> {
+  /// The physical addresses of the memory.
+  pub range: Range<u64>,
+
+  /// The kind of the memory.
+  pub kind: K,
+}
+
+/// A set of at most `N` disjoint [`Region`]s, with kinds of type `K`.
+#[derive(Clone, Debug)]
+pub struct RegionSet<K, const N: usize> {
+  /// The regions, in address order, none of which are empty or adjacent to
+  /// another of the same kind.
+  regions: ArrayVec<Region<K>, N>,
+}
+
+impl<K: Copy + Eq, const N: usize> RegionSet<K, N> {
+  /// Constructs an empty set.
+  pub const fn new() -> Self {
+    Self {
+      regions: ArrayVec::new(),
+    }
+  }
+
+  /// Returns the number of regions in the set.
+  pub fn len(&self) -> usize {
+    self.regions.len()
+  }
+
+  /// Returns whether the set has no regions.
+  pub fn is_empty(&self) -> bool {
+    self.regions.is_empty()
+  }
+
+  /// Adds `range` to the set as memory of `kind`, replacing whatever kind any
+  /// of it already had, and merging it with adjacent regions of the same kind.
+  ///
+  /// Returns `false`, leaving the set unchanged, if the set has no room for
+  /// the regions that would result.
+  ///
+  /// # Arguments
+  ///
+  /// * `range` - the physical addresses of the memory
+  /// * `kind` - the kind of the memory
+  pub fn insert(&mut self, range: Range<u64>, kind: K) -> bool {
+    self.replace(range, Some(kind))
+  }
+
+  /// Removes `range` from the set, splitting any region that it lies within,
+  /// such as to carve the kernel out of usable memory.
+  ///
+  /// Returns `false`, leaving the set unchanged, if the set has no room for
+  /// the regions that would result.
+  ///
+  /// # Arguments
+  ///
+  /// * `range` - the physical addresses of the memory
+  pub fn remove(&mut self, range: Range<u64>) -> bool {
+    self.replace(range, None)
+  }
+
+  /// Removes every region from the set.
+  pub fn clear(&mut self) {
+    self.regions.clear();
+  }
+
+  /// Returns the kind of the memory at `address`, if it is in the set.
+  ///
+  /// # Arguments
+  ///
+  /// * `address` - the physical address
+  pub fn kind_of(&self, address: u64) -> Option<K> {
+    let index = self
+      .regions
+      .partition_point(|region| region.range.end <= address);
+    self
+      .regions
+      .get(index)
+      .filter(|region| region.range.contains(&address))
+      .map(|region| region.kind)
+  }
+
+  /// Returns the number of bytes of memory of `kind` in the set.
+  ///
+  /// # Arguments
+  ///
+  /// * `kind` - the kind of the memory
+  pub fn size_of(&self, kind: K) -> u64 {
+    self
+      .iter_kind(kind)
+      .map(|range| range.end - range.start)
+      .sum()
+  }
+
+  /// Returns an iterator over the regions, in address order.
+  pub fn iter(&self) -> core::slice::Iter<'_, Region<K>> {
+    self.regions.iter()
+  }
+
+  /// Returns an iterator over the ranges of memory of `kind`, in address
+  /// order.
+  ///
+  /// # Arguments
+  ///
+  /// * `kind` - the kind of the memory
+  pub fn iter_kind(&self, kind: K) -> impl Iterator<Item = Range<u64>> + '_ {
+    self
+      .regions
+      .iter()
+      .filter(move |region| region.kind == kind)
+      .map(|region| region.range.clone())
+  }
+
+  /// Replaces the memory of `range` with memory of `kind`, or removes it if
+  /// `kind` is `None`, rebuilding the regions so that the set is unchanged
+  /// should they not fit.
+  fn replace(&mut self, range: Range<u64>, kind: Option<K>) -> bool {
+    if range.start >= range.end {
+      return true;
+    }
+    let mut regions = ArrayVec::new();
+    // The new region, until it has been placed.
+    let mut pending = kind;
+    for region in self.regions.iter() {
+      let before = region.range.start..region.range.end.min(range.start);
+      let after = region.range.start.max(range.end)..region.range.end;
+      if !push(&mut regions, before, region.kind) {
+        return false;
+      }
+      if let Some(kind) = pending.filter(|_| region.range.end > range.start) {
+        pending = None;
+        if !push(&mut regions, range.clone(), kind) {
+          return false;
+        }
+      }
+      if !push(&mut regions, after, region.kind) {
+        return false;
+      }
+    }
+    if let Some(kind) = pending {
+      if !push(&mut regions, range, kind) {
+        return false;
+      }
+    }
+    self.regions = regions;
+    true
+  }
+}
+
+impl<K: Copy + Eq, const N: usize> Default for RegionSet<K, N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<'a, K: Copy + Eq, const N: usize> IntoIterator for &'a RegionSet<K, N> {
+  type Item = &'a Region<K>;
+  type IntoIter = core::slice::Iter<'a, Region<K>>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.iter()
+  }
+}
+
+/// Appends `range` of `kind` to `regions`, which must all lie below it,
+/// merging it with the last region if that is adjacent and of the same kind.
+///
+/// Returns `false` if there is no room for it.
+///
+/// # Arguments
+///
+/// * `regions` - the regions to append to
+/// * `range` - the physical addresses of the memory, which may be empty
+/// * `kind` - the kind of the memory
+fn push<K: Copy + Eq, const N: usize>(
+  regions: &mut ArrayVec<Region<K>, N>,
+  range: Range<u64>,
+  kind: K,
+) -> bool {
+  if range.start >= range.end {
+    return true;
+  }
+  match regions.last_mut() {
+    Some(last) if last.kind == kind && last.range.end == range.start => {
+      last.range.end = range.end;
+      true
+    }
+    _ => regions.try_push(Region { range, kind }).is_ok(),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Region, RegionSet};
+  use core::ops::Range;
+
+  #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+  enum Kind {
+    Usable,
+    Reserved,
+    Loader,
+  }
+
+  fn regions<const N: usize>(
+    set: &RegionSet<Kind, N>,
+  ) -> [Option<(Range<u64>, Kind)>; N] {
+    let mut result = core::array::from_fn(|_| None);
+    for (slot, Region { range, kind }) in result.iter_mut().zip(set) {
+      *slot = Some((range.clone(), *kind));
+    }
+    result
+  }
+
+  #[test]
+  fn adjacent_regions_of_the_same_kind_merge() {
+    let mut set = RegionSet::<Kind, 4>::new();
+    assert!(set.insert(0x3000..0x4000, Kind::Usable));
+    assert!(set.insert(0x1000..0x2000, Kind::Usable));
+    assert!(set.insert(0x2000..0x3000, Kind::Usable));
+    assert!(set.insert(0x4000..0x5000, Kind::Reserved));
+    assert_eq!(
+      regions(&set),
+      [
+        Some((0x1000..0x4000, Kind::Usable)),
+        Some((0x4000..0x5000, Kind::Reserved)),
+        None,
+        None,
+      ]
+    );
+    assert_eq!(set.size_of(Kind::Usable), 0x3000);
+  }
+
+  #[test]
+  fn insertions_override_what_they_overlap() {
+    let mut set = RegionSet::<Kind, 4>::new();
+    assert!(set.insert(0x0..0x10000, Kind::Usable));
+    assert!(set.insert(0x4000..0x6000, Kind::Loader));
+    assert!(set.insert(0x5000..0x12000, Kind::Reserved));
+    assert_eq!(
+      regions(&set),
+      [
+        Some((0x0..0x4000, Kind::Usable)),
+        Some((0x4000..0x5000, Kind::Loader)),
+        Some((0x5000..0x12000, Kind::Reserved)),
+        None,
+      ]
+    );
+    assert_eq!(set.kind_of(0x4fff), Some(Kind::Loader));
+    assert_eq!(set.kind_of(0x12000), None);
+  }
+
+  #[test]
+  fn removal_carves_out_of_regions() {
+    let mut set = RegionSet::<Kind, 4>::new();
+    assert!(set.insert(0x0..0x10000, Kind::Usable));
+    assert!(set.remove(0x2000..0x3000));
+    assert!(set.remove(0x8000..0x9000));
+    assert_eq!(
+      set
+        .iter_kind(Kind::Usable)
+        .collect::<super::ArrayVec<_, 4>>(),
+      [0x0..0x2000, 0x3000..0x8000, 0x9000..0x10000][..]
+    );
+    assert_eq!(set.kind_of(0x2000), None);
+    assert!(set.remove(0x0..0x2000));
+    assert_eq!(set.len(), 2);
+  }
+
+  #[test]
+  fn full_sets_are_left_unchanged() {
+    let mut set = RegionSet::<Kind, 2>::new();
+    assert!(set.insert(0x0..0x4000, Kind::Usable));
+    assert!(!set.insert(0x1000..0x2000, Kind::Reserved));
+    assert!(set.remove(0x1000..0x2000));
+    assert!(!set.remove(0x2800..0x3000));
+    assert_eq!(
+      regions(&set),
+      [
+        Some((0x0..0x1000, Kind::Usable)),
+        Some((0x2000..0x4000, Kind::Usable)),
+      ]
+    );
+    assert!(set.insert(0x0..0x4000, Kind::Reserved));
+    assert!(set.remove(0x0..0x0));
+    assert_eq!(set.len(), 1);
+  }
+}
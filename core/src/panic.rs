@@ -0,0 +1,158 @@
+//! This module provides the report of a panic, shared by the panic handlers
+//! of the bootloader and the kernel, which differ only in where they write
+//! it.
+//!
+//! The report gives the message and location of the panic and the processor
+//! it happened on, followed by a backtrace where one can be taken: the code
+//! that can walk the stack registers a [`Walker`] with [`set_walker`], and
+//! the code that knows the symbols of the binary registers a [`Symbolizer`]
+//! with [`set_symbolizer`], once each is ready. Until then, the report leaves
+//! the backtrace out, or gives its addresses alone.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// The largest number of frames in a backtrace.
+pub const MAX_FRAMES: usize = 32;
+
+/// Walks the stack of the running code, calling `visit` with the return
+/// address of each frame, innermost first, until it returns `false`.
+pub type Walker = fn(visit: &mut dyn FnMut(u64) -> bool);
+
+/// Returns the symbol that holds `address`, if it is known.
+pub type Symbolizer = fn(address: u64) -> Option<Symbol<'static>>;
+
+/// The symbol that holds an address.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Symbol<'a> {
+  /// The name of the symbol.
+  pub name: &'a str,
+
+  /// The offset of the address from the start of the symbol.
+  pub offset: u64,
+}
+
+/// The walker of the stack, as a [`Walker`], or null.
+static WALKER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// The symbolizer of addresses, as a [`Symbolizer`], or null.
+static SYMBOLIZER: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Sets the walker that backtraces are taken with.
+///
+/// # Arguments
+///
+/// * `walker` - walks the stack of the running code
+pub fn set_walker(walker: Walker) {
+  WALKER.store(walker as *mut (), Ordering::Release);
+}
+
+/// Sets the symbolizer that the addresses of backtraces are named with.
+///
+/// # Arguments
+///
+/// * `symbolizer` - returns the symbol that holds an address
+pub fn set_symbolizer(symbolizer: Symbolizer) {
+  SYMBOLIZER.store(symbolizer as *mut (), Ordering::Release);
+}
+
+/// Writes the report of the panic described by `info` to `output`.
+///
+/// # Arguments
+///
+/// * `info` - the description of the panic
+/// * `output` - the writer to write the report to
+pub fn report(info: &PanicInfo<'_>, output: &mut dyn Write) -> fmt::Result {
+  write_report(info, output)
+}
+
+/// Writes the report of `panic`, which describes itself with its message and
+/// location, to `output`.
+///
+/// # Arguments
+///
+/// * `panic` - the description of the panic
+/// * `output` - the writer to write the report to
+fn write_report(
+  panic: &dyn fmt::Display,
+  output: &mut dyn Write,
+) -> fmt::Result {
+  writeln!(
+    output,
+    "processor {} {}",
+    arch::target::processor_id(),
+    panic
+  )?;
+  let walker = WALKER.load(Ordering::Acquire);
+  if walker.is_null() {
+    return Ok(());
+  }
+  // SAFETY: only `Walker`s are stored in `WALKER`.
+  let walker: Walker = unsafe { core::mem::transmute(walker) };
+  let symbolizer = SYMBOLIZER.load(Ordering::Acquire);
+  // SAFETY: only `Symbolizer`s are stored in `SYMBOLIZER`.
+  let symbolizer = (!symbolizer.is_null())
+    .then(|| unsafe { core::mem::transmute::<_, Symbolizer>(symbolizer) });
+
+  writeln!(output, "backtrace:")?;
+  let (mut frame, mut result) = (0, Ok(()));
+  walker(&mut |address| {
+    result = match symbolizer.and_then(|symbolizer| symbolizer(address)) {
+      Some(symbol) => writeln!(
+        output,
+        "  {:2}: {:#018x} {}+{:#x}",
+        frame, address, symbol.name, symbol.offset
+      ),
+      None => writeln!(output, "  {:2}: {:#018x}", frame, address),
+    };
+    frame += 1;
+    result.is_ok() && frame < MAX_FRAMES
+  });
+  result
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::string::String;
+
+  fn walk(visit: &mut dyn FnMut(u64) -> bool) {
+    for address in (0x1000..).step_by(0x10) {
+      if !visit(address) {
+        break;
+      }
+    }
+  }
+
+  fn symbolize(address: u64) -> Option<Symbol<'static>> {
+    (address < 0x1020).then_some(Symbol {
+      name: "kmain",
+      offset: address - 0x1000,
+    })
+  }
+
+  #[test]
+  fn reports_give_the_processor_and_a_symbolized_backtrace() {
+    let mut output = String::new();
+    write_report(&"panicked at 'boom', main.rs:1:2", &mut output).unwrap();
+    let processor = arch::target::processor_id();
+    assert_eq!(
+      output,
+      std::format!("processor {} panicked at 'boom', main.rs:1:2\n", processor)
+    );
+
+    set_walker(walk);
+    set_symbolizer(symbolize);
+    let mut output = String::new();
+    write_report(&"panicked", &mut output).unwrap();
+    let lines: std::vec::Vec<_> = output.lines().collect();
+    assert_eq!(lines.len(), 2 + MAX_FRAMES);
+    assert_eq!(lines[1], "backtrace:");
+    assert_eq!(lines[2], "   0: 0x0000000000001000 kmain+0x0");
+    assert_eq!(lines[3], "   1: 0x0000000000001010 kmain+0x10");
+    assert_eq!(lines[4], "   2: 0x0000000000001020");
+  }
+}
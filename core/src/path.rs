@@ -0,0 +1,335 @@
+//! This module provides the paths of files, as taken by the file systems of
+//! [`vfs`](crate::vfs), so that every file system splits and resolves them
+//! the same way.
+//!
+//! A [`Path`] borrows a path as it was written, with components separated by
+//! `/` or `\`, and iterates over its [`Component`]s. A [`PathBuf`] owns a
+//! normalized path in a fixed-size buffer: it is absolute, its components are
+//! separated by single `/`s, and it holds no `.` or `..` components, which are
+//! resolved as they are pushed onto it, so a file system walks its
+//! components without looking up either.
+
+use crate::error::{Error, Result};
+use core::fmt;
+use core::ops::Deref;
+
+/// The longest path, in bytes of UTF-8, that the file systems resolve.
+pub const MAX_PATH_LEN: usize = 1024;
+
+/// The separator of the components of a normalized path.
+pub const SEPARATOR: char = '/';
+
+/// The separators of the components of a path as it is written.
+const SEPARATORS: [char; 2] = ['/', '\\'];
+
+/// A path, as it was written.
+#[repr(transparent)]
+pub struct Path(str);
+
+impl Path {
+  /// Returns `path` as a [`Path`].
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path, with components separated by `/` or `\`
+  pub fn new(path: &str) -> &Self {
+    // SAFETY: `Path` is a transparent wrapper of `str`.
+    unsafe { &*(path as *const str as *const Self) }
+  }
+
+  /// Returns the path as it was written.
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+
+  /// Returns `true` if the path starts from the root directory.
+  pub fn is_absolute(&self) -> bool {
+    self.0.starts_with(SEPARATORS)
+  }
+
+  /// Returns an iterator over the components of the path, leaving out empty
+  /// ones, such as those between repeated separators.
+  pub fn components(&self) -> Components<'_> {
+    Components(self.0.split(SEPARATORS))
+  }
+
+  /// Returns the name of the last component of the path, or `None` if it is
+  /// `.` or `..`, or there are no components.
+  pub fn file_name(&self) -> Option<&str> {
+    self.components().last().and_then(Component::name)
+  }
+
+  /// Returns the path normalized, from the root directory, failing with
+  /// [`Error::BufferTooSmall`] if it is longer than `N` bytes.
+  pub fn normalize<const N: usize>(&self) -> Result<PathBuf<N>> {
+    let mut path = PathBuf::new();
+    path.push(self)?;
+    Ok(path)
+  }
+}
+
+impl AsRef<Path> for Path {
+  fn as_ref(&self) -> &Path {
+    self
+  }
+}
+
+impl AsRef<Path> for str {
+  fn as_ref(&self) -> &Path {
+    Path::new(self)
+  }
+}
+
+impl PartialEq for Path {
+  fn eq(&self, other: &Self) -> bool {
+    self.0 == other.0
+  }
+}
+
+impl Eq for Path {}
+
+impl fmt::Debug for Path {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Debug::fmt(&self.0, f)
+  }
+}
+
+impl fmt::Display for Path {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+/// A component of a [`Path`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Component<'a> {
+  /// The current directory, `.`.
+  CurDir,
+
+  /// The parent directory, `..`.
+  ParentDir,
+
+  /// An entry of a directory, by its name.
+  Normal(&'a str),
+}
+
+impl<'a> Component<'a> {
+  /// Returns the name of the entry, or `None` for `.` and `..`, which are
+  /// the only components left out when walking a normalized path.
+  pub fn name(self) -> Option<&'a str> {
+    match self {
+      Component::Normal(name) => Some(name),
+      Component::CurDir | Component::ParentDir => None,
+    }
+  }
+
+  /// Returns the component as it was written.
+  pub fn as_str(self) -> &'a str {
+    match self {
+      Component::CurDir => ".",
+      Component::ParentDir => "..",
+      Component::Normal(name) => name,
+    }
+  }
+}
+
+/// An iterator over the components of a [`Path`].
+#[derive(Clone)]
+pub struct Components<'a>(core::str::Split<'a, [char; 2]>);
+
+impl<'a> Iterator for Components<'a> {
+  type Item = Component<'a>;
+
+  fn next(&mut self) -> Option<Component<'a>> {
+    loop {
+      return match self.0.next()? {
+        "" => continue,
+        "." => Some(Component::CurDir),
+        ".." => Some(Component::ParentDir),
+        name => Some(Component::Normal(name)),
+      };
+    }
+  }
+}
+
+/// A normalized path of at most `N` bytes, owned in a fixed-size buffer.
+#[derive(Clone)]
+pub struct PathBuf<const N: usize> {
+  bytes: [u8; N],
+  len: usize,
+}
+
+impl<const N: usize> PathBuf<N> {
+  /// Constructs the path of the root directory.
+  pub const fn new() -> Self {
+    let mut bytes = [0; N];
+    bytes[0] = SEPARATOR as u8;
+    Self { bytes, len: 1 }
+  }
+
+  /// Resolves `path` from this path, failing with [`Error::BufferTooSmall`]
+  /// if the result is longer than `N` bytes, in which case the path is left
+  /// as it was.
+  ///
+  /// An absolute `path` replaces this one, `.` is skipped and `..` removes
+  /// the last component, if there is any.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path to resolve
+  pub fn push(&mut self, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let mut resolved = self.clone();
+    if path.is_absolute() {
+      resolved.len = 1;
+    }
+    for component in path.components() {
+      match component {
+        Component::CurDir => {}
+        Component::ParentDir => {
+          resolved.pop();
+        }
+        Component::Normal(name) => resolved.push_name(name)?,
+      }
+    }
+    *self = resolved;
+    Ok(())
+  }
+
+  /// Removes the last component of the path, returning `false` if it is the
+  /// root directory, which has none.
+  pub fn pop(&mut self) -> bool {
+    if self.len == 1 {
+      return false;
+    }
+    let separator = self.as_str().rfind(SEPARATOR).unwrap_or(0);
+    self.len = separator.max(1);
+    true
+  }
+
+  /// Returns the path.
+  pub fn as_path(&self) -> &Path {
+    Path::new(self.as_str())
+  }
+
+  /// Returns the path as a string.
+  pub fn as_str(&self) -> &str {
+    // The bytes are only ever written from strings.
+    unsafe { core::str::from_utf8_unchecked(&self.bytes[..self.len]) }
+  }
+
+  /// Appends `name` to the path as its last component.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - the name of the component
+  fn push_name(&mut self, name: &str) -> Result<()> {
+    let start = self.len + (self.len > 1) as usize;
+    let end = start + name.len();
+    let bytes = self
+      .bytes
+      .get_mut(start..end)
+      .ok_or(Error::BufferTooSmall)?;
+    bytes.copy_from_slice(name.as_bytes());
+    self.bytes[start - 1] = SEPARATOR as u8;
+    self.len = end;
+    Ok(())
+  }
+}
+
+impl<const N: usize> Default for PathBuf<N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<const N: usize> Deref for PathBuf<N> {
+  type Target = Path;
+
+  fn deref(&self) -> &Path {
+    self.as_path()
+  }
+}
+
+impl<const N: usize> AsRef<Path> for PathBuf<N> {
+  fn as_ref(&self) -> &Path {
+    self.as_path()
+  }
+}
+
+impl<const N: usize> PartialEq for PathBuf<N> {
+  fn eq(&self, other: &Self) -> bool {
+    self.as_str() == other.as_str()
+  }
+}
+
+impl<const N: usize> Eq for PathBuf<N> {}
+
+impl<const N: usize> fmt::Debug for PathBuf<N> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Debug::fmt(self.as_str(), f)
+  }
+}
+
+impl<const N: usize> fmt::Display for PathBuf<N> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::vec::Vec;
+
+  #[test]
+  fn components_accept_either_separator() {
+    let path = Path::new("\\boot//./efi/../kernel.elf/");
+    let components: Vec<_> = path.components().collect();
+
+    assert!(path.is_absolute());
+    assert!(!Path::new("boot").is_absolute());
+    assert_eq!(
+      components,
+      [
+        Component::Normal("boot"),
+        Component::CurDir,
+        Component::Normal("efi"),
+        Component::ParentDir,
+        Component::Normal("kernel.elf"),
+      ]
+    );
+    assert_eq!(path.file_name(), Some("kernel.elf"));
+    assert_eq!(Path::new("/boot/..").file_name(), None);
+  }
+
+  #[test]
+  fn normalize_resolves_dots() {
+    let path = Path::new("boot\\.\\efi/../../../kernel.elf");
+
+    assert_eq!(path.normalize::<64>().unwrap().as_str(), "/kernel.elf");
+    assert_eq!(Path::new("./..").normalize::<64>().unwrap().as_str(), "/");
+    assert_eq!(
+      Path::new("a//b/").normalize::<64>().unwrap().as_path(),
+      Path::new("/a/b")
+    );
+  }
+
+  #[test]
+  fn push_resolves_from_the_path() {
+    let mut path = PathBuf::<16>::new();
+    path.push("/boot").unwrap();
+    path.push("efi/../grub").unwrap();
+
+    assert_eq!(path.as_str(), "/boot/grub");
+    assert_eq!(path.push("../a/long/name"), Err(Error::BufferTooSmall));
+    assert_eq!(path.as_str(), "/boot/grub");
+    path.push("/etc").unwrap();
+    assert_eq!(path.as_str(), "/etc");
+    assert!(path.pop());
+    assert!(!path.pop());
+    assert_eq!(path.as_str(), "/");
+  }
+}
@@ -0,0 +1,664 @@
+//! This module provides parsing of PE32+ images, the format of EFI binaries,
+//! as views over their bytes that copy nothing: the DOS stub, the COFF file
+//! header, the optional header and its data directories, the section headers,
+//! and the certificates of the security directory that Authenticode
+//! signatures are stored in.
+//!
+//! [`Pe::parse`] validates the headers and that the section table lies within
+//! the file, so that they can be read without further checks; the contents
+//! they point at are checked as they are asked for. Images of any machine and
+//! subsystem are read; what is acceptable to load is for the loader to decide.
+//!
+//! Malformed images fail with [`Error::Corrupted`], and images in a form that
+//! is not read, such as PE32 images, with [`Error::Unsupported`].
+
+mod certificates;
+
+pub use certificates::{
+  Certificate, Certificates, WIN_CERT_REVISION_2_0,
+  WIN_CERT_TYPE_PKCS_SIGNED_DATA,
+};
+
+use crate::bytes::{read_u16, read_u32, read_u64};
+use crate::error::{Error, Result};
+use core::iter::FusedIterator;
+use core::ops::Range;
+
+/// The machine type of i386 images.
+pub const IMAGE_FILE_MACHINE_I386: u16 = 0x14c;
+
+/// The machine type of x86-64 images.
+pub const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+
+/// The machine type of AArch64 images.
+pub const IMAGE_FILE_MACHINE_ARM64: u16 = 0xaa64;
+
+/// The subsystem of EFI applications, such as bootloaders.
+pub const IMAGE_SUBSYSTEM_EFI_APPLICATION: u16 = 10;
+
+/// The subsystem of EFI drivers that are unloaded when boot services exit.
+pub const IMAGE_SUBSYSTEM_EFI_BOOT_SERVICE_DRIVER: u16 = 11;
+
+/// The subsystem of EFI drivers that remain after boot services exit.
+pub const IMAGE_SUBSYSTEM_EFI_RUNTIME_DRIVER: u16 = 12;
+
+/// The index of the data directory of the export table.
+pub const IMAGE_DIRECTORY_ENTRY_EXPORT: usize = 0;
+
+/// The index of the data directory of the import table.
+pub const IMAGE_DIRECTORY_ENTRY_IMPORT: usize = 1;
+
+/// The index of the data directory of the resource table.
+pub const IMAGE_DIRECTORY_ENTRY_RESOURCE: usize = 2;
+
+/// The index of the data directory of the exception table.
+pub const IMAGE_DIRECTORY_ENTRY_EXCEPTION: usize = 3;
+
+/// The index of the data directory of the certificate table, whose address
+/// is a file offset rather than a relative virtual address.
+pub const IMAGE_DIRECTORY_ENTRY_SECURITY: usize = 4;
+
+/// The index of the data directory of the base relocation table.
+pub const IMAGE_DIRECTORY_ENTRY_BASERELOC: usize = 5;
+
+/// The index of the data directory of the debug information.
+pub const IMAGE_DIRECTORY_ENTRY_DEBUG: usize = 6;
+
+/// A section flag marking the section as holding code.
+pub const IMAGE_SCN_CNT_CODE: u32 = 0x20;
+
+/// A section flag marking the section as holding initialized data.
+pub const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x40;
+
+/// A section flag marking the section as holding uninitialized data.
+pub const IMAGE_SCN_CNT_UNINITIALIZED_DATA: u32 = 0x80;
+
+/// A section flag marking the section as executable.
+pub const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+
+/// A section flag marking the section as readable.
+pub const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+
+/// A section flag marking the section as writable.
+pub const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// The magic number of the optional header of PE32+ images.
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+
+/// The magic number of the optional header of PE32 images.
+const PE32_MAGIC: u16 = 0x10b;
+
+/// The size of the COFF file header.
+const COFF_HEADER_SIZE: usize = 20;
+
+/// The size of the fields of the PE32+ optional header before its data
+/// directories.
+const OPTIONAL_HEADER_SIZE: usize = 112;
+
+/// The size of a data directory.
+const DATA_DIRECTORY_SIZE: usize = 8;
+
+/// The size of a section header.
+const SECTION_HEADER_SIZE: usize = 40;
+
+/// The offset of the checksum within the optional header.
+const CHECKSUM_OFFSET: usize = 64;
+
+/// The COFF file header, which describes the machine and the layout of the
+/// headers that follow it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CoffHeader {
+  /// The `IMAGE_FILE_MACHINE_*` type of the machine the image targets.
+  pub machine: u16,
+
+  /// The number of sections.
+  pub section_count: u16,
+
+  /// The time the image was created, in seconds since the Unix epoch.
+  pub timestamp: u32,
+
+  /// The file offset of the COFF symbol table, which images do not have.
+  pub symbol_table: u32,
+
+  /// The number of entries of the COFF symbol table.
+  pub symbol_count: u32,
+
+  /// The size of the optional header.
+  pub optional_header_size: u16,
+
+  /// The `IMAGE_FILE_*` characteristics of the image.
+  pub characteristics: u16,
+}
+
+/// The fields of the PE32+ optional header, without its data directories.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OptionalHeader {
+  /// The relative virtual address of the entry point.
+  pub entry: u32,
+
+  /// The relative virtual address of the start of the code.
+  pub code_base: u32,
+
+  /// The preferred virtual address of the image.
+  pub image_base: u64,
+
+  /// The alignment of sections in memory.
+  pub section_alignment: u32,
+
+  /// The alignment of sections in the file.
+  pub file_alignment: u32,
+
+  /// The size of the image in memory, including its headers.
+  pub image_size: u32,
+
+  /// The size of the headers in the file, including the section table.
+  pub headers_size: u32,
+
+  /// The checksum of the image, which is zero for most EFI binaries.
+  pub checksum: u32,
+
+  /// The `IMAGE_SUBSYSTEM_*` subsystem that runs the image.
+  pub subsystem: u16,
+
+  /// The `IMAGE_DLLCHARACTERISTICS_*` characteristics of the image.
+  pub dll_characteristics: u16,
+
+  /// The size of the stack to reserve.
+  pub stack_reserve: u64,
+
+  /// The size of the stack to commit.
+  pub stack_commit: u64,
+
+  /// The size of the heap to reserve.
+  pub heap_reserve: u64,
+
+  /// The size of the heap to commit.
+  pub heap_commit: u64,
+}
+
+/// A data directory, which locates a table such as the relocations or the
+/// certificates.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct DataDirectory {
+  /// The relative virtual address of the table, or its file offset for the
+  /// [security directory](IMAGE_DIRECTORY_ENTRY_SECURITY).
+  pub address: u32,
+
+  /// The size of the table.
+  pub size: u32,
+}
+
+impl DataDirectory {
+  /// Returns whether the directory locates no table.
+  pub fn is_empty(&self) -> bool {
+    self.size == 0
+  }
+}
+
+/// A section header, which describes a section.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SectionHeader {
+  /// The name of the section, padded with NULs.
+  pub name: [u8; 8],
+
+  /// The size of the section in memory.
+  pub virtual_size: u32,
+
+  /// The relative virtual address of the section.
+  pub virtual_address: u32,
+
+  /// The size of the section's contents in the file, which is rounded up to
+  /// the file alignment and may exceed the size in memory.
+  pub raw_size: u32,
+
+  /// The offset of the section's contents within the file.
+  pub raw_offset: u32,
+
+  /// The `IMAGE_SCN_*` flags of the section.
+  pub characteristics: u32,
+}
+
+impl SectionHeader {
+  /// Returns the name of the section, without its padding, failing with
+  /// [`Error::Corrupted`] if it is not UTF-8.
+  ///
+  /// Long names, which refer to the COFF string table, are returned as is,
+  /// since images have no such table.
+  pub fn name(&self) -> Result<&str> {
+    let len = self.name.iter().position(|&byte| byte == 0).unwrap_or(8);
+    core::str::from_utf8(&self.name[..len]).map_err(|_| Error::Corrupted)
+  }
+
+  /// Returns whether the relative virtual address `rva` is within the section
+  /// in memory.
+  ///
+  /// # Arguments
+  ///
+  /// * `rva` - the relative virtual address
+  pub fn contains(&self, rva: u32) -> bool {
+    rva
+      .checked_sub(self.virtual_address)
+      .is_some_and(|offset| offset < self.virtual_size.max(self.raw_size))
+  }
+}
+
+/// A parsed PE32+ image.
+#[derive(Clone)]
+pub struct Pe<'a> {
+  data: &'a [u8],
+  coff_header: CoffHeader,
+  optional_header: Range<usize>,
+  directories: Range<usize>,
+  sections: Range<usize>,
+}
+
+impl<'a> Pe<'a> {
+  /// Parses and validates the headers of `data`, and the bounds of its
+  /// section table.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the contents of the image
+  pub fn parse(data: &'a [u8]) -> Result<Self> {
+    let dos = data.get(..64).ok_or(Error::Corrupted)?;
+    if dos[..2] != *b"MZ" {
+      return Err(Error::Corrupted);
+    }
+    let pe = read_u32(dos, 0x3c) as usize;
+    let signature = contents(data, pe, 4)?;
+    if signature != b"PE\0\0" {
+      return Err(Error::Corrupted);
+    }
+
+    let coff = contents(data, pe + 4, COFF_HEADER_SIZE)?;
+    let coff_header = CoffHeader {
+      machine: read_u16(coff, 0),
+      section_count: read_u16(coff, 2),
+      timestamp: read_u32(coff, 4),
+      symbol_table: read_u32(coff, 8),
+      symbol_count: read_u32(coff, 12),
+      optional_header_size: read_u16(coff, 16),
+      characteristics: read_u16(coff, 18),
+    };
+
+    let start = pe + 4 + COFF_HEADER_SIZE;
+    let size = coff_header.optional_header_size as usize;
+    let optional = contents(data, start, size)?;
+    match optional.get(..2).map(|magic| read_u16(magic, 0)) {
+      Some(PE32_PLUS_MAGIC) => {}
+      Some(PE32_MAGIC) => return Err(Error::Unsupported),
+      _ => return Err(Error::Corrupted),
+    }
+    if size < OPTIONAL_HEADER_SIZE {
+      return Err(Error::Corrupted);
+    }
+    let count = read_u32(optional, OPTIONAL_HEADER_SIZE - 4) as usize;
+    let directories = count
+      .checked_mul(DATA_DIRECTORY_SIZE)
+      .map(|len| OPTIONAL_HEADER_SIZE..OPTIONAL_HEADER_SIZE + len)
+      .filter(|directories| directories.end <= size)
+      .ok_or(Error::Corrupted)?;
+
+    let sections = start + size;
+    let len = coff_header.section_count as usize * SECTION_HEADER_SIZE;
+    contents(data, sections, len)?;
+
+    Ok(Self {
+      data,
+      coff_header,
+      optional_header: start..start + size,
+      directories: start + directories.start..start + directories.end,
+      sections: sections..sections + len,
+    })
+  }
+
+  /// Returns the raw contents of the image.
+  pub fn data(&self) -> &'a [u8] {
+    self.data
+  }
+
+  /// Returns the COFF file header.
+  pub fn coff_header(&self) -> CoffHeader {
+    self.coff_header
+  }
+
+  /// Returns the `IMAGE_FILE_MACHINE_*` type of the machine the image
+  /// targets.
+  pub fn machine(&self) -> u16 {
+    self.coff_header.machine
+  }
+
+  /// Returns the fields of the optional header.
+  pub fn optional_header(&self) -> OptionalHeader {
+    let header = &self.data[self.optional_header.clone()];
+    OptionalHeader {
+      entry: read_u32(header, 16),
+      code_base: read_u32(header, 20),
+      image_base: read_u64(header, 24),
+      section_alignment: read_u32(header, 32),
+      file_alignment: read_u32(header, 36),
+      image_size: read_u32(header, 56),
+      headers_size: read_u32(header, 60),
+      checksum: read_u32(header, CHECKSUM_OFFSET),
+      subsystem: read_u16(header, 68),
+      dll_characteristics: read_u16(header, 70),
+      stack_reserve: read_u64(header, 72),
+      stack_commit: read_u64(header, 80),
+      heap_reserve: read_u64(header, 88),
+      heap_commit: read_u64(header, 96),
+    }
+  }
+
+  /// Returns the number of data directories.
+  pub fn data_directory_count(&self) -> usize {
+    self.directories.len() / DATA_DIRECTORY_SIZE
+  }
+
+  /// Returns the data directory at `index`, if the image has that many.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the `IMAGE_DIRECTORY_ENTRY_*` index of the directory
+  pub fn data_directory(&self, index: usize) -> Option<DataDirectory> {
+    let entry = self.data_directory_range(index)?;
+    Some(DataDirectory {
+      address: read_u32(self.data, entry.start),
+      size: read_u32(self.data, entry.start + 4),
+    })
+  }
+
+  /// Returns the range of the file holding the data directory at `index`, if
+  /// the image has that many, such as to leave the security directory out of
+  /// an Authenticode digest.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the `IMAGE_DIRECTORY_ENTRY_*` index of the directory
+  pub fn data_directory_range(&self, index: usize) -> Option<Range<usize>> {
+    let start = index
+      .checked_mul(DATA_DIRECTORY_SIZE)
+      .map(|offset| self.directories.start + offset)
+      .filter(|&start| start < self.directories.end)?;
+    Some(start..start + DATA_DIRECTORY_SIZE)
+  }
+
+  /// Returns the range of the file holding the checksum of the optional
+  /// header, which an Authenticode digest leaves out.
+  pub fn checksum_range(&self) -> Range<usize> {
+    let start = self.optional_header.start + CHECKSUM_OFFSET;
+    start..start + 4
+  }
+
+  /// Returns an iterator over the section headers.
+  pub fn section_headers(&self) -> SectionHeaders<'a> {
+    SectionHeaders {
+      headers: self.data[self.sections.clone()]
+        .chunks_exact(SECTION_HEADER_SIZE),
+    }
+  }
+
+  /// Returns the header of the first section named `name`, if there is one.
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - the name of the section, such as `.text`
+  pub fn section_by_name(&self, name: &str) -> Option<SectionHeader> {
+    self
+      .section_headers()
+      .find(|header| header.name() == Ok(name))
+  }
+
+  /// Returns the contents in the file of the section of `header`, failing
+  /// with [`Error::Corrupted`] if they extend past the end of the file.
+  ///
+  /// # Arguments
+  ///
+  /// * `header` - the section header of the section
+  pub fn section_data(&self, header: &SectionHeader) -> Result<&'a [u8]> {
+    contents(
+      self.data,
+      header.raw_offset as usize,
+      header.raw_size as usize,
+    )
+  }
+
+  /// Returns the `size` bytes of the file that are loaded at the relative
+  /// virtual address `rva`, failing with [`Error::Corrupted`] if they are not
+  /// all stored in the file within one section.
+  ///
+  /// # Arguments
+  ///
+  /// * `rva` - the relative virtual address of the bytes
+  /// * `size` - the number of bytes
+  pub fn data_at(&self, rva: u32, size: usize) -> Result<&'a [u8]> {
+    let header = self
+      .section_headers()
+      .find(|header| header.contains(rva))
+      .ok_or(Error::Corrupted)?;
+    let offset = (rva - header.virtual_address) as usize;
+    let section = self.section_data(&header)?;
+    offset
+      .checked_add(size)
+      .and_then(|end| section.get(offset..end))
+      .ok_or(Error::Corrupted)
+  }
+
+  /// Returns the contents of the table of the data directory at `index`, or
+  /// `None` if the image has no such table.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the `IMAGE_DIRECTORY_ENTRY_*` index of the directory
+  pub fn directory_data(&self, index: usize) -> Result<Option<&'a [u8]>> {
+    let Some(directory) =
+      self.data_directory(index).filter(|dir| !dir.is_empty())
+    else {
+      return Ok(None);
+    };
+    if index == IMAGE_DIRECTORY_ENTRY_SECURITY {
+      let offset = directory.address as usize;
+      return contents(self.data, offset, directory.size as usize).map(Some);
+    }
+    self
+      .data_at(directory.address, directory.size as usize)
+      .map(Some)
+  }
+
+  /// Returns an iterator over the certificates of the security directory,
+  /// which is empty if the image is not signed.
+  pub fn certificates(&self) -> Result<Certificates<'a>> {
+    let table = self.directory_data(IMAGE_DIRECTORY_ENTRY_SECURITY)?;
+    Ok(Certificates::new(table.unwrap_or(&[])))
+  }
+}
+
+/// An iterator over the section headers of a [`Pe`] image.
+#[derive(Clone)]
+pub struct SectionHeaders<'a> {
+  headers: core::slice::ChunksExact<'a, u8>,
+}
+
+impl Iterator for SectionHeaders<'_> {
+  type Item = SectionHeader;
+
+  fn next(&mut self) -> Option<SectionHeader> {
+    let header = self.headers.next()?;
+    let mut name = [0; 8];
+    name.copy_from_slice(&header[..8]);
+    Some(SectionHeader {
+      name,
+      virtual_size: read_u32(header, 8),
+      virtual_address: read_u32(header, 12),
+      raw_size: read_u32(header, 16),
+      raw_offset: read_u32(header, 20),
+      characteristics: read_u32(header, 36),
+    })
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.headers.size_hint()
+  }
+}
+
+impl ExactSizeIterator for SectionHeaders<'_> {}
+
+impl FusedIterator for SectionHeaders<'_> {}
+
+/// Returns the `size` bytes of `data` at `offset`, failing with
+/// [`Error::Corrupted`] if they extend past its end.
+///
+/// # Arguments
+///
+/// * `data` - the contents of the image
+/// * `offset` - the offset of the bytes
+/// * `size` - the number of bytes
+fn contents(data: &[u8], offset: usize, size: usize) -> Result<&[u8]> {
+  offset
+    .checked_add(size)
+    .and_then(|end| data.get(offset..end))
+    .ok_or(Error::Corrupted)
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::vec::Vec;
+
+  /// The offset of the PE signature in the test image.
+  const PE: usize = 0x40;
+
+  /// The offset of the optional header in the test image.
+  const OPTIONAL: usize = PE + 4 + COFF_HEADER_SIZE;
+
+  /// Overwrites the bytes of `data` at `offset` with `bytes`.
+  fn set(data: &mut [u8], offset: usize, bytes: &[u8]) {
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+  }
+
+  /// Returns an x86-64 EFI application with a `.text` section of 16 bytes at
+  /// 0x1000 and one signature.
+  fn image() -> Vec<u8> {
+    let mut data = std::vec![0; 0x410];
+    set(&mut data, 0, b"MZ");
+    set(&mut data, 0x3c, &(PE as u32).to_le_bytes());
+    set(&mut data, PE, b"PE\0\0");
+    set(&mut data, PE + 4, &IMAGE_FILE_MACHINE_AMD64.to_le_bytes());
+    set(&mut data, PE + 6, &1u16.to_le_bytes());
+    set(&mut data, PE + 20, &(112u16 + 16 * 8).to_le_bytes());
+
+    set(&mut data, OPTIONAL, &PE32_PLUS_MAGIC.to_le_bytes());
+    set(&mut data, OPTIONAL + 16, &0x1004u32.to_le_bytes());
+    set(&mut data, OPTIONAL + 24, &0x1_4000_0000u64.to_le_bytes());
+    set(&mut data, OPTIONAL + 56, &0x2000u32.to_le_bytes());
+    set(&mut data, OPTIONAL + 60, &0x200u32.to_le_bytes());
+    set(&mut data, OPTIONAL + 64, &0xdead_beefu32.to_le_bytes());
+    set(
+      &mut data,
+      OPTIONAL + 68,
+      &IMAGE_SUBSYSTEM_EFI_APPLICATION.to_le_bytes(),
+    );
+    set(&mut data, OPTIONAL + 108, &16u32.to_le_bytes());
+    // The security directory.
+    set(&mut data, OPTIONAL + 112 + 4 * 8, &0x400u32.to_le_bytes());
+    set(&mut data, OPTIONAL + 112 + 4 * 8 + 4, &16u32.to_le_bytes());
+
+    let section = OPTIONAL + 112 + 16 * 8;
+    set(&mut data, section, b".text");
+    set(&mut data, section + 8, &16u32.to_le_bytes());
+    set(&mut data, section + 12, &0x1000u32.to_le_bytes());
+    set(&mut data, section + 16, &0x200u32.to_le_bytes());
+    set(&mut data, section + 20, &0x200u32.to_le_bytes());
+    let flags = IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ;
+    set(&mut data, section + 36, &flags.to_le_bytes());
+    set(&mut data, 0x200, &[0xc3; 16]);
+
+    set(&mut data, 0x400, &12u32.to_le_bytes());
+    set(&mut data, 0x404, &WIN_CERT_REVISION_2_0.to_le_bytes());
+    set(
+      &mut data,
+      0x406,
+      &WIN_CERT_TYPE_PKCS_SIGNED_DATA.to_le_bytes(),
+    );
+    set(&mut data, 0x408, &[0x30, 0x82, 0, 0]);
+    data
+  }
+
+  #[test]
+  fn headers_are_read() {
+    let data = image();
+    let pe = Pe::parse(&data).unwrap();
+    assert_eq!(pe.machine(), IMAGE_FILE_MACHINE_AMD64);
+    assert_eq!(pe.coff_header().section_count, 1);
+    let header = pe.optional_header();
+    assert_eq!(header.entry, 0x1004);
+    assert_eq!(header.image_base, 0x1_4000_0000);
+    assert_eq!((header.image_size, header.headers_size), (0x2000, 0x200));
+    assert_eq!(header.checksum, 0xdead_beef);
+    assert_eq!(header.subsystem, IMAGE_SUBSYSTEM_EFI_APPLICATION);
+    assert_eq!(data[pe.checksum_range()], 0xdead_beefu32.to_le_bytes());
+  }
+
+  #[test]
+  fn sections_and_directories_are_read() {
+    let data = image();
+    let pe = Pe::parse(&data).unwrap();
+    assert_eq!(pe.section_headers().len(), 1);
+    let text = pe.section_by_name(".text").unwrap();
+    assert_eq!(text.name(), Ok(".text"));
+    assert_eq!(pe.section_data(&text).unwrap().len(), 0x200);
+    assert_eq!(pe.data_at(0x1004, 4).unwrap(), [0xc3; 4]);
+    assert_eq!(pe.data_at(0x3000, 4), Err(Error::Corrupted));
+    assert!(pe.section_by_name(".data").is_none());
+
+    assert_eq!(pe.data_directory_count(), 16);
+    assert!(pe.data_directory(16).is_none());
+    assert_eq!(pe.directory_data(IMAGE_DIRECTORY_ENTRY_BASERELOC), Ok(None));
+    let security = pe.data_directory(IMAGE_DIRECTORY_ENTRY_SECURITY).unwrap();
+    assert_eq!(security.address, 0x400);
+    let range = pe
+      .data_directory_range(IMAGE_DIRECTORY_ENTRY_SECURITY)
+      .unwrap();
+    assert_eq!(data[range][..4], 0x400u32.to_le_bytes());
+  }
+
+  #[test]
+  fn certificates_are_read() {
+    let data = image();
+    let pe = Pe::parse(&data).unwrap();
+    let certificates: Vec<_> = pe.certificates().unwrap().collect();
+    assert_eq!(
+      certificates,
+      [Ok(Certificate {
+        revision: WIN_CERT_REVISION_2_0,
+        ty: WIN_CERT_TYPE_PKCS_SIGNED_DATA,
+        data: &[0x30, 0x82, 0, 0],
+      })]
+    );
+
+    let mut bad = data.clone();
+    set(&mut bad, 0x400, &64u32.to_le_bytes());
+    let pe = Pe::parse(&bad).unwrap();
+    let certificates: Vec<_> = pe.certificates().unwrap().collect();
+    assert_eq!(certificates, [Err(Error::Corrupted)]);
+  }
+
+  #[test]
+  fn malformed_images_are_rejected() {
+    let data = image();
+    assert_eq!(Pe::parse(&data[..0x100]).err(), Some(Error::Corrupted));
+    let mut bad = data.clone();
+    bad[0] = b'X';
+    assert_eq!(Pe::parse(&bad).err(), Some(Error::Corrupted));
+    let mut bad = data.clone();
+    set(&mut bad, OPTIONAL, &PE32_MAGIC.to_le_bytes());
+    assert_eq!(Pe::parse(&bad).err(), Some(Error::Unsupported));
+    let mut bad = data.clone();
+    set(&mut bad, OPTIONAL + 108, &17u32.to_le_bytes());
+    assert_eq!(Pe::parse(&bad).err(), Some(Error::Corrupted));
+    let mut bad = data.clone();
+    set(&mut bad, PE + 6, &100u16.to_le_bytes());
+    assert_eq!(Pe::parse(&bad).err(), Some(Error::Corrupted));
+  }
+}
@@ -0,0 +1,76 @@
+//! This module provides [`Certificates`], a view of the `WIN_CERTIFICATE`
+//! entries of the security directory of an image, which hold its
+//! Authenticode signatures.
+
+use super::{read_u16, read_u32};
+use crate::error::{Error, Result};
+use core::iter::FusedIterator;
+
+/// The revision of certificates in the current format.
+pub const WIN_CERT_REVISION_2_0: u16 = 0x200;
+
+/// The type of certificates holding PKCS#7 signed data, which Authenticode
+/// signatures are.
+pub const WIN_CERT_TYPE_PKCS_SIGNED_DATA: u16 = 2;
+
+/// The size of the header of a certificate.
+const HEADER_SIZE: usize = 8;
+
+/// A certificate of the security directory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Certificate<'a> {
+  /// The `WIN_CERT_REVISION_*` revision of the certificate.
+  pub revision: u16,
+
+  /// The `WIN_CERT_TYPE_*` type of the certificate.
+  pub ty: u16,
+
+  /// The contents of the certificate, such as DER-encoded PKCS#7 signed data.
+  pub data: &'a [u8],
+}
+
+/// An iterator over the certificates of a security directory, failing with
+/// [`Error::Corrupted`] at the first one that extends past its end.
+#[derive(Clone)]
+pub struct Certificates<'a> {
+  table: &'a [u8],
+}
+
+impl<'a> Certificates<'a> {
+  /// Constructs a view of the certificates in `table`.
+  ///
+  /// # Arguments
+  ///
+  /// * `table` - the contents of the security directory
+  pub fn new(table: &'a [u8]) -> Self {
+    Self { table }
+  }
+}
+
+impl<'a> Iterator for Certificates<'a> {
+  type Item = Result<Certificate<'a>>;
+
+  fn next(&mut self) -> Option<Result<Certificate<'a>>> {
+    if self.table.is_empty() {
+      return None;
+    }
+    let table = core::mem::take(&mut self.table);
+    let len = match table.get(..HEADER_SIZE) {
+      Some(header) => read_u32(header, 0) as usize,
+      None => return Some(Err(Error::Corrupted)),
+    };
+    if len < HEADER_SIZE || len > table.len() {
+      return Some(Err(Error::Corrupted));
+    }
+    // Each certificate starts on a multiple of 8 bytes.
+    let next = len.checked_add(7).map_or(table.len(), |end| end & !7);
+    self.table = table.get(next..).unwrap_or(&[]);
+    Some(Ok(Certificate {
+      revision: read_u16(table, 4),
+      ty: read_u16(table, 6),
+      data: &table[HEADER_SIZE..len],
+    }))
+  }
+}
+
+impl FusedIterator for Certificates<'_> {}
@@ -0,0 +1,651 @@
+//! This module provides decoding of PNG images, as described by the PNG
+//! specification, into buffers that the caller provides.
+//!
+//! An image is read in place by [`Png::parse`], which checks its chunks and
+//! learns its size without decompressing anything. [`Png::decode`] then
+//! decompresses it with [`zlib`](crate::zlib) into a working buffer of
+//! [`Png::buffer_len`] bytes, and converts it into 8-bit RGBA pixels in an
+//! output buffer of [`Png::output_len`] bytes, so that the memory it takes is
+//! known, and bounded by [`MAX_DIMENSION`], before any of it is used.
+//!
+//! Every color type and bit depth is decoded, along with the palette and
+//! transparency of the image. Interlaced images are not. Ancillary chunks
+//! other than the transparency, such as gamma and text, are ignored.
+//!
+//! Malformed images, including those whose chunks do not match their CRCs,
+//! fail with [`Error::Corrupted`], and images in a form that is not read with
+//! [`Error::Unsupported`].
+
+use crate::checksum::Crc32;
+use crate::error::{Error, Result};
+use crate::zlib;
+
+/// The signature that PNG files start with.
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// The size of the header chunk.
+const HEADER_SIZE: usize = 13;
+
+/// The largest width or height of an image that is decoded, in pixels.
+pub const MAX_DIMENSION: usize = 1 << 14;
+
+/// The size of the bytes that the pixels of an image are decoded into.
+pub const BYTES_PER_PIXEL: usize = 4;
+
+/// How the samples of a pixel describe its color.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorType {
+  /// A gray level.
+  Grayscale,
+
+  /// Red, green and blue levels.
+  Rgb,
+
+  /// An index into the palette.
+  Indexed,
+
+  /// A gray level and an alpha level.
+  GrayscaleAlpha,
+
+  /// Red, green, blue and alpha levels.
+  Rgba,
+}
+
+impl ColorType {
+  /// Returns the color type with the code `code`, if it is one.
+  ///
+  /// # Arguments
+  ///
+  /// * `code` - the code of the header
+  fn from_code(code: u8) -> Option<Self> {
+    match code {
+      0 => Some(Self::Grayscale),
+      2 => Some(Self::Rgb),
+      3 => Some(Self::Indexed),
+      4 => Some(Self::GrayscaleAlpha),
+      6 => Some(Self::Rgba),
+      _ => None,
+    }
+  }
+
+  /// Returns the number of samples of each pixel.
+  fn samples(&self) -> usize {
+    match self {
+      Self::Grayscale | Self::Indexed => 1,
+      Self::GrayscaleAlpha => 2,
+      Self::Rgb => 3,
+      Self::Rgba => 4,
+    }
+  }
+
+  /// Returns `true` if the samples of the color type may have `depth` bits.
+  ///
+  /// # Arguments
+  ///
+  /// * `depth` - the number of bits of each sample
+  fn allows(&self, depth: u8) -> bool {
+    match self {
+      Self::Grayscale => matches!(depth, 1 | 2 | 4 | 8 | 16),
+      Self::Indexed => matches!(depth, 1 | 2 | 4 | 8),
+      _ => matches!(depth, 8 | 16),
+    }
+  }
+}
+
+/// A PNG image.
+#[derive(Clone, Copy, Debug)]
+pub struct Png<'a> {
+  /// The chunks of the image from its first `IDAT` chunk on.
+  image_data: &'a [u8],
+  width: usize,
+  height: usize,
+  bit_depth: u8,
+  color_type: ColorType,
+  palette: &'a [u8],
+  transparency: &'a [u8],
+  compressed_len: usize,
+}
+
+impl<'a> Png<'a> {
+  /// Reads the PNG image held in `data`, checking all of its chunks.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the contents of the file
+  pub fn parse(data: &'a [u8]) -> Result<Self> {
+    if !data.starts_with(&SIGNATURE) {
+      return Err(Error::Corrupted);
+    }
+    let mut chunks = Chunks {
+      data: &data[SIGNATURE.len()..],
+    };
+    let header = match chunks.next().transpose()? {
+      Some((b"IHDR", header)) if header.len() == HEADER_SIZE => header,
+      _ => return Err(Error::Corrupted),
+    };
+    let width = read_u32(header, 0) as usize;
+    let height = read_u32(header, 4) as usize;
+    let bit_depth = header[8];
+    let color_type = ColorType::from_code(header[9]).ok_or(Error::Corrupted)?;
+    if width == 0 || height == 0 || !color_type.allows(bit_depth) {
+      return Err(Error::Corrupted);
+    }
+    if header[10] != 0 || header[11] != 0 {
+      return Err(Error::Corrupted);
+    }
+    if header[12] != 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+      return Err(Error::Unsupported);
+    }
+
+    let mut image = Self {
+      image_data: &[],
+      width,
+      height,
+      bit_depth,
+      color_type,
+      palette: &[],
+      transparency: &[],
+      compressed_len: 0,
+    };
+    // The image data is split between consecutive `IDAT` chunks.
+    let (mut data_seen, mut data_ended) = (false, false);
+    loop {
+      let rest = chunks.data;
+      let (kind, chunk) = chunks.next().ok_or(Error::Corrupted)??;
+      if kind == b"IDAT" {
+        if data_ended {
+          return Err(Error::Corrupted);
+        }
+        if !data_seen {
+          image.image_data = rest;
+        }
+        data_seen = true;
+        image.compressed_len += chunk.len();
+        continue;
+      }
+      data_ended = data_seen;
+      match kind {
+        b"IEND" => break,
+        b"PLTE" if chunk.len() % 3 == 0 && chunk.len() <= 256 * 3 => {
+          image.palette = chunk;
+        }
+        b"tRNS" => image.transparency = chunk,
+        // Chunks whose name starts with an uppercase letter are critical,
+        // and cannot be ignored.
+        _ if kind[0].is_ascii_uppercase() => return Err(Error::Unsupported),
+        _ => {}
+      }
+    }
+    if !data_seen
+      || color_type == ColorType::Indexed && image.palette.is_empty()
+    {
+      return Err(Error::Corrupted);
+    }
+    Ok(image)
+  }
+
+  /// Returns the width of the image, in pixels.
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  /// Returns the height of the image, in pixels.
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
+  /// Returns how the samples of the image's pixels describe their colors.
+  pub fn color_type(&self) -> ColorType {
+    self.color_type
+  }
+
+  /// Returns the number of bits of each sample of the image.
+  pub fn bit_depth(&self) -> u8 {
+    self.bit_depth
+  }
+
+  /// Returns the size of the working buffer that [`Png::decode`] needs, in
+  /// bytes.
+  pub fn buffer_len(&self) -> usize {
+    self.compressed_len + self.height * (1 + self.stride())
+  }
+
+  /// Returns the size of the output buffer that [`Png::decode`] needs, in
+  /// bytes.
+  pub fn output_len(&self) -> usize {
+    self.width * self.height * BYTES_PER_PIXEL
+  }
+
+  /// Decodes the image into `output`, as rows of pixels from the top down,
+  /// each of [`BYTES_PER_PIXEL`] bytes of red, green, blue and alpha, using
+  /// `buffer` to decompress it.
+  ///
+  /// Fails with [`Error::BufferTooSmall`] if `buffer` is smaller than
+  /// [`Png::buffer_len`] or `output` than [`Png::output_len`].
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the working buffer
+  /// * `output` - the buffer to decode into
+  pub fn decode(&self, buffer: &mut [u8], output: &mut [u8]) -> Result<()> {
+    if buffer.len() < self.buffer_len() || output.len() < self.output_len() {
+      return Err(Error::BufferTooSmall);
+    }
+    let (compressed, raw) = buffer.split_at_mut(self.compressed_len);
+    let raw = &mut raw[..self.buffer_len() - self.compressed_len];
+
+    let mut at = 0;
+    let chunks = Chunks {
+      data: self.image_data,
+    };
+    for (_, chunk) in chunks
+      .map_while(|chunk| chunk.ok())
+      .take_while(|(kind, _)| *kind == b"IDAT")
+    {
+      compressed[at..at + chunk.len()].copy_from_slice(chunk);
+      at += chunk.len();
+    }
+    // The image data cannot be larger than its header says.
+    let len =
+      zlib::decompress(compressed, raw).map_err(|_| Error::Corrupted)?;
+    if len != raw.len() {
+      return Err(Error::Corrupted);
+    }
+
+    let stride = self.stride();
+    let bytes_per_pixel = ((self.bits_per_pixel() + 7) / 8).max(1);
+    for y in 0..self.height {
+      let (before, rest) = raw.split_at_mut(y * (1 + stride));
+      let previous = y.checked_sub(1).map(|_| &before[before.len() - stride..]);
+      let (filter, row) = rest[..1 + stride].split_at_mut(1);
+      unfilter(filter[0], row, previous, bytes_per_pixel)?;
+
+      let pixels = &mut output[y * self.width * BYTES_PER_PIXEL..]
+        [..self.width * BYTES_PER_PIXEL];
+      for (x, pixel) in pixels.chunks_exact_mut(BYTES_PER_PIXEL).enumerate() {
+        pixel.copy_from_slice(&self.pixel(row, x)?);
+      }
+    }
+    Ok(())
+  }
+
+  /// Returns the number of bits of each pixel of the image.
+  fn bits_per_pixel(&self) -> usize {
+    self.color_type.samples() * self.bit_depth as usize
+  }
+
+  /// Returns the number of bytes of each row of the image, without its filter.
+  fn stride(&self) -> usize {
+    (self.width * self.bits_per_pixel() + 7) / 8
+  }
+
+  /// Returns the red, green, blue and alpha levels of the pixel `x` of the
+  /// unfiltered `row`.
+  ///
+  /// # Arguments
+  ///
+  /// * `row` - the unfiltered row
+  /// * `x` - the column of the pixel
+  fn pixel(&self, row: &[u8], x: usize) -> Result<[u8; 4]> {
+    let depth = self.bit_depth;
+    let samples = self.color_type.samples();
+    let sample = |index: usize| sample(row, x * samples + index, depth);
+    let level = |index: usize| scale(sample(index), depth);
+    // The transparency of images without alpha samples is a color key, of
+    // the samples of the one color that is transparent.
+    let key = |samples: usize| {
+      let transparent = (0..samples).all(|index| {
+        self.transparency.len() == samples * 2
+          && read_u16(self.transparency, index * 2) == sample(index)
+      });
+      if transparent {
+        0
+      } else {
+        0xff
+      }
+    };
+    Ok(match self.color_type {
+      ColorType::Grayscale => {
+        let gray = level(0);
+        [gray, gray, gray, key(1)]
+      }
+      ColorType::Rgb => [level(0), level(1), level(2), key(3)],
+      ColorType::Indexed => {
+        let index = sample(0) as usize;
+        let color = self
+          .palette
+          .get(index * 3..index * 3 + 3)
+          .ok_or(Error::Corrupted)?;
+        let alpha = self.transparency.get(index).copied().unwrap_or(0xff);
+        [color[0], color[1], color[2], alpha]
+      }
+      ColorType::GrayscaleAlpha => {
+        let gray = level(0);
+        [gray, gray, gray, level(1)]
+      }
+      ColorType::Rgba => [level(0), level(1), level(2), level(3)],
+    })
+  }
+}
+
+/// An iterator over the names and contents of the chunks of an image, which
+/// fails on chunks that are truncated or do not match their CRCs.
+struct Chunks<'a> {
+  data: &'a [u8],
+}
+
+impl<'a> Iterator for Chunks<'a> {
+  type Item = Result<(&'a [u8; 4], &'a [u8])>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.data.is_empty() {
+      return None;
+    }
+    let chunk = self.data.get(..8).and_then(|header| {
+      let len = read_u32(header, 0) as usize;
+      let end = len.checked_add(12)?;
+      self.data.get(..end)
+    });
+    let Some(chunk) = chunk else {
+      self.data = &[];
+      return Some(Err(Error::Corrupted));
+    };
+    self.data = &self.data[chunk.len()..];
+    let (body, crc) = chunk[4..].split_at(chunk.len() - 8);
+    let mut expected = Crc32::new();
+    expected.update(body);
+    if expected.finish() != read_u32(crc, 0) {
+      self.data = &[];
+      return Some(Err(Error::Corrupted));
+    }
+    let (kind, contents) = body.split_at(4);
+    Some(Ok((kind.try_into().ok()?, contents)))
+  }
+}
+
+/// Reverses the filter `filter` of `row`, a row of an image.
+///
+/// # Arguments
+///
+/// * `filter` - the filter of the row
+/// * `row` - the row, without its filter
+/// * `previous` - the unfiltered row above it, if there is one
+/// * `bytes_per_pixel` - the number of bytes of a pixel, rounded up to one
+fn unfilter(
+  filter: u8,
+  row: &mut [u8],
+  previous: Option<&[u8]>,
+  bytes_per_pixel: usize,
+) -> Result<()> {
+  let up = |i: usize| previous.map_or(0, |previous| previous[i]);
+  for i in 0..row.len() {
+    let left = i.checked_sub(bytes_per_pixel).map_or(0, |j| row[j]);
+    let upper_left = i
+      .checked_sub(bytes_per_pixel)
+      .map_or(0, |j| previous.map_or(0, |previous| previous[j]));
+    let predictor = match filter {
+      0 => 0,
+      1 => left,
+      2 => up(i),
+      3 => ((left as u16 + up(i) as u16) / 2) as u8,
+      4 => paeth(left, up(i), upper_left),
+      _ => return Err(Error::Corrupted),
+    };
+    row[i] = row[i].wrapping_add(predictor);
+  }
+  Ok(())
+}
+
+/// Returns whichever of the pixels to the left, above and to the upper left
+/// is closest to their gradient, the Paeth predictor.
+///
+/// # Arguments
+///
+/// * `a` - the byte to the left
+/// * `b` - the byte above
+/// * `c` - the byte to the upper left
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+  let p = a as i16 + b as i16 - c as i16;
+  let (pa, pb, pc) = (
+    (p - a as i16).abs(),
+    (p - b as i16).abs(),
+    (p - c as i16).abs(),
+  );
+  if pa <= pb && pa <= pc {
+    a
+  } else if pb <= pc {
+    b
+  } else {
+    c
+  }
+}
+
+/// Returns the sample `index` of the unfiltered `row`, of `depth` bits.
+///
+/// # Arguments
+///
+/// * `row` - the unfiltered row
+/// * `index` - the index of the sample in the row
+/// * `depth` - the number of bits of each sample
+fn sample(row: &[u8], index: usize, depth: u8) -> u16 {
+  match depth {
+    16 => read_u16(row, index * 2),
+    8 => row[index] as u16,
+    _ => {
+      let bit = index * depth as usize;
+      let shift = 8 - depth as usize - bit % 8;
+      (row[bit / 8] >> shift) as u16 & ((1 << depth) - 1)
+    }
+  }
+}
+
+/// Scales the sample `value` of `depth` bits to 8 bits.
+///
+/// # Arguments
+///
+/// * `value` - the sample
+/// * `depth` - the number of bits of the sample
+fn scale(value: u16, depth: u8) -> u8 {
+  match depth {
+    16 => (value >> 8) as u8,
+    8 => value as u8,
+    _ => (value * 0xff / ((1 << depth) - 1)) as u8,
+  }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+  u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+  let mut bytes = [0; 4];
+  bytes.copy_from_slice(&data[offset..offset + 4]);
+  u32::from_be_bytes(bytes)
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use crate::checksum::{adler32, crc32};
+  use std::vec::Vec;
+
+  /// Builds images for the tests, from chunks whose contents are given.
+  struct Builder {
+    data: Vec<u8>,
+  }
+
+  impl Builder {
+    fn new(width: u32, height: u32, depth: u8, color: u8) -> Self {
+      let mut header = Vec::new();
+      header.extend_from_slice(&width.to_be_bytes());
+      header.extend_from_slice(&height.to_be_bytes());
+      header.extend_from_slice(&[depth, color, 0, 0, 0]);
+      let builder = Self {
+        data: SIGNATURE.to_vec(),
+      };
+      builder.chunk(b"IHDR", &header)
+    }
+
+    fn chunk(mut self, kind: &[u8; 4], contents: &[u8]) -> Self {
+      let mut body = kind.to_vec();
+      body.extend_from_slice(contents);
+      self
+        .data
+        .extend_from_slice(&(contents.len() as u32).to_be_bytes());
+      self.data.extend_from_slice(&body);
+      self.data.extend_from_slice(&crc32(&body).to_be_bytes());
+      self
+    }
+
+    /// Adds the rows `raw` as a zlib stream of one stored block, split
+    /// between `chunks` chunks.
+    fn image(self, raw: &[u8], chunks: usize) -> Self {
+      let mut stream = std::vec![0x78, 0x01, 0x01];
+      let len = raw.len() as u16;
+      stream.extend_from_slice(&len.to_le_bytes());
+      stream.extend_from_slice(&(!len).to_le_bytes());
+      stream.extend_from_slice(raw);
+      stream.extend_from_slice(&adler32(raw).to_be_bytes());
+      let size = (stream.len() + chunks - 1) / chunks;
+      stream
+        .chunks(size)
+        .fold(self, |builder, chunk| builder.chunk(b"IDAT", chunk))
+    }
+
+    fn finish(self) -> Vec<u8> {
+      self.chunk(b"IEND", &[]).data
+    }
+  }
+
+  fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    let image = Png::parse(data)?;
+    let mut buffer = std::vec![0; image.buffer_len()];
+    let mut output = std::vec![0; image.output_len()];
+    image.decode(&mut buffer, &mut output)?;
+    Ok(output)
+  }
+
+  #[test]
+  fn filtered_rgb_rows_are_decoded() {
+    #[rustfmt::skip]
+    let raw = [
+      1, 10, 20, 30, 5, 5, 5,
+      2, 1, 1, 1, 1, 1, 1,
+      3, 0, 0, 0, 0, 0, 0,
+      4, 0, 0, 0, 1, 1, 1,
+    ];
+    let data = Builder::new(2, 4, 8, 2).image(&raw, 2).finish();
+    let image = Png::parse(&data).unwrap();
+
+    assert_eq!((image.width(), image.height()), (2, 4));
+    assert_eq!((image.color_type(), image.bit_depth()), (ColorType::Rgb, 8));
+    #[rustfmt::skip]
+    let expected = [
+      10, 20, 30, 0xff, 15, 25, 35, 0xff,
+      11, 21, 31, 0xff, 16, 26, 36, 0xff,
+      5, 10, 15, 0xff, 10, 18, 25, 0xff,
+      5, 10, 15, 0xff, 11, 19, 26, 0xff,
+    ];
+    assert_eq!(decode(&data).unwrap(), expected);
+  }
+
+  #[test]
+  fn palettes_and_their_transparency_are_applied() {
+    // Pixels of two bits, the last from past the transparency.
+    let data = Builder::new(3, 1, 2, 3)
+      .chunk(b"PLTE", &[1, 2, 3, 4, 5, 6, 7, 8, 9])
+      .chunk(b"tRNS", &[0, 0x80])
+      .image(&[0, 0b00_01_10_00], 1)
+      .finish();
+    let expected = [1, 2, 3, 0, 4, 5, 6, 0x80, 7, 8, 9, 0xff];
+    assert_eq!(decode(&data).unwrap(), expected);
+
+    let data = Builder::new(4, 1, 2, 3)
+      .chunk(b"PLTE", &[1, 2, 3, 4, 5, 6, 7, 8, 9])
+      .image(&[0, 0b00_01_10_11], 1)
+      .finish();
+    assert_eq!(decode(&data), Err(Error::Corrupted));
+  }
+
+  #[test]
+  fn grayscale_is_scaled_and_keyed() {
+    let data = Builder::new(2, 1, 16, 0)
+      .chunk(b"tRNS", &[0x12, 0x34])
+      .image(&[0, 0x12, 0x34, 0xab, 0xcd], 1)
+      .finish();
+    assert_eq!(
+      decode(&data).unwrap(),
+      [0x12, 0x12, 0x12, 0, 0xab, 0xab, 0xab, 0xff]
+    );
+
+    let data = Builder::new(2, 1, 1, 0)
+      .image(&[0, 0b0100_0000], 1)
+      .finish();
+    assert_eq!(
+      decode(&data).unwrap(),
+      [0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff]
+    );
+  }
+
+  #[test]
+  fn buffers_must_hold_the_image() {
+    let data = Builder::new(1, 1, 8, 6).image(&[0, 1, 2, 3, 4], 1).finish();
+    let image = Png::parse(&data).unwrap();
+    let mut buffer = std::vec![0; image.buffer_len()];
+    let mut output = [0; 4];
+
+    assert_eq!(image.output_len(), 4);
+    assert_eq!(
+      image.decode(&mut buffer[1..], &mut output),
+      Err(Error::BufferTooSmall)
+    );
+    assert_eq!(
+      image.decode(&mut buffer, &mut output[1..]),
+      Err(Error::BufferTooSmall)
+    );
+    assert_eq!(image.decode(&mut buffer, &mut output), Ok(()));
+    assert_eq!(output, [1, 2, 3, 4]);
+  }
+
+  #[test]
+  fn malformed_images_are_rejected() {
+    let data = Builder::new(1, 1, 8, 0).image(&[0, 0], 1).finish();
+    assert!(Png::parse(&data).is_ok());
+
+    let mut corrupted = data.clone();
+    corrupted[20] ^= 1;
+    assert_eq!(Png::parse(&corrupted).err(), Some(Error::Corrupted));
+    let truncated = &data[..data.len() - 1];
+    assert_eq!(Png::parse(truncated).err(), Some(Error::Corrupted));
+    let data = Builder::new(1, 1, 8, 0).image(&[0, 0, 0], 1).finish();
+    assert_eq!(decode(&data), Err(Error::Corrupted));
+    let data = Builder::new(1, 1, 8, 0).image(&[5, 0], 1).finish();
+    assert_eq!(decode(&data), Err(Error::Corrupted));
+    let data = Builder::new(1, 1, 3, 0).finish();
+    assert_eq!(Png::parse(&data).err(), Some(Error::Corrupted));
+  }
+
+  #[test]
+  fn other_forms_are_unsupported() {
+    let mut header = Builder::new(1, 1, 8, 0).data;
+    header[28] = 1;
+    let offset = SIGNATURE.len() + 4;
+    let crc = crc32(&header[offset..offset + 4 + HEADER_SIZE]);
+    header[offset + 4 + HEADER_SIZE..][..4].copy_from_slice(&crc.to_be_bytes());
+    let interlaced = Builder { data: header }.image(&[0, 0], 1).finish();
+    assert_eq!(Png::parse(&interlaced).err(), Some(Error::Unsupported));
+
+    let data = Builder::new(1, 1, 8, 0)
+      .chunk(b"XYZW", &[])
+      .image(&[0, 0], 1)
+      .finish();
+    assert_eq!(Png::parse(&data).err(), Some(Error::Unsupported));
+    let data = Builder::new(1, 1, 8, 0)
+      .chunk(b"xYZW", &[])
+      .image(&[0, 0], 1)
+      .finish();
+    assert!(Png::parse(&data).is_ok());
+  }
+}
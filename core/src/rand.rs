@@ -0,0 +1,184 @@
+//! This module provides the interface to random number generators, which the
+//! random number generator of the processor and the deterministic generators
+//! of the `crypto` crate implement, so that code that needs random numbers,
+//! such as for the keys of hash tables or version 4 GUIDs, takes any of them.
+//!
+//! Generators implement [`RngCore`], and get the helpers of [`Rng`] with it.
+//! Those whose output is fit for keys and nonces are also [`CryptoRng`]s. The
+//! generator of the processor, [`Hardware`], is slow and may be missing or
+//! fail, so it is meant to seed a deterministic generator rather than to be
+//! used directly.
+
+use crate::error::{Error, Result};
+use crate::guid::Guid;
+use crate::hash::RandomState;
+
+/// A generator of random bytes.
+pub trait RngCore {
+  /// Fills `buffer` with random bytes, failing if the generator cannot, such
+  /// as when a hardware generator runs dry, or a deterministic one must be
+  /// reseeded first.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer to fill
+  fn try_fill(&mut self, buffer: &mut [u8]) -> Result<()>;
+
+  /// Fills `buffer` with random bytes, panicking if the generator fails.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer to fill
+  fn fill_bytes(&mut self, buffer: &mut [u8]) {
+    if let Err(error) = self.try_fill(buffer) {
+      panic!("random number generator failed: {:?}", error);
+    }
+  }
+
+  /// Returns a random `u32`, panicking if the generator fails.
+  fn next_u32(&mut self) -> u32 {
+    let mut bytes = [0; 4];
+    self.fill_bytes(&mut bytes);
+    u32::from_le_bytes(bytes)
+  }
+
+  /// Returns a random `u64`, panicking if the generator fails.
+  fn next_u64(&mut self) -> u64 {
+    let mut bytes = [0; 8];
+    self.fill_bytes(&mut bytes);
+    u64::from_le_bytes(bytes)
+  }
+}
+
+/// A generator whose output cannot be predicted from what it has output
+/// before, which makes it fit for keys and nonces.
+pub trait CryptoRng: RngCore {}
+
+impl<R: RngCore + ?Sized> RngCore for &mut R {
+  fn try_fill(&mut self, buffer: &mut [u8]) -> Result<()> {
+    (**self).try_fill(buffer)
+  }
+
+  fn fill_bytes(&mut self, buffer: &mut [u8]) {
+    (**self).fill_bytes(buffer)
+  }
+
+  fn next_u32(&mut self) -> u32 {
+    (**self).next_u32()
+  }
+
+  fn next_u64(&mut self) -> u64 {
+    (**self).next_u64()
+  }
+}
+
+impl<R: CryptoRng + ?Sized> CryptoRng for &mut R {}
+
+/// The helpers that every [`RngCore`] has, which panic if the generator
+/// fails.
+pub trait Rng: RngCore {
+  /// Returns `N` random bytes.
+  fn gen_bytes<const N: usize>(&mut self) -> [u8; N] {
+    let mut bytes = [0; N];
+    self.fill_bytes(&mut bytes);
+    bytes
+  }
+
+  /// Returns a random number below `bound`, every one equally likely.
+  ///
+  /// # Arguments
+  ///
+  /// * `bound` - the bound of the number, which must not be `0`
+  fn gen_below(&mut self, bound: u64) -> u64 {
+    assert!(bound != 0, "the bound of a random number must not be 0");
+    // The high half of the product of a random number and the bound is below
+    // the bound; the products whose low halves fall below the threshold would
+    // make some numbers likelier than others, so they are drawn again.
+    let threshold = bound.wrapping_neg() % bound;
+    loop {
+      let product = self.next_u64() as u128 * bound as u128;
+      if product as u64 >= threshold {
+        return (product >> 64) as u64;
+      }
+    }
+  }
+
+  /// Returns a random, version 4, [`Guid`].
+  fn gen_guid(&mut self) -> Guid {
+    Guid::new_v4(self.gen_bytes())
+  }
+
+  /// Returns a builder of hashers with a random key, for hash tables whose
+  /// keys come from untrusted sources.
+  fn gen_random_state(&mut self) -> RandomState {
+    RandomState::with_keys(self.next_u64(), self.next_u64())
+  }
+}
+
+impl<R: RngCore + ?Sized> Rng for R {}
+
+/// The random number generator of the processor: RDRAND on x86-64, and RNDR
+/// on AArch64.
+///
+/// Filling fails with [`Error::Busy`] if the generator fails to produce bytes
+/// after a few tries, which it may while it gathers more entropy.
+pub struct Hardware(());
+
+impl Hardware {
+  /// Returns the generator of the processor, or `None` if it has none.
+  pub fn new() -> Option<Self> {
+    arch::hardware_random().map(|_| Self(()))
+  }
+}
+
+impl RngCore for Hardware {
+  fn try_fill(&mut self, buffer: &mut [u8]) -> Result<()> {
+    for chunk in buffer.chunks_mut(8) {
+      let bytes = arch::hardware_random().ok_or(Error::Busy)?.to_le_bytes();
+      chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+    Ok(())
+  }
+}
+
+impl CryptoRng for Hardware {}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  /// A generator that counts up, one byte at a time.
+  struct Counter(u8);
+
+  impl RngCore for Counter {
+    fn try_fill(&mut self, buffer: &mut [u8]) -> Result<()> {
+      for byte in buffer {
+        *byte = self.0;
+        self.0 = self.0.wrapping_add(1);
+      }
+      Ok(())
+    }
+  }
+
+  fn next_u64(mut rng: impl RngCore) -> u64 {
+    rng.next_u64()
+  }
+
+  #[test]
+  fn helpers_draw_from_the_generator() {
+    let mut rng = Counter(0);
+
+    assert_eq!(rng.gen_bytes::<3>(), [0, 1, 2]);
+    assert_eq!(rng.next_u32(), 0x0605_0403);
+    assert_eq!(next_u64(&mut rng), 0x0e0d_0c0b_0a09_0807);
+    assert_eq!(rng.gen_guid().version(), 4);
+  }
+
+  #[test]
+  fn gen_below_stays_below_the_bound() {
+    let mut rng = Counter(0);
+
+    assert!((0..100).all(|_| rng.gen_below(7) < 7));
+    assert_eq!(rng.gen_below(1), 0);
+  }
+}
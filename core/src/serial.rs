@@ -0,0 +1,429 @@
+//! This module provides [`SerialPort`], the interface to a byte stream such
+//! as a UART, and [`Link`], a reliable exchange of frames over one, for
+//! structured diagnostics, file transfer and debugging over a noisy line.
+//!
+//! Each frame holds its kind, a sequence number, its payload and the CRC32 of
+//! all three, encoded with [`cobs`] and delimited by zeros, so that a receiver
+//! that loses bytes or starts midway resynchronizes at the next frame. Frames
+//! that fail their checksum are dropped. Every data frame is acknowledged by
+//! its sequence number, and sent again until it is, so that each payload is
+//! delivered once, in order; a data frame that is received again, because
+//! its acknowledgement was lost, is acknowledged but not delivered again.
+
+pub mod cobs;
+
+use crate::checksum::crc32;
+use crate::error::{Error, Result};
+use crate::time::{ClockSource, Deadline, Duration};
+
+/// The largest payload of a frame, in bytes.
+pub const MAX_PAYLOAD: usize = 256;
+
+/// The time a data frame waits to be acknowledged before it is sent again.
+pub const RETRANSMIT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The number of times a data frame is sent before it is given up on.
+pub const MAX_ATTEMPTS: usize = 8;
+
+/// The byte that delimits frames.
+const DELIMITER: u8 = 0;
+
+/// The kind of a frame carrying a payload.
+const KIND_DATA: u8 = 1;
+
+/// The kind of a frame acknowledging a data frame.
+const KIND_ACK: u8 = 2;
+
+/// The size of a frame that is not its payload: its kind, sequence number
+/// and checksum.
+const OVERHEAD: usize = 6;
+
+/// The largest size of a frame before it is encoded.
+const MAX_FRAME: usize = MAX_PAYLOAD + OVERHEAD;
+
+/// The largest size of a frame once it is encoded, without its delimiter.
+const MAX_ENCODED: usize = cobs::max_encoded_len(MAX_FRAME);
+
+/// A byte stream, such as a UART.
+pub trait SerialPort {
+  /// Writes all of `bytes`, waiting for room to send them if need be.
+  ///
+  /// # Arguments
+  ///
+  /// * `bytes` - the bytes to write
+  fn write(&mut self, bytes: &[u8]) -> Result<()>;
+
+  /// Returns the next byte received, or [`None`] if none has arrived,
+  /// without waiting for one.
+  fn read_byte(&mut self) -> Result<Option<u8>>;
+}
+
+impl<T: SerialPort + ?Sized> SerialPort for &mut T {
+  fn write(&mut self, bytes: &[u8]) -> Result<()> {
+    (**self).write(bytes)
+  }
+
+  fn read_byte(&mut self) -> Result<Option<u8>> {
+    (**self).read_byte()
+  }
+}
+
+/// A reliable exchange of frames over a [`SerialPort`], timed by a
+/// [`ClockSource`].
+pub struct Link<P, C> {
+  port: P,
+  clock: C,
+  sequence: u8,
+  received: Option<u8>,
+  encoded: [u8; MAX_ENCODED],
+  encoded_len: usize,
+  discarding: bool,
+  frame: [u8; MAX_FRAME],
+  payload: [u8; MAX_PAYLOAD],
+  pending: Option<usize>,
+}
+
+impl<P: SerialPort, C: ClockSource> Link<P, C> {
+  /// Constructs a link over `port`, timed by `clock`.
+  ///
+  /// # Arguments
+  ///
+  /// * `port` - the byte stream to exchange frames over
+  /// * `clock` - the clock to time retransmission with
+  pub fn new(port: P, clock: C) -> Self {
+    Self {
+      port,
+      clock,
+      sequence: 0,
+      received: None,
+      encoded: [0; MAX_ENCODED],
+      encoded_len: 0,
+      discarding: false,
+      frame: [0; MAX_FRAME],
+      payload: [0; MAX_PAYLOAD],
+      pending: None,
+    }
+  }
+
+  /// Returns the port that frames are exchanged over.
+  pub fn port(&mut self) -> &mut P {
+    &mut self.port
+  }
+
+  /// Sends `payload`, and waits for it to be acknowledged.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if it is longer than
+  /// [`MAX_PAYLOAD`], or [`Error::Timeout`] if it is not acknowledged after
+  /// [`MAX_ATTEMPTS`]. A payload received while waiting is kept for the next
+  /// [`receive`](Self::receive), unless one already is, in which case it is
+  /// left unacknowledged for the peer to send again.
+  ///
+  /// # Arguments
+  ///
+  /// * `payload` - the payload to send
+  pub fn send(&mut self, payload: &[u8]) -> Result<()> {
+    if payload.len() > MAX_PAYLOAD {
+      return Err(Error::InvalidArgument);
+    }
+    let sequence = self.sequence;
+    for _ in 0..MAX_ATTEMPTS {
+      self.write_frame(KIND_DATA, sequence, payload)?;
+      let deadline = Deadline::after(&self.clock, RETRANSMIT_TIMEOUT);
+      while !deadline.has_passed(&self.clock) {
+        match self.poll()? {
+          Some((KIND_ACK, acked, _)) if acked == sequence => {
+            self.sequence = sequence.wrapping_add(1);
+            return Ok(());
+          }
+          Some((KIND_DATA, _, len)) if self.pending.is_none() => {
+            self.pending = self.accept(len)?;
+          }
+          _ => core::hint::spin_loop(),
+        }
+      }
+    }
+    Err(Error::Timeout)
+  }
+
+  /// Returns the next payload received into `buffer`, and its length, or
+  /// [`None`] if none has arrived, without waiting for one.
+  ///
+  /// Fails with [`Error::BufferTooSmall`] if the payload does not fit, in
+  /// which case it is lost.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer to receive the payload into
+  pub fn receive(&mut self, buffer: &mut [u8]) -> Result<Option<usize>> {
+    loop {
+      if let Some(len) = self.pending.take() {
+        let payload = &self.payload[..len];
+        buffer
+          .get_mut(..len)
+          .ok_or(Error::BufferTooSmall)?
+          .copy_from_slice(payload);
+        return Ok(Some(len));
+      }
+      match self.poll()? {
+        Some((KIND_DATA, _, len)) => self.pending = self.accept(len)?,
+        Some(_) => {}
+        None => return Ok(None),
+      }
+    }
+  }
+
+  /// Acknowledges the data frame in `frame` with a payload of `len` bytes,
+  /// returning `len` if it was not already delivered, in which case the
+  /// payload is kept in `payload`, since the next frame polled overwrites
+  /// `frame`.
+  ///
+  /// # Arguments
+  ///
+  /// * `len` - the length of the payload
+  fn accept(&mut self, len: usize) -> Result<Option<usize>> {
+    let sequence = self.frame[1];
+    self.write_frame(KIND_ACK, sequence, &[])?;
+    if self.received == Some(sequence) {
+      return Ok(None);
+    }
+    self.received = Some(sequence);
+    self.payload[..len].copy_from_slice(&self.frame[2..2 + len]);
+    Ok(Some(len))
+  }
+
+  /// Reads the bytes received until a whole frame has been, returning its
+  /// kind, sequence number and the length of its payload, which is left in
+  /// `frame`, or [`None`] if no more bytes have arrived.
+  ///
+  /// Frames that are too long, malformed or fail their checksum are dropped.
+  fn poll(&mut self) -> Result<Option<(u8, u8, usize)>> {
+    while let Some(byte) = self.port.read_byte()? {
+      if byte != DELIMITER {
+        match self.encoded.get_mut(self.encoded_len) {
+          Some(slot) if !self.discarding => {
+            *slot = byte;
+            self.encoded_len += 1;
+          }
+          _ => self.discarding = true,
+        }
+        continue;
+      }
+
+      let encoded = &self.encoded[..self.encoded_len];
+      let decoded = (!self.discarding)
+        .then(|| cobs::decode(encoded, &mut self.frame).ok())
+        .flatten();
+      self.encoded_len = 0;
+      self.discarding = false;
+      let Some(len) = decoded.filter(|&len| len >= OVERHEAD) else {
+        continue;
+      };
+      let (frame, checksum) = self.frame[..len].split_at(len - 4);
+      if crc32(frame).to_le_bytes() == checksum {
+        return Ok(Some((frame[0], frame[1], len - OVERHEAD)));
+      }
+    }
+    Ok(None)
+  }
+
+  /// Writes the frame of `kind` and `sequence` holding `payload`.
+  ///
+  /// The frame is preceded by a delimiter too, so that the receiver drops
+  /// any noise that it received since the last frame.
+  ///
+  /// # Arguments
+  ///
+  /// * `kind` - the kind of the frame
+  /// * `sequence` - the sequence number of the frame
+  /// * `payload` - the payload of the frame
+  fn write_frame(
+    &mut self,
+    kind: u8,
+    sequence: u8,
+    payload: &[u8],
+  ) -> Result<()> {
+    let mut frame = [0; MAX_FRAME];
+    let len = payload.len() + OVERHEAD;
+    frame[0] = kind;
+    frame[1] = sequence;
+    frame[2..len - 4].copy_from_slice(payload);
+    let checksum = crc32(&frame[..len - 4]);
+    frame[len - 4..len].copy_from_slice(&checksum.to_le_bytes());
+
+    let mut encoded = [0; MAX_ENCODED + 2];
+    let encoded_len = cobs::encode(&frame[..len], &mut encoded[1..])?;
+    encoded[0] = DELIMITER;
+    encoded[encoded_len + 1] = DELIMITER;
+    self.port.write(&encoded[..encoded_len + 2])
+  }
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::collections::VecDeque;
+  use std::sync::{Arc, Mutex};
+  use std::vec::Vec;
+
+  /// One direction of a line, which corrupts the byte at `corrupt` once.
+  #[derive(Default)]
+  struct Line {
+    bytes: VecDeque<u8>,
+    sent: usize,
+    corrupt: Option<usize>,
+  }
+
+  /// One end of a line between two ports.
+  struct Port {
+    tx: Arc<Mutex<Line>>,
+    rx: Arc<Mutex<Line>>,
+  }
+
+  impl SerialPort for Port {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+      let mut line = self.tx.lock().unwrap();
+      for &byte in bytes {
+        let byte = match line.corrupt {
+          Some(index) if index == line.sent => byte ^ 0x10,
+          _ => byte,
+        };
+        line.sent += 1;
+        line.bytes.push_back(byte);
+      }
+      Ok(())
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+      Ok(self.rx.lock().unwrap().bytes.pop_front())
+    }
+  }
+
+  /// The time since the clock was created, in nanoseconds.
+  struct Clock(std::time::Instant);
+
+  impl ClockSource for Clock {
+    fn frequency(&self) -> u64 {
+      1_000_000_000
+    }
+
+    fn read(&self) -> u64 {
+      self.0.elapsed().as_nanos() as u64
+    }
+  }
+
+  fn pair(corrupt: Option<usize>) -> (Link<Port, Clock>, Link<Port, Clock>) {
+    let there = Arc::new(Mutex::new(Line {
+      corrupt,
+      ..Line::default()
+    }));
+    let back = Arc::new(Mutex::new(Line::default()));
+    let near = Port {
+      tx: there.clone(),
+      rx: back.clone(),
+    };
+    let far = Port {
+      tx: back,
+      rx: there,
+    };
+    let clock = || Clock(std::time::Instant::now());
+    (Link::new(near, clock()), Link::new(far, clock()))
+  }
+
+  /// Receives `count` payloads on `link`, from another thread.
+  fn receive(
+    mut link: Link<Port, Clock>,
+    count: usize,
+  ) -> std::thread::JoinHandle<Vec<Vec<u8>>> {
+    std::thread::spawn(move || {
+      let mut payloads = Vec::new();
+      let mut buffer = [0; MAX_PAYLOAD];
+      while payloads.len() < count {
+        if let Some(len) = link.receive(&mut buffer).unwrap() {
+          payloads.push(buffer[..len].to_vec());
+        }
+      }
+      // Keep acknowledging frames sent again until the sender is done.
+      let deadline = Deadline::after(&link.clock, Duration::from_millis(300));
+      while !deadline.has_passed(&link.clock) {
+        link.receive(&mut buffer).unwrap();
+      }
+      payloads
+    })
+  }
+
+  #[test]
+  fn payloads_are_delivered_in_order() {
+    let (mut near, far) = pair(None);
+    let receiver = receive(far, 3);
+    near.send(b"first").unwrap();
+    near.send(&[0; MAX_PAYLOAD]).unwrap();
+    near.send(b"").unwrap();
+    let payloads = receiver.join().unwrap();
+    assert_eq!(payloads, [&b"first"[..], &[0; MAX_PAYLOAD], b""]);
+    assert_eq!(
+      near.send(&[0; MAX_PAYLOAD + 1]),
+      Err(Error::InvalidArgument)
+    );
+  }
+
+  #[test]
+  fn corrupted_frames_are_sent_again() {
+    let (mut near, far) = pair(Some(5));
+    let receiver = receive(far, 2);
+    near.send(b"corrupted once").unwrap();
+    near.send(b"intact").unwrap();
+    let payloads = receiver.join().unwrap();
+    assert_eq!(payloads, [&b"corrupted once"[..], b"intact"]);
+  }
+
+  #[test]
+  fn payloads_sent_both_ways_at_once_are_kept() {
+    let (mut near, mut far) = pair(None);
+    let sender = std::thread::spawn(move || {
+      far.send(b"hello from far").unwrap();
+      let mut buffer = [0; MAX_PAYLOAD];
+      let received = loop {
+        if let Some(len) = far.receive(&mut buffer).unwrap() {
+          break buffer[..len].to_vec();
+        }
+      };
+      // Keep acknowledging frames sent again until the peer is done.
+      let deadline = Deadline::after(&far.clock, Duration::from_millis(300));
+      while !deadline.has_passed(&far.clock) {
+        far.receive(&mut buffer).unwrap();
+      }
+      received
+    });
+    near.send(b"hello from near").unwrap();
+    let mut buffer = [0; MAX_PAYLOAD];
+    let received = loop {
+      if let Some(len) = near.receive(&mut buffer).unwrap() {
+        break buffer[..len].to_vec();
+      }
+    };
+
+    assert_eq!(received, b"hello from far");
+    assert_eq!(sender.join().unwrap(), b"hello from near");
+  }
+
+  #[test]
+  fn unacknowledged_frames_time_out() {
+    let (mut near, _far) = pair(None);
+    assert_eq!(near.send(b"nobody listens"), Err(Error::Timeout));
+  }
+
+  #[test]
+  fn noise_between_frames_is_ignored() {
+    let (mut near, mut far) = pair(None);
+    far.port().write(&[0x55; MAX_ENCODED + 10]).unwrap();
+    far.port().write(&[3, 1, 2, 0]).unwrap();
+    near.write_frame(KIND_DATA, 0, b"ok").unwrap();
+    let mut buffer = [0; 16];
+    assert_eq!(near.receive(&mut buffer), Ok(None));
+    assert_eq!(far.receive(&mut buffer), Ok(Some(2)));
+    assert_eq!(&buffer[..2], b"ok");
+    assert_eq!(near.poll(), Ok(Some((KIND_ACK, 0, 0))));
+  }
+}
@@ -0,0 +1,137 @@
+//! This module provides Consistent Overhead Byte Stuffing, which encodes data
+//! without any zero bytes, so that a zero can delimit frames on a byte stream.
+//!
+//! The data is split at each zero into blocks, each of which is written as
+//! its length plus one followed by its bytes; blocks of 254 bytes are split
+//! without a zero between them. The encoding is at most one byte longer for
+//! every 254 bytes of data, plus one.
+
+use crate::error::{Error, Result};
+
+/// The largest code of a block, which is followed by 254 bytes and no zero.
+const MAX_CODE: u8 = 0xff;
+
+/// Returns the largest length of the encoding of `len` bytes.
+///
+/// # Arguments
+///
+/// * `len` - the length of the data
+pub const fn max_encoded_len(len: usize) -> usize {
+  len + len / (MAX_CODE as usize - 1) + 1
+}
+
+/// Encodes `data` into `output`, returning the length of the encoding, or
+/// failing with [`Error::BufferTooSmall`] if it does not fit.
+///
+/// # Arguments
+///
+/// * `data` - the data to encode
+/// * `output` - the buffer to write the encoding into
+pub fn encode(data: &[u8], output: &mut [u8]) -> Result<usize> {
+  if output.len() < max_encoded_len(data.len()) {
+    return Err(Error::BufferTooSmall);
+  }
+  let (mut code_index, mut len, mut code) = (0, 1, 1);
+  for &byte in data {
+    if byte != 0 {
+      output[len] = byte;
+      len += 1;
+      code += 1;
+    }
+    if byte == 0 || code == MAX_CODE {
+      output[code_index] = code;
+      code_index = len;
+      len += 1;
+      code = 1;
+    }
+  }
+  output[code_index] = code;
+  Ok(len)
+}
+
+/// Decodes `data`, which holds no delimiter, into `output`, returning the
+/// length of the data, or failing with [`Error::Corrupted`] if it is not an
+/// encoding or [`Error::BufferTooSmall`] if the data does not fit.
+///
+/// # Arguments
+///
+/// * `data` - the encoding
+/// * `output` - the buffer to write the data into
+pub fn decode(data: &[u8], output: &mut [u8]) -> Result<usize> {
+  let (mut index, mut len) = (0, 0);
+  while index < data.len() {
+    let code = data[index] as usize;
+    let block = data.get(index + 1..index + code).ok_or(Error::Corrupted)?;
+    if code == 0 || block.contains(&0) {
+      return Err(Error::Corrupted);
+    }
+    index += code;
+    let zero = code != MAX_CODE as usize && index < data.len();
+    output
+      .get_mut(len..len + block.len() + zero as usize)
+      .ok_or(Error::BufferTooSmall)?
+      .iter_mut()
+      .zip(block.iter().chain([0].iter()))
+      .for_each(|(output, &byte)| *output = byte);
+    len += block.len() + zero as usize;
+  }
+  Ok(len)
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::vec::Vec;
+
+  fn round_trip(data: &[u8]) -> Vec<u8> {
+    let mut encoded = [0xaa; 1024];
+    let len = encode(data, &mut encoded).unwrap();
+    let encoded = &encoded[..len];
+    assert!(!encoded.contains(&0));
+    assert!(len <= max_encoded_len(data.len()));
+
+    let mut decoded = [0xaa; 1024];
+    let decoded_len = decode(encoded, &mut decoded).unwrap();
+    assert_eq!(&decoded[..decoded_len], data);
+    encoded.to_vec()
+  }
+
+  #[test]
+  fn zeros_are_replaced_with_block_lengths() {
+    assert_eq!(round_trip(&[]), [1]);
+    assert_eq!(round_trip(&[0]), [1, 1]);
+    assert_eq!(round_trip(&[0, 0]), [1, 1, 1]);
+    assert_eq!(round_trip(&[0x11, 0x22, 0, 0x33]), [3, 0x11, 0x22, 2, 0x33]);
+    assert_eq!(round_trip(&[0x11, 0, 0, 0]), [2, 0x11, 1, 1, 1]);
+  }
+
+  #[test]
+  fn long_blocks_are_split() {
+    let data: Vec<u8> = (1..=255).collect();
+    let encoded = round_trip(&data[..254]);
+    assert_eq!((encoded[0], encoded.len()), (0xff, 256));
+    let encoded = round_trip(&data);
+    assert_eq!((encoded[0], encoded[255], encoded.len()), (0xff, 2, 257));
+    round_trip(&[0; 600]);
+    let mixed: Vec<u8> = (0..1000).map(|i| (i % 300) as u8).collect();
+    round_trip(&mixed[..700]);
+  }
+
+  #[test]
+  fn malformed_encodings_are_rejected() {
+    let mut output = [0; 16];
+    assert_eq!(decode(&[3, 1], &mut output), Err(Error::Corrupted));
+    assert_eq!(decode(&[2, 0], &mut output), Err(Error::Corrupted));
+    assert_eq!(decode(&[0], &mut output), Err(Error::Corrupted));
+    assert_eq!(
+      decode(&[3, 1, 2], &mut output[..1]),
+      Err(Error::BufferTooSmall)
+    );
+    assert_eq!(
+      encode(&[1; 10], &mut output[..10]),
+      Err(Error::BufferTooSmall)
+    );
+  }
+}
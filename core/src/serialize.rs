@@ -0,0 +1,495 @@
+//! This module provides the binary encoding of the structures that outlive a
+//! single binary, such as the records handed to the kernel and state saved
+//! across boots, with the [`Encode`] and [`Decode`] traits and the
+//! [`serializable!`](crate::serializable!) macro that implements them.
+//!
+//! ```ignore
+//! struct Saved {
+//!   entry: u32,
+//!   attempts: u8,
+//!   last_error: u16,
+//! }
+//!
+//! // `last_error` was added in version 2, and is zero in older blobs.
+//! kcore::serializable!(Saved { entry, attempts, last_error @ 2 });
+//!
+//! let len = serialize::encode(MAGIC, 2, &saved, &mut buffer)?;
+//! let saved: Saved = serialize::decode(MAGIC, 2, &buffer[..len])?;
+//! ```
+//!
+//! Values are encoded field by field in little-endian order, without padding,
+//! so that the encoding does not depend on the compiler's layout of the
+//! structure. Nothing is allocated: values are written into caller-provided
+//! buffers, and borrowed slices and strings are decoded in place.
+//!
+//! A blob, as written by [`encode`], starts with a magic number, the version
+//! of the layout it was written with, the length of the encoding and its
+//! CRC32. Decoding fails with [`Error::InvalidArgument`] for another magic
+//! number, with [`Error::Unsupported`] for a version newer than the reader
+//! knows, and with [`Error::Corrupted`] for a blob that is malformed or fails
+//! its checksum. Fields added in later versions are appended, and take their
+//! default values when older blobs are decoded.
+
+use crate::checksum::crc32;
+use crate::error::{Error, Result};
+
+/// The size of the header of a blob.
+const HEADER_SIZE: usize = 16;
+
+/// A cursor that writes encoded values into a buffer.
+pub struct Writer<'a> {
+  buffer: &'a mut [u8],
+  position: usize,
+}
+
+impl<'a> Writer<'a> {
+  /// Constructs a writer at the start of `buffer`.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer to write into
+  pub fn new(buffer: &'a mut [u8]) -> Self {
+    Self {
+      buffer,
+      position: 0,
+    }
+  }
+
+  /// Returns the number of bytes written so far.
+  pub fn position(&self) -> usize {
+    self.position
+  }
+
+  /// Writes `bytes`, failing with [`Error::BufferTooSmall`] if they do not
+  /// fit.
+  ///
+  /// # Arguments
+  ///
+  /// * `bytes` - the bytes to write
+  pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+    let end = self.position + bytes.len();
+    self
+      .buffer
+      .get_mut(self.position..end)
+      .ok_or(Error::BufferTooSmall)?
+      .copy_from_slice(bytes);
+    self.position = end;
+    Ok(())
+  }
+}
+
+/// A cursor that reads encoded values from data.
+#[derive(Clone)]
+pub struct Reader<'a> {
+  data: &'a [u8],
+  version: u16,
+}
+
+impl<'a> Reader<'a> {
+  /// Constructs a reader over `data`, which was written with the layout
+  /// `version`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the encoded values
+  /// * `version` - the version of the layout the values were written with
+  pub fn new(data: &'a [u8], version: u16) -> Self {
+    Self { data, version }
+  }
+
+  /// Returns the version of the layout that the data was written with.
+  pub fn version(&self) -> u16 {
+    self.version
+  }
+
+  /// Returns the data that has not been read yet.
+  pub fn remaining(&self) -> &'a [u8] {
+    self.data
+  }
+
+  /// Reads the next `len` bytes, failing with [`Error::Corrupted`] if the
+  /// data ends first.
+  ///
+  /// # Arguments
+  ///
+  /// * `len` - the number of bytes to read
+  pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+    if len > self.data.len() {
+      return Err(Error::Corrupted);
+    }
+    let (bytes, rest) = self.data.split_at(len);
+    self.data = rest;
+    Ok(bytes)
+  }
+
+  /// Reads the next `N` bytes as an array.
+  fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+    let mut array = [0; N];
+    array.copy_from_slice(self.read_bytes(N)?);
+    Ok(array)
+  }
+}
+
+/// A value that can be encoded.
+pub trait Encode {
+  /// Writes the encoding of the value to `writer`.
+  ///
+  /// # Arguments
+  ///
+  /// * `writer` - the writer to encode into
+  fn encode(&self, writer: &mut Writer<'_>) -> Result<()>;
+}
+
+/// A value that can be decoded, possibly borrowing from the data `'a`.
+pub trait Decode<'a>: Sized {
+  /// Reads a value from `reader`, failing with [`Error::Corrupted`] if the
+  /// data is malformed.
+  ///
+  /// # Arguments
+  ///
+  /// * `reader` - the reader to decode from
+  fn decode(reader: &mut Reader<'a>) -> Result<Self>;
+}
+
+/// Implements [`Encode`] and [`Decode`] for a structure, encoding the listed
+/// fields in order.
+///
+/// A field followed by `@` and a version was added in that version of the
+/// layout, and is decoded as its [`Default`] from data written with an older
+/// one. Fields of tuple structures are listed by index. A lifetime after the
+/// name of the structure is the lifetime of the data it borrows when decoded.
+#[macro_export]
+macro_rules! serializable {
+  (
+    $name:ident<$lifetime:lifetime> {
+      $($field:tt $(@ $since:literal)?),* $(,)?
+    }
+  ) => {
+    $crate::serializable!(@encode $name<'_> { $($field),* });
+
+    impl<$lifetime> $crate::serialize::Decode<$lifetime> for $name<$lifetime> {
+      fn decode(
+        reader: &mut $crate::serialize::Reader<$lifetime>,
+      ) -> $crate::error::Result<Self> {
+        Ok(Self {
+          $($field: $crate::serializable!(@field reader $(, $since)?),)*
+        })
+      }
+    }
+  };
+  ($name:ident { $($field:tt $(@ $since:literal)?),* $(,)? }) => {
+    $crate::serializable!(@encode $name { $($field),* });
+
+    impl<'a> $crate::serialize::Decode<'a> for $name {
+      fn decode(
+        reader: &mut $crate::serialize::Reader<'a>,
+      ) -> $crate::error::Result<Self> {
+        Ok(Self {
+          $($field: $crate::serializable!(@field reader $(, $since)?),)*
+        })
+      }
+    }
+  };
+  (@encode $type:ty { $($field:tt),* }) => {
+    impl $crate::serialize::Encode for $type {
+      fn encode(
+        &self,
+        writer: &mut $crate::serialize::Writer<'_>,
+      ) -> $crate::error::Result<()> {
+        $($crate::serialize::Encode::encode(&self.$field, writer)?;)*
+        Ok(())
+      }
+    }
+  };
+  (@field $reader:ident) => {
+    $crate::serialize::Decode::decode($reader)?
+  };
+  (@field $reader:ident, $since:literal) => {
+    if $reader.version() >= $since {
+      $crate::serialize::Decode::decode($reader)?
+    } else {
+      Default::default()
+    }
+  };
+}
+
+/// Encodes `value` as a blob of the layout `version` into `buffer`,
+/// returning the length of the blob.
+///
+/// Fails with [`Error::BufferTooSmall`] if the blob does not fit.
+///
+/// # Arguments
+///
+/// * `magic` - the magic number identifying the kind of blob
+/// * `version` - the version of the layout that `value` is encoded with
+/// * `value` - the value to encode
+/// * `buffer` - the buffer to write the blob into
+pub fn encode<T: Encode + ?Sized>(
+  magic: [u8; 4],
+  version: u16,
+  value: &T,
+  buffer: &mut [u8],
+) -> Result<usize> {
+  if buffer.len() < HEADER_SIZE {
+    return Err(Error::BufferTooSmall);
+  }
+  let (header, payload) = buffer.split_at_mut(HEADER_SIZE);
+  let mut writer = Writer::new(payload);
+  value.encode(&mut writer)?;
+  let len = writer.position();
+
+  let mut writer = Writer::new(header);
+  writer.write_bytes(&magic)?;
+  version.encode(&mut writer)?;
+  0u16.encode(&mut writer)?;
+  (len as u32).encode(&mut writer)?;
+  crc32(&payload[..len]).encode(&mut writer)?;
+  Ok(HEADER_SIZE + len)
+}
+
+/// Decodes the blob in `data`, which must be of the layout `version` or an
+/// older one.
+///
+/// # Arguments
+///
+/// * `magic` - the magic number identifying the kind of blob
+/// * `version` - the newest version of the layout that is understood
+/// * `data` - the blob
+pub fn decode<'a, T: Decode<'a>>(
+  magic: [u8; 4],
+  version: u16,
+  data: &'a [u8],
+) -> Result<T> {
+  let mut header = Reader::new(data, 0);
+  if header.read_array::<4>()? != magic {
+    return Err(Error::InvalidArgument);
+  }
+  let written = u16::decode(&mut header)?;
+  let _reserved = u16::decode(&mut header)?;
+  let len = u32::decode(&mut header)? as usize;
+  let crc = u32::decode(&mut header)?;
+  if written > version {
+    return Err(Error::Unsupported);
+  }
+  let payload = header.read_bytes(len)?;
+  if crc32(payload) != crc {
+    return Err(Error::Corrupted);
+  }
+
+  let mut reader = Reader::new(payload, written);
+  let value = T::decode(&mut reader)?;
+  if !reader.remaining().is_empty() {
+    return Err(Error::Corrupted);
+  }
+  Ok(value)
+}
+
+macro_rules! serializable_integers {
+  ($($type:ty),*) => {
+    $(
+      impl Encode for $type {
+        fn encode(&self, writer: &mut Writer<'_>) -> Result<()> {
+          writer.write_bytes(&self.to_le_bytes())
+        }
+      }
+
+      impl Decode<'_> for $type {
+        fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+          Ok(Self::from_le_bytes(reader.read_array()?))
+        }
+      }
+    )*
+  };
+}
+
+serializable_integers!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl Encode for bool {
+  fn encode(&self, writer: &mut Writer<'_>) -> Result<()> {
+    (*self as u8).encode(writer)
+  }
+}
+
+impl Decode<'_> for bool {
+  fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+    match u8::decode(reader)? {
+      0 => Ok(false),
+      1 => Ok(true),
+      _ => Err(Error::Corrupted),
+    }
+  }
+}
+
+/// Arrays of bytes are encoded as they are, without a length.
+impl<const N: usize> Encode for [u8; N] {
+  fn encode(&self, writer: &mut Writer<'_>) -> Result<()> {
+    writer.write_bytes(self)
+  }
+}
+
+impl<const N: usize> Decode<'_> for [u8; N] {
+  fn decode(reader: &mut Reader<'_>) -> Result<Self> {
+    reader.read_array()
+  }
+}
+
+/// Slices of bytes are encoded with their length, as a `u32`, first.
+impl Encode for [u8] {
+  fn encode(&self, writer: &mut Writer<'_>) -> Result<()> {
+    let len = u32::try_from(self.len()).map_err(|_| Error::InvalidArgument)?;
+    len.encode(writer)?;
+    writer.write_bytes(self)
+  }
+}
+
+impl<'a> Decode<'a> for &'a [u8] {
+  fn decode(reader: &mut Reader<'a>) -> Result<Self> {
+    let len = u32::decode(reader)? as usize;
+    reader.read_bytes(len)
+  }
+}
+
+/// Strings are encoded as their UTF-8 bytes.
+impl Encode for str {
+  fn encode(&self, writer: &mut Writer<'_>) -> Result<()> {
+    self.as_bytes().encode(writer)
+  }
+}
+
+impl<'a> Decode<'a> for &'a str {
+  fn decode(reader: &mut Reader<'a>) -> Result<Self> {
+    core::str::from_utf8(Decode::decode(reader)?).map_err(|_| Error::Corrupted)
+  }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+  fn encode(&self, writer: &mut Writer<'_>) -> Result<()> {
+    (**self).encode(writer)
+  }
+}
+
+/// Optional values are encoded with a byte that is `1` if there is a value,
+/// followed by the value, or `0` if there is not.
+impl<T: Encode> Encode for Option<T> {
+  fn encode(&self, writer: &mut Writer<'_>) -> Result<()> {
+    self.is_some().encode(writer)?;
+    match self {
+      Some(value) => value.encode(writer),
+      None => Ok(()),
+    }
+  }
+}
+
+impl<'a, T: Decode<'a>> Decode<'a> for Option<T> {
+  fn decode(reader: &mut Reader<'a>) -> Result<Self> {
+    if bool::decode(reader)? {
+      T::decode(reader).map(Some)
+    } else {
+      Ok(None)
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  const MAGIC: [u8; 4] = *b"TEST";
+
+  #[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+  struct Id(u16);
+
+  crate::serializable!(Id { 0 });
+
+  #[derive(PartialEq, Eq, Debug)]
+  struct Saved<'a> {
+    entry: u32,
+    name: &'a str,
+    digest: Option<[u8; 4]>,
+    enabled: bool,
+    id: Id,
+    attempts: i8,
+  }
+
+  crate::serializable!(Saved<'a> {
+    entry,
+    name,
+    digest,
+    enabled,
+    id @ 2,
+    attempts @ 3,
+  });
+
+  fn saved() -> Saved<'static> {
+    Saved {
+      entry: 0x1234_5678,
+      name: "kernel",
+      digest: Some(*b"\xde\xad\xbe\xef"),
+      enabled: true,
+      id: Id(7),
+      attempts: -1,
+    }
+  }
+
+  #[test]
+  fn values_are_encoded_in_little_endian_order() {
+    let mut buffer = [0; 64];
+    let len = encode(MAGIC, 3, &saved(), &mut buffer).unwrap();
+    assert_eq!(&buffer[..4], b"TEST");
+    assert_eq!(&buffer[4..6], &[3, 0]);
+    assert_eq!(&buffer[8..12], &(len as u32 - 16).to_le_bytes());
+    assert_eq!(
+      &buffer[HEADER_SIZE..len],
+      b"\x78\x56\x34\x12\x06\0\0\0kernel\x01\xde\xad\xbe\xef\x01\x07\0\xff"
+    );
+    assert_eq!(decode(MAGIC, 3, &buffer[..len]), Ok(saved()));
+  }
+
+  #[test]
+  fn fields_missing_from_older_versions_take_their_defaults() {
+    let mut buffer = [0; 64];
+    let old = &(0x1234_5678u32, "kernel", None::<[u8; 4]>, false);
+    let len = encode(MAGIC, 1, &Tuple(old), &mut buffer).unwrap();
+    let saved: Saved = decode(MAGIC, 3, &buffer[..len]).unwrap();
+    assert_eq!((saved.digest, saved.id, saved.attempts), (None, Id(0), 0));
+    assert_eq!(saved.name, "kernel");
+  }
+
+  /// Encodes the fields of an older layout of [`Saved`].
+  struct Tuple<'a>(&'a (u32, &'a str, Option<[u8; 4]>, bool));
+
+  impl Encode for Tuple<'_> {
+    fn encode(&self, writer: &mut Writer<'_>) -> Result<()> {
+      let (entry, name, digest, enabled) = self.0;
+      entry.encode(writer)?;
+      name.encode(writer)?;
+      digest.encode(writer)?;
+      enabled.encode(writer)
+    }
+  }
+
+  #[test]
+  fn unreadable_blobs_are_rejected() {
+    let mut buffer = [0; 64];
+    assert_eq!(
+      encode(MAGIC, 3, &saved(), &mut buffer[..30]),
+      Err(Error::BufferTooSmall)
+    );
+    let len = encode(MAGIC, 3, &saved(), &mut buffer).unwrap();
+    let blob = &buffer[..len];
+
+    let result =
+      |data: &[u8], magic, version| decode::<Saved>(magic, version, data).err();
+    assert_eq!(result(blob, *b"ELSE", 3), Some(Error::InvalidArgument));
+    assert_eq!(result(blob, MAGIC, 2), Some(Error::Unsupported));
+    assert_eq!(result(&blob[..len - 1], MAGIC, 3), Some(Error::Corrupted));
+    let mut flipped = buffer;
+    flipped[HEADER_SIZE] ^= 1;
+    assert_eq!(result(&flipped[..len], MAGIC, 3), Some(Error::Corrupted));
+
+    // A boolean that is neither zero nor one is malformed, even with a
+    // matching checksum.
+    let mut reader = Reader::new(&[2], 0);
+    assert_eq!(bool::decode(&mut reader), Err(Error::Corrupted));
+  }
+}
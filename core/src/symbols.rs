@@ -0,0 +1,315 @@
+//! This module provides symbol maps, a compact table of the functions and
+//! data objects of a binary sorted by address, for naming the addresses of
+//! backtraces without the binary's ELF file.
+//!
+//! A map is generated from the symbol table of the ELF file with
+//! [`write_map`], into a buffer sized with [`map_size`]. It is a header of
+//! [`HEADER_SIZE`] bytes, followed by an entry of [`ENTRY_SIZE`] bytes for
+//! each symbol, sorted by address, and the names of the symbols, each ended
+//! by a zero:
+//!
+//! | Offset | Size | Field                                       |
+//! |--------|------|---------------------------------------------|
+//! | 0      | 4    | [`MAGIC`]                                   |
+//! | 4      | 2    | the [`VERSION`] of the format               |
+//! | 6      | 2    | reserved, zero                              |
+//! | 8      | 4    | the number of entries                       |
+//! | 12     | 4    | the length of the names                     |
+//!
+//! Each entry is the address of the symbol as a `u64`, its size as a `u32`,
+//! and the offset of its name from the start of the names as a `u32`, all
+//! little-endian. Symbols without a size are taken to extend up to the next
+//! symbol.
+
+use crate::bytes::{read_u16, read_u32, read_u64};
+use crate::elf::{SymbolTable, SHN_UNDEF, STT_FUNC, STT_OBJECT};
+use crate::error::{Error, Result};
+use crate::panic::{self, Symbol};
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// The bytes that a symbol map starts with.
+pub const MAGIC: [u8; 4] = *b"SYMS";
+
+/// The version of the format that is written.
+pub const VERSION: u16 = 1;
+
+/// The size of the header of a map.
+pub const HEADER_SIZE: usize = 16;
+
+/// The size of an entry of a map.
+pub const ENTRY_SIZE: usize = 16;
+
+/// A symbol map, read in place.
+#[derive(Clone, Copy, Debug)]
+pub struct SymbolMap<'a> {
+  entries: &'a [u8],
+  names: &'a [u8],
+}
+
+impl<'a> SymbolMap<'a> {
+  /// Reads the symbol map in `data`, failing with [`Error::InvalidArgument`]
+  /// if it is not one, [`Error::Unsupported`] if it is of a newer version,
+  /// or [`Error::Corrupted`] if its tables overrun the data.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the map
+  pub fn parse(data: &'a [u8]) -> Result<Self> {
+    let header = data.get(..HEADER_SIZE).ok_or(Error::InvalidArgument)?;
+    if header[..4] != MAGIC {
+      return Err(Error::InvalidArgument);
+    }
+    if read_u16(header, 4) > VERSION {
+      return Err(Error::Unsupported);
+    }
+    let count = read_u32(header, 8) as usize;
+    let names_len = read_u32(header, 12) as usize;
+    let entries_len = count.checked_mul(ENTRY_SIZE).ok_or(Error::Corrupted)?;
+    let rest = &data[HEADER_SIZE..];
+    let entries = rest.get(..entries_len).ok_or(Error::Corrupted)?;
+    let names = rest[entries_len..]
+      .get(..names_len)
+      .ok_or(Error::Corrupted)?;
+    Ok(Self { entries, names })
+  }
+
+  /// Returns the number of symbols.
+  pub fn len(&self) -> usize {
+    self.entries.len() / ENTRY_SIZE
+  }
+
+  /// Returns `true` if there are no symbols.
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  /// Returns the symbol that holds `address`, and the offset of `address`
+  /// into it, if any.
+  ///
+  /// # Arguments
+  ///
+  /// * `address` - the virtual address to symbolize
+  pub fn symbolize(&self, address: u64) -> Option<Symbol<'a>> {
+    let entries = self.entries.chunks_exact(ENTRY_SIZE);
+    // The entries are sorted, so the symbol is the last that starts at or
+    // below the address.
+    let index = partition_point(entries.len(), |index| {
+      read_u64(self.entries, index * ENTRY_SIZE) <= address
+    });
+    let entry = &self.entries[index.checked_sub(1)? * ENTRY_SIZE..];
+    let start = read_u64(entry, 0);
+    let size = read_u32(entry, 8) as u64;
+    if size != 0 && address - start >= size {
+      return None;
+    }
+    let name = self.names.get(read_u32(entry, 12) as usize..)?;
+    let end = name.iter().position(|&byte| byte == 0)?;
+    let name = core::str::from_utf8(&name[..end]).ok()?;
+    Some(Symbol {
+      name,
+      offset: address - start,
+    })
+  }
+}
+
+/// The map that [`install`] names the addresses of backtraces with.
+static INSTALLED: AtomicPtr<SymbolMap<'static>> =
+  AtomicPtr::new(core::ptr::null_mut());
+
+/// Names the addresses of the backtraces of panic reports with `map`.
+///
+/// # Arguments
+///
+/// * `map` - the symbol map of the running binary
+pub fn install(map: &'static SymbolMap<'static>) {
+  INSTALLED.store(map as *const _ as *mut _, Ordering::Release);
+  panic::set_symbolizer(symbolize_installed);
+}
+
+/// Returns the symbol that holds `address` in the installed map.
+fn symbolize_installed(address: u64) -> Option<Symbol<'static>> {
+  // SAFETY: only maps that live forever are installed.
+  let map = unsafe { INSTALLED.load(Ordering::Acquire).as_ref()? };
+  map.symbolize(address)
+}
+
+/// Returns the size of the map of the functions and data objects in `table`.
+///
+/// Fails with [`Error::Corrupted`] if the name of a symbol is not in the
+/// string table.
+///
+/// # Arguments
+///
+/// * `table` - the symbol table of an ELF file
+pub fn map_size(table: &SymbolTable<'_>) -> Result<usize> {
+  let (mut count, mut names_len) = (0, 0);
+  for symbol in table.iter().filter(is_mapped) {
+    count += 1;
+    names_len += table.name(&symbol)?.len() + 1;
+  }
+  Ok(HEADER_SIZE + count * ENTRY_SIZE + names_len)
+}
+
+/// Writes the map of the functions and data objects in `table` into
+/// `buffer`, returning its length.
+///
+/// Fails with [`Error::BufferTooSmall`] if it is shorter than [`map_size`],
+/// or [`Error::Corrupted`] if the name of a symbol is not in the string
+/// table. Symbols larger than 4 GiB are cut short.
+///
+/// # Arguments
+///
+/// * `table` - the symbol table of an ELF file
+/// * `slide` - the distance the binary was moved from the addresses it was
+///   linked at, which is added to the address of every symbol
+/// * `buffer` - the buffer to write the map into
+pub fn write_map(
+  table: &SymbolTable<'_>,
+  slide: u64,
+  buffer: &mut [u8],
+) -> Result<usize> {
+  let size = map_size(table)?;
+  let buffer = buffer.get_mut(..size).ok_or(Error::BufferTooSmall)?;
+  let count = table.iter().filter(is_mapped).count();
+  let (header, rest) = buffer.split_at_mut(HEADER_SIZE);
+  let (entries, names) = rest.split_at_mut(count * ENTRY_SIZE);
+
+  let mut names_len = 0;
+  let symbols = table.iter().filter(is_mapped);
+  for (entry, symbol) in entries.chunks_exact_mut(ENTRY_SIZE).zip(symbols) {
+    let name = table.name(&symbol)?.as_bytes();
+    names[names_len..names_len + name.len()].copy_from_slice(name);
+    names[names_len + name.len()] = 0;
+    let address = symbol.value.wrapping_add(slide);
+    let size = symbol.size.min(u32::MAX as u64) as u32;
+    entry[..8].copy_from_slice(&address.to_le_bytes());
+    entry[8..12].copy_from_slice(&size.to_le_bytes());
+    entry[12..].copy_from_slice(&(names_len as u32).to_le_bytes());
+    names_len += name.len() + 1;
+  }
+  // SAFETY: the entries are a whole number of arrays of bytes, which have no
+  // alignment.
+  let entries = unsafe {
+    core::slice::from_raw_parts_mut(
+      entries.as_mut_ptr().cast::<[u8; ENTRY_SIZE]>(),
+      count,
+    )
+  };
+  entries.sort_unstable_by_key(|entry| read_u64(entry, 0));
+
+  header[..4].copy_from_slice(&MAGIC);
+  header[4..6].copy_from_slice(&VERSION.to_le_bytes());
+  header[6..8].fill(0);
+  header[8..12].copy_from_slice(&(count as u32).to_le_bytes());
+  header[12..].copy_from_slice(&(names_len as u32).to_le_bytes());
+  Ok(size)
+}
+
+/// Returns `true` if `symbol` is a defined function or data object, which
+/// belongs in a map.
+///
+/// # Arguments
+///
+/// * `symbol` - a symbol of an ELF symbol table
+fn is_mapped(symbol: &crate::elf::Symbol) -> bool {
+  matches!(symbol.ty(), STT_FUNC | STT_OBJECT) && symbol.section != SHN_UNDEF
+}
+
+/// Returns the number of indices below `len` for which `predicate` holds,
+/// which must hold for every index below the first for which it does not.
+fn partition_point(len: usize, predicate: impl Fn(usize) -> bool) -> usize {
+  let (mut low, mut high) = (0, len);
+  while low < high {
+    let middle = low + (high - low) / 2;
+    if predicate(middle) {
+      low = middle + 1;
+    } else {
+      high = middle;
+    }
+  }
+  low
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use crate::elf::Class;
+  use std::vec::Vec;
+
+  /// Returns an ELF64 symbol table entry.
+  fn entry(
+    name: u32,
+    info: u8,
+    section: u16,
+    value: u64,
+    size: u64,
+  ) -> Vec<u8> {
+    let mut entry = Vec::new();
+    entry.extend_from_slice(&name.to_le_bytes());
+    entry.extend_from_slice(&[info, 0]);
+    entry.extend_from_slice(&section.to_le_bytes());
+    entry.extend_from_slice(&value.to_le_bytes());
+    entry.extend_from_slice(&size.to_le_bytes());
+    entry
+  }
+
+  fn map() -> Vec<u8> {
+    let strings = b"\0kmain\0data\0undefined\0panic\0";
+    let symbols = [
+      entry(0, 0, 0, 0, 0),
+      entry(1, STT_FUNC, 1, 0x2000, 0x40),
+      entry(7, STT_OBJECT, 2, 0x1000, 0x10),
+      entry(12, STT_FUNC, SHN_UNDEF, 0, 0),
+      entry(22, 0x10 | STT_FUNC, 1, 0x3000, 0),
+    ]
+    .concat();
+    let table = SymbolTable::new(Class::Elf64, &symbols, strings).unwrap();
+    let size = map_size(&table).unwrap();
+    assert_eq!(size, HEADER_SIZE + 3 * ENTRY_SIZE + 17);
+    let mut buffer = std::vec![0xaa; size + 8];
+    assert_eq!(
+      write_map(&table, 0x100, &mut buffer[..size - 1]),
+      Err(Error::BufferTooSmall)
+    );
+    assert_eq!(write_map(&table, 0x100, &mut buffer), Ok(size));
+    buffer.truncate(size);
+    buffer
+  }
+
+  #[test]
+  fn maps_are_sorted_by_address() {
+    let data = map();
+    let map = SymbolMap::parse(&data).unwrap();
+    assert_eq!(map.len(), 3);
+    let symbol = |name, offset| Some(Symbol { name, offset });
+    assert_eq!(map.symbolize(0x1108), symbol("data", 8));
+    assert_eq!(map.symbolize(0x2100), symbol("kmain", 0));
+    assert_eq!(map.symbolize(0x213f), symbol("kmain", 0x3f));
+    assert_eq!(map.symbolize(0x2140), None);
+    assert_eq!(map.symbolize(0x10ff), None);
+    // Symbols without a size extend up to the next one.
+    assert_eq!(map.symbolize(0xffff_0000), symbol("panic", 0xfffe_cf00));
+  }
+
+  #[test]
+  fn malformed_maps_are_rejected() {
+    let data = map();
+    assert!(SymbolMap::parse(&data[..data.len() - 1]).is_err());
+    assert_eq!(
+      SymbolMap::parse(&data[..HEADER_SIZE + 8]).err(),
+      Some(Error::Corrupted)
+    );
+    let mut newer = data.clone();
+    newer[4] = 2;
+    assert_eq!(SymbolMap::parse(&newer).err(), Some(Error::Unsupported));
+    assert_eq!(
+      SymbolMap::parse(b"ELF\0").err(),
+      Some(Error::InvalidArgument)
+    );
+    let empty = SymbolMap::parse(b"SYMS\x01\0\0\0\0\0\0\0\0\0\0\0").unwrap();
+    assert!(empty.is_empty());
+    assert_eq!(empty.symbolize(0x1000), None);
+  }
+}
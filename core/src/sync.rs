@@ -0,0 +1,26 @@
+//! This module provides the synchronization primitives used to share kernel
+//! structures between processors.
+//!
+//! Every lock here spins rather than sleeps, since they are needed before,
+//! and underneath, anything that could put a processor to sleep. Tasks that
+//! must wait for longer, such as for a device, wait on the queues and events
+//! of [`WaitQueue`] and [`Event`] instead, which interrupt handlers signal.
+//! Globals that need no lock, since they are set once or only touched during
+//! early boot, live in an [`InitCell`] or a [`RacyCell`].
+
+mod cell;
+mod irq_mutex;
+mod rwlock;
+mod seqlock;
+mod spsc;
+mod wait;
+
+pub use cell::{InitCell, RacyCell};
+pub use irq_mutex::{IrqMutex, IrqMutexGuard};
+pub use rwlock::{
+  Preference, SpinRwLock, SpinRwLockReadGuard, SpinRwLockUpgradeableGuard,
+  SpinRwLockWriteGuard,
+};
+pub use seqlock::SeqLock;
+pub use spsc::{Consumer, Producer, RingBuffer};
+pub use wait::{Event, WaitQueue};
@@ -0,0 +1,304 @@
+//! This module provides the cells that hold globals in place of `static mut`:
+//! [`RacyCell`], for state that is only touched before other processors or
+//! interrupts could race on it, and [`InitCell`], for values that are set
+//! once, such as the early console or the heap, and read thereafter.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// A cell that may be shared between threads without any synchronization.
+///
+/// Every access is `unsafe`: the caller promises that no other access races
+/// with it, which holds while a single processor runs with interrupts
+/// disabled, as during early boot. Once anything could race on the value, it
+/// belongs in a lock instead.
+#[repr(transparent)]
+pub struct RacyCell<T>(UnsafeCell<T>);
+
+// SAFETY: every access is unsafe, and its caller promises that it does not
+// race with any other.
+unsafe impl<T: Send> Sync for RacyCell<T> {}
+
+impl<T> RacyCell<T> {
+  /// Constructs a cell holding `value`.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to hold
+  pub const fn new(value: T) -> Self {
+    Self(UnsafeCell::new(value))
+  }
+
+  /// Returns a pointer to the value, which is safe to take, but not to use
+  /// while anything else may access the value.
+  pub const fn get(&self) -> *mut T {
+    self.0.get()
+  }
+
+  /// Returns the value, to read.
+  ///
+  /// # Safety
+  ///
+  /// Nothing may write the value while the reference lives, such as another
+  /// processor or an interrupt handler.
+  pub unsafe fn get_ref(&self) -> &T {
+    &*self.0.get()
+  }
+
+  /// Returns the value, to write.
+  ///
+  /// # Safety
+  ///
+  /// Nothing else may access the value while the reference lives, such as
+  /// another processor or an interrupt handler.
+  #[allow(clippy::mut_from_ref)]
+  pub unsafe fn get_mut(&self) -> &mut T {
+    &mut *self.0.get()
+  }
+
+  /// Consumes the cell, returning its value.
+  pub fn into_inner(self) -> T {
+    self.0.into_inner()
+  }
+}
+
+/// The state of an [`InitCell`] that has not been set.
+const UNINIT: u8 = 0;
+
+/// The state of an [`InitCell`] that is being set.
+const INITIALIZING: u8 = 1;
+
+/// The state of an [`InitCell`] that has been set.
+const INIT: u8 = 2;
+
+/// A cell that is set at most once, and may then be read from any thread.
+///
+/// Setting the cell publishes the value with release ordering, and reading it
+/// checks for it with acquire ordering, so a reader either sees no value or
+/// all of it. A thread that sets the cell while another is setting it fails,
+/// while one that initializes it with [`get_or_init`](Self::get_or_init)
+/// spins until the other is done.
+pub struct InitCell<T> {
+  state: AtomicU8,
+  value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// SAFETY: the value is written once, before it is published, and only read
+// afterwards; it may be set on one thread and dropped on another.
+unsafe impl<T: Send + Sync> Sync for InitCell<T> {}
+unsafe impl<T: Send> Send for InitCell<T> {}
+
+impl<T> InitCell<T> {
+  /// Constructs a cell that has not been set.
+  pub const fn new() -> Self {
+    Self {
+      state: AtomicU8::new(UNINIT),
+      value: UnsafeCell::new(MaybeUninit::uninit()),
+    }
+  }
+
+  /// Sets the cell to `value`, returning it back if the cell has been set, or
+  /// is being set, already.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to set
+  pub fn set(&self, value: T) -> Result<(), T> {
+    if !self.begin() {
+      return Err(value);
+    }
+    self.publish(value);
+    Ok(())
+  }
+
+  /// Returns the value, or `None` if the cell has not been set.
+  pub fn get(&self) -> Option<&T> {
+    if self.is_initialized() {
+      // SAFETY: the value was published before the state was.
+      Some(unsafe { (*self.value.get()).assume_init_ref() })
+    } else {
+      None
+    }
+  }
+
+  /// Returns the value, setting the cell to the result of `init` first if it
+  /// has not been set.
+  ///
+  /// If another thread is setting the cell, this spins until it is done,
+  /// and `init` is not called. If `init` panics, the cell is left unset, for
+  /// the threads waiting on it, or any later one, to set.
+  ///
+  /// # Arguments
+  ///
+  /// * `init` - the function that computes the value
+  pub fn get_or_init(&self, init: impl FnOnce() -> T) -> &T {
+    if self.begin() {
+      let reset = ResetOnUnwind(&self.state);
+      let value = init();
+      core::mem::forget(reset);
+      self.publish(value);
+    }
+    loop {
+      if let Some(value) = self.get() {
+        return value;
+      }
+      core::hint::spin_loop();
+    }
+  }
+
+  /// Returns `true` if the cell has been set.
+  pub fn is_initialized(&self) -> bool {
+    self.state.load(Ordering::Acquire) == INIT
+  }
+
+  /// Returns the value, without checking that the cell has been set.
+  ///
+  /// # Safety
+  ///
+  /// The cell must have been set, and the setting seen by this thread, such
+  /// as by it having set the cell itself.
+  pub unsafe fn get_unchecked(&self) -> &T {
+    (*self.value.get()).assume_init_ref()
+  }
+
+  /// Returns the value, or `None` if the cell has not been set, through the
+  /// exclusive reference that makes the check free of races.
+  pub fn get_mut(&mut self) -> Option<&mut T> {
+    if *self.state.get_mut() == INIT {
+      // SAFETY: the value was written when the state was set.
+      Some(unsafe { self.value.get_mut().assume_init_mut() })
+    } else {
+      None
+    }
+  }
+
+  /// Consumes the cell, returning its value if it was set.
+  pub fn into_inner(mut self) -> Option<T> {
+    if *self.state.get_mut() != INIT {
+      return None;
+    }
+    *self.state.get_mut() = UNINIT;
+    // SAFETY: the value was set, and the state no longer says so, so it is
+    // not dropped again.
+    Some(unsafe { self.value.get_mut().assume_init_read() })
+  }
+
+  /// Claims the right to set the cell, returning `false` if it has been set,
+  /// or is being set, already.
+  fn begin(&self) -> bool {
+    self
+      .state
+      .compare_exchange(
+        UNINIT,
+        INITIALIZING,
+        Ordering::Acquire,
+        Ordering::Acquire,
+      )
+      .is_ok()
+  }
+
+  /// Writes `value`, and publishes it to the threads that read the cell.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to publish, after [`begin`](Self::begin) succeeded
+  fn publish(&self, value: T) {
+    // SAFETY: the state is `INITIALIZING`, so nothing else reads or writes
+    // the value.
+    unsafe { (*self.value.get()).write(value) };
+    self.state.store(INIT, Ordering::Release);
+  }
+}
+
+/// A guard that returns the state of an [`InitCell`] to [`UNINIT`] if it is
+/// dropped while the value is being computed, which only a panic does.
+struct ResetOnUnwind<'a>(&'a AtomicU8);
+
+impl Drop for ResetOnUnwind<'_> {
+  fn drop(&mut self) {
+    self.0.store(UNINIT, Ordering::Release);
+  }
+}
+
+impl<T> Default for InitCell<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T> Drop for InitCell<T> {
+  fn drop(&mut self) {
+    if let Some(value) = self.get_mut() {
+      // SAFETY: the value was set, and the cell is not used again.
+      unsafe { core::ptr::drop_in_place(value) };
+    }
+  }
+}
+
+impl<T: fmt::Debug> fmt::Debug for InitCell<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.get() {
+      Some(value) => f.debug_tuple("InitCell").field(value).finish(),
+      None => f.write_str("InitCell(<uninit>)"),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::rc::Rc;
+
+  #[test]
+  fn init_cell_is_set_once() {
+    let cell = InitCell::new();
+
+    assert_eq!(cell.get(), None);
+    assert_eq!(cell.set(1), Ok(()));
+    assert_eq!(cell.set(2), Err(2));
+    assert_eq!(cell.get_or_init(|| 3), &1);
+    assert_eq!(cell.into_inner(), Some(1));
+    assert_eq!(*InitCell::new().get_or_init(|| 4), 4);
+  }
+
+  #[test]
+  fn init_cell_is_left_unset_when_init_panics() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    let cell = InitCell::new();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+      cell.get_or_init(|| panic!("init failed"));
+    }));
+
+    assert!(result.is_err());
+    assert_eq!(cell.get(), None);
+    assert_eq!(cell.get_or_init(|| 5), &5);
+  }
+
+  #[test]
+  fn init_cell_drops_its_value() {
+    let value = Rc::new(());
+    let cell = InitCell::new();
+    cell.set(value.clone()).unwrap();
+
+    assert_eq!(Rc::strong_count(&value), 2);
+    drop(cell);
+    assert_eq!(Rc::strong_count(&value), 1);
+    drop(InitCell::<Rc<()>>::new());
+  }
+
+  #[test]
+  fn racy_cell_holds_its_value() {
+    static COUNT: RacyCell<u32> = RacyCell::new(0);
+
+    // SAFETY: nothing else touches the cell.
+    unsafe {
+      *COUNT.get_mut() += 1;
+      assert_eq!(*COUNT.get_ref(), 1);
+    }
+  }
+}
@@ -0,0 +1,198 @@
+//! This module provides [`IrqMutex`], a spinning mutex that keeps interrupts
+//! disabled on the processor holding it.
+//!
+//! An interrupt handler that takes a lock which the code it interrupted
+//! already holds spins forever, since the holder cannot run again until the
+//! handler returns. Any lock that an interrupt handler takes must therefore be
+//! an [`IrqMutex`], so that no interrupt arrives while it is held.
+
+use arch::critical_section::CriticalSection;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A mutex that spins while it waits, and disables interrupts on the running
+/// processor for as long as it is held.
+pub struct IrqMutex<T: ?Sized> {
+  locked: AtomicBool,
+  value: UnsafeCell<T>,
+}
+
+// SAFETY: the mutex hands out `&mut T` to one thread at a time.
+unsafe impl<T: ?Sized + Send> Send for IrqMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for IrqMutex<T> {}
+
+impl<T> IrqMutex<T> {
+  /// Creates an unlocked mutex holding `value`.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to protect
+  pub const fn new(value: T) -> Self {
+    Self {
+      locked: AtomicBool::new(false),
+      value: UnsafeCell::new(value),
+    }
+  }
+
+  /// Consumes the mutex, returning the value it protects.
+  pub fn into_inner(self) -> T {
+    self.value.into_inner()
+  }
+}
+
+impl<T: ?Sized> IrqMutex<T> {
+  /// Disables interrupts, then acquires the mutex, spinning until it is
+  /// released.
+  ///
+  /// Interrupts stay disabled while spinning, so that the mutex cannot be
+  /// taken by a handler on this processor in the meantime.
+  pub fn lock(&self) -> IrqMutexGuard<'_, T> {
+    let section = CriticalSection::enter();
+    while !self.acquire() {
+      core::hint::spin_loop();
+    }
+    IrqMutexGuard {
+      mutex: self,
+      _section: section,
+    }
+  }
+
+  /// Disables interrupts and acquires the mutex if it is not held, restoring
+  /// interrupts otherwise.
+  pub fn try_lock(&self) -> Option<IrqMutexGuard<'_, T>> {
+    let section = CriticalSection::enter();
+    if !self.acquire() {
+      return None;
+    }
+    Some(IrqMutexGuard {
+      mutex: self,
+      _section: section,
+    })
+  }
+
+  /// Returns whether the mutex is held, which is only a hint, since it may be
+  /// acquired or released at any time.
+  pub fn is_locked(&self) -> bool {
+    self.locked.load(Ordering::Relaxed)
+  }
+
+  /// Returns a mutable reference to the protected value, which needs no
+  /// locking since the mutex is borrowed mutably.
+  pub fn get_mut(&mut self) -> &mut T {
+    self.value.get_mut()
+  }
+
+  /// Attempts once to acquire the mutex, returning whether it was acquired.
+  fn acquire(&self) -> bool {
+    self
+      .locked
+      .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+      .is_ok()
+  }
+}
+
+impl<T: Default> Default for IrqMutex<T> {
+  fn default() -> Self {
+    Self::new(T::default())
+  }
+}
+
+impl<T: ?Sized> fmt::Debug for IrqMutex<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // Reading the value would mean disabling interrupts to format it.
+    f.debug_struct("IrqMutex")
+      .field("locked", &self.is_locked())
+      .finish_non_exhaustive()
+  }
+}
+
+/// A guard that holds an [`IrqMutex`], and releases it when dropped, before
+/// restoring interrupts.
+pub struct IrqMutexGuard<'a, T: ?Sized> {
+  mutex: &'a IrqMutex<T>,
+  // Dropped after the mutex is released, by `Drop`.
+  _section: CriticalSection,
+}
+
+// SAFETY: the guard only hands out `&T` to other threads.
+unsafe impl<T: ?Sized + Sync> Sync for IrqMutexGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for IrqMutexGuard<'_, T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    // SAFETY: this guard holds the mutex.
+    unsafe { &*self.mutex.value.get() }
+  }
+}
+
+impl<T: ?Sized> DerefMut for IrqMutexGuard<'_, T> {
+  fn deref_mut(&mut self) -> &mut T {
+    // SAFETY: this guard holds the mutex.
+    unsafe { &mut *self.mutex.value.get() }
+  }
+}
+
+impl<T: ?Sized> Drop for IrqMutexGuard<'_, T> {
+  fn drop(&mut self) {
+    self.mutex.locked.store(false, Ordering::Release);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::sync::Arc;
+  use std::vec::Vec;
+
+  #[test]
+  fn mutexes_are_held_until_guards_drop() {
+    let mutex = IrqMutex::new(1);
+    {
+      let mut guard = mutex.lock();
+      *guard += 1;
+      assert!(mutex.is_locked());
+      assert!(mutex.try_lock().is_none());
+    }
+
+    assert!(!mutex.is_locked());
+    assert_eq!(*mutex.try_lock().unwrap(), 2);
+  }
+
+  #[test]
+  fn interrupts_stay_disabled_until_the_last_guard_drops() {
+    let outer = IrqMutex::new(());
+    let inner = IrqMutex::new(());
+    let outer_guard = outer.lock();
+    let inner_guard = inner.lock();
+    drop(inner_guard);
+
+    assert!(!CriticalSection::enter().interrupts_were_enabled());
+    drop(outer_guard);
+    assert!(CriticalSection::enter().interrupts_were_enabled());
+  }
+
+  #[test]
+  fn mutexes_exclude_other_threads() {
+    let mutex = Arc::new(IrqMutex::new(0));
+    let threads: Vec<_> = (0..4)
+      .map(|_| {
+        let mutex = mutex.clone();
+        std::thread::spawn(move || {
+          for _ in 0..1000 {
+            *mutex.lock() += 1;
+          }
+        })
+      })
+      .collect();
+    for thread in threads {
+      thread.join().unwrap();
+    }
+
+    assert_eq!(*mutex.lock(), 4000);
+  }
+}
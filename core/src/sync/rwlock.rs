@@ -0,0 +1,488 @@
+//! This module provides [`SpinRwLock`], a reader-writer lock that spins while
+//! it waits, for read-mostly structures such as the module list and the maps
+//! of memory regions.
+//!
+//! The whole state of the lock is kept in a single word: a bit for a writer, a
+//! bit for an upgradeable reader, a bit for a writer waiting to acquire the
+//! lock, and the count of readers in the remaining bits.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Set while the lock is held by a writer.
+const WRITER: usize = 1 << 0;
+
+/// Set while the lock is held by an upgradeable reader.
+const UPGRADEABLE: usize = 1 << 1;
+
+/// Set while a writer is waiting for the lock, so that readers stop acquiring
+/// it under [`Preference::Writers`].
+const WRITER_WAITING: usize = 1 << 2;
+
+/// The amount that the state is incremented by for each reader.
+const READER: usize = 1 << 3;
+
+/// Which of the waiting readers and writers a [`SpinRwLock`] favors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preference {
+  /// Readers acquire the lock whenever it is not held by a writer, which
+  /// gives the most concurrency, but lets a steady stream of readers starve
+  /// writers.
+  Readers,
+
+  /// Readers stop acquiring the lock as soon as a writer is waiting for it,
+  /// so writers are never starved, at the cost of stalling readers behind
+  /// them.
+  Writers,
+}
+
+/// A reader-writer lock that spins while it waits.
+///
+/// Any number of readers, or a single writer, may hold the lock at once. One
+/// of the readers may instead hold an upgradeable guard, which can be turned
+/// into a write guard without letting another writer in between.
+pub struct SpinRwLock<T: ?Sized> {
+  state: AtomicUsize,
+  preference: Preference,
+  value: UnsafeCell<T>,
+}
+
+// SAFETY: the lock hands out `&mut T` to one thread at a time, so it may be
+// shared whenever `T` may be sent, and `&T` to many threads, whenever `T` may
+// also be shared.
+unsafe impl<T: ?Sized + Send> Send for SpinRwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for SpinRwLock<T> {}
+
+impl<T> SpinRwLock<T> {
+  /// Creates an unlocked lock holding `value`, which prefers readers.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to protect
+  pub const fn new(value: T) -> Self {
+    Self::with_preference(value, Preference::Readers)
+  }
+
+  /// Creates an unlocked lock holding `value`, which prefers `preference`.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to protect
+  /// * `preference` - which of waiting readers and writers to favor
+  pub const fn with_preference(value: T, preference: Preference) -> Self {
+    Self {
+      state: AtomicUsize::new(0),
+      preference,
+      value: UnsafeCell::new(value),
+    }
+  }
+
+  /// Consumes the lock, returning the value it protects.
+  pub fn into_inner(self) -> T {
+    self.value.into_inner()
+  }
+}
+
+impl<T: ?Sized> SpinRwLock<T> {
+  /// Returns which of waiting readers and writers this lock favors.
+  pub fn preference(&self) -> Preference {
+    self.preference
+  }
+
+  /// Acquires the lock for reading, spinning until no writer holds it.
+  pub fn read(&self) -> SpinRwLockReadGuard<'_, T> {
+    loop {
+      if let Some(guard) = self.try_read() {
+        return guard;
+      }
+      core::hint::spin_loop();
+    }
+  }
+
+  /// Acquires the lock for reading if no writer holds it, or is waiting for
+  /// it under [`Preference::Writers`].
+  pub fn try_read(&self) -> Option<SpinRwLockReadGuard<'_, T>> {
+    let mut state = self.state.load(Ordering::Relaxed);
+    while !self.blocks_readers(state) {
+      match self.state.compare_exchange_weak(
+        state,
+        state.checked_add(READER).expect("too many readers"),
+        Ordering::Acquire,
+        Ordering::Relaxed,
+      ) {
+        Ok(_) => return Some(SpinRwLockReadGuard::new(self)),
+        Err(current) => state = current,
+      }
+    }
+    None
+  }
+
+  /// Acquires the lock for writing, spinning until no one else holds it.
+  pub fn write(&self) -> SpinRwLockWriteGuard<'_, T> {
+    loop {
+      if let Some(guard) = self.try_write() {
+        return guard;
+      }
+      self.announce_writer();
+      core::hint::spin_loop();
+    }
+  }
+
+  /// Acquires the lock for writing if no one else holds it.
+  pub fn try_write(&self) -> Option<SpinRwLockWriteGuard<'_, T>> {
+    let mut state = self.state.load(Ordering::Relaxed);
+    while state & !WRITER_WAITING == 0 {
+      match self.state.compare_exchange_weak(
+        state,
+        WRITER,
+        Ordering::Acquire,
+        Ordering::Relaxed,
+      ) {
+        Ok(_) => return Some(SpinRwLockWriteGuard::new(self)),
+        Err(current) => state = current,
+      }
+    }
+    None
+  }
+
+  /// Acquires the lock for reading with the option of upgrading to writing,
+  /// spinning until no writer or other upgradeable reader holds it.
+  pub fn upgradeable_read(&self) -> SpinRwLockUpgradeableGuard<'_, T> {
+    loop {
+      if let Some(guard) = self.try_upgradeable_read() {
+        return guard;
+      }
+      core::hint::spin_loop();
+    }
+  }
+
+  /// Acquires the lock for reading with the option of upgrading to writing, if
+  /// no writer or other upgradeable reader holds it.
+  pub fn try_upgradeable_read(
+    &self,
+  ) -> Option<SpinRwLockUpgradeableGuard<'_, T>> {
+    let mut state = self.state.load(Ordering::Relaxed);
+    while state & UPGRADEABLE == 0 && !self.blocks_readers(state) {
+      match self.state.compare_exchange_weak(
+        state,
+        state | UPGRADEABLE,
+        Ordering::Acquire,
+        Ordering::Relaxed,
+      ) {
+        Ok(_) => return Some(SpinRwLockUpgradeableGuard::new(self)),
+        Err(current) => state = current,
+      }
+    }
+    None
+  }
+
+  /// Returns a mutable reference to the protected value, which needs no
+  /// locking since the lock is borrowed mutably.
+  pub fn get_mut(&mut self) -> &mut T {
+    self.value.get_mut()
+  }
+
+  /// Returns whether `state` keeps new readers from acquiring the lock.
+  ///
+  /// # Arguments
+  ///
+  /// * `state` - the state of the lock
+  fn blocks_readers(&self, state: usize) -> bool {
+    state & WRITER != 0
+      || (self.preference == Preference::Writers && state & WRITER_WAITING != 0)
+  }
+
+  /// Records that a writer is waiting for the lock, if writers are preferred.
+  fn announce_writer(&self) {
+    if self.preference == Preference::Writers {
+      self.state.fetch_or(WRITER_WAITING, Ordering::Relaxed);
+    }
+  }
+}
+
+impl<T: Default> Default for SpinRwLock<T> {
+  fn default() -> Self {
+    Self::new(T::default())
+  }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SpinRwLock<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut debug = f.debug_struct("SpinRwLock");
+    match self.try_read() {
+      Some(guard) => debug.field("value", &&*guard),
+      None => debug.field("value", &format_args!("<locked>")),
+    };
+    debug.field("preference", &self.preference).finish()
+  }
+}
+
+/// A guard that holds a [`SpinRwLock`] for reading, and releases it when
+/// dropped.
+pub struct SpinRwLockReadGuard<'a, T: ?Sized> {
+  lock: &'a SpinRwLock<T>,
+  // Guards must be released on the processor that acquired them.
+  _not_send: PhantomData<*const ()>,
+}
+
+impl<'a, T: ?Sized> SpinRwLockReadGuard<'a, T> {
+  fn new(lock: &'a SpinRwLock<T>) -> Self {
+    Self {
+      lock,
+      _not_send: PhantomData,
+    }
+  }
+}
+
+// SAFETY: the guard only hands out `&T`.
+unsafe impl<T: ?Sized + Sync> Sync for SpinRwLockReadGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for SpinRwLockReadGuard<'_, T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    // SAFETY: no writer holds the lock while this guard exists.
+    unsafe { &*self.lock.value.get() }
+  }
+}
+
+impl<T: ?Sized> Drop for SpinRwLockReadGuard<'_, T> {
+  fn drop(&mut self) {
+    self.lock.state.fetch_sub(READER, Ordering::Release);
+  }
+}
+
+/// A guard that holds a [`SpinRwLock`] for writing, and releases it when
+/// dropped.
+pub struct SpinRwLockWriteGuard<'a, T: ?Sized> {
+  lock: &'a SpinRwLock<T>,
+  _not_send: PhantomData<*const ()>,
+}
+
+impl<'a, T: ?Sized> SpinRwLockWriteGuard<'a, T> {
+  fn new(lock: &'a SpinRwLock<T>) -> Self {
+    Self {
+      lock,
+      _not_send: PhantomData,
+    }
+  }
+
+  /// Turns this guard into a read guard, without letting a writer in between.
+  pub fn downgrade(self) -> SpinRwLockReadGuard<'a, T> {
+    let lock = self.lock;
+    core::mem::forget(self);
+    // Adding the reader before clearing the writer keeps the lock held
+    // throughout.
+    lock.state.fetch_add(READER, Ordering::Acquire);
+    lock.state.fetch_and(!WRITER, Ordering::Release);
+    SpinRwLockReadGuard::new(lock)
+  }
+}
+
+// SAFETY: the guard only hands out `&T` to other threads.
+unsafe impl<T: ?Sized + Sync> Sync for SpinRwLockWriteGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for SpinRwLockWriteGuard<'_, T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    // SAFETY: this guard holds the lock exclusively.
+    unsafe { &*self.lock.value.get() }
+  }
+}
+
+impl<T: ?Sized> DerefMut for SpinRwLockWriteGuard<'_, T> {
+  fn deref_mut(&mut self) -> &mut T {
+    // SAFETY: this guard holds the lock exclusively.
+    unsafe { &mut *self.lock.value.get() }
+  }
+}
+
+impl<T: ?Sized> Drop for SpinRwLockWriteGuard<'_, T> {
+  fn drop(&mut self) {
+    // Clearing the writer leaves any waiting writer to announce itself again.
+    self.lock.state.fetch_and(!WRITER, Ordering::Release);
+  }
+}
+
+/// A guard that holds a [`SpinRwLock`] for reading, alongside other readers,
+/// but which can be upgraded to hold it for writing. Only one such guard can
+/// exist at a time, which is what lets it upgrade without any other writer
+/// getting in between.
+pub struct SpinRwLockUpgradeableGuard<'a, T: ?Sized> {
+  lock: &'a SpinRwLock<T>,
+  _not_send: PhantomData<*const ()>,
+}
+
+impl<'a, T: ?Sized> SpinRwLockUpgradeableGuard<'a, T> {
+  fn new(lock: &'a SpinRwLock<T>) -> Self {
+    Self {
+      lock,
+      _not_send: PhantomData,
+    }
+  }
+
+  /// Upgrades this guard to hold the lock for writing, spinning until the
+  /// other readers have released it.
+  pub fn upgrade(self) -> SpinRwLockWriteGuard<'a, T> {
+    let mut guard = self;
+    loop {
+      match guard.try_upgrade() {
+        Ok(guard) => return guard,
+        Err(unchanged) => guard = unchanged,
+      }
+      guard.lock.announce_writer();
+      core::hint::spin_loop();
+    }
+  }
+
+  /// Upgrades this guard to hold the lock for writing if there are no other
+  /// readers, or returns it unchanged.
+  pub fn try_upgrade(self) -> Result<SpinRwLockWriteGuard<'a, T>, Self> {
+    let mut state = self.lock.state.load(Ordering::Relaxed);
+    while state & !WRITER_WAITING == UPGRADEABLE {
+      match self.lock.state.compare_exchange_weak(
+        state,
+        WRITER,
+        Ordering::Acquire,
+        Ordering::Relaxed,
+      ) {
+        Ok(_) => {
+          let lock = self.lock;
+          core::mem::forget(self);
+          return Ok(SpinRwLockWriteGuard::new(lock));
+        }
+        Err(current) => state = current,
+      }
+    }
+    Err(self)
+  }
+
+  /// Turns this guard into a plain read guard, letting another upgradeable
+  /// reader or a writer acquire the lock.
+  pub fn downgrade(self) -> SpinRwLockReadGuard<'a, T> {
+    let lock = self.lock;
+    core::mem::forget(self);
+    lock.state.fetch_add(READER, Ordering::Acquire);
+    lock.state.fetch_and(!UPGRADEABLE, Ordering::Release);
+    SpinRwLockReadGuard::new(lock)
+  }
+}
+
+// SAFETY: the guard only hands out `&T`.
+unsafe impl<T: ?Sized + Sync> Sync for SpinRwLockUpgradeableGuard<'_, T> {}
+
+impl<T: ?Sized> Deref for SpinRwLockUpgradeableGuard<'_, T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    // SAFETY: no writer holds the lock while this guard exists.
+    unsafe { &*self.lock.value.get() }
+  }
+}
+
+impl<T: ?Sized> Drop for SpinRwLockUpgradeableGuard<'_, T> {
+  fn drop(&mut self) {
+    self.lock.state.fetch_and(!UPGRADEABLE, Ordering::Release);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Preference, SpinRwLock};
+
+  #[test]
+  fn readers_share_the_lock() {
+    let lock = SpinRwLock::new(5);
+    let first = lock.read();
+    let second = lock.read();
+    assert_eq!(*first + *second, 10);
+    assert!(lock.try_write().is_none());
+  }
+
+  #[test]
+  fn writer_excludes_readers() {
+    let lock = SpinRwLock::new(0);
+    let mut writer = lock.write();
+    *writer = 1;
+    assert!(lock.try_read().is_none());
+    assert!(lock.try_upgradeable_read().is_none());
+    drop(writer);
+    assert_eq!(*lock.read(), 1);
+  }
+
+  #[test]
+  fn upgradeable_read_coexists_with_readers() {
+    let lock = SpinRwLock::new(0);
+    let upgradeable = lock.upgradeable_read();
+    let reader = lock.read();
+    assert!(lock.try_upgradeable_read().is_none());
+    assert!(lock.try_write().is_none());
+
+    let upgradeable = upgradeable.try_upgrade().err().unwrap();
+    drop(reader);
+    let mut writer = upgradeable.upgrade();
+    *writer = 2;
+    assert!(lock.try_read().is_none());
+    drop(writer);
+    assert_eq!(*lock.read(), 2);
+  }
+
+  #[test]
+  fn downgrade_keeps_the_lock_held() {
+    let lock = SpinRwLock::new(0);
+    let reader = lock.write().downgrade();
+    assert!(lock.try_write().is_none());
+    assert!(lock.try_read().is_some());
+    drop(reader);
+
+    let reader = lock.upgradeable_read().downgrade();
+    assert!(lock.try_write().is_none());
+    assert!(lock.try_upgradeable_read().is_some());
+    drop(reader);
+    assert!(lock.try_write().is_some());
+  }
+
+  #[test]
+  fn waiting_writer_blocks_readers_when_preferred() {
+    let lock = SpinRwLock::with_preference(0, Preference::Writers);
+    let reader = lock.read();
+    lock.announce_writer();
+    assert!(lock.try_read().is_none());
+    drop(reader);
+    assert!(lock.try_write().is_some());
+    assert!(lock.try_read().is_some());
+
+    let lock = SpinRwLock::new(0);
+    let _reader = lock.read();
+    lock.announce_writer();
+    assert!(lock.try_read().is_some());
+  }
+
+  #[test]
+  fn concurrent_writers_do_not_lose_updates() {
+    extern crate std;
+    use std::thread;
+
+    for preference in [Preference::Readers, Preference::Writers] {
+      let lock = SpinRwLock::with_preference(0usize, preference);
+      thread::scope(|scope| {
+        for _ in 0..4 {
+          scope.spawn(|| {
+            for i in 0..1000 {
+              if i % 2 == 0 {
+                *lock.write() += 1;
+              } else {
+                *lock.upgradeable_read().upgrade() += 1;
+              }
+            }
+          });
+        }
+      });
+      assert_eq!(lock.into_inner(), 4000);
+    }
+  }
+}
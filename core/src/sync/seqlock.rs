@@ -0,0 +1,242 @@
+//! This module provides [`SeqLock`], a sequence lock for small values that are
+//! read far more often than they are written, such as the time and boot
+//! statistics.
+//!
+//! Readers never block writers, and never write to shared memory themselves.
+//! Instead, each write makes the sequence counter odd while it is underway and
+//! even again once it is done, and a reader copies the value out and retries
+//! if the counter was odd, or changed, around its copy. Writers update a copy
+//! of the value, which is stored only once they are done, so no code but the
+//! store runs while the counter is odd.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// A sequence lock, which lets readers copy out a value without blocking the
+/// writers of it.
+///
+/// Writers exclude each other by spinning. Readers retry for as long as a
+/// writer is active, so a value that is written continuously can starve its
+/// readers; it is meant for values that change rarely.
+pub struct SeqLock<T: Copy> {
+  sequence: AtomicUsize,
+  writing: AtomicBool,
+  value: UnsafeCell<T>,
+}
+
+// SAFETY: readers only ever copy the value out, and writers exclude each
+// other, so the lock may be shared whenever `T` may be sent.
+unsafe impl<T: Copy + Send> Send for SeqLock<T> {}
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+  /// Creates a sequence lock holding `value`.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to protect
+  pub const fn new(value: T) -> Self {
+    Self {
+      sequence: AtomicUsize::new(0),
+      writing: AtomicBool::new(false),
+      value: UnsafeCell::new(value),
+    }
+  }
+
+  /// Returns a copy of the value, spinning while it is being written.
+  pub fn read(&self) -> T {
+    loop {
+      if let Some(value) = self.try_read() {
+        return value;
+      }
+      core::hint::spin_loop();
+    }
+  }
+
+  /// Returns a copy of the value, unless it was written during the copy.
+  pub fn try_read(&self) -> Option<T> {
+    let before = self.sequence.load(Ordering::Acquire);
+    if before % 2 != 0 {
+      return None;
+    }
+    // SAFETY: the pointer is valid and aligned. The copy may be torn by a
+    // concurrent write, but `T` is `Copy`, and a torn copy is discarded
+    // below without being used.
+    let value = unsafe { core::ptr::read_volatile(self.value.get()) };
+    arch::barrier::read();
+    let after = self.sequence.load(Ordering::Relaxed);
+    (before == after).then_some(value)
+  }
+
+  /// Replaces the value with `value`, spinning until any other writer is
+  /// done.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the new value
+  pub fn write(&self, value: T) {
+    self.update(|current| *current = value);
+  }
+
+  /// Updates the value with `f`, spinning until any other writer is done.
+  ///
+  /// `f` updates a copy of the value, which is stored once it returns. Until
+  /// then, readers see the value from before the update, even those that `f`
+  /// makes itself, and if `f` panics, the value is left as it was. `f` must
+  /// not write the lock itself, which would spin forever.
+  ///
+  /// # Arguments
+  ///
+  /// * `f` - the function to update the value with
+  pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+    let _writer = self.lock_writers();
+    // SAFETY: only writers write the value, and every other writer is
+    // excluded, so nothing writes it during the copy.
+    let mut value = unsafe { *self.value.get() };
+    let result = f(&mut value);
+    self.store(value);
+    result
+  }
+
+  /// Returns a mutable reference to the value, which needs no locking since
+  /// the lock is borrowed mutably.
+  pub fn get_mut(&mut self) -> &mut T {
+    self.value.get_mut()
+  }
+
+  /// Consumes the lock, returning the value it protects.
+  pub fn into_inner(self) -> T {
+    self.value.into_inner()
+  }
+
+  /// Excludes every other writer until the returned guard is dropped,
+  /// spinning while another writer is active.
+  fn lock_writers(&self) -> WriteGuard<'_> {
+    while self
+      .writing
+      .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+      .is_err()
+    {
+      core::hint::spin_loop();
+    }
+    WriteGuard {
+      writing: &self.writing,
+    }
+  }
+
+  /// Stores `value` as the value, with the sequence odd while it does.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the new value
+  fn store(&self, value: T) {
+    let sequence = self.sequence.load(Ordering::Relaxed);
+    self
+      .sequence
+      .store(sequence.wrapping_add(1), Ordering::Relaxed);
+    // Keeps readers from seeing the new value with the old sequence.
+    arch::barrier::write();
+    // SAFETY: every other writer is excluded, and readers discard whatever
+    // they copy while the sequence is odd.
+    unsafe { core::ptr::write_volatile(self.value.get(), value) };
+    arch::barrier::write();
+    self
+      .sequence
+      .store(sequence.wrapping_add(2), Ordering::Release);
+  }
+}
+
+/// A guard that excludes the other writers of a [`SeqLock`] until it is
+/// dropped, including by a panic of the writer.
+struct WriteGuard<'a> {
+  writing: &'a AtomicBool,
+}
+
+impl Drop for WriteGuard<'_> {
+  fn drop(&mut self) {
+    self.writing.store(false, Ordering::Release);
+  }
+}
+
+impl<T: Copy + Default> Default for SeqLock<T> {
+  fn default() -> Self {
+    Self::new(T::default())
+  }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for SeqLock<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("SeqLock")
+      .field("value", &self.read())
+      .finish()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::SeqLock;
+
+  #[test]
+  fn read_returns_the_last_write() {
+    let lock = SeqLock::new((1, 2));
+    assert_eq!(lock.read(), (1, 2));
+    lock.write((3, 4));
+    assert_eq!(lock.read(), (3, 4));
+    assert_eq!(lock.update(|value| value.0 + value.1), 7);
+  }
+
+  #[test]
+  fn reads_during_an_update_see_the_value_before_it() {
+    let lock = SeqLock::new(0);
+    lock.update(|value| {
+      *value = 1;
+      assert_eq!(lock.try_read(), Some(0));
+      assert_eq!(lock.read(), 0);
+    });
+    assert_eq!(lock.try_read(), Some(1));
+  }
+
+  #[test]
+  fn panicking_updates_leave_the_value_unchanged() {
+    extern crate std;
+    use std::panic::{self, AssertUnwindSafe};
+
+    let lock = SeqLock::new(0);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+      lock.update(|value| {
+        *value = 1;
+        panic!("update failed");
+      })
+    }));
+
+    assert!(result.is_err());
+    assert_eq!(lock.try_read(), Some(0));
+    lock.write(2);
+    assert_eq!(lock.read(), 2);
+  }
+
+  #[test]
+  fn readers_never_see_torn_values() {
+    extern crate std;
+    use std::thread;
+
+    let lock = SeqLock::new([0u64; 8]);
+    thread::scope(|scope| {
+      scope.spawn(|| {
+        for i in 1..=10000 {
+          lock.write([i; 8]);
+        }
+      });
+      for _ in 0..2 {
+        scope.spawn(|| {
+          for _ in 0..10000 {
+            let value = lock.read();
+            assert!(value.iter().all(|&part| part == value[0]));
+          }
+        });
+      }
+    });
+    assert_eq!(lock.read(), [10000; 8]);
+  }
+}
@@ -0,0 +1,276 @@
+//! This module provides [`RingBuffer`], a fixed-capacity queue between one
+//! producer and one consumer that needs neither locks nor allocation, for
+//! handing data from interrupt handlers to threads, such as keyboard scan
+//! codes and received serial bytes.
+//!
+//! The producer only ever writes the tail index and the consumer only ever
+//! writes the head index. Each publishes its index with release ordering
+//! after touching the slot it covers, and reads the other's with acquire
+//! ordering before touching a slot, which is all the synchronization needed.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A queue of up to `N` values between a single [`Producer`] and a single
+/// [`Consumer`].
+///
+/// The indices run freely and wrap around, so the number of values queued is
+/// always their difference.
+pub struct RingBuffer<T, const N: usize> {
+  /// The index of the next value to pop, written only by the consumer.
+  head: AtomicUsize,
+
+  /// The index of the next value to push, written only by the producer.
+  tail: AtomicUsize,
+
+  slots: UnsafeCell<[MaybeUninit<T>; N]>,
+}
+
+// SAFETY: values are moved from the producer to the consumer, which may be on
+// different threads, and each slot is only accessed by one side at a time.
+unsafe impl<T: Send, const N: usize> Send for RingBuffer<T, N> {}
+unsafe impl<T: Send, const N: usize> Sync for RingBuffer<T, N> {}
+
+impl<T, const N: usize> RingBuffer<T, N> {
+  /// Creates an empty ring buffer.
+  pub const fn new() -> Self {
+    assert!(N > 0, "a ring buffer must have a capacity");
+    Self {
+      head: AtomicUsize::new(0),
+      tail: AtomicUsize::new(0),
+      // SAFETY: an array of `MaybeUninit` needs no initialization.
+      slots: UnsafeCell::new(unsafe {
+        MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init()
+      }),
+    }
+  }
+
+  /// Returns the number of values that the ring buffer can hold.
+  pub const fn capacity(&self) -> usize {
+    N
+  }
+
+  /// Returns the number of values queued, which is only a hint while the
+  /// buffer is split, since either side may change it at any time.
+  pub fn len(&self) -> usize {
+    let tail = self.tail.load(Ordering::Acquire);
+    tail.wrapping_sub(self.head.load(Ordering::Acquire))
+  }
+
+  /// Returns whether no values are queued; see [`len`](Self::len).
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Splits the ring buffer into its producer and consumer, which may be
+  /// handed to different threads, or to an interrupt handler and a thread.
+  ///
+  /// Borrowing the buffer mutably ensures that there is only ever one of
+  /// each.
+  pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+    let buffer = &*self;
+    (
+      Producer {
+        buffer,
+        _not_sync: PhantomData,
+      },
+      Consumer {
+        buffer,
+        _not_sync: PhantomData,
+      },
+    )
+  }
+
+  /// Returns a pointer to the slot for `index`.
+  ///
+  /// # Arguments
+  ///
+  /// * `index` - the free-running index of the slot
+  fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+    // SAFETY: the offset is within the array.
+    unsafe { (self.slots.get() as *mut MaybeUninit<T>).add(index % N) }
+  }
+}
+
+impl<T, const N: usize> Default for RingBuffer<T, N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<T, const N: usize> Drop for RingBuffer<T, N> {
+  fn drop(&mut self) {
+    let tail = *self.tail.get_mut();
+    let mut head = *self.head.get_mut();
+    while head != tail {
+      // SAFETY: the slots between the head and the tail hold values that
+      // were pushed and never popped.
+      unsafe { (*self.slot(head)).assume_init_drop() };
+      head = head.wrapping_add(1);
+    }
+  }
+}
+
+impl<T, const N: usize> fmt::Debug for RingBuffer<T, N> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("RingBuffer")
+      .field("len", &self.len())
+      .field("capacity", &N)
+      .finish()
+  }
+}
+
+/// The side of a [`RingBuffer`] that pushes values into it.
+pub struct Producer<'a, T, const N: usize> {
+  buffer: &'a RingBuffer<T, N>,
+  // Only one thread may push at a time, so the producer may be sent to
+  // another thread, but not shared with one.
+  _not_sync: PhantomData<core::cell::Cell<()>>,
+}
+
+impl<T, const N: usize> Producer<'_, T, N> {
+  /// Pushes `value` onto the back of the queue, or returns it if the queue is
+  /// full.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the value to push
+  pub fn push(&mut self, value: T) -> Result<(), T> {
+    let tail = self.buffer.tail.load(Ordering::Relaxed);
+    let head = self.buffer.head.load(Ordering::Acquire);
+    if tail.wrapping_sub(head) == N {
+      return Err(value);
+    }
+    // SAFETY: the slot is outside of the queued values, so the consumer is
+    // not reading it, and it holds no value to overwrite.
+    unsafe { (*self.buffer.slot(tail)).write(value) };
+    self
+      .buffer
+      .tail
+      .store(tail.wrapping_add(1), Ordering::Release);
+    Ok(())
+  }
+
+  /// Returns whether the queue is full, which may stop being true at any
+  /// time as the consumer pops values.
+  pub fn is_full(&self) -> bool {
+    self.buffer.len() == N
+  }
+}
+
+/// The side of a [`RingBuffer`] that pops values from it.
+pub struct Consumer<'a, T, const N: usize> {
+  buffer: &'a RingBuffer<T, N>,
+  // Only one thread may pop at a time, as for the producer.
+  _not_sync: PhantomData<core::cell::Cell<()>>,
+}
+
+impl<T, const N: usize> Consumer<'_, T, N> {
+  /// Pops the value at the front of the queue, if there is one.
+  pub fn pop(&mut self) -> Option<T> {
+    let head = self.buffer.head.load(Ordering::Relaxed);
+    let tail = self.buffer.tail.load(Ordering::Acquire);
+    if head == tail {
+      return None;
+    }
+    // SAFETY: the slot holds a queued value, which the producer does not
+    // touch until the head moves past it.
+    let value = unsafe { (*self.buffer.slot(head)).assume_init_read() };
+    self
+      .buffer
+      .head
+      .store(head.wrapping_add(1), Ordering::Release);
+    Some(value)
+  }
+
+  /// Returns whether the queue is empty, which may stop being true at any
+  /// time as the producer pushes values.
+  pub fn is_empty(&self) -> bool {
+    self.buffer.is_empty()
+  }
+}
+
+impl<T, const N: usize> Iterator for Consumer<'_, T, N> {
+  type Item = T;
+
+  /// Pops values until the queue is empty.
+  fn next(&mut self) -> Option<T> {
+    self.pop()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::RingBuffer;
+
+  #[test]
+  fn values_are_popped_in_order() {
+    let mut buffer = RingBuffer::<u8, 4>::new();
+    let (mut producer, mut consumer) = buffer.split();
+    assert_eq!(consumer.pop(), None);
+    for round in 0..3 {
+      for i in 0..4 {
+        assert_eq!(producer.push(round * 4 + i), Ok(()));
+      }
+      assert!(producer.is_full());
+      assert_eq!(producer.push(0xff), Err(0xff));
+      for i in 0..4 {
+        assert_eq!(consumer.pop(), Some(round * 4 + i));
+      }
+      assert!(consumer.is_empty());
+    }
+  }
+
+  #[test]
+  fn queued_values_are_dropped() {
+    extern crate std;
+    use std::rc::Rc;
+
+    let value = Rc::new(());
+    let mut buffer = RingBuffer::<_, 4>::new();
+    let (mut producer, mut consumer) = buffer.split();
+    for _ in 0..3 {
+      producer.push(value.clone()).unwrap();
+    }
+    drop(consumer.pop());
+    assert_eq!(Rc::strong_count(&value), 3);
+    drop(buffer);
+    assert_eq!(Rc::strong_count(&value), 1);
+  }
+
+  #[test]
+  fn values_cross_threads_intact() {
+    extern crate std;
+    use std::thread;
+
+    let mut buffer = RingBuffer::<usize, 16>::new();
+    let (mut producer, consumer) = buffer.split();
+    thread::scope(|scope| {
+      scope.spawn(move || {
+        for i in 0..10_000 {
+          let mut value = i;
+          while let Err(rejected) = producer.push(value) {
+            value = rejected;
+            thread::yield_now();
+          }
+        }
+      });
+      scope.spawn(move || {
+        let mut consumer = consumer;
+        let mut expected = 0;
+        while expected < 10_000 {
+          match consumer.pop() {
+            Some(value) => {
+              assert_eq!(value, expected);
+              expected += 1;
+            }
+            None => thread::yield_now(),
+          }
+        }
+      });
+    });
+  }
+}
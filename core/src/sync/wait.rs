@@ -0,0 +1,272 @@
+//! This module provides [`WaitQueue`] and [`Event`], through which interrupt
+//! handlers wake the tasks of an [`Executor`](crate::executor::Executor) that
+//! wait on them, such as for a key press, a timer tick or a received byte.
+//!
+//! A waiting task registers its waker with the queue, and the handler wakes
+//! every waker registered, or one of them. The wakers are kept under an
+//! [`IrqMutex`], so a handler that signals cannot interrupt a task that is
+//! registering. A task checks its condition again after registering, so a
+//! signal that arrives in between is never lost.
+
+use super::IrqMutex;
+use core::future::Future;
+use core::mem;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Poll, Waker};
+
+/// A queue of up to `N` tasks waiting for something that an interrupt
+/// handler, or another task, signals.
+///
+/// A task that waits while the queue is full is woken again at once, so that
+/// it polls its condition rather than sleeping through the signal.
+pub struct WaitQueue<const N: usize> {
+  wakers: IrqMutex<Wakers<N>>,
+}
+
+impl<const N: usize> WaitQueue<N> {
+  /// Constructs a queue with no tasks waiting.
+  pub const fn new() -> Self {
+    Self {
+      wakers: IrqMutex::new(Wakers::new()),
+    }
+  }
+
+  /// Registers `waker` to be woken when the queue is next signaled, waking
+  /// it at once if the queue is full.
+  ///
+  /// # Arguments
+  ///
+  /// * `waker` - the waker of the waiting task
+  pub fn register(&self, waker: &Waker) {
+    if !self.wakers.lock().register(waker) {
+      waker.wake_by_ref();
+    }
+  }
+
+  /// Wakes the task that has waited the longest, returning `false` if none
+  /// were waiting.
+  pub fn wake_one(&self) -> bool {
+    let waker = self.wakers.lock().take_one();
+    waker.map(Waker::wake).is_some()
+  }
+
+  /// Wakes every waiting task, returning how many there were.
+  pub fn wake_all(&self) -> usize {
+    let wakers = self.wakers.lock().take_all();
+    wakers.into_iter().flatten().map(Waker::wake).count()
+  }
+
+  /// Returns a future that waits until `condition` returns a value, and
+  /// outputs it.
+  ///
+  /// The condition is checked each time the task is polled, and again after
+  /// registering it, so it may be made true by whoever signals the queue
+  /// just before they do.
+  ///
+  /// # Arguments
+  ///
+  /// * `condition` - the condition to wait for, which returns `None` until it
+  ///   holds
+  pub fn wait_until<'a, T>(
+    &'a self,
+    mut condition: impl FnMut() -> Option<T> + 'a,
+  ) -> impl Future<Output = T> + 'a {
+    core::future::poll_fn(move |context| {
+      if let Some(value) = condition() {
+        return Poll::Ready(value);
+      }
+      self.register(context.waker());
+      match condition() {
+        Some(value) => Poll::Ready(value),
+        None => Poll::Pending,
+      }
+    })
+  }
+}
+
+impl<const N: usize> Default for WaitQueue<N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// An event that interrupt handlers signal, and that up to `N` tasks wait
+/// for.
+///
+/// A signal is latched until a task consumes it by waiting, so one that is
+/// raised while no task waits is not lost; signals raised before one is
+/// consumed are merged into it.
+pub struct Event<const N: usize = 1> {
+  signaled: AtomicBool,
+  waiters: WaitQueue<N>,
+}
+
+impl<const N: usize> Event<N> {
+  /// Constructs an event that has not been signaled.
+  pub const fn new() -> Self {
+    Self {
+      signaled: AtomicBool::new(false),
+      waiters: WaitQueue::new(),
+    }
+  }
+
+  /// Signals the event, waking the tasks that wait for it. Only the first
+  /// task polled consumes the signal, and the others wait again.
+  ///
+  /// This is safe to call from an interrupt handler.
+  pub fn signal(&self) {
+    self.signaled.store(true, Ordering::Release);
+    self.waiters.wake_all();
+  }
+
+  /// Returns `true` if the event has been signaled and not consumed yet.
+  pub fn is_signaled(&self) -> bool {
+    self.signaled.load(Ordering::Acquire)
+  }
+
+  /// Consumes the signal of the event, returning `false` if there was none.
+  pub fn try_take(&self) -> bool {
+    self.signaled.swap(false, Ordering::AcqRel)
+  }
+
+  /// Returns a future that waits until the event is signaled, and consumes
+  /// the signal.
+  pub fn wait(&self) -> impl Future<Output = ()> + '_ {
+    self.waiters.wait_until(|| self.try_take().then_some(()))
+  }
+}
+
+impl<const N: usize> Default for Event<N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// The wakers of the tasks waiting on a [`WaitQueue`], in the order that
+/// they registered.
+struct Wakers<const N: usize> {
+  wakers: [Option<Waker>; N],
+}
+
+impl<const N: usize> Wakers<N> {
+  const EMPTY: Option<Waker> = None;
+
+  /// Constructs an empty set of wakers.
+  const fn new() -> Self {
+    Self {
+      wakers: [Self::EMPTY; N],
+    }
+  }
+
+  /// Adds `waker`, unless it wakes the same task as one already added,
+  /// returning `false` if there is no room for it.
+  ///
+  /// # Arguments
+  ///
+  /// * `waker` - the waker to add
+  fn register(&mut self, waker: &Waker) -> bool {
+    if self
+      .wakers
+      .iter()
+      .flatten()
+      .any(|other| other.will_wake(waker))
+    {
+      return true;
+    }
+    match self.wakers.iter_mut().find(|slot| slot.is_none()) {
+      Some(slot) => {
+        *slot = Some(waker.clone());
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Removes the waker added first, if any, and returns it.
+  fn take_one(&mut self) -> Option<Waker> {
+    let waker = self.wakers.first_mut()?.take();
+    self.wakers.rotate_left(1);
+    waker
+  }
+
+  /// Removes every waker, and returns them.
+  fn take_all(&mut self) -> [Option<Waker>; N] {
+    mem::replace(&mut self.wakers, [Self::EMPTY; N])
+  }
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use core::task::{RawWaker, RawWakerVTable};
+  use std::sync::atomic::AtomicUsize;
+
+  static WAKES: [AtomicUsize; 3] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+  ];
+
+  static VTABLE: RawWakerVTable = RawWakerVTable::new(
+    |data| RawWaker::new(data, &VTABLE),
+    wake,
+    wake,
+    |_| {},
+  );
+
+  fn wake(data: *const ()) {
+    WAKES[data as usize].fetch_add(1, Ordering::Relaxed);
+  }
+
+  fn waker(id: usize) -> Waker {
+    // SAFETY: the data is an index, never dereferenced.
+    unsafe { Waker::from_raw(RawWaker::new(id as *const (), &VTABLE)) }
+  }
+
+  #[test]
+  fn wakers_are_taken_in_order() {
+    let mut wakers = Wakers::<2>::new();
+
+    assert!(wakers.register(&waker(0)));
+    assert!(wakers.register(&waker(0)));
+    assert!(wakers.register(&waker(1)));
+    assert!(!wakers.register(&waker(2)));
+
+    wakers.take_one().unwrap().wake();
+    assert_eq!(WAKES[0].load(Ordering::Relaxed), 1);
+    assert!(wakers.register(&waker(2)));
+    let taken = wakers.take_all();
+    assert_eq!(taken.iter().flatten().count(), 2);
+    assert!(taken[0].as_ref().unwrap().will_wake(&waker(1)));
+    assert!(wakers.take_one().is_none());
+  }
+
+  #[test]
+  fn queues_wake_registered_tasks() {
+    let queue = WaitQueue::<1>::new();
+    queue.register(&waker(1));
+    // The queue is full, so the second task is woken at once to poll again.
+    queue.register(&waker(2));
+    assert_eq!(WAKES[2].load(Ordering::Relaxed), 1);
+
+    assert!(queue.wake_one());
+    assert_eq!(WAKES[1].load(Ordering::Relaxed), 1);
+    assert!(!queue.wake_one());
+    queue.register(&waker(1));
+    assert_eq!(queue.wake_all(), 1);
+    assert_eq!(WAKES[1].load(Ordering::Relaxed), 2);
+  }
+
+  #[test]
+  fn events_latch_signals_until_taken() {
+    let event = Event::<1>::new();
+    event.signal();
+    event.signal();
+
+    assert!(event.is_signaled());
+    assert!(event.try_take());
+    assert!(!event.try_take());
+  }
+}
@@ -0,0 +1,314 @@
+//! This module provides a test harness for code that only runs on the machine
+//! itself, such as paging, interrupts and drivers, which host unit tests
+//! cannot reach.
+//!
+//! Tests are declared with [`os_test!`](crate::os_test!), listed by the test
+//! build of a binary, and run by [`run_and_exit`], which reports each result
+//! over a [`SerialPort`] in the format of `cargo test`, and then exits the
+//! emulator with [`PASS`] or [`FAIL`] so that a script running QEMU can tell
+//! the outcome: through the `isa-debug-exit` device on x86-64, and by powering
+//! off through PSCI on AArch64, where only the last line reported tells it.
+//!
+//! Tests run one after another, without preemption, so a timeout cannot stop
+//! a test that hangs; a test that runs past its timeout fails when it returns,
+//! and tests that wait for hardware bound their waits by the deadline in
+//! their [`Context`]. A test that panics ends the run: the panic handler of
+//! the test build reports the panic, which follows the name of the test, and
+//! calls [`exit`] with `false`.
+
+use crate::error::{Error, Result};
+use crate::serial::SerialPort;
+use crate::time::{ClockSource, Deadline, Duration};
+use core::fmt::{self, Write};
+
+/// The time a test may run for when it gives no timeout of its own.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The code that the emulator is exited with when every test passes.
+pub const PASS: u32 = 0x10;
+
+/// The code that the emulator is exited with when any test fails.
+pub const FAIL: u32 = 0x11;
+
+/// A test, as declared by [`os_test!`](crate::os_test!).
+#[derive(Clone, Copy, Debug)]
+pub struct Test {
+  /// The path of the test, from the crate it is declared in.
+  pub name: &'static str,
+
+  /// The test, which passes if it returns `Ok`.
+  pub function: fn(&mut Context<'_>) -> Result<()>,
+
+  /// The time the test may run for.
+  pub timeout: Duration,
+}
+
+/// The environment that a test runs in.
+pub struct Context<'a> {
+  clock: &'a dyn ClockSource,
+  deadline: Deadline,
+}
+
+impl<'a> Context<'a> {
+  /// Returns the clock that the test is timed by.
+  pub fn clock(&self) -> &'a dyn ClockSource {
+    self.clock
+  }
+
+  /// Returns the end of the test's timeout, for waits on hardware.
+  pub fn deadline(&self) -> Deadline {
+    self.deadline
+  }
+
+  /// Returns `true` if the test has run past its timeout.
+  pub fn timed_out(&self) -> bool {
+    self.deadline.has_passed(&self.clock)
+  }
+}
+
+/// The result of a test.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+  /// The test returned `Ok` within its timeout.
+  Passed,
+
+  /// The test returned the error.
+  Failed(Error),
+
+  /// The test returned `Ok` after its timeout, having run for the duration.
+  TimedOut(Duration),
+}
+
+/// Runs `test`, timed by `clock`, and returns its result.
+///
+/// # Arguments
+///
+/// * `test` - the test to run
+/// * `clock` - the clock to time the test by
+pub fn run_test(test: &Test, clock: &dyn ClockSource) -> Outcome {
+  let start = clock.now();
+  let mut context = Context {
+    clock,
+    deadline: Deadline::after(&clock, test.timeout),
+  };
+  let result = (test.function)(&mut context);
+  match result {
+    Err(error) => Outcome::Failed(error),
+    Ok(()) if context.timed_out() => Outcome::TimedOut(start.elapsed(&clock)),
+    Ok(()) => Outcome::Passed,
+  }
+}
+
+/// Runs `tests` in order, timed by `clock`, reports each result on `port`,
+/// and returns `true` if all of them passed.
+///
+/// The results are reported even if the port fails, as far as it lets them.
+///
+/// # Arguments
+///
+/// * `tests` - the tests to run
+/// * `port` - the port to report the results on
+/// * `clock` - the clock to time the tests by
+pub fn run(
+  tests: &[&Test],
+  port: &mut impl SerialPort,
+  clock: &impl ClockSource,
+) -> bool {
+  let mut report = Report(port);
+  let _ = writeln!(report, "running {} tests", tests.len());
+  let mut failed = 0;
+  for test in tests {
+    // The name comes first, so that a panic is reported after it.
+    let _ = write!(report, "test {} ... ", test.name);
+    let outcome = run_test(test, clock);
+    let _ = match outcome {
+      Outcome::Passed => writeln!(report, "ok"),
+      Outcome::Failed(error) => writeln!(report, "FAILED: {:?}", error),
+      Outcome::TimedOut(duration) => {
+        writeln!(report, "FAILED: timed out after {:?}", duration)
+      }
+    };
+    failed += (outcome != Outcome::Passed) as usize;
+  }
+  let status = if failed == 0 { "ok" } else { "FAILED" };
+  let _ = writeln!(
+    report,
+    "test result: {}. {} passed; {} failed",
+    status,
+    tests.len() - failed,
+    failed
+  );
+  failed == 0
+}
+
+/// Runs `tests` as [`run`] does, and exits the emulator with the outcome.
+///
+/// # Arguments
+///
+/// * `tests` - the tests to run
+/// * `port` - the port to report the results on
+/// * `clock` - the clock to time the tests by
+pub fn run_and_exit(
+  tests: &[&Test],
+  port: &mut impl SerialPort,
+  clock: &impl ClockSource,
+) -> ! {
+  exit(run(tests, port, clock))
+}
+
+/// Exits the emulator with [`PASS`] if `passed`, or [`FAIL`] otherwise.
+///
+/// # Arguments
+///
+/// * `passed` - whether every test passed
+pub fn exit(passed: bool) -> ! {
+  arch::exit_emulator(if passed { PASS } else { FAIL })
+}
+
+/// Declares a test for the machine, as a public `static` [`Test`] with the
+/// name of the function, for the test build to list.
+///
+/// The function takes the [`Context`] of the test, and passes if it returns
+/// `Ok`. It may be preceded by `timeout(duration)`, for tests that run for
+/// longer than [`DEFAULT_TIMEOUT`], or should fail sooner.
+///
+/// ```ignore
+/// kcore::os_test! {
+///   /// Checks that the cycle counter advances.
+///   timeout(Duration::from_millis(100))
+///   fn cycle_counter_advances(context) {
+///     let start = arch::cycle_counter();
+///     let advanced = context.deadline().wait(&context.clock(), || {
+///       arch::cycle_counter() > start
+///     });
+///     advanced.then_some(()).ok_or(Error::Timeout)
+///   }
+/// }
+/// ```
+#[macro_export]
+macro_rules! os_test {
+  (
+    $(#[$attr:meta])*
+    $(timeout($timeout:expr))?
+    fn $name:ident($context:ident) $body:block
+  ) => {
+    $(#[$attr])*
+    #[allow(non_upper_case_globals)]
+    pub static $name: $crate::testing::Test = $crate::testing::Test {
+      name: concat!(module_path!(), "::", stringify!($name)),
+      function: {
+        fn $name(
+          $context: &mut $crate::testing::Context<'_>,
+        ) -> $crate::error::Result<()> {
+          $body
+        }
+        $name
+      },
+      timeout: $crate::os_test!(@timeout $($timeout)?),
+    };
+  };
+  (@timeout) => {
+    $crate::testing::DEFAULT_TIMEOUT
+  };
+  (@timeout $timeout:expr) => {
+    $timeout
+  };
+}
+
+/// A writer of text to a [`SerialPort`].
+struct Report<'a, P>(&'a mut P);
+
+impl<P: SerialPort> Write for Report<'_, P> {
+  fn write_str(&mut self, text: &str) -> fmt::Result {
+    self.0.write(text.as_bytes()).map_err(|_| fmt::Error)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use core::cell::Cell;
+  use std::string::String;
+  use std::vec::Vec;
+
+  /// A clock of 1 kHz that advances by a tick every time it is read.
+  #[derive(Default)]
+  struct Clock(Cell<u64>);
+
+  impl ClockSource for Clock {
+    fn frequency(&self) -> u64 {
+      1_000
+    }
+
+    fn read(&self) -> u64 {
+      self.0.set(self.0.get() + 1);
+      self.0.get()
+    }
+  }
+
+  impl SerialPort for Vec<u8> {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+      self.extend_from_slice(bytes);
+      Ok(())
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>> {
+      Ok(None)
+    }
+  }
+
+  crate::os_test! {
+    fn passes(_context) {
+      Ok(())
+    }
+  }
+
+  crate::os_test! {
+    /// Fails with an error.
+    fn fails(_context) {
+      Err(Error::Corrupted)
+    }
+  }
+
+  crate::os_test! {
+    timeout(Duration::from_millis(5))
+    fn waits(context) {
+      while !context.timed_out() {}
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn tests_are_declared_with_their_path() {
+    assert_eq!(passes.name, "kcore::testing::test::passes");
+    assert_eq!(passes.timeout, DEFAULT_TIMEOUT);
+    assert_eq!(waits.timeout, Duration::from_millis(5));
+  }
+
+  #[test]
+  fn outcomes_are_reported() {
+    let clock = Clock::default();
+    assert_eq!(run_test(&passes, &clock), Outcome::Passed);
+    assert_eq!(run_test(&fails, &clock), Outcome::Failed(Error::Corrupted));
+    assert!(matches!(
+      run_test(&waits, &clock),
+      Outcome::TimedOut(duration) if duration >= Duration::from_millis(5)
+    ));
+
+    let mut port = Vec::new();
+    assert!(run(&[&passes, &passes], &mut port, &clock));
+    assert!(!run(&[&passes, &fails], &mut port, &clock));
+    let report = String::from_utf8(port).unwrap();
+    let lines: Vec<_> = report.lines().collect();
+    assert_eq!(lines[0], "running 2 tests");
+    assert_eq!(lines[1], "test kcore::testing::test::passes ... ok");
+    assert_eq!(lines[3], "test result: ok. 2 passed; 0 failed");
+    assert_eq!(
+      lines[6],
+      "test kcore::testing::test::fails ... FAILED: Corrupted"
+    );
+    assert_eq!(lines[7], "test result: FAILED. 1 passed; 1 failed");
+  }
+}
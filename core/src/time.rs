@@ -0,0 +1,410 @@
+//! This module provides the measurement of time against a [`ClockSource`], a
+//! free-running counter of known frequency, such as the cycle counter in
+//! [`CycleCounter`] or the high precision event timer in [`Hpet`].
+//!
+//! Points in time are [`Instant`]s, which are read from a clock and only
+//! compared with others from the same one; the spans between them are
+//! [`Ticks`] of that clock, which convert to and from the
+//! [`Duration`]s that timeouts and scheduler periods are written in. A
+//! [`Deadline`] is the end of a timeout, so that code that waits for a device
+//! is written once, whichever clock the machine has.
+
+pub mod hpet;
+
+pub use core::time::Duration;
+pub use hpet::Hpet;
+
+use crate::math::{mul_div_u64, Rounding};
+use core::ops::{Add, Sub};
+
+/// The number of nanoseconds in a second.
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// A free-running counter that only ever increases, at a constant frequency.
+pub trait ClockSource {
+  /// Returns the rate of the counter, in ticks per second.
+  fn frequency(&self) -> u64;
+
+  /// Returns the current value of the counter.
+  fn read(&self) -> u64;
+
+  /// Returns the current time.
+  fn now(&self) -> Instant {
+    Instant::from_ticks(self.read())
+  }
+}
+
+impl<T: ClockSource + ?Sized> ClockSource for &T {
+  fn frequency(&self) -> u64 {
+    (**self).frequency()
+  }
+
+  fn read(&self) -> u64 {
+    (**self).read()
+  }
+}
+
+/// A span of time, in ticks of a [`ClockSource`].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct Ticks(pub u64);
+
+impl Ticks {
+  /// Returns the number of ticks that span at least `duration` at
+  /// `frequency`, saturating if there are too many to count.
+  ///
+  /// # Arguments
+  ///
+  /// * `duration` - the span of time
+  /// * `frequency` - the rate of the clock, in ticks per second
+  pub fn from_duration(duration: Duration, frequency: u64) -> Self {
+    let nanos = duration.subsec_nanos() as u64;
+    let ticks = mul_div_u64(nanos, frequency, NANOS_PER_SECOND, Rounding::Up)
+      .and_then(|ticks| {
+        duration
+          .as_secs()
+          .checked_mul(frequency)?
+          .checked_add(ticks)
+      });
+    Self(ticks.unwrap_or(u64::MAX))
+  }
+
+  /// Returns the span of time of these ticks at `frequency`, rounded down to
+  /// a whole nanosecond, or [`Duration::MAX`] if the clock does not tick.
+  ///
+  /// # Arguments
+  ///
+  /// * `frequency` - the rate of the clock, in ticks per second
+  pub fn to_duration(self, frequency: u64) -> Duration {
+    if frequency == 0 {
+      return Duration::MAX;
+    }
+    let nanos = self.0 % frequency;
+    // The remainder is below the frequency, so its nanoseconds are below a
+    // second, and fit.
+    let nanos = mul_div_u64(nanos, NANOS_PER_SECOND, frequency, Rounding::Down);
+    Duration::new(self.0 / frequency, nanos.unwrap_or_default() as u32)
+  }
+}
+
+impl Add for Ticks {
+  type Output = Ticks;
+
+  fn add(self, other: Ticks) -> Ticks {
+    Ticks(self.0.saturating_add(other.0))
+  }
+}
+
+impl Sub for Ticks {
+  type Output = Ticks;
+
+  fn sub(self, other: Ticks) -> Ticks {
+    Ticks(self.0.saturating_sub(other.0))
+  }
+}
+
+/// A point in time, as read from a [`ClockSource`].
+///
+/// Since the clock only ever increases, spans between instants are never
+/// negative: the span from a later instant to an earlier one is zero.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Instant(u64);
+
+impl Instant {
+  /// Constructs the instant at which the clock read `ticks`.
+  ///
+  /// # Arguments
+  ///
+  /// * `ticks` - the value of the counter
+  pub const fn from_ticks(ticks: u64) -> Self {
+    Self(ticks)
+  }
+
+  /// Returns the value of the counter at this instant.
+  pub const fn ticks(self) -> u64 {
+    self.0
+  }
+
+  /// Returns the span of time from `earlier` to this instant, or zero if
+  /// `earlier` is later.
+  ///
+  /// # Arguments
+  ///
+  /// * `earlier` - the instant to measure from
+  pub fn duration_since(self, earlier: Instant) -> Ticks {
+    Ticks(self.0.saturating_sub(earlier.0))
+  }
+
+  /// Returns the span of time from this instant to now on `clock`.
+  ///
+  /// # Arguments
+  ///
+  /// * `clock` - the clock that this instant was read from
+  pub fn elapsed(self, clock: &impl ClockSource) -> Duration {
+    clock
+      .now()
+      .duration_since(self)
+      .to_duration(clock.frequency())
+  }
+}
+
+impl Add<Ticks> for Instant {
+  type Output = Instant;
+
+  fn add(self, ticks: Ticks) -> Instant {
+    Instant(self.0.saturating_add(ticks.0))
+  }
+}
+
+impl Sub for Instant {
+  type Output = Ticks;
+
+  fn sub(self, earlier: Instant) -> Ticks {
+    self.duration_since(earlier)
+  }
+}
+
+/// The end of a timeout on a [`ClockSource`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Deadline {
+  end: Instant,
+}
+
+impl Deadline {
+  /// Constructs the deadline `timeout` from now on `clock`.
+  ///
+  /// # Arguments
+  ///
+  /// * `clock` - the clock to measure the timeout on
+  /// * `timeout` - the span of time until the deadline
+  pub fn after(clock: &impl ClockSource, timeout: Duration) -> Self {
+    let ticks = Ticks::from_duration(timeout, clock.frequency());
+    Self {
+      end: clock.now() + ticks,
+    }
+  }
+
+  /// Returns the instant of the deadline.
+  pub fn instant(&self) -> Instant {
+    self.end
+  }
+
+  /// Returns `true` if the deadline has passed on `clock`.
+  ///
+  /// # Arguments
+  ///
+  /// * `clock` - the clock that the deadline was made on
+  pub fn has_passed(&self, clock: &impl ClockSource) -> bool {
+    clock.now() >= self.end
+  }
+
+  /// Returns the span of time left before the deadline on `clock`, or zero
+  /// if it has passed.
+  ///
+  /// # Arguments
+  ///
+  /// * `clock` - the clock that the deadline was made on
+  pub fn remaining(&self, clock: &impl ClockSource) -> Duration {
+    self
+      .end
+      .duration_since(clock.now())
+      .to_duration(clock.frequency())
+  }
+
+  /// Spins until `done` returns `true` or the deadline passes on `clock`,
+  /// returning whether `done` did.
+  ///
+  /// `done` is checked once more after the deadline passes, so that a
+  /// condition that became true while the caller was preempted is not
+  /// reported as a timeout.
+  ///
+  /// # Arguments
+  ///
+  /// * `clock` - the clock that the deadline was made on
+  /// * `done` - checks whether to stop waiting
+  pub fn wait(
+    &self,
+    clock: &impl ClockSource,
+    mut done: impl FnMut() -> bool,
+  ) -> bool {
+    while !self.has_passed(clock) {
+      if done() {
+        return true;
+      }
+      core::hint::spin_loop();
+    }
+    done()
+  }
+}
+
+/// Spins for at least `duration` on `clock`.
+///
+/// # Arguments
+///
+/// * `clock` - the clock to measure the delay on
+/// * `duration` - the span of time to spin for
+pub fn delay(clock: &impl ClockSource, duration: Duration) {
+  Deadline::after(clock, duration).wait(clock, || false);
+}
+
+/// The cycle counter of the processor, [`arch::cycle_counter`]: the timestamp
+/// counter on x86-64, or the virtual count of the generic timer on AArch64.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CycleCounter {
+  frequency: u64,
+}
+
+impl CycleCounter {
+  /// Constructs the cycle counter, which ticks at `frequency`.
+  ///
+  /// # Arguments
+  ///
+  /// * `frequency` - the rate of the counter, in ticks per second
+  pub const fn new(frequency: u64) -> Self {
+    Self { frequency }
+  }
+
+  /// Returns the cycle counter at the frequency that the machine reports
+  /// for it, if it does, as the generic timer always does.
+  pub fn architectural() -> Option<Self> {
+    arch::counter_frequency().map(Self::new)
+  }
+
+  /// Measures the frequency of the cycle counter by spinning for `duration`
+  /// on `reference`.
+  ///
+  /// # Arguments
+  ///
+  /// * `reference` - the clock of known frequency to measure against
+  /// * `duration` - the span of time to measure over; longer is more precise
+  pub fn calibrate(reference: &impl ClockSource, duration: Duration) -> Self {
+    let start = arch::cycle_counter();
+    let begin = reference.now();
+    delay(reference, duration);
+    let cycles = arch::cycle_counter() - start;
+    let elapsed = reference.now().duration_since(begin).0.max(1);
+    let frequency =
+      cycles as u128 * reference.frequency() as u128 / elapsed as u128;
+    Self::new((frequency.min(u64::MAX as u128) as u64).max(1))
+  }
+}
+
+impl ClockSource for CycleCounter {
+  fn frequency(&self) -> u64 {
+    self.frequency
+  }
+
+  fn read(&self) -> u64 {
+    arch::cycle_counter()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use core::cell::Cell;
+
+  /// A clock of 1 MHz that advances by `step` ticks every time it is read.
+  struct Fake {
+    ticks: Cell<u64>,
+    step: u64,
+  }
+
+  impl Fake {
+    fn new(step: u64) -> Self {
+      Self {
+        ticks: Cell::new(0),
+        step,
+      }
+    }
+  }
+
+  impl ClockSource for Fake {
+    fn frequency(&self) -> u64 {
+      1_000_000
+    }
+
+    fn read(&self) -> u64 {
+      self.ticks.set(self.ticks.get() + self.step);
+      self.ticks.get()
+    }
+  }
+
+  #[test]
+  fn ticks_convert_to_and_from_durations() {
+    let frequency = 3_000_000;
+    assert_eq!(
+      Ticks::from_duration(Duration::from_micros(2), frequency),
+      Ticks(6)
+    );
+    // Partial ticks are rounded up, so that timeouts are never short.
+    assert_eq!(
+      Ticks::from_duration(Duration::from_nanos(1), frequency),
+      Ticks(1)
+    );
+    assert_eq!(Ticks(6).to_duration(frequency), Duration::from_micros(2));
+    assert_eq!(Ticks(1).to_duration(frequency), Duration::from_nanos(333));
+    assert_eq!(
+      Ticks(u64::MAX).to_duration(1).as_secs(),
+      u64::MAX,
+      "long spans do not overflow"
+    );
+    assert_eq!(
+      Ticks::from_duration(Duration::MAX, u64::MAX),
+      Ticks(u64::MAX)
+    );
+    assert_eq!(Ticks(1).to_duration(0), Duration::MAX);
+  }
+
+  #[test]
+  fn instants_never_go_backwards() {
+    let earlier = Instant::from_ticks(100);
+    let later = earlier + Ticks(50);
+    assert!(later > earlier);
+    assert_eq!(later - earlier, Ticks(50));
+    assert_eq!(earlier - later, Ticks(0));
+    assert_eq!(Instant::from_ticks(u64::MAX) + Ticks(1), Instant(u64::MAX));
+  }
+
+  #[test]
+  fn deadlines_pass_after_their_timeout() {
+    let clock = Fake::new(1);
+    let deadline = Deadline::after(&clock, Duration::from_micros(10));
+    assert_eq!(deadline.instant(), Instant::from_ticks(11));
+    assert!(!deadline.has_passed(&clock));
+    assert_eq!(deadline.remaining(&clock), Duration::from_micros(8));
+
+    let mut checks = 0;
+    assert!(!deadline.wait(&clock, || {
+      checks += 1;
+      false
+    }));
+    assert!(deadline.has_passed(&clock));
+    assert_eq!(deadline.remaining(&clock), Duration::ZERO);
+    assert_eq!(checks, 8);
+
+    let deadline = Deadline::after(&clock, Duration::from_secs(1));
+    let mut checks = 0;
+    assert!(deadline.wait(&clock, || {
+      checks += 1;
+      checks == 3
+    }));
+  }
+
+  #[test]
+  fn elapsed_time_is_measured_on_the_clock() {
+    let clock = Fake::new(500);
+    let start = clock.now();
+    assert_eq!(start.elapsed(&clock), Duration::from_micros(500));
+    delay(&clock, Duration::from_millis(2));
+    assert!(start.elapsed(&clock) >= Duration::from_millis(2));
+  }
+
+  #[test]
+  fn the_cycle_counter_is_calibrated_against_a_reference() {
+    let counter =
+      CycleCounter::calibrate(&Fake::new(1), Duration::from_micros(50));
+    assert!(counter.frequency() > 0);
+    let first = counter.read();
+    assert!(counter.read() >= first);
+  }
+}
@@ -0,0 +1,110 @@
+//! This module provides [`Hpet`], the main counter of the high precision event
+//! timer as a [`ClockSource`], at the address described by the ACPI
+//! [`Hpet`](crate::acpi::Hpet) table.
+
+use super::ClockSource;
+use crate::error::{Error, Result};
+use arch::volatile::{ReadOnly, Volatile};
+
+/// The bit of the capabilities that is set if the main counter is 64 bits.
+const CAPABILITY_64_BIT: u64 = 1 << 13;
+
+/// The bit of the configuration that enables the main counter.
+const CONFIGURATION_ENABLE: u64 = 1 << 0;
+
+/// The largest period of the main counter that the specification allows, in
+/// femtoseconds.
+const MAX_PERIOD: u64 = 100_000_000;
+
+/// The number of femtoseconds in a second.
+const FEMTOS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+/// The general registers of a timer block, at the start of its window.
+#[repr(C)]
+pub struct Registers {
+  capabilities: ReadOnly<u64>,
+  reserved0: u64,
+  configuration: Volatile<u64>,
+  reserved1: [u64; 27],
+  counter: Volatile<u64>,
+}
+
+crate::const_assert_size!(Registers, 0xf8);
+
+/// The main counter of a high precision event timer.
+pub struct Hpet<'a> {
+  registers: &'a Registers,
+  frequency: u64,
+}
+
+impl<'a> Hpet<'a> {
+  /// Starts the main counter of the timer block with `registers`, failing
+  /// with [`Error::Unsupported`] if it is only 32 bits, since it would wrap
+  /// within minutes, or [`Error::Corrupted`] if its period is out of range.
+  ///
+  /// # Arguments
+  ///
+  /// * `registers` - the general registers of the timer block
+  pub fn new(registers: &'a Registers) -> Result<Self> {
+    let capabilities = registers.capabilities.read();
+    if capabilities & CAPABILITY_64_BIT == 0 {
+      return Err(Error::Unsupported);
+    }
+    let period = capabilities >> 32;
+    if period == 0 || period > MAX_PERIOD {
+      return Err(Error::Corrupted);
+    }
+    registers
+      .configuration
+      .update(|configuration| configuration | CONFIGURATION_ENABLE);
+    Ok(Self {
+      registers,
+      frequency: FEMTOS_PER_SECOND / period,
+    })
+  }
+}
+
+impl ClockSource for Hpet<'_> {
+  fn frequency(&self) -> u64 {
+    self.frequency
+  }
+
+  fn read(&self) -> u64 {
+    self.registers.counter.read()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn registers(capabilities: u64) -> Registers {
+    Registers {
+      capabilities: ReadOnly::new(capabilities),
+      reserved0: 0,
+      configuration: Volatile::new(0),
+      reserved1: [0; 27],
+      counter: Volatile::new(1234),
+    }
+  }
+
+  #[test]
+  fn the_counter_is_started_at_its_period() {
+    // The 100 MHz counter of QEMU's timer block.
+    let registers = registers(10_000_000 << 32 | CAPABILITY_64_BIT);
+    let hpet = Hpet::new(&registers).unwrap();
+    assert_eq!(hpet.frequency(), 100_000_000);
+    assert_eq!(hpet.read(), 1234);
+    assert_eq!(registers.configuration.read(), CONFIGURATION_ENABLE);
+    assert_eq!(core::mem::size_of::<Registers>(), 0xf8);
+  }
+
+  #[test]
+  fn unusable_counters_are_rejected() {
+    let narrow = registers(10_000_000 << 32);
+    assert_eq!(Hpet::new(&narrow).err(), Some(Error::Unsupported));
+    let slow = registers((MAX_PERIOD + 1) << 32 | CAPABILITY_64_BIT);
+    assert_eq!(Hpet::new(&slow).err(), Some(Error::Corrupted));
+    assert_eq!(slow.configuration.read(), 0);
+  }
+}
@@ -0,0 +1,279 @@
+//! This module provides lists of type-length-value records, for data that is
+//! extended over time by kinds of record that older readers do not know, and
+//! skip.
+//!
+//! Each record is its tag and the length of its value, as little-endian
+//! `u32`s, followed by the value, padded with zeros to a multiple of
+//! [`ALIGN`] bytes so that every record starts aligned. The list ends with
+//! the data, or at a record with the tag [`END`], so that it may be written
+//! into a buffer of zeros larger than it needs.
+//!
+//! Records are read in place with [`Records`], which checks each one against
+//! the bounds of the data; a record that overruns them ends the list with
+//! [`Error::Corrupted`].
+
+use crate::bytes::read_u32;
+use crate::error::{Error, Result};
+use crate::serialize::{self, Encode};
+use core::iter::FusedIterator;
+
+/// The alignment of every record, in bytes.
+pub const ALIGN: usize = 8;
+
+/// The tag that ends a list of records.
+pub const END: u32 = 0;
+
+/// The size of the tag and length of a record.
+const HEADER_SIZE: usize = 8;
+
+/// A record of a list.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Record<'a> {
+  /// The kind of the record.
+  pub tag: u32,
+
+  /// The value of the record, without its padding.
+  pub value: &'a [u8],
+}
+
+/// A cursor that appends records to a list in a buffer.
+pub struct Writer<'a> {
+  buffer: &'a mut [u8],
+  len: usize,
+}
+
+impl<'a> Writer<'a> {
+  /// Constructs a writer of an empty list at the start of `buffer`.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer to write the list into
+  pub fn new(buffer: &'a mut [u8]) -> Self {
+    Self { buffer, len: 0 }
+  }
+
+  /// Returns the length of the list written so far, in bytes.
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  /// Returns `true` if no records have been written.
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Appends the record `tag` holding `value`, failing with
+  /// [`Error::BufferTooSmall`] if it does not fit, or
+  /// [`Error::InvalidArgument`] if `tag` is [`END`].
+  ///
+  /// # Arguments
+  ///
+  /// * `tag` - the kind of the record
+  /// * `value` - the value of the record
+  pub fn push(&mut self, tag: u32, value: &[u8]) -> Result<()> {
+    self.push_with(tag, |buffer| {
+      buffer
+        .get_mut(..value.len())
+        .ok_or(Error::BufferTooSmall)?
+        .copy_from_slice(value);
+      Ok(value.len())
+    })
+  }
+
+  /// Appends the record `tag` holding the encoding of `value` with
+  /// [`serialize`].
+  ///
+  /// # Arguments
+  ///
+  /// * `tag` - the kind of the record
+  /// * `value` - the value to encode
+  pub fn push_encoded<T: Encode + ?Sized>(
+    &mut self,
+    tag: u32,
+    value: &T,
+  ) -> Result<()> {
+    self.push_with(tag, |buffer| {
+      let mut writer = serialize::Writer::new(buffer);
+      value.encode(&mut writer)?;
+      Ok(writer.position())
+    })
+  }
+
+  /// Appends the record `tag` with the value that `write` writes into the
+  /// space after the header, returning its length.
+  ///
+  /// # Arguments
+  ///
+  /// * `tag` - the kind of the record
+  /// * `write` - writes the value, returning its length
+  fn push_with(
+    &mut self,
+    tag: u32,
+    write: impl FnOnce(&mut [u8]) -> Result<usize>,
+  ) -> Result<()> {
+    if tag == END {
+      return Err(Error::InvalidArgument);
+    }
+    let rest = &mut self.buffer[self.len..];
+    if rest.len() < HEADER_SIZE {
+      return Err(Error::BufferTooSmall);
+    }
+    let (header, value) = rest.split_at_mut(HEADER_SIZE);
+    let len = write(value)?;
+    let padded = align(len);
+    value
+      .get_mut(len..padded)
+      .ok_or(Error::BufferTooSmall)?
+      .fill(0);
+    let size = u32::try_from(len).map_err(|_| Error::InvalidArgument)?;
+    header[..4].copy_from_slice(&tag.to_le_bytes());
+    header[4..].copy_from_slice(&size.to_le_bytes());
+    self.len += HEADER_SIZE + padded;
+    Ok(())
+  }
+}
+
+/// An iterator over the records of a list, which ends after the first
+/// malformed one.
+#[derive(Clone)]
+pub struct Records<'a> {
+  data: &'a [u8],
+}
+
+impl<'a> Records<'a> {
+  /// Constructs an iterator over the records of the list in `data`.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the list
+  pub fn new(data: &'a [u8]) -> Self {
+    Self { data }
+  }
+
+  /// Returns the value of the first record with `tag`, if any, or the
+  /// failure to read a malformed record before it.
+  ///
+  /// # Arguments
+  ///
+  /// * `tag` - the kind of record to find
+  pub fn find_tag(self, tag: u32) -> Result<Option<&'a [u8]>> {
+    for record in self {
+      let record = record?;
+      if record.tag == tag {
+        return Ok(Some(record.value));
+      }
+    }
+    Ok(None)
+  }
+
+  /// Reads the record at the start of the data.
+  fn read(&mut self) -> Result<Option<Record<'a>>> {
+    let Some(header) = self.data.get(..HEADER_SIZE) else {
+      return if self.data.iter().all(|&byte| byte == 0) {
+        Ok(None)
+      } else {
+        Err(Error::Corrupted)
+      };
+    };
+    let tag = read_u32(header, 0);
+    if tag == END {
+      return Ok(None);
+    }
+    let len = read_u32(header, 4) as usize;
+    let rest = &self.data[HEADER_SIZE..];
+    let value = rest.get(..len).ok_or(Error::Corrupted)?;
+    // The padding of the last record may be cut off by the end of the data.
+    self.data = rest.get(align(len)..).unwrap_or_default();
+    Ok(Some(Record { tag, value }))
+  }
+}
+
+impl<'a> Iterator for Records<'a> {
+  type Item = Result<Record<'a>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let result = self.read();
+    if !matches!(result, Ok(Some(_))) {
+      self.data = &[];
+    }
+    result.transpose()
+  }
+}
+
+impl FusedIterator for Records<'_> {}
+
+fn align(value: usize) -> usize {
+  (value + ALIGN - 1) & !(ALIGN - 1)
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::vec::Vec;
+
+  #[test]
+  fn records_are_appended_and_read_in_order() {
+    let mut buffer = [0xaa; 64];
+    let mut writer = Writer::new(&mut buffer);
+    assert!(writer.is_empty());
+    writer.push(7, b"hello").unwrap();
+    writer.push(9, &[]).unwrap();
+    writer.push_encoded(3, &0x1234u16).unwrap();
+    let len = writer.len();
+    assert_eq!(len, 16 + 8 + 16);
+    assert_eq!(&buffer[..16], b"\x07\0\0\0\x05\0\0\0hello\0\0\0");
+
+    let records: Vec<_> = Records::new(&buffer[..len])
+      .map(|record| record.unwrap())
+      .collect();
+    let expected = [
+      Record {
+        tag: 7,
+        value: b"hello",
+      },
+      Record { tag: 9, value: &[] },
+      Record {
+        tag: 3,
+        value: &[0x34, 0x12],
+      },
+    ];
+    assert_eq!(records, expected);
+    assert_eq!(Records::new(&buffer[..len]).find_tag(9), Ok(Some(&[][..])));
+    assert_eq!(Records::new(&buffer[..len]).find_tag(8), Ok(None));
+  }
+
+  #[test]
+  fn lists_end_at_the_end_tag_or_trailing_zeros() {
+    let mut buffer = [0; 64];
+    let mut writer = Writer::new(&mut buffer);
+    writer.push(1, b"abc").unwrap();
+    assert_eq!(writer.push(END, b""), Err(Error::InvalidArgument));
+    assert_eq!(Records::new(&buffer).count(), 1);
+    assert_eq!(Records::new(&buffer[..20]).count(), 1);
+    // The padding of the last record may be missing.
+    assert_eq!(Records::new(&buffer[..11]).count(), 1);
+  }
+
+  #[test]
+  fn records_that_overrun_the_data_are_rejected() {
+    let mut buffer = [0; 32];
+    let mut writer = Writer::new(&mut buffer);
+    writer.push(1, b"abcdefgh").unwrap();
+    assert_eq!(writer.push(2, &[1; 9]), Err(Error::BufferTooSmall));
+    writer.push(2, b"").unwrap();
+    assert_eq!(writer.push(3, b"x"), Err(Error::BufferTooSmall));
+
+    let mut records = Records::new(&buffer[..12]);
+    assert_eq!(records.next(), Some(Err(Error::Corrupted)));
+    assert_eq!(records.next(), None);
+    let mut records = Records::new(&buffer[..20]);
+    assert!(matches!(records.next(), Some(Ok(_))));
+    assert_eq!(records.next(), Some(Err(Error::Corrupted)));
+    assert_eq!(
+      Records::new(&buffer[..20]).find_tag(3),
+      Err(Error::Corrupted)
+    );
+  }
+}
@@ -0,0 +1,266 @@
+//! This module provides [`Version`], the version numbers of the bootloader,
+//! the kernel and the interfaces between them, and [`BuildInfo`], the
+//! description of how a binary was built, which [`build_info!`] fills in at
+//! compile time.
+//!
+//! Versions follow semantic versioning loosely: they are compared by their
+//! major, minor and patch numbers alone, and any pre-release or build
+//! metadata is accepted when parsing but discarded.
+
+use crate::error::{Error, Result};
+use core::fmt;
+use core::str::FromStr;
+
+/// A version number, such as `1.4.2`.
+///
+/// Versions are ordered by their major, then minor, then patch numbers.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub struct Version {
+  /// The major number, incremented by incompatible changes.
+  pub major: u32,
+
+  /// The minor number, incremented by compatible additions.
+  pub minor: u32,
+
+  /// The patch number, incremented by compatible fixes.
+  pub patch: u32,
+}
+
+crate::const_assert_size!(Version, 12);
+crate::const_assert_offset!(Version, major: 0, minor: 4, patch: 8);
+
+impl Version {
+  /// Constructs the version `major.minor.patch`.
+  ///
+  /// # Arguments
+  ///
+  /// * `major` - the major number
+  /// * `minor` - the minor number
+  /// * `patch` - the patch number
+  pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+    Self {
+      major,
+      minor,
+      patch,
+    }
+  }
+
+  /// Parses a version such as `1.4.2`, failing with
+  /// [`Error::InvalidArgument`] if it is malformed.
+  ///
+  /// Missing minor and patch numbers, as in `1` or `1.4`, are taken to be
+  /// zero, and a pre-release or build suffix, as in `1.4.2-rc.1+abcdef`, is
+  /// ignored. This is usable in constant expressions, such as to parse
+  /// `env!("CARGO_PKG_VERSION")`.
+  ///
+  /// # Arguments
+  ///
+  /// * `text` - the text to parse
+  pub const fn parse(text: &str) -> Result<Self> {
+    let bytes = text.as_bytes();
+    let mut numbers = [0u32; 3];
+    let mut count = 0;
+    let mut digits = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+      let byte = bytes[index];
+      match byte {
+        b'0'..=b'9' => {
+          let value = match numbers[count].checked_mul(10) {
+            Some(value) => value.checked_add((byte - b'0') as u32),
+            None => None,
+          };
+          numbers[count] = match value {
+            Some(value) => value,
+            None => return Err(Error::InvalidArgument),
+          };
+          digits += 1;
+        }
+        b'.' if digits != 0 && count < 2 => {
+          count += 1;
+          digits = 0;
+        }
+        b'-' | b'+' if digits != 0 => break,
+        _ => return Err(Error::InvalidArgument),
+      }
+      index += 1;
+    }
+    if digits == 0 {
+      return Err(Error::InvalidArgument);
+    }
+    Ok(Self::new(numbers[0], numbers[1], numbers[2]))
+  }
+
+  /// Returns `true` if something written against `required` can use this
+  /// version: it has the same major number and is no older, or, before
+  /// `1.0.0`, has the same minor number and is no older.
+  ///
+  /// # Arguments
+  ///
+  /// * `required` - the oldest version that is known to work
+  pub fn is_compatible_with(&self, required: &Version) -> bool {
+    let same = if self.major == 0 {
+      required.major == 0 && self.minor == required.minor
+    } else {
+      self.major == required.major
+    };
+    same && self >= required
+  }
+}
+
+crate::serializable!(Version {
+  major,
+  minor,
+  patch
+});
+
+impl FromStr for Version {
+  type Err = Error;
+
+  fn from_str(text: &str) -> Result<Self> {
+    Self::parse(text)
+  }
+}
+
+impl fmt::Display for Version {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+  }
+}
+
+/// The description of how a binary was built.
+///
+/// This is usually created with [`build_info!`], rather than by hand.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BuildInfo {
+  /// The name of the package that was built, such as `bootloader`.
+  pub name: &'static str,
+
+  /// The version of the package that was built.
+  pub version: Version,
+
+  /// The abbreviated hash of the git commit that was built, or `unknown`.
+  pub git_hash: &'static str,
+
+  /// The target triple that was built for, such as `x86_64-unknown-uefi`,
+  /// or `unknown`.
+  pub target: &'static str,
+
+  /// The profile that was built with, `debug` or `release`, or `unknown`.
+  pub profile: &'static str,
+}
+
+impl fmt::Display for BuildInfo {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} {} ({}, {}, {})",
+      self.name, self.version, self.git_hash, self.target, self.profile
+    )
+  }
+}
+
+/// Expands to the [`BuildInfo`](crate::version::BuildInfo) of the package
+/// being compiled, as a constant expression.
+///
+/// The name and version come from Cargo. The git hash, target and profile
+/// come from the `BUILD_GIT_HASH`, `BUILD_TARGET` and `BUILD_PROFILE`
+/// variables, which the build script of the package is expected to set with
+/// `cargo:rustc-env`; those it does not set are `unknown`.
+#[macro_export]
+macro_rules! build_info {
+  () => {
+    $crate::version::BuildInfo {
+      name: env!("CARGO_PKG_NAME"),
+      version: match $crate::version::Version::parse(env!(
+        "CARGO_PKG_VERSION"
+      )) {
+        Ok(version) => version,
+        Err(_) => panic!("the package version is malformed"),
+      },
+      git_hash: $crate::build_info!(@env "BUILD_GIT_HASH"),
+      target: $crate::build_info!(@env "BUILD_TARGET"),
+      profile: $crate::build_info!(@env "BUILD_PROFILE"),
+    }
+  };
+  (@env $name:literal) => {
+    match option_env!($name) {
+      Some(value) => value,
+      None => "unknown",
+    }
+  };
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+  use std::string::ToString;
+
+  #[test]
+  fn versions_are_parsed() {
+    assert_eq!(Version::parse("1.4.2"), Ok(Version::new(1, 4, 2)));
+    assert_eq!(Version::parse("1.4"), Ok(Version::new(1, 4, 0)));
+    assert_eq!(Version::parse("7"), Ok(Version::new(7, 0, 0)));
+    assert_eq!(
+      "0.10.3-rc.1+abcdef".parse::<Version>(),
+      Ok(Version::new(0, 10, 3))
+    );
+    for text in [
+      "",
+      ".1",
+      "1.",
+      "1..2",
+      "1.2.3.4",
+      "v1",
+      "1.2.x",
+      "99999999999",
+    ] {
+      assert_eq!(Version::parse(text), Err(Error::InvalidArgument), "{text}");
+    }
+  }
+
+  #[test]
+  fn versions_are_ordered_numerically() {
+    let parse = |text| Version::parse(text).unwrap();
+    assert!(parse("1.10.0") > parse("1.9.9"));
+    assert!(parse("2.0.0") > parse("1.99.99"));
+    assert!(parse("1.2.3-rc.1") == parse("1.2.3"));
+    assert_eq!(parse("1.10.0").to_string(), "1.10.0");
+  }
+
+  #[test]
+  fn compatibility_follows_the_major_number() {
+    let parse = |text| Version::parse(text).unwrap();
+    assert!(parse("1.4.0").is_compatible_with(&parse("1.2.0")));
+    assert!(!parse("1.1.0").is_compatible_with(&parse("1.2.0")));
+    assert!(!parse("2.0.0").is_compatible_with(&parse("1.2.0")));
+    assert!(parse("0.3.5").is_compatible_with(&parse("0.3.1")));
+    assert!(!parse("0.4.0").is_compatible_with(&parse("0.3.1")));
+  }
+
+  #[test]
+  fn versions_are_serialized_as_three_words() {
+    use crate::serialize::{Decode, Encode, Reader, Writer};
+
+    let mut buffer = [0; 12];
+    Version::new(1, 2, 3)
+      .encode(&mut Writer::new(&mut buffer))
+      .unwrap();
+    assert_eq!(buffer, [1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0]);
+    let decoded = Version::decode(&mut Reader::new(&buffer, 0));
+    assert_eq!(decoded, Ok(Version::new(1, 2, 3)));
+  }
+
+  #[test]
+  fn build_info_describes_this_package() {
+    const BUILD: BuildInfo = crate::build_info!();
+    assert_eq!(BUILD.name, "kcore");
+    assert_eq!(BUILD.version.to_string(), env!("CARGO_PKG_VERSION"));
+    assert_eq!(BUILD.git_hash, "unknown");
+    let text = BUILD.to_string();
+    assert!(text.starts_with("kcore 0.1.0 (unknown,"), "{text}");
+  }
+}
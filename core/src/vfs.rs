@@ -0,0 +1,311 @@
+//! This module provides the interface that file systems implement, so that
+//! the bootloader and the kernel find and read files the same way whether
+//! they are on FAT, ext2 or ISO 9660 volumes, or in an initramfs.
+//!
+//! A [`FileSystem`] is mounted on its [`FileSystem::Device`], such as a
+//! [`BlockDevice`], and opens [`File`]s and lists directories by [`Path`],
+//! which it normalizes and resolves from its root directory. Opened files and
+//! directory listings borrow their file system, so that nothing is
+//! allocated.
+
+pub use crate::block::BlockDevice;
+pub use crate::path::Path;
+
+use crate::error::{Error, Result};
+use core::fmt;
+use core::ops::Deref;
+
+/// The longest name of a directory entry, in bytes of UTF-8, which holds the
+/// 255 UTF-16 code units of the longest FAT name.
+pub const MAX_NAME_LEN: usize = 255 * 3;
+
+/// The type of a file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileType {
+  /// A regular file.
+  File,
+
+  /// A directory.
+  Directory,
+
+  /// A symbolic link.
+  Symlink,
+
+  /// Any other file, such as a device or a pipe.
+  Other,
+}
+
+/// The metadata of a file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Metadata {
+  /// The type of the file.
+  pub ty: FileType,
+
+  /// The size of the file, in bytes.
+  pub size: u64,
+}
+
+impl Metadata {
+  /// Returns whether the file is a directory.
+  pub fn is_dir(&self) -> bool {
+    self.ty == FileType::Directory
+  }
+
+  /// Returns whether the file is a regular file.
+  pub fn is_file(&self) -> bool {
+    self.ty == FileType::File
+  }
+}
+
+/// The name of a directory entry, of at most [`MAX_NAME_LEN`] bytes.
+#[derive(Clone)]
+pub struct Name {
+  bytes: [u8; MAX_NAME_LEN],
+  len: usize,
+}
+
+impl Name {
+  /// Constructs the name `name`, failing with [`Error::BufferTooSmall`] if it
+  /// is longer than [`MAX_NAME_LEN`].
+  ///
+  /// # Arguments
+  ///
+  /// * `name` - the name
+  pub fn new(name: &str) -> Result<Self> {
+    Self::from_chars(name.chars())
+  }
+
+  /// Constructs the name from `chars`, failing with
+  /// [`Error::BufferTooSmall`] if it is longer than [`MAX_NAME_LEN`].
+  ///
+  /// # Arguments
+  ///
+  /// * `chars` - the characters of the name
+  pub fn from_chars(chars: impl IntoIterator<Item = char>) -> Result<Self> {
+    let mut name = Self {
+      bytes: [0; MAX_NAME_LEN],
+      len: 0,
+    };
+    for c in chars {
+      let end = name.len + c.len_utf8();
+      let bytes = name.bytes.get_mut(name.len..end);
+      c.encode_utf8(bytes.ok_or(Error::BufferTooSmall)?);
+      name.len = end;
+    }
+    Ok(name)
+  }
+
+  /// Returns the name.
+  pub fn as_str(&self) -> &str {
+    // The bytes are only ever written from characters.
+    unsafe { core::str::from_utf8_unchecked(&self.bytes[..self.len]) }
+  }
+}
+
+impl Deref for Name {
+  type Target = str;
+
+  fn deref(&self) -> &str {
+    self.as_str()
+  }
+}
+
+impl PartialEq for Name {
+  fn eq(&self, other: &Self) -> bool {
+    self.as_str() == other.as_str()
+  }
+}
+
+impl Eq for Name {}
+
+impl fmt::Debug for Name {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Debug::fmt(self.as_str(), f)
+  }
+}
+
+impl fmt::Display for Name {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// An entry of a directory.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DirEntry {
+  /// The name of the entry.
+  pub name: Name,
+
+  /// The metadata of the file of the entry.
+  pub metadata: Metadata,
+}
+
+/// A position to seek to within a file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SeekFrom {
+  /// The offset from the start of the file.
+  Start(u64),
+
+  /// The offset from the end of the file.
+  End(i64),
+
+  /// The offset from the current position.
+  Current(i64),
+}
+
+impl SeekFrom {
+  /// Returns the position sought, failing with [`Error::InvalidArgument`] if
+  /// it is before the start of the file.
+  ///
+  /// # Arguments
+  ///
+  /// * `position` - the current position
+  /// * `len` - the size of the file
+  pub fn resolve(self, position: u64, len: u64) -> Result<u64> {
+    let (base, offset) = match self {
+      SeekFrom::Start(offset) => return Ok(offset),
+      SeekFrom::End(offset) => (len, offset),
+      SeekFrom::Current(offset) => (position, offset),
+    };
+    base
+      .checked_add_signed(offset)
+      .ok_or(Error::InvalidArgument)
+  }
+}
+
+/// An open file, which is read from its position.
+pub trait File {
+  /// Returns the metadata of the file.
+  fn metadata(&self) -> Metadata;
+
+  /// Reads from the position into `buffer`, advancing the position and
+  /// returning the number of bytes read, which is only less than the length
+  /// of `buffer` at the end of the file.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer to read into
+  fn read(&mut self, buffer: &mut [u8]) -> Result<usize>;
+
+  /// Moves the position, which may be past the end of the file, and returns
+  /// it.
+  ///
+  /// # Arguments
+  ///
+  /// * `position` - the position to move to
+  fn seek(&mut self, position: SeekFrom) -> Result<u64>;
+
+  /// Fills `buffer` from the position, failing with [`Error::Corrupted`] if
+  /// the file ends first, such as when a format stored in it is truncated.
+  ///
+  /// # Arguments
+  ///
+  /// * `buffer` - the buffer to fill
+  fn read_exact(&mut self, buffer: &mut [u8]) -> Result<()> {
+    let mut done = 0;
+    while done < buffer.len() {
+      match self.read(&mut buffer[done..])? {
+        0 => return Err(Error::Corrupted),
+        read => done += read,
+      }
+    }
+    Ok(())
+  }
+
+  /// Returns the size of the file.
+  fn len(&self) -> u64 {
+    self.metadata().size
+  }
+
+  /// Returns whether the file is empty.
+  fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+/// An iterator over the entries of a directory, other than `.` and `..`.
+pub trait DirIter: Iterator<Item = Result<DirEntry>> {}
+
+impl<I: Iterator<Item = Result<DirEntry>>> DirIter for I {}
+
+/// A mounted file system.
+pub trait FileSystem {
+  /// What the file system is mounted on, such as a [`BlockDevice`], or the
+  /// bytes of an archive.
+  type Device;
+
+  /// An open file, which borrows the file system.
+  type File<'a>: File
+  where
+    Self: 'a;
+
+  /// A listing of a directory, which borrows the file system.
+  type Dir<'a>: DirIter
+  where
+    Self: 'a;
+
+  /// Mounts the file system on `device`, failing with [`Error::Corrupted`]
+  /// if it does not hold this file system.
+  ///
+  /// # Arguments
+  ///
+  /// * `device` - what the file system is on
+  fn mount(device: Self::Device) -> Result<Self>
+  where
+    Self: Sized;
+
+  /// Opens the file at `path`, failing with [`Error::NotFound`] if there is
+  /// none, and with [`Error::InvalidArgument`] if it is a directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the file
+  fn open(&mut self, path: impl AsRef<Path>) -> Result<Self::File<'_>>;
+
+  /// Lists the directory at `path`, failing with [`Error::NotFound`] if
+  /// there is no such directory.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the directory
+  fn read_dir(&mut self, path: impl AsRef<Path>) -> Result<Self::Dir<'_>>;
+
+  /// Returns the metadata of the file at `path`, failing with
+  /// [`Error::NotFound`] if there is none.
+  ///
+  /// # Arguments
+  ///
+  /// * `path` - the path of the file
+  fn metadata(&mut self, path: impl AsRef<Path>) -> Result<Metadata>;
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn name_holds_utf8() {
+    let name = Name::from_chars("kernel·elf".chars()).unwrap();
+
+    assert_eq!(name.as_str(), "kernel·elf");
+    assert_eq!(name.len(), 11);
+  }
+
+  #[test]
+  fn name_rejects_long_names() {
+    let name = Name::from_chars(core::iter::repeat('x').take(MAX_NAME_LEN + 1));
+
+    assert_eq!(name.err(), Some(Error::BufferTooSmall));
+  }
+
+  #[test]
+  fn seek_from_resolves_positions() {
+    assert_eq!(SeekFrom::Start(5).resolve(2, 10), Ok(5));
+    assert_eq!(SeekFrom::End(-3).resolve(2, 10), Ok(7));
+    assert_eq!(SeekFrom::Current(4).resolve(2, 10), Ok(6));
+    assert_eq!(
+      SeekFrom::Current(-3).resolve(2, 10),
+      Err(Error::InvalidArgument)
+    );
+  }
+}
@@ -0,0 +1,112 @@
+//! This module provides decompression of zlib streams, as described by RFC
+//! 1950, such as the image data of PNG files.
+//!
+//! Streams that depend on a preset dictionary are not decompressed. The
+//! Adler-32 of the decompressed data is verified against the trailer.
+
+use crate::checksum::adler32;
+use crate::deflate;
+use crate::error::{Error, Result};
+
+/// The compression method for DEFLATE, the only one defined.
+const METHOD_DEFLATE: u8 = 8;
+
+/// The largest DEFLATE window, of 2^15 bytes, as its base-2 logarithm less 8.
+const MAX_WINDOW: u8 = 7;
+
+/// The header flag indicating the stream depends on a preset dictionary.
+const FLAG_DICTIONARY: u8 = 1 << 5;
+
+/// The size of the header.
+const HEADER_SIZE: usize = 2;
+
+/// The size of the trailer, holding the Adler-32 of the data.
+const TRAILER_SIZE: usize = 4;
+
+/// Returns `true` if `data` begins with a zlib header of a DEFLATE stream.
+///
+/// # Arguments
+///
+/// * `data` - the data to check
+pub fn is_zlib(data: &[u8]) -> bool {
+  let Some(header) = data.get(..HEADER_SIZE) else {
+    return false;
+  };
+  header[0] & 0x0f == METHOD_DEFLATE
+    && header[0] >> 4 <= MAX_WINDOW
+    && u16::from_be_bytes([header[0], header[1]]) % 31 == 0
+}
+
+/// Decompresses the zlib stream in `data` into `output`, returning the number
+/// of bytes written.
+///
+/// Fails with [`Error::Corrupted`] if the stream is malformed or the
+/// decompressed data does not match the Adler-32 recorded in the trailer,
+/// with [`Error::Unsupported`] if it depends on a preset dictionary, and with
+/// [`Error::BufferTooSmall`] if it does not fit in `output`.
+///
+/// # Arguments
+///
+/// * `data` - the zlib stream
+/// * `output` - the buffer to decompress into
+pub fn decompress(data: &[u8], output: &mut [u8]) -> Result<usize> {
+  if !is_zlib(data) {
+    return Err(Error::Corrupted);
+  }
+  if data[1] & FLAG_DICTIONARY != 0 {
+    return Err(Error::Unsupported);
+  }
+  let (consumed, len) = deflate::inflate(&data[HEADER_SIZE..], output)?;
+  let offset = HEADER_SIZE + consumed;
+  let trailer = data
+    .get(offset..offset + TRAILER_SIZE)
+    .ok_or(Error::Corrupted)?;
+  let expected =
+    u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+  if adler32(&output[..len]) != expected {
+    return Err(Error::Corrupted);
+  }
+  Ok(len)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  /// `hello, zlib!`, as compressed by zlib.
+  const STREAM: [u8; 20] = [
+    0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0xa8, 0xca, 0xc9,
+    0x4c, 0x52, 0x04, 0x00, 0x1c, 0xe5, 0x04, 0x33,
+  ];
+
+  #[test]
+  fn streams_are_decompressed() {
+    let mut output = [0; 32];
+
+    assert!(is_zlib(&STREAM));
+    let len = decompress(&STREAM, &mut output).unwrap();
+    assert_eq!(&output[..len], b"hello, zlib!");
+  }
+
+  #[test]
+  fn failures_are_told_apart() {
+    let mut output = [0; 32];
+
+    let mut stream = STREAM;
+    stream[STREAM.len() - 1] ^= 1;
+    assert_eq!(decompress(&stream, &mut output), Err(Error::Corrupted));
+    assert_eq!(
+      decompress(&STREAM[..STREAM.len() - 1], &mut output),
+      Err(Error::Corrupted)
+    );
+    assert_eq!(
+      decompress(&STREAM, &mut output[..4]),
+      Err(Error::BufferTooSmall)
+    );
+    // The check bits of the header are adjusted for the dictionary flag.
+    let preset = [0x78, 0xbb, 0, 0, 0, 0];
+    assert!(is_zlib(&preset));
+    assert_eq!(decompress(&preset, &mut output), Err(Error::Unsupported));
+    assert!(!is_zlib(&[0x78, 0x9d]));
+  }
+}
@@ -0,0 +1,65 @@
+//! This module provides the adapter of [`Hasher`]s to the [`Checksum`] of
+//! `kcore`, so that code generic over the checksum of a format also takes
+//! formats that protect their data with a digest, such as SHA256.
+
+use crate::Hasher;
+use kcore::checksum::Checksum;
+
+/// A [`Hasher`] as a [`Checksum`], whose output is its digest.
+///
+/// Finishing the checksum hashes a copy of the hasher, so more data can be
+/// added afterwards.
+#[derive(Clone, Default)]
+pub struct HashChecksum<H>(H);
+
+impl<H: Hasher> HashChecksum<H> {
+  /// Constructs the checksum of the data that `hasher` has been given.
+  ///
+  /// # Arguments
+  ///
+  /// * `hasher` - the hasher to adapt
+  pub const fn new(hasher: H) -> Self {
+    Self(hasher)
+  }
+
+  /// Returns the adapted hasher.
+  pub fn into_inner(self) -> H {
+    self.0
+  }
+}
+
+impl<H> Checksum for HashChecksum<H>
+where
+  H: Hasher + Clone + Default,
+  H::Digest: PartialEq,
+{
+  type Output = H::Digest;
+
+  fn update(&mut self, data: &[u8]) {
+    self.0.update(data)
+  }
+
+  fn finish(&self) -> H::Digest {
+    self.0.clone().digest()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::sha256::{Digest, SHA256};
+
+  #[test]
+  fn digest_is_the_checksum() {
+    let expected: Digest =
+      "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3"
+        .parse()
+        .unwrap();
+    let mut checksum = HashChecksum::<SHA256>::default();
+    checksum.update(b"Hello, ");
+    checksum.update(b"world!");
+
+    assert_eq!(checksum.finish(), expected);
+    assert!(HashChecksum::<SHA256>::verify(b"Hello, world!", &expected));
+  }
+}
@@ -0,0 +1,287 @@
+//! This module provides HMAC_DRBG, the deterministic random bit generator of
+//! NIST SP 800-90A built on HMAC-SHA256, and the generator that the
+//! bootloader seeds for itself and the kernel.
+//!
+//! A [`HmacDrbg`] stretches a seed of at least [`SEED_SIZE`] bytes of entropy
+//! into as many random bytes as are asked of it, until it has been asked
+//! [`RESEED_INTERVAL`] times and must be reseeded. It implements the
+//! [`RngCore`] of `kcore`, so it can be used wherever a generator is taken.
+//!
+//! [`BOOT_RNG`] is the generator shared by everything in a binary. The
+//! bootloader seeds it from every source of entropy it finds, and hands the
+//! kernel a seed drawn from it, which the kernel seeds its own with.
+
+use crate::sha256::SHA256;
+use crate::Hasher;
+use kcore::error::{Error, Result};
+use kcore::rand::{CryptoRng, RngCore};
+use kcore::sync::IrqMutex;
+
+/// The fewest bytes of entropy that a generator is seeded or reseeded with,
+/// which is its security strength.
+pub const SEED_SIZE: usize = 32;
+
+/// The number of requests for bytes that a generator answers before it must
+/// be reseeded.
+pub const RESEED_INTERVAL: u64 = 1 << 48;
+
+/// The most bytes that a generator produces in a single request.
+pub const MAX_REQUEST: usize = 1 << 16;
+
+/// The size of a SHA256 digest, and of the key and value of a generator.
+const DIGEST_SIZE: usize = 32;
+
+/// The size of the blocks that SHA256 hashes, which HMAC pads its key to.
+const BLOCK_SIZE: usize = 64;
+
+/// The generator shared by everything in a binary, which is unusable until it
+/// has been seeded.
+pub static BOOT_RNG: BootRng = BootRng(IrqMutex::new(None));
+
+/// A deterministic random bit generator, as HMAC_DRBG with SHA256.
+#[derive(Clone)]
+pub struct HmacDrbg {
+  key: [u8; DIGEST_SIZE],
+  value: [u8; DIGEST_SIZE],
+  reseed_counter: u64,
+}
+
+impl HmacDrbg {
+  /// Constructs a generator seeded from `entropy` and `nonce`, and set apart
+  /// from others by `personalization`, failing with
+  /// [`Error::InvalidArgument`] if there are fewer than [`SEED_SIZE`] bytes
+  /// of entropy.
+  ///
+  /// # Arguments
+  ///
+  /// * `entropy` - the entropy to seed the generator with
+  /// * `nonce` - a value that is not repeated between seedings
+  /// * `personalization` - a value that tells apart the users of generators
+  pub fn new(
+    entropy: &[u8],
+    nonce: &[u8],
+    personalization: &[u8],
+  ) -> Result<Self> {
+    if entropy.len() < SEED_SIZE {
+      return Err(Error::InvalidArgument);
+    }
+    let mut drbg = Self {
+      key: [0x00; DIGEST_SIZE],
+      value: [0x01; DIGEST_SIZE],
+      reseed_counter: 1,
+    };
+    drbg.update(&[entropy, nonce, personalization]);
+    Ok(drbg)
+  }
+
+  /// Reseeds the generator with `entropy`, failing with
+  /// [`Error::InvalidArgument`] if there are fewer than [`SEED_SIZE`] bytes
+  /// of it.
+  ///
+  /// # Arguments
+  ///
+  /// * `entropy` - the entropy to reseed the generator with
+  /// * `additional` - additional input, which may be empty
+  pub fn reseed(&mut self, entropy: &[u8], additional: &[u8]) -> Result<()> {
+    if entropy.len() < SEED_SIZE {
+      return Err(Error::InvalidArgument);
+    }
+    self.update(&[entropy, additional]);
+    self.reseed_counter = 1;
+    Ok(())
+  }
+
+  /// Fills `output` with random bytes, failing with [`Error::Busy`] if the
+  /// generator must be reseeded first, and with [`Error::InvalidArgument`]
+  /// if `output` is longer than [`MAX_REQUEST`].
+  ///
+  /// # Arguments
+  ///
+  /// * `output` - the buffer to fill
+  /// * `additional` - additional input, which may be empty
+  pub fn generate(
+    &mut self,
+    output: &mut [u8],
+    additional: &[u8],
+  ) -> Result<()> {
+    if output.len() > MAX_REQUEST {
+      return Err(Error::InvalidArgument);
+    }
+    if self.reseed_counter > RESEED_INTERVAL {
+      return Err(Error::Busy);
+    }
+    if !additional.is_empty() {
+      self.update(&[additional]);
+    }
+    for chunk in output.chunks_mut(DIGEST_SIZE) {
+      self.value = hmac(&self.key, [&self.value[..]]);
+      chunk.copy_from_slice(&self.value[..chunk.len()]);
+    }
+    self.update(&[additional]);
+    self.reseed_counter += 1;
+    Ok(())
+  }
+
+  /// Mixes `data`, the concatenation of its slices, into the key and value.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the data to mix in, which may be empty
+  fn update(&mut self, data: &[&[u8]]) {
+    for round in [0x00, 0x01] {
+      let prefix = [&self.value[..], &[round]];
+      self.key =
+        hmac(&self.key, prefix.into_iter().chain(data.iter().copied()));
+      self.value = hmac(&self.key, [&self.value[..]]);
+      if data.iter().all(|part| part.is_empty()) {
+        return;
+      }
+    }
+  }
+}
+
+impl RngCore for HmacDrbg {
+  fn try_fill(&mut self, buffer: &mut [u8]) -> Result<()> {
+    buffer
+      .chunks_mut(MAX_REQUEST)
+      .try_for_each(|chunk| self.generate(chunk, &[]))
+  }
+}
+
+impl CryptoRng for HmacDrbg {}
+
+/// The generator shared by everything in a binary; see [`BOOT_RNG`].
+///
+/// Filling fails with [`Error::NotFound`] until the generator is seeded.
+pub struct BootRng(IrqMutex<Option<HmacDrbg>>);
+
+impl BootRng {
+  /// Seeds the generator with `entropy`, or reseeds it if it was seeded
+  /// before, failing with [`Error::InvalidArgument`] if there are fewer than
+  /// [`SEED_SIZE`] bytes of it.
+  ///
+  /// # Arguments
+  ///
+  /// * `entropy` - the entropy to seed the generator with
+  /// * `personalization` - a value that tells apart the binaries seeding
+  ///   their generators, such as their names
+  pub fn seed(&self, entropy: &[u8], personalization: &[u8]) -> Result<()> {
+    let mut drbg = self.0.lock();
+    match &mut *drbg {
+      Some(drbg) => drbg.reseed(entropy, personalization),
+      None => {
+        *drbg = Some(HmacDrbg::new(entropy, &[], personalization)?);
+        Ok(())
+      }
+    }
+  }
+
+  /// Returns `true` if the generator has been seeded.
+  pub fn is_seeded(&self) -> bool {
+    self.0.lock().is_some()
+  }
+}
+
+impl RngCore for &BootRng {
+  fn try_fill(&mut self, buffer: &mut [u8]) -> Result<()> {
+    self
+      .0
+      .lock()
+      .as_mut()
+      .ok_or(Error::NotFound)?
+      .try_fill(buffer)
+  }
+}
+
+impl CryptoRng for &BootRng {}
+
+/// Returns the HMAC-SHA256 of `message`, the concatenation of its slices,
+/// keyed by `key`.
+///
+/// # Arguments
+///
+/// * `key` - the key
+/// * `message` - the message
+fn hmac<'a>(
+  key: &[u8; DIGEST_SIZE],
+  message: impl IntoIterator<Item = &'a [u8]>,
+) -> [u8; DIGEST_SIZE] {
+  let mut pad = [0; BLOCK_SIZE];
+  pad[..DIGEST_SIZE].copy_from_slice(key);
+
+  let mut inner = SHA256::new();
+  inner.update(&pad.map(|byte| byte ^ 0x36));
+  message.into_iter().for_each(|part| inner.update(part));
+  let mut outer = SHA256::new();
+  outer.update(&pad.map(|byte| byte ^ 0x5c));
+  outer.update(&inner.digest().0);
+  outer.digest().0
+}
+
+#[cfg(test)]
+mod test {
+  extern crate std;
+
+  use super::*;
+
+  fn bytes<const N: usize>(hex: &str) -> [u8; N] {
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+      *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+    }
+    bytes
+  }
+
+  #[test]
+  fn hmac_matches_rfc_4231() {
+    let mut key = [0; DIGEST_SIZE];
+    key[..20].fill(0x0b);
+
+    assert_eq!(
+      hmac(&key, [&b"Hi There"[..]]),
+      bytes("b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7")
+    );
+  }
+
+  #[test]
+  fn generate_matches_nist_vector() {
+    let entropy: [u8; 32] =
+      bytes("ca851911349384bffe89de1cbdc46e6831e44d34a4fb935ee285dd14b71a7488");
+    let nonce: [u8; 16] = bytes("659ba96c601dc69fc902940805ec0ca8");
+    let mut drbg = HmacDrbg::new(&entropy, &nonce, &[]).unwrap();
+    let mut output = [0; 128];
+
+    drbg.generate(&mut output, &[]).unwrap();
+    drbg.generate(&mut output, &[]).unwrap();
+    assert_eq!(
+      output,
+      bytes::<128>(concat!(
+        "e528e9abf2dece54d47c7e75e5fe302149f817ea9fb4bee6f4199697d04d5b89",
+        "d54fbb978a15b5c443c9ec21036d2460b6f73ebad0dc2aba6e624abf07745bc1",
+        "07694bb7547bb0995f70de25d6b29e2d3011bb19d27676c07162c8b5ccde0668",
+        "961df86803482cb37ed6d5c0bb8d50cf1f50d476aa0458bdaba806f48be9dcb8",
+      ))
+    );
+  }
+
+  #[test]
+  fn generators_need_enough_entropy_and_reseeding() {
+    assert_eq!(
+      HmacDrbg::new(&[0; SEED_SIZE - 1], &[], &[]).err(),
+      Some(Error::InvalidArgument)
+    );
+
+    let mut drbg = HmacDrbg::new(&[0; SEED_SIZE], &[], &[]).unwrap();
+    let mut other = drbg.clone();
+    let (mut first, mut second) = ([0; 40], [0; 40]);
+    drbg.fill_bytes(&mut first);
+    other.reseed(&[1; SEED_SIZE], &[]).unwrap();
+    other.fill_bytes(&mut second);
+    assert_ne!(first, second);
+
+    drbg.reseed_counter = RESEED_INTERVAL + 1;
+    assert_eq!(drbg.try_fill(&mut first), Err(Error::Busy));
+    drbg.reseed(&[2; SEED_SIZE], &[]).unwrap();
+    assert!(drbg.try_fill(&mut first).is_ok());
+  }
+}
@@ -0,0 +1,81 @@
+//! This module provides [`PublicKey`], the raw bytes of the public key of a
+//! signature scheme, such as the trust anchors that the bootloader checks the
+//! signatures of payloads against, built into it with
+//! [`kcore::embed_asset!`].
+
+/// The public key of a signature scheme, as its `N` raw bytes, such as the
+/// 32 bytes of an Ed25519 key.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PublicKey<const N: usize>([u8; N]);
+
+impl<const N: usize> PublicKey<N> {
+  /// Constructs the key of the bytes `key`.
+  ///
+  /// # Arguments
+  ///
+  /// * `key` - the bytes of the key
+  pub const fn new(key: [u8; N]) -> Self {
+    Self(key)
+  }
+
+  /// Constructs the key held in the file `data`, for keys built into
+  /// binaries with [`kcore::embed_asset!`].
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the contents of the file, which are the `N` bytes of the key
+  ///
+  /// # Panics
+  ///
+  /// Panics, failing the build when evaluated at compile time, if `data` is
+  /// not `N` bytes long.
+  pub const fn embed(data: &[u8]) -> Self {
+    assert!(data.len() == N, "embedded public key has the wrong length");
+    let mut key = [0; N];
+    let mut i = 0;
+    while i < N {
+      key[i] = data[i];
+      i += 1;
+    }
+    Self(key)
+  }
+
+  /// Returns the bytes of the key.
+  pub const fn as_bytes(&self) -> &[u8; N] {
+    &self.0
+  }
+}
+
+impl<const N: usize> AsRef<[u8]> for PublicKey<N> {
+  fn as_ref(&self) -> &[u8] {
+    &self.0
+  }
+}
+
+impl<const N: usize> core::fmt::Display for PublicKey<N> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    for v in &self.0 {
+      write!(f, "{:02x}", v)?;
+    }
+    Ok(())
+  }
+}
+
+impl<const N: usize> core::fmt::Debug for PublicKey<N> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(f, "PublicKey({})", self)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn keys_are_embedded_from_their_bytes() {
+    const KEY: PublicKey<4> = PublicKey::embed(&[1, 2, 3, 4]);
+
+    assert_eq!(KEY, PublicKey::new([1, 2, 3, 4]));
+    assert_eq!(KEY.as_ref(), &[1, 2, 3, 4]);
+  }
+}
@@ -1,11 +1,19 @@
 //! This module provides some cryptographic primitives such as 1-way hashes like
-//! SHA256.
+//! SHA256, the SHA-512 family and BLAKE2b.
 #![no_std]
 
 // use core::hash::Hash;
 // pub mod md5;
 // pub mod merkle;
+pub mod blake2;
+pub mod checksum;
+pub mod drbg;
+pub mod key;
+pub mod sha1;
 pub mod sha256;
+pub mod sha384;
+pub mod sha512;
+pub mod sha512_256;
 
 #[derive(Clone, Copy)]
 pub(crate) enum DigestErrorKind {
@@ -186,6 +194,50 @@ impl<const N: usize> FixedDigest<N> {
     result
   }
 
+  /// Constructs the digest held in the file `data`, for digests built into
+  /// binaries with [`kcore::embed_asset!`].
+  ///
+  /// The file holds either the `N` bytes of the digest, or the digest in
+  /// hexadecimal, optionally followed by whitespace and anything else, such
+  /// as the line that `sha256sum` writes for a file.
+  ///
+  /// # Arguments
+  ///
+  /// * `data` - the contents of the file
+  ///
+  /// # Panics
+  ///
+  /// Panics, failing the build when evaluated at compile time, if `data` is
+  /// neither.
+  pub const fn embed(data: &[u8]) -> Self {
+    let mut result = Self::zeroed();
+    let mut i = 0;
+    if data.len() == N {
+      while i < N {
+        result.0[i] = data[i];
+        i += 1;
+      }
+      return result;
+    }
+    assert!(data.len() >= 2 * N, "embedded digest is too short");
+    if data.len() > 2 * N {
+      assert!(
+        data[2 * N].is_ascii_whitespace(),
+        "embedded digest is too long"
+      );
+    }
+    while i < N {
+      let (Some(high), Some(low)) =
+        (hex_value(data[i * 2]), hex_value(data[i * 2 + 1]))
+      else {
+        panic!("embedded digest is not hexadecimal");
+      };
+      result.0[i] = high << 4 | low;
+      i += 1;
+    }
+    result
+  }
+
   /// Converts an 8-bit ascii hexadecimal value into its corresponding integer
   /// form without checking.
   ///
@@ -333,3 +385,18 @@ impl<const N: usize> Hashable for FixedDigest<N> {
     hasher.update(&self.0)
   }
 }
+
+/// Returns the value of the hexadecimal digit `ascii`, or `None` if it is
+/// not one.
+///
+/// # Arguments
+///
+/// * `ascii` - the 8-bit ascii value
+const fn hex_value(ascii: u8) -> Option<u8> {
+  match ascii {
+    b'0'..=b'9' => Some(ascii - b'0'),
+    b'a'..=b'f' => Some(ascii - b'a' + 10),
+    b'A'..=b'F' => Some(ascii - b'A' + 10),
+    _ => None,
+  }
+}
@@ -0,0 +1,203 @@
+//! This module provides SHA-1 1-way hashing, for interoperating with the
+//! artifacts that still use it: TPM 1.2 event logs, some GPT tooling, and
+//! older Authenticode signatures.
+//!
+//! SHA-1 is broken: collisions can be made for it in practice. It is only
+//! for reading and checking such legacy artifacts, and must never be used to
+//! decide whether something is trusted where a stronger hash could be, nor
+//! for anything new; use [`sha256`](crate::sha256) or the SHA-512 family
+//! instead.
+
+use crate::sha256::Block;
+use crate::{FixedDigest, Hashable, Hasher};
+
+/// A 20-byte SHA-1 digest.
+pub type Digest = FixedDigest<20>;
+
+/// The state of a SHA-1 hash operation, which is only for legacy artifacts;
+/// see the [module](self) for why.
+#[derive(Clone)]
+pub struct SHA1 {
+  len: u64,
+  buffer: Block,
+  hash: [u32; 5],
+}
+
+impl SHA1 {
+  // The default seed for an empty SHA-1 hash.
+  const SEED: [u32; 5] =
+    [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+  // The constant of each 20 rounds.
+  const CONSTANTS: [u32; 4] = [0x5a827999, 0x6ed9eba1, 0x8f1bbcdc, 0xca62c1d6];
+
+  /// Constructs a new [`SHA1`] instance.
+  pub const fn new() -> Self {
+    Self {
+      len: 0,
+      hash: Self::SEED,
+      buffer: Block::zeroed(),
+    }
+  }
+
+  /// Updates this hash with a full block value.
+  ///
+  /// # Arguments
+  ///
+  /// * `block` - the block to update the hash with.
+  pub fn update_block(&mut self, block: &Block) {
+    let mut words = [0u32; 80];
+
+    for i in 0..16 {
+      words[i] = u32::from_be_bytes([
+        block[i * 4],
+        block[i * 4 + 1],
+        block[i * 4 + 2],
+        block[i * 4 + 3],
+      ]);
+    }
+
+    for i in 16..80 {
+      words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16])
+        .rotate_left(1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e] = self.hash;
+
+    for (i, &word) in words.iter().enumerate() {
+      let f = match i / 20 {
+        0 => (b & c) | (!b & d),
+        2 => (b & c) | (b & d) | (c & d),
+        _ => b ^ c ^ d,
+      };
+      let temp = a
+        .rotate_left(5)
+        .wrapping_add(f)
+        .wrapping_add(e)
+        .wrapping_add(Self::CONSTANTS[i / 20])
+        .wrapping_add(word);
+
+      e = d;
+      d = c;
+      c = b.rotate_left(30);
+      b = a;
+      a = temp;
+    }
+
+    for (state, value) in self.hash.iter_mut().zip([a, b, c, d, e]) {
+      *state = state.wrapping_add(value);
+    }
+  }
+}
+
+impl Default for SHA1 {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl super::Hasher for SHA1 {
+  type Digest = Digest;
+
+  fn update(&mut self, data: &[u8]) {
+    let mut data_idx = 0;
+
+    while data_idx < data.len() {
+      let len = self.len as usize;
+      let space_in_buffer = 64 - (len % 64);
+      let remaining_data = data.len() - data_idx;
+
+      let copy_len = core::cmp::min(space_in_buffer, remaining_data);
+
+      let buffer_idx = len % 64;
+      self.buffer[buffer_idx..buffer_idx + copy_len]
+        .copy_from_slice(&data[data_idx..data_idx + copy_len]);
+
+      self.len += copy_len as u64;
+      data_idx += copy_len;
+
+      if self.len % 64 == 0 {
+        let block = self.buffer.clone();
+        self.update_block(&block);
+      }
+    }
+  }
+
+  fn digest(mut self) -> Self::Digest {
+    let length = self.len * 8;
+    let buffer = &self.buffer[..self.len as usize % Block::SIZE];
+    let mut padded = Block::zeroed();
+    padded[..buffer.len()].copy_from_slice(buffer);
+    padded[buffer.len()] = 0x80;
+
+    if buffer.len() >= 56 {
+      self.update_block(&padded);
+      padded = Block::zeroed();
+    }
+
+    padded[56..].copy_from_slice(&length.to_be_bytes());
+
+    self.update_block(&padded);
+
+    let mut result = Digest::zeroed();
+    for (i, &word) in self.hash.iter().enumerate() {
+      result.0[i * 4..(i + 1) * 4].copy_from_slice(&word.to_be_bytes());
+    }
+
+    result
+  }
+}
+
+/// Hash the input byte sequence and return a SHA-1 [`Digest`] representing
+/// the hashed bytes, for legacy artifacts only.
+///
+/// # Arguments
+///
+/// * `bytes` - a slice of bytes to hash
+pub fn hash_bytes(bytes: &[u8]) -> Digest {
+  let mut hasher = SHA1::new();
+  hasher.update(bytes);
+  hasher.digest()
+}
+
+/// Hash the object and return a SHA-1 [`Digest`] representing this hashed
+/// object, for legacy artifacts only.
+///
+/// # Arguments
+///
+/// * `obj` - the object to hash
+pub fn hash<T: Hashable>(obj: T) -> Digest {
+  let mut hasher = SHA1::new();
+  obj.update_hash(&mut hasher);
+  hasher.digest()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use core::str::FromStr;
+
+  #[test]
+  fn sha1_input_less_than_block_size() {
+    let expect =
+      Digest::from_str("a9993e364706816aba3e25717850c26c9cd0d89d").unwrap();
+
+    assert_eq!(hash_bytes(b"abc"), expect);
+  }
+
+  #[test]
+  fn sha1_input_spans_blocks_in_parts() {
+    let message = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+    let expect =
+      Digest::from_str("84983e441c3bd26ebaae4aa1f95129e5e54670f1").unwrap();
+
+    let mut hasher = SHA1::new();
+    hasher.update(&message[..10]);
+    hasher.update(&message[10..]);
+    assert_eq!(hasher.digest(), expect);
+    assert_eq!(
+      hash_bytes(&[b'a'; 1000]),
+      Digest::from_str("291e9a6c66994949b57ba5e650361e98fc36b1ba").unwrap()
+    );
+  }
+}
@@ -60,6 +60,7 @@ impl core::ops::DerefMut for Block {
   }
 }
 
+#[derive(Clone)]
 pub struct SHA256 {
   len: u64,
   buffer: Block,
@@ -199,6 +200,12 @@ impl SHA256 {
   }
 }
 
+impl Default for SHA256 {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 impl super::Hasher for SHA256 {
   type Digest = Digest;
 
@@ -398,4 +405,33 @@ mod test {
 
     assert_eq!(digest, expect);
   }
+
+  #[test]
+  fn digests_are_embedded_from_bytes_or_text() {
+    use crate::sha256;
+    use core::str::FromStr;
+
+    const HELLO: &str =
+      "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3";
+    const LINE: sha256::Digest = sha256::Digest::embed(
+      b"315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3  \
+        hello.txt\n",
+    );
+    let expect = sha256::Digest::from_str(HELLO).unwrap();
+    let bytes: [u8; 32] = core::array::from_fn(|i| {
+      u8::from_str_radix(&HELLO[i * 2..i * 2 + 2], 16).unwrap()
+    });
+
+    assert_eq!(LINE, expect);
+    assert_eq!(sha256::Digest::embed(HELLO.as_bytes()), expect);
+    assert_eq!(sha256::Digest::embed(&bytes), expect);
+  }
+
+  #[test]
+  #[should_panic]
+  fn malformed_embedded_digests_are_rejected() {
+    use crate::sha256;
+
+    sha256::Digest::embed(b"315f5bdb76d078c43b8ac0064e4a0164612b1fce77c8693");
+  }
 }
@@ -0,0 +1,115 @@
+//! This module provides SHA-384 1-way hashing, the hash that certificate
+//! chains and the measurements of firmware, such as those of TPM PCR banks,
+//! most often standardize on.
+//!
+//! A SHA-384 digest is the first 48 bytes of the SHA-512 hash of the same
+//! data, started from another initial value, so that neither can be derived
+//! from the other.
+
+use crate::sha512::SHA512;
+use crate::{FixedDigest, Hashable, Hasher};
+
+/// A 48-byte SHA-384 digest.
+pub type Digest = FixedDigest<48>;
+
+/// The state of a SHA-384 hash operation.
+#[derive(Clone)]
+pub struct SHA384(SHA512);
+
+impl SHA384 {
+  // The seed for an empty SHA-384 hash.
+  const SEED: [u64; 8] = [
+    0xcbbb9d5dc1059ed8,
+    0x629a292a367cd507,
+    0x9159015a3070dd17,
+    0x152fecd8f70e5939,
+    0x67332667ffc00b31,
+    0x8eb44a8768581511,
+    0xdb0c2e0d64f98fa7,
+    0x47b5481dbefa4fa4,
+  ];
+
+  /// Constructs a new [`SHA384`] instance.
+  pub const fn new() -> Self {
+    Self(SHA512::with_seed(Self::SEED))
+  }
+}
+
+impl Default for SHA384 {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl super::Hasher for SHA384 {
+  type Digest = Digest;
+
+  fn update(&mut self, data: &[u8]) {
+    self.0.update(data)
+  }
+
+  fn digest(self) -> Self::Digest {
+    self.0.finish()
+  }
+}
+
+/// Hash the input byte sequence and return a SHA-384 [`Digest`] representing
+/// the hashed bytes.
+///
+/// # Arguments
+///
+/// * `bytes` - a slice of bytes to hash
+pub fn hash_bytes(bytes: &[u8]) -> Digest {
+  let mut hasher = SHA384::new();
+  hasher.update(bytes);
+  hasher.digest()
+}
+
+/// Hash the object and return a SHA-384 [`Digest`] representing this hashed
+/// object.
+///
+/// # Arguments
+///
+/// * `obj` - the object to hash
+pub fn hash<T: Hashable>(obj: T) -> Digest {
+  let mut hasher = SHA384::new();
+  obj.update_hash(&mut hasher);
+  hasher.digest()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use core::str::FromStr;
+
+  #[test]
+  fn sha384_input_less_than_block_size() {
+    let expect = Digest::from_str(concat!(
+      "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded163",
+      "1a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7"
+    ))
+    .unwrap();
+
+    assert_eq!(hash_bytes(b"abc"), expect);
+  }
+
+  #[test]
+  fn sha384_input_spans_blocks_in_parts() {
+    let message = concat!(
+      "abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmn",
+      "hijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu"
+    )
+    .as_bytes();
+    let expect = Digest::from_str(concat!(
+      "09330c33f71147e83d192fc782cd1b4753111b173b3b05d2",
+      "2fa08086e3b0f712fcc7c71a557e2db966c3e9fa91746039"
+    ))
+    .unwrap();
+
+    let mut hasher = SHA384::new();
+    hasher.update(&message[..100]);
+    hasher.update(&message[100..]);
+    assert_eq!(hasher.digest(), expect);
+    assert_eq!(hash_bytes(message), expect);
+  }
+}
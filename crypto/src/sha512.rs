@@ -0,0 +1,393 @@
+//! This module provides SHA-512 1-way hashing, and the compression that
+//! SHA-384 and SHA-512/256 share with it, which differ only in their initial
+//! values and in how much of the final state their digests keep.
+
+use crate::{FixedDigest, Hashable, Hasher};
+
+/// A 64-byte SHA-512 digest.
+pub type Digest = FixedDigest<64>;
+
+/// A 128-byte block of data that is hashed in the SHA-512 algorithm.
+///
+/// Like the block of SHA-256, this is a thin wrapper of an array with added
+/// alignment, which derefs directly into a slice of [`u8`].
+#[derive(Clone)]
+#[repr(align(32))]
+pub struct Block([u8; 128]);
+
+impl Block {
+  /// The size of all [`Block`] instances.
+  pub const SIZE: usize = 128;
+
+  /// Constructs a [`Block`] containing only zeros.
+  #[inline]
+  pub const fn zeroed() -> Self {
+    Self([0; 128])
+  }
+
+  /// Constructs a [`Block`] from an array of the same size.
+  ///
+  /// # Arguments
+  ///
+  /// * `value` - the array value to use.
+  #[inline(always)]
+  pub const fn from_array(value: [u8; 128]) -> Self {
+    Self(value)
+  }
+}
+
+impl From<[u8; 128]> for Block {
+  #[inline(always)]
+  fn from(value: [u8; 128]) -> Self {
+    Self::from_array(value)
+  }
+}
+
+impl core::ops::Deref for Block {
+  type Target = [u8];
+
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
+impl core::ops::DerefMut for Block {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.0
+  }
+}
+
+/// The state of a SHA-512 hash operation, which is also the state of the
+/// SHA-384 and SHA-512/256 hashes built on it.
+#[derive(Clone)]
+pub struct SHA512 {
+  len: u128,
+  buffer: Block,
+  hash: [u64; 8],
+}
+
+impl SHA512 {
+  // The default seed for an empty SHA-512 hash.
+  const SEED: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+  ];
+
+  const CONSTANTS: [u64; 80] = [
+    0x428a2f98d728ae22,
+    0x7137449123ef65cd,
+    0xb5c0fbcfec4d3b2f,
+    0xe9b5dba58189dbbc,
+    0x3956c25bf348b538,
+    0x59f111f1b605d019,
+    0x923f82a4af194f9b,
+    0xab1c5ed5da6d8118,
+    0xd807aa98a3030242,
+    0x12835b0145706fbe,
+    0x243185be4ee4b28c,
+    0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f,
+    0x80deb1fe3b1696b1,
+    0x9bdc06a725c71235,
+    0xc19bf174cf692694,
+    0xe49b69c19ef14ad2,
+    0xefbe4786384f25e3,
+    0x0fc19dc68b8cd5b5,
+    0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275,
+    0x4a7484aa6ea6e483,
+    0x5cb0a9dcbd41fbd4,
+    0x76f988da831153b5,
+    0x983e5152ee66dfab,
+    0xa831c66d2db43210,
+    0xb00327c898fb213f,
+    0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2,
+    0xd5a79147930aa725,
+    0x06ca6351e003826f,
+    0x142929670a0e6e70,
+    0x27b70a8546d22ffc,
+    0x2e1b21385c26c926,
+    0x4d2c6dfc5ac42aed,
+    0x53380d139d95b3df,
+    0x650a73548baf63de,
+    0x766a0abb3c77b2a8,
+    0x81c2c92e47edaee6,
+    0x92722c851482353b,
+    0xa2bfe8a14cf10364,
+    0xa81a664bbc423001,
+    0xc24b8b70d0f89791,
+    0xc76c51a30654be30,
+    0xd192e819d6ef5218,
+    0xd69906245565a910,
+    0xf40e35855771202a,
+    0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8,
+    0x1e376c085141ab53,
+    0x2748774cdf8eeb99,
+    0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63,
+    0x4ed8aa4ae3418acb,
+    0x5b9cca4f7763e373,
+    0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc,
+    0x78a5636f43172f60,
+    0x84c87814a1f0ab72,
+    0x8cc702081a6439ec,
+    0x90befffa23631e28,
+    0xa4506cebde82bde9,
+    0xbef9a3f7b2c67915,
+    0xc67178f2e372532b,
+    0xca273eceea26619c,
+    0xd186b8c721c0c207,
+    0xeada7dd6cde0eb1e,
+    0xf57d4f7fee6ed178,
+    0x06f067aa72176fba,
+    0x0a637dc5a2c898a6,
+    0x113f9804bef90dae,
+    0x1b710b35131c471b,
+    0x28db77f523047d84,
+    0x32caab7b40c72493,
+    0x3c9ebe0a15c9bebc,
+    0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6,
+    0x597f299cfc657e2a,
+    0x5fcb6fab3ad6faec,
+    0x6c44198c4a475817,
+  ];
+
+  /// Constructs a new [`SHA512`] instance.
+  pub const fn new() -> Self {
+    Self::with_seed(Self::SEED)
+  }
+
+  /// Constructs a hash that starts from `seed` rather than the seed of
+  /// SHA-512, as the hashes of the SHA-512 family do.
+  ///
+  /// # Arguments
+  ///
+  /// * `seed` - the initial hash value
+  pub(crate) const fn with_seed(seed: [u64; 8]) -> Self {
+    Self {
+      len: 0,
+      hash: seed,
+      buffer: Block::zeroed(),
+    }
+  }
+
+  #[inline]
+  fn ch(x: u64, y: u64, z: u64) -> u64 {
+    (x & y) ^ (!x & z)
+  }
+
+  #[inline]
+  fn maj(x: u64, y: u64, z: u64) -> u64 {
+    (x & y) ^ (x & z) ^ (y & z)
+  }
+
+  #[inline]
+  fn sigma0(x: u64) -> u64 {
+    x.rotate_right(28) ^ x.rotate_right(34) ^ x.rotate_right(39)
+  }
+
+  #[inline]
+  fn sigma1(x: u64) -> u64 {
+    x.rotate_right(14) ^ x.rotate_right(18) ^ x.rotate_right(41)
+  }
+
+  #[inline]
+  fn gamma0(x: u64) -> u64 {
+    x.rotate_right(1) ^ x.rotate_right(8) ^ (x >> 7)
+  }
+
+  #[inline]
+  fn gamma1(x: u64) -> u64 {
+    x.rotate_right(19) ^ x.rotate_right(61) ^ (x >> 6)
+  }
+
+  /// Updates this hash with a full block value.
+  ///
+  /// # Arguments
+  ///
+  /// * `block` - the block to update the hash with.
+  pub fn update_block(&mut self, block: &Block) {
+    let mut words = [0u64; 80];
+
+    for (i, word) in words.iter_mut().take(16).enumerate() {
+      let mut bytes = [0; 8];
+      bytes.copy_from_slice(&block[i * 8..(i + 1) * 8]);
+      *word = u64::from_be_bytes(bytes);
+    }
+
+    for i in 16..80 {
+      let s0 = Self::gamma0(words[i - 15]);
+      let s1 = Self::gamma1(words[i - 2]);
+      words[i] = words[i - 16]
+        .wrapping_add(s0)
+        .wrapping_add(words[i - 7])
+        .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.hash;
+
+    // As in SHA-256, the index is kept for the symmetry with the loops above,
+    // although it also indexes Self::CONSTANTS.
+    #[allow(clippy::needless_range_loop)]
+    for i in 0..80 {
+      let s1 = Self::sigma1(e);
+      let ch = Self::ch(e, f, g);
+      let temp1 = h
+        .wrapping_add(s1)
+        .wrapping_add(ch)
+        .wrapping_add(Self::CONSTANTS[i])
+        .wrapping_add(words[i]);
+      let s0 = Self::sigma0(a);
+      let maj = Self::maj(a, b, c);
+      let temp2 = s0.wrapping_add(maj);
+
+      h = g;
+      g = f;
+      f = e;
+      e = d.wrapping_add(temp1);
+      d = c;
+      c = b;
+      b = a;
+      a = temp1.wrapping_add(temp2);
+    }
+
+    for (state, value) in self.hash.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+      *state = state.wrapping_add(value);
+    }
+  }
+
+  /// Pads the data hashed so far, and returns the first `N` bytes of the
+  /// final state, which is the digest of SHA-512 or of a hash built on it.
+  pub(crate) fn finish<const N: usize>(mut self) -> FixedDigest<N> {
+    let length = self.len * 8;
+    let buffer = &self.buffer[..(self.len % Block::SIZE as u128) as usize];
+    let mut padded = Block::zeroed();
+    padded[..buffer.len()].copy_from_slice(buffer);
+    padded[buffer.len()] = 0x80;
+
+    if buffer.len() >= 112 {
+      self.update_block(&padded);
+      padded = Block::zeroed();
+    }
+
+    padded[112..].copy_from_slice(&length.to_be_bytes());
+
+    self.update_block(&padded);
+
+    let mut state = [0; 64];
+    for (i, &word) in self.hash.iter().enumerate() {
+      state[i * 8..(i + 1) * 8].copy_from_slice(&word.to_be_bytes());
+    }
+    let mut result = FixedDigest::zeroed();
+    result.0.copy_from_slice(&state[..N]);
+    result
+  }
+}
+
+impl Default for SHA512 {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl super::Hasher for SHA512 {
+  type Digest = Digest;
+
+  fn update(&mut self, data: &[u8]) {
+    let mut data_idx = 0;
+
+    while data_idx < data.len() {
+      let buffer_idx = (self.len % Block::SIZE as u128) as usize;
+      let space_in_buffer = Block::SIZE - buffer_idx;
+      let remaining_data = data.len() - data_idx;
+
+      let copy_len = core::cmp::min(space_in_buffer, remaining_data);
+
+      self.buffer[buffer_idx..buffer_idx + copy_len]
+        .copy_from_slice(&data[data_idx..data_idx + copy_len]);
+
+      self.len += copy_len as u128;
+      data_idx += copy_len;
+
+      if self.len % Block::SIZE as u128 == 0 {
+        let block = self.buffer.clone();
+        self.update_block(&block);
+      }
+    }
+  }
+
+  fn digest(self) -> Self::Digest {
+    self.finish()
+  }
+}
+
+/// Hash the input byte sequence and return a SHA-512 [`Digest`] representing
+/// the hashed bytes.
+///
+/// # Arguments
+///
+/// * `bytes` - a slice of bytes to hash
+pub fn hash_bytes(bytes: &[u8]) -> Digest {
+  let mut hasher = SHA512::new();
+  hasher.update(bytes);
+  hasher.digest()
+}
+
+/// Hash the object and return a SHA-512 [`Digest`] representing this hashed
+/// object.
+///
+/// # Arguments
+///
+/// * `obj` - the object to hash
+pub fn hash<T: Hashable>(obj: T) -> Digest {
+  let mut hasher = SHA512::new();
+  obj.update_hash(&mut hasher);
+  hasher.digest()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use core::str::FromStr;
+
+  #[test]
+  fn sha512_input_less_than_block_size() {
+    let expect = Digest::from_str(concat!(
+      "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a",
+      "2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+    ))
+    .unwrap();
+
+    assert_eq!(hash_bytes(b"abc"), expect);
+  }
+
+  #[test]
+  fn sha512_input_spans_blocks_in_parts() {
+    let message = concat!(
+      "abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmn",
+      "hijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu"
+    )
+    .as_bytes();
+    let expect = Digest::from_str(concat!(
+      "8e959b75dae313da8cf4f72814fc143f8f7779c6eb9f7fa17299aeadb6889018",
+      "501d289e4900f7e4331b99dec4b5433ac7d329eeb6dd26545e96e55b874be909"
+    ))
+    .unwrap();
+
+    let mut hasher = SHA512::new();
+    hasher.update(&message[..100]);
+    hasher.update(&message[100..]);
+    assert_eq!(hasher.digest(), expect);
+    assert_eq!(hash_bytes(message), expect);
+  }
+}
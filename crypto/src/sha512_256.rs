@@ -0,0 +1,113 @@
+//! This module provides SHA-512/256 1-way hashing, whose digests are the size
+//! of those of SHA-256, but are computed faster on 64-bit processors.
+//!
+//! It runs the compression of SHA-512 from an initial value of its own, and
+//! keeps only half of the state it ends in. Since the rest is never revealed,
+//! a digest cannot be extended to that of longer data, as those of SHA-256
+//! and SHA-512 can.
+
+use crate::sha512::SHA512;
+use crate::{FixedDigest, Hashable, Hasher};
+
+/// A 32-byte SHA-512/256 digest.
+pub type Digest = FixedDigest<32>;
+
+/// The state of a SHA-512/256 hash operation.
+#[derive(Clone)]
+pub struct SHA512_256(SHA512);
+
+impl SHA512_256 {
+  // The seed for an empty SHA-512/256 hash.
+  const SEED: [u64; 8] = [
+    0x22312194fc2bf72c,
+    0x9f555fa3c84c64c2,
+    0x2393b86b6f53b151,
+    0x963877195940eabd,
+    0x96283ee2a88effe3,
+    0xbe5e1e2553863992,
+    0x2b0199fc2c85b8aa,
+    0x0eb72ddc81c52ca2,
+  ];
+
+  /// Constructs a new [`SHA512_256`] instance.
+  pub const fn new() -> Self {
+    Self(SHA512::with_seed(Self::SEED))
+  }
+}
+
+impl Default for SHA512_256 {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl super::Hasher for SHA512_256 {
+  type Digest = Digest;
+
+  fn update(&mut self, data: &[u8]) {
+    self.0.update(data)
+  }
+
+  fn digest(self) -> Self::Digest {
+    self.0.finish()
+  }
+}
+
+/// Hash the input byte sequence and return a SHA-512/256 [`Digest`]
+/// representing the hashed bytes.
+///
+/// # Arguments
+///
+/// * `bytes` - a slice of bytes to hash
+pub fn hash_bytes(bytes: &[u8]) -> Digest {
+  let mut hasher = SHA512_256::new();
+  hasher.update(bytes);
+  hasher.digest()
+}
+
+/// Hash the object and return a SHA-512/256 [`Digest`] representing this hashed
+/// object.
+///
+/// # Arguments
+///
+/// * `obj` - the object to hash
+pub fn hash<T: Hashable>(obj: T) -> Digest {
+  let mut hasher = SHA512_256::new();
+  obj.update_hash(&mut hasher);
+  hasher.digest()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use core::str::FromStr;
+
+  #[test]
+  fn sha512_256_input_less_than_block_size() {
+    let expect = Digest::from_str(
+      "53048e2681941ef99b2e29b76b4c7dabe4c2d0c634fc6d46e0e2f13107e7af23",
+    )
+    .unwrap();
+
+    assert_eq!(hash_bytes(b"abc"), expect);
+  }
+
+  #[test]
+  fn sha512_256_input_spans_blocks_in_parts() {
+    let message = concat!(
+      "abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmn",
+      "hijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu"
+    )
+    .as_bytes();
+    let expect = Digest::from_str(
+      "3928e184fb8690f840da3988121d31be65cb9d3ef83ee6146feac861e19b563a",
+    )
+    .unwrap();
+
+    let mut hasher = SHA512_256::new();
+    hasher.update(&message[..100]);
+    hasher.update(&message[100..]);
+    assert_eq!(hasher.digest(), expect);
+    assert_eq!(hash_bytes(message), expect);
+  }
+}
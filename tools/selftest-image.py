@@ -0,0 +1,321 @@
+#!/usr/bin/env python3
+"""Generates the disk image that the bootloader's self-test runs against.
+
+The image is a GPT disk holding a single EFI system partition, formatted as
+FAT16, with the self-test build of the bootloader at the removable media boot
+path, a boot.cfg, and a minimal ELF kernel whose digest boot.cfg gives.
+
+Only the Python standard library is used, so that the image can be generated
+without partitioning or formatting tools.
+"""
+
+import argparse
+import hashlib
+import struct
+import uuid
+import zlib
+
+SECTOR = 512
+
+# The layout of the disk, in sectors.
+PARTITION_START = 2048
+PARTITION_SIZE = 65536
+DISK_SIZE = PARTITION_START + PARTITION_SIZE + 2048
+
+# The number of partition entries, and their size, in the GUID partition table.
+GPT_ENTRIES = 128
+GPT_ENTRY_SIZE = 128
+
+ESP_TYPE = uuid.UUID("c12a7328-f81f-11d2-ba4b-00a0c93ec93b")
+
+# The geometry of the FAT16 file system.
+SECTORS_PER_CLUSTER = 4
+RESERVED_SECTORS = 4
+ROOT_ENTRIES = 512
+CLUSTER = SECTORS_PER_CLUSTER * SECTOR
+
+ATTRIBUTE_DIRECTORY = 0x10
+ATTRIBUTE_ARCHIVE = 0x20
+
+# The details of the kernel for each architecture: the ELF machine, the
+# removable media boot path, and code that spins forever.
+ARCHES = {
+    "x86_64": (62, "BOOTX64.EFI", b"\xeb\xfe"),
+    "aarch64": (183, "BOOTAA64.EFI", struct.pack("<I", 0x14000000)),
+}
+
+# The address that the kernel is linked at.
+KERNEL_BASE = 0xFFFFFFFF80000000
+
+CONFIG_PATH = r"\EFI\untitled\kernel.elf"
+
+
+def kernel(machine, code):
+    """Returns a minimal ELF64 executable with one loadable segment."""
+    header_size, phdr_size, offset = 64, 56, 0x1000
+    header = b"\x7fELF" + bytes([2, 1, 1]) + bytes(9)
+    header += struct.pack(
+        "<HHIQQQIHHHHHH",
+        2,  # ET_EXEC
+        machine,
+        1,
+        KERNEL_BASE,
+        header_size,
+        0,
+        0,
+        header_size,
+        phdr_size,
+        1,
+        0,
+        0,
+        0,
+    )
+    phdr = struct.pack(
+        "<IIQQQQQQ",
+        1,  # PT_LOAD
+        0x5,  # PF_R | PF_X
+        offset,
+        KERNEL_BASE,
+        KERNEL_BASE,
+        len(code),
+        0x1000,
+        0x1000,
+    )
+    image = header + phdr
+    return image + bytes(offset - len(image)) + code
+
+
+class Fat16:
+    """A FAT16 file system being built in memory."""
+
+    def __init__(self, sectors):
+        self.sectors = sectors
+        self.fat_sectors = 1
+        while True:
+            clusters = self._clusters()
+            needed = (clusters + 2) * 2
+            if self.fat_sectors * SECTOR >= needed:
+                break
+            self.fat_sectors += 1
+        assert 4085 <= self._clusters() < 65525
+        self.fat = [0xFFF8, 0xFFFF]
+        self.data = {}
+        self.root = []
+        self.directories = {}
+
+    def _root_sectors(self):
+        return ROOT_ENTRIES * 32 // SECTOR
+
+    def _data_start(self):
+        return RESERVED_SECTORS + 2 * self.fat_sectors + self._root_sectors()
+
+    def _clusters(self):
+        return (self.sectors - self._data_start()) // SECTORS_PER_CLUSTER
+
+    def _allocate(self, contents):
+        count = max(1, -(-len(contents) // CLUSTER))
+        first = len(self.fat)
+        for i in range(count):
+            self.fat.append(first + i + 1 if i + 1 < count else 0xFFFF)
+        assert len(self.fat) <= self._clusters() + 2
+        for i in range(count):
+            chunk = contents[i * CLUSTER : (i + 1) * CLUSTER]
+            self.data[first + i] = chunk.ljust(CLUSTER, b"\0")
+        return first
+
+    @staticmethod
+    def _entry(name, attribute, cluster, size):
+        base, _, extension = name.upper().partition(".")
+        assert len(base) <= 8 and len(extension) <= 3
+        short = base.ljust(8).encode() + extension.ljust(3).encode()
+        # 2024-01-01 00:00:00.
+        date = (2024 - 1980) << 9 | 1 << 5 | 1
+        return short + struct.pack(
+            "<BBBHHHHHHHI",
+            attribute,
+            0,
+            0,
+            0,
+            date,
+            date,
+            0,
+            0,
+            date,
+            cluster,
+            size,
+        )
+
+    def add(self, path, contents):
+        """Adds the file at `path`, creating its directories as needed."""
+        *directories, name = path.strip("\\").split("\\")
+        entries, parent = self.root, 0
+        for directory in directories:
+            entries, parent = self._directory(entries, parent, directory)
+        cluster = self._allocate(contents)
+        entries.append(
+            self._entry(name, ATTRIBUTE_ARCHIVE, cluster, len(contents))
+        )
+
+    def _directory(self, entries, parent, name):
+        for entry in entries:
+            if entry[:11] == self._entry(name, 0, 0, 0)[:11]:
+                cluster = struct.unpack_from("<H", entry, 26)[0]
+                return self.directories[cluster], cluster
+        cluster = self._allocate(b"")
+        children = [
+            self._entry(".", ATTRIBUTE_DIRECTORY, cluster, 0),
+            self._entry("..", ATTRIBUTE_DIRECTORY, parent, 0),
+        ]
+        self.directories[cluster] = children
+        entries.append(self._entry(name, ATTRIBUTE_DIRECTORY, cluster, 0))
+        return children, cluster
+
+    def build(self, hidden):
+        """Returns the file system, which starts `hidden` sectors into the
+        disk."""
+        for cluster, children in self.directories.items():
+            contents = b"".join(children)
+            assert len(contents) <= CLUSTER
+            self.data[cluster] = contents.ljust(CLUSTER, b"\0")
+
+        boot = bytearray(SECTOR)
+        boot[0:3] = b"\xeb\x3c\x90"
+        boot[3:11] = b"MSWIN4.1"
+        struct.pack_into(
+            "<HBHBHHBHHHII",
+            boot,
+            11,
+            SECTOR,
+            SECTORS_PER_CLUSTER,
+            RESERVED_SECTORS,
+            2,
+            ROOT_ENTRIES,
+            0 if self.sectors > 0xFFFF else self.sectors,
+            0xF8,
+            self.fat_sectors,
+            63,
+            255,
+            hidden,
+            self.sectors if self.sectors > 0xFFFF else 0,
+        )
+        struct.pack_into("<BBBI", boot, 36, 0x80, 0, 0x29, 0x5E1F7E57)
+        boot[43:54] = b"SELFTEST   "
+        boot[54:62] = b"FAT16   "
+        boot[510:512] = b"\x55\xaa"
+
+        fat = struct.pack("<%dH" % len(self.fat), *self.fat)
+        fat = fat.ljust(self.fat_sectors * SECTOR, b"\0")
+        root = b"".join(self.root).ljust(ROOT_ENTRIES * 32, b"\0")
+
+        image = bytearray(self.sectors * SECTOR)
+        image[0:SECTOR] = boot
+        offset = RESERVED_SECTORS * SECTOR
+        for _ in range(2):
+            image[offset : offset + len(fat)] = fat
+            offset += len(fat)
+        image[offset : offset + len(root)] = root
+        start = self._data_start() * SECTOR
+        for cluster, contents in self.data.items():
+            offset = start + (cluster - 2) * CLUSTER
+            image[offset : offset + CLUSTER] = contents
+        return bytes(image)
+
+
+def gpt_header(lba, alternate, entries_lba, entries_crc, disk_guid):
+    header = struct.pack(
+        "<8sIIIIQQQQ16sQIII",
+        b"EFI PART",
+        0x00010000,
+        92,
+        0,
+        0,
+        lba,
+        alternate,
+        34,
+        DISK_SIZE - 34,
+        disk_guid.bytes_le,
+        entries_lba,
+        GPT_ENTRIES,
+        GPT_ENTRY_SIZE,
+        entries_crc,
+    )
+    header = header[:16] + struct.pack("<I", zlib.crc32(header)) + header[20:]
+    return header.ljust(SECTOR, b"\0")
+
+
+def disk(partition):
+    """Returns a GPT disk holding `partition` as its EFI system partition."""
+    image = bytearray(DISK_SIZE * SECTOR)
+
+    # The protective MBR.
+    mbr = bytearray(SECTOR)
+    struct.pack_into(
+        "<B3sB3sII",
+        mbr,
+        446,
+        0,
+        b"\x00\x02\x00",
+        0xEE,
+        b"\xff\xff\xff",
+        1,
+        min(DISK_SIZE - 1, 0xFFFFFFFF),
+    )
+    mbr[510:512] = b"\x55\xaa"
+    image[0:SECTOR] = mbr
+
+    entry = struct.pack(
+        "<16s16sQQQ72s",
+        ESP_TYPE.bytes_le,
+        uuid.uuid4().bytes_le,
+        PARTITION_START,
+        PARTITION_START + PARTITION_SIZE - 1,
+        0,
+        "EFI system partition".encode("utf-16-le"),
+    )
+    entries = entry.ljust(GPT_ENTRIES * GPT_ENTRY_SIZE, b"\0")
+    entries_crc = zlib.crc32(entries)
+    entries_sectors = len(entries) // SECTOR
+    disk_guid = uuid.uuid4()
+
+    last = DISK_SIZE - 1
+    backup_entries = last - entries_sectors
+    image[SECTOR : 2 * SECTOR] = gpt_header(1, last, 2, entries_crc, disk_guid)
+    image[2 * SECTOR : 2 * SECTOR + len(entries)] = entries
+    start = backup_entries * SECTOR
+    image[start : start + len(entries)] = entries
+    image[last * SECTOR :] = gpt_header(
+        last, 1, backup_entries, entries_crc, disk_guid
+    )
+
+    start = PARTITION_START * SECTOR
+    image[start : start + len(partition)] = partition
+    return bytes(image)
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--arch", choices=ARCHES, required=True)
+    parser.add_argument("--bootloader", required=True)
+    parser.add_argument("--output", required=True)
+    args = parser.parse_args()
+
+    machine, boot_name, code = ARCHES[args.arch]
+    elf = kernel(machine, code)
+    config = (
+        "# Generated by tools/selftest-image.py.\n"
+        f"kernel = {CONFIG_PATH}\n"
+        f"kernel_sha256 = {hashlib.sha256(elf).hexdigest()}\n"
+    )
+    with open(args.bootloader, "rb") as f:
+        bootloader = f.read()
+
+    fs = Fat16(PARTITION_SIZE)
+    fs.add(r"\EFI\BOOT" + "\\" + boot_name, bootloader)
+    fs.add(r"\EFI\untitled\boot.cfg", config.encode())
+    fs.add(CONFIG_PATH, elf)
+    with open(args.output, "wb") as f:
+        f.write(disk(fs.build(PARTITION_START)))
+
+
+if __name__ == "__main__":
+    main()